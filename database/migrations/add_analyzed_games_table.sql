@@ -0,0 +1,9 @@
+-- Migration: Add AnalyzedGames table for the smart bulk analysis queue
+-- One row per game whose main line has been analyzed by a smart analysis run, so that the next
+-- run resumes with the games left.
+
+CREATE TABLE IF NOT EXISTS AnalyzedGames (
+    GameID INTEGER PRIMARY KEY,
+    AnalyzedAt TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (GameID) REFERENCES Games(ID) ON DELETE CASCADE
+);
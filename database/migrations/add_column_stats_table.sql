@@ -0,0 +1,11 @@
+-- Migration: Add ColumnStats table for query planning hints
+-- Histograms of the Games table written by analyze_database: the total under Kind 'total',
+-- games per player with white and with black under 'white' and 'black' keyed by player ID, and
+-- games per year under 'year' keyed by the four-digit year of Date.
+
+CREATE TABLE IF NOT EXISTS ColumnStats (
+    Kind TEXT NOT NULL,
+    Key TEXT NOT NULL,
+    Games INTEGER NOT NULL,
+    PRIMARY KEY (Kind, Key)
+);
@@ -0,0 +1,15 @@
+-- Migration: Add GameConditionals table for correspondence conditional moves
+-- Each row is one conditional line starting after Ply half-moves of the game's main line:
+-- the opponent's expected move followed by the prepared answer (and optionally more moves),
+-- stored as space-separated SAN.
+
+CREATE TABLE IF NOT EXISTS GameConditionals (
+    ID INTEGER PRIMARY KEY AUTOINCREMENT,
+    GameID INTEGER NOT NULL,
+    Ply INTEGER NOT NULL,
+    Moves TEXT NOT NULL,
+    CreatedAt TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (GameID) REFERENCES Games(ID) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS game_conditionals_game_idx ON GameConditionals(GameID, Ply);
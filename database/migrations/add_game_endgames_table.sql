@@ -0,0 +1,11 @@
+-- Migration: Add GameEndgames table for endgame classification
+-- Stores the material configuration reached at the end of each game's main line.
+-- A NULL Endgame marks a classified game that never reached an endgame.
+
+CREATE TABLE IF NOT EXISTS GameEndgames (
+    GameID INTEGER PRIMARY KEY NOT NULL,
+    Endgame TEXT,
+    FOREIGN KEY (GameID) REFERENCES Games(ID) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS game_endgames_endgame_idx ON GameEndgames(Endgame);
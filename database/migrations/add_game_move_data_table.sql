@@ -0,0 +1,16 @@
+-- Migration: Add GameMoveData table for annotated imports
+-- Stores the [%eval], [%clk] and [%emt] comment commands of each main-line move, so evaluation
+-- graphs and time statistics do not need to parse the move text. Ply 1 is the first move.
+-- Eval is in centipawns and Mate in moves, both from white's point of view; ClockMs is the time
+-- left after the move and EmtMs the time spent on it, in milliseconds.
+
+CREATE TABLE IF NOT EXISTS GameMoveData (
+    GameID INTEGER NOT NULL,
+    Ply INTEGER NOT NULL,
+    Eval INTEGER,
+    Mate INTEGER,
+    ClockMs INTEGER,
+    EmtMs INTEGER,
+    PRIMARY KEY (GameID, Ply),
+    FOREIGN KEY (GameID) REFERENCES Games(ID) ON DELETE CASCADE
+) WITHOUT ROWID;
@@ -0,0 +1,13 @@
+-- Migration: Add GamePhases table for game phase segmentation
+-- Stores the ply at which each game's main line enters the middlegame and the endgame.
+-- A NULL ply marks a phase the game never reached.
+
+CREATE TABLE IF NOT EXISTS GamePhases (
+    GameID INTEGER PRIMARY KEY NOT NULL,
+    MiddlegamePly INTEGER,
+    EndgamePly INTEGER,
+    FOREIGN KEY (GameID) REFERENCES Games(ID) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS game_phases_middlegame_idx ON GamePhases(MiddlegamePly);
+CREATE INDEX IF NOT EXISTS game_phases_endgame_idx ON GamePhases(EndgamePly);
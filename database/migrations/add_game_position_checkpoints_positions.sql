@@ -0,0 +1,25 @@
+-- Migration: Store positions in game_position_checkpoints and remove them with their game
+-- fen holds the position at checkpoints a search can resume its replay from. SQLite cannot add a
+-- foreign key to an existing table, so the table is rebuilt; existing checkpoints have no
+-- positions until the next build, and those of games that no longer exist are dropped.
+
+CREATE TABLE game_position_checkpoints_new (
+    game_id INTEGER NOT NULL,
+    ply INTEGER NOT NULL,
+    structure_hash INTEGER NOT NULL,
+    moves_len INTEGER NOT NULL,
+    fen TEXT,
+    PRIMARY KEY (game_id, ply),
+    FOREIGN KEY (game_id) REFERENCES Games(ID) ON DELETE CASCADE
+);
+
+INSERT INTO game_position_checkpoints_new (game_id, ply, structure_hash, moves_len)
+SELECT game_id, ply, structure_hash, moves_len FROM game_position_checkpoints
+WHERE game_id IN (SELECT ID FROM Games);
+
+DROP TABLE game_position_checkpoints;
+
+ALTER TABLE game_position_checkpoints_new RENAME TO game_position_checkpoints;
+
+CREATE INDEX IF NOT EXISTS idx_gpc_structure
+ON game_position_checkpoints(structure_hash);
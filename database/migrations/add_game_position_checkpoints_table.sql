@@ -0,0 +1,14 @@
+-- Migration: Add game_position_checkpoints table for exact position search
+-- The plies where a game enters a new pawn structure and material balance, keyed by the hash of
+-- that structure. Tables from earlier versions, which sampled full-board hashes, are dropped first.
+
+CREATE TABLE IF NOT EXISTS game_position_checkpoints (
+    game_id INTEGER NOT NULL,
+    ply INTEGER NOT NULL,
+    structure_hash INTEGER NOT NULL,
+    moves_len INTEGER NOT NULL,
+    PRIMARY KEY (game_id, ply)
+);
+
+CREATE INDEX IF NOT EXISTS idx_gpc_structure
+ON game_position_checkpoints(structure_hash);
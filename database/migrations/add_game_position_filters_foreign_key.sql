@@ -0,0 +1,19 @@
+-- Migration: Remove the position filters of a game together with it
+-- SQLite cannot add a foreign key to an existing table, so game_position_filters is rebuilt with
+-- one. Filters of games that no longer exist are dropped on the way.
+
+CREATE TABLE game_position_filters_new (
+    game_id INTEGER PRIMARY KEY,
+    moves_len INTEGER NOT NULL,
+    final_hash INTEGER NOT NULL,
+    bloom BLOB NOT NULL,
+    FOREIGN KEY (game_id) REFERENCES Games(ID) ON DELETE CASCADE
+);
+
+INSERT INTO game_position_filters_new (game_id, moves_len, final_hash, bloom)
+SELECT game_id, moves_len, final_hash, bloom FROM game_position_filters
+WHERE game_id IN (SELECT ID FROM Games);
+
+DROP TABLE game_position_filters;
+
+ALTER TABLE game_position_filters_new RENAME TO game_position_filters;
@@ -0,0 +1,10 @@
+-- Migration: Add game_position_filters table for exact position search
+-- One bloom filter of the positions of each game, so exact searches can skip games that cannot
+-- contain the target. moves_len is the length of the move blob the filter was built from.
+
+CREATE TABLE IF NOT EXISTS game_position_filters (
+    game_id INTEGER PRIMARY KEY,
+    moves_len INTEGER NOT NULL,
+    final_hash INTEGER NOT NULL,
+    bloom BLOB NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Migration: Add GameSources table for import provenance
+-- Records where each game came from (file name, URL, TWIC issue, account sync), so the games of
+-- one import can be found or removed later. Games added before this table existed have no row.
+
+CREATE TABLE IF NOT EXISTS GameSources (
+    GameID INTEGER PRIMARY KEY NOT NULL,
+    Source TEXT NOT NULL,
+    ImportedAt TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (GameID) REFERENCES Games(ID) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS game_sources_source_idx ON GameSources(Source);
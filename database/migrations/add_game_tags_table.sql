@@ -0,0 +1,12 @@
+-- Migration: Add GameTags table for user-defined game tags
+-- Free-form labels such as "model game" or "endgame lesson"; a game can carry any number of them.
+
+CREATE TABLE IF NOT EXISTS GameTags (
+    GameID INTEGER NOT NULL,
+    Tag TEXT NOT NULL,
+    TaggedAt TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (GameID, Tag),
+    FOREIGN KEY (GameID) REFERENCES Games(ID) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS game_tags_tag_idx ON GameTags(Tag);
@@ -0,0 +1,13 @@
+-- Migration: Add GameTimeForfeits table for flagging detection
+-- Stores the decisive games of an import whose Termination header reports a loss on time.
+-- LoserAdvantage is how far ahead the side that lost on time was at the end, in centipawns,
+-- from the final engine evaluation or, without one, from the material balance.
+
+CREATE TABLE IF NOT EXISTS GameTimeForfeits (
+    GameID INTEGER PRIMARY KEY NOT NULL,
+    Loser TEXT NOT NULL,
+    LoserAdvantage INTEGER NOT NULL,
+    FOREIGN KEY (GameID) REFERENCES Games(ID) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS game_time_forfeits_advantage_idx ON GameTimeForfeits(LoserAdvantage);
@@ -0,0 +1,10 @@
+-- Migration: Add MergeJournal table for undoing event and site merges
+-- Each entry holds the rows a merge deleted and the games it moved, as JSON, so it can be reverted.
+
+CREATE TABLE IF NOT EXISTS MergeJournal (
+    ID INTEGER PRIMARY KEY AUTOINCREMENT,
+    Kind TEXT NOT NULL,
+    KeptID INTEGER NOT NULL,
+    Merged TEXT NOT NULL,
+    MergedAt TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
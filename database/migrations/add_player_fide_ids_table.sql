@@ -0,0 +1,8 @@
+-- Migration: Add player_fide_ids table for players imported from FIDE event pages
+-- Links a player row to its FIDE id, so rating history and profiles can be looked up. A FIDE id
+-- belongs to at most one player of the database.
+
+CREATE TABLE IF NOT EXISTS player_fide_ids (
+    player_id INTEGER PRIMARY KEY,
+    fide_id INTEGER NOT NULL UNIQUE
+);
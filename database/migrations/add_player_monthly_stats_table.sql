@@ -0,0 +1,25 @@
+-- Migration: Add PlayerMonthlyStats table for player dashboards
+-- One row per player, site and month with the player's results, summed rating and opening counts.
+-- PlayerStatsWatermark records the last game folded in and how many games that covered, so the
+-- aggregates can be updated incrementally and detected as stale when games are deleted.
+
+CREATE TABLE IF NOT EXISTS PlayerMonthlyStats (
+    PlayerID INTEGER NOT NULL,
+    Site TEXT NOT NULL,
+    -- YYYY-MM
+    Month TEXT NOT NULL,
+    Games INTEGER NOT NULL DEFAULT 0,
+    Wins INTEGER NOT NULL DEFAULT 0,
+    Draws INTEGER NOT NULL DEFAULT 0,
+    Losses INTEGER NOT NULL DEFAULT 0,
+    RatingSum INTEGER NOT NULL DEFAULT 0,
+    -- JSON object of opening name -> games
+    Openings TEXT NOT NULL DEFAULT '{}',
+    PRIMARY KEY (PlayerID, Site, Month)
+);
+
+CREATE TABLE IF NOT EXISTS PlayerStatsWatermark (
+    ID INTEGER PRIMARY KEY CHECK (ID = 1),
+    LastGameID INTEGER NOT NULL,
+    GameCount INTEGER NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Migration: Add SavedFilters table for named game queries
+-- Stores the serialized GameQueryJs of each user-defined database view
+
+CREATE TABLE IF NOT EXISTS SavedFilters (
+    Name TEXT PRIMARY KEY NOT NULL,
+    Query TEXT NOT NULL,
+    UpdatedAt TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,9 @@
+-- Migration: Add SchemaVersion table for database migrations
+-- Records every migration applied to a games database; the highest Version is the schema version.
+-- Databases created by the current app version run all migrations when initialized.
+
+CREATE TABLE IF NOT EXISTS SchemaVersion (
+    Version INTEGER PRIMARY KEY NOT NULL,
+    Name TEXT NOT NULL,
+    AppliedAt TEXT NOT NULL
+);
@@ -8,7 +8,8 @@ CREATE TABLE IF NOT EXISTS puzzles (
     nb_plays INTEGER NOT NULL DEFAULT 0,
     themes TEXT,
     game_url TEXT,
-    opening_tags TEXT
+    opening_tags TEXT,
+    variant TEXT
 );
 
 -- Normalized tables for fast filtering
@@ -1,2 +1,3 @@
-pub mod setup;
-pub mod platform;
+pub mod platform;
+pub mod safe_mode;
+pub mod setup;
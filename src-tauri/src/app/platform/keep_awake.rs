@@ -0,0 +1,181 @@
+//! Keeping the computer awake during long operations.
+//!
+//! Imports, database analyses and engine matches can run for hours and are cut short when the OS
+//! puts the machine to sleep. They hold a `KeepAwake` guard for their duration; while at least one
+//! guard is alive, idle sleep is inhibited with the platform's own mechanism:
+//!
+//! - Windows: `SetThreadExecutionState` on a dedicated thread.
+//! - macOS: a `caffeinate` process, which also exits with the app.
+//! - Linux: a `systemd-inhibit` lock held by a child reading our stdin pipe, so it is released
+//!   when the pipe closes, including when the app crashes.
+//!
+//! Failing to inhibit sleep is logged and otherwise ignored. Mobile platforms keep running tasks
+//! alive on their own terms and are not handled.
+
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+use self::inhibitor::Inhibitor;
+use crate::{error::Result, AppState};
+
+struct KeepAwakeState {
+    holders: usize,
+    inhibitor: Option<Inhibitor>,
+}
+
+static KEEP_AWAKE: Lazy<Mutex<KeepAwakeState>> = Lazy::new(|| {
+    Mutex::new(KeepAwakeState {
+        holders: 0,
+        inhibitor: None,
+    })
+});
+
+/// Keeps the computer awake until dropped.
+pub struct KeepAwake(());
+
+impl Drop for KeepAwake {
+    fn drop(&mut self) {
+        let mut state = KEEP_AWAKE.lock().unwrap_or_else(|e| e.into_inner());
+        state.holders = state.holders.saturating_sub(1);
+        if state.holders == 0 && state.inhibitor.take().is_some() {
+            log::info!("Allowing the system to sleep again");
+        }
+    }
+}
+
+/// Inhibits sleep until the returned guard is dropped. `reason` is shown by the OS where it lists
+/// sleep inhibitors.
+pub fn keep_awake(reason: &str) -> KeepAwake {
+    let mut state = KEEP_AWAKE.lock().unwrap_or_else(|e| e.into_inner());
+    state.holders += 1;
+    if state.inhibitor.is_none() {
+        match Inhibitor::start(reason) {
+            Ok(inhibitor) => {
+                log::info!("Keeping the system awake: {}", reason);
+                state.inhibitor = Some(inhibitor);
+            }
+            Err(e) => log::warn!("Failed to keep the system awake: {}", e),
+        }
+    }
+    KeepAwake(())
+}
+
+#[cfg(target_os = "windows")]
+mod inhibitor {
+    use std::{sync::mpsc, thread};
+
+    const ES_CONTINUOUS: u32 = 0x8000_0000;
+    const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+
+    /// The execution state belongs to the thread that set it, so a dedicated thread holds it
+    /// until the sender is dropped.
+    pub struct Inhibitor(#[allow(dead_code)] mpsc::Sender<()>);
+
+    impl Inhibitor {
+        pub fn start(_reason: &str) -> std::io::Result<Self> {
+            let (release, released) = mpsc::channel::<()>();
+            let (started, start_result) = mpsc::channel();
+            thread::Builder::new()
+                .name("keep-awake".to_string())
+                .spawn(move || {
+                    // SAFETY: plain Win32 call without pointers.
+                    let previous =
+                        unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+                    let _ = started.send(previous != 0);
+                    if previous != 0 {
+                        // Returns once the sender is dropped.
+                        let _ = released.recv();
+                        unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+                    }
+                })?;
+            match start_result.recv() {
+                Ok(true) => Ok(Self(release)),
+                _ => Err(std::io::Error::other("SetThreadExecutionState failed")),
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+mod inhibitor {
+    use std::process::{Child, Command, Stdio};
+
+    /// A child process holding the inhibition while it runs.
+    pub struct Inhibitor(Child);
+
+    impl Inhibitor {
+        #[cfg(target_os = "macos")]
+        pub fn start(_reason: &str) -> std::io::Result<Self> {
+            Command::new("caffeinate")
+                .args(["-i", "-w", &std::process::id().to_string()])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map(Self)
+        }
+
+        #[cfg(target_os = "linux")]
+        pub fn start(reason: &str) -> std::io::Result<Self> {
+            // `cat` runs until its stdin, a pipe held by the `Child`, is closed.
+            Command::new("systemd-inhibit")
+                .args([
+                    "--what=idle:sleep",
+                    "--who=Pawn Appetit",
+                    &format!("--why={}", reason),
+                    "--mode=block",
+                    "cat",
+                ])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map(Self)
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            drop(self.0.stdin.take());
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod inhibitor {
+    pub struct Inhibitor;
+
+    impl Inhibitor {
+        pub fn start(_reason: &str) -> std::io::Result<Self> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "not supported on this platform",
+            ))
+        }
+    }
+}
+
+/// Keeps the computer awake for an operation driven by the frontend, such as an engine match,
+/// until `release_keep_awake` is called with the returned id.
+#[tauri::command]
+#[specta::specta]
+pub fn acquire_keep_awake(reason: String, state: tauri::State<'_, AppState>) -> Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    state.keep_awake.insert(id.clone(), keep_awake(&reason));
+    Ok(id)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn release_keep_awake(id: String, state: tauri::State<'_, AppState>) -> Result<()> {
+    state.keep_awake.remove(&id);
+    Ok(())
+}
@@ -4,7 +4,9 @@ use tauri::{App, Manager, Window};
 use crate::AppState;
 
 pub mod desktop;
+pub mod keep_awake;
 pub mod mobile;
+pub mod power;
 pub mod shared;
 
 #[tauri::command]
@@ -76,11 +78,16 @@ pub fn setup_tauri_plugins(
     builder: tauri::Builder<tauri::Wry>,
     specta_builder: &tauri_specta::Builder,
 ) -> tauri::Builder<tauri::Wry> {
+    // A second launch has to be caught before any other plugin sets up
+    #[cfg(desktop)]
+    let builder = builder.plugin(crate::instance::plugin());
+
     let builder = builder
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_os::init())
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(
             tauri_plugin_log::Builder::new()
                 .targets([
@@ -0,0 +1,205 @@
+//! Battery-aware throttling of engines and background jobs.
+//!
+//! On battery below `LOW_BATTERY_PERCENT`, engines are limited to `THROTTLED_THREADS` search
+//! threads and background jobs pause between units of work, so a long analysis does not drain a
+//! phone. The battery is read from the kernel's power supply class on Android; where it cannot be
+//! read (iOS, desktops) the automatic policy never throttles. `set_power_policy` overrides the
+//! automatic choice in both directions.
+
+use std::{
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{chess::EngineOption, error::Result};
+
+const LOW_BATTERY_PERCENT: u8 = 30;
+const THROTTLED_THREADS: u32 = 1;
+/// Pause between units of background work while throttled.
+const BACKGROUND_PAUSE: Duration = Duration::from_secs(2);
+/// How long a battery reading is reused.
+const BATTERY_CACHE_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum PowerPolicy {
+    /// Throttle on battery below the threshold.
+    Auto,
+    /// Never throttle.
+    Performance,
+    /// Always throttle.
+    PowerSaver,
+}
+
+impl PowerPolicy {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Performance,
+            2 => Self::PowerSaver,
+            _ => Self::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+pub struct BatteryStatus {
+    pub percent: u8,
+    pub charging: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct PowerStatus {
+    pub policy: PowerPolicy,
+    pub battery: Option<BatteryStatus>,
+    pub throttled: bool,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(PowerPolicy::Auto as u8);
+static BATTERY: Mutex<Option<(Instant, Option<BatteryStatus>)>> = Mutex::new(None);
+
+/// Parses the `capacity` and `status` attributes of a power supply.
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
+fn parse_battery(capacity: &str, status: &str) -> Option<BatteryStatus> {
+    let percent = capacity.trim().parse::<u8>().ok()?.min(100);
+    let charging = matches!(status.trim(), "Charging" | "Full");
+    Some(BatteryStatus { percent, charging })
+}
+
+#[cfg(target_os = "android")]
+fn read_battery() -> Option<BatteryStatus> {
+    let dir = std::path::Path::new("/sys/class/power_supply/battery");
+    let capacity = std::fs::read_to_string(dir.join("capacity")).ok()?;
+    let status = std::fs::read_to_string(dir.join("status")).ok()?;
+    parse_battery(&capacity, &status)
+}
+
+#[cfg(not(target_os = "android"))]
+fn read_battery() -> Option<BatteryStatus> {
+    None
+}
+
+fn battery() -> Option<BatteryStatus> {
+    let mut cached = BATTERY.lock().unwrap_or_else(|e| e.into_inner());
+    match *cached {
+        Some((read_at, status)) if read_at.elapsed() < BATTERY_CACHE_TTL => status,
+        _ => {
+            let status = read_battery();
+            *cached = Some((Instant::now(), status));
+            status
+        }
+    }
+}
+
+fn policy() -> PowerPolicy {
+    PowerPolicy::from_u8(POLICY.load(Ordering::Relaxed))
+}
+
+fn throttles(policy: PowerPolicy, battery: Option<BatteryStatus>) -> bool {
+    match policy {
+        PowerPolicy::Performance => false,
+        PowerPolicy::PowerSaver => true,
+        PowerPolicy::Auto => {
+            battery.is_some_and(|b| !b.charging && b.percent < LOW_BATTERY_PERCENT)
+        }
+    }
+}
+
+/// Whether engines and background jobs should currently be throttled.
+pub fn is_throttled() -> bool {
+    throttles(policy(), battery())
+}
+
+/// Caps the `Threads` option of an engine while throttled.
+pub fn throttle_engine_options(options: &mut [EngineOption]) {
+    if !is_throttled() {
+        return;
+    }
+    for option in options.iter_mut().filter(|o| o.name == "Threads") {
+        if option
+            .value
+            .parse::<u32>()
+            .map_or(true, |t| t > THROTTLED_THREADS)
+        {
+            option.value = THROTTLED_THREADS.to_string();
+        }
+    }
+}
+
+/// Called by background jobs between units of work; pauses while throttled.
+pub async fn pace_background_job() {
+    if is_throttled() {
+        tokio::time::sleep(BACKGROUND_PAUSE).await;
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_power_policy(policy: PowerPolicy) -> Result<()> {
+    log::info!("Power policy set to {:?}", policy);
+    POLICY.store(policy as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_power_status() -> Result<PowerStatus> {
+    let policy = policy();
+    let battery = battery();
+    Ok(PowerStatus {
+        policy,
+        battery,
+        throttled: throttles(policy, battery),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_battery() {
+        assert_eq!(
+            parse_battery("25\n", "Discharging\n"),
+            Some(BatteryStatus {
+                percent: 25,
+                charging: false
+            })
+        );
+        assert!(parse_battery("100", "Full").unwrap().charging);
+        assert_eq!(parse_battery("", "Charging"), None);
+    }
+
+    #[test]
+    fn test_throttles() {
+        let low = Some(BatteryStatus {
+            percent: 20,
+            charging: false,
+        });
+        let charging = Some(BatteryStatus {
+            percent: 20,
+            charging: true,
+        });
+        assert!(throttles(PowerPolicy::Auto, low));
+        assert!(!throttles(PowerPolicy::Auto, charging));
+        assert!(!throttles(PowerPolicy::Auto, None));
+        assert!(!throttles(PowerPolicy::Performance, low));
+        assert!(throttles(PowerPolicy::PowerSaver, None));
+    }
+
+    #[test]
+    fn test_policy_round_trip() {
+        for policy in [
+            PowerPolicy::Auto,
+            PowerPolicy::Performance,
+            PowerPolicy::PowerSaver,
+        ] {
+            assert_eq!(PowerPolicy::from_u8(policy as u8), policy);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Crash detection and safe-mode startup.
+//!
+//! A sentinel file is written to the app data directory on startup and removed on a clean
+//! exit. If the sentinel is still present on the next launch the previous run crashed, and the
+//! app starts in safe mode: the frontend skips session restore and engine auto-start, work the app
+//! would start on its own (startup telemetry, the starter pack download and explorer prefetching)
+//! is not launched, and recently used databases can be integrity-checked before the user leaves
+//! safe mode. Imports, analyses and other jobs the user starts still run.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use diesel::{sql_query, Connection, QueryableByName, RunQueryDsl, SqliteConnection};
+use serde::Serialize;
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager, Runtime};
+
+use crate::error::Error;
+
+const SENTINEL_FILE: &str = "running.lock";
+/// Forces safe mode regardless of how the previous run ended.
+const SAFE_MODE_ENV: &str = "PAWN_APPETIT_SAFE_MODE";
+
+static PREVIOUS_RUN_CRASHED: AtomicBool = AtomicBool::new(false);
+static SAFE_MODE: AtomicBool = AtomicBool::new(false);
+
+fn sentinel_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, Error> {
+    Ok(app.path().resolve(SENTINEL_FILE, BaseDirectory::AppData)?)
+}
+
+/// Detect an unclean previous shutdown and arm the sentinel for this run.
+pub fn init<R: Runtime>(app: &AppHandle<R>) -> Result<(), Error> {
+    let path = sentinel_path(app)?;
+    let crashed = path.exists();
+    let forced = std::env::var(SAFE_MODE_ENV).map(|v| v == "1").unwrap_or(false);
+
+    PREVIOUS_RUN_CRASHED.store(crashed, Ordering::Relaxed);
+    SAFE_MODE.store(crashed || forced, Ordering::Relaxed);
+
+    if crashed {
+        log::warn!("Previous run did not shut down cleanly, starting in safe mode");
+    } else if forced {
+        log::info!("Safe mode forced via {}", SAFE_MODE_ENV);
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, std::process::id().to_string())?;
+    Ok(())
+}
+
+/// Remove the sentinel so the next launch starts normally.
+pub fn mark_clean_exit<R: Runtime>(app: &AppHandle<R>) {
+    match sentinel_path(app) {
+        Ok(path) => {
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to remove {}: {}", path.display(), e);
+                }
+            }
+        }
+        Err(e) => log::warn!("Failed to resolve sentinel path: {}", e),
+    }
+}
+
+pub fn is_safe_mode() -> bool {
+    SAFE_MODE.load(Ordering::Relaxed)
+}
+
+/// Fails with `Error::SafeMode` while in safe mode, for work the app starts on its own rather than
+/// at the user's request.
+pub fn check_background_work_allowed() -> Result<(), Error> {
+    if is_safe_mode() {
+        return Err(Error::SafeMode);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupStatus {
+    pub previous_run_crashed: bool,
+    pub safe_mode: bool,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_startup_status() -> StartupStatus {
+    StartupStatus {
+        previous_run_crashed: PREVIOUS_RUN_CRASHED.load(Ordering::Relaxed),
+        safe_mode: is_safe_mode(),
+    }
+}
+
+/// Leave safe mode once the user has confirmed the checks; automatic work runs again the next time
+/// the frontend asks for it.
+#[tauri::command]
+#[specta::specta]
+pub fn exit_safe_mode() {
+    SAFE_MODE.store(false, Ordering::Relaxed);
+    log::info!("Leaving safe mode");
+}
+
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseIntegrity {
+    pub path: PathBuf,
+    pub ok: bool,
+    pub problems: Vec<String>,
+}
+
+#[derive(QueryableByName)]
+struct QuickCheckRow {
+    #[diesel(sql_type = diesel::sql_types::Text, column_name = "quick_check")]
+    quick_check: String,
+}
+
+fn check_database(path: &PathBuf) -> DatabaseIntegrity {
+    let problems = if !path.exists() {
+        vec!["Database file not found".to_string()]
+    } else {
+        match SqliteConnection::establish(&path.to_string_lossy()) {
+            Ok(mut conn) => match sql_query("PRAGMA quick_check").load::<QuickCheckRow>(&mut conn) {
+                Ok(rows) => rows
+                    .into_iter()
+                    .map(|r| r.quick_check)
+                    .filter(|r| r != "ok")
+                    .collect(),
+                Err(e) => vec![e.to_string()],
+            },
+            Err(e) => vec![e.to_string()],
+        }
+    };
+
+    DatabaseIntegrity {
+        path: path.clone(),
+        ok: problems.is_empty(),
+        problems,
+    }
+}
+
+/// Run `PRAGMA quick_check` on the given databases, typically the recently used ones.
+/// Connections are opened directly rather than through the pool so that a corrupt file is
+/// never cached in `AppState`.
+#[tauri::command]
+#[specta::specta]
+pub async fn check_databases_integrity(paths: Vec<PathBuf>) -> Result<Vec<DatabaseIntegrity>, Error> {
+    let results = tokio::task::spawn_blocking(move || paths.iter().map(check_database).collect())
+        .await
+        .map_err(|e| Error::PackageManager(format!("Integrity check task failed: {}", e)))?;
+    Ok(results)
+}
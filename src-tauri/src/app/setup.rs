@@ -1,7 +1,7 @@
 use tauri::App;
 
 use crate::telemetry::handle_initial_run_telemetry;
-use crate::app::platform;
+use crate::app::{platform, safe_mode};
 
 /// Shared app setup logic for both desktop and mobile
 pub fn setup_tauri_app(app: &App, specta_builder: &tauri_specta::Builder) -> Result<(), Box<dyn std::error::Error>> {
@@ -9,9 +9,41 @@ pub fn setup_tauri_app(app: &App, specta_builder: &tauri_specta::Builder) -> Res
 
     platform::init_platform(app)?;
 
+    if let Err(e) = crate::crash::install_panic_hook(app.handle()) {
+        log::warn!("Crash report handler installation failed: {}", e);
+    }
+
+    if let Err(e) = safe_mode::init(app.handle()) {
+        log::warn!("Crash sentinel initialization failed: {}", e);
+    }
+
     specta_builder.mount_events(app);
 
+    if let Err(e) = crate::deep_link::init(app) {
+        log::warn!("Deep link handler initialization failed: {}", e);
+    }
+
+    if let Err(e) = crate::friendly_names::init(app.handle()) {
+        log::warn!("Friendly name overrides could not be loaded: {}", e);
+    }
+
+    if let Err(e) = crate::webhook::init(app.handle()) {
+        log::warn!("Webhook configuration could not be loaded: {}", e);
+    }
+
+    if let Err(e) = crate::notation::init(app.handle()) {
+        log::warn!("Notation preference could not be loaded: {}", e);
+    }
+
+    if let Err(e) = crate::metrics::init(app.handle()) {
+        log::warn!("Performance metrics initialization failed: {}", e);
+    }
+
     log::info!("Finished tauri application initialization");
+    if safe_mode::is_safe_mode() {
+        log::info!("Safe mode active, skipping startup telemetry");
+        return Ok(());
+    }
     if let Err(e) = handle_initial_run_telemetry(app.handle()) {
         log::warn!("Telemetry initial run handling failed: {}", e);
     }
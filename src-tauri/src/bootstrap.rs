@@ -0,0 +1,564 @@
+//! First-run starter pack.
+//!
+//! A fresh install has no games, no puzzles and no engine, and each of them normally takes its own
+//! trip through the add dialogs. `bootstrap_content` sets up all three in one go: a reference
+//! database of master games, the Lichess puzzle database imported from its CSV export, and
+//! Stockfish registered in `engines/engines.json`. The whole pack reports a single `TaskProgress`
+//! with the id `bootstrap`.
+//!
+//! A run cut short can be started again: finished items are recorded in `bootstrap.json` in the
+//! app config directory and skipped, and downloads are written to `.part` files that are resumed
+//! with range requests.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    db::{DatabaseKind, DatabaseRef},
+    error::{Error, Result},
+    fs::{extract_tar_file_from_path, set_file_as_executable, unzip_file_from_path},
+    http::Request,
+    progress::{TaskKind, TaskProgress},
+    puzzle::import_puzzle_file,
+};
+
+const PROGRESS_ID: &str = "bootstrap";
+
+const REFERENCE_DB_URL: &str = "https://pub-561e4f3376ea4e4eb2ffd01a876ba46e.r2.dev/mb-3.db3";
+const REFERENCE_DB_FILE: &str = "MillionBase.db3";
+
+const PUZZLES_URL: &str = "https://database.lichess.org/lichess_db_puzzle.csv.zst";
+const PUZZLES_TITLE: &str = "Lichess Puzzles";
+const PUZZLES_DESCRIPTION: &str = "A collection of all puzzles from Lichess.org";
+
+const STOCKFISH_RELEASE: &str =
+    "https://github.com/official-stockfish/Stockfish/releases/latest/download";
+const STOCKFISH_VERSION: &str = "17.1";
+const STOCKFISH_IMAGE: &str = "https://upload.wikimedia.org/wikipedia/commons/3/3a/NewLogoSF.png";
+const STOCKFISH_ELO: u32 = 3635;
+
+/// The reference database is several hundred megabytes, which takes a while on a slow line.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum StarterItem {
+    ReferenceDatabase,
+    Puzzles,
+    Engine,
+}
+
+const ALL_ITEMS: [StarterItem; 3] = [
+    StarterItem::ReferenceDatabase,
+    StarterItem::Puzzles,
+    StarterItem::Engine,
+];
+
+impl StarterItem {
+    fn label(self) -> &'static str {
+        match self {
+            StarterItem::ReferenceDatabase => "reference database",
+            StarterItem::Puzzles => "puzzle database",
+            StarterItem::Engine => "engine",
+        }
+    }
+
+    /// Share of the pack's progress, roughly in proportion to the time the item takes.
+    fn weight(self) -> f64 {
+        match self {
+            StarterItem::ReferenceDatabase => 0.5,
+            StarterItem::Puzzles => 0.4,
+            StarterItem::Engine => 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BootstrapState {
+    completed: Vec<StarterItem>,
+}
+
+impl BootstrapState {
+    fn get_config_path(app: &AppHandle) -> Result<PathBuf> {
+        Ok(app
+            .path()
+            .resolve("bootstrap.json", BaseDirectory::AppConfig)?)
+    }
+
+    fn load(app: &AppHandle) -> Result<Self> {
+        let config_path = Self::get_config_path(app)?;
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&config_path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::PackageManager(format!("Invalid bootstrap state: {}", e)))
+    }
+
+    fn save(&self, app: &AppHandle) -> Result<()> {
+        let config_path = Self::get_config_path(app)?;
+        if let Some(parent) = config_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| {
+            Error::PackageManager(format!("Failed to serialize bootstrap state: {}", e))
+        })?;
+        std::fs::write(&config_path, json)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapStatus {
+    pub completed: Vec<StarterItem>,
+    /// Items still to set up. Empty once the starter pack has been installed or declined item by
+    /// item, so the frontend only offers it when there is something left.
+    pub pending: Vec<StarterItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapSummary {
+    pub installed: Vec<StarterItem>,
+    /// Items that were already set up and left alone.
+    pub skipped: Vec<StarterItem>,
+    /// Items with nothing to install on this platform, e.g. no Stockfish build.
+    pub unavailable: Vec<StarterItem>,
+}
+
+/// Clears the running flag when a run ends, however it ends.
+struct RunGuard;
+
+impl RunGuard {
+    fn acquire() -> Result<Self> {
+        RUNNING
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .map(|_| RunGuard)
+            .map_err(|_| Error::PackageManager("The starter pack is already being set up".into()))
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Combined progress of the items being set up.
+struct PackProgress<'a> {
+    app: &'a AppHandle,
+    /// Weight of the items finished so far.
+    done: f64,
+    total: f64,
+}
+
+impl PackProgress<'_> {
+    fn report(&self, item: StarterItem, fraction: f64, message: impl Into<String>) {
+        TaskProgress::new(
+            TaskKind::Download,
+            PROGRESS_ID,
+            pack_percent(self.done, item.weight() * fraction, self.total),
+        )
+        .message(message)
+        .send(self.app);
+    }
+}
+
+fn pack_percent(done: f64, current: f64, total: f64) -> f64 {
+    if total <= 0.0 {
+        return 100.0;
+    }
+    ((done + current) / total * 100.0).clamp(0.0, 100.0)
+}
+
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Downloads `url` to `dest` through `dest.part`, continuing a partial download left by an earlier
+/// run. `on_progress` gets the downloaded fraction when the size is known.
+async fn download_resumable(
+    url: &str,
+    dest: &Path,
+    mut on_progress: impl FnMut(f64),
+) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let part = part_path(dest);
+    let mut offset = tokio::fs::metadata(&part)
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+
+    let mut request = Request::get(url).timeout(DOWNLOAD_TIMEOUT);
+    if offset > 0 {
+        request = request.header("Range", format!("bytes={}-", offset));
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        // The part file is complete; the previous run stopped before renaming it.
+        tokio::fs::rename(&part, dest).await?;
+        return Ok(());
+    }
+    if !status.is_success() {
+        return Err(Error::HttpStatus(status.as_u16()));
+    }
+    if status != StatusCode::PARTIAL_CONTENT {
+        // The server ignored the range, start over.
+        offset = 0;
+    }
+    let total = response.content_length().map(|len| len + offset);
+
+    let mut options = tokio::fs::OpenOptions::new();
+    if offset > 0 {
+        options.append(true);
+    } else {
+        options.write(true).create(true).truncate(true);
+    }
+    let mut file = options.open(&part).await?;
+    let mut downloaded = offset;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        if crate::shutdown::is_shutting_down() {
+            return Err(Error::PackageManager("Download interrupted".into()));
+        }
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        downloaded += chunk.len() as u64;
+        if let Some(total) = total {
+            on_progress((downloaded as f64 / total as f64).min(1.0));
+        }
+    }
+    file.sync_all().await?;
+    drop(file);
+
+    tokio::fs::rename(&part, dest).await?;
+    Ok(())
+}
+
+fn reference_db_path(app: &AppHandle) -> Result<PathBuf> {
+    DatabaseRef::Name(REFERENCE_DB_FILE.to_string()).resolve(app, DatabaseKind::Games)
+}
+
+async fn install_reference_database(app: &AppHandle, progress: &PackProgress<'_>) -> Result<()> {
+    let item = StarterItem::ReferenceDatabase;
+    progress.report(item, 0.0, "Downloading the reference database");
+    download_resumable(REFERENCE_DB_URL, &reference_db_path(app)?, |fraction| {
+        progress.report(item, fraction, "Downloading the reference database")
+    })
+    .await
+}
+
+async fn install_puzzles(app: &AppHandle, progress: &PackProgress<'_>) -> Result<()> {
+    let item = StarterItem::Puzzles;
+    let csv = app
+        .path()
+        .app_cache_dir()?
+        .join("lichess_db_puzzle.csv.zst");
+    progress.report(item, 0.0, "Downloading the Lichess puzzles");
+    // The download is about half of the work, the import the rest.
+    download_resumable(PUZZLES_URL, &csv, |fraction| {
+        progress.report(item, fraction * 0.5, "Downloading the Lichess puzzles")
+    })
+    .await?;
+
+    progress.report(item, 0.5, "Importing the Lichess puzzles");
+    let db_path =
+        DatabaseRef::Name(format!("{}.db3", PUZZLES_TITLE)).resolve(app, DatabaseKind::Puzzles)?;
+    import_puzzle_file(
+        csv.clone(),
+        db_path,
+        PUZZLES_TITLE.to_string(),
+        Some(PUZZLES_DESCRIPTION.to_string()),
+        None,
+        app.clone(),
+    )
+    .await?;
+    let _ = std::fs::remove_file(&csv);
+    Ok(())
+}
+
+/// Release asset and path of the binary inside it of the Stockfish build for `os`, matching the
+/// builds offered in the engine dialog. Only x86-64 builds are offered there.
+fn stockfish_build(os: &str, bmi2: bool) -> Option<(&'static str, &'static str)> {
+    match (os, bmi2) {
+        ("windows", true) => Some((
+            "stockfish-windows-x86-64-avx2.zip",
+            "stockfish/stockfish-windows-x86-64-avx2.exe",
+        )),
+        ("windows", false) => Some((
+            "stockfish-windows-x86-64-sse41-popcnt.zip",
+            "stockfish/stockfish-windows-x86-64-sse41-popcnt.exe",
+        )),
+        ("linux", true) => Some((
+            "stockfish-ubuntu-x86-64-avx2.tar",
+            "stockfish/stockfish-ubuntu-x86-64-avx2",
+        )),
+        ("linux", false) => Some((
+            "stockfish-ubuntu-x86-64-sse41-popcnt.tar",
+            "stockfish/stockfish-ubuntu-x86-64-sse41-popcnt",
+        )),
+        _ => None,
+    }
+}
+
+fn local_stockfish_build() -> Option<(&'static str, &'static str)> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        stockfish_build(
+            std::env::consts::OS,
+            std::arch::is_x86_feature_detected!("bmi2"),
+        )
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        None
+    }
+}
+
+fn engines_config_path(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app
+        .path()
+        .resolve("engines/engines.json", BaseDirectory::AppData)?)
+}
+
+fn load_engines(app: &AppHandle) -> Result<Vec<serde_json::Value>> {
+    let path = engines_config_path(app)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    serde_json::from_str(&content)
+        .map_err(|e| Error::PackageManager(format!("Invalid engines.json: {}", e)))
+}
+
+fn has_local_engine(engines: &[serde_json::Value]) -> bool {
+    engines
+        .iter()
+        .any(|engine| engine.get("type").and_then(|t| t.as_str()) == Some("local"))
+}
+
+/// Adds Stockfish at `binary` to the engines list the frontend reads, unless it is there already.
+fn register_engine(app: &AppHandle, binary: &Path, download_link: &str) -> Result<()> {
+    let mut engines = load_engines(app)?;
+    let path = binary.to_string_lossy();
+    if engines
+        .iter()
+        .any(|engine| engine.get("path").and_then(|p| p.as_str()) == Some(&path))
+    {
+        return Ok(());
+    }
+    engines.push(serde_json::json!({
+        "type": "local",
+        "name": "Stockfish",
+        "version": STOCKFISH_VERSION,
+        "path": path,
+        "image": STOCKFISH_IMAGE,
+        "elo": STOCKFISH_ELO,
+        "installMethod": "download",
+        "downloadLink": download_link,
+        "loaded": true,
+    }));
+    let json = serde_json::to_string(&engines)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize engines: {}", e)))?;
+    let config_path = engines_config_path(app)?;
+    if let Some(parent) = config_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&config_path, json)?;
+    Ok(())
+}
+
+async fn install_engine(
+    app: &AppHandle,
+    progress: &PackProgress<'_>,
+    asset: &str,
+    binary: &str,
+) -> Result<()> {
+    let item = StarterItem::Engine;
+    let url = format!("{}/{}", STOCKFISH_RELEASE, asset);
+    let archive = app.path().app_cache_dir()?.join(asset);
+    progress.report(item, 0.0, "Downloading Stockfish");
+    download_resumable(&url, &archive, |fraction| {
+        progress.report(item, fraction * 0.9, "Downloading Stockfish")
+    })
+    .await?;
+
+    progress.report(item, 0.9, "Installing Stockfish");
+    let engines_dir = app.path().resolve("engines", BaseDirectory::AppData)?;
+    let dest = engines_dir.clone();
+    let source = archive.clone();
+    tokio::task::spawn_blocking(move || {
+        if source.extension().is_some_and(|ext| ext == "zip") {
+            unzip_file_from_path(&dest, &source)
+        } else {
+            extract_tar_file_from_path(&dest, &source, false)
+        }
+    })
+    .await
+    .map_err(|e| Error::PackageManager(format!("Extraction task failed: {}", e)))??;
+    let _ = std::fs::remove_file(&archive);
+
+    let binary = binary
+        .split('/')
+        .fold(engines_dir, |path, segment| path.join(segment));
+    set_file_as_executable(binary.to_string_lossy().into_owned()).await?;
+    register_engine(app, &binary, &url)
+}
+
+/// Whether `item` is set up, by the starter pack or by hand.
+fn is_installed(app: &AppHandle, state: &BootstrapState, item: StarterItem) -> Result<bool> {
+    if state.completed.contains(&item) {
+        return Ok(true);
+    }
+    Ok(match item {
+        // Only renamed into place once complete.
+        StarterItem::ReferenceDatabase => reference_db_path(app)?.exists(),
+        // An interrupted import leaves a partial database behind, so only the state counts.
+        StarterItem::Puzzles => false,
+        StarterItem::Engine => has_local_engine(&load_engines(app)?),
+    })
+}
+
+/// Which items of the starter pack are set up and which are still to do.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_bootstrap_status(app: AppHandle) -> Result<BootstrapStatus> {
+    let state = BootstrapState::load(&app)?;
+    let mut status = BootstrapStatus {
+        completed: Vec::new(),
+        pending: Vec::new(),
+    };
+    for item in ALL_ITEMS {
+        if is_installed(&app, &state, item)? {
+            status.completed.push(item);
+        } else {
+            status.pending.push(item);
+        }
+    }
+    Ok(status)
+}
+
+/// Download and set up the starter pack, or the given `items` of it. Items already set up are
+/// skipped, and a run cut short can be resumed by calling this again.
+#[tauri::command]
+#[specta::specta]
+pub async fn bootstrap_content(
+    items: Option<Vec<StarterItem>>,
+    app: AppHandle,
+) -> Result<BootstrapSummary> {
+    crate::app::safe_mode::check_background_work_allowed()?;
+    let _guard = RunGuard::acquire()?;
+    let _job = crate::shutdown::start_job();
+    let mut state = BootstrapState::load(&app)?;
+    let requested = items.unwrap_or_else(|| ALL_ITEMS.to_vec());
+
+    let mut summary = BootstrapSummary {
+        installed: Vec::new(),
+        skipped: Vec::new(),
+        unavailable: Vec::new(),
+    };
+    let mut todo = Vec::new();
+    for item in ALL_ITEMS
+        .into_iter()
+        .filter(|item| requested.contains(item))
+    {
+        if is_installed(&app, &state, item)? {
+            summary.skipped.push(item);
+        } else if item == StarterItem::Engine && local_stockfish_build().is_none() {
+            summary.unavailable.push(item);
+        } else {
+            todo.push(item);
+        }
+    }
+
+    let mut progress = PackProgress {
+        app: &app,
+        done: 0.0,
+        total: todo.iter().map(|item| item.weight()).sum(),
+    };
+    for item in todo {
+        log::info!("Setting up the starter {}", item.label());
+        match item {
+            StarterItem::ReferenceDatabase => install_reference_database(&app, &progress).await?,
+            StarterItem::Puzzles => install_puzzles(&app, &progress).await?,
+            StarterItem::Engine => {
+                if let Some((asset, binary)) = local_stockfish_build() {
+                    install_engine(&app, &progress, asset, binary).await?;
+                }
+            }
+        }
+        state.completed.push(item);
+        state.save(&app)?;
+        summary.installed.push(item);
+        progress.done += item.weight();
+    }
+
+    TaskProgress::done(TaskKind::Download, PROGRESS_ID)
+        .message(format!(
+            "{} of {} items set up",
+            summary.installed.len() + summary.skipped.len(),
+            requested.len()
+        ))
+        .send(&app);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_percent() {
+        assert_eq!(pack_percent(0.0, 0.0, 1.0), 0.0);
+        assert_eq!(pack_percent(0.5, 0.25, 1.0), 75.0);
+        assert_eq!(pack_percent(0.1, 0.0, 0.2), 50.0);
+        assert_eq!(pack_percent(0.0, 0.0, 0.0), 100.0);
+    }
+
+    #[test]
+    fn test_stockfish_build() {
+        assert_eq!(
+            stockfish_build("linux", true),
+            Some((
+                "stockfish-ubuntu-x86-64-avx2.tar",
+                "stockfish/stockfish-ubuntu-x86-64-avx2"
+            ))
+        );
+        assert!(stockfish_build("windows", false)
+            .unwrap()
+            .1
+            .ends_with("sse41-popcnt.exe"));
+        assert_eq!(stockfish_build("macos", true), None);
+    }
+
+    #[test]
+    fn test_engine_list_helpers() {
+        assert_eq!(
+            part_path(Path::new("/data/db/MillionBase.db3")),
+            PathBuf::from("/data/db/MillionBase.db3.part")
+        );
+        let remote = serde_json::json!({ "type": "lichess", "name": "Lichess", "url": "" });
+        let local = serde_json::json!({ "type": "local", "name": "Stockfish", "path": "/sf" });
+        assert!(!has_local_engine(&[remote.clone()]));
+        assert!(has_local_engine(&[remote, local]));
+    }
+}
@@ -8,8 +8,9 @@ use std::path::PathBuf;
 use shakmaty::{fen::Fen, uci::UciMove, CastlingMode, Chess, EnPassantMode, Position};
 use vampirc_uci::parse_one;
 
-use crate::db::{is_position_in_db, GameQueryJs, PositionQueryJs};
+use crate::db::{is_position_in_db, GameQueryJs, PositionPresence, PositionQueryJs};
 use crate::error::Error;
+use crate::progress::{TaskKind, TaskProgress};
 use crate::AppState;
 
 use super::evaluation::naive_eval;
@@ -46,6 +47,10 @@ impl GameAnalysisService {
         state: tauri::State<'_, AppState>,
         app: tauri::AppHandle,
     ) -> Result<Vec<MoveAnalysis>, Error> {
+        let job = state
+            .jobs
+            .start(&app, TaskKind::Analysis, id.clone(), "Analyzing a game");
+        let _awake = crate::app::platform::keep_awake::keep_awake("Analyzing a game");
         let path = PathBuf::from(&engine);
         let mut analysis: Vec<MoveAnalysis> = Vec::new();
 
@@ -83,7 +88,13 @@ impl GameAnalysisService {
 
         // Analyze each position using the engine, reporting progress.
         for (i, (_, moves, _)) in fens.iter().enumerate() {
-            ReportProgress { progress: (i as f64 / fens.len() as f64) * 100.0, id: id.clone(), finished: false }.emit(&app)?;
+            if job.is_cancelled() {
+                proc.quit(std::time::Duration::from_millis(500)).await;
+                return Err(Error::JobCancelled);
+            }
+            let progress = (i as f64 / fens.len() as f64) * 100.0;
+            ReportProgress { progress, id: id.clone(), finished: false }.emit(&app)?;
+            TaskProgress::new(TaskKind::Analysis, id.clone(), progress).send(&app);
 
             // Ensure MultiPV=2 for principal variation analysis.
             let mut extra_options = uci_options.clone();
@@ -136,12 +147,19 @@ impl GameAnalysisService {
         // Annotate sacrifices and novelties for each analyzed position.
         for (i, analysis) in analysis.iter_mut().enumerate() {
             let fen = &fens[i].0;
-            let query = PositionQueryJs { fen: fen.to_string(), type_: "exact".to_string() };
+            let query = PositionQueryJs {
+                fen: fen.to_string(),
+                type_: "exact".to_string(),
+                include_mirrored: None,
+                constraints: None,
+            };
 
             analysis.is_sacrifice = fens[i].2;
             if options.annotate_novelties && !novelty_found {
                 if let Some(reference) = options.reference_db.clone() {
-                    analysis.novelty = !is_position_in_db(reference, GameQueryJs::new().position(query.clone()).clone(), state.clone()).await?;
+                    // A check that timed out or was cancelled does not count as a novelty.
+                    let presence = is_position_in_db(reference, GameQueryJs::new().position(query.clone()).clone(), None, state.clone()).await?;
+                    analysis.novelty = presence == PositionPresence::NotFound;
                     if analysis.novelty { novelty_found = true; }
                 } else {
                     return Err(Error::MissingReferenceDatabase);
@@ -150,6 +168,7 @@ impl GameAnalysisService {
         }
 
         ReportProgress { progress: 100.0, id: id.clone(), finished: true }.emit(&app)?;
+        TaskProgress::done(TaskKind::Analysis, id.clone()).send(&app);
         Ok(analysis)
     }
 }
@@ -3,8 +3,12 @@
 //! This module exposes async functions as Tauri commands for engine process control, game analysis, and engine configuration.
 //! It acts as the bridge between the frontend and backend chess logic.
 
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
+use tauri_specta::Event;
 use vampirc_uci::parse_one;
 
 use crate::error::Error;
@@ -96,10 +100,73 @@ pub async fn get_best_moves(
     tab: String,
     go_mode: GoMode,
     options: EngineOptions,
+    emission: Option<EmissionOptions>,
     app: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
 ) -> Result<Option<(f32, Vec<BestMoves>)>, Error> {
-    EngineManager::new(state).get_best_moves(id, engine, tab, go_mode, options, app).await
+    // Tablebase positions are answered exactly, without the engine.
+    if let Some(lines) = super::tablebase::best_lines(&app, &state, &options)? {
+        // Stop the search of the previous position so its lines don't replace these.
+        if let Some(process) = state.engine_processes.get(&(tab.clone(), engine.clone())) {
+            process.lock().await.stop().await?;
+        }
+        BestMovesPayload {
+            best_lines: lines.clone(),
+            engine: id,
+            tab,
+            fen: options.fen,
+            moves: options.moves,
+            progress: 100.0,
+        }
+        .emit(&app)?;
+        return Ok(Some((100.0, lines)));
+    }
+    EngineManager::new(state)
+        .get_best_moves(id, engine, tab, go_mode, options, emission.unwrap_or_default(), app)
+        .await
+}
+
+/// Change a UCI option of a running engine without restarting it. `session` limits a `MultiPV`
+/// change to that analysis session.
+#[tauri::command]
+#[specta::specta]
+pub async fn set_engine_option(
+    engine: String,
+    tab: String,
+    option: EngineOption,
+    session: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), Error> {
+    EngineManager::new(state)
+        .set_engine_option(engine, tab, option, session)
+        .await
+}
+
+/// End an analysis session started with `get_best_moves`, leaving the engine to its other sessions.
+#[tauri::command]
+#[specta::specta]
+pub async fn end_analysis_session(
+    id: String,
+    engine: String,
+    tab: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), Error> {
+    EngineManager::new(state)
+        .end_analysis_session(id, engine, tab)
+        .await
+}
+
+/// Let an engine that finished its search think on the opponent's time. Requesting the
+/// pondered position with `get_best_moves` afterwards turns the search into a normal one.
+#[tauri::command]
+#[specta::specta]
+pub async fn start_pondering(
+    engine: String,
+    tab: String,
+    go_mode: GoMode,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<String>, Error> {
+    EngineManager::new(state).start_pondering(engine, tab, go_mode).await
 }
 
 /// Analyze a game using the engine, returning move-by-move analysis.
@@ -122,10 +189,16 @@ pub async fn analyze_game(
 #[tauri::command]
 #[specta::specta]
 pub async fn get_engine_config(path: PathBuf) -> Result<EngineConfig, Error> {
+    probe_engine(&path, Duration::from_secs(5)).await
+}
+
+/// Start the engine at `path`, send `uci` and collect its name and options until `uciok`.
+/// The process is killed afterwards; engines that do not answer within `limit` time out.
+pub(crate) async fn probe_engine(path: &Path, limit: Duration) -> Result<EngineConfig, Error> {
     use tokio::io::AsyncBufReadExt;
-    use tokio::time::{timeout, Duration};
+    use tokio::time::timeout;
 
-    let mut command = tokio::process::Command::new(&path);
+    let mut command = tokio::process::Command::new(path);
     // FIXED: Safe parent path handling
     if let Some(parent) = path.parent() {
         command.current_dir(parent);
@@ -149,23 +222,25 @@ pub async fn get_engine_config(path: PathBuf) -> Result<EngineConfig, Error> {
     // FIXED: Add timeout to prevent hanging on unresponsive engines
     let config_future = async {
     loop {
-        if let Some(line) = stdout.next_line().await? {
-                if let vampirc_uci::UciMessage::Id { name: Some(name), author: _ } = parse_one(&line) { 
-                    config.name = name; 
-                }
-                if let vampirc_uci::UciMessage::Option(opt) = parse_one(&line) { 
-                    config.options.push(opt); 
-                }
-                if let vampirc_uci::UciMessage::UciOk = parse_one(&line) { 
-                    break; 
-                }
+        // Programs that are not engines usually exit right away instead of answering.
+        let Some(line) = stdout.next_line().await? else {
+            return Err(Error::NoStdout);
+        };
+        if let vampirc_uci::UciMessage::Id { name: Some(name), author: _ } = parse_one(&line) { 
+            config.name = name; 
+        }
+        if let vampirc_uci::UciMessage::Option(opt) = parse_one(&line) { 
+            config.options.push(opt); 
+        }
+        if let vampirc_uci::UciMessage::UciOk = parse_one(&line) { 
+            break; 
         }
     }
         Ok::<_, Error>(config)
     };
     
-    // FIXED: 5 second timeout and ensure process cleanup
-    let result = timeout(Duration::from_secs(5), config_future).await;
+    // FIXED: Timeout and ensure process cleanup
+    let result = timeout(limit, config_future).await;
     
     // FIXED: Always kill the child process to prevent zombies
     let _ = child.kill().await;
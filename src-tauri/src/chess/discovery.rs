@@ -0,0 +1,170 @@
+//! Discovery of UCI engines installed on disk.
+//!
+//! Common install locations are searched for binaries named after well-known engines, while
+//! folders given by the user and the app's own `engines` folder are searched for any executable.
+//! Every candidate is probed with `uci` under a short timeout and killed afterwards; only binaries
+//! that answer with `uciok` are returned, ready to be registered with their name and options.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use futures_util::future::join_all;
+use serde::Serialize;
+use specta::Type;
+use tauri::{path::BaseDirectory, Manager};
+use vampirc_uci::uci::UciOptionConfig;
+
+use crate::error::Error;
+
+use super::commands::probe_engine;
+
+/// Time an engine gets to answer `uci` while scanning.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Binaries probed per scan, so a folder full of executables cannot stall it.
+const MAX_PROBES: usize = 32;
+
+/// Folder levels searched below each location, e.g. `Program Files/Stockfish/stockfish.exe`.
+const MAX_DEPTH: usize = 2;
+
+/// Lowercase name fragments of engines looked for in common install locations. Fragments that
+/// also match ordinary programs (e.g. `fire` in `firefox`) are left out, since every match is run.
+const KNOWN_ENGINES: &[&str] = &[
+    "stockfish", "lc0", "leela", "komodo", "dragon", "berserk", "ethereal", "rubichess", "koivisto",
+    "caissa", "texel", "crafty", "arasan", "halogen", "viridithas", "alexandria",
+];
+
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineCandidate {
+    pub path: PathBuf,
+    pub name: String,
+    pub options: Vec<UciOptionConfig>,
+}
+
+/// Install locations searched on every scan, for binaries with a known engine name only.
+fn common_locations() -> Vec<PathBuf> {
+    let mut locations: Vec<PathBuf> = Vec::new();
+    if cfg!(windows) {
+        for var in ["ProgramFiles", "ProgramFiles(x86)", "LOCALAPPDATA"] {
+            if let Some(dir) = std::env::var_os(var) {
+                locations.push(PathBuf::from(dir));
+            }
+        }
+    } else {
+        for dir in ["/usr/bin", "/usr/local/bin", "/usr/games", "/opt/homebrew/bin", "/opt"] {
+            locations.push(PathBuf::from(dir));
+        }
+        if let Some(home) = std::env::var_os("HOME") {
+            locations.push(Path::new(&home).join(".local/bin"));
+        }
+    }
+    locations
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+            && path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("exe"))
+    }
+}
+
+fn has_known_name(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .is_some_and(|name| KNOWN_ENGINES.iter().any(|engine| name.contains(engine)))
+}
+
+/// Executables below `dir`, down to `MAX_DEPTH` levels. With `known_only`, only files named after
+/// a known engine are kept, and only folders named after one are entered past the first level.
+fn collect_candidates(dir: &Path, known_only: bool, depth: usize, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth + 1 < MAX_DEPTH && (!known_only || has_known_name(&path)) {
+                collect_candidates(&path, known_only, depth + 1, found);
+            }
+        } else if (!known_only || has_known_name(&path)) && is_executable(&path) {
+            found.push(path);
+        }
+    }
+}
+
+/// Search common locations, the app's engines folder and `paths` for UCI engines.
+#[tauri::command]
+#[specta::specta]
+pub async fn scan_for_engines(
+    paths: Vec<PathBuf>,
+    app: tauri::AppHandle,
+) -> Result<Vec<EngineCandidate>, Error> {
+    let mut found: Vec<PathBuf> = Vec::new();
+    for dir in common_locations() {
+        collect_candidates(&dir, true, 0, &mut found);
+    }
+    let engines_dir = app.path().resolve("engines", BaseDirectory::AppData)?;
+    for dir in std::iter::once(engines_dir).chain(paths) {
+        collect_candidates(&dir, false, 0, &mut found);
+    }
+
+    // The same binary is often reachable through several links, e.g. /usr/games and /usr/bin.
+    let mut seen = HashSet::new();
+    found.retain(|path| seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())));
+    if found.len() > MAX_PROBES {
+        log::warn!("Found {} engine candidates, probing the first {}", found.len(), MAX_PROBES);
+        found.truncate(MAX_PROBES);
+    }
+
+    let probes = found.iter().map(|path| probe_engine(path, PROBE_TIMEOUT));
+    let mut candidates: Vec<EngineCandidate> = found
+        .iter()
+        .zip(join_all(probes).await)
+        .filter_map(|(path, result)| match result {
+            Ok(config) => Some(EngineCandidate {
+                path: path.clone(),
+                name: if config.name.is_empty() {
+                    path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+                } else {
+                    config.name
+                },
+                options: config.options,
+            }),
+            Err(e) => {
+                log::debug!("{} is not a UCI engine: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.path.cmp(&b.path)));
+
+    log::info!("Engine scan found {} engines", candidates.len());
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_known_name() {
+        assert!(has_known_name(Path::new("/usr/games/stockfish")));
+        assert!(has_known_name(Path::new("C:/Engines/Lc0-v0.31/lc0.exe")));
+        assert!(has_known_name(Path::new("/opt/berserk-13-avx2")));
+        assert!(!has_known_name(Path::new("/usr/bin/python3")));
+    }
+}
@@ -0,0 +1,844 @@
+//! Automated engine-vs-engine matches.
+//!
+//! Two engines, the candidate and the baseline as in `match_stats`, play a number of games from
+//! the start position, a list of FENs or an opening book. Every opening is played twice with the
+//! colors reversed, so neither engine profits from a lopsided opening. Games are played with a
+//! clock (`GoMode::PlayersTime`), or with a fixed time, depth or node limit per move, and end by the
+//! rules of chess, on time, on an illegal move or after `max_plies`.
+//!
+//! Every finished game is appended to the PGN file of the match and reported through an
+//! `EngineMatchProgress` event carrying the statistics so far. When the match is over, the PGN is
+//! imported into the database of the config, if any. A match with SPRT bounds ends as soon as the
+//! test reaches a decision.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use pgn_reader::{BufferedReader, RawHeader, SanPlus, Skip, Visitor};
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    fen::Fen,
+    san::San,
+    uci::UciMove,
+    zobrist::{Zobrist64, ZobristHash},
+    CastlingMode, Chess, Color, EnPassantMode, Position,
+};
+use specta::Type;
+use tauri_specta::Event;
+use tokio::{
+    io::{AsyncWriteExt, BufReader, Lines},
+    process::ChildStdout,
+};
+
+use crate::{
+    error::{Error, Result},
+    progress::{TaskKind, TaskProgress},
+    AppState,
+};
+
+use super::{
+    match_stats::{match_statistics, MatchGameResult, MatchStatistics, SprtDecision, SprtParams},
+    process::EngineProcess,
+    types::{EngineOption, EngineOptions, GoMode, PlayersTime},
+};
+
+/// Games are adjudicated as draws after this many plies unless the config says otherwise.
+const DEFAULT_MAX_PLIES: u32 = 600;
+/// Time an engine may exceed its clock by before it loses, for the latency of the pipes.
+const TIME_MARGIN: Duration = Duration::from_millis(100);
+/// How long to wait for a move beyond a `movetime` limit before forfeiting the engine.
+const MOVE_TIME_GRACE: Duration = Duration::from_secs(1);
+const START_POSITION: &str = "Start position";
+
+type EngineReader = Lines<BufReader<ChildStdout>>;
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchEngine {
+    /// Name used in the PGN headers.
+    pub name: String,
+    pub path: PathBuf,
+    pub uci_options: Vec<EngineOption>,
+}
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(tag = "t", content = "c")]
+pub enum MatchOpenings {
+    StartPosition,
+    Fens(Vec<String>),
+    /// A PGN file, whose main lines are played up to `plies` moves, or a file of EPD/FEN lines.
+    Book {
+        file: PathBuf,
+        plies: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineMatchConfig {
+    pub candidate: MatchEngine,
+    pub baseline: MatchEngine,
+    pub games: u32,
+    /// `PlayersTime` gives each side a clock; the other limits apply to every move.
+    pub go_mode: GoMode,
+    pub openings: MatchOpenings,
+    /// PGN file the games are written to; replaced if it exists.
+    pub pgn_file: PathBuf,
+    /// Database the games are imported into after the match, created if needed.
+    pub database: Option<PathBuf>,
+    pub max_plies: Option<u32>,
+    pub sprt: Option<SprtParams>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    InsufficientMaterial,
+    FiftyMoves,
+    Repetition,
+    TimeForfeit,
+    IllegalMove,
+    MaxPlies,
+}
+
+impl Termination {
+    /// Value of the PGN `Termination` header.
+    fn pgn_value(self) -> &'static str {
+        match self {
+            Termination::TimeForfeit => "time forfeit",
+            Termination::IllegalMove => "rules infraction",
+            Termination::MaxPlies => "adjudication",
+            _ => "normal",
+        }
+    }
+}
+
+/// Sent after every game of a match.
+#[derive(Debug, Clone, Serialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineMatchProgress {
+    pub id: String,
+    /// Number of the game just finished, from 1.
+    pub game: u32,
+    pub games: u32,
+    pub result: String,
+    pub termination: Termination,
+    pub candidate_white: bool,
+    pub statistics: MatchStatistics,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineMatchResult {
+    pub pgn_file: PathBuf,
+    pub games: Vec<MatchGameResult>,
+    pub statistics: MatchStatistics,
+}
+
+/// Position a game starts from: a FEN and the book moves played from it, in UCI notation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Opening {
+    fen: String,
+    moves: Vec<String>,
+    name: Option<String>,
+}
+
+impl Opening {
+    /// Name of the opening in the per-opening statistics.
+    fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None if self.moves.is_empty() => self.fen.clone(),
+            None => self.moves.join(" "),
+        }
+    }
+}
+
+fn parse_position(fen: &str) -> Result<Chess> {
+    let fen: Fen = fen.trim().parse()?;
+    Ok(fen.into_position(CastlingMode::Standard)?)
+}
+
+fn to_fen(position: &Chess) -> String {
+    Fen::from_position(position.clone(), EnPassantMode::Legal).to_string()
+}
+
+fn fen_opening(fen: &str) -> Result<Opening> {
+    Ok(Opening {
+        fen: to_fen(&parse_position(fen)?),
+        moves: Vec::new(),
+        name: None,
+    })
+}
+
+/// Openings of a file of EPD or FEN lines; EPD operations are ignored.
+fn parse_fen_book(content: &str) -> Vec<Opening> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let counters = fields.len() >= 6
+                && fields[4..6]
+                    .iter()
+                    .all(|field| field.chars().all(|c| c.is_ascii_digit()));
+            let fen = if counters {
+                fields[..6].join(" ")
+            } else {
+                format!("{} 0 1", fields.get(..4)?.join(" "))
+            };
+            fen_opening(&fen).ok()
+        })
+        .collect()
+}
+
+/// Start position, name and main line of each game of a PGN book.
+#[derive(Default)]
+struct BookLine {
+    fen: Option<String>,
+    name: Option<String>,
+    eco: Option<String>,
+    sans: Vec<San>,
+}
+
+impl Visitor for BookLine {
+    type Result = BookLine;
+
+    fn begin_game(&mut self) {
+        *self = BookLine::default();
+    }
+
+    fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
+        let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+        match key {
+            b"FEN" => self.fen = Some(value),
+            b"Opening" => self.name = Some(value),
+            b"ECO" => self.eco = Some(value),
+            _ => {}
+        }
+    }
+
+    fn san(&mut self, san_plus: SanPlus) {
+        self.sans.push(san_plus.san);
+    }
+
+    fn begin_variation(&mut self) -> Skip {
+        Skip(true)
+    }
+
+    fn end_game(&mut self) -> Self::Result {
+        std::mem::take(self)
+    }
+}
+
+/// Openings of a PGN book, cut after `plies` moves. A line stops at its first illegal move.
+fn parse_pgn_book(content: &[u8], plies: Option<u32>) -> Vec<Opening> {
+    let mut visitor = BookLine::default();
+    BufferedReader::new_cursor(content)
+        .into_iter(&mut visitor)
+        .flatten()
+        .filter_map(|line| {
+            let mut position = match &line.fen {
+                Some(fen) => parse_position(fen).ok()?,
+                None => Chess::default(),
+            };
+            let fen = to_fen(&position);
+            let mut moves = Vec::new();
+            for san in line
+                .sans
+                .iter()
+                .take(plies.map_or(usize::MAX, |p| p as usize))
+            {
+                let Ok(m) = san.to_move(&position) else {
+                    break;
+                };
+                moves.push(m.to_uci(CastlingMode::Standard).to_string());
+                position.play_unchecked(&m);
+            }
+            Some(Opening {
+                fen,
+                moves,
+                name: line.name.or(line.eco),
+            })
+        })
+        .collect()
+}
+
+fn load_openings(openings: &MatchOpenings) -> Result<Vec<Opening>> {
+    let openings = match openings {
+        MatchOpenings::StartPosition => vec![Opening {
+            fen: to_fen(&Chess::default()),
+            moves: Vec::new(),
+            name: Some(START_POSITION.to_string()),
+        }],
+        MatchOpenings::Fens(fens) => fens
+            .iter()
+            .map(|fen| fen_opening(fen))
+            .collect::<Result<_>>()?,
+        MatchOpenings::Book { file, plies } => {
+            let content = std::fs::read(file)?;
+            if file.extension().is_some_and(|ext| ext == "pgn") {
+                parse_pgn_book(&content, *plies)
+            } else {
+                parse_fen_book(&String::from_utf8_lossy(&content))
+            }
+        }
+    };
+    if openings.is_empty() {
+        return Err(Error::PackageManager(
+            "No openings to play the match from".to_string(),
+        ));
+    }
+    Ok(openings)
+}
+
+/// Opening of game `index`, and whether the candidate plays it with white. Each opening is
+/// played twice in a row, with the colors reversed.
+fn game_setup(index: u32, openings: usize) -> (usize, bool) {
+    ((index / 2) as usize % openings, index % 2 == 0)
+}
+
+fn repetition_key(position: &Chess) -> Zobrist64 {
+    position.zobrist_hash(EnPassantMode::Legal)
+}
+
+fn win_for(color: Color) -> &'static str {
+    match color {
+        Color::White => "1-0",
+        Color::Black => "0-1",
+    }
+}
+
+/// Result of the game if `position`, reached for the `repetitions`th time, ends it.
+fn game_end(position: &Chess, repetitions: u32) -> Option<(&'static str, Termination)> {
+    if position.is_checkmate() {
+        Some((win_for(!position.turn()), Termination::Checkmate))
+    } else if position.is_stalemate() {
+        Some(("1/2-1/2", Termination::Stalemate))
+    } else if position.is_insufficient_material() {
+        Some(("1/2-1/2", Termination::InsufficientMaterial))
+    } else if position.halfmoves() >= 100 {
+        Some(("1/2-1/2", Termination::FiftyMoves))
+    } else if repetitions >= 3 {
+        Some(("1/2-1/2", Termination::Repetition))
+    } else {
+        None
+    }
+}
+
+/// Movetext of `sans` played from `start`, wrapped at 80 columns.
+fn movetext(start: &Chess, sans: &[String], result: &str) -> String {
+    let mut tokens = Vec::with_capacity(sans.len() * 3 / 2 + 1);
+    let mut turn = start.turn();
+    let mut number = start.fullmoves().get();
+    for (i, san) in sans.iter().enumerate() {
+        match turn {
+            Color::White => tokens.push(format!("{}.", number)),
+            Color::Black if i == 0 => tokens.push(format!("{}...", number)),
+            Color::Black => {}
+        }
+        tokens.push(san.clone());
+        if turn == Color::Black {
+            number += 1;
+        }
+        turn = !turn;
+    }
+    tokens.push(result.to_string());
+
+    let mut text = String::new();
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + 1 + token.len() > 80 {
+            text.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            text.push(' ');
+            line_len += 1;
+        }
+        line_len += token.len();
+        text.push_str(&token);
+    }
+    text
+}
+
+/// Seconds for a PGN `TimeControl` header.
+fn seconds(ms: u32) -> String {
+    if ms % 1000 == 0 {
+        (ms / 1000).to_string()
+    } else {
+        (ms as f64 / 1000.0).to_string()
+    }
+}
+
+struct PlayedGame {
+    result: &'static str,
+    termination: Termination,
+    start: Chess,
+    sans: Vec<String>,
+}
+
+struct Player {
+    name: String,
+    proc: EngineProcess,
+    reader: EngineReader,
+    options: Vec<EngineOption>,
+}
+
+impl Player {
+    async fn start(engine: &MatchEngine) -> Result<Self> {
+        let (proc, reader) = EngineProcess::new(engine.path.clone()).await?;
+        Ok(Player {
+            name: engine.name.clone(),
+            proc,
+            reader,
+            options: engine.uci_options.clone(),
+        })
+    }
+
+    /// Resets the engine for a new game, skipping whatever it still had to say about the last
+    /// one.
+    async fn new_game(&mut self) -> Result<()> {
+        self.proc.stdin.write_all(b"ucinewgame\nisready\n").await?;
+        while let Some(line) = self.reader.next_line().await? {
+            if line.trim() == "readyok" {
+                return Ok(());
+            }
+        }
+        Err(Error::PackageManager(format!("{} exited", self.name)))
+    }
+
+    /// The engine's move after `moves` from `fen`, or `None` if it gave none within `limit`.
+    async fn best_move(
+        &mut self,
+        fen: &str,
+        moves: &[String],
+        go_mode: &GoMode,
+        limit: Option<Duration>,
+    ) -> Result<Option<String>> {
+        self.proc
+            .set_options(EngineOptions {
+                fen: fen.to_string(),
+                moves: moves.to_vec(),
+                extra_options: self.options.clone(),
+            })
+            .await?;
+        self.proc.go(go_mode).await?;
+
+        let (reader, name) = (&mut self.reader, &self.name);
+        let read = async {
+            while let Some(line) = reader.next_line().await? {
+                if let Some(rest) = line.strip_prefix("bestmove") {
+                    return Ok(rest.split_whitespace().next().map(str::to_string));
+                }
+            }
+            Err(Error::PackageManager(format!("{} exited", name)))
+        };
+        let Some(limit) = limit else {
+            return read.await;
+        };
+        let best = tokio::time::timeout(limit, read).await;
+        match best {
+            Ok(best) => best,
+            Err(_) => {
+                self.proc.stop().await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Plays `opening` between `players`, `white` being the index of the player with white.
+async fn play_game(
+    players: &mut [Player; 2],
+    white: usize,
+    opening: &Opening,
+    go_mode: &GoMode,
+    max_plies: u32,
+) -> Result<PlayedGame> {
+    for player in players.iter_mut() {
+        player.new_game().await?;
+    }
+    let start = parse_position(&opening.fen)?;
+    let mut position = start.clone();
+    let mut moves: Vec<String> = Vec::new();
+    let mut sans: Vec<String> = Vec::new();
+    let mut repetitions: HashMap<Zobrist64, u32> = HashMap::new();
+    *repetitions.entry(repetition_key(&position)).or_default() += 1;
+    for uci in &opening.moves {
+        let m = UciMove::from_ascii(uci.as_bytes())?.to_move(&position)?;
+        moves.push(uci.clone());
+        sans.push(SanPlus::from_move_and_play_unchecked(&mut position, &m).to_string());
+        *repetitions.entry(repetition_key(&position)).or_default() += 1;
+    }
+
+    // Remaining time and increment of each side, in milliseconds.
+    let mut clocks = match go_mode {
+        GoMode::PlayersTime(time) => Some((
+            [time.white as i64, time.black as i64],
+            [time.winc as i64, time.binc as i64],
+        )),
+        _ => None,
+    };
+    let end = |result, termination, sans| PlayedGame {
+        result,
+        termination,
+        start: start.clone(),
+        sans,
+    };
+
+    loop {
+        let seen = repetitions[&repetition_key(&position)];
+        if let Some((result, termination)) = game_end(&position, seen) {
+            return Ok(end(result, termination, sans));
+        }
+        if moves.len() as u32 >= max_plies {
+            return Ok(end("1/2-1/2", Termination::MaxPlies, sans));
+        }
+
+        let turn = position.turn();
+        let side = if turn == Color::White { 0 } else { 1 };
+        let (mode, limit) = match (&clocks, go_mode) {
+            (Some((remaining, increment)), _) => (
+                GoMode::PlayersTime(PlayersTime {
+                    white: remaining[0].max(0) as u32,
+                    black: remaining[1].max(0) as u32,
+                    winc: increment[0] as u32,
+                    binc: increment[1] as u32,
+                }),
+                Some(Duration::from_millis(remaining[side].max(0) as u64) + TIME_MARGIN),
+            ),
+            (None, GoMode::Time(ms)) => (
+                go_mode.clone(),
+                Some(Duration::from_millis(*ms as u64) * 2 + MOVE_TIME_GRACE),
+            ),
+            (None, _) => (go_mode.clone(), None),
+        };
+
+        let mover = if turn == Color::White {
+            white
+        } else {
+            1 - white
+        };
+        let started = Instant::now();
+        let best = players[mover]
+            .best_move(&opening.fen, &moves, &mode, limit)
+            .await?;
+        let elapsed = started.elapsed();
+
+        let flagged = match &mut clocks {
+            Some((remaining, increment)) => {
+                remaining[side] -= elapsed.as_millis() as i64;
+                let flagged = remaining[side] < -(TIME_MARGIN.as_millis() as i64);
+                remaining[side] += increment[side];
+                flagged
+            }
+            None => false,
+        };
+        if flagged || best.is_none() {
+            // A flag only loses if the opponent could still mate.
+            let result = if position.has_insufficient_material(!turn) {
+                "1/2-1/2"
+            } else {
+                win_for(!turn)
+            };
+            return Ok(end(result, Termination::TimeForfeit, sans));
+        }
+
+        let legal = best.as_deref().and_then(|uci| {
+            UciMove::from_ascii(uci.as_bytes())
+                .ok()?
+                .to_move(&position)
+                .ok()
+        });
+        let Some(m) = legal else {
+            log::warn!(
+                "{} played the illegal move {:?} in {}",
+                players[mover].name,
+                best,
+                to_fen(&position)
+            );
+            return Ok(end(win_for(!turn), Termination::IllegalMove, sans));
+        };
+        moves.push(m.to_uci(CastlingMode::Standard).to_string());
+        sans.push(SanPlus::from_move_and_play_unchecked(&mut position, &m).to_string());
+        *repetitions.entry(repetition_key(&position)).or_default() += 1;
+    }
+}
+
+fn game_pgn(
+    config: &EngineMatchConfig,
+    round: u32,
+    candidate_white: bool,
+    opening: &Opening,
+    game: &PlayedGame,
+) -> String {
+    let (white, black) = if candidate_white {
+        (&config.candidate.name, &config.baseline.name)
+    } else {
+        (&config.baseline.name, &config.candidate.name)
+    };
+    let mut headers = vec![
+        (
+            "Event",
+            format!("{} vs {}", config.candidate.name, config.baseline.name),
+        ),
+        ("Site", "?".to_string()),
+        ("Date", chrono::Utc::now().format("%Y.%m.%d").to_string()),
+        ("Round", round.to_string()),
+        ("White", white.clone()),
+        ("Black", black.clone()),
+        ("Result", game.result.to_string()),
+    ];
+    if opening.fen != to_fen(&Chess::default()) {
+        headers.push(("SetUp", "1".to_string()));
+        headers.push(("FEN", opening.fen.clone()));
+    }
+    if let GoMode::PlayersTime(time) = &config.go_mode {
+        headers.push((
+            "TimeControl",
+            format!("{}+{}", seconds(time.white), seconds(time.winc)),
+        ));
+    }
+    if let Some(name) = opening.name.as_ref().filter(|name| *name != START_POSITION) {
+        headers.push(("Opening", name.clone()));
+    }
+    headers.push(("Termination", game.termination.pgn_value().to_string()));
+
+    let mut pgn = String::new();
+    for (name, value) in headers {
+        pgn.push_str(&format!(
+            "[{} \"{}\"]\n",
+            name,
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        ));
+    }
+    pgn.push('\n');
+    pgn.push_str(&movetext(&game.start, &game.sans, game.result));
+    pgn.push_str("\n\n");
+    pgn
+}
+
+fn create_pgn_file(path: &Path) -> Result<std::fs::File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(std::fs::File::create(path)?)
+}
+
+/// Play an engine match, writing the games to `config.pgn_file` and reporting each of them with
+/// an `EngineMatchProgress` event.
+#[tauri::command]
+#[specta::specta]
+pub async fn run_engine_match(
+    id: String,
+    config: EngineMatchConfig,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<EngineMatchResult> {
+    if matches!(config.go_mode, GoMode::Infinite) {
+        return Err(Error::PackageManager(
+            "Engine matches need a clock, or a time, depth or node limit".to_string(),
+        ));
+    }
+    let job = state.jobs.start(
+        &app,
+        TaskKind::Analysis,
+        id.clone(),
+        format!("{} vs {}", config.candidate.name, config.baseline.name),
+    );
+    let openings = load_openings(&config.openings)?;
+    let max_plies = config.max_plies.unwrap_or(DEFAULT_MAX_PLIES);
+    let mut pgn_file = create_pgn_file(&config.pgn_file)?;
+
+    state.engine_match_stop.store(false, Ordering::Relaxed);
+    let _awake = crate::app::platform::keep_awake::keep_awake("Running an engine match");
+    let mut players = [
+        Player::start(&config.candidate).await?,
+        Player::start(&config.baseline).await?,
+    ];
+
+    let mut results: Vec<MatchGameResult> = Vec::new();
+    let mut statistics = match_statistics(&results, config.sprt);
+    for index in 0..config.games {
+        if state.engine_match_stop.load(Ordering::Relaxed) || job.is_cancelled() {
+            break;
+        }
+        let (opening, candidate_white) = game_setup(index, openings.len());
+        let opening = &openings[opening];
+        let white = if candidate_white { 0 } else { 1 };
+        let game = play_game(&mut players, white, opening, &config.go_mode, max_plies).await?;
+
+        pgn_file
+            .write_all(game_pgn(&config, index + 1, candidate_white, opening, &game).as_bytes())?;
+        pgn_file.flush()?;
+        results.push(MatchGameResult {
+            result: game.result.to_string(),
+            candidate_white,
+            opening: Some(opening.label()),
+        });
+        statistics = match_statistics(&results, config.sprt);
+
+        EngineMatchProgress {
+            id: id.clone(),
+            game: index + 1,
+            games: config.games,
+            result: game.result.to_string(),
+            termination: game.termination,
+            candidate_white,
+            statistics: statistics.clone(),
+        }
+        .emit(&app)?;
+        TaskProgress::new(
+            TaskKind::Analysis,
+            id.clone(),
+            (index + 1) as f64 * 100.0 / config.games as f64,
+        )
+        .message(format!("Game {} of {}", index + 1, config.games))
+        .send(&app);
+
+        if statistics
+            .sprt
+            .as_ref()
+            .is_some_and(|sprt| sprt.decision != SprtDecision::Continue)
+        {
+            log::info!("SPRT reached a decision after {} games", results.len());
+            break;
+        }
+    }
+    for player in players.iter_mut() {
+        player.proc.quit(Duration::from_secs(1)).await;
+    }
+
+    if let Some(database) = config.database.clone() {
+        crate::db::convert_pgn(
+            config.pgn_file.clone(),
+            database,
+            None,
+            app.clone(),
+            format!("{} vs {}", config.candidate.name, config.baseline.name),
+            Some("Engine match".to_string()),
+            None,
+            None,
+            state.clone(),
+        )
+        .await?;
+    }
+
+    let score = format!(
+        "+{} ={} -{}",
+        statistics.overall.wins, statistics.overall.draws, statistics.overall.losses
+    );
+    log::info!(
+        "{} vs {}: {}",
+        config.candidate.name,
+        config.baseline.name,
+        score
+    );
+    TaskProgress::done(TaskKind::Analysis, id)
+        .message(score)
+        .send(&app);
+    Ok(EngineMatchResult {
+        pgn_file: config.pgn_file,
+        games: results,
+        statistics,
+    })
+}
+
+/// Stop the running engine match after the game being played.
+#[tauri::command]
+#[specta::specta]
+pub fn stop_engine_match(state: tauri::State<'_, AppState>) {
+    state.engine_match_stop.store(true, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(sans: &[&str]) -> Chess {
+        let mut position = Chess::default();
+        for san in sans {
+            let m = san.parse::<San>().unwrap().to_move(&position).unwrap();
+            position.play_unchecked(&m);
+        }
+        position
+    }
+
+    #[test]
+    fn test_game_end() {
+        let mate = play(&["f3", "e5", "g4", "Qh4#"]);
+        assert_eq!(game_end(&mate, 1), Some(("0-1", Termination::Checkmate)));
+        let open = play(&["e4", "e5"]);
+        assert_eq!(game_end(&open, 2), None);
+        assert_eq!(
+            game_end(&open, 3),
+            Some(("1/2-1/2", Termination::Repetition))
+        );
+        let bare_kings = parse_position("8/8/4k3/8/8/3K4/8/8 w - - 0 1").unwrap();
+        assert_eq!(
+            game_end(&bare_kings, 1),
+            Some(("1/2-1/2", Termination::InsufficientMaterial))
+        );
+    }
+
+    #[test]
+    fn test_movetext() {
+        let sans: Vec<String> = ["e4", "e5", "Nf3"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(movetext(&Chess::default(), &sans, "*"), "1. e4 e5 2. Nf3 *");
+        let black_first = play(&["d4"]);
+        assert_eq!(
+            movetext(&black_first, &sans[1..], "1-0"),
+            "1... e5 2. Nf3 1-0"
+        );
+        let long: Vec<String> = vec!["Nf3".to_string(); 40];
+        assert!(movetext(&Chess::default(), &long, "*")
+            .lines()
+            .all(|line| line.len() <= 80));
+    }
+
+    #[test]
+    fn test_parse_books() {
+        let pgn = b"[Opening \"Sicilian\"]\n\n1. e4 c5 (1... e5) 2. Nf3 d6 *\n\n\
+            [ECO \"D00\"]\n\n1. d4 d5 2. Qxd5 *\n";
+        let openings = parse_pgn_book(pgn, Some(3));
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[0].moves, vec!["e2e4", "c7c5", "g1f3"]);
+        assert_eq!(openings[0].label(), "Sicilian");
+        // The illegal third move ends the line.
+        assert_eq!(openings[1].moves, vec!["d2d4", "d7d5"]);
+        assert_eq!(openings[1].label(), "D00");
+
+        let epd = "# comment\n\
+            rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - id \"e4\";\n\
+            not a position\n\
+            4k3/8/8/8/8/8/8/4K2R w K - 3 40\n";
+        let openings = parse_fen_book(epd);
+        assert_eq!(openings.len(), 2);
+        assert!(openings[0].fen.ends_with(" b KQkq - 0 1"));
+        assert_eq!(openings[1].label(), "4k3/8/8/8/8/8/8/4K2R w K - 3 40");
+    }
+
+    #[test]
+    fn test_game_setup() {
+        let setups: Vec<(usize, bool)> = (0..6).map(|i| game_setup(i, 2)).collect();
+        assert_eq!(
+            setups,
+            vec![
+                (0, true),
+                (0, false),
+                (1, true),
+                (1, false),
+                (0, true),
+                (0, false)
+            ]
+        );
+    }
+}
@@ -14,7 +14,7 @@ use crate::error::Error;
 use crate::AppState;
 
 use super::process::EngineProcess;
-use super::types::{EngineLog, EngineOptions, GoMode};
+use super::types::{EmissionOptions, EngineLog, EngineOption, EngineOptions, GoMode};
 
 /// Manager for UCI engine processes, handling best-move queries and process lifecycle.
 pub struct EngineManager<'a> {
@@ -32,12 +32,17 @@ impl<'a> EngineManager<'a> {
     /// If an engine process is already running for the given key, it will reuse or update it as needed.
     /// Otherwise, it spawns a new process and background reader task.
     ///
+    /// Every `id` is a session of the engine with its own number of lines: the engine searches with
+    /// the largest `MultiPV` of its sessions and each session is sent its best lines. Sessions share
+    /// the position, so a session asking for another one moves the engine for all of them.
+    ///
     /// # Arguments
     /// * `id` - Unique analysis session identifier.
     /// * `engine` - Path to the UCI engine binary.
     /// * `tab` - Tab identifier for engine process grouping.
     /// * `go_mode` - Engine search mode (depth, time, etc).
     /// * `options` - Engine options (FEN, moves, etc).
+    /// * `emission` - Filtering and rate limit of the emitted lines.
     /// * `app` - Tauri app handle for event emission.
     ///
     /// # Returns
@@ -52,19 +57,37 @@ impl<'a> EngineManager<'a> {
         tab: String,
         go_mode: GoMode,
         options: EngineOptions,
+        emission: EmissionOptions,
         app: tauri::AppHandle,
     ) -> Result<Option<(f32, Vec<super::types::BestMoves>)>, Error> {
         let path = PathBuf::from(&engine);
         let key = (tab.clone(), engine.clone());
+        let mut options = options;
 
         // If an engine process already exists for this key, reuse or update it.
         if self.state.engine_processes.contains_key(&key) {
             {
                 let process = self.state.engine_processes.get_mut(&key).unwrap();
                 let mut process = process.lock().await;
+                process.emission = emission;
+                process.sessions.insert(id.clone(), options.multipv());
+                options.set_extra_option("MultiPV", process.sessions_multipv().to_string());
+                // The expected move was played while pondering: keep the search going. Clock
+                // times differ between requests in play mode, so only the kind of limit must match.
+                if process.pondering
+                    && options == process.options
+                    && std::mem::discriminant(&go_mode) == std::mem::discriminant(&process.go_mode)
+                {
+                    process.ponderhit().await?;
+                    process.go_mode = go_mode;
+                    if process.last_best_moves.is_empty() {
+                        return Ok(None);
+                    }
+                    return Ok(Some((process.last_progress, process.last_lines(&id))));
+                }
                 // If options and mode match and engine is running, return cached result.
                 if options == process.options && go_mode == process.go_mode && process.running {
-                    return Ok(Some((process.last_progress, process.last_best_moves.clone())));
+                    return Ok(Some((process.last_progress, process.last_lines(&id))));
                 }
                 // Otherwise, stop and reconfigure the engine.
                 process.stop().await?;
@@ -81,6 +104,8 @@ impl<'a> EngineManager<'a> {
         }
 
         let (mut process, mut reader) = EngineProcess::new(path).await?;
+        process.emission = emission;
+        process.sessions.insert(id.clone(), options.multipv());
         process.set_options(options.clone()).await?;
         process.go(&go_mode).await?;
 
@@ -89,14 +114,11 @@ impl<'a> EngineManager<'a> {
 
         // Spawn background reader task so multiple engines can run concurrently.
         let app_cloned = app.clone();
-        let id_cloned = id.clone();
         let tab_cloned = tab.clone();
         let key_cloned = key.clone();
         let engines_map = self.state.engine_processes.clone();
         tokio::spawn(async move {
             info!("Engine loop started: tab={} engine={}", key_cloned.0, key_cloned.1);
-            // OPTIMIZED: Increased emission rate from 5 to 10 events/sec for more responsive UI
-            let lim = governor::RateLimiter::direct(governor::Quota::per_second(nonzero_ext::nonzero!(10u32)));
             while let Ok(Some(line)) = reader.next_line().await {
                 // REMOVED: Excessive logging that slows down engine communication
                 if let Some(proc_arc) = engines_map.get(&key_cloned) {
@@ -110,8 +132,10 @@ impl<'a> EngineManager<'a> {
                                 if multipv as usize == proc.best_moves.len() + 1 {
                                     proc.best_moves.push(best_moves);
                                     if multipv == proc.real_multipv {
-                                        // Only emit if all lines are at the same depth and rate limit allows.
-                                        if proc.best_moves.iter().all(|x| x.depth == cur_depth) && cur_depth >= proc.last_depth && lim.check().is_ok() {
+                                        // Only emit if all lines are at the same depth and the emission options allow.
+                                        // While pondering the lines are only cached for a ponderhit, the
+                                        // position on screen is still the one before the opponent's move.
+                                        if proc.best_moves.iter().all(|x| x.depth == cur_depth) && cur_depth >= proc.last_depth && (proc.pondering || proc.emission_due(cur_depth)) {
                                             let progress = match proc.go_mode {
                                                 GoMode::Depth(depth) => (cur_depth as f64 / depth as f64) * 100.0,
                                                 GoMode::Time(time) => (proc.start.elapsed().as_millis() as f64 / time as f64) * 100.0,
@@ -119,9 +143,14 @@ impl<'a> EngineManager<'a> {
                                                 GoMode::PlayersTime(_) => 99.99,
                                                 GoMode::Infinite => 99.99,
                                             };
-                                            super::types::BestMovesPayload { best_lines: proc.best_moves.clone(), engine: id_cloned.clone(), tab: tab_cloned.clone(), fen: proc.options.fen.clone(), moves: proc.options.moves.clone(), progress }.emit(&app_cloned).ok();
+                                            let lines = super::process::filter_lines(&proc.best_moves, proc.emission.max_cp_loss);
+                                            if !proc.pondering {
+                                                for (session, best_lines) in proc.session_lines(&lines) {
+                                                    super::types::BestMovesPayload { best_lines, engine: session, tab: tab_cloned.clone(), fen: proc.options.fen.clone(), moves: proc.options.moves.clone(), progress }.emit(&app_cloned).ok();
+                                                }
+                                            }
                                             proc.last_depth = cur_depth;
-                                            proc.last_best_moves = proc.best_moves.clone();
+                                            proc.last_best_moves = lines;
                                             proc.last_progress = progress as f32;
                                         }
                                         proc.best_moves.clear();
@@ -129,10 +158,18 @@ impl<'a> EngineManager<'a> {
                                 }
                             }
                         }
-                        vampirc_uci::UciMessage::BestMove { .. } => {
-                            // Emit final result when engine signals best move.
-                            super::types::BestMovesPayload { best_lines: proc.last_best_moves.clone(), engine: id_cloned.clone(), tab: tab_cloned.clone(), fen: proc.options.fen.clone(), moves: proc.options.moves.clone(), progress: 100.0 }.emit(&app_cloned).ok();
-                            proc.last_progress = 100.0;
+                        vampirc_uci::UciMessage::BestMove { ponder, .. } => {
+                            if proc.discard_bestmove {
+                                // Result of a stopped pondering search.
+                                proc.discard_bestmove = false;
+                            } else {
+                                // Emit final result when engine signals best move.
+                                for (session, best_lines) in proc.session_lines(&proc.last_best_moves) {
+                                    super::types::BestMovesPayload { best_lines, engine: session, tab: tab_cloned.clone(), fen: proc.options.fen.clone(), moves: proc.options.moves.clone(), progress: 100.0 }.emit(&app_cloned).ok();
+                                }
+                                proc.last_progress = 100.0;
+                                proc.ponder_move = ponder.map(|m| m.to_string());
+                            }
                         }
                         _ => {}
                     }
@@ -145,6 +182,127 @@ impl<'a> EngineManager<'a> {
 
         Ok(None)
     }
-}
 
+    /// Change a UCI option (e.g. `Hash`, `Threads`, `MultiPV` or `Contempt`) of a running engine
+    /// without restarting it.
+    ///
+    /// `MultiPV` is the number of lines of `session`, or of every session when none is given.
+    ///
+    /// # Errors
+    /// Returns `Error` if no such engine is running or engine I/O fails.
+    pub async fn set_engine_option(
+        &self,
+        engine: String,
+        tab: String,
+        option: EngineOption,
+        session: Option<String>,
+    ) -> Result<(), Error> {
+        let key = (tab, engine);
+        let Some(process) = self.state.engine_processes.get(&key).map(|p| p.clone()) else {
+            return Err(Error::PackageManager(format!("Engine {} is not running", key.1)));
+        };
+
+        let options = {
+            let mut process = process.lock().await;
+            let mut options = process.options.clone();
+            if option.name == "MultiPV" {
+                let multipv = option.value.parse().unwrap_or(1);
+                for (id, lines) in process.sessions.iter_mut() {
+                    if session.as_ref().is_none_or(|session| session == id) {
+                        *lines = multipv;
+                    }
+                }
+                options.set_extra_option("MultiPV", process.sessions_multipv().to_string());
+            } else {
+                options.set_extra_option(&option.name, option.value);
+            }
+            options
+        };
+        Self::apply_options(&process, options).await
+    }
+
+    /// End the analysis session `id` of an engine. The engine keeps running for its other
+    /// sessions, searching only as many lines as they need, and stops once none is left.
+    ///
+    /// # Errors
+    /// Returns `Error` if engine I/O fails.
+    pub async fn end_analysis_session(
+        &self,
+        id: String,
+        engine: String,
+        tab: String,
+    ) -> Result<(), Error> {
+        let key = (tab, engine);
+        let Some(process) = self.state.engine_processes.get(&key).map(|p| p.clone()) else {
+            return Ok(());
+        };
+        let options = {
+            let mut process = process.lock().await;
+            process.sessions.remove(&id);
+            if process.sessions.is_empty() {
+                return process.stop().await;
+            }
+            let mut options = process.options.clone();
+            options.set_extra_option("MultiPV", process.sessions_multipv().to_string());
+            options
+        };
+        Self::apply_options(&process, options).await
+    }
+
+    /// Send the options that changed to an engine. A search in progress is stopped while they are
+    /// set, as engines only apply options when idle, and then resumed with the same limits.
+    async fn apply_options(
+        process: &Mutex<EngineProcess>,
+        options: EngineOptions,
+    ) -> Result<(), Error> {
+        let resume = {
+            let mut process = process.lock().await;
+            if options == process.options {
+                return Ok(());
+            }
+            let resume = process.running && !process.pondering;
+            if process.running {
+                process.stop().await?;
+            }
+            resume
+        };
+        if resume {
+            // Let the stopped search report its best move before the new one starts.
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let mut process = process.lock().await;
+        process.set_options(options).await?;
+        if resume {
+            let go_mode = process.go_mode.clone();
+            process.go(&go_mode).await?;
+        }
+        Ok(())
+    }
 
+    /// Start pondering on the move the engine expects the opponent to play, once it has
+    /// finished its own search.
+    ///
+    /// # Returns
+    /// The move being pondered, or `None` if the engine has no ponder move.
+    ///
+    /// # Errors
+    /// Returns `Error` if the ponder move is illegal or engine I/O fails.
+    pub async fn start_pondering(
+        &self,
+        engine: String,
+        tab: String,
+        go_mode: GoMode,
+    ) -> Result<Option<String>, Error> {
+        let key = (tab, engine);
+        let Some(process) = self.state.engine_processes.get(&key) else {
+            return Ok(None);
+        };
+        let mut process = process.lock().await;
+        let Some(ponder_move) = process.ponder_move.take() else {
+            return Ok(None);
+        };
+        process.go_ponder(&go_mode, ponder_move.clone()).await?;
+        Ok(Some(ponder_move))
+    }
+}
@@ -0,0 +1,352 @@
+//! Statistics for engine-vs-engine matches.
+//!
+//! Results are always seen from the first engine (the "candidate", e.g. a patched build) against
+//! the second (the "baseline"). The Elo difference uses the logistic model with a 95% confidence
+//! interval from the trinomial (win/draw/loss) variance, and the SPRT uses the usual GSPRT
+//! approximation of the log-likelihood ratio, so numbers match what common testing frameworks
+//! report for the same games.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// Two-sided 95% quantile of the normal distribution.
+const Z_95: f64 = 1.959964;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchGameResult {
+    /// PGN result (`1-0`, `0-1`, `1/2-1/2`); unfinished games are ignored.
+    pub result: String,
+    /// Whether the candidate engine had the white pieces.
+    pub candidate_white: bool,
+    /// Opening name or ECO code the game started from.
+    pub opening: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SprtParams {
+    /// Elo difference under the null hypothesis, e.g. 0.
+    pub elo0: f64,
+    /// Elo difference under the alternative hypothesis, e.g. 5.
+    pub elo1: f64,
+    /// False positive rate, e.g. 0.05.
+    pub alpha: f64,
+    /// False negative rate, e.g. 0.05.
+    pub beta: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum SprtDecision {
+    /// Keep playing games.
+    Continue,
+    /// The results fit `elo0` better than `elo1`: reject the patch.
+    AcceptH0,
+    /// The results fit `elo1` better than `elo0`: accept the patch.
+    AcceptH1,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SprtResult {
+    pub llr: f64,
+    pub lower_bound: f64,
+    pub upper_bound: f64,
+    pub decision: SprtDecision,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreStats {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Candidate score, between 0 and 1.
+    pub score: f64,
+    pub draw_ratio: f64,
+    /// Estimated Elo difference; `None` when the score is 0% or 100%.
+    pub elo: Option<f64>,
+    /// Half-width of the 95% confidence interval around `elo`.
+    pub elo_error: Option<f64>,
+    /// Likelihood of superiority: probability that the candidate is the stronger engine.
+    pub los: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct OpeningStats {
+    pub opening: String,
+    pub stats: ScoreStats,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchStatistics {
+    pub overall: ScoreStats,
+    pub as_white: ScoreStats,
+    pub as_black: ScoreStats,
+    pub sprt: Option<SprtResult>,
+    /// Results per opening, most played first.
+    pub openings: Vec<OpeningStats>,
+}
+
+/// Candidate's score for a PGN result.
+fn candidate_score(game: &MatchGameResult) -> Option<f64> {
+    let white = match game.result.as_str() {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        _ => return None,
+    };
+    Some(if game.candidate_white { white } else { 1.0 - white })
+}
+
+/// Expected score for an Elo difference.
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Elo difference for an expected score in (0, 1).
+fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Error function, Abramowitz and Stegun 7.1.26 (max error 1.5e-7).
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    sign * (1.0 - poly * (-x * x).exp())
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Tally {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl Tally {
+    fn add(&mut self, score: f64) {
+        if score >= 1.0 {
+            self.wins += 1;
+        } else if score <= 0.0 {
+            self.losses += 1;
+        } else {
+            self.draws += 1;
+        }
+    }
+
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Mean score and per-game variance of the score.
+    fn mean_and_variance(&self) -> Option<(f64, f64)> {
+        let n = self.games() as f64;
+        if n == 0.0 {
+            return None;
+        }
+        let (w, d, l) = (
+            self.wins as f64 / n,
+            self.draws as f64 / n,
+            self.losses as f64 / n,
+        );
+        let mean = w + d / 2.0;
+        let variance = w * (1.0 - mean).powi(2) + d * (0.5 - mean).powi(2) + l * mean.powi(2);
+        Some((mean, variance))
+    }
+
+    fn stats(&self) -> ScoreStats {
+        let games = self.games();
+        let mut stats = ScoreStats {
+            games,
+            wins: self.wins,
+            draws: self.draws,
+            losses: self.losses,
+            ..Default::default()
+        };
+        let Some((mean, variance)) = self.mean_and_variance() else {
+            return stats;
+        };
+        stats.score = mean;
+        stats.draw_ratio = self.draws as f64 / games as f64;
+
+        if mean > 0.0 && mean < 1.0 {
+            let elo = elo_from_score(mean);
+            stats.elo = Some(elo);
+            let margin = Z_95 * (variance / games as f64).sqrt();
+            let (low, high) = (mean - margin, mean + margin);
+            if low > 0.0 && high < 1.0 {
+                stats.elo_error = Some((elo_from_score(high) - elo_from_score(low)) / 2.0);
+            }
+        }
+
+        let decisive = (self.wins + self.losses) as f64;
+        if decisive > 0.0 {
+            let z = (self.wins as f64 - self.losses as f64) / (2.0 * decisive).sqrt();
+            stats.los = Some(0.5 * (1.0 + erf(z)));
+        }
+        stats
+    }
+
+    /// Log-likelihood ratio of H1 (`elo1`) against H0 (`elo0`).
+    fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        // Without both wins and losses the variance estimate is degenerate.
+        if self.wins == 0 || self.losses == 0 {
+            return 0.0;
+        }
+        let Some((mean, variance)) = self.mean_and_variance() else {
+            return 0.0;
+        };
+        let (s0, s1) = (expected_score(elo0), expected_score(elo1));
+        self.games() as f64 * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+}
+
+fn sprt(tally: &Tally, params: SprtParams) -> SprtResult {
+    let llr = tally.llr(params.elo0, params.elo1);
+    let lower_bound = (params.beta / (1.0 - params.alpha)).ln();
+    let upper_bound = ((1.0 - params.beta) / params.alpha).ln();
+    let decision = if llr >= upper_bound {
+        SprtDecision::AcceptH1
+    } else if llr <= lower_bound {
+        SprtDecision::AcceptH0
+    } else {
+        SprtDecision::Continue
+    };
+    SprtResult {
+        llr,
+        lower_bound,
+        upper_bound,
+        decision,
+    }
+}
+
+pub fn match_statistics(games: &[MatchGameResult], sprt_params: Option<SprtParams>) -> MatchStatistics {
+    let mut overall = Tally::default();
+    let mut as_white = Tally::default();
+    let mut as_black = Tally::default();
+    let mut openings: BTreeMap<String, Tally> = BTreeMap::new();
+
+    for game in games {
+        let Some(score) = candidate_score(game) else {
+            continue;
+        };
+        overall.add(score);
+        if game.candidate_white {
+            as_white.add(score);
+        } else {
+            as_black.add(score);
+        }
+        let opening = game
+            .opening
+            .as_deref()
+            .map(str::trim)
+            .filter(|o| !o.is_empty())
+            .unwrap_or("Unknown");
+        openings.entry(opening.to_string()).or_default().add(score);
+    }
+
+    let mut openings: Vec<OpeningStats> = openings
+        .into_iter()
+        .map(|(opening, tally)| OpeningStats {
+            opening,
+            stats: tally.stats(),
+        })
+        .collect();
+    openings.sort_by(|a, b| b.stats.games.cmp(&a.stats.games));
+
+    MatchStatistics {
+        overall: overall.stats(),
+        as_white: as_white.stats(),
+        as_black: as_black.stats(),
+        sprt: sprt_params.map(|params| sprt(&overall, params)),
+        openings,
+    }
+}
+
+/// Elo estimate, SPRT state and per-opening breakdown of an engine match.
+#[tauri::command]
+#[specta::specta]
+pub fn get_match_statistics(
+    games: Vec<MatchGameResult>,
+    sprt: Option<SprtParams>,
+) -> MatchStatistics {
+    match_statistics(&games, sprt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn games(wins: u32, draws: u32, losses: u32) -> Vec<MatchGameResult> {
+        let game = |result: &str, i: u32| MatchGameResult {
+            result: result.to_string(),
+            candidate_white: i % 2 == 0,
+            opening: Some(if i % 3 == 0 { "B90" } else { "C65" }.to_string()),
+        };
+        let mut all = Vec::new();
+        for i in 0..wins {
+            all.push(game(if i % 2 == 0 { "1-0" } else { "0-1" }, i));
+        }
+        for i in 0..draws {
+            all.push(game("1/2-1/2", i));
+        }
+        for i in 0..losses {
+            all.push(game(if i % 2 == 0 { "0-1" } else { "1-0" }, i));
+        }
+        all
+    }
+
+    #[test]
+    fn test_elo_estimate() {
+        let stats = match_statistics(&games(30, 40, 30), None);
+        assert_eq!(stats.overall.games, 100);
+        assert!((stats.overall.score - 0.5).abs() < 1e-9);
+        assert!(stats.overall.elo.unwrap().abs() < 1e-9);
+        assert!((stats.overall.los.unwrap() - 0.5).abs() < 1e-6);
+
+        // 60% is about +70 Elo.
+        let stats = match_statistics(&games(40, 40, 20), None);
+        let elo = stats.overall.elo.unwrap();
+        assert!((elo - 70.4).abs() < 0.5, "{}", elo);
+        let error = stats.overall.elo_error.unwrap();
+        assert!(error > 30.0 && error < 80.0, "{}", error);
+        assert_eq!(stats.as_white.games + stats.as_black.games, 100);
+        assert_eq!(stats.openings.iter().map(|o| o.stats.games).sum::<u32>(), 100);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let params = SprtParams {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let stats = match_statistics(&games(600, 800, 400), Some(params));
+        assert_eq!(stats.sprt.unwrap().decision, SprtDecision::AcceptH1);
+        let stats = match_statistics(&games(400, 800, 600), Some(params));
+        assert_eq!(stats.sprt.unwrap().decision, SprtDecision::AcceptH0);
+        let stats = match_statistics(&games(10, 10, 10), Some(params));
+        assert_eq!(stats.sprt.unwrap().decision, SprtDecision::Continue);
+    }
+
+    #[test]
+    fn test_ignores_unfinished_games() {
+        let mut all = games(1, 0, 1);
+        all.push(MatchGameResult {
+            result: "*".to_string(),
+            candidate_white: true,
+            opening: None,
+        });
+        assert_eq!(match_statistics(&all, None).overall.games, 2);
+    }
+}
@@ -9,6 +9,15 @@ pub mod process;
 pub mod manager;
 pub mod evaluation;
 pub mod analysis;
+pub mod position_features;
+pub mod setup;
+pub mod tablebase;
+pub mod match_stats;
+pub mod strength;
+pub mod engine_match;
+pub mod test_suite;
+pub mod presets;
+pub mod discovery;
 pub mod commands;
 
 #[allow(unused_imports)]
@@ -19,5 +28,14 @@ pub use {
     manager::*,
     evaluation::*,
     analysis::*,
+    position_features::*,
+    setup::*,
+    tablebase::*,
+    match_stats::*,
+    strength::*,
+    engine_match::*,
+    test_suite::*,
+    presets::*,
+    discovery::*,
     commands::*,
 };
\ No newline at end of file
@@ -0,0 +1,348 @@
+//! Engine-free description of a position.
+//!
+//! Computes positional features from the board alone (material, pawn structure, king safety,
+//! open files and space) and a short list of human-readable observations, so the UI can explain a
+//! position without running an engine.
+
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    attacks, fen::Fen, Bitboard, Board, CastlingMode, Chess, Color, File, Piece, Position, Rank,
+    Role, Square,
+};
+use specta::Type;
+
+use crate::error::Error;
+
+/// Non-pawn material (in pawns) at or below which the position counts as an endgame.
+const ENDGAME_MATERIAL: u32 = 26;
+/// Non-pawn material (in pawns) of the starting position.
+const FULL_MATERIAL: u32 = 62;
+const OPENING_MOVES: u32 = 10;
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SideFeatures {
+    /// Material in pawn units (P=1, N=B=3, R=5, Q=9).
+    pub material: u32,
+    pub bishop_pair: bool,
+    pub pawn_islands: u32,
+    pub isolated_pawns: Vec<String>,
+    /// Files with more than one pawn of this side.
+    pub doubled_files: Vec<String>,
+    pub passed_pawns: Vec<String>,
+    /// Files without own pawns but with enemy pawns.
+    pub half_open_files: Vec<String>,
+    pub king_square: Option<String>,
+    /// Own pawns on the king's file and the adjacent files, at most two ranks ahead.
+    pub king_shelter: u32,
+    /// Enemy pieces attacking the king or the squares around it.
+    pub king_attackers: u32,
+    /// Squares in the opponent's half controlled by this side.
+    pub space: u32,
+    /// Squares attacked by pieces (not pawns or king) that are not occupied by own pieces.
+    pub mobility: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum GamePhase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionDescription {
+    pub turn: String,
+    pub phase: GamePhase,
+    /// White material minus black material, in pawns.
+    pub material_balance: i32,
+    pub white: SideFeatures,
+    pub black: SideFeatures,
+    /// Files without any pawn.
+    pub open_files: Vec<String>,
+    pub in_check: bool,
+    /// Short observations in plain English, most important first.
+    pub summary: Vec<String>,
+}
+
+fn role_value(role: Role) -> u32 {
+    match role {
+        Role::Pawn => 1,
+        Role::Knight | Role::Bishop => 3,
+        Role::Rook => 5,
+        Role::Queen => 9,
+        Role::King => 0,
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+fn file_name(file: File) -> String {
+    file.char().to_string()
+}
+
+fn files() -> impl Iterator<Item = File> {
+    (0..8).map(File::new)
+}
+
+/// Rank index counted from `color`'s side of the board (0 = own back rank).
+fn relative_rank(color: Color, square: Square) -> u32 {
+    let rank = u32::from(square.rank());
+    match color {
+        Color::White => rank,
+        Color::Black => 7 - rank,
+    }
+}
+
+fn adjacent_files(file: File) -> Bitboard {
+    let f = u32::from(file);
+    let mut mask = Bitboard::EMPTY;
+    if f > 0 {
+        mask |= Bitboard::from_file(File::new(f - 1));
+    }
+    if f < 7 {
+        mask |= Bitboard::from_file(File::new(f + 1));
+    }
+    mask
+}
+
+fn opponent_half(color: Color) -> Bitboard {
+    let ranks = match color {
+        Color::White => 4..8,
+        Color::Black => 0..4,
+    };
+    ranks.fold(Bitboard::EMPTY, |acc, r| acc | Bitboard::from_rank(Rank::new(r)))
+}
+
+fn side_features(board: &Board, color: Color) -> SideFeatures {
+    let pawn = Piece { color, role: Role::Pawn };
+    let enemy_pawn = Piece { color: !color, role: Role::Pawn };
+    let own_pawns = board.by_piece(pawn);
+    let enemy_pawns = board.by_piece(enemy_pawn);
+    let own = board.by_color(color);
+
+    let mut features = SideFeatures {
+        material: own
+            .into_iter()
+            .filter_map(|sq| board.role_at(sq))
+            .map(role_value)
+            .sum(),
+        bishop_pair: (board.by_role(Role::Bishop) & own).count() >= 2,
+        ..Default::default()
+    };
+
+    let mut in_island = false;
+    for file in files() {
+        let on_file = own_pawns & Bitboard::from_file(file);
+        if on_file.is_empty() {
+            in_island = false;
+            if !(enemy_pawns & Bitboard::from_file(file)).is_empty() {
+                features.half_open_files.push(file_name(file));
+            }
+            continue;
+        }
+        if !in_island {
+            features.pawn_islands += 1;
+            in_island = true;
+        }
+        if on_file.count() > 1 {
+            features.doubled_files.push(file_name(file));
+        }
+    }
+
+    for sq in own_pawns {
+        let neighbours = adjacent_files(sq.file());
+        if (own_pawns & neighbours).is_empty() {
+            features.isolated_pawns.push(sq.to_string());
+        }
+        let front_span = neighbours | Bitboard::from_file(sq.file());
+        let blockers = (enemy_pawns & front_span)
+            .into_iter()
+            .any(|enemy| relative_rank(color, enemy) > relative_rank(color, sq));
+        if !blockers {
+            features.passed_pawns.push(sq.to_string());
+        }
+    }
+
+    if let Some(king) = board.king_of(color) {
+        features.king_square = Some(king.to_string());
+        let shelter_files = adjacent_files(king.file()) | Bitboard::from_file(king.file());
+        features.king_shelter = (own_pawns & shelter_files)
+            .into_iter()
+            .filter(|sq| {
+                let ahead = relative_rank(color, *sq) as i32 - relative_rank(color, king) as i32;
+                (1..=2).contains(&ahead)
+            })
+            .count() as u32;
+        let zone = attacks::king_attacks(king) | Bitboard::from(king);
+        features.king_attackers = board
+            .by_color(!color)
+            .into_iter()
+            .filter(|sq| !(board.attacks_from(*sq) & zone).is_empty())
+            .count() as u32;
+    }
+
+    let mut controlled = Bitboard::EMPTY;
+    for sq in own {
+        let attacked = board.attacks_from(sq);
+        controlled |= attacked;
+        if !matches!(board.role_at(sq), Some(Role::Pawn | Role::King)) {
+            features.mobility += (attacked & !own).count() as u32;
+        }
+    }
+    features.space = (controlled & opponent_half(color)).count() as u32;
+
+    features
+}
+
+fn phase(board: &Board, fullmoves: u32) -> GamePhase {
+    let non_pawn: u32 = board
+        .occupied()
+        .into_iter()
+        .filter_map(|sq| board.role_at(sq))
+        .filter(|role| *role != Role::Pawn)
+        .map(role_value)
+        .sum();
+    if non_pawn <= ENDGAME_MATERIAL {
+        GamePhase::Endgame
+    } else if fullmoves <= OPENING_MOVES && non_pawn + 6 >= FULL_MATERIAL {
+        GamePhase::Opening
+    } else {
+        GamePhase::Middlegame
+    }
+}
+
+fn summarize(description: &PositionDescription) -> Vec<String> {
+    let mut summary = Vec::new();
+    let balance = description.material_balance;
+    if balance == 0 {
+        summary.push("Material is equal".to_string());
+    } else {
+        let leader = if balance > 0 { Color::White } else { Color::Black };
+        let pawns = balance.unsigned_abs();
+        summary.push(format!(
+            "{} is up {} pawn{} of material",
+            color_name(leader),
+            pawns,
+            if pawns == 1 { "" } else { "s" }
+        ));
+    }
+
+    for color in [Color::White, Color::Black] {
+        let side = match color {
+            Color::White => &description.white,
+            Color::Black => &description.black,
+        };
+        let name = color_name(color);
+        if side.king_attackers >= 2 || (side.king_shelter == 0 && description.phase != GamePhase::Endgame)
+        {
+            summary.push(format!("{}'s king is exposed", name));
+        }
+        if !side.passed_pawns.is_empty() {
+            summary.push(format!(
+                "{} has a passed pawn on {}",
+                name,
+                side.passed_pawns.join(", ")
+            ));
+        }
+        if side.bishop_pair {
+            summary.push(format!("{} has the bishop pair", name));
+        }
+        if !side.isolated_pawns.is_empty() {
+            summary.push(format!(
+                "{} has an isolated pawn on {}",
+                name,
+                side.isolated_pawns.join(", ")
+            ));
+        }
+        if !side.doubled_files.is_empty() {
+            summary.push(format!(
+                "{} has doubled pawns on the {}-file",
+                name,
+                side.doubled_files.join(", ")
+            ));
+        }
+    }
+
+    if !description.open_files.is_empty() {
+        summary.push(format!("Open files: {}", description.open_files.join(", ")));
+    }
+    let space_diff = description.white.space as i32 - description.black.space as i32;
+    if space_diff.abs() >= 4 {
+        let leader = if space_diff > 0 { Color::White } else { Color::Black };
+        summary.push(format!("{} has more space", color_name(leader)));
+    }
+    summary
+}
+
+pub fn describe(position: &Chess) -> PositionDescription {
+    let board = position.board();
+    let white = side_features(board, Color::White);
+    let black = side_features(board, Color::Black);
+    let pawns = board.by_role(Role::Pawn);
+    let open_files = files()
+        .filter(|file| (pawns & Bitboard::from_file(*file)).is_empty())
+        .map(file_name)
+        .collect();
+
+    let mut description = PositionDescription {
+        turn: color_name(position.turn()).to_lowercase(),
+        phase: phase(board, position.fullmoves().get()),
+        material_balance: white.material as i32 - black.material as i32,
+        white,
+        black,
+        open_files,
+        in_check: position.is_check(),
+        summary: Vec::new(),
+    };
+    description.summary = summarize(&description);
+    description
+}
+
+/// Structured positional features of `fen`, computed without an engine.
+#[tauri::command]
+#[specta::specta]
+pub fn describe_position(fen: String) -> Result<PositionDescription, Error> {
+    let fen: Fen = fen.parse()?;
+    let position: Chess = fen.into_position(CastlingMode::Chess960)?;
+    Ok(describe(&position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe_fen(fen: &str) -> PositionDescription {
+        describe_position(fen.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_starting_position() {
+        let description = describe_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(description.phase, GamePhase::Opening);
+        assert_eq!(description.material_balance, 0);
+        assert_eq!(description.white.pawn_islands, 1);
+        assert!(description.white.bishop_pair);
+        assert!(description.open_files.is_empty());
+        assert!(description.white.passed_pawns.is_empty());
+    }
+
+    #[test]
+    fn test_pawn_structure() {
+        // White has doubled c-pawns and two islands; black has a passed a-pawn.
+        let description = describe_fen("4k3/p4ppp/8/8/2PP4/2P5/5PPP/4K3 w - - 0 30");
+        assert_eq!(description.phase, GamePhase::Endgame);
+        assert_eq!(description.white.doubled_files, vec!["c"]);
+        assert_eq!(description.black.passed_pawns, vec!["a7"]);
+        assert_eq!(description.white.pawn_islands, 2);
+        assert!(description.open_files.contains(&"b".to_string()));
+        assert_eq!(description.material_balance, 2);
+    }
+}
@@ -0,0 +1,164 @@
+//! Default engine and analysis preset per database or tab type.
+//!
+//! A preset names an engine and its search settings. Presets can be attached to a database file
+//! (e.g. a fast preset for a blitz collection, deep analysis for correspondence games) or to a tab
+//! type; when a game is opened the database preset wins over the tab type preset. The mapping is
+//! stored in `analysis_presets.json` in the app config directory.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisPreset {
+    /// Path of the engine binary.
+    pub engine: String,
+    /// Search depth; `None` analyses until stopped.
+    pub depth: Option<u32>,
+    pub multipv: u16,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisPresetConfig {
+    /// Presets keyed by database path.
+    pub databases: HashMap<String, AnalysisPreset>,
+    /// Presets keyed by tab type (e.g. `analysis`, `play`).
+    pub tab_types: HashMap<String, AnalysisPreset>,
+}
+
+impl AnalysisPresetConfig {
+    fn get_config_path(app: &AppHandle) -> Result<PathBuf, Error> {
+        Ok(app
+            .path()
+            .resolve("analysis_presets.json", BaseDirectory::AppConfig)?)
+    }
+
+    pub fn load(app: &AppHandle) -> Result<Self, Error> {
+        let config_path = Self::get_config_path(app)?;
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&config_path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::PackageManager(format!("Invalid analysis presets: {}", e)))
+    }
+
+    pub fn save(&self, app: &AppHandle) -> Result<(), Error> {
+        let config_path = Self::get_config_path(app)?;
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::PackageManager(format!("Failed to serialize analysis presets: {}", e)))?;
+        fs::write(&config_path, json)?;
+        Ok(())
+    }
+
+    /// Preset for a game from `file` opened in a tab of type `tab_type`.
+    pub fn resolve(&self, file: Option<&Path>, tab_type: Option<&str>) -> Option<AnalysisPreset> {
+        file.and_then(|file| self.databases.get(&database_key(file)))
+            .or_else(|| tab_type.and_then(|t| self.tab_types.get(t)))
+            .cloned()
+    }
+}
+
+/// Databases are keyed by their canonical path, so the same file opened through different
+/// relative paths or symlinks shares its preset.
+fn database_key(file: &Path) -> String {
+    fs::canonicalize(file)
+        .unwrap_or_else(|_| file.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_analysis_presets(app: AppHandle) -> Result<AnalysisPresetConfig, Error> {
+    AnalysisPresetConfig::load(&app)
+}
+
+/// Attach a preset to a database, or remove it with `None`.
+#[tauri::command]
+#[specta::specta]
+pub fn set_database_analysis_preset(
+    app: AppHandle,
+    file: PathBuf,
+    preset: Option<AnalysisPreset>,
+) -> Result<(), Error> {
+    let mut config = AnalysisPresetConfig::load(&app)?;
+    let key = database_key(&file);
+    match preset {
+        Some(preset) => config.databases.insert(key, preset),
+        None => config.databases.remove(&key),
+    };
+    config.save(&app)
+}
+
+/// Attach a preset to a tab type, or remove it with `None`.
+#[tauri::command]
+#[specta::specta]
+pub fn set_tab_type_analysis_preset(
+    app: AppHandle,
+    tab_type: String,
+    preset: Option<AnalysisPreset>,
+) -> Result<(), Error> {
+    let mut config = AnalysisPresetConfig::load(&app)?;
+    match preset {
+        Some(preset) => config.tab_types.insert(tab_type, preset),
+        None => config.tab_types.remove(&tab_type),
+    };
+    config.save(&app)
+}
+
+/// Preset to use for a game, if any: the database preset, else the tab type preset.
+#[tauri::command]
+#[specta::specta]
+pub fn resolve_analysis_preset(
+    app: AppHandle,
+    file: Option<PathBuf>,
+    tab_type: Option<String>,
+) -> Result<Option<AnalysisPreset>, Error> {
+    Ok(AnalysisPresetConfig::load(&app)?.resolve(file.as_deref(), tab_type.as_deref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(depth: Option<u32>) -> AnalysisPreset {
+        AnalysisPreset {
+            engine: "stockfish".to_string(),
+            depth,
+            multipv: 3,
+        }
+    }
+
+    #[test]
+    fn test_resolve_prefers_database() {
+        let mut config = AnalysisPresetConfig::default();
+        config.tab_types.insert("analysis".to_string(), preset(Some(20)));
+        config
+            .databases
+            .insert(database_key(Path::new("blitz.db3")), preset(Some(12)));
+
+        assert_eq!(
+            config.resolve(Some(Path::new("blitz.db3")), Some("analysis")),
+            Some(preset(Some(12)))
+        );
+        assert_eq!(
+            config.resolve(Some(Path::new("other.db3")), Some("analysis")),
+            Some(preset(Some(20)))
+        );
+        assert_eq!(config.resolve(None, Some("play")), None);
+    }
+}
@@ -3,24 +3,34 @@
 //! This module provides the `EngineProcess` struct for managing a UCI chess engine process,
 //! sending commands, updating options, and parsing engine output for best-move analysis.
 
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use tokio::io::AsyncWriteExt;
 use vampirc_uci::{uci::ScoreValue, UciInfoAttribute};
 
+use crate::app::platform::power;
 use crate::error::Error;
+use crate::notation::display_san;
 
-use super::types::{BestMoves, EngineLog, EngineOptions, GoMode};
+use super::types::{BestMoves, EmissionOptions, EngineLog, EngineOptions, GoMode};
 use super::uci::UciCommunicator;
 use shakmaty::{fen::Fen, san::SanPlus, uci::UciMove, CastlingMode, Chess, Color, Position};
 
 #[cfg(target_os = "windows")]
 pub const CREATE_NO_WINDOW: u32 = 0x08000000;
 
+/// Best-move updates emitted per second when the caller sets no limit.
+const DEFAULT_EMISSIONS_PER_SECOND: u32 = 10;
+
+/// Centipawn value given to mate scores when comparing lines.
+const MATE_CP: i32 = 100_000;
+
 /// Represents a running UCI engine process and its state.
 pub struct EngineProcess {
     pub stdin: tokio::process::ChildStdin,
+    child: tokio::process::Child,
     pub last_depth: u32,
     pub best_moves: Vec<BestMoves>,
     pub last_best_moves: Vec<BestMoves>,
@@ -31,6 +41,19 @@ pub struct EngineProcess {
     pub real_multipv: u16,
     pub logs: Vec<EngineLog>,
     pub start: Instant,
+    /// Searching the position after the expected opponent move (`go ponder`).
+    pub pondering: bool,
+    /// Expected opponent move from the last `bestmove`, if the engine suggested one.
+    pub ponder_move: Option<String>,
+    /// A pondering search was stopped; its `bestmove` is for a position that was never reached.
+    pub discard_bestmove: bool,
+    /// Filtering and rate limit of the lines emitted to the frontend.
+    pub emission: EmissionOptions,
+    last_emit: Option<Instant>,
+    ponder_option_set: bool,
+    /// Analysis sessions served by this engine with the number of lines each asked for. The engine
+    /// searches with the largest `MultiPV` and every session gets its own best lines.
+    pub sessions: HashMap<String, u16>,
 }
 
 impl EngineProcess {
@@ -62,6 +85,7 @@ impl EngineProcess {
         Ok((
             Self {
                 stdin: comm.stdin,
+                child: comm.child,
                 last_depth: 0,
                 best_moves: Vec::new(),
                 last_best_moves: Vec::new(),
@@ -72,6 +96,13 @@ impl EngineProcess {
                 go_mode: GoMode::Infinite,
                 running: false,
                 start: Instant::now(),
+                pondering: false,
+                ponder_move: None,
+                discard_bestmove: false,
+                emission: EmissionOptions::default(),
+                last_emit: None,
+                ponder_option_set: false,
+                sessions: HashMap::new(),
             },
             comm.stdout_lines,
         ))
@@ -90,7 +121,8 @@ impl EngineProcess {
 
     /// Set all engine options, including FEN, moves, and extra UCI options.
     /// Updates multipv and resets best-move tracking.
-    pub async fn set_options(&mut self, options: EngineOptions) -> Result<(), Error> {
+    pub async fn set_options(&mut self, mut options: EngineOptions) -> Result<(), Error> {
+        power::throttle_engine_options(&mut options.extra_options);
         let fen: Fen = options.fen.parse()?;
         let mut pos: Chess = match fen.into_position(CastlingMode::Chess960) {
             Ok(p) => p,
@@ -101,14 +133,7 @@ impl EngineProcess {
             let mv = uci.to_move(&pos)?;
             pos.play_unchecked(&mv);
         }
-        let multipv = options
-            .extra_options
-            .iter()
-            .find(|x| x.name == "MultiPV")
-            .map(|x| x.value.parse().unwrap_or(1))
-            .unwrap_or(1);
-
-        self.real_multipv = multipv.min(pos.legal_moves().len() as u16);
+        self.real_multipv = options.multipv().min(pos.legal_moves().len() as u16);
 
         for option in &options.extra_options {
             if !self.options.extra_options.contains(option) {
@@ -140,26 +165,63 @@ impl EngineProcess {
         Ok(())
     }
 
-    /// Start engine search with the given mode (depth, time, etc).
-    pub async fn go(&mut self, mode: &GoMode) -> Result<(), Error> {
-        self.go_mode = mode.clone();
-        let msg = match mode {
-            GoMode::Depth(depth) => format!("go depth {}\n", depth),
-            GoMode::Time(time) => format!("go movetime {}\n", time),
-            GoMode::Nodes(nodes) => format!("go nodes {}\n", nodes),
+    /// Search limits of a `go` command, e.g. `depth 20`.
+    fn go_limits(mode: &GoMode) -> String {
+        match mode {
+            GoMode::Depth(depth) => format!("depth {}", depth),
+            GoMode::Time(time) => format!("movetime {}", time),
+            GoMode::Nodes(nodes) => format!("nodes {}", nodes),
             GoMode::PlayersTime(super::types::PlayersTime { white, black, winc, binc }) => {
                 // Don't add movetime limit - let the engine use the available time
                 // The engine will manage its time based on wtime/btime
-                format!(
-                    "go wtime {} btime {} winc {} binc {}\n",
-                    white, black, winc, binc
-                )
+                format!("wtime {} btime {} winc {} binc {}", white, black, winc, binc)
             }
-            GoMode::Infinite => "go infinite\n".to_string(),
-        };
+            GoMode::Infinite => "infinite".to_string(),
+        }
+    }
+
+    /// Start engine search with the given mode (depth, time, etc).
+    pub async fn go(&mut self, mode: &GoMode) -> Result<(), Error> {
+        self.go_mode = mode.clone();
+        let msg = format!("go {}\n", Self::go_limits(mode));
+        self.stdin.write_all(msg.as_bytes()).await?;
+        self.logs.push(EngineLog::Gui(msg));
+        self.running = true;
+        self.pondering = false;
+        self.ponder_move = None;
+        self.start = Instant::now();
+        Ok(())
+    }
+
+    /// Think on the opponent's time: search the position after `ponder_move` until the opponent
+    /// moves. If they play the expected move, `ponderhit` turns this into a normal search with the
+    /// same limits; otherwise `stop` ends it and its result is discarded.
+    pub async fn go_ponder(&mut self, mode: &GoMode, ponder_move: String) -> Result<(), Error> {
+        let mut options = self.options.clone();
+        options.moves.push(ponder_move);
+        // Validates the ponder move before anything is sent to the engine.
+        self.set_options(options).await?;
+        if !self.ponder_option_set {
+            self.set_option("Ponder", true).await?;
+            self.ponder_option_set = true;
+        }
+
+        self.go_mode = mode.clone();
+        let msg = format!("go ponder {}\n", Self::go_limits(mode));
         self.stdin.write_all(msg.as_bytes()).await?;
         self.logs.push(EngineLog::Gui(msg));
         self.running = true;
+        self.pondering = true;
+        self.ponder_move = None;
+        self.start = Instant::now();
+        Ok(())
+    }
+
+    /// The opponent played the expected move: continue the pondering search as a normal one.
+    pub async fn ponderhit(&mut self) -> Result<(), Error> {
+        self.stdin.write_all(b"ponderhit\n").await?;
+        self.logs.push(EngineLog::Gui("ponderhit\n".to_string()));
+        self.pondering = false;
         self.start = Instant::now();
         Ok(())
     }
@@ -169,9 +231,53 @@ impl EngineProcess {
         self.stdin.write_all(b"stop\n").await?;
         self.logs.push(EngineLog::Gui("stop\n".to_string()));
         self.running = false;
+        if self.pondering {
+            self.pondering = false;
+            self.discard_bestmove = true;
+        }
         Ok(())
     }
 
+    /// Whether lines reaching `depth` may be emitted now. Counts as an emission when it returns
+    /// `true`.
+    pub fn emission_due(&mut self, depth: u32) -> bool {
+        if depth < self.emission.min_depth.unwrap_or(0) {
+            return false;
+        }
+        let per_second = self
+            .emission
+            .max_per_second
+            .unwrap_or(DEFAULT_EMISSIONS_PER_SECOND)
+            .max(1);
+        let interval = Duration::from_secs(1) / per_second;
+        if self.last_emit.is_some_and(|last| last.elapsed() < interval) {
+            return false;
+        }
+        self.last_emit = Some(Instant::now());
+        true
+    }
+
+    /// `MultiPV` covering the lines of every session.
+    pub fn sessions_multipv(&self) -> u16 {
+        self.sessions.values().copied().max().unwrap_or(1)
+    }
+
+    /// The best lines of `lines` for each session.
+    pub fn session_lines(&self, lines: &[BestMoves]) -> Vec<(String, Vec<BestMoves>)> {
+        self.sessions
+            .iter()
+            .map(|(session, multipv)| {
+                (session.clone(), lines.iter().take(*multipv as usize).cloned().collect())
+            })
+            .collect()
+    }
+
+    /// The last emitted lines of `session`.
+    pub fn last_lines(&self, session: &str) -> Vec<BestMoves> {
+        let multipv = self.sessions.get(session).copied().unwrap_or(self.real_multipv);
+        self.last_best_moves.iter().take(multipv as usize).cloned().collect()
+    }
+
     /// Kill the engine process.
     pub async fn kill(&mut self) -> Result<(), Error> {
         self.stdin.write_all(b"quit\n").await?;
@@ -179,6 +285,43 @@ impl EngineProcess {
         self.running = false;
         Ok(())
     }
+
+    /// Ask the engine to quit and kill it if it has not exited within `timeout`.
+    pub async fn quit(&mut self, timeout: Duration) {
+        let _ = self.kill().await;
+        match tokio::time::timeout(timeout, self.child.wait()).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => log::warn!("Failed to wait for engine exit: {}", e),
+            Err(_) => {
+                log::warn!("Engine did not quit within {:?}, killing it", timeout);
+                if let Err(e) = self.child.kill().await {
+                    log::warn!("Failed to kill engine: {}", e);
+                }
+            }
+        }
+    }
+}
+
+fn line_cp(line: &BestMoves) -> i32 {
+    match line.score.value {
+        ScoreValue::Cp(cp) => cp,
+        ScoreValue::Mate(n) if n > 0 => MATE_CP - n as i32,
+        ScoreValue::Mate(n) => -MATE_CP - n as i32,
+    }
+}
+
+/// Lines within `max_cp_loss` centipawns of the first (best) one. Scores are all from the same
+/// side's point of view and no line scores better than the best, so the distance is enough.
+pub fn filter_lines(lines: &[BestMoves], max_cp_loss: Option<u32>) -> Vec<BestMoves> {
+    let (Some(max_cp_loss), Some(best)) = (max_cp_loss, lines.first()) else {
+        return lines.to_vec();
+    };
+    let best = line_cp(best);
+    lines
+        .iter()
+        .filter(|line| (best - line_cp(line)).unsigned_abs() <= max_cp_loss)
+        .cloned()
+        .collect()
 }
 
 /// Invert a UCI score (for black's perspective).
@@ -228,7 +371,9 @@ pub fn parse_uci_attrs(
                     let uci: UciMove = mv.to_string().parse()?;
                     let m = uci.to_move(&pos)?;
                     let san = SanPlus::from_move_and_play_unchecked(&mut pos, &m);
-                    best_moves.san_moves.push(san.to_string());
+                    let san = san.to_string();
+                    best_moves.display_moves.push(display_san(&san));
+                    best_moves.san_moves.push(san);
                     best_moves.uci_moves.push(uci.to_string());
                 }
             }
@@ -263,3 +408,40 @@ pub fn parse_uci_attrs(
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(value: ScoreValue) -> BestMoves {
+        let mut line = BestMoves::default();
+        line.score.value = value;
+        line
+    }
+
+    #[test]
+    fn test_filter_lines() {
+        let lines = vec![
+            line(ScoreValue::Cp(-120)),
+            line(ScoreValue::Cp(-150)),
+            line(ScoreValue::Cp(-400)),
+        ];
+        assert_eq!(filter_lines(&lines, Some(50)).len(), 2);
+        assert_eq!(filter_lines(&lines, None).len(), 3);
+
+        let mates = vec![line(ScoreValue::Mate(2)), line(ScoreValue::Mate(3)), line(ScoreValue::Cp(900))];
+        assert_eq!(filter_lines(&mates, Some(10)).len(), 2);
+    }
+
+    #[test]
+    fn test_options_multipv() {
+        let mut options = EngineOptions::default();
+        assert_eq!(options.multipv(), 1);
+        options.set_extra_option("Threads", "4".to_string());
+        options.set_extra_option("MultiPV", "3".to_string());
+        assert_eq!(options.multipv(), 3);
+        options.set_extra_option("MultiPV", "5".to_string());
+        assert_eq!(options.multipv(), 5);
+        assert_eq!(options.extra_options.len(), 2);
+    }
+}
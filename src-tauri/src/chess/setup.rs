@@ -0,0 +1,464 @@
+//! Legality checks and completion of board editor setups.
+//!
+//! `validate_setup` explains why a FEN is not a legal position, naming the colors and squares
+//! involved, so the board editor can point at the problem instead of only refusing the position.
+//! `complete_setup` turns a partial FEN, possibly just the piece placement, into a full one:
+//! missing fields get defaults derived from the board, and castling rights or en passant squares
+//! that cannot apply are dropped.
+
+use serde::Serialize;
+use shakmaty::{
+    fen::Fen, Bitboard, Board, CastlingMode, Chess, Color, EnPassantMode, FromSetup,
+    PositionErrorKinds, Rank, Role, Setup, Square,
+};
+use specta::Type;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum SetupIssueKind {
+    InvalidFen,
+    EmptyBoard,
+    MissingKing,
+    TooManyKings,
+    PawnsOnBackRank,
+    TooManyPawns,
+    /// More pieces than promotions could have produced.
+    TooMuchMaterial,
+    /// The side that just moved is in check.
+    OppositeCheck,
+    /// The side to move is in check by more pieces, or in a way, that no move can give.
+    ImpossibleCheck,
+    InvalidCastlingRights,
+    InvalidEnPassant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SetupIssue {
+    pub kind: SetupIssueKind,
+    /// Side the issue is about, "white" or "black".
+    pub color: Option<String>,
+    /// Squares involved, e.g. the pawns on a back rank or the checking pieces.
+    pub squares: Vec<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SetupDiagnostics {
+    pub legal: bool,
+    pub issues: Vec<SetupIssue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletedSetup {
+    pub fen: String,
+    pub diagnostics: SetupDiagnostics,
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+fn square_names(squares: Bitboard) -> Vec<String> {
+    squares.into_iter().map(|sq| sq.to_string()).collect()
+}
+
+fn issue(
+    kind: SetupIssueKind,
+    color: Option<Color>,
+    squares: Bitboard,
+    message: String,
+) -> SetupIssue {
+    SetupIssue {
+        kind,
+        color: color.map(|color| color_name(color).to_string()),
+        squares: square_names(squares),
+        message,
+    }
+}
+
+/// Pieces giving check to the king of `king_color`.
+fn checkers(board: &Board, king_color: Color) -> Bitboard {
+    match board.king_of(king_color) {
+        Some(king) => board.attacks_to(king, !king_color, board.occupied()),
+        None => Bitboard::EMPTY,
+    }
+}
+
+/// Castling rights of `setup` without a king and rook of the same color on their back rank.
+fn invalid_castling_rights(setup: &Setup) -> Bitboard {
+    setup
+        .castling_rights
+        .into_iter()
+        .filter(|&rook| {
+            let color = if rook.rank() == Rank::First {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let on_backrank = |sq: Square| sq.rank() == color.backrank();
+            let has_rook =
+                rook.rank() == color.backrank() && setup.board.piece_at(rook) == Some(color.rook());
+            let has_king = setup.board.king_of(color).is_some_and(on_backrank);
+            !(has_rook && has_king)
+        })
+        .collect()
+}
+
+fn diagnose(setup: &Setup) -> Vec<SetupIssue> {
+    let board = &setup.board;
+    let mut issues = Vec::new();
+
+    if board.occupied().is_empty() {
+        issues.push(issue(
+            SetupIssueKind::EmptyBoard,
+            None,
+            Bitboard::EMPTY,
+            "The board is empty".to_string(),
+        ));
+    }
+    for color in Color::ALL {
+        let name = color_name(color);
+        let kings = board.kings() & board.by_color(color);
+        match kings.count() {
+            0 => issues.push(issue(
+                SetupIssueKind::MissingKing,
+                Some(color),
+                Bitboard::EMPTY,
+                format!("The {} king is missing", name),
+            )),
+            1 => {}
+            n => issues.push(issue(
+                SetupIssueKind::TooManyKings,
+                Some(color),
+                kings,
+                format!("{} has {} kings", capitalized(name), n),
+            )),
+        }
+
+        let pawns = board.pawns() & board.by_color(color);
+        let back_rank_pawns = pawns & Bitboard::BACKRANKS;
+        if back_rank_pawns.any() {
+            issues.push(issue(
+                SetupIssueKind::PawnsOnBackRank,
+                Some(color),
+                back_rank_pawns,
+                format!(
+                    "{} pawns cannot stand on the first or last rank",
+                    capitalized(name)
+                ),
+            ));
+        }
+        if pawns.count() > 8 {
+            issues.push(issue(
+                SetupIssueKind::TooManyPawns,
+                Some(color),
+                pawns,
+                format!("{} has {} pawns", capitalized(name), pawns.count()),
+            ));
+        }
+        // Every piece beyond the initial set must come from a promoted pawn.
+        let promoted: usize = [
+            (Role::Queen, 1),
+            (Role::Rook, 2),
+            (Role::Bishop, 2),
+            (Role::Knight, 2),
+        ]
+        .iter()
+        .map(|&(role, initial)| {
+            (board.by_role(role) & board.by_color(color))
+                .count()
+                .saturating_sub(initial)
+        })
+        .sum();
+        if pawns.count() <= 8 && promoted > 8 - pawns.count() {
+            issues.push(issue(
+                SetupIssueKind::TooMuchMaterial,
+                Some(color),
+                Bitboard::EMPTY,
+                format!(
+                    "{} has more pieces than its missing pawns could have promoted to",
+                    capitalized(name)
+                ),
+            ));
+        }
+    }
+
+    let waiting = !setup.turn;
+    let opposite = checkers(board, waiting);
+    if opposite.any() {
+        issues.push(issue(
+            SetupIssueKind::OppositeCheck,
+            Some(waiting),
+            opposite,
+            format!(
+                "The {} king is in check but it is {}'s move",
+                color_name(waiting),
+                color_name(setup.turn)
+            ),
+        ));
+    }
+    let checking = checkers(board, setup.turn);
+    if checking.count() > 2 {
+        issues.push(issue(
+            SetupIssueKind::ImpossibleCheck,
+            Some(setup.turn),
+            checking,
+            format!(
+                "The {} king is in check by {} pieces at once",
+                color_name(setup.turn),
+                checking.count()
+            ),
+        ));
+    }
+
+    let castling = invalid_castling_rights(setup);
+    if castling.any() {
+        issues.push(issue(
+            SetupIssueKind::InvalidCastlingRights,
+            None,
+            castling,
+            "Castling rights need the king and the rook on their original rank".to_string(),
+        ));
+    }
+    issues
+}
+
+fn capitalized(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Diagnostics of `setup`, with the issues only the full rules catch added from shakmaty.
+fn validate(setup: &Setup) -> SetupDiagnostics {
+    let mut issues = diagnose(setup);
+    let kinds = match Chess::from_setup(setup.clone(), CastlingMode::Chess960) {
+        Ok(_) => PositionErrorKinds::empty(),
+        Err(e) => e.kinds(),
+    };
+    let reported =
+        |kind: SetupIssueKind, issues: &[SetupIssue]| issues.iter().any(|issue| issue.kind == kind);
+    if kinds.contains(PositionErrorKinds::IMPOSSIBLE_CHECK)
+        && !reported(SetupIssueKind::ImpossibleCheck, &issues)
+    {
+        issues.push(issue(
+            SetupIssueKind::ImpossibleCheck,
+            Some(setup.turn),
+            checkers(&setup.board, setup.turn),
+            format!(
+                "No move could have put the {} king in this check",
+                color_name(setup.turn)
+            ),
+        ));
+    }
+    if kinds.contains(PositionErrorKinds::INVALID_CASTLING_RIGHTS)
+        && !reported(SetupIssueKind::InvalidCastlingRights, &issues)
+    {
+        issues.push(issue(
+            SetupIssueKind::InvalidCastlingRights,
+            None,
+            setup.castling_rights,
+            "The castling rights do not match the position".to_string(),
+        ));
+    }
+    if kinds.contains(PositionErrorKinds::INVALID_EP_SQUARE) {
+        issues.push(issue(
+            SetupIssueKind::InvalidEnPassant,
+            None,
+            setup.ep_square.map_or(Bitboard::EMPTY, Bitboard::from),
+            "No pawn can have just moved past the en passant square".to_string(),
+        ));
+    }
+    if kinds.contains(PositionErrorKinds::TOO_MUCH_MATERIAL)
+        && !reported(SetupIssueKind::TooMuchMaterial, &issues)
+        && !reported(SetupIssueKind::TooManyPawns, &issues)
+    {
+        issues.push(issue(
+            SetupIssueKind::TooMuchMaterial,
+            None,
+            Bitboard::EMPTY,
+            "There is more material than a game can reach".to_string(),
+        ));
+    }
+    SetupDiagnostics {
+        legal: kinds.is_empty() && issues.is_empty(),
+        issues,
+    }
+}
+
+/// Castling rights of every king and rook still on their original squares.
+fn default_castling_rights(board: &Board) -> Bitboard {
+    let mut rights = Bitboard::EMPTY;
+    for color in Color::ALL {
+        let rank = color.backrank();
+        if board.king_of(color) != Some(Square::from_coords(shakmaty::File::E, rank)) {
+            continue;
+        }
+        for file in [shakmaty::File::A, shakmaty::File::H] {
+            let rook = Square::from_coords(file, rank);
+            if board.piece_at(rook) == Some(color.rook()) {
+                rights.add(rook);
+            }
+        }
+    }
+    rights
+}
+
+/// Full setup for a partial FEN. Missing fields get defaults: the side that is not giving check
+/// to move, castling for kings and rooks on their original squares, no en passant square and
+/// move counters of a fresh position.
+fn complete(partial: &str) -> Result<Setup, Error> {
+    let fields: Vec<&str> = partial.split_whitespace().collect();
+    let Some(placement) = fields.first() else {
+        return Err(Error::PackageManager("The setup has no pieces".to_string()));
+    };
+    let turn = fields.get(1).copied();
+    let castling = fields.get(2).copied();
+    let fen = format!(
+        "{} {} {} {} {} {}",
+        placement,
+        turn.unwrap_or("w"),
+        castling.unwrap_or("-"),
+        fields.get(3).unwrap_or(&"-"),
+        fields.get(4).unwrap_or(&"0"),
+        fields.get(5).unwrap_or(&"1"),
+    );
+    let mut setup = fen.parse::<Fen>()?.into_setup();
+
+    if turn.is_none() && checkers(&setup.board, Color::Black).any() {
+        setup.turn = Color::Black;
+    }
+    if castling.is_none() {
+        setup.castling_rights = default_castling_rights(&setup.board);
+    } else {
+        setup.castling_rights &= !invalid_castling_rights(&setup);
+    }
+    if let Err(e) = Chess::from_setup(setup.clone(), CastlingMode::Chess960) {
+        if e.kinds().contains(PositionErrorKinds::INVALID_EP_SQUARE) {
+            setup.ep_square = None;
+        }
+    }
+    Ok(setup)
+}
+
+/// Legality diagnostics of a board editor position.
+#[tauri::command]
+#[specta::specta]
+pub fn validate_setup(fen: String) -> SetupDiagnostics {
+    match fen.parse::<Fen>() {
+        Ok(fen) => validate(&fen.into_setup()),
+        Err(e) => SetupDiagnostics {
+            legal: false,
+            issues: vec![issue(
+                SetupIssueKind::InvalidFen,
+                None,
+                Bitboard::EMPTY,
+                format!("Invalid FEN: {}", e),
+            )],
+        },
+    }
+}
+
+/// Complete a partial FEN, such as only the piece placement, into a full one with its
+/// diagnostics.
+#[tauri::command]
+#[specta::specta]
+pub fn complete_setup(partial: String) -> Result<CompletedSetup, Error> {
+    let setup = complete(&partial)?;
+    let diagnostics = validate(&setup);
+    let fen = match Chess::from_setup(setup.clone(), CastlingMode::Chess960) {
+        Ok(position) => Fen::from_position(position, EnPassantMode::Legal).to_string(),
+        Err(_) => Fen::from_setup(setup).to_string(),
+    };
+    Ok(CompletedSetup { fen, diagnostics })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(fen: &str) -> Vec<SetupIssueKind> {
+        validate_setup(fen.to_string())
+            .issues
+            .into_iter()
+            .map(|issue| issue.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_legal_positions() {
+        let diagnostics =
+            validate_setup("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string());
+        assert!(diagnostics.legal);
+        assert!(diagnostics.issues.is_empty());
+        assert!(validate_setup("4k3/8/8/8/8/8/8/4K2R w K - 0 1".to_string()).legal);
+    }
+
+    #[test]
+    fn test_piece_issues() {
+        assert_eq!(
+            kinds("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            vec![SetupIssueKind::MissingKing]
+        );
+        assert_eq!(
+            kinds("4k3/8/8/8/8/8/8/K3K3 w - - 0 1"),
+            vec![SetupIssueKind::TooManyKings]
+        );
+        let diagnostics = validate_setup("P3k3/8/8/8/8/8/8/4K2p w - - 0 1".to_string());
+        assert!(!diagnostics.legal);
+        assert_eq!(diagnostics.issues.len(), 2);
+        assert_eq!(diagnostics.issues[0].squares, vec!["a8"]);
+        assert_eq!(diagnostics.issues[1].color.as_deref(), Some("black"));
+        assert_eq!(kinds("not a fen"), vec![SetupIssueKind::InvalidFen]);
+    }
+
+    #[test]
+    fn test_check_issues() {
+        let diagnostics = validate_setup("4k3/8/8/8/8/8/8/4KR2 b - - 0 1".to_string());
+        assert!(diagnostics.legal);
+        let diagnostics = validate_setup("4k3/8/8/8/8/8/8/4K2R w - - 0 1".to_string());
+        assert!(diagnostics.legal);
+        let diagnostics = validate_setup("4k3/4R3/8/8/8/8/8/4K3 w - - 0 1".to_string());
+        assert_eq!(diagnostics.issues[0].kind, SetupIssueKind::OppositeCheck);
+        assert_eq!(diagnostics.issues[0].squares, vec!["e7"]);
+    }
+
+    #[test]
+    fn test_castling_issues() {
+        assert_eq!(
+            kinds("4k3/8/8/8/8/8/4K3/7R w K - 0 1"),
+            vec![SetupIssueKind::InvalidCastlingRights]
+        );
+    }
+
+    #[test]
+    fn test_complete_setup() {
+        let completed =
+            complete_setup("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string()).unwrap();
+        assert_eq!(
+            completed.fen,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert!(completed.diagnostics.legal);
+
+        // Black is in check, so it must be black's move; the h1 rook has moved.
+        let completed = complete_setup("r3k3/4R3/8/8/8/8/8/4K1R1".to_string()).unwrap();
+        assert_eq!(completed.fen, "r3k3/4R3/8/8/8/8/8/4K1R1 b q - 0 1");
+        assert!(completed.diagnostics.legal);
+
+        // Given fields are kept, impossible castling rights and en passant squares dropped.
+        let completed = complete_setup("4k3/8/8/8/8/8/8/4K3 b KQ e3".to_string()).unwrap();
+        assert_eq!(completed.fen, "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+        assert!(complete_setup("  ".to_string()).is_err());
+    }
+}
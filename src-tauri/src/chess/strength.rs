@@ -0,0 +1,288 @@
+//! Rough playing strength from training results.
+//!
+//! Three independent estimates are combined: the performance rating of puzzle attempts, the
+//! average accuracy of reviewed games and how often the player's moves matched the engine's first
+//! choice. Puzzle performance is the maximum likelihood rating under the Elo model; accuracy and
+//! match rate are mapped to ratings through tables of typical values per rating band, with a
+//! per-game spread that shrinks as more games are reviewed. The estimates are weighted by their
+//! inverse variance, so a handful of games barely moves a rating backed by hundreds of puzzles.
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// Two-sided 95% quantile of the normal distribution.
+const Z_95: f64 = 1.959964;
+const MIN_RATING: f64 = 100.0;
+const MAX_RATING: f64 = 3200.0;
+
+/// Average accuracy (percent) of players of a given rating.
+const ACCURACY_RATINGS: [(f64, f64); 8] = [
+    (40.0, 400.0),
+    (55.0, 800.0),
+    (65.0, 1200.0),
+    (73.0, 1600.0),
+    (80.0, 2000.0),
+    (86.0, 2400.0),
+    (91.0, 2800.0),
+    (95.0, 3200.0),
+];
+/// Spread of the rating implied by the accuracy of a single game.
+const ACCURACY_GAME_SD: f64 = 400.0;
+
+/// Share of moves matching the engine's first choice for players of a given rating.
+const MATCH_RATE_RATINGS: [(f64, f64); 7] = [
+    (0.25, 400.0),
+    (0.33, 800.0),
+    (0.40, 1200.0),
+    (0.46, 1600.0),
+    (0.52, 2000.0),
+    (0.58, 2400.0),
+    (0.66, 2800.0),
+];
+/// Spread of the rating implied by the match rate of a single game.
+const MATCH_RATE_GAME_SD: f64 = 450.0;
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PuzzleAttempt {
+    pub rating: f64,
+    pub solved: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewedGameStats {
+    /// Accuracy of the player's moves, between 0 and 100.
+    pub accuracy: Option<f64>,
+    /// Share of the player's moves that were the engine's first choice, between 0 and 1.
+    pub engine_match_rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum StrengthSource {
+    Puzzles,
+    Accuracy,
+    EngineMatch,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct StrengthComponent {
+    pub source: StrengthSource,
+    pub rating: f64,
+    /// Standard error of `rating`.
+    pub error: f64,
+    /// Puzzles or games the component is based on.
+    pub samples: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct StrengthEstimate {
+    pub rating: f64,
+    /// Bounds of the 95% confidence interval around `rating`.
+    pub low: f64,
+    pub high: f64,
+    pub components: Vec<StrengthComponent>,
+}
+
+/// Expected score against an opponent `diff` points weaker.
+fn expected_score(diff: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-diff / 400.0))
+}
+
+/// Linear interpolation in a table sorted by its first column, clamped to its ends.
+fn interpolate(table: &[(f64, f64)], x: f64) -> f64 {
+    let (first, last) = (table[0], table[table.len() - 1]);
+    if x <= first.0 {
+        return first.1;
+    }
+    if x >= last.0 {
+        return last.1;
+    }
+    let i = table.iter().position(|&(x1, _)| x1 >= x).unwrap();
+    let ((x0, y0), (x1, y1)) = (table[i - 1], table[i]);
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
+
+/// Performance rating of the attempts. A virtual win and loss against their average rating keep
+/// the estimate finite when every puzzle was solved or failed.
+fn puzzle_component(attempts: &[PuzzleAttempt]) -> Option<StrengthComponent> {
+    if attempts.is_empty() {
+        return None;
+    }
+    let mean = attempts.iter().map(|a| a.rating).sum::<f64>() / attempts.len() as f64;
+    let mut games: Vec<(f64, f64)> = attempts
+        .iter()
+        .map(|a| (a.rating, if a.solved { 1.0 } else { 0.0 }))
+        .collect();
+    games.extend([(mean, 1.0), (mean, 0.0)]);
+    let score: f64 = games.iter().map(|(_, s)| s).sum();
+
+    // The expected total score rises with the rating, so bisection finds where it meets the score.
+    let (mut low, mut high) = (MIN_RATING - 1000.0, MAX_RATING + 1000.0);
+    for _ in 0..60 {
+        let mid = (low + high) / 2.0;
+        let expected: f64 = games.iter().map(|(r, _)| expected_score(mid - r)).sum();
+        if expected < score {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    let rating = (low + high) / 2.0;
+
+    let scale = 10f64.ln() / 400.0;
+    let information: f64 = games
+        .iter()
+        .map(|(r, _)| {
+            let p = expected_score(rating - r);
+            p * (1.0 - p) * scale * scale
+        })
+        .sum();
+    Some(StrengthComponent {
+        source: StrengthSource::Puzzles,
+        rating,
+        error: 1.0 / information.sqrt(),
+        samples: attempts.len() as u32,
+    })
+}
+
+/// Average of the ratings implied by each game's value.
+fn game_component(
+    source: StrengthSource,
+    values: impl Iterator<Item = f64>,
+    table: &[(f64, f64)],
+    game_sd: f64,
+) -> Option<StrengthComponent> {
+    let ratings: Vec<f64> = values.map(|v| interpolate(table, v)).collect();
+    if ratings.is_empty() {
+        return None;
+    }
+    let n = ratings.len() as f64;
+    Some(StrengthComponent {
+        source,
+        rating: ratings.iter().sum::<f64>() / n,
+        error: game_sd / n.sqrt(),
+        samples: ratings.len() as u32,
+    })
+}
+
+pub fn strength_estimate(
+    puzzles: &[PuzzleAttempt],
+    games: &[ReviewedGameStats],
+) -> Option<StrengthEstimate> {
+    let accuracies = games
+        .iter()
+        .filter_map(|g| g.accuracy)
+        .filter(|a| a.is_finite());
+    let match_rates = games
+        .iter()
+        .filter_map(|g| g.engine_match_rate)
+        .filter(|m| m.is_finite());
+    let components: Vec<StrengthComponent> = [
+        puzzle_component(puzzles),
+        game_component(
+            StrengthSource::Accuracy,
+            accuracies,
+            &ACCURACY_RATINGS,
+            ACCURACY_GAME_SD,
+        ),
+        game_component(
+            StrengthSource::EngineMatch,
+            match_rates,
+            &MATCH_RATE_RATINGS,
+            MATCH_RATE_GAME_SD,
+        ),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if components.is_empty() {
+        return None;
+    }
+
+    let weights: Vec<f64> = components
+        .iter()
+        .map(|c| 1.0 / (c.error * c.error))
+        .collect();
+    let total: f64 = weights.iter().sum();
+    let rating = components
+        .iter()
+        .zip(&weights)
+        .map(|(c, w)| c.rating * w)
+        .sum::<f64>()
+        / total;
+    let margin = Z_95 / total.sqrt();
+    Some(StrengthEstimate {
+        rating: rating.clamp(MIN_RATING, MAX_RATING),
+        low: (rating - margin).clamp(MIN_RATING, MAX_RATING),
+        high: (rating + margin).clamp(MIN_RATING, MAX_RATING),
+        components,
+    })
+}
+
+/// Rough rating with a 95% confidence interval from puzzle attempts and reviewed games. `None`
+/// without any puzzles or reviewed games.
+#[tauri::command]
+#[specta::specta]
+pub fn estimate_playing_strength(
+    puzzles: Vec<PuzzleAttempt>,
+    games: Vec<ReviewedGameStats>,
+) -> Option<StrengthEstimate> {
+    strength_estimate(&puzzles, &games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempts(rating: f64, solved: usize, failed: usize) -> Vec<PuzzleAttempt> {
+        let attempt = |solved| PuzzleAttempt { rating, solved };
+        (0..solved)
+            .map(|_| attempt(true))
+            .chain((0..failed).map(|_| attempt(false)))
+            .collect()
+    }
+
+    #[test]
+    fn test_puzzle_performance() {
+        let even = puzzle_component(&attempts(1500.0, 50, 50)).unwrap();
+        assert!((even.rating - 1500.0).abs() < 1.0, "{}", even.rating);
+
+        // 76% is about +200 points.
+        let strong = puzzle_component(&attempts(1500.0, 76, 24)).unwrap();
+        assert!((strong.rating - 1700.0).abs() < 15.0, "{}", strong.rating);
+
+        let perfect = puzzle_component(&attempts(1500.0, 10, 0)).unwrap();
+        assert!(perfect.rating.is_finite() && perfect.rating > 1500.0);
+        assert!(perfect.error > strong.error);
+    }
+
+    #[test]
+    fn test_strength_estimate() {
+        assert!(strength_estimate(&[], &[]).is_none());
+        assert_eq!(interpolate(&ACCURACY_RATINGS, 76.5), 1800.0);
+        assert_eq!(interpolate(&ACCURACY_RATINGS, 99.0), 3200.0);
+
+        let games = vec![
+            ReviewedGameStats {
+                accuracy: Some(80.0),
+                engine_match_rate: Some(0.52),
+            };
+            4
+        ];
+        let estimate = strength_estimate(&attempts(1500.0, 100, 100), &games).unwrap();
+        assert_eq!(estimate.components.len(), 3);
+        // The puzzles carry most of the weight.
+        assert!(
+            estimate.rating > 1500.0 && estimate.rating < 1750.0,
+            "{}",
+            estimate.rating
+        );
+        assert!(estimate.low < estimate.rating && estimate.rating < estimate.high);
+        let puzzles = &estimate.components[0];
+        assert!(estimate.high - estimate.low < 2.0 * Z_95 * puzzles.error);
+    }
+}
@@ -0,0 +1,430 @@
+//! Syzygy endgame tablebase probing.
+//!
+//! The folders holding the `.rtbw` (WDL) and `.rtbz` (DTZ) files are stored in `tablebases.json`
+//! in the app config directory and opened on first use. Positions without castling rights and
+//! with no more pieces than the largest table are answered exactly: every legal move is ranked
+//! by its result and its distance to zeroing (DTZ), the number of plies until the next capture or
+//! pawn move on the way to that result. Wins that take longer than the 50-move rule allows are
+//! cursed wins, and are draws in practice.
+//!
+//! `get_best_moves` asks here first, and answers tablebase positions with these lines instead of
+//! running the engine.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+use shakmaty::{fen::Fen, san::SanPlus, uci::UciMove, CastlingMode, Chess, Color, Move, Position};
+use shakmaty_syzygy::Tablebase;
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+use vampirc_uci::uci::{Score, ScoreValue};
+
+use crate::{error::Error, notation::display_san, AppState};
+
+use super::types::{BestMoves, EngineOptions};
+
+/// Score reported for a tablebase win, less the DTZ, like engines report tablebase wins.
+const TB_WIN_CP: i32 = 20_000;
+/// Moves of each line beyond the first, following the best tablebase move.
+const PV_PLIES: usize = 10;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TablebaseConfig {
+    /// Folders searched for Syzygy files.
+    pub paths: Vec<PathBuf>,
+}
+
+impl TablebaseConfig {
+    fn get_config_path(app: &AppHandle) -> Result<PathBuf, Error> {
+        Ok(app
+            .path()
+            .resolve("tablebases.json", BaseDirectory::AppConfig)?)
+    }
+
+    pub fn load(app: &AppHandle) -> Result<Self, Error> {
+        let config_path = Self::get_config_path(app)?;
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&config_path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::PackageManager(format!("Invalid tablebase settings: {}", e)))
+    }
+
+    pub fn save(&self, app: &AppHandle) -> Result<(), Error> {
+        let config_path = Self::get_config_path(app)?;
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| {
+            Error::PackageManager(format!("Failed to serialize tablebase settings: {}", e))
+        })?;
+        fs::write(&config_path, json)?;
+        Ok(())
+    }
+}
+
+/// Tablebases opened from the configured folders; `None` until the first probe.
+#[derive(Default)]
+pub struct TablebaseState(RwLock<Option<Arc<Tablebase<Chess>>>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum TablebaseWdl {
+    Loss,
+    /// Lost, but drawn by the 50-move rule.
+    BlessedLoss,
+    Draw,
+    /// Won, but drawn by the 50-move rule.
+    CursedWin,
+    Win,
+}
+
+impl TablebaseWdl {
+    /// Result of a position whose side to move reaches `dtz` with `halfmoves` already on the
+    /// clock.
+    fn from_dtz(dtz: i32, halfmoves: u32) -> Self {
+        let within_rule = dtz.unsigned_abs() + halfmoves <= 100;
+        match dtz {
+            0 => TablebaseWdl::Draw,
+            dtz if dtz > 0 && within_rule => TablebaseWdl::Win,
+            dtz if dtz > 0 => TablebaseWdl::CursedWin,
+            _ if within_rule => TablebaseWdl::Loss,
+            _ => TablebaseWdl::BlessedLoss,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TablebaseMove {
+    pub uci: String,
+    pub san: String,
+    /// Result for the side playing the move.
+    pub wdl: TablebaseWdl,
+    /// Plies to the next zeroing move, positive when winning and negative when losing.
+    pub dtz: i32,
+    pub checkmate: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TablebaseProbe {
+    /// Result for the side to move.
+    pub wdl: TablebaseWdl,
+    pub dtz: i32,
+    /// Legal moves, best first.
+    pub moves: Vec<TablebaseMove>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TablebaseInfo {
+    pub tables: u32,
+    /// Most pieces, kings included, of the positions that can be probed.
+    pub max_pieces: u32,
+}
+
+fn probe_error(e: impl std::fmt::Display) -> Error {
+    Error::PackageManager(format!("Tablebase probe failed: {}", e))
+}
+
+fn open_tables(paths: &[PathBuf]) -> Result<(Tablebase<Chess>, u32), Error> {
+    let mut tables = Tablebase::new();
+    let mut count = 0;
+    for path in paths {
+        count += tables.add_directory(path).map_err(|e| {
+            Error::PackageManager(format!(
+                "Cannot read tablebases in {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+    }
+    Ok((tables, count as u32))
+}
+
+/// Tablebases of the configured folders, opened on first use; `None` when none are configured.
+fn tables(app: &AppHandle, state: &AppState) -> Result<Option<Arc<Tablebase<Chess>>>, Error> {
+    if let Some(tables) = state
+        .tablebase
+        .0
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+    {
+        return Ok(Some(tables.clone()));
+    }
+    let config = TablebaseConfig::load(app)?;
+    if config.paths.is_empty() {
+        return Ok(None);
+    }
+    let (tables, _) = open_tables(&config.paths)?;
+    let tables = Arc::new(tables);
+    *state.tablebase.0.write().unwrap_or_else(|e| e.into_inner()) = Some(tables.clone());
+    Ok(Some(tables))
+}
+
+fn in_tables(tables: &Tablebase<Chess>, position: &Chess) -> bool {
+    position.castles().is_empty() && position.board().occupied().count() <= tables.max_pieces()
+}
+
+/// Result and DTZ of `m` for the side playing it.
+fn move_dtz(tables: &Tablebase<Chess>, position: &Chess, m: &Move) -> Result<(i32, bool), Error> {
+    let mut after = position.clone();
+    after.play_unchecked(m);
+    if after.is_checkmate() {
+        return Ok((1, true));
+    }
+    let dtz = tables
+        .probe_dtz(&after)
+        .map_err(probe_error)?
+        .ignore_rounding()
+        .0;
+    // The opponent's DTZ counts from the position after the move; a winning capture or pawn
+    // move zeroes the clock itself.
+    let dtz = match dtz {
+        0 => 0,
+        dtz if dtz < 0 && m.is_zeroing() => 1,
+        dtz if dtz < 0 => -dtz + 1,
+        dtz => -dtz - 1,
+    };
+    Ok((dtz, false))
+}
+
+/// Orders moves best first: wins by the shortest way to zeroing, losses by the longest.
+fn sort_moves(moves: &mut [TablebaseMove]) {
+    moves.sort_by_key(|m| {
+        let distance = m.dtz.abs();
+        let tiebreak = if m.dtz > 0 { distance } else { -distance };
+        (std::cmp::Reverse(m.wdl), !m.checkmate, tiebreak)
+    });
+}
+
+fn probe_position(tables: &Tablebase<Chess>, position: &Chess) -> Result<TablebaseProbe, Error> {
+    let halfmoves = position.halfmoves();
+    let mut moves = Vec::new();
+    for m in position.legal_moves() {
+        let (dtz, checkmate) = move_dtz(tables, position, &m)?;
+        let zeroing = m.is_zeroing();
+        moves.push(TablebaseMove {
+            uci: m.to_uci(CastlingMode::Standard).to_string(),
+            san: SanPlus::from_move(position.clone(), &m).to_string(),
+            wdl: TablebaseWdl::from_dtz(dtz, if zeroing { 0 } else { halfmoves }),
+            dtz,
+            checkmate,
+        });
+    }
+    sort_moves(&mut moves);
+
+    let (wdl, dtz) = match moves.first() {
+        Some(best) => (best.wdl, best.dtz),
+        None if position.is_checkmate() => (TablebaseWdl::Loss, 0),
+        None => (TablebaseWdl::Draw, 0),
+    };
+    Ok(TablebaseProbe { wdl, dtz, moves })
+}
+
+/// Score of `m` for white, as engines report it.
+fn white_score(m: &TablebaseMove, turn: Color) -> Score {
+    let value = if m.checkmate {
+        ScoreValue::Mate(1)
+    } else {
+        match m.wdl {
+            TablebaseWdl::Win => ScoreValue::Cp(TB_WIN_CP - m.dtz),
+            TablebaseWdl::Loss => ScoreValue::Cp(-TB_WIN_CP - m.dtz),
+            _ => ScoreValue::Cp(0),
+        }
+    };
+    let value = match (turn, value) {
+        (Color::White, value) => value,
+        (Color::Black, ScoreValue::Cp(cp)) => ScoreValue::Cp(-cp),
+        (Color::Black, ScoreValue::Mate(n)) => ScoreValue::Mate(-n),
+    };
+    Score {
+        value,
+        ..Default::default()
+    }
+}
+
+/// Main line from `position` after `m`, following the best tablebase move.
+fn principal_variation(
+    tables: &Tablebase<Chess>,
+    position: &Chess,
+    m: &Move,
+) -> (Vec<String>, Vec<String>) {
+    let mut position = position.clone();
+    let mut m = m.clone();
+    let mut uci_moves = Vec::new();
+    let mut san_moves = Vec::new();
+    for _ in 0..=PV_PLIES {
+        uci_moves.push(m.to_uci(CastlingMode::Standard).to_string());
+        san_moves.push(SanPlus::from_move_and_play_unchecked(&mut position, &m).to_string());
+        match tables.best_move(&position) {
+            Ok(Some((best, _))) => m = best,
+            _ => break,
+        }
+    }
+    (uci_moves, san_moves)
+}
+
+/// Engine lines answering `options` from the tablebases, or `None` when the position is not in
+/// them. As many lines as the `MultiPV` option asks for are returned.
+pub fn best_lines(
+    app: &AppHandle,
+    state: &AppState,
+    options: &EngineOptions,
+) -> Result<Option<Vec<BestMoves>>, Error> {
+    let Some(tables) = tables(app, state)? else {
+        return Ok(None);
+    };
+    let fen: Fen = options.fen.parse()?;
+    let mut position: Chess = fen.into_position(CastlingMode::Chess960)?;
+    for m in &options.moves {
+        let m = UciMove::from_ascii(m.as_bytes())?.to_move(&position)?;
+        position.play_unchecked(&m);
+    }
+    if !in_tables(&tables, &position) || position.legal_moves().is_empty() {
+        return Ok(None);
+    }
+    let probe = match probe_position(&tables, &position) {
+        Ok(probe) => probe,
+        Err(e) => {
+            // A table missing from the set: let the engine search instead.
+            log::debug!("{}", e);
+            return Ok(None);
+        }
+    };
+
+    let multipv = options
+        .extra_options
+        .iter()
+        .find(|option| option.name == "MultiPV")
+        .and_then(|option| option.value.parse().ok())
+        .unwrap_or(1usize)
+        .max(1);
+    let lines = probe
+        .moves
+        .iter()
+        .take(multipv)
+        .enumerate()
+        .map(|(i, tb_move)| {
+            let m = UciMove::from_ascii(tb_move.uci.as_bytes())?.to_move(&position)?;
+            let (uci_moves, san_moves) = principal_variation(&tables, &position, &m);
+            Ok(BestMoves {
+                score: white_score(tb_move, position.turn()),
+                display_moves: san_moves.iter().map(|san| display_san(san)).collect(),
+                uci_moves,
+                san_moves,
+                multipv: i as u16 + 1,
+                ..Default::default()
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Some(lines))
+}
+
+/// Use the Syzygy tablebases in `paths`, replacing the folders used before.
+#[tauri::command]
+#[specta::specta]
+pub fn set_tablebase_paths(
+    paths: Vec<PathBuf>,
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<TablebaseInfo, Error> {
+    let (tables, count) = open_tables(&paths)?;
+    let info = TablebaseInfo {
+        tables: count,
+        max_pieces: tables.max_pieces() as u32,
+    };
+    TablebaseConfig { paths }.save(&app)?;
+    *state.tablebase.0.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(tables));
+    Ok(info)
+}
+
+/// Exact result and ranked moves of `fen`, or `None` when no tablebase covers it.
+#[tauri::command]
+#[specta::specta]
+pub fn probe_tablebase(
+    fen: String,
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<TablebaseProbe>, Error> {
+    let Some(tables) = tables(&app, &state)? else {
+        return Ok(None);
+    };
+    let fen: Fen = fen.parse()?;
+    let position: Chess = fen.into_position(CastlingMode::Chess960)?;
+    if !in_tables(&tables, &position) {
+        return Ok(None);
+    }
+    probe_position(&tables, &position).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tb_move(uci: &str, wdl: TablebaseWdl, dtz: i32, checkmate: bool) -> TablebaseMove {
+        TablebaseMove {
+            uci: uci.to_string(),
+            san: uci.to_string(),
+            wdl,
+            dtz,
+            checkmate,
+        }
+    }
+
+    #[test]
+    fn test_wdl_from_dtz() {
+        assert_eq!(TablebaseWdl::from_dtz(0, 0), TablebaseWdl::Draw);
+        assert_eq!(TablebaseWdl::from_dtz(15, 80), TablebaseWdl::Win);
+        assert_eq!(TablebaseWdl::from_dtz(15, 90), TablebaseWdl::CursedWin);
+        assert_eq!(TablebaseWdl::from_dtz(-101, 0), TablebaseWdl::BlessedLoss);
+        assert_eq!(TablebaseWdl::from_dtz(-4, 0), TablebaseWdl::Loss);
+    }
+
+    #[test]
+    fn test_sort_moves() {
+        let mut moves = vec![
+            tb_move("a", TablebaseWdl::Loss, -3, false),
+            tb_move("b", TablebaseWdl::Win, 9, false),
+            tb_move("c", TablebaseWdl::Loss, -20, false),
+            tb_move("d", TablebaseWdl::Draw, 0, false),
+            tb_move("e", TablebaseWdl::Win, 1, true),
+            tb_move("f", TablebaseWdl::Win, 1, false),
+            tb_move("g", TablebaseWdl::CursedWin, 40, false),
+        ];
+        sort_moves(&mut moves);
+        let order: Vec<&str> = moves.iter().map(|m| m.uci.as_str()).collect();
+        assert_eq!(order, vec!["e", "f", "b", "g", "d", "c", "a"]);
+    }
+
+    #[test]
+    fn test_white_score() {
+        let win = tb_move("a", TablebaseWdl::Win, 12, false);
+        assert!(matches!(
+            white_score(&win, Color::White).value,
+            ScoreValue::Cp(19_988)
+        ));
+        assert!(matches!(
+            white_score(&win, Color::Black).value,
+            ScoreValue::Cp(-19_988)
+        ));
+        let mate = tb_move("a", TablebaseWdl::Win, 1, true);
+        assert!(matches!(
+            white_score(&mate, Color::Black).value,
+            ScoreValue::Mate(-1)
+        ));
+        let cursed = tb_move("a", TablebaseWdl::CursedWin, 120, false);
+        assert!(matches!(
+            white_score(&cursed, Color::White).value,
+            ScoreValue::Cp(0)
+        ));
+    }
+}
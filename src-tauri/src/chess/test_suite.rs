@@ -0,0 +1,544 @@
+//! EPD test suites such as the Strategic Test Suite (STS).
+//!
+//! A suite is an EPD file with one position per line. Answers are scored from the operations on
+//! each line, in order of preference:
+//! - `c8`/`c9`: points and UCI moves, as used by STS (e.g. 10 points for the best move and partial
+//!   credit for reasonable alternatives);
+//! - `c0`: `SAN=points` pairs, as in older STS releases;
+//! - `bm`/`am`: one point for a best move, or for any move that is not an avoid move.
+//!
+//! Positions are grouped into themes by their `id` (`STS(v1.0) Undermine.001` belongs to
+//! `Undermine`). A suite can be run against an engine or answered by hand, and every run is stored
+//! in a history file so progress can be tracked over time.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    fen::Fen, san::San, uci::UciMove, CastlingMode, Chess, EnPassantMode, Move, Position,
+};
+use specta::Type;
+use tauri::{path::BaseDirectory, Manager};
+use tauri_specta::Event;
+use vampirc_uci::parse_one;
+
+use crate::{
+    error::{Error, Result},
+    progress::{TaskKind, TaskProgress},
+    AppState,
+};
+
+use super::{
+    process::EngineProcess,
+    types::{EngineOption, EngineOptions, GoMode, ReportProgress},
+};
+
+const HISTORY_FILE: &str = "test-suites.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MovePoints {
+    /// Move in UCI notation.
+    pub uci: String,
+    pub points: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TestPosition {
+    pub id: String,
+    pub theme: String,
+    pub fen: String,
+    /// Moves that score, in UCI notation, best first.
+    pub scored_moves: Vec<MovePoints>,
+    /// Moves that score nothing (`am`), in UCI notation.
+    pub avoid_moves: Vec<String>,
+    pub max_points: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TestSuite {
+    pub name: String,
+    pub positions: Vec<TestPosition>,
+    pub themes: Vec<String>,
+    /// Lines that could not be parsed.
+    pub skipped: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionOutcome {
+    pub id: String,
+    pub theme: String,
+    /// Answer in UCI notation; `None` when no (legal) answer was given.
+    pub answer: Option<String>,
+    pub points: u32,
+    pub max_points: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ThemeScore {
+    pub theme: String,
+    pub positions: u32,
+    /// Positions answered with a full-point move.
+    pub solved: u32,
+    pub points: u32,
+    pub max_points: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TestSuiteResult {
+    pub id: String,
+    pub suite: String,
+    pub file: PathBuf,
+    /// Engine name, or the name the user answered under.
+    pub solver: String,
+    pub engine: Option<String>,
+    pub timestamp: String,
+    pub points: u32,
+    pub max_points: u32,
+    pub solved: u32,
+    pub themes: Vec<ThemeScore>,
+    pub positions: Vec<PositionOutcome>,
+}
+
+/// Split an EPD operation list on `;`, ignoring semicolons inside quoted strings.
+fn split_operations(ops: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in ops.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                result.push(ops[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(ops[start..].trim());
+    result.into_iter().filter(|op| !op.is_empty()).collect()
+}
+
+/// Opcode and operands of one operation; a quoted string is a single operand.
+fn parse_operation(op: &str) -> (&str, Vec<String>) {
+    let (opcode, rest) = op.split_once(char::is_whitespace).unwrap_or((op, ""));
+    let rest = rest.trim();
+    let operands = if let Some(quoted) = rest.strip_prefix('"') {
+        vec![quoted.trim_end_matches('"').to_string()]
+    } else {
+        rest.split_whitespace().map(str::to_string).collect()
+    };
+    (opcode, operands)
+}
+
+fn to_uci(m: &Move) -> String {
+    m.to_uci(CastlingMode::Standard).to_string()
+}
+
+/// Parse a move in SAN or UCI notation, returning it in UCI notation.
+fn parse_move(position: &Chess, text: &str) -> Option<String> {
+    let text = text.trim().trim_end_matches(['+', '#', '!', '?']);
+    if let Some(m) = UciMove::from_ascii(text.as_bytes())
+        .ok()
+        .and_then(|uci| uci.to_move(position).ok())
+    {
+        return Some(to_uci(&m));
+    }
+    let san: San = text.parse().ok()?;
+    san.to_move(position).ok().map(|m| to_uci(&m))
+}
+
+/// Theme of a position from its id, e.g. `STS(v1.0) Undermine.001` -> `Undermine`.
+fn theme_from_id(id: &str) -> String {
+    let name = id.rsplit_once(')').map(|(_, name)| name).unwrap_or(id).trim();
+    let name = match name.rsplit_once('.') {
+        Some((theme, number)) if number.chars().all(|c| c.is_ascii_digit()) => theme,
+        _ => name,
+    };
+    match name.trim() {
+        "" => "Other".to_string(),
+        name => name.to_string(),
+    }
+}
+
+fn parse_epd_line(line: &str, index: usize) -> Option<TestPosition> {
+    let placement: Vec<&str> = line.split_whitespace().take(4).collect();
+    if placement.len() < 4 {
+        return None;
+    }
+    let ops_start = placement.iter().fold(0, |pos, field| {
+        line[pos..].find(field).map(|i| pos + i + field.len()).unwrap_or(pos)
+    });
+    let operations: Vec<(&str, Vec<String>)> = split_operations(&line[ops_start..])
+        .into_iter()
+        .map(parse_operation)
+        .collect();
+    let operand = |code: &str| operations.iter().find(|(c, _)| *c == code).map(|(_, o)| o);
+
+    let halfmoves = operand("hmvc").and_then(|o| o.first()).map_or("0", |s| s.as_str());
+    let fullmoves = operand("fmvn").and_then(|o| o.first()).map_or("1", |s| s.as_str());
+    let fen: Fen = format!("{} {} {}", placement.join(" "), halfmoves, fullmoves)
+        .parse()
+        .ok()?;
+    let position: Chess = fen.into_position(CastlingMode::Chess960).ok()?;
+
+    let moves = |code: &str| -> Vec<String> {
+        operand(code)
+            .map(|o| o.iter().filter_map(|m| parse_move(&position, m)).collect())
+            .unwrap_or_default()
+    };
+
+    let mut scored_moves = Vec::new();
+    if let (Some(points), Some(ucis)) = (
+        operand("c8").and_then(|o| o.first()),
+        operand("c9").and_then(|o| o.first()),
+    ) {
+        for (points, uci) in points.split_whitespace().zip(ucis.split_whitespace()) {
+            if let (Ok(points), Some(uci)) = (points.parse(), parse_move(&position, uci)) {
+                scored_moves.push(MovePoints { uci, points });
+            }
+        }
+    }
+    if scored_moves.is_empty() {
+        if let Some(pairs) = operand("c0").and_then(|o| o.first()) {
+            for pair in pairs.split(',') {
+                if let Some((m, points)) = pair.split_once('=') {
+                    if let (Some(uci), Ok(points)) = (parse_move(&position, m), points.trim().parse()) {
+                        scored_moves.push(MovePoints { uci, points });
+                    }
+                }
+            }
+        }
+    }
+    if scored_moves.is_empty() {
+        scored_moves = moves("bm")
+            .into_iter()
+            .map(|uci| MovePoints { uci, points: 1 })
+            .collect();
+    }
+    let avoid_moves = moves("am");
+    if scored_moves.is_empty() && avoid_moves.is_empty() {
+        return None;
+    }
+    scored_moves.sort_by(|a, b| b.points.cmp(&a.points));
+
+    let id = operand("id").and_then(|o| o.first().cloned());
+    Some(TestPosition {
+        theme: id.as_deref().map_or_else(|| "Other".to_string(), theme_from_id),
+        id: id.unwrap_or_else(|| (index + 1).to_string()),
+        fen: Fen::from_position(position, EnPassantMode::Legal).to_string(),
+        max_points: scored_moves.first().map_or(1, |m| m.points),
+        scored_moves,
+        avoid_moves,
+    })
+}
+
+fn parse_suite(name: String, content: &str) -> TestSuite {
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_epd_line(line, i) {
+            Some(position) => positions.push(position),
+            None => skipped += 1,
+        }
+    }
+    let mut themes: Vec<String> = Vec::new();
+    for position in &positions {
+        if !themes.contains(&position.theme) {
+            themes.push(position.theme.clone());
+        }
+    }
+    TestSuite {
+        name,
+        positions,
+        themes,
+        skipped,
+    }
+}
+
+fn read_suite(file: &Path) -> Result<TestSuite> {
+    let name = file
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let suite = parse_suite(name, &std::fs::read_to_string(file)?);
+    if suite.positions.is_empty() {
+        return Err(Error::UnsupportedFileFormat(format!(
+            "No EPD test positions found in {}",
+            file.display()
+        )));
+    }
+    if suite.skipped > 0 {
+        log::warn!("Skipped {} invalid lines in {}", suite.skipped, file.display());
+    }
+    Ok(suite)
+}
+
+impl TestPosition {
+    fn score(&self, answer: Option<&str>) -> PositionOutcome {
+        let answer = answer.and_then(|a| {
+            let fen: Fen = self.fen.parse().ok()?;
+            let position: Chess = fen.into_position(CastlingMode::Chess960).ok()?;
+            parse_move(&position, a)
+        });
+        let points = match &answer {
+            None => 0,
+            Some(uci) if self.avoid_moves.contains(uci) => 0,
+            Some(uci) => match self.scored_moves.iter().find(|m| &m.uci == uci) {
+                Some(m) => m.points,
+                // Suites with only avoid moves accept anything else.
+                None if self.scored_moves.is_empty() => self.max_points,
+                None => 0,
+            },
+        };
+        PositionOutcome {
+            id: self.id.clone(),
+            theme: self.theme.clone(),
+            answer,
+            points,
+            max_points: self.max_points,
+        }
+    }
+}
+
+fn summarize(
+    suite: &TestSuite,
+    file: PathBuf,
+    solver: String,
+    engine: Option<String>,
+    positions: Vec<PositionOutcome>,
+) -> TestSuiteResult {
+    let mut themes: BTreeMap<&str, ThemeScore> = BTreeMap::new();
+    for outcome in &positions {
+        let theme = themes.entry(outcome.theme.as_str()).or_insert_with(|| ThemeScore {
+            theme: outcome.theme.clone(),
+            ..Default::default()
+        });
+        theme.positions += 1;
+        theme.points += outcome.points;
+        theme.max_points += outcome.max_points;
+        if outcome.points == outcome.max_points {
+            theme.solved += 1;
+        }
+    }
+    let themes: Vec<ThemeScore> = suite
+        .themes
+        .iter()
+        .filter_map(|t| themes.remove(t.as_str()))
+        .collect();
+
+    TestSuiteResult {
+        id: uuid::Uuid::new_v4().to_string(),
+        suite: suite.name.clone(),
+        file,
+        solver,
+        engine,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        points: themes.iter().map(|t| t.points).sum(),
+        max_points: themes.iter().map(|t| t.max_points).sum(),
+        solved: themes.iter().map(|t| t.solved).sum(),
+        themes,
+        positions,
+    }
+}
+
+fn history_path(app: &tauri::AppHandle) -> Result<PathBuf> {
+    Ok(app.path().resolve(HISTORY_FILE, BaseDirectory::AppData)?)
+}
+
+fn load_history(app: &tauri::AppHandle) -> Result<Vec<TestSuiteResult>> {
+    let path = history_path(app)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(&std::fs::read(&path)?)
+        .map_err(|e| Error::PackageManager(format!("Invalid test suite history: {}", e)))
+}
+
+fn save_result(app: &tauri::AppHandle, result: &TestSuiteResult) -> Result<()> {
+    let mut history = load_history(app)?;
+    history.push(result.clone());
+    let path = history_path(app)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_vec(&history)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize history: {}", e)))?;
+    std::fs::write(&path, json)?;
+    Ok(())
+}
+
+/// Parse an EPD test suite.
+#[tauri::command]
+#[specta::specta]
+pub fn load_test_suite(file: PathBuf) -> Result<TestSuite> {
+    read_suite(&file)
+}
+
+/// Name results of `engine` are stored under: its file name without the extension.
+fn solver_name(engine: &str) -> String {
+    Path::new(engine)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| engine.to_string())
+}
+
+/// Run every position of a suite through an engine and store the result.
+#[tauri::command]
+#[specta::specta]
+pub async fn run_test_suite(
+    id: String,
+    engine: String,
+    file: PathBuf,
+    go_mode: GoMode,
+    uci_options: Vec<EngineOption>,
+    app: tauri::AppHandle,
+) -> Result<TestSuiteResult> {
+    if matches!(go_mode, GoMode::Infinite) {
+        return Err(Error::PackageManager(
+            "Test suites need a depth, time or node limit".to_string(),
+        ));
+    }
+    let suite = read_suite(&file)?;
+    let job = app.state::<AppState>().jobs.start(
+        &app,
+        TaskKind::Analysis,
+        id.clone(),
+        format!("{} on {}", solver_name(&engine), suite.name),
+    );
+    let _awake = crate::app::platform::keep_awake::keep_awake("Running a test suite");
+    let (mut proc, mut reader) = EngineProcess::new(PathBuf::from(&engine)).await?;
+
+    let mut outcomes = Vec::with_capacity(suite.positions.len());
+    for (i, position) in suite.positions.iter().enumerate() {
+        if job.is_cancelled() {
+            proc.kill().await?;
+            return Err(Error::JobCancelled);
+        }
+        let progress = i as f64 / suite.positions.len() as f64 * 100.0;
+        ReportProgress { progress, id: id.clone(), finished: false }.emit(&app)?;
+        TaskProgress::new(TaskKind::Analysis, id.clone(), progress).send(&app);
+
+        proc.set_options(EngineOptions {
+            fen: position.fen.clone(),
+            moves: Vec::new(),
+            extra_options: uci_options.clone(),
+        })
+        .await?;
+        proc.go(&go_mode).await?;
+
+        let mut answer = None;
+        while let Some(line) = reader.next_line().await? {
+            if let vampirc_uci::UciMessage::BestMove { best_move, .. } = parse_one(&line) {
+                answer = Some(best_move.to_string());
+                break;
+            }
+        }
+        outcomes.push(position.score(answer.as_deref()));
+    }
+    proc.kill().await?;
+
+    let result = summarize(&suite, file, solver_name(&engine), Some(engine), outcomes);
+    log::info!(
+        "{} scored {}/{} on {}",
+        result.solver,
+        result.points,
+        result.max_points,
+        result.suite
+    );
+    save_result(&app, &result)?;
+
+    ReportProgress { progress: 100.0, id: id.clone(), finished: true }.emit(&app)?;
+    TaskProgress::done(TaskKind::Analysis, id)
+        .message(format!("{}/{}", result.points, result.max_points))
+        .send(&app);
+    Ok(result)
+}
+
+/// Score answers given by hand (SAN or UCI, one per position; `None` for skipped positions) and
+/// store the result.
+#[tauri::command]
+#[specta::specta]
+pub fn submit_test_suite_answers(
+    file: PathBuf,
+    solver: String,
+    answers: Vec<Option<String>>,
+    app: tauri::AppHandle,
+) -> Result<TestSuiteResult> {
+    let suite = read_suite(&file)?;
+    let outcomes = suite
+        .positions
+        .iter()
+        .enumerate()
+        .map(|(i, position)| position.score(answers.get(i).and_then(|a| a.as_deref())))
+        .collect();
+    let result = summarize(&suite, file, solver, None, outcomes);
+    save_result(&app, &result)?;
+    Ok(result)
+}
+
+/// Stored test suite results, oldest first.
+#[tauri::command]
+#[specta::specta]
+pub fn get_test_suite_history(app: tauri::AppHandle) -> Result<Vec<TestSuiteResult>> {
+    load_history(&app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STS_LINE: &str = r#"1kr5/3n4/q3p2p/p2n2p1/PppB1P2/5BP1/1P2Q2P/3R2K1 w - - bm f5; id "STS(v1.0) Undermine.001"; c0 "f5=10, Be5+=2, Bf2=3, Bg4=2"; c7 "f5 Bf2 Be5+ Bg4"; c8 "10 3 2 2"; c9 "f4f5 d4f2 d4e5 f3g4";"#;
+
+    #[test]
+    fn test_parse_sts_line() {
+        let position = parse_epd_line(STS_LINE, 0).unwrap();
+        assert_eq!(position.theme, "Undermine");
+        assert_eq!(position.max_points, 10);
+        assert_eq!(position.scored_moves[0].uci, "f4f5");
+        assert_eq!(position.scored_moves.len(), 4);
+
+        assert_eq!(position.score(Some("f5")).points, 10);
+        assert_eq!(position.score(Some("d4f2")).points, 3);
+        assert_eq!(position.score(Some("Kh1")).points, 0);
+        assert_eq!(position.score(Some("Ke8")).answer, None);
+    }
+
+    #[test]
+    fn test_parse_bm_am_suite() {
+        let suite = parse_suite(
+            "wac".to_string(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";\n\
+             invalid line\n\
+             r1b1kb1r/3q1ppp/pBp1pn2/8/Np3P2/5B2/PPP3PP/R2QK2R w KQkq - am O-O; id \"WAC.002\";\n",
+        );
+        assert_eq!(suite.positions.len(), 2);
+        assert_eq!(suite.skipped, 1);
+        assert_eq!(suite.themes, vec!["WAC"]);
+        assert_eq!(suite.positions[0].score(Some("Qg6")).points, 1);
+        assert_eq!(suite.positions[1].score(Some("e1g1")).points, 0);
+        assert_eq!(suite.positions[1].score(Some("Qd2")).points, 1);
+
+        let outcomes = suite
+            .positions
+            .iter()
+            .map(|p| p.score(Some("Qg6")))
+            .collect();
+        let result = summarize(&suite, PathBuf::from("wac.epd"), "me".to_string(), None, outcomes);
+        assert_eq!(result.points, 1);
+        assert_eq!(result.max_points, 2);
+        assert_eq!(result.themes[0].solved, 1);
+    }
+}
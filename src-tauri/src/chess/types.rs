@@ -34,6 +34,40 @@ pub struct EngineOptions {
     pub extra_options: Vec<EngineOption>,
 }
 
+impl EngineOptions {
+    /// Number of lines requested through the `MultiPV` option, 1 when it is not set.
+    pub fn multipv(&self) -> u16 {
+        self.extra_options
+            .iter()
+            .find(|x| x.name == "MultiPV")
+            .map(|x| x.value.parse().unwrap_or(1))
+            .unwrap_or(1)
+    }
+
+    /// Set the UCI option `name`, replacing its previous value.
+    pub fn set_extra_option(&mut self, name: &str, value: String) {
+        match self.extra_options.iter_mut().find(|x| x.name == name) {
+            Some(option) => option.value = value,
+            None => self.extra_options.push(EngineOption {
+                name: name.to_string(),
+                value,
+            }),
+        }
+    }
+}
+
+/// Server-side filtering of the lines emitted while an engine is searching.
+#[derive(Deserialize, Debug, Clone, Default, Type, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EmissionOptions {
+    /// Only emit lines within this many centipawns of the best line.
+    pub max_cp_loss: Option<u32>,
+    /// Emit nothing before the search reaches this depth.
+    pub min_depth: Option<u32>,
+    /// Maximum number of updates emitted per second (10 by default).
+    pub max_per_second: Option<u32>,
+}
+
 /// Engine search mode (depth, time, nodes, etc).
 #[derive(Deserialize, Debug, Clone, Type, PartialEq, Eq)]
 #[serde(tag = "t", content = "c")]
@@ -65,6 +99,9 @@ pub struct BestMoves {
     pub uci_moves: Vec<String>,
     #[serde(rename = "sanMoves")]
     pub san_moves: Vec<String>,
+    /// `san_moves` rendered in the user's notation preference; display only.
+    #[serde(rename = "displayMoves")]
+    pub display_moves: Vec<String>,
     #[derivative(Default(value = "1"))]
     pub multipv: u16,
     pub nps: u32,
@@ -0,0 +1,416 @@
+//! Dual chess clock for recording over-the-board games.
+//!
+//! One clock runs at a time. Moves are entered with `clock_move`, which charges the time the
+//! side to move used, adds the increment and starts the opponent's clock. While the clock runs a
+//! `ClockState` event is emitted every `TICK_INTERVAL`. `stop_clock` returns the recorded game as
+//! PGN with a `[%clk]` comment after every move, ready to be saved to a database.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    fen::Fen, san::SanPlus, uci::UciMove, CastlingMode, Chess, Color, EnPassantMode, Position,
+};
+use specta::Type;
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+
+use crate::{error::Error, AppState};
+
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum ClockMode {
+    /// Fischer increment: `increment_ms` is added after every move.
+    Increment,
+    /// Simple (US) delay: the first `increment_ms` of every move are not charged.
+    Delay,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockConfig {
+    pub initial_ms: u32,
+    pub increment_ms: u32,
+    pub mode: ClockMode,
+}
+
+#[derive(Debug, Clone, Serialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockState {
+    pub white_ms: i32,
+    pub black_ms: i32,
+    pub white_to_move: bool,
+    pub running: bool,
+    /// Set once a side has run out of time; the clock keeps recording moves.
+    pub flagged: Option<String>,
+    pub moves: u32,
+    pub fen: String,
+}
+
+#[derive(Debug, Clone)]
+struct ClockMove {
+    san: String,
+    /// Time left after the move, increment included.
+    clock_ms: i64,
+}
+
+#[derive(Debug)]
+pub struct ChessClock {
+    config: ClockConfig,
+    start: Chess,
+    position: Chess,
+    remaining: [i64; 2],
+    /// Time used in the current turn before the clock was last resumed.
+    turn_elapsed: Duration,
+    /// When the clock was last started or resumed, `None` while paused.
+    running_since: Option<Instant>,
+    flagged: Option<Color>,
+    moves: Vec<ClockMove>,
+    started_at: chrono::DateTime<chrono::Local>,
+}
+
+fn side(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+/// `[%clk]` time, `h:mm:ss`. Negative times are shown as zero.
+fn format_clock(ms: i64) -> String {
+    let secs = ms.max(0) / 1000;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+impl ChessClock {
+    fn new(config: ClockConfig, start: Chess, now: Instant) -> Self {
+        let initial = config.initial_ms as i64;
+        Self {
+            config,
+            position: start.clone(),
+            start,
+            remaining: [initial, initial],
+            turn_elapsed: Duration::ZERO,
+            running_since: Some(now),
+            flagged: None,
+            moves: Vec::new(),
+            started_at: chrono::Local::now(),
+        }
+    }
+
+    fn turn_elapsed_at(&self, now: Instant) -> Duration {
+        self.turn_elapsed
+            + self
+                .running_since
+                .map(|since| now.saturating_duration_since(since))
+                .unwrap_or_default()
+    }
+
+    /// Time charged for `elapsed` of thinking.
+    fn charge(&self, elapsed: Duration) -> i64 {
+        let elapsed = elapsed.as_millis() as i64;
+        match self.config.mode {
+            ClockMode::Increment => elapsed,
+            ClockMode::Delay => (elapsed - self.config.increment_ms as i64).max(0),
+        }
+    }
+
+    fn remaining_at(&self, now: Instant) -> [i64; 2] {
+        let mut remaining = self.remaining;
+        remaining[side(self.position.turn())] -= self.charge(self.turn_elapsed_at(now));
+        remaining
+    }
+
+    /// Record a flag fall. Returns whether a side ran out of time just now.
+    fn check_flag(&mut self, now: Instant) -> bool {
+        if self.flagged.is_some() {
+            return false;
+        }
+        let turn = self.position.turn();
+        if self.remaining_at(now)[side(turn)] <= 0 {
+            self.flagged = Some(turn);
+            return true;
+        }
+        false
+    }
+
+    fn pause(&mut self, now: Instant) {
+        self.turn_elapsed = self.turn_elapsed_at(now);
+        self.running_since = None;
+    }
+
+    fn resume(&mut self, now: Instant) {
+        if self.running_since.is_none() {
+            self.running_since = Some(now);
+        }
+    }
+
+    /// Play a move, charge its time and start the opponent's clock.
+    fn play(&mut self, uci: &str, now: Instant) -> Result<(), Error> {
+        let uci: UciMove = uci.parse()?;
+        let m = uci.to_move(&self.position)?;
+        self.check_flag(now);
+        let turn = side(self.position.turn());
+        let used = self.charge(self.turn_elapsed_at(now));
+        self.remaining[turn] -= used;
+        if self.config.mode == ClockMode::Increment {
+            self.remaining[turn] += self.config.increment_ms as i64;
+        }
+        let san = SanPlus::from_move_and_play_unchecked(&mut self.position, &m);
+        self.moves.push(ClockMove {
+            san: san.to_string(),
+            clock_ms: self.remaining[turn],
+        });
+        self.turn_elapsed = Duration::ZERO;
+        if self.running_since.is_some() {
+            self.running_since = Some(now);
+        }
+        Ok(())
+    }
+
+    fn state(&self, now: Instant) -> ClockState {
+        let remaining = self.remaining_at(now);
+        ClockState {
+            white_ms: remaining[0].clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+            black_ms: remaining[1].clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+            white_to_move: self.position.turn() == Color::White,
+            running: self.running_since.is_some(),
+            flagged: self.flagged.map(|color| match color {
+                Color::White => "white".to_string(),
+                Color::Black => "black".to_string(),
+            }),
+            moves: self.moves.len() as u32,
+            fen: Fen::from_position(self.position.clone(), EnPassantMode::Legal).to_string(),
+        }
+    }
+
+    fn time_control(&self) -> String {
+        let initial = self.config.initial_ms / 1000;
+        match self.config.mode {
+            ClockMode::Increment => format!("{}+{}", initial, self.config.increment_ms / 1000),
+            ClockMode::Delay => format!("{}d{}", initial, self.config.increment_ms / 1000),
+        }
+    }
+
+    /// The recorded game as PGN, with the clock time after each move.
+    fn to_pgn(&self) -> String {
+        let result = match self.flagged {
+            Some(Color::White) => "0-1",
+            Some(Color::Black) => "1-0",
+            None => "*",
+        };
+        let mut pgn = String::new();
+        pgn.push_str("[Event \"Casual game\"]\n");
+        pgn.push_str(&format!(
+            "[Date \"{}\"]\n",
+            self.started_at.format("%Y.%m.%d")
+        ));
+        pgn.push_str(&format!("[Result \"{}\"]\n", result));
+        pgn.push_str(&format!("[TimeControl \"{}\"]\n", self.time_control()));
+        if self.start.clone().into_setup(EnPassantMode::Legal)
+            != Chess::default().into_setup(EnPassantMode::Legal)
+        {
+            pgn.push_str("[SetUp \"1\"]\n");
+            pgn.push_str(&format!(
+                "[FEN \"{}\"]\n",
+                Fen::from_position(self.start.clone(), EnPassantMode::Legal)
+            ));
+        }
+        pgn.push('\n');
+
+        let mut move_number = self.start.fullmoves().get();
+        let mut turn = self.start.turn();
+        for m in &self.moves {
+            match turn {
+                Color::White => pgn.push_str(&format!("{}. ", move_number)),
+                Color::Black => pgn.push_str(&format!("{}... ", move_number)),
+            }
+            pgn.push_str(&format!("{} {{[%clk {}]}} ", m.san, format_clock(m.clock_ms)));
+            if turn == Color::Black {
+                move_number += 1;
+            }
+            turn = !turn;
+        }
+        pgn.push_str(result);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+fn no_clock() -> Error {
+    Error::PackageManager("No clock is running".to_string())
+}
+
+fn with_clock<T>(
+    state: &AppState,
+    f: impl FnOnce(&mut ChessClock, Instant) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let mut clock = state
+        .clock
+        .lock()
+        .map_err(|e| Error::MutexLockFailed(format!("Failed to lock clock: {}", e)))?;
+    let clock = clock.as_mut().ok_or_else(no_clock)?;
+    f(clock, Instant::now())
+}
+
+/// Emit the clock state every `TICK_INTERVAL` until the clock is stopped or replaced.
+fn spawn_ticker(app: AppHandle, generation: usize) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let state = app.state::<AppState>();
+            if state.clock_generation.load(std::sync::atomic::Ordering::Relaxed) != generation {
+                break;
+            }
+            let tick = with_clock(&state, |clock, now| {
+                let flagged = clock.check_flag(now);
+                Ok((clock.running_since.is_some() || flagged).then(|| clock.state(now)))
+            });
+            match tick {
+                Ok(Some(tick)) => {
+                    let _ = tick.emit(&app);
+                }
+                Ok(None) => {}
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+/// Start a new clock, replacing the running one. The side to move in `fen` (or White) starts.
+#[tauri::command]
+#[specta::specta]
+pub fn start_clock(
+    config: ClockConfig,
+    fen: Option<String>,
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+) -> Result<ClockState, Error> {
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            fen.into_position(CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let now = Instant::now();
+    let clock = ChessClock::new(config, start, now);
+    let clock_state = clock.state(now);
+    *state
+        .clock
+        .lock()
+        .map_err(|e| Error::MutexLockFailed(format!("Failed to lock clock: {}", e)))? =
+        Some(clock);
+    let generation = state
+        .clock_generation
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        + 1;
+    spawn_ticker(app, generation);
+    Ok(clock_state)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn pause_clock(state: tauri::State<'_, AppState>) -> Result<ClockState, Error> {
+    with_clock(&state, |clock, now| {
+        clock.pause(now);
+        Ok(clock.state(now))
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn resume_clock(state: tauri::State<'_, AppState>) -> Result<ClockState, Error> {
+    with_clock(&state, |clock, now| {
+        clock.resume(now);
+        Ok(clock.state(now))
+    })
+}
+
+/// Record a move (UCI) played on the board and switch the clocks.
+#[tauri::command]
+#[specta::specta]
+pub fn clock_move(uci: String, state: tauri::State<'_, AppState>) -> Result<ClockState, Error> {
+    with_clock(&state, |clock, now| {
+        clock.play(&uci, now)?;
+        Ok(clock.state(now))
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_clock_state(state: tauri::State<'_, AppState>) -> Result<ClockState, Error> {
+    with_clock(&state, |clock, now| Ok(clock.state(now)))
+}
+
+/// Stop the clock and return the recorded game as PGN with `[%clk]` comments.
+#[tauri::command]
+#[specta::specta]
+pub fn stop_clock(state: tauri::State<'_, AppState>) -> Result<String, Error> {
+    let clock = state
+        .clock
+        .lock()
+        .map_err(|e| Error::MutexLockFailed(format!("Failed to lock clock: {}", e)))?
+        .take()
+        .ok_or_else(no_clock)?;
+    state
+        .clock_generation
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    Ok(clock.to_pgn())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: ClockMode) -> ClockConfig {
+        ClockConfig {
+            initial_ms: 60_000,
+            increment_ms: 2_000,
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_increment() {
+        let now = Instant::now();
+        let mut clock = ChessClock::new(config(ClockMode::Increment), Chess::default(), now);
+        clock.play("e2e4", now + Duration::from_secs(5)).unwrap();
+        assert_eq!(clock.remaining, [57_000, 60_000]);
+        let state = clock.state(now + Duration::from_secs(8));
+        assert_eq!((state.white_ms, state.black_ms), (57_000, 57_000));
+        assert!(!state.white_to_move);
+    }
+
+    #[test]
+    fn test_delay_and_pause() {
+        let now = Instant::now();
+        let mut clock = ChessClock::new(config(ClockMode::Delay), Chess::default(), now);
+        clock.play("e2e4", now + Duration::from_secs(1)).unwrap();
+        assert_eq!(clock.remaining[0], 60_000);
+
+        clock.pause(now + Duration::from_secs(2));
+        clock.resume(now + Duration::from_secs(100));
+        clock.play("e7e5", now + Duration::from_secs(104)).unwrap();
+        // 1 second before the pause and 4 after it, 2 of which are delay.
+        assert_eq!(clock.remaining[1], 57_000);
+    }
+
+    #[test]
+    fn test_flag_and_pgn() {
+        let now = Instant::now();
+        let mut clock = ChessClock::new(config(ClockMode::Increment), Chess::default(), now);
+        clock.play("e2e4", now + Duration::from_secs(3)).unwrap();
+        assert!(clock.check_flag(now + Duration::from_secs(70)));
+        clock.play("e7e5", now + Duration::from_secs(70)).unwrap();
+        assert_eq!(clock.flagged, Some(Color::Black));
+
+        let pgn = clock.to_pgn();
+        assert!(pgn.contains("[TimeControl \"60+2\"]"));
+        assert!(pgn.contains("[Result \"1-0\"]"));
+        assert!(pgn.ends_with("1. e4 {[%clk 0:00:59]} 1... e5 {[%clk 0:00:00]} 1-0\n"), "{}", pgn);
+    }
+}
@@ -0,0 +1,261 @@
+//! Local crash reports.
+//!
+//! A panic hook writes a report (panic message, location, backtrace, app version and platform)
+//! to `crash-reports/` in the app data directory before the process goes down. Reports are
+//! redacted when captured: home directories, user names in paths, e-mail addresses and IP
+//! addresses are replaced, so the files can be shown and submitted as they are. Nothing leaves
+//! the machine unless the user submits a report and has consented to crash reports in the
+//! telemetry settings.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+
+use crate::{
+    error::{Error, Result},
+    http,
+    telemetry::{get_platform_info, supabase_endpoint, telemetry_allows, TelemetryCategory},
+};
+
+const CRASH_DIR: &str = "crash-reports";
+/// Oldest reports are pruned beyond this many.
+const MAX_REPORTS: usize = 20;
+
+static EMAIL_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap());
+static IPV4_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(?:\d{1,3}\.){3}\d{1,3}\b").unwrap());
+/// User directories on Linux, macOS and Windows, for paths not under the current home.
+static USER_DIR_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)(/home/|/Users/|[A-Z]:\\Users\\)[^/\\\s]+").unwrap());
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+    pub id: String,
+    pub timestamp: String,
+    pub app_version: String,
+    pub platform: String,
+    pub thread: Option<String>,
+    pub message: String,
+    pub location: Option<String>,
+    pub backtrace: String,
+    #[serde(default)]
+    pub submitted: bool,
+}
+
+fn home_dir() -> Option<String> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()
+        .filter(|home| home.len() > 1)
+}
+
+fn redact_with(text: &str, home: Option<&str>) -> String {
+    let text = match home {
+        Some(home) => text.replace(home, "~"),
+        None => text.to_string(),
+    };
+    let text = USER_DIR_RE.replace_all(&text, "${1}<user>");
+    let text = EMAIL_RE.replace_all(&text, "<email>");
+    IPV4_RE.replace_all(&text, "<ip>").into_owned()
+}
+
+/// Strip personal data from text that goes into a crash report.
+pub fn redact(text: &str) -> String {
+    redact_with(text, home_dir().as_deref())
+}
+
+fn crash_dir(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app.path().resolve(CRASH_DIR, BaseDirectory::AppData)?)
+}
+
+fn report_path(dir: &Path, id: &str) -> Result<PathBuf> {
+    // Ids come from the frontend, keep them inside the crash directory.
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(Error::IoError(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid crash report id: {}", id),
+        )));
+    }
+    Ok(dir.join(format!("{}.json", id)))
+}
+
+fn write_report(dir: &Path, report: &CrashReport) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = report_path(dir, &report.id)?;
+    let json = serde_json::to_vec_pretty(report)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize crash report: {}", e)))?;
+    fs::write(&path, json)?;
+    Ok(path)
+}
+
+fn read_report(path: &Path) -> Result<CrashReport> {
+    serde_json::from_slice(&fs::read(path)?).map_err(|e| {
+        Error::PackageManager(format!("Invalid crash report {}: {}", path.display(), e))
+    })
+}
+
+fn load_reports(dir: &Path) -> Result<Vec<(PathBuf, CrashReport)>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut reports = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        match read_report(&path) {
+            Ok(report) => reports.push((path, report)),
+            Err(e) => log::warn!("Skipping crash report: {}", e),
+        }
+    }
+    // Ids start with the capture time, so this is newest first.
+    reports.sort_by(|a, b| b.1.id.cmp(&a.1.id));
+    Ok(reports)
+}
+
+fn prune_reports(dir: &Path) -> Result<()> {
+    for (path, _) in load_reports(dir)?.into_iter().skip(MAX_REPORTS) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Install a panic hook that saves a redacted report before the default hook runs. Release
+/// builds abort on panic, so this is the last chance to record anything.
+pub fn install_panic_hook(app: &AppHandle) -> Result<()> {
+    let dir = crash_dir(app)?;
+    let app_version = app.package_info().version.to_string();
+    let platform = get_platform_info();
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Box<dyn Any>".to_string());
+        let now = chrono::Utc::now();
+        let uuid = uuid::Uuid::new_v4().simple().to_string();
+
+        let report = CrashReport {
+            id: format!("{}-{}", now.format("%Y%m%dT%H%M%S"), &uuid[..8]),
+            timestamp: now.to_rfc3339(),
+            app_version: app_version.clone(),
+            platform: platform.clone(),
+            thread: std::thread::current().name().map(|name| name.to_string()),
+            message: redact(&message),
+            location: info
+                .location()
+                .map(|l| redact(&format!("{}:{}:{}", l.file(), l.line(), l.column()))),
+            backtrace: redact(&std::backtrace::Backtrace::force_capture().to_string()),
+            submitted: false,
+        };
+        match write_report(&dir, &report) {
+            Ok(path) => {
+                log::error!("Crash report written to {}", path.display());
+                if let Err(e) = prune_reports(&dir) {
+                    log::warn!("Failed to prune crash reports: {}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to write crash report: {}", e),
+        }
+
+        previous(info);
+    }));
+    Ok(())
+}
+
+/// Saved crash reports, newest first.
+#[tauri::command]
+#[specta::specta]
+pub fn list_crash_reports(app: AppHandle) -> Result<Vec<CrashReport>> {
+    Ok(load_reports(&crash_dir(&app)?)?
+        .into_iter()
+        .map(|(_, report)| report)
+        .collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn delete_crash_report(app: AppHandle, id: String) -> Result<()> {
+    let path = report_path(&crash_dir(&app)?, &id)?;
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Send a saved report. Only allowed when the user consented to crash reports.
+#[tauri::command]
+#[specta::specta]
+pub async fn submit_crash_report(app: AppHandle, id: String) -> Result<()> {
+    if !telemetry_allows(&app, TelemetryCategory::CrashReports) {
+        return Err(Error::PackageManager(
+            "Crash reports are disabled in the telemetry settings".to_string(),
+        ));
+    }
+
+    let path = report_path(&crash_dir(&app)?, &id)?;
+    let mut report = read_report(&path)?;
+    if report.submitted {
+        return Ok(());
+    }
+
+    let (supabase_url, supabase_key) = supabase_endpoint();
+    let body = serde_json::to_vec(&report)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize crash report: {}", e)))?;
+    http::Request::new(Method::POST, format!("{}/rest/v1/crash_reports", supabase_url))
+        .header("apikey", supabase_key.clone())
+        .header("Authorization", format!("Bearer {}", supabase_key))
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal")
+        .body(body)
+        .bytes()
+        .await?;
+
+    report.submitted = true;
+    write_report(path.parent().unwrap_or(Path::new(".")), &report)?;
+    log::info!("Submitted crash report {}", id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(
+            redact_with("failed to open /home/alice/chess/db.db3", Some("/home/alice")),
+            "failed to open ~/chess/db.db3"
+        );
+        assert_eq!(
+            redact_with(r"C:\Users\Bob\AppData\x.pgn and /Users/carol/a", None),
+            r"C:\Users\<user>\AppData\x.pgn and /Users/<user>/a"
+        );
+        assert_eq!(
+            redact_with("login bob@example.com from 192.168.1.20", None),
+            "login <email> from <ip>"
+        );
+    }
+
+    #[test]
+    fn test_report_path_rejects_traversal() {
+        let dir = Path::new("crash-reports");
+        assert!(report_path(dir, "20260101T000000-abcd1234").is_ok());
+        assert!(report_path(dir, "../secrets").is_err());
+        assert!(report_path(dir, "").is_err());
+    }
+}
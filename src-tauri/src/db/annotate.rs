@@ -0,0 +1,219 @@
+//! Move assessment symbols (NAGs) derived from stored evaluations.
+//!
+//! Games analysed by an engine carry `[%eval ...]` comments after each main-line move. The change
+//! in win chance between consecutive evaluations, seen from the side that moved, is turned into
+//! the standard move assessments: `??`, `?` and `?!` for losses, `!` and `!?` for moves that turned
+//! out clearly better than the previous evaluation expected. Assessments already in the game are
+//! kept, so hand-made annotations are never overwritten.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use pgn_reader::Nag;
+use serde::Deserialize;
+use shakmaty::{fen::Fen, CastlingMode, Chess, Color, FromSetup, Position};
+use specta::Type;
+
+use crate::{
+    db::{
+        eval_comment::eval_comment_cp,
+        get_db_or_create,
+        move_data::update_move_data,
+        pgn::{GameTree, GameTreeNode},
+        review::{win_chance, BLUNDER_THRESHOLD, INACCURACY_THRESHOLD, MISTAKE_THRESHOLD},
+        schema::games,
+        ConnectionOptions,
+    },
+    error::Result,
+    notation::NotationLocale,
+    AppState,
+};
+
+/// Win-chance changes (percentage points) that trigger each assessment.
+#[derive(Debug, Clone, Copy, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct NagThresholds {
+    pub dubious: f64,
+    pub mistake: f64,
+    pub blunder: f64,
+    pub interesting: f64,
+    pub good: f64,
+}
+
+impl Default for NagThresholds {
+    fn default() -> Self {
+        Self {
+            dubious: INACCURACY_THRESHOLD,
+            mistake: MISTAKE_THRESHOLD,
+            blunder: BLUNDER_THRESHOLD,
+            interesting: 5.0,
+            good: 10.0,
+        }
+    }
+}
+
+/// NAGs 1-6: `!`, `?`, `!!`, `??`, `!?`, `?!`.
+fn is_move_assessment(nag: Nag) -> bool {
+    (1..=6).contains(&nag.0)
+}
+
+/// Assessment of a move by `mover` from the evaluations before and after it.
+fn assess(mover: Color, before: f64, after: f64, thresholds: &NagThresholds) -> Option<Nag> {
+    let sign = match mover {
+        Color::White => 1.0,
+        Color::Black => -1.0,
+    };
+    let change = win_chance(sign * after) - win_chance(sign * before);
+    if change <= -thresholds.blunder {
+        Some(Nag::BLUNDER)
+    } else if change <= -thresholds.mistake {
+        Some(Nag::MISTAKE)
+    } else if change <= -thresholds.dubious {
+        Some(Nag::DUBIOUS_MOVE)
+    } else if change >= thresholds.good {
+        Some(Nag::GOOD_MOVE)
+    } else if change >= thresholds.interesting {
+        Some(Nag::SPECULATIVE_MOVE)
+    } else {
+        None
+    }
+}
+
+/// Main-line move indices (into `nodes`) with the evaluation stored after each move.
+fn move_evals(nodes: &[GameTreeNode]) -> Vec<(usize, Option<f64>, bool)> {
+    let mut moves: Vec<(usize, Option<f64>, bool)> = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        match node {
+            GameTreeNode::Move(_) => moves.push((i, None, false)),
+            GameTreeNode::Comment(comment) => {
+                if let Some(last) = moves.last_mut() {
+                    if let Some(eval) = eval_comment_cp(comment) {
+                        last.1 = Some(eval);
+                    }
+                }
+            }
+            GameTreeNode::Nag(nag) if is_move_assessment(*nag) => {
+                if let Some(last) = moves.last_mut() {
+                    last.2 = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    moves
+}
+
+/// Insert assessments into the main line. Returns the new tree and the number of NAGs added.
+fn annotate_tree(tree: GameTree, start: &Chess, thresholds: &NagThresholds) -> (GameTree, usize) {
+    let nodes = tree.into_nodes();
+    let evals = move_evals(&nodes);
+
+    let first_mover = start.turn();
+    let mut nags: Vec<Option<Nag>> = vec![None; nodes.len()];
+    let mut previous: Option<f64> = None;
+    for (ply, (index, eval, annotated)) in evals.iter().enumerate() {
+        let mover = if ply % 2 == 0 { first_mover } else { !first_mover };
+        if let (Some(before), Some(after), false) = (previous, *eval, *annotated) {
+            nags[*index] = assess(mover, before, after, thresholds);
+        }
+        previous = *eval;
+    }
+
+    let mut added = 0;
+    let mut annotated = GameTree::new();
+    for (node, nag) in nodes.into_iter().zip(nags) {
+        annotated.push(node);
+        if let Some(nag) = nag {
+            annotated.push(GameTreeNode::Nag(nag));
+            added += 1;
+        }
+    }
+    (annotated, added)
+}
+
+/// Add move assessments derived from the stored `[%eval]` comments to a game, save it and return
+/// its annotated movetext.
+#[tauri::command]
+#[specta::specta]
+pub async fn annotate_movetext(
+    file: PathBuf,
+    game_id: i32,
+    thresholds: Option<NagThresholds>,
+    state: tauri::State<'_, AppState>,
+) -> Result<String> {
+    let _write = state.db_writes.lock(&file, "annotate_movetext").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .filter(games::id.eq(game_id))
+        .select((games::moves, games::fen))
+        .first(db)?;
+
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let tree = GameTree::from_bytes(&moves, Some(start.clone()))?;
+    let (tree, added) = annotate_tree(tree, &start, &thresholds.unwrap_or_default());
+
+    if added > 0 {
+        let mut bytes = Vec::new();
+        tree.encode(&mut bytes, Some(start.clone()));
+        diesel::update(games::table.filter(games::id.eq(game_id)))
+            .set(games::moves.eq(bytes))
+            .execute(db)?;
+        update_move_data(db, game_id, &tree)?;
+        log::info!("Added {} move assessments to game {}", added, game_id);
+    }
+
+    let mut movetext = String::new();
+    tree.pretty_print_localized(&mut movetext, Some(start), NotationLocale::English)?;
+    Ok(movetext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pgn_reader::SanPlus;
+
+    fn tree(items: &[(&str, &str)]) -> GameTree {
+        let mut tree = GameTree::new();
+        for (san, eval) in items {
+            tree.push(GameTreeNode::Move(san.parse::<SanPlus>().unwrap()));
+            if !eval.is_empty() {
+                tree.push(GameTreeNode::Comment(format!("[%eval {}]", eval)));
+            }
+        }
+        tree
+    }
+
+    #[test]
+    fn test_annotate_tree() {
+        let tree = tree(&[
+            ("e4", "0.3"),
+            ("e5", "0.3"),
+            ("Qh5", "0.2"),
+            ("Nc6", "0.1"),
+            ("Bc4", "0.1"),
+            ("Nf6", "#1"),
+        ]);
+        let (tree, added) = annotate_tree(tree, &Chess::default(), &NagThresholds::default());
+        assert_eq!(added, 1);
+        let mut movetext = String::new();
+        tree.pretty_print_localized(&mut movetext, None, NotationLocale::English)
+            .unwrap();
+        assert!(movetext.contains("Nf6 $4"), "{}", movetext);
+    }
+
+    #[test]
+    fn test_keeps_existing_assessment() {
+        let mut tree = tree(&[("e4", "0.3"), ("f5", "")]);
+        tree.push(GameTreeNode::Nag(Nag::SPECULATIVE_MOVE));
+        tree.push(GameTreeNode::Comment("[%eval 2.5]".to_string()));
+        let (tree, added) = annotate_tree(tree, &Chess::default(), &NagThresholds::default());
+        assert_eq!(added, 0);
+        assert_eq!(tree.nodes().len(), 5);
+    }
+}
@@ -0,0 +1,438 @@
+//! Optional sync of game annotations between devices.
+//!
+//! Every edit saved through `update_game` is appended to a local, append-only event log while
+//! sync is enabled. `sync_push` uploads this device's log to a user-provided endpoint and
+//! `sync_pull` downloads the logs of the other devices and replays their edits on a database.
+//!
+//! The endpoint is any HTTP server that accepts `GET` and `PUT` of files below a base URL with basic
+//! or bearer authentication, such as a WebDAV share. S3 buckets are not supported, as their
+//! requests have to be signed. Each device owns `{endpoint}/{device_id}.jsonl`;
+//! `{endpoint}/devices.json` lists the known devices.
+//!
+//! Games are matched across devices by their identifying headers rather than by ID, and edits to
+//! the same game are merged by timestamp: the most recent edit wins.
+
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose, Engine as _};
+use diesel::prelude::*;
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::{
+    db::{
+        core,
+        get_db_or_create,
+        models::UpdateGame,
+        schema::{events, games, players, sites},
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    http,
+    AppState,
+};
+
+const SYNC_DIR: &str = "sync";
+const DEVICES_FILE: &str = "devices.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConfig {
+    pub enabled: bool,
+    /// Base URL of the WebDAV folder, e.g. `https://dav.example.com/chess/`.
+    pub endpoint: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Password for basic authentication, or a bearer token when no username is set. It is stored
+    /// in plaintext in `sync/config.json` in the app data directory, which only the user can read
+    /// on Unix, so an app password or a token limited to the sync folder should be used.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Generated on first use; identifies this device's log on the endpoint.
+    #[serde(default)]
+    pub device_id: String,
+}
+
+/// Headers that identify a game independently of its row ID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GameIdentity {
+    white: String,
+    black: String,
+    event: String,
+    site: String,
+    date: Option<String>,
+    round: Option<String>,
+}
+
+impl GameIdentity {
+    fn key(&self) -> String {
+        [
+            self.white.as_str(),
+            self.black.as_str(),
+            self.event.as_str(),
+            self.site.as_str(),
+            self.date.as_deref().unwrap_or_default(),
+            self.round.as_deref().unwrap_or_default(),
+        ]
+        .join("\u{1f}")
+    }
+
+    fn of_update(update: &UpdateGame) -> Self {
+        Self {
+            white: update.white.clone(),
+            black: update.black.clone(),
+            event: update.event.clone(),
+            site: update.site.clone(),
+            date: update.date.clone(),
+            round: update.round.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnnotationEvent {
+    id: String,
+    device_id: String,
+    /// Milliseconds since the Unix epoch.
+    timestamp: i64,
+    /// File name of the database the edit was made in.
+    database: String,
+    /// The game's headers before the edit.
+    game: GameIdentity,
+    update: UpdateGame,
+}
+
+/// Local bookkeeping: the timestamp of the last edit applied to each game and how many events of
+/// each remote log have been consumed.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncState {
+    applied: HashMap<String, i64>,
+    consumed: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncPullResult {
+    pub applied: u32,
+    /// Edits older than one already applied to the same game.
+    pub outdated: u32,
+    /// Edits to games that do not exist in the database.
+    pub missing: u32,
+}
+
+fn sync_path(app: &tauri::AppHandle, name: &str) -> Result<PathBuf> {
+    Ok(app
+        .path()
+        .resolve(format!("{}/{}", SYNC_DIR, name), BaseDirectory::AppData)?)
+}
+
+fn read_json<T: for<'de> Deserialize<'de> + Default>(path: &Path) -> Result<T> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    serde_json::from_slice(&std::fs::read(path)?)
+        .map_err(|e| Error::PackageManager(format!("Invalid sync file {}: {}", path.display(), e)))
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_vec_pretty(value)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize sync data: {}", e)))?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+fn parse_events(data: &[u8]) -> Vec<AnnotationEvent> {
+    BufReader::new(data)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(&line) {
+            Ok(event) => Some(event),
+            Err(e) => {
+                log::warn!("Skipping invalid sync event: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn database_name(file: &Path) -> String {
+    file.file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn load_config(app: &tauri::AppHandle) -> Result<SyncConfig> {
+    read_json(&sync_path(app, "config.json")?)
+}
+
+/// Write the config, which holds the secret, readable by the user only.
+fn save_config(app: &tauri::AppHandle, config: &SyncConfig) -> Result<()> {
+    let path = sync_path(app, "config.json")?;
+    write_json(&path, config)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+fn remote_request(config: &SyncConfig, method: Method, name: &str) -> http::Request {
+    let url = format!("{}/{}", config.endpoint.trim_end_matches('/'), name);
+    let request = http::Request::new(method, url);
+    match (&config.username, &config.secret) {
+        (Some(user), Some(secret)) => {
+            let credentials = general_purpose::STANDARD.encode(format!("{}:{}", user, secret));
+            request.header("Authorization", format!("Basic {}", credentials))
+        }
+        (None, Some(token)) => request.header("Authorization", format!("Bearer {}", token)),
+        _ => request,
+    }
+}
+
+/// Download a remote file, treating a missing file as empty.
+async fn remote_get(config: &SyncConfig, name: &str) -> Result<Vec<u8>> {
+    let response = remote_request(config, Method::GET, name).send().await?;
+    match response.status() {
+        StatusCode::NOT_FOUND => Ok(Vec::new()),
+        status if status.is_success() => Ok(response.bytes().await?.to_vec()),
+        status => Err(Error::HttpStatus(status.as_u16())),
+    }
+}
+
+async fn remote_put(config: &SyncConfig, name: &str, body: Vec<u8>) -> Result<()> {
+    let response = remote_request(config, Method::PUT, name)
+        .body(body)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status().as_u16()));
+    }
+    Ok(())
+}
+
+fn enabled_config(app: &tauri::AppHandle) -> Result<SyncConfig> {
+    let config = load_config(app)?;
+    if !config.enabled || config.endpoint.is_empty() {
+        return Err(Error::PackageManager("Annotation sync is not configured".to_string()));
+    }
+    Ok(config)
+}
+
+/// Append an edit of the game `before` to the local log. Does nothing while sync is disabled.
+pub(super) fn record_edit(
+    app: &tauri::AppHandle,
+    file: &Path,
+    before: &crate::db::NormalizedGame,
+    update: &UpdateGame,
+) -> Result<()> {
+    let config = load_config(app)?;
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let event = AnnotationEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        device_id: config.device_id,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        database: database_name(file),
+        game: GameIdentity {
+            white: before.white.clone(),
+            black: before.black.clone(),
+            event: before.event.clone(),
+            site: before.site.clone(),
+            date: before.date.clone(),
+            round: before.round.clone(),
+        },
+        update: update.clone(),
+    };
+
+    // Our own edits count as applied, so older remote edits do not overwrite them.
+    let state_path = sync_path(app, "state.json")?;
+    let mut state: SyncState = read_json(&state_path)?;
+    for identity in [event.game.clone(), GameIdentity::of_update(update)] {
+        state.applied.insert(identity.key(), event.timestamp);
+    }
+    write_json(&state_path, &state)?;
+
+    let log_path = sync_path(app, "events.jsonl")?;
+    if let Some(parent) = log_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let line = serde_json::to_string(&event)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize sync event: {}", e)))?;
+    let mut log = OpenOptions::new().create(true).append(true).open(log_path)?;
+    writeln!(log, "{}", line)?;
+    Ok(())
+}
+
+fn find_game(db: &mut SqliteConnection, identity: &GameIdentity) -> Result<Option<i32>> {
+    let (white_players, black_players) = diesel::alias!(players as white, players as black);
+    let mut query = games::table
+        .inner_join(white_players.on(games::white_id.eq(white_players.field(players::id))))
+        .inner_join(black_players.on(games::black_id.eq(black_players.field(players::id))))
+        .inner_join(events::table.on(games::event_id.eq(events::id)))
+        .inner_join(sites::table.on(games::site_id.eq(sites::id)))
+        .filter(white_players.field(players::name).eq(&identity.white))
+        .filter(black_players.field(players::name).eq(&identity.black))
+        .filter(events::name.eq(&identity.event))
+        .filter(sites::name.eq(&identity.site))
+        .select(games::id)
+        .into_boxed();
+    query = match &identity.date {
+        Some(date) => query.filter(games::date.eq(date)),
+        None => query.filter(games::date.is_null()),
+    };
+    query = match &identity.round {
+        Some(round) => query.filter(games::round.eq(round)),
+        None => query.filter(games::round.is_null()),
+    };
+    Ok(query.first(db).optional()?)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_sync_config(app: tauri::AppHandle) -> Result<SyncConfig> {
+    load_config(&app)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_sync_config(mut config: SyncConfig, app: tauri::AppHandle) -> Result<SyncConfig> {
+    let current = load_config(&app)?;
+    config.device_id = if current.device_id.is_empty() {
+        uuid::Uuid::new_v4().to_string()
+    } else {
+        current.device_id
+    };
+    save_config(&app, &config)?;
+    Ok(config)
+}
+
+/// Upload this device's edit log. Returns the number of events in it.
+#[tauri::command]
+#[specta::specta]
+pub async fn sync_push(app: tauri::AppHandle) -> Result<u32> {
+    let config = enabled_config(&app)?;
+
+    let log_path = sync_path(&app, "events.jsonl")?;
+    let log = if log_path.exists() {
+        std::fs::read(&log_path)?
+    } else {
+        Vec::new()
+    };
+    let count = parse_events(&log).len() as u32;
+    remote_put(&config, &format!("{}.jsonl", config.device_id), log).await?;
+
+    let mut devices: Vec<String> =
+        serde_json::from_slice(&remote_get(&config, DEVICES_FILE).await?).unwrap_or_default();
+    if !devices.contains(&config.device_id) {
+        devices.push(config.device_id.clone());
+        let body = serde_json::to_vec(&devices)
+            .map_err(|e| Error::PackageManager(format!("Failed to serialize devices: {}", e)))?;
+        remote_put(&config, DEVICES_FILE, body).await?;
+    }
+
+    log::info!("Pushed {} annotation events", count);
+    Ok(count)
+}
+
+/// Download the other devices' edit logs and apply their new edits to `file`.
+#[tauri::command]
+#[specta::specta]
+pub async fn sync_pull(
+    file: PathBuf,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<SyncPullResult> {
+    let config = enabled_config(&app)?;
+    let devices: Vec<String> =
+        serde_json::from_slice(&remote_get(&config, DEVICES_FILE).await?).unwrap_or_default();
+
+    let state_path = sync_path(&app, "state.json")?;
+    let mut sync_state: SyncState = read_json(&state_path)?;
+
+    let mut incoming: Vec<AnnotationEvent> = Vec::new();
+    for device in devices.iter().filter(|d| **d != config.device_id) {
+        let events = parse_events(&remote_get(&config, &format!("{}.jsonl", device)).await?);
+        let consumed = sync_state.consumed.get(device).copied().unwrap_or(0);
+        incoming.extend(events.iter().skip(consumed).cloned());
+        sync_state.consumed.insert(device.clone(), events.len());
+    }
+    incoming.sort_by_key(|e| e.timestamp);
+
+    let database = database_name(&file);
+    let _write = state.db_writes.lock(&file, "sync_pull").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let mut result = SyncPullResult::default();
+    for event in incoming.into_iter().filter(|e| e.database == database) {
+        let key = event.game.key();
+        if sync_state.applied.get(&key).is_some_and(|t| *t >= event.timestamp) {
+            result.outdated += 1;
+            continue;
+        }
+        // The edit may have been applied already under its new headers.
+        let target = GameIdentity::of_update(&event.update);
+        let game_id = match find_game(db, &event.game)? {
+            Some(id) => Some(id),
+            None => find_game(db, &target)?,
+        };
+        let Some(game_id) = game_id else {
+            result.missing += 1;
+            continue;
+        };
+
+        core::update_game(db, game_id, &event.update)?;
+        sync_state.applied.insert(key, event.timestamp);
+        sync_state.applied.insert(target.key(), event.timestamp);
+        result.applied += 1;
+    }
+
+    write_json(&state_path, &sync_state)?;
+    log::info!(
+        "Pulled annotation events: {} applied, {} outdated, {} missing",
+        result.applied,
+        result.outdated,
+        result.missing
+    );
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_key() {
+        let identity = |white: &str, black: &str| GameIdentity {
+            white: white.to_string(),
+            black: black.to_string(),
+            event: "Wijk".to_string(),
+            site: "NED".to_string(),
+            date: Some("2024.01.20".to_string()),
+            round: None,
+        };
+        assert_eq!(identity("a", "bc").key(), identity("a", "bc").key());
+        assert_ne!(identity("a", "bc").key(), identity("ab", "c").key());
+    }
+
+    #[test]
+    fn test_parse_events_skips_invalid_lines() {
+        assert!(parse_events(b"not json\n\n{\"id\":\"x\"}\n").is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+//! Import of a Chess.com account's games through the public API.
+//!
+//! The API lists a player's monthly archives, each of which can be downloaded as one PGN. The
+//! games go into `{username}_chesscom.db3` in the games database folder, the name the position
+//! search recognizes as an online database. Every month is recorded as the source of its games,
+//! so importing again only downloads the months not seen yet, plus the newest one, which is
+//! replaced because it may have grown since.
+
+use std::path::PathBuf;
+
+use diesel::{connection::SimpleConnection, prelude::*};
+use pgn_reader::BufferedReader;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    db::{
+        core, get_db_or_create, insert_to_db,
+        location::{DatabaseKind, DatabaseRef},
+        pgn::{Importer, TempGame},
+        player_aggregates,
+        schema::game_sources,
+        sources, update_info_counts, ConnectionOptions, INDEXES_SQL,
+    },
+    error::{Error, Result},
+    http::Request,
+    progress::{TaskKind, TaskProgress},
+    AppState,
+};
+
+const CHESSCOM_API: &str = "https://api.chess.com/pub";
+
+#[derive(Deserialize)]
+struct Archives {
+    archives: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ChesscomImportSummary {
+    pub path: PathBuf,
+    /// Monthly archives downloaded by this import.
+    pub archives: i32,
+    pub games: i32,
+    /// Monthly archives that failed to download or parse, with the error.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Chess.com usernames are letters, digits, `_` and `-`, and case-insensitive.
+fn normalize_username(username: &str) -> Result<String> {
+    let username = username.trim().to_lowercase();
+    if username.is_empty()
+        || !username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(Error::PackageManager(format!(
+            "Invalid Chess.com username: {}",
+            username
+        )));
+    }
+    Ok(username)
+}
+
+/// `YYYY/MM` of a monthly archive URL.
+fn archive_month(url: &str) -> &str {
+    let url = url.trim_end_matches('/');
+    let mut parts = url.rsplitn(3, '/');
+    match (parts.next(), parts.next()) {
+        (Some(month), Some(year)) => &url[url.len() - month.len() - year.len() - 1..],
+        _ => url,
+    }
+}
+
+/// Archives to download, oldest first: those never imported, and always the newest one.
+fn archives_to_fetch(mut archives: Vec<String>, imported: &[String]) -> Vec<String> {
+    archives.sort();
+    let newest = archives.last().cloned();
+    archives
+        .into_iter()
+        .filter(|url| !imported.contains(url) || Some(url) == newest.as_ref())
+        .collect()
+}
+
+async fn import_archive(db: &mut SqliteConnection, url: &str) -> Result<i32> {
+    let pgn = Request::get(format!("{}/pgn", url)).bytes().await?;
+    let mut importer = Importer::new(None);
+    let games: Vec<TempGame> = BufferedReader::new_cursor(&pgn[..])
+        .into_iter(&mut importer)
+        .flatten()
+        .flatten()
+        .collect();
+
+    // The newest month is imported again on every run; drop what it held before.
+    sources::delete_source_games(db, url)?;
+    let last_id = sources::last_game_id(db)?;
+    db.transaction::<_, Error, _>(|db| {
+        for game in &games {
+            insert_to_db(db, game)?;
+        }
+        Ok(())
+    })?;
+    sources::record_import(db, last_id, url)?;
+    Ok(games.len() as i32)
+}
+
+/// Downloads every game of `username` on Chess.com into `{username}_chesscom.db3`, creating it
+/// if needed.
+#[tauri::command]
+#[specta::specta]
+pub async fn import_chesscom_games(
+    username: String,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<ChesscomImportSummary> {
+    let username = normalize_username(&username)?;
+    let path = DatabaseRef::Name(format!("{}_chesscom.db3", username))
+        .resolve(&app, DatabaseKind::Games)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let _write = state.db_writes.lock(&path, "import_chesscom_games").await;
+    let job = state.jobs.start(
+        &app,
+        TaskKind::Import,
+        path.to_string_lossy(),
+        format!("Importing the Chess.com games of {}", username),
+    );
+
+    let archives: Archives = serde_json::from_str(
+        &Request::get(format!(
+            "{}/player/{}/games/archives",
+            CHESSCOM_API, username
+        ))
+        .text()
+        .await?,
+    )
+    .map_err(|e| Error::PackageManager(format!("Invalid Chess.com archive list: {}", e)))?;
+
+    let needs_init = !path.exists();
+    let db = &mut get_db_or_create(&state, path.to_str().unwrap(), ConnectionOptions::default())?;
+    if needs_init {
+        core::init_db(
+            db,
+            &format!("{} (Chess.com)", username),
+            &format!("Games of {} on Chess.com", username),
+        )?;
+    }
+
+    let imported: Vec<String> = game_sources::table
+        .select(game_sources::source)
+        .distinct()
+        .load(db)?;
+    let to_fetch = archives_to_fetch(archives.archives, &imported);
+
+    let progress_id = path.to_string_lossy();
+    let mut summary = ChesscomImportSummary {
+        path: path.clone(),
+        archives: 0,
+        games: 0,
+        failed: Vec::new(),
+    };
+    for (index, url) in to_fetch.iter().enumerate() {
+        if job.is_cancelled() {
+            break;
+        }
+        TaskProgress::new(
+            TaskKind::Import,
+            progress_id.clone(),
+            index as f64 * 100.0 / to_fetch.len() as f64,
+        )
+        .message(format!("Importing {}", archive_month(url)))
+        .send(&app);
+
+        match import_archive(db, url).await {
+            Ok(games) => {
+                summary.archives += 1;
+                summary.games += games;
+            }
+            Err(e) => {
+                log::warn!("Failed to import Chess.com archive {}: {}", url, e);
+                summary
+                    .failed
+                    .push((archive_month(url).to_string(), e.to_string()));
+            }
+        }
+    }
+
+    if needs_init {
+        db.batch_execute(INDEXES_SQL)?;
+    }
+    update_info_counts(db)?;
+    player_aggregates::update_after_import(db)?;
+
+    TaskProgress::done(TaskKind::Import, progress_id)
+        .message(format!("{} games imported", summary.games))
+        .send(&app);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARCHIVE: &str = "https://api.chess.com/pub/player/hikaru/games";
+
+    #[test]
+    fn test_normalize_username() {
+        assert_eq!(normalize_username(" Hikaru ").unwrap(), "hikaru");
+        assert_eq!(normalize_username("some_user-1").unwrap(), "some_user-1");
+        assert!(normalize_username("").is_err());
+        assert!(normalize_username("../etc").is_err());
+    }
+
+    #[test]
+    fn test_archive_month() {
+        assert_eq!(archive_month(&format!("{}/2024/05", ARCHIVE)), "2024/05");
+        assert_eq!(archive_month("2024"), "2024");
+    }
+
+    #[test]
+    fn test_archives_to_fetch() {
+        let month = |m: &str| format!("{}/{}", ARCHIVE, m);
+        let archives = vec![month("2024/02"), month("2023/12"), month("2024/01")];
+        assert_eq!(
+            archives_to_fetch(archives.clone(), &[]),
+            vec![month("2023/12"), month("2024/01"), month("2024/02")]
+        );
+        assert_eq!(
+            archives_to_fetch(archives, &[month("2023/12"), month("2024/02")]),
+            vec![month("2024/01"), month("2024/02")]
+        );
+    }
+}
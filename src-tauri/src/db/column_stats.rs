@@ -0,0 +1,307 @@
+//! Column statistics used as query planning hints.
+//!
+//! `analyze_database` runs SQLite's `ANALYZE`, so the planner knows how selective each index is,
+//! and stores histograms of the games per player and color and per year in `ColumnStats`. Game
+//! queries use them to estimate how many rows they touch before running: `get_games` reports the
+//! estimate instead of counting a huge result, and position searches filtered by a player with few
+//! games read that player's games through the player index instead of scanning all of them.
+//!
+//! The histograms are a snapshot, so games imported afterwards are missing until the next
+//! analysis. Databases that were never analyzed have no estimates and behave as before.
+
+use std::path::PathBuf;
+
+use diesel::{connection::SimpleConnection, prelude::*};
+use serde::Serialize;
+use shakmaty::Color;
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        schema::{column_stats, games},
+        ConnectionOptions, GameQueryJs, Sides,
+    },
+    error::Result,
+    AppState,
+};
+
+/// Estimated matches above which `get_games` reports the estimate instead of counting them, with
+/// `count_is_estimate` set.
+pub(super) const EXACT_COUNT_LIMIT: i64 = 1_000_000;
+/// Largest share of all games a player may have for a search to go through the player index.
+const INDEX_SCAN_SHARE: f64 = 0.1;
+
+const TOTAL: &str = "total";
+const WHITE: &str = "white";
+const BLACK: &str = "black";
+const YEAR: &str = "year";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseAnalysis {
+    pub games: i64,
+    /// Players with at least one game.
+    pub players: i64,
+    /// Distinct years of the dated games.
+    pub years: i64,
+}
+
+/// A player filter to be answered through the `WhiteID` or `BlackID` index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct PlayerScan {
+    pub player_id: i32,
+    pub color: Color,
+}
+
+fn rebuild_histograms(db: &mut SqliteConnection) -> Result<DatabaseAnalysis> {
+    db.transaction(|db| {
+        db.batch_execute(&format!(
+            "DELETE FROM ColumnStats;
+            INSERT INTO ColumnStats (Kind, Key, Games) SELECT '{TOTAL}', '', COUNT(*) FROM Games;
+            INSERT INTO ColumnStats (Kind, Key, Games)
+                SELECT '{WHITE}', CAST(WhiteID AS TEXT), COUNT(*) FROM Games GROUP BY WhiteID;
+            INSERT INTO ColumnStats (Kind, Key, Games)
+                SELECT '{BLACK}', CAST(BlackID AS TEXT), COUNT(*) FROM Games GROUP BY BlackID;
+            INSERT INTO ColumnStats (Kind, Key, Games)
+                SELECT '{YEAR}', substr(Date, 1, 4), COUNT(*) FROM Games
+                WHERE Date GLOB '[0-9][0-9][0-9][0-9]*' GROUP BY substr(Date, 1, 4);"
+        ))?;
+
+        let players = column_stats::table
+            .filter(column_stats::kind.eq_any([WHITE, BLACK]))
+            .select(column_stats::key)
+            .distinct()
+            .load::<String>(db)?
+            .len() as i64;
+        let years: i64 = column_stats::table
+            .filter(column_stats::kind.eq(YEAR))
+            .count()
+            .get_result(db)?;
+        Ok(DatabaseAnalysis {
+            games: stat(db, TOTAL, "").unwrap_or(0),
+            players,
+            years,
+        })
+    })
+}
+
+/// A value of the histograms; `None` as well when the database was never analyzed.
+fn stat(db: &mut SqliteConnection, kind: &str, key: &str) -> Option<i64> {
+    column_stats::table
+        .filter(column_stats::kind.eq(kind))
+        .filter(column_stats::key.eq(key))
+        .select(column_stats::games)
+        .first(db)
+        .ok()
+}
+
+fn color_kind(color: Color) -> &'static str {
+    match color {
+        Color::White => WHITE,
+        Color::Black => BLACK,
+    }
+}
+
+/// Games of `player_id` with `color`, or with either color.
+fn player_games(db: &mut SqliteConnection, player_id: i32, color: Option<Color>) -> i64 {
+    let key = player_id.to_string();
+    match color {
+        Some(color) => stat(db, color_kind(color), &key).unwrap_or(0),
+        None => stat(db, WHITE, &key).unwrap_or(0) + stat(db, BLACK, &key).unwrap_or(0),
+    }
+}
+
+/// Games dated in the years from `start_date` to `end_date`.
+fn year_games(db: &mut SqliteConnection, start_date: Option<&str>, end_date: Option<&str>) -> i64 {
+    let mut query = column_stats::table
+        .filter(column_stats::kind.eq(YEAR))
+        .select(column_stats::games)
+        .into_boxed();
+    if let Some(start) = start_date.and_then(|date| date.get(..4)) {
+        query = query.filter(column_stats::key.ge(start.to_string()));
+    }
+    if let Some(end) = end_date.and_then(|date| date.get(..4)) {
+        query = query.filter(column_stats::key.le(end.to_string()));
+    }
+    query
+        .load::<i64>(db)
+        .map_or(0, |counts| counts.iter().sum())
+}
+
+/// Number of games in the database, from the histograms when it was analyzed and counted
+/// otherwise.
+pub(super) fn total_games(db: &mut SqliteConnection) -> i64 {
+    match stat(db, TOTAL, "") {
+        Some(total) => total,
+        None => games::table.count().get_result(db).unwrap_or(0),
+    }
+}
+
+/// Player filters of a `get_games` query with the color each player must have.
+fn game_player_filters(query: &GameQueryJs) -> Vec<(i32, Option<Color>)> {
+    let (color1, color2) = match query.sides {
+        Some(Sides::BlackWhite) => (Some(Color::Black), Some(Color::White)),
+        Some(Sides::WhiteBlack) => (Some(Color::White), Some(Color::Black)),
+        Some(Sides::Any) => (None, None),
+        None => return Vec::new(),
+    };
+    [(query.player1, color1), (query.player2, color2)]
+        .into_iter()
+        .filter_map(|(player, color)| Some((player?, color)))
+        .collect()
+}
+
+/// Upper bound on the games matching the player and date filters of `query`, or `None` when the
+/// database was never analyzed.
+pub(super) fn estimate_game_rows(db: &mut SqliteConnection, query: &GameQueryJs) -> Option<i64> {
+    let mut estimate = stat(db, TOTAL, "")?;
+    for (player_id, color) in game_player_filters(query) {
+        estimate = estimate.min(player_games(db, player_id, color));
+    }
+    if query.start_date.is_some() || query.end_date.is_some() {
+        estimate = estimate.min(year_games(
+            db,
+            query.start_date.as_deref(),
+            query.end_date.as_deref(),
+        ));
+    }
+    Some(estimate)
+}
+
+/// The candidate with the fewest games, if it has few enough of the `total` for an index scan.
+fn choose_scan(total: i64, candidates: &[(PlayerScan, i64)]) -> Option<PlayerScan> {
+    let (scan, games) = candidates.iter().min_by_key(|(_, games)| *games)?;
+    (*games as f64 <= total as f64 * INDEX_SCAN_SHARE).then_some(*scan)
+}
+
+/// Index scan for the player filters of a position search, where `player1` plays white and
+/// `player2` black, or `None` to scan every game.
+pub(super) fn position_player_scan(
+    db: &mut SqliteConnection,
+    query: &GameQueryJs,
+) -> Option<PlayerScan> {
+    let total = stat(db, TOTAL, "")?;
+    let candidates: Vec<(PlayerScan, i64)> =
+        [(query.player1, Color::White), (query.player2, Color::Black)]
+            .into_iter()
+            .filter_map(|(player, color)| {
+                let scan = PlayerScan {
+                    player_id: player?,
+                    color,
+                };
+                Some((scan, player_games(db, scan.player_id, Some(color))))
+            })
+            .collect();
+    choose_scan(total, &candidates)
+}
+
+/// Run `ANALYZE` on `file` and rebuild its per-player and per-year game histograms.
+#[tauri::command]
+#[specta::specta]
+pub async fn analyze_database(
+    file: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<DatabaseAnalysis> {
+    let _write = state.db_writes.lock(&file, "analyze_database").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    db.batch_execute("ANALYZE;")?;
+    let analysis = rebuild_histograms(db)?;
+    log::info!(
+        "Analyzed {}: {} games, {} players, {} years",
+        file.display(),
+        analysis.games,
+        analysis.players,
+        analysis.years
+    );
+    Ok(analysis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> SqliteConnection {
+        let mut db = SqliteConnection::establish(":memory:").unwrap();
+        db.batch_execute(
+            "CREATE TABLE Games (ID INTEGER PRIMARY KEY, Date TEXT, WhiteID INTEGER, BlackID INTEGER);
+            INSERT INTO Games VALUES (1, '2023.01.05', 1, 2), (2, '2023.06.01', 2, 1),
+                (3, '2024.02.11', 1, 3), (4, '????.??.??', 3, 2), (5, NULL, 1, 2);",
+        )
+        .unwrap();
+        db.batch_execute(include_str!(
+            "../../../database/migrations/add_column_stats_table.sql"
+        ))
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_rebuild_histograms() {
+        let mut db = test_db();
+        assert_eq!(stat(&mut db, TOTAL, ""), None);
+
+        let analysis = rebuild_histograms(&mut db).unwrap();
+        assert_eq!(
+            analysis,
+            DatabaseAnalysis {
+                games: 5,
+                players: 3,
+                years: 2,
+            }
+        );
+        assert_eq!(player_games(&mut db, 1, Some(Color::White)), 3);
+        assert_eq!(player_games(&mut db, 2, None), 4);
+        assert_eq!(player_games(&mut db, 9, None), 0);
+        assert_eq!(year_games(&mut db, Some("2023.03.01"), None), 3);
+        assert_eq!(year_games(&mut db, None, Some("2023.12.31")), 2);
+
+        // Rebuilding replaces the previous histograms.
+        db.batch_execute("DELETE FROM Games WHERE ID > 2;").unwrap();
+        assert_eq!(rebuild_histograms(&mut db).unwrap().games, 2);
+        assert_eq!(total_games(&mut db), 2);
+    }
+
+    #[test]
+    fn test_estimate_game_rows() {
+        let mut db = test_db();
+        let query = GameQueryJs {
+            player1: Some(3),
+            sides: Some(Sides::WhiteBlack),
+            ..Default::default()
+        };
+        assert_eq!(estimate_game_rows(&mut db, &query), None);
+
+        rebuild_histograms(&mut db).unwrap();
+        assert_eq!(estimate_game_rows(&mut db, &query), Some(1));
+        let any_side = GameQueryJs {
+            sides: Some(Sides::Any),
+            ..query.clone()
+        };
+        assert_eq!(estimate_game_rows(&mut db, &any_side), Some(2));
+        let dated = GameQueryJs {
+            start_date: Some("2024.01.01".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(estimate_game_rows(&mut db, &dated), Some(1));
+        assert_eq!(
+            estimate_game_rows(&mut db, &GameQueryJs::default()),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn test_choose_scan() {
+        let rare = PlayerScan {
+            player_id: 1,
+            color: Color::White,
+        };
+        let common = PlayerScan {
+            player_id: 2,
+            color: Color::Black,
+        };
+        assert_eq!(choose_scan(1000, &[(common, 400), (rare, 20)]), Some(rare));
+        assert_eq!(choose_scan(1000, &[(common, 400)]), None);
+        assert_eq!(choose_scan(1000, &[]), None);
+    }
+}
@@ -0,0 +1,254 @@
+//! Conditional moves for correspondence games.
+//!
+//! A conditional line is attached to a point of a game's main line and starts with the move the
+//! opponent is expected to play, followed by the prepared answer and optionally further moves:
+//! "if 25...Rxd4 then 26.Qe8+ Kh7 27.Qxf7". Lines are validated against the game when added and
+//! stored in the database file next to the game. `export_conditional_lines` renders them as PGN
+//! comments in the `{Conditional: if ... then ...}` form most correspondence servers accept.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use serde::Serialize;
+use shakmaty::{
+    fen::Fen, san::SanPlus, CastlingMode, Chess, Color, EnPassantMode, FromSetup, Position,
+};
+use specta::Type;
+
+use crate::{
+    db::{
+        encoding::extract_main_line_moves,
+        get_db_or_create,
+        schema::{game_conditionals, games},
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionalLine {
+    pub id: i32,
+    /// Main-line half-moves played before the line starts.
+    pub ply: i32,
+    /// Position the line starts from.
+    pub fen: String,
+    /// SAN moves, starting with the opponent's expected move.
+    pub moves: Vec<String>,
+    /// Readable form, e.g. `if 25...Rxd4 then 26.Qe8+ Kh7`.
+    pub text: String,
+}
+
+/// Position of a game after `ply` half-moves of its main line.
+fn game_position(db: &mut SqliteConnection, game_id: i32, ply: i32) -> Result<Chess> {
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first(db)?;
+    let mut position = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let main_line = extract_main_line_moves(&moves, Some(position.clone()))?;
+    if ply < 0 || ply as usize > main_line.len() {
+        return Err(Error::PackageManager(format!(
+            "Ply {} is outside of the game ({} half-moves)",
+            ply,
+            main_line.len()
+        )));
+    }
+    for m in &main_line[..ply as usize] {
+        position.play_unchecked(m);
+    }
+    Ok(position)
+}
+
+/// Check that `moves` can be played from `position` and return them in normalized SAN.
+fn validate_line(position: &Chess, moves: &[String]) -> Result<Vec<SanPlus>> {
+    if moves.len() < 2 {
+        return Err(Error::PackageManager(
+            "A conditional line needs the expected move and an answer".to_string(),
+        ));
+    }
+    let mut position = position.clone();
+    moves
+        .iter()
+        .map(|san| {
+            let san: SanPlus = san
+                .trim()
+                .parse()
+                .map_err(|_| Error::PackageManager(format!("Invalid move: {}", san)))?;
+            let m = san.san.to_move(&position)?;
+            Ok(SanPlus::from_move_and_play_unchecked(&mut position, &m))
+        })
+        .collect()
+}
+
+/// `if 25...Rxd4 then 26.Qe8+ Kh7 27.Qxf7` for a line starting from `position`.
+fn line_text(position: &Chess, moves: &[String]) -> String {
+    let mut fullmoves = position.fullmoves().get();
+    let mut turn = position.turn();
+    let mut numbered = Vec::with_capacity(moves.len());
+    for (i, san) in moves.iter().enumerate() {
+        numbered.push(match turn {
+            Color::White => format!("{}.{}", fullmoves, san),
+            Color::Black if i == 0 || i == 1 => format!("{}...{}", fullmoves, san),
+            Color::Black => san.clone(),
+        });
+        if turn == Color::Black {
+            fullmoves += 1;
+        }
+        turn = !turn;
+    }
+    match numbered.split_first() {
+        Some((condition, answer)) => format!("if {} then {}", condition, answer.join(" ")),
+        None => String::new(),
+    }
+}
+
+fn load_lines(db: &mut SqliteConnection, game_id: i32) -> Result<Vec<ConditionalLine>> {
+    let rows: Vec<(i32, i32, String)> = game_conditionals::table
+        .filter(game_conditionals::game_id.eq(game_id))
+        .select((
+            game_conditionals::id,
+            game_conditionals::ply,
+            game_conditionals::moves,
+        ))
+        .order((game_conditionals::ply.asc(), game_conditionals::id.asc()))
+        .load(db)?;
+
+    rows.into_iter()
+        .map(|(id, ply, moves)| {
+            let position = game_position(db, game_id, ply)?;
+            let moves: Vec<String> = moves.split_whitespace().map(str::to_string).collect();
+            Ok(ConditionalLine {
+                id,
+                ply,
+                fen: Fen::from_position(position.clone(), EnPassantMode::Legal).to_string(),
+                text: line_text(&position, &moves),
+                moves,
+            })
+        })
+        .collect()
+}
+
+/// Attach a conditional line to a game after `ply` half-moves of its main line.
+#[tauri::command]
+#[specta::specta]
+pub async fn add_conditional_line(
+    file: PathBuf,
+    game_id: i32,
+    ply: i32,
+    moves: Vec<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<ConditionalLine> {
+    let _write = state.db_writes.lock(&file, "add_conditional_line").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let position = game_position(db, game_id, ply)?;
+    let moves: Vec<String> = validate_line(&position, &moves)?
+        .iter()
+        .map(|san| san.to_string())
+        .collect();
+
+    let id: i32 = diesel::insert_into(game_conditionals::table)
+        .values((
+            game_conditionals::game_id.eq(game_id),
+            game_conditionals::ply.eq(ply),
+            game_conditionals::moves.eq(moves.join(" ")),
+        ))
+        .returning(game_conditionals::id)
+        .get_result(db)?;
+
+    Ok(ConditionalLine {
+        id,
+        ply,
+        fen: Fen::from_position(position.clone(), EnPassantMode::Legal).to_string(),
+        text: line_text(&position, &moves),
+        moves,
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn remove_conditional_line(
+    file: PathBuf,
+    id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    let _write = state.db_writes.lock(&file, "remove_conditional_line").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    diesel::delete(game_conditionals::table.filter(game_conditionals::id.eq(id))).execute(db)?;
+    Ok(())
+}
+
+/// Conditional lines of a game, in main-line order.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_conditional_lines(
+    file: PathBuf,
+    game_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ConditionalLine>> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    load_lines(db, game_id)
+}
+
+/// Conditional lines of a game as PGN comments, one per line.
+#[tauri::command]
+#[specta::specta]
+pub async fn export_conditional_lines(
+    file: PathBuf,
+    game_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<String> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    Ok(load_lines(db, game_id)?
+        .iter()
+        .map(|line| format!("{{Conditional: {}}}", line.text))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(fen: &str) -> Chess {
+        let fen: Fen = fen.parse().unwrap();
+        Chess::from_setup(fen.into(), CastlingMode::Standard).unwrap()
+    }
+
+    fn strings(moves: &[&str]) -> Vec<String> {
+        moves.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_line() {
+        let start = Chess::default();
+        let line = validate_line(&start, &strings(&["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7"]))
+            .unwrap();
+        assert_eq!(line.last().unwrap().to_string(), "Qxf7#");
+        assert!(validate_line(&start, &strings(&["e4"])).is_err());
+        assert!(validate_line(&start, &strings(&["e4", "e4"])).is_err());
+    }
+
+    #[test]
+    fn test_line_text() {
+        let black_to_move =
+            position("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 25");
+        assert_eq!(
+            line_text(&black_to_move, &strings(&["e5", "Nf3", "Nc6", "Bb5"])),
+            "if 25...e5 then 26.Nf3 Nc6 27.Bb5"
+        );
+        assert_eq!(
+            line_text(&Chess::default(), &strings(&["e4", "c5", "Nf3"])),
+            "if 1.e4 then 1...c5 2.Nf3"
+        );
+    }
+}
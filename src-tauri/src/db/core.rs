@@ -1,9 +1,12 @@
 use super::{
-    create_event, create_player, create_site, models::{Event, Game, NewGame, NormalizedGame, Outcome, Player, Site, UpdateGame}, pgn::{GameTree, Importer}, schema::{events, games, players, sites}
+    create_event, create_player, create_site, derived_columns::derived_columns, get_db_or_create, move_data::update_move_data, player_aggregates, position_filter::forget_filter, search::forget_checkpoints, models::{Event, Game, NewGame, NormalizedGame, Outcome, Player, Site, UpdateGame}, pgn::{GameTree, Importer}, schema::{events, games, players, schema_version, sites}, ConnectionOptions
 };
-use crate::error::{Result};
-use diesel::{connection::SimpleConnection, prelude::*};
+use crate::{error::{Result}, progress::{TaskKind, TaskProgress}, AppState};
+use diesel::{connection::SimpleConnection, dsl::sql, prelude::*, sql_types::Bool};
+use serde::Serialize;
 use shakmaty::{Chess, fen::Fen, CastlingMode, FromSetup};
+use specta::Type;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::string::ToString;
 use pgn_reader::BufferedReader;
@@ -12,6 +15,9 @@ const DATABASE_VERSION: &str = "1.0.0";
 const CREATE_TABLES_SQL: &str = include_str!("../../../database/schema/core_tables.sql");
 const INITIAL_DATA_SQL: &str = include_str!("../../../database/seeds/initial_data.sql");
 const INFO_INSERT_METADATA: &str = include_str!("../../../database/queries/info/insert_metadata.sql");
+const SCHEMA_VERSION_SQL: &str = include_str!("../../../database/migrations/add_schema_version_table.sql");
+const GAME_POSITION_CHECKPOINTS_SQL: &str =
+    include_str!("../../../database/migrations/add_game_position_checkpoints_table.sql");
 #[cfg(test)]
 const GAMES_CHECK_INDEXES: &str = include_str!("../../../database/queries/games/check_indexes.sql");
 
@@ -36,14 +42,390 @@ pub fn init_db(conn: &mut SqliteConnection, title: &str, description: &str) -> R
             .replace("{2}", description)
     )?;
     log::info!("✓ Metadata inserted");
+
+    // STEP 4: Create the tables added by migrations
+    migrate(conn)?;
     
-    // STEP 4: Now apply performance pragmas AFTER tables are created
+    // STEP 5: Now apply performance pragmas AFTER tables are created
     sql_query(include_str!("../../../database/pragmas/performance_pragmas.sql")).execute(conn)?;
     log::info!("✓ Performance pragmas applied");
 
     Ok(())
 }
 
+/// A schema change for game databases created by older versions of the app or by en-croissant.
+/// Migrations run in `version` order and each runs once per database.
+struct Migration {
+    version: i32,
+    name: &'static str,
+    step: MigrationStep,
+}
+
+enum MigrationStep {
+    /// Statements that can run on any database, such as `CREATE TABLE IF NOT EXISTS`.
+    Sql(&'static str),
+    Code(fn(&mut SqliteConnection) -> Result<()>),
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "add_missing_game_columns",
+        step: MigrationStep::Code(add_missing_game_columns),
+    },
+    Migration {
+        version: 2,
+        name: "add_game_sources_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_sources_table.sql"
+        )),
+    },
+    Migration {
+        version: 3,
+        name: "add_game_tags_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_tags_table.sql"
+        )),
+    },
+    Migration {
+        version: 4,
+        name: "add_saved_filters_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_saved_filters_table.sql"
+        )),
+    },
+    Migration {
+        version: 5,
+        name: "add_column_stats_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_column_stats_table.sql"
+        )),
+    },
+    Migration {
+        version: 6,
+        name: "add_game_phases_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_phases_table.sql"
+        )),
+    },
+    Migration {
+        version: 7,
+        name: "add_game_endgames_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_endgames_table.sql"
+        )),
+    },
+    Migration {
+        version: 8,
+        name: "add_game_conditionals_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_conditionals_table.sql"
+        )),
+    },
+    Migration {
+        version: 9,
+        name: "add_merge_journal_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_merge_journal_table.sql"
+        )),
+    },
+    Migration {
+        version: 10,
+        name: "add_player_monthly_stats_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_player_monthly_stats_table.sql"
+        )),
+    },
+    Migration {
+        version: 11,
+        name: "add_analyzed_games_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_analyzed_games_table.sql"
+        )),
+    },
+    Migration {
+        version: 12,
+        name: "add_game_time_forfeits_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_time_forfeits_table.sql"
+        )),
+    },
+    Migration {
+        version: 13,
+        name: "add_game_move_data_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_move_data_table.sql"
+        )),
+    },
+    Migration {
+        version: 14,
+        name: "add_player_fide_ids_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_player_fide_ids_table.sql"
+        )),
+    },
+    Migration {
+        version: 15,
+        name: "add_game_position_filters_table",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_position_filters_table.sql"
+        )),
+    },
+    Migration {
+        version: 16,
+        name: "add_game_position_checkpoints_table",
+        step: MigrationStep::Code(add_game_position_checkpoints_table),
+    },
+    Migration {
+        version: 17,
+        name: "add_game_position_filters_foreign_key",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_position_filters_foreign_key.sql"
+        )),
+    },
+    Migration {
+        version: 18,
+        name: "add_game_position_checkpoints_positions",
+        step: MigrationStep::Sql(include_str!(
+            "../../../database/migrations/add_game_position_checkpoints_positions.sql"
+        )),
+    },
+];
+
+/// Columns of `Games` that older databases may lack, with their type.
+const GAME_COLUMNS: &[(&str, &str)] = &[
+    ("UTCTime", "TEXT"),
+    ("Round", "INTEGER"),
+    ("WhiteElo", "INTEGER"),
+    ("BlackElo", "INTEGER"),
+    ("WhiteMaterial", "INTEGER"),
+    ("BlackMaterial", "INTEGER"),
+    ("TimeControl", "TEXT"),
+    ("ECO", "TEXT"),
+    ("PlyCount", "INTEGER"),
+    ("FEN", "TEXT"),
+    ("PawnHome", "INTEGER"),
+];
+
+/// Games whose derived columns are filled per migration transaction.
+const BACKFILL_BATCH: i64 = 5000;
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    pub from_version: i32,
+    pub to_version: i32,
+    /// Names of the migrations applied, in order.
+    pub applied: Vec<String>,
+}
+
+fn table_exists(conn: &mut SqliteConnection, table: &str) -> Result<bool> {
+    Ok(diesel::select(sql::<Bool>(&format!(
+        "EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '{}')",
+        table
+    )))
+    .get_result(conn)?)
+}
+
+fn column_exists(conn: &mut SqliteConnection, table: &str, column: &str) -> Result<bool> {
+    Ok(diesel::select(sql::<Bool>(&format!(
+        "EXISTS(SELECT 1 FROM pragma_table_info('{}') WHERE name = '{}')",
+        table, column
+    )))
+    .get_result(conn)?)
+}
+
+fn add_missing_game_columns(conn: &mut SqliteConnection) -> Result<()> {
+    let mut added = Vec::new();
+    for (column, kind) in GAME_COLUMNS {
+        if !column_exists(conn, "Games", column)? {
+            conn.batch_execute(&format!("ALTER TABLE Games ADD COLUMN {} {};", column, kind))?;
+            added.push(*column);
+        }
+    }
+    if !column_exists(conn, "Players", "Elo")? {
+        conn.batch_execute("ALTER TABLE Players ADD COLUMN Elo INTEGER;")?;
+    }
+    if !added
+        .iter()
+        .any(|c| ["WhiteMaterial", "BlackMaterial", "PawnHome", "PlyCount"].contains(c))
+    {
+        return Ok(());
+    }
+
+    log::info!("Backfilling {} for existing games", added.join(", "));
+    let mut last_id = 0;
+    let mut skipped = 0;
+    loop {
+        let batch: Vec<(i32, Vec<u8>, Option<String>)> = games::table
+            .filter(games::id.gt(last_id))
+            .select((games::id, games::moves, games::fen))
+            .order(games::id)
+            .limit(BACKFILL_BATCH)
+            .load(conn)?;
+        let Some((id, _, _)) = batch.last() else {
+            break;
+        };
+        last_id = *id;
+        for (id, moves, fen) in &batch {
+            // Games that cannot be replayed keep NULL, like `recompute_derived_columns` leaves
+            // them, instead of failing the migration of the whole database.
+            let Ok(derived) = derived_columns(moves, fen.as_deref()) else {
+                skipped += 1;
+                continue;
+            };
+            diesel::update(games::table.find(*id))
+                .set((
+                    games::white_material.eq(derived.white_material),
+                    games::black_material.eq(derived.black_material),
+                    games::pawn_home.eq(derived.pawn_home),
+                    games::ply_count.eq(derived.ply_count),
+                ))
+                .execute(conn)?;
+        }
+    }
+    if skipped > 0 {
+        log::warn!(
+            "Skipped {} games whose moves could not be replayed",
+            skipped
+        );
+    }
+    Ok(())
+}
+
+/// Earlier versions sampled full-board hashes every 8 plies, which cannot be looked up, so their
+/// checkpoints are dropped and rebuilt.
+fn add_game_position_checkpoints_table(conn: &mut SqliteConnection) -> Result<()> {
+    if column_exists(conn, "game_position_checkpoints", "board_hash")? {
+        conn.batch_execute("DROP TABLE game_position_checkpoints;")?;
+    }
+    conn.batch_execute(GAME_POSITION_CHECKPOINTS_SQL)?;
+    Ok(())
+}
+
+fn current_version(conn: &mut SqliteConnection) -> Result<i32> {
+    Ok(schema_version::table
+        .select(diesel::dsl::max(schema_version::version))
+        .first::<Option<i32>>(conn)?
+        .unwrap_or(0))
+}
+
+fn record_migration(conn: &mut SqliteConnection, migration: &Migration) -> Result<()> {
+    diesel::replace_into(schema_version::table)
+        .values((
+            schema_version::version.eq(migration.version),
+            schema_version::name.eq(migration.name),
+            schema_version::applied_at.eq(chrono::Utc::now().to_rfc3339()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Bring a games database up to the current schema. Databases without a `Games` table, such as
+/// ones not initialized yet, are left alone.
+pub fn migrate(conn: &mut SqliteConnection) -> Result<MigrationReport> {
+    migrate_with_progress(conn, |_, _| {})
+}
+
+/// Number of migrations `migrate` would apply, without writing to the database.
+fn pending_migrations(conn: &mut SqliteConnection) -> Result<usize> {
+    if !table_exists(conn, "Games")? {
+        return Ok(0);
+    }
+    let from_version = if table_exists(conn, "schema_version")? {
+        current_version(conn)?
+    } else {
+        0
+    };
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| m.version > from_version)
+        .count())
+}
+
+/// `migrate`, calling `progress` with the number of migrations applied so far and the number
+/// pending before each one runs.
+fn migrate_with_progress(
+    conn: &mut SqliteConnection,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<MigrationReport> {
+    if !table_exists(conn, "Games")? {
+        return Ok(MigrationReport::default());
+    }
+    conn.batch_execute(SCHEMA_VERSION_SQL)?;
+    let from_version = current_version(conn)?;
+    let mut report = MigrationReport {
+        from_version,
+        to_version: from_version,
+        applied: Vec::new(),
+    };
+    let pending: Vec<&Migration> = MIGRATIONS
+        .iter()
+        .filter(|m| m.version > from_version)
+        .collect();
+    for (i, migration) in pending.iter().enumerate() {
+        progress(i, pending.len());
+        conn.transaction::<_, crate::error::Error, _>(|conn| {
+            match migration.step {
+                MigrationStep::Sql(sql) => conn.batch_execute(sql)?,
+                MigrationStep::Code(apply) => apply(conn)?,
+            }
+            record_migration(conn, migration)
+        })?;
+        log::info!("Applied database migration {} ({})", migration.version, migration.name);
+        report.to_version = migration.version;
+        report.applied.push(migration.name.to_string());
+    }
+    Ok(report)
+}
+
+/// Run the pending migrations of a games database. The app runs it on every database it lists,
+/// before anything else reads it, so other commands never change the schema. Migrating runs on a
+/// blocking thread under the write lock and reports each step as `Database` progress; a database
+/// is only remembered as migrated once every migration succeeded.
+#[tauri::command]
+#[specta::specta]
+pub async fn migrate_database(
+    file: PathBuf,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<MigrationReport> {
+    let db_path = file.to_str().unwrap().to_string();
+    if state.migrated_dbs.contains(&db_path) {
+        return Ok(MigrationReport::default());
+    }
+    {
+        let db = &mut get_db_or_create(&state, &db_path, ConnectionOptions::default())?;
+        if pending_migrations(db)? == 0 {
+            state.migrated_dbs.insert(db_path);
+            return Ok(MigrationReport::default());
+        }
+    }
+
+    let _write = state.db_writes.lock(&file, "migrate_database").await;
+    let mut db = get_db_or_create(&state, &db_path, ConnectionOptions::default())?;
+    let task_id = db_path.clone();
+    let progress_app = app.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        migrate_with_progress(&mut db, |applied, pending| {
+            let percent = applied as f64 / pending as f64 * 100.0;
+            TaskProgress::new(TaskKind::Database, task_id.clone(), percent)
+                .message(format!(
+                    "Upgrading the database ({} of {})",
+                    applied + 1,
+                    pending
+                ))
+                .send(&progress_app);
+        })
+    })
+    .await
+    .map_err(|e| crate::error::Error::PackageManager(format!("Migration task failed: {}", e)))??;
+    TaskProgress::done(TaskKind::Database, db_path.clone()).send(&app);
+
+    state.migrated_dbs.insert(db_path);
+    Ok(report)
+}
+
 pub fn normalize_game(
     game: Game,
     white: Player,
@@ -76,7 +458,8 @@ pub fn normalize_game(
         eco: game.eco,
         ply_count: game.ply_count,
         fen: fen.to_string(),
-        moves: GameTree::from_bytes(&game.moves, Some(Chess::from_setup(fen.into(), CastlingMode::Chess960)?))?.to_string()
+        moves: GameTree::from_bytes(&game.moves, Some(Chess::from_setup(fen.into(), CastlingMode::Chess960)?))?.to_string(),
+        source: None,
     })
 }
 
@@ -140,12 +523,18 @@ pub fn update_game(conn: &mut SqliteConnection, id: i32, data: &UpdateGame) -> R
             games::moves.eq(&moves)
         ))
         .execute(conn)?;
+    update_move_data(conn, id, &tree)?;
+    forget_filter(conn, id)?;
+    forget_checkpoints(conn, id)?;
+    player_aggregates::invalidate(conn)?;
     
     Ok(())
 }
 
 
 pub fn remove_game(conn: &mut SqliteConnection, id: i32) -> Result<()> {
+    forget_filter(conn, id)?;
+    forget_checkpoints(conn, id)?;
     diesel::delete(games::table.filter(games::id.eq(id))).execute(conn)?;
 
     Ok(())
@@ -171,6 +560,49 @@ mod tests {
         _name: String,
     }
 
+    #[test]
+    fn test_migrate() {
+        let mut db = test_db();
+        let report = migrate(&mut db).unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(report.from_version, MIGRATIONS.last().unwrap().version);
+
+        // A database from before the material and pawn home columns existed.
+        let mut db = SqliteConnection::establish(":memory:").unwrap();
+        db.batch_execute(
+            "CREATE TABLE Players (ID INTEGER PRIMARY KEY, Name TEXT UNIQUE);
+             CREATE TABLE Games (ID INTEGER PRIMARY KEY AUTOINCREMENT, EventID INTEGER,
+                 SiteID INTEGER, Date TEXT, WhiteID INTEGER, BlackID INTEGER, Result TEXT,
+                 FEN TEXT, Moves BLOB);
+             INSERT INTO Games (Moves) VALUES (x'0c0c');
+             INSERT INTO Games (FEN, Moves) VALUES ('not a fen', x'0c0c');",
+        )
+        .unwrap();
+        assert_eq!(pending_migrations(&mut db).unwrap(), MIGRATIONS.len());
+        let report = migrate(&mut db).unwrap();
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.applied.len(), MIGRATIONS.len());
+        assert_eq!(report.applied[0], "add_missing_game_columns");
+        assert!(table_exists(&mut db, "GameTags").unwrap());
+        assert!(table_exists(&mut db, "game_position_checkpoints").unwrap());
+        let (ply_count, white_material): (Option<i32>, i32) = games::table
+            .find(1)
+            .select((games::ply_count, games::white_material))
+            .first(&mut db)
+            .unwrap();
+        assert_eq!(ply_count, Some(2));
+        assert_eq!(white_material, 39);
+        // A game that cannot be replayed does not fail the migration.
+        let ply_count: Option<i32> = games::table
+            .find(2)
+            .select(games::ply_count)
+            .first(&mut db)
+            .unwrap();
+        assert_eq!(ply_count, None);
+        assert_eq!(pending_migrations(&mut db).unwrap(), 0);
+        assert!(migrate(&mut db).unwrap().applied.is_empty());
+    }
+
     #[test]
     fn test_add_game() {
         let mut db = test_db();
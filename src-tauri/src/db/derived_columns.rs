@@ -0,0 +1,168 @@
+//! Backfill of the columns derived from a game's moves.
+//!
+//! `PlyCount`, `PawnHome`, `WhiteMaterial` and `BlackMaterial` are computed at import and used by
+//! position search to skip games that cannot reach the searched position. Databases written by
+//! older versions may have them missing or wrong, which makes those prefilters drop matching
+//! games. Recomputing replays every game the same way the importer does and rewrites the values
+//! that differ.
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use diesel::prelude::*;
+use rayon::prelude::*;
+use shakmaty::{fen::Fen, CastlingMode, Chess, FromSetup, Position};
+
+use crate::{
+    db::{
+        encoding::extract_main_line_moves, get_db_or_create, get_pawn_home, pgn::get_material_count,
+        schema::games, ConnectionOptions,
+    },
+    error::{Error, Result},
+    progress::{TaskKind, TaskProgress},
+    AppState,
+};
+
+const RECOMPUTE_BATCH: i64 = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct DerivedColumns {
+    pub ply_count: Option<i32>,
+    pub pawn_home: i32,
+    pub white_material: i32,
+    pub black_material: i32,
+}
+
+/// Values the importer stores for a game: main-line length, pawns on their home squares in the
+/// starting position, and the lower of the starting and final material of each side.
+pub(super) fn derived_columns(moves: &[u8], fen: Option<&str>) -> Result<DerivedColumns> {
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let main_line = extract_main_line_moves(moves, Some(start.clone()))?;
+    let mut position = start.clone();
+    for m in &main_line {
+        position.play_unchecked(m);
+    }
+
+    let start_material = get_material_count(start.board());
+    let final_material = get_material_count(position.board());
+    Ok(DerivedColumns {
+        ply_count: Some(main_line.len() as i32),
+        pawn_home: get_pawn_home(start.board()) as i32,
+        white_material: start_material.white.min(final_material.white) as i32,
+        black_material: start_material.black.min(final_material.black) as i32,
+    })
+}
+
+/// Recompute the ply count, pawn structure and material columns of every game, in batches, and
+/// rewrite those that differ. Returns the number of games updated.
+#[tauri::command]
+#[specta::specta]
+pub async fn recompute_derived_columns(
+    file: PathBuf,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize> {
+    let _write = state.db_writes.lock(&file, "recompute_derived_columns").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let task_id = file.to_string_lossy().into_owned();
+
+    let total: i64 = games::table.count().get_result(db)?;
+    let processed = AtomicUsize::new(0);
+    let mut updated = 0;
+    let mut last_id = 0;
+    loop {
+        let batch: Vec<(i32, Vec<u8>, Option<String>, Option<i32>, i32, i32, i32)> = games::table
+            .filter(games::id.gt(last_id))
+            .select((
+                games::id,
+                games::moves,
+                games::fen,
+                games::ply_count,
+                games::pawn_home,
+                games::white_material,
+                games::black_material,
+            ))
+            .order(games::id)
+            .limit(RECOMPUTE_BATCH)
+            .load(db)?;
+        let Some(&(batch_last, ..)) = batch.last() else {
+            break;
+        };
+        last_id = batch_last;
+
+        let changed: Vec<(i32, DerivedColumns)> = batch
+            .par_iter()
+            .filter_map(|(id, moves, fen, ply_count, pawn_home, white_material, black_material)| {
+                processed.fetch_add(1, Ordering::Relaxed);
+                // Games that cannot be replayed keep their values.
+                let derived = derived_columns(moves, fen.as_deref()).ok()?;
+                let stored = DerivedColumns {
+                    ply_count: *ply_count,
+                    pawn_home: *pawn_home,
+                    white_material: *white_material,
+                    black_material: *black_material,
+                };
+                (derived != stored).then_some((*id, derived))
+            })
+            .collect();
+
+        db.transaction::<_, Error, _>(|db| {
+            for (id, derived) in &changed {
+                diesel::update(games::table.filter(games::id.eq(id)))
+                    .set((
+                        games::ply_count.eq(derived.ply_count),
+                        games::pawn_home.eq(derived.pawn_home),
+                        games::white_material.eq(derived.white_material),
+                        games::black_material.eq(derived.black_material),
+                    ))
+                    .execute(db)?;
+            }
+            Ok(())
+        })?;
+        updated += changed.len();
+
+        let percent = processed.load(Ordering::Relaxed) as f64 / total.max(1) as f64 * 100.0;
+        TaskProgress::new(TaskKind::Database, task_id.clone(), percent.min(99.0))
+            .message(format!("{} games updated", updated))
+            .send(&app);
+    }
+
+    TaskProgress::done(TaskKind::Database, task_id).send(&app);
+    log::info!("Recomputed derived columns of {}: {} games updated", file.display(), updated);
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(pgn_moves: &[&str]) -> Vec<u8> {
+        let mut position = Chess::default();
+        let mut bytes = Vec::new();
+        for san in pgn_moves {
+            let m = san.parse::<shakmaty::san::San>().unwrap().to_move(&position).unwrap();
+            let index = position.legal_moves().iter().position(|legal| *legal == m).unwrap();
+            bytes.push(index as u8);
+            position.play_unchecked(&m);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_derived_columns() {
+        let moves = encode(&["e4", "d5", "exd5", "Qxd5"]);
+        let derived = derived_columns(&moves, None).unwrap();
+        assert_eq!(derived.ply_count, Some(4));
+        assert_eq!(derived.pawn_home, 0xFFFF);
+        assert_eq!(derived.white_material, 38);
+        assert_eq!(derived.black_material, 38);
+    }
+}
@@ -0,0 +1,316 @@
+//! Diffing two snapshots of a database, e.g. before and after applying a TWIC update.
+//!
+//! Game IDs are not stable across imports, so games are matched by a hash of their identifying
+//! headers (players, event, site, date and round). Games with the same identity whose content
+//! (moves, result, ratings, ...) differs are reported as modified.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use diesel::{connection::DefaultLoadingMode, prelude::*};
+use serde::Serialize;
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        models::{Event, Game, Player, Site},
+        schema::{events, games, players, sites},
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+/// 64-bit FNV-1a, used instead of `DefaultHasher` so hashes are stable across builds and can be
+/// shown to the user.
+#[derive(Clone, Copy)]
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        // Field separator, so ("ab", "c") and ("a", "bc") differ.
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+
+    fn write_opt(&mut self, value: Option<&str>) {
+        self.write(value.unwrap_or_default().trim().as_bytes());
+    }
+
+    fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+fn hash_field(bytes: &[u8]) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Content fields compared for modified games, with the name reported to the user.
+const CONTENT_FIELDS: [&str; 8] = [
+    "moves",
+    "result",
+    "whiteElo",
+    "blackElo",
+    "timeControl",
+    "eco",
+    "fen",
+    "time",
+];
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GameDiffEntry {
+    /// Hex-encoded identity hash, shared by both versions of a modified game.
+    pub hash: String,
+    pub old_id: Option<i32>,
+    pub new_id: Option<i32>,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    pub event: Option<String>,
+    pub date: Option<String>,
+    /// Fields that differ, for modified games.
+    pub changes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseDiff {
+    pub unchanged: u32,
+    pub added: Vec<GameDiffEntry>,
+    pub removed: Vec<GameDiffEntry>,
+    pub modified: Vec<GameDiffEntry>,
+}
+
+struct Fingerprint {
+    id: i32,
+    identity: u64,
+    content: u64,
+    fields: [u64; CONTENT_FIELDS.len()],
+    white: Option<String>,
+    black: Option<String>,
+    event: Option<String>,
+    date: Option<String>,
+}
+
+impl Fingerprint {
+    fn from_row((game, white, black, event, site): (Game, Player, Player, Event, Site)) -> Self {
+        let mut identity = StableHasher::new();
+        identity.write_opt(white.name.as_deref());
+        identity.write_opt(black.name.as_deref());
+        identity.write_opt(event.name.as_deref());
+        identity.write_opt(site.name.as_deref());
+        identity.write_opt(game.date.as_deref());
+        identity.write_opt(game.round.as_deref());
+
+        let elo = |elo: Option<i32>| elo.map(|e| e.to_string()).unwrap_or_default();
+        let fields = [
+            hash_field(&game.moves),
+            hash_field(game.result.unwrap_or_default().as_bytes()),
+            hash_field(elo(game.white_elo).as_bytes()),
+            hash_field(elo(game.black_elo).as_bytes()),
+            hash_field(game.time_control.unwrap_or_default().as_bytes()),
+            hash_field(game.eco.unwrap_or_default().as_bytes()),
+            hash_field(game.fen.unwrap_or_default().as_bytes()),
+            hash_field(game.time.unwrap_or_default().as_bytes()),
+        ];
+        let mut content = StableHasher::new();
+        for field in &fields {
+            content.write(&field.to_le_bytes());
+        }
+
+        Self {
+            id: game.id,
+            identity: identity.finish(),
+            content: content.finish(),
+            fields,
+            white: white.name,
+            black: black.name,
+            event: event.name,
+            date: game.date,
+        }
+    }
+
+    fn entry(self, old_id: Option<i32>, new_id: Option<i32>, changes: Vec<String>) -> GameDiffEntry {
+        GameDiffEntry {
+            hash: format!("{:016x}", self.identity),
+            old_id,
+            new_id,
+            white: self.white,
+            black: self.black,
+            event: self.event,
+            date: self.date,
+            changes,
+        }
+    }
+}
+
+fn load_fingerprints(db: &mut SqliteConnection) -> Result<Vec<Fingerprint>> {
+    let (white_players, black_players) = diesel::alias!(players as white, players as black);
+    let fingerprints = games::table
+        .inner_join(white_players.on(games::white_id.eq(white_players.field(players::id))))
+        .inner_join(black_players.on(games::black_id.eq(black_players.field(players::id))))
+        .inner_join(events::table.on(games::event_id.eq(events::id)))
+        .inner_join(sites::table.on(games::site_id.eq(sites::id)))
+        .order(games::id)
+        .load_iter::<(Game, Player, Player, Event, Site), DefaultLoadingMode>(db)?
+        .map(|row| row.map(Fingerprint::from_row))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(fingerprints)
+}
+
+fn diff_fingerprints(old: Vec<Fingerprint>, new: Vec<Fingerprint>) -> DatabaseDiff {
+    let mut by_identity: HashMap<u64, Vec<Fingerprint>> = HashMap::new();
+    for fp in old {
+        by_identity.entry(fp.identity).or_default().push(fp);
+    }
+
+    let mut diff = DatabaseDiff::default();
+    // New games whose identity exists in the old snapshot but whose content matched nothing.
+    let mut unmatched: Vec<Fingerprint> = Vec::new();
+    for fp in new {
+        let candidates = by_identity.get_mut(&fp.identity);
+        match candidates.and_then(|c| c.iter().position(|o| o.content == fp.content).map(|i| (c, i))) {
+            Some((candidates, i)) => {
+                candidates.swap_remove(i);
+                diff.unchanged += 1;
+            }
+            None => unmatched.push(fp),
+        }
+    }
+
+    // Same identity, different content: pair them up in game order.
+    for fp in unmatched {
+        match by_identity.get_mut(&fp.identity).filter(|c| !c.is_empty()) {
+            Some(candidates) => {
+                let old = candidates.remove(0);
+                let changes = CONTENT_FIELDS
+                    .iter()
+                    .zip(old.fields.iter().zip(fp.fields.iter()))
+                    .filter(|(_, (a, b))| a != b)
+                    .map(|(name, _)| name.to_string())
+                    .collect();
+                let new_id = fp.id;
+                diff.modified.push(fp.entry(Some(old.id), Some(new_id), changes));
+            }
+            None => {
+                let new_id = fp.id;
+                diff.added.push(fp.entry(None, Some(new_id), Vec::new()));
+            }
+        }
+    }
+
+    let mut removed: Vec<Fingerprint> = by_identity.into_values().flatten().collect();
+    removed.sort_by_key(|fp| fp.id);
+    diff.removed = removed
+        .into_iter()
+        .map(|fp| {
+            let old_id = fp.id;
+            fp.entry(Some(old_id), None, Vec::new())
+        })
+        .collect();
+    diff
+}
+
+/// Games added, removed and modified in `new` compared to `old`.
+#[tauri::command]
+#[specta::specta]
+pub async fn diff_databases(
+    old: PathBuf,
+    new: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<DatabaseDiff> {
+    for path in [&old, &new] {
+        if !path.exists() {
+            return Err(Error::IoError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Database not found: {}", path.display()),
+            )));
+        }
+    }
+
+    let old_games = {
+        let db = &mut get_db_or_create(&state, old.to_str().unwrap(), ConnectionOptions::default())?;
+        load_fingerprints(db)?
+    };
+    let new_games = {
+        let db = &mut get_db_or_create(&state, new.to_str().unwrap(), ConnectionOptions::default())?;
+        load_fingerprints(db)?
+    };
+
+    let diff = diff_fingerprints(old_games, new_games);
+    log::info!(
+        "Database diff {} -> {}: {} added, {} removed, {} modified, {} unchanged",
+        old.display(),
+        new.display(),
+        diff.added.len(),
+        diff.removed.len(),
+        diff.modified.len(),
+        diff.unchanged
+    );
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(id: i32, white: &str, round: &str, result: &str) -> Fingerprint {
+        Fingerprint::from_row((
+            Game {
+                id,
+                round: Some(round.to_string()),
+                result: Some(result.to_string()),
+                moves: vec![1, 2, 3],
+                ..Default::default()
+            },
+            Player {
+                name: Some(white.to_string()),
+                ..Default::default()
+            },
+            Player::default(),
+            Event::default(),
+            Site::default(),
+        ))
+    }
+
+    #[test]
+    fn test_stable_hash() {
+        assert_eq!(hash_field(b""), hash_field(b""));
+        assert_ne!(hash_field(b"ab"), hash_field(b"ba"));
+        assert_eq!(game(1, "Carlsen", "1", "1-0").identity, game(7, "Carlsen", "1", "0-1").identity);
+    }
+
+    #[test]
+    fn test_diff_fingerprints() {
+        let old = vec![
+            game(1, "Carlsen", "1", "1-0"),
+            game(2, "Nakamura", "1", "1/2-1/2"),
+            game(3, "Caruana", "1", "*"),
+        ];
+        let new = vec![
+            game(1, "Carlsen", "1", "1-0"),
+            game(2, "Caruana", "1", "0-1"),
+            game(3, "Firouzja", "2", "1-0"),
+        ];
+        let diff = diff_fingerprints(old, new);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].old_id, Some(3));
+        assert_eq!(diff.modified[0].changes, vec!["result"]);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].white.as_deref(), Some("Firouzja"));
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].old_id, Some(2));
+    }
+}
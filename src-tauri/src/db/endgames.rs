@@ -0,0 +1,195 @@
+//! Endgame classification of stored games.
+//!
+//! Each game is classified by the material left at the end of its main line, e.g. `R vs R` or
+//! `B+N vs K`, with the stronger side first. Classifications live in the `GameEndgames` table and are
+//! computed incrementally, so existing databases are backfilled the first time they are needed.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use serde::Serialize;
+use shakmaty::{fen::Fen, Board, CastlingMode, Chess, Color, FromSetup, Position, Role};
+use specta::Type;
+
+use crate::{
+    db::{
+        encoding::extract_main_line_moves,
+        get_db_or_create,
+        schema::{game_endgames, games},
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+/// Largest non-pawn material (in pawns) a side may keep for the position to count as an endgame,
+/// enough for queen and minor piece or two rooks.
+const MAX_SIDE_MATERIAL: u32 = 13;
+const BACKFILL_BATCH: i64 = 5000;
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EndgameCount {
+    pub endgame: String,
+    pub count: i64,
+}
+
+fn role_value(role: Role) -> u32 {
+    match role {
+        Role::Knight | Role::Bishop => 3,
+        Role::Rook => 5,
+        Role::Queen => 9,
+        Role::Pawn | Role::King => 0,
+    }
+}
+
+/// Pieces of one side (`Q`, `R`, `B`, `N`), strongest first, and their value. Sides with only
+/// pawns are `P`, a bare king is `K`.
+fn side_signature(board: &Board, color: Color) -> (String, u32) {
+    let own = board.by_color(color);
+    let mut pieces = Vec::new();
+    let mut value = 0;
+    for role in [Role::Queen, Role::Rook, Role::Bishop, Role::Knight] {
+        for _ in (board.by_role(role) & own).into_iter() {
+            pieces.push(role.upper_char().to_string());
+            value += role_value(role);
+        }
+    }
+    let signature = if !pieces.is_empty() {
+        pieces.join("+")
+    } else if !(board.by_role(Role::Pawn) & own).is_empty() {
+        "P".to_string()
+    } else {
+        "K".to_string()
+    };
+    (signature, value)
+}
+
+/// Material configuration of an endgame position, or `None` if there is still too much material.
+pub fn classify_endgame(position: &Chess) -> Option<String> {
+    let board = position.board();
+    let white = side_signature(board, Color::White);
+    let black = side_signature(board, Color::Black);
+    if white.1 > MAX_SIDE_MATERIAL || black.1 > MAX_SIDE_MATERIAL {
+        return None;
+    }
+    let pawns = |color: Color| (board.by_role(Role::Pawn) & board.by_color(color)).count();
+    let (strong, weak) = if (white.1, pawns(Color::White)) >= (black.1, pawns(Color::Black)) {
+        (white.0, black.0)
+    } else {
+        (black.0, white.0)
+    };
+    Some(format!("{} vs {}", strong, weak))
+}
+
+fn final_position(moves: &[u8], fen: Option<&str>) -> Result<Chess> {
+    let mut position = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    for m in extract_main_line_moves(moves, Some(position.clone()))? {
+        position.play_unchecked(&m);
+    }
+    Ok(position)
+}
+
+/// Classify every game that has no entry in `GameEndgames` yet. Returns the number of games
+/// classified.
+pub(super) fn backfill_endgames(db: &mut SqliteConnection) -> Result<usize> {
+    let mut classified = 0;
+    loop {
+        let batch: Vec<(i32, Vec<u8>, Option<String>)> = games::table
+            .filter(games::id.ne_all(game_endgames::table.select(game_endgames::game_id)))
+            .select((games::id, games::moves, games::fen))
+            .order(games::id)
+            .limit(BACKFILL_BATCH)
+            .load(db)?;
+        if batch.is_empty() {
+            break;
+        }
+
+        let rows: Vec<_> = batch
+            .iter()
+            .map(|(id, moves, fen)| {
+                // Undecodable games are recorded as unclassified so they are not retried forever.
+                let endgame = final_position(moves, fen.as_deref())
+                    .ok()
+                    .and_then(|position| classify_endgame(&position));
+                (game_endgames::game_id.eq(*id), game_endgames::endgame.eq(endgame))
+            })
+            .collect();
+        db.transaction::<_, Error, _>(|db| {
+            diesel::insert_into(game_endgames::table)
+                .values(&rows)
+                .execute(db)?;
+            Ok(())
+        })?;
+        classified += rows.len();
+    }
+
+    if classified > 0 {
+        log::info!("Classified endgames of {} games", classified);
+    }
+    Ok(classified)
+}
+
+/// Classify the endgames of all games not classified yet.
+#[tauri::command]
+#[specta::specta]
+pub async fn classify_endgames(
+    file: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize> {
+    let _write = state.db_writes.lock(&file, "classify_endgames").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    backfill_endgames(db)
+}
+
+/// Number of games per endgame type, most common first.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_endgame_distribution(
+    file: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<EndgameCount>> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    // Reads do not wait for writes; games left unclassified are counted by a later call.
+    if let Some(_write) = state.db_writes.try_lock(&file, "get_endgame_distribution") {
+        backfill_endgames(db)?;
+    }
+
+    let rows: Vec<(Option<String>, i64)> = game_endgames::table
+        .filter(game_endgames::endgame.is_not_null())
+        .group_by(game_endgames::endgame)
+        .select((game_endgames::endgame, diesel::dsl::count_star()))
+        .order(diesel::dsl::count_star().desc())
+        .load(db)?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(endgame, count)| Some(EndgameCount { endgame: endgame?, count }))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(fen: &str) -> Option<String> {
+        let fen: Fen = fen.parse().unwrap();
+        let position: Chess = fen.into_position(CastlingMode::Chess960).unwrap();
+        classify_endgame(&position)
+    }
+
+    #[test]
+    fn test_classify_endgame() {
+        assert_eq!(classify("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), None);
+        assert_eq!(classify("4k3/r7/8/8/8/8/R4P2/4K3 w - - 0 40"), Some("R vs R".to_string()));
+        assert_eq!(classify("4k3/8/8/8/8/8/8/2BNK3 w - - 0 60"), Some("B+N vs K".to_string()));
+        assert_eq!(classify("4k3/3p4/8/8/8/8/8/1r2K3 w - - 0 50"), Some("R vs K".to_string()));
+        assert_eq!(classify("4k3/3p4/8/8/8/8/4P3/4K3 w - - 0 50"), Some("P vs P".to_string()));
+    }
+}
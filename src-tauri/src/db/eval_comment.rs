@@ -0,0 +1,113 @@
+//! `[%eval ...]` commands in PGN comments.
+//!
+//! Engine analysis and Lichess exports store the evaluation after a move as `[%eval 0.35]`, as
+//! `[%eval 0.35,20]` with the search depth, or as `[%eval #-3]` for a forced mate, always from
+//! white's point of view. Every feature reading stored evaluations parses them here, so they agree
+//! on what a score means.
+
+/// Evaluations are clamped so that a missed mate does not dominate averages, and mates count as
+/// the cap of the side that mates.
+pub(super) const EVAL_CAP_CP: f64 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum EvalScore {
+    Pawns(f64),
+    /// Moves to mate, negative when black mates. A mate on the board is written `#0` when white
+    /// mated and `#-0` when black did, so the side is kept apart from `moves`.
+    Mate {
+        moves: i32,
+        white_mates: bool,
+    },
+}
+
+impl EvalScore {
+    /// Score such as `0.35` or `#-3`.
+    pub(super) fn parse(score: &str) -> Option<Self> {
+        let score = score.trim();
+        match score.strip_prefix('#') {
+            Some(mate) => Some(Self::Mate {
+                moves: mate.parse().ok()?,
+                white_mates: !mate.starts_with('-'),
+            }),
+            None => score.parse().ok().map(Self::Pawns),
+        }
+    }
+
+    /// Centipawns clamped to `EVAL_CAP_CP`, mates counting as the cap of the side that mates.
+    pub(super) fn capped_cp(self) -> f64 {
+        match self {
+            Self::Pawns(pawns) => (pawns * 100.0).clamp(-EVAL_CAP_CP, EVAL_CAP_CP),
+            Self::Mate {
+                white_mates: true, ..
+            } => EVAL_CAP_CP,
+            Self::Mate { .. } => -EVAL_CAP_CP,
+        }
+    }
+}
+
+/// Score and depth, verbatim, of the `[%eval ...]` command in `comment`.
+pub(super) fn eval_fields(comment: &str) -> Option<(&str, Option<&str>)> {
+    let start = comment.find("[%eval")? + "[%eval".len();
+    let rest = &comment[start..];
+    let value = rest[..rest.find(']')?].trim();
+    let mut parts = value.splitn(2, ',');
+    let score = parts.next()?.trim();
+    let depth = parts.next().map(str::trim);
+    Some((score, depth))
+}
+
+/// Score of the `[%eval ...]` command in `comment`.
+pub(super) fn parse_eval_comment(comment: &str) -> Option<EvalScore> {
+    EvalScore::parse(eval_fields(comment)?.0)
+}
+
+/// Score of the `[%eval ...]` command in `comment` in centipawns, clamped to `EVAL_CAP_CP`.
+pub(super) fn eval_comment_cp(comment: &str) -> Option<f64> {
+    parse_eval_comment(comment).map(EvalScore::capped_cp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_fields() {
+        assert_eq!(
+            eval_fields("[%eval 0.17,23] [%clk 0:03:00]"),
+            Some(("0.17", Some("23")))
+        );
+        assert_eq!(eval_fields("[%eval #-2]"), Some(("#-2", None)));
+        assert_eq!(eval_fields("just a comment"), None);
+    }
+
+    #[test]
+    fn test_parse_eval_comment() {
+        assert_eq!(
+            parse_eval_comment("[%eval 0.35,20]"),
+            Some(EvalScore::Pawns(0.35))
+        );
+        assert_eq!(
+            parse_eval_comment("[%eval #-3]"),
+            Some(EvalScore::Mate {
+                moves: -3,
+                white_mates: false
+            })
+        );
+        assert_eq!(parse_eval_comment("[%eval abc]"), None);
+        assert_eq!(parse_eval_comment("no eval here"), None);
+    }
+
+    #[test]
+    fn test_eval_comment_cp() {
+        assert_eq!(eval_comment_cp("[%eval 0.35]"), Some(35.0));
+        assert_eq!(
+            eval_comment_cp("good move [%eval -1.2] [%clk 0:01:00]"),
+            Some(-120.0)
+        );
+        assert_eq!(eval_comment_cp("[%eval 25.0]"), Some(EVAL_CAP_CP));
+        assert_eq!(eval_comment_cp("[%eval #4]"), Some(EVAL_CAP_CP));
+        assert_eq!(eval_comment_cp("[%eval #-3]"), Some(-EVAL_CAP_CP));
+        assert_eq!(eval_comment_cp("[%eval #0]"), Some(EVAL_CAP_CP));
+        assert_eq!(eval_comment_cp("[%eval #-0]"), Some(-EVAL_CAP_CP));
+    }
+}
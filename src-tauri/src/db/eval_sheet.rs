@@ -0,0 +1,127 @@
+//! Export of a game's stored engine analysis as a CSV "eval sheet".
+//!
+//! Analysis is stored in the game itself: `[%eval score]` or `[%eval score,depth]` comments after
+//! each move, and engine alternatives as variations branching at the analyzed ply. The sheet has
+//! one row per main-line move so the data can be loaded into a spreadsheet; evaluations and the
+//! delimiter follow the user's regional number format.
+
+use std::{fs::File, path::PathBuf};
+
+use diesel::prelude::*;
+use serde::Serialize;
+use shakmaty::{fen::Fen, CastlingMode, Chess, Color, FromSetup, Position};
+
+use crate::{
+    db::{
+        eval_comment::eval_fields,
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode},
+        schema::games,
+        ConnectionOptions,
+    },
+    error::Result,
+    regional::current_format,
+    AppState,
+};
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct EvalSheetRow {
+    ply: u32,
+    move_number: u32,
+    color: &'static str,
+    san: String,
+    eval: String,
+    depth: String,
+    best_move: String,
+}
+
+fn eval_sheet_rows(moves: &[u8], fen: Option<&str>) -> Result<Vec<EvalSheetRow>> {
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let tree = GameTree::from_bytes(moves, Some(start.clone()))?;
+
+    let mut position = start;
+    let mut rows: Vec<EvalSheetRow> = Vec::new();
+    for node in tree.nodes() {
+        match node {
+            GameTreeNode::Move(san_plus) => {
+                let color = position.turn();
+                let move_number = position.fullmoves().get();
+                let m = san_plus.san.to_move(&position)?;
+                position.play_unchecked(&m);
+                rows.push(EvalSheetRow {
+                    ply: rows.len() as u32 + 1,
+                    move_number,
+                    color: match color {
+                        Color::White => "white",
+                        Color::Black => "black",
+                    },
+                    san: san_plus.to_string(),
+                    ..Default::default()
+                });
+            }
+            GameTreeNode::Comment(comment) => {
+                if let (Some(row), Some((eval, depth))) = (rows.last_mut(), eval_fields(comment)) {
+                    row.eval = eval.to_string();
+                    row.depth = depth.unwrap_or_default().to_string();
+                }
+            }
+            GameTreeNode::Variation(variation) => {
+                let Some(row) = rows.last_mut() else {
+                    continue;
+                };
+                if !row.best_move.is_empty() {
+                    continue;
+                }
+                if let Some(GameTreeNode::Move(best)) = variation
+                    .nodes()
+                    .iter()
+                    .find(|n| matches!(n, GameTreeNode::Move(_)))
+                {
+                    row.best_move = best.to_string();
+                }
+            }
+            GameTreeNode::Nag(_) => {}
+        }
+    }
+    Ok(rows)
+}
+
+/// Write ply, SAN, evaluation, depth and engine best move of every main-line move to `dest`.
+#[tauri::command]
+#[specta::specta]
+pub async fn export_analysis_csv(
+    file: PathBuf,
+    game_id: i32,
+    dest: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first(db)?;
+    let rows = eval_sheet_rows(&moves, fen.as_deref())?;
+
+    let format = current_format();
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(format.csv_delimiter())
+        .from_writer(File::create(&dest)?);
+    for row in &rows {
+        let row = EvalSheetRow {
+            eval: format.localize_decimal(&row.eval),
+            ..row.clone()
+        };
+        writer.serialize(&row).map_err(std::io::Error::from)?;
+    }
+    writer.flush()?;
+
+    log::info!("Exported {} analyzed moves to {}", rows.len(), dest.display());
+    Ok(())
+}
@@ -0,0 +1,278 @@
+//! Tournament timeline built from the games of a database.
+//!
+//! Games are grouped by event; each event gets the range of its game dates, the sites it was
+//! played at, its number of games and players and, for a given player, their score in it. With
+//! a player the timeline only holds the events they took part in, so a "my tournament history"
+//! view can be rendered straight from imported games.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::PathBuf,
+};
+
+use diesel::prelude::*;
+use serde::Serialize;
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        schema::{events, games, sites},
+        ConnectionOptions,
+    },
+    error::Result,
+    AppState,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EventResult {
+    pub games: i32,
+    pub wins: i32,
+    pub draws: i32,
+    pub losses: i32,
+    /// Points scored, a draw counting half a point.
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineEvent {
+    pub id: i32,
+    pub name: String,
+    /// First and last known game dates (`YYYY.MM.DD`, possibly with `??` parts).
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    /// Sites the games were played at, e.g. the city of the tournament.
+    pub sites: Vec<String>,
+    pub games: i32,
+    pub players: i32,
+    /// Score of the requested player, if any.
+    pub result: Option<EventResult>,
+}
+
+/// Event id and name, site name, date, white and black ids and result of a game.
+type TimelineRow = (
+    i32,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    i32,
+    i32,
+    Option<String>,
+);
+
+struct TimelineGame {
+    event_id: i32,
+    event: Option<String>,
+    site: Option<String>,
+    date: Option<String>,
+    white_id: i32,
+    black_id: i32,
+    result: Option<String>,
+}
+
+#[derive(Default)]
+struct EventAccumulator {
+    name: String,
+    dates: BTreeSet<String>,
+    sites: BTreeSet<String>,
+    games: i32,
+    players: HashSet<i32>,
+    result: Option<EventResult>,
+}
+
+/// Names that stand for a missing value rather than a real event or site.
+fn is_known(name: &str) -> bool {
+    let name = name.trim();
+    !name.is_empty() && name != "?" && name != "Unknown"
+}
+
+/// PGN dates with an unknown year (`????.??.??`) cannot be placed on the timeline.
+fn has_year(date: &str) -> bool {
+    date.len() >= 4 && date.as_bytes()[..4].iter().all(u8::is_ascii_digit)
+}
+
+fn record_result(result: &mut EventResult, outcome: Option<&str>, white: bool) {
+    result.games += 1;
+    match (outcome, white) {
+        (Some("1-0"), true) | (Some("0-1"), false) => {
+            result.wins += 1;
+            result.score += 1.0;
+        }
+        (Some("1-0"), false) | (Some("0-1"), true) => result.losses += 1,
+        (Some("1/2-1/2"), _) => {
+            result.draws += 1;
+            result.score += 0.5;
+        }
+        // Unfinished or unknown results only count as played games.
+        _ => {}
+    }
+}
+
+fn build_timeline(rows: Vec<TimelineGame>, player_id: Option<i32>) -> Vec<TimelineEvent> {
+    let mut events: BTreeMap<i32, EventAccumulator> = BTreeMap::new();
+    for row in rows {
+        let Some(name) = row.event.filter(|name| is_known(name)) else {
+            continue;
+        };
+        let event = events.entry(row.event_id).or_default();
+        event.name = name;
+        if let Some(date) = row.date.filter(|date| has_year(date)) {
+            event.dates.insert(date);
+        }
+        if let Some(site) = row.site.filter(|site| is_known(site)) {
+            event.sites.insert(site);
+        }
+        event.games += 1;
+        event.players.insert(row.white_id);
+        event.players.insert(row.black_id);
+        if let Some(player_id) = player_id {
+            if row.white_id == player_id || row.black_id == player_id {
+                record_result(
+                    event.result.get_or_insert_with(EventResult::default),
+                    row.result.as_deref(),
+                    row.white_id == player_id,
+                );
+            }
+        }
+    }
+
+    let mut timeline: Vec<TimelineEvent> = events
+        .into_iter()
+        .filter(|(_, event)| player_id.is_none() || event.result.is_some())
+        .map(|(id, event)| TimelineEvent {
+            id,
+            name: event.name,
+            start_date: event.dates.first().cloned(),
+            end_date: event.dates.last().cloned(),
+            sites: event.sites.into_iter().collect(),
+            games: event.games,
+            players: event.players.len() as i32,
+            result: event.result,
+        })
+        .collect();
+    // Chronological, undated events last.
+    timeline.sort_by(|a, b| match (&a.start_date, &b.start_date) {
+        (Some(a_date), Some(b_date)) => a_date.cmp(b_date),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.name.cmp(&b.name),
+    });
+    timeline
+}
+
+/// Events of a database in chronological order. With `player_id`, only the events the player
+/// took part in, each with the player's result.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_event_timeline(
+    file: PathBuf,
+    player_id: Option<i32>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<TimelineEvent>> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let mut query = games::table
+        .inner_join(events::table.on(games::event_id.eq(events::id)))
+        .inner_join(sites::table.on(games::site_id.eq(sites::id)))
+        .select((
+            games::event_id,
+            events::name,
+            sites::name,
+            games::date,
+            games::white_id,
+            games::black_id,
+            games::result,
+        ))
+        .into_boxed();
+    if let Some(player_id) = player_id {
+        query = query.filter(
+            games::event_id.eq_any(
+                games::table
+                    .filter(games::white_id.eq(player_id).or(games::black_id.eq(player_id)))
+                    .select(games::event_id),
+            ),
+        );
+    }
+    let rows: Vec<TimelineRow> = query.load(db)?;
+
+    let rows = rows
+        .into_iter()
+        .map(
+            |(event_id, event, site, date, white_id, black_id, result)| TimelineGame {
+                event_id,
+                event,
+                site,
+                date,
+                white_id,
+                black_id,
+                result,
+            },
+        )
+        .collect();
+    Ok(build_timeline(rows, player_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(event_id: i32, date: &str, white_id: i32, black_id: i32, result: &str) -> TimelineGame {
+        TimelineGame {
+            event_id,
+            event: Some(format!("Event {}", event_id)),
+            site: Some("Wijk aan Zee".to_string()),
+            date: Some(date.to_string()),
+            white_id,
+            black_id,
+            result: Some(result.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_build_timeline() {
+        let rows = vec![
+            game(2, "2024.01.20", 1, 2, "1-0"),
+            game(2, "2024.01.14", 3, 1, "1/2-1/2"),
+            game(2, "2024.01.15", 2, 3, "0-1"),
+            game(1, "2023.06.01", 1, 4, "0-1"),
+            game(3, "2022.03.01", 5, 6, "1-0"),
+        ];
+        let timeline = build_timeline(rows, Some(1));
+        assert_eq!(timeline.len(), 2);
+
+        let first = &timeline[0];
+        assert_eq!(first.name, "Event 1");
+        assert_eq!(first.result.as_ref().unwrap().losses, 1);
+
+        let second = &timeline[1];
+        assert_eq!(second.start_date.as_deref(), Some("2024.01.14"));
+        assert_eq!(second.end_date.as_deref(), Some("2024.01.20"));
+        assert_eq!(second.sites, vec!["Wijk aan Zee"]);
+        assert_eq!((second.games, second.players), (3, 3));
+        assert_eq!(
+            second.result,
+            Some(EventResult {
+                games: 2,
+                wins: 1,
+                draws: 1,
+                losses: 0,
+                score: 1.5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_skips_unknown_events() {
+        let mut unknown = game(0, "2024.01.01", 1, 2, "1-0");
+        unknown.event = Some("Unknown".to_string());
+        let mut undated = game(4, "????.??.??", 1, 2, "1-0");
+        undated.site = Some("?".to_string());
+        let timeline = build_timeline(vec![unknown, undated, game(5, "2020.01.01", 1, 2, "*")], None);
+        assert_eq!(timeline.len(), 2);
+        assert_eq!(timeline[0].id, 5);
+        assert_eq!(timeline[1].start_date, None);
+        assert!(timeline[1].sites.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+//! Move statistics from the Lichess opening explorer.
+//!
+//! `get_remote_explorer_stats` returns the moves of a position from the masters database, the
+//! Lichess games database or the games of one player, in the same `PositionStats` shape as a
+//! local database search, so the explorer panel can switch between the two.
+//!
+//! The explorer asks clients to send one request at a time and to wait a full minute after a
+//! `429`, so requests are serialized here and refused while backing off. Responses are cached by
+//! the shared HTTP layer; master games change rarely and are kept longer than the others.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use shakmaty::{fen::Fen, CastlingMode, Chess};
+use specta::Type;
+
+use crate::{
+    db::PositionStats,
+    error::{Error, Result},
+    http::Request,
+    oauth::lichess_token,
+    AppState,
+};
+
+const EXPLORER: &str = "https://explorer.lichess.ovh";
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum ExplorerColor {
+    White,
+    Black,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ExplorerSource {
+    Masters,
+    Lichess,
+    Player {
+        player: String,
+        color: ExplorerColor,
+    },
+}
+
+impl ExplorerSource {
+    fn cache_ttl(&self) -> Duration {
+        match self {
+            ExplorerSource::Masters => Duration::from_secs(24 * 60 * 60),
+            ExplorerSource::Lichess => Duration::from_secs(60 * 60),
+            ExplorerSource::Player { .. } => Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+static REQUEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+static BLOCKED_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+fn explorer_url(fen: &str, source: &ExplorerSource) -> Result<String> {
+    let url = match source {
+        ExplorerSource::Masters => {
+            Url::parse_with_params(&format!("{}/masters", EXPLORER), [("fen", fen)])
+        }
+        ExplorerSource::Lichess => {
+            Url::parse_with_params(&format!("{}/lichess", EXPLORER), [("fen", fen)])
+        }
+        ExplorerSource::Player { player, color } => {
+            let color = match color {
+                ExplorerColor::White => "white",
+                ExplorerColor::Black => "black",
+            };
+            Url::parse_with_params(
+                &format!("{}/player", EXPLORER),
+                [("player", player.as_str()), ("color", color), ("fen", fen)],
+            )
+        }
+    };
+    url.map(String::from)
+        .map_err(|e| Error::PackageManager(format!("Invalid explorer URL: {}", e)))
+}
+
+#[derive(Deserialize)]
+struct ExplorerMove {
+    san: String,
+    white: i32,
+    draws: i32,
+    black: i32,
+}
+
+#[derive(Deserialize)]
+struct ExplorerResponse {
+    moves: Vec<ExplorerMove>,
+}
+
+/// Parses an explorer response. The player endpoint streams NDJSON with a growing snapshot per
+/// line, of which the last one is complete.
+fn parse_response(body: &str) -> Result<Vec<PositionStats>> {
+    let last = body
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .last()
+        .ok_or_else(|| Error::PackageManager("Empty explorer response".to_string()))?;
+    let response: ExplorerResponse = serde_json::from_str(last)
+        .map_err(|e| Error::PackageManager(format!("Invalid explorer response: {}", e)))?;
+    Ok(response
+        .moves
+        .into_iter()
+        .map(|m| PositionStats {
+            move_: m.san,
+            white: m.white,
+            draw: m.draws,
+            black: m.black,
+        })
+        .collect())
+}
+
+fn check_backoff() -> Result<()> {
+    let mut blocked = BLOCKED_UNTIL.lock().unwrap_or_else(|e| e.into_inner());
+    match *blocked {
+        Some(until) if until > Instant::now() => Err(Error::HttpStatus(429)),
+        _ => {
+            *blocked = None;
+            Ok(())
+        }
+    }
+}
+
+/// Move statistics of a position from the Lichess opening explorer.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_remote_explorer_stats(
+    fen: String,
+    source: ExplorerSource,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<PositionStats>> {
+    let fen = fen.trim();
+    let parsed: Fen = fen
+        .parse()
+        .map_err(|e| Error::PackageManager(format!("Invalid FEN: {}", e)))?;
+    parsed
+        .into_position::<Chess>(CastlingMode::Chess960)
+        .map_err(|e| Error::PackageManager(format!("Invalid position: {}", e)))?;
+    if let ExplorerSource::Player { player, .. } = &source {
+        if player.trim().is_empty() {
+            return Err(Error::PackageManager("No player given".to_string()));
+        }
+    }
+
+    let mut request = Request::get(explorer_url(fen, &source)?).cache_for(source.cache_ttl());
+    if let Some(token) = lichess_token(&state) {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let _lock = REQUEST_LOCK.lock().await;
+    check_backoff()?;
+    let body = match request.text().await {
+        Err(Error::HttpStatus(429)) => {
+            log::warn!("Rate limited by the opening explorer, backing off");
+            *BLOCKED_UNTIL.lock().unwrap_or_else(|e| e.into_inner()) =
+                Some(Instant::now() + RATE_LIMIT_BACKOFF);
+            return Err(Error::HttpStatus(429));
+        }
+        result => result?,
+    };
+    parse_response(&body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn test_explorer_url() {
+        assert_eq!(
+            explorer_url(START, &ExplorerSource::Masters).unwrap(),
+            "https://explorer.lichess.ovh/masters?fen=rnbqkbnr%2Fpppppppp%2F8%2F8%2F8%2F8%2FPPPPPPPP%2FRNBQKBNR+w+KQkq+-+0+1"
+        );
+        let player = ExplorerSource::Player {
+            player: "DrNykterstein".to_string(),
+            color: ExplorerColor::Black,
+        };
+        assert!(explorer_url(START, &player).unwrap().starts_with(
+            "https://explorer.lichess.ovh/player?player=DrNykterstein&color=black&fen="
+        ));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let body = r#"{"white":10,"draws":5,"black":3,"moves":[{"uci":"e2e4","san":"e4","white":6,"draws":3,"black":2,"averageRating":2400}]}"#;
+        let stats = parse_response(body).unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].move_, "e4");
+        assert_eq!((stats[0].white, stats[0].draw, stats[0].black), (6, 3, 2));
+    }
+
+    #[test]
+    fn test_parse_streamed_response() {
+        let body =
+            "{\"moves\":[]}\n{\"moves\":[{\"san\":\"d4\",\"white\":1,\"draws\":0,\"black\":0}]}\n";
+        let stats = parse_response(body).unwrap();
+        assert_eq!(stats[0].move_, "d4");
+        assert!(parse_response("\n").is_err());
+    }
+}
@@ -0,0 +1,372 @@
+//! Import of engine analysis produced outside the app, e.g. by batch runs on a cloud machine.
+//!
+//! An analysis file holds one entry per analyzed position, identified by its ply: the number of
+//! main-line half-moves played from the game's start. An entry may carry an evaluation (`cp`,
+//! `mate` or an `eval` score such as `0.35` or `#-3`, from White's point of view like `[%eval]`
+//! comments), a `depth` and a `pv` of space-separated SAN or UCI moves. JSON files are an array
+//! of such objects; CSV files have a header row naming the columns.
+//!
+//! Evaluations are stored as `[%eval score,depth]` comments after the move leading to the
+//! position, replacing any previous evaluation. A PV becomes a variation of the move played from
+//! the position, unless it starts with that move or the game already has a variation starting
+//! with the same move.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use diesel::prelude::*;
+use serde::Deserialize;
+use shakmaty::{
+    fen::Fen, san::SanPlus, uci::UciMove, CastlingMode, Chess, FromSetup, Move, Position,
+};
+use specta::Type;
+use vampirc_uci::uci::ScoreValue;
+
+use crate::{
+    chess::BestMoves,
+    db::{
+        get_db_or_create,
+        move_data::update_move_data,
+        pgn::{GameTree, GameTreeNode},
+        schema::games,
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+#[derive(Debug, Clone, Copy, Deserialize, Type)]
+pub enum AnalysisFileFormat {
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "csv")]
+    Csv,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct AnalysisEntry {
+    ply: u32,
+    #[serde(default)]
+    eval: Option<String>,
+    #[serde(default)]
+    cp: Option<i32>,
+    #[serde(default)]
+    mate: Option<i32>,
+    #[serde(default)]
+    depth: Option<u32>,
+    #[serde(default)]
+    pv: Option<String>,
+}
+
+impl AnalysisEntry {
+    /// Entry for an engine line found for the position after `ply` half-moves.
+    pub(super) fn from_line(ply: u32, line: &BestMoves) -> Self {
+        let (cp, mate) = match line.score.value {
+            ScoreValue::Cp(cp) => (Some(cp), None),
+            ScoreValue::Mate(n) => (None, Some(n as i32)),
+        };
+        Self {
+            ply,
+            cp,
+            mate,
+            depth: Some(line.depth),
+            pv: Some(line.uci_moves.join(" ")),
+            ..Default::default()
+        }
+    }
+
+    /// Score in `[%eval]` notation: pawns with two decimals or `#n`.
+    fn score(&self) -> Option<String> {
+        if let Some(mate) = self.mate {
+            return Some(format!("#{}", mate));
+        }
+        if let Some(cp) = self.cp {
+            return Some(format!("{:.2}", cp as f64 / 100.0));
+        }
+        // Sheets written with a decimal comma are accepted as well.
+        let eval = self.eval.as_deref()?.trim().replace(',', ".");
+        let valid = match eval.strip_prefix('#') {
+            Some(mate) => mate.parse::<i32>().is_ok(),
+            None => eval.parse::<f64>().is_ok(),
+        };
+        valid.then_some(eval)
+    }
+
+    fn eval_comment(&self) -> Option<String> {
+        let score = self.score()?;
+        Some(match self.depth {
+            Some(depth) => format!("[%eval {},{}]", score, depth),
+            None => format!("[%eval {}]", score),
+        })
+    }
+}
+
+fn invalid_analysis(e: impl std::fmt::Display) -> Error {
+    Error::PackageManager(format!("Invalid analysis file: {}", e))
+}
+
+fn parse_entries(contents: &str, format: AnalysisFileFormat) -> Result<Vec<AnalysisEntry>> {
+    match format {
+        AnalysisFileFormat::Json => serde_json::from_str(contents).map_err(invalid_analysis),
+        AnalysisFileFormat::Csv => {
+            // Sheets exported with a decimal comma use semicolons between columns.
+            let header = contents.lines().next().unwrap_or_default();
+            let delimiter = if header.contains(';') { b';' } else { b',' };
+            csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .trim(csv::Trim::All)
+                .from_reader(contents.as_bytes())
+                .deserialize()
+                .map(|entry| entry.map_err(invalid_analysis))
+                .collect()
+        }
+    }
+}
+
+/// `comment` with its `[%eval ...]` replaced by `eval`, or `eval` prepended if it has none.
+fn set_eval(comment: &str, eval: &str) -> String {
+    if let Some(start) = comment.find("[%eval") {
+        if let Some(len) = comment[start..].find(']') {
+            return format!("{}{}{}", &comment[..start], eval, &comment[start + len + 1..]);
+        }
+    }
+    if comment.trim().is_empty() {
+        eval.to_string()
+    } else {
+        format!("{} {}", eval, comment)
+    }
+}
+
+/// The legal prefix of a PV given in SAN or UCI, starting from `position`.
+fn pv_line(position: &Chess, pv: &str) -> Vec<SanPlus> {
+    let mut position = position.clone();
+    let mut line = Vec::new();
+    for token in pv.split_whitespace() {
+        let san = token
+            .parse::<SanPlus>()
+            .ok()
+            .and_then(|san| san.san.to_move(&position).ok());
+        let m = san.or_else(|| {
+            UciMove::from_ascii(token.as_bytes())
+                .ok()
+                .and_then(|uci| uci.to_move(&position).ok())
+        });
+        let Some(m) = m else {
+            break;
+        };
+        line.push(SanPlus::from_move_and_play_unchecked(&mut position, &m));
+    }
+    line
+}
+
+fn first_move(tree: &GameTree, position: &Chess) -> Option<Move> {
+    tree.nodes().iter().find_map(|node| match node {
+        GameTreeNode::Move(san) => san.san.to_move(position).ok(),
+        _ => None,
+    })
+}
+
+/// Insert evaluations and PV variations into the main line. Returns the new tree and the number
+/// of moves that received analysis.
+pub(super) fn attach_to_tree(
+    tree: GameTree,
+    start: &Chess,
+    entries: &HashMap<u32, &AnalysisEntry>,
+) -> Result<(GameTree, usize)> {
+    let mut nodes = tree.into_nodes().into_iter().peekable();
+    let mut attached = GameTree::new();
+    let mut position = start.clone();
+    let mut ply = 0;
+    let mut analyzed = 0;
+
+    // Nodes before the first move, e.g. a game comment, are kept as they are.
+    while let Some(node) = nodes.next_if(|n| !matches!(n, GameTreeNode::Move(_))) {
+        attached.push(node);
+    }
+    while let Some(GameTreeNode::Move(san)) = nodes.next() {
+        let mut tail = Vec::new();
+        while let Some(node) = nodes.next_if(|n| !matches!(n, GameTreeNode::Move(_))) {
+            tail.push(node);
+        }
+        let before = position.clone();
+        let played = san.san.to_move(&position)?;
+        position.play_unchecked(&played);
+        ply += 1;
+        let mut changed = false;
+
+        if let Some(eval) = entries.get(&ply).and_then(|e| e.eval_comment()) {
+            match tail.iter_mut().find_map(|node| match node {
+                GameTreeNode::Comment(comment) => Some(comment),
+                _ => None,
+            }) {
+                Some(comment) => *comment = set_eval(comment, &eval),
+                None => {
+                    let index = tail
+                        .iter()
+                        .position(|n| !matches!(n, GameTreeNode::Nag(_)))
+                        .unwrap_or(tail.len());
+                    tail.insert(index, GameTreeNode::Comment(eval));
+                }
+            }
+            changed = true;
+        }
+
+        if let Some(pv) = entries.get(&(ply - 1)).and_then(|e| e.pv.as_deref()) {
+            let line = pv_line(&before, pv);
+            let first = line.first().and_then(|san| san.san.to_move(&before).ok());
+            let known = tail.iter().any(|node| match node {
+                GameTreeNode::Variation(variation) => first_move(variation, &before) == first,
+                _ => false,
+            });
+            if first.is_some() && first != Some(played) && !known {
+                let mut variation = GameTree::new();
+                for san in line {
+                    variation.push(GameTreeNode::Move(san));
+                }
+                tail.push(GameTreeNode::Variation(variation));
+                changed = true;
+            }
+        }
+
+        attached.push(GameTreeNode::Move(san));
+        for node in tail {
+            attached.push(node);
+        }
+        if changed {
+            analyzed += 1;
+        }
+    }
+    Ok((attached, analyzed))
+}
+
+/// Attach evaluations and PVs from an external analysis file (JSON or CSV) to a game and save
+/// it. Returns the number of moves that received analysis.
+#[tauri::command]
+#[specta::specta]
+pub async fn attach_external_analysis(
+    file: PathBuf,
+    game_id: i32,
+    path: PathBuf,
+    format: AnalysisFileFormat,
+    state: tauri::State<'_, AppState>,
+) -> Result<i32> {
+    let entries = parse_entries(&std::fs::read_to_string(&path)?, format)?;
+    let by_ply: HashMap<u32, &AnalysisEntry> = entries.iter().map(|e| (e.ply, e)).collect();
+
+    let _write = state.db_writes.lock(&file, "attach_external_analysis").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first(db)?;
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let tree = GameTree::from_bytes(&moves, Some(start.clone()))?;
+    let (tree, analyzed) = attach_to_tree(tree, &start, &by_ply)?;
+
+    if analyzed > 0 {
+        let mut bytes = Vec::new();
+        tree.encode(&mut bytes, Some(start));
+        diesel::update(games::table.filter(games::id.eq(game_id)))
+            .set(games::moves.eq(bytes))
+            .execute(db)?;
+        update_move_data(db, game_id, &tree)?;
+    }
+    log::info!(
+        "Attached analysis from {} to {} moves of game {}",
+        path.display(),
+        analyzed,
+        game_id
+    );
+    Ok(analyzed as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::NotationLocale;
+
+    fn tree(sans: &[&str]) -> GameTree {
+        let mut tree = GameTree::new();
+        for san in sans {
+            tree.push(GameTreeNode::Move(san.parse::<SanPlus>().unwrap()));
+        }
+        tree
+    }
+
+    fn movetext(tree: &GameTree) -> String {
+        let mut movetext = String::new();
+        tree.pretty_print_localized(&mut movetext, None, NotationLocale::English)
+            .unwrap();
+        movetext
+    }
+
+    #[test]
+    fn test_parse_entries() {
+        let json = r#"[{"ply": 1, "cp": 35, "depth": 20}, {"ply": 2, "mate": -3, "pv": "g1f3"}]"#;
+        let entries = parse_entries(json, AnalysisFileFormat::Json).unwrap();
+        assert_eq!(entries[0].eval_comment().as_deref(), Some("[%eval 0.35,20]"));
+        assert_eq!(entries[1].eval_comment().as_deref(), Some("[%eval #-3]"));
+
+        let csv = "ply;eval;depth;pv\n1;0,35;18;e7e5 g1f3\n2;;;\n";
+        let entries = parse_entries(csv, AnalysisFileFormat::Csv).unwrap();
+        assert_eq!(entries[0].eval_comment().as_deref(), Some("[%eval 0.35,18]"));
+        assert_eq!(entries[0].pv.as_deref(), Some("e7e5 g1f3"));
+        assert_eq!(entries[1].eval_comment(), None);
+
+        assert!(parse_entries("{}", AnalysisFileFormat::Json).is_err());
+    }
+
+    #[test]
+    fn test_set_eval() {
+        assert_eq!(set_eval("", "[%eval 0.1]"), "[%eval 0.1]");
+        assert_eq!(set_eval("good move", "[%eval 0.1]"), "[%eval 0.1] good move");
+        assert_eq!(
+            set_eval("[%clk 0:01:00] [%eval 2.0,10]", "[%eval 0.1]"),
+            "[%clk 0:01:00] [%eval 0.1]"
+        );
+    }
+
+    #[test]
+    fn test_pv_line() {
+        let line = pv_line(&Chess::default(), "e2e4 e5 g1f3 Zz9");
+        let sans: Vec<String> = line.iter().map(|san| san.to_string()).collect();
+        assert_eq!(sans, vec!["e4", "e5", "Nf3"]);
+    }
+
+    #[test]
+    fn test_attach_to_tree() {
+        let entries = [
+            AnalysisEntry {
+                ply: 1,
+                cp: Some(30),
+                pv: Some("c7c5 g1f3".to_string()),
+                ..Default::default()
+            },
+            AnalysisEntry {
+                ply: 2,
+                cp: Some(40),
+                // Same as the move played: no variation.
+                pv: Some("g1f3".to_string()),
+                ..Default::default()
+            },
+        ];
+        let by_ply = entries.iter().map(|e| (e.ply, e)).collect();
+        let (tree, analyzed) =
+            attach_to_tree(tree(&["e4", "e5", "Nf3"]), &Chess::default(), &by_ply).unwrap();
+        assert_eq!(analyzed, 2);
+        let movetext = movetext(&tree);
+        assert!(movetext.contains("[%eval 0.30]"), "{}", movetext);
+        assert!(movetext.contains("[%eval 0.40]"), "{}", movetext);
+        assert!(movetext.contains("c5"), "{}", movetext);
+        assert_eq!(tree.nodes().len(), 6);
+
+        // Attaching the same analysis again does not duplicate the variation.
+        let (tree, _) = attach_to_tree(tree, &Chess::default(), &by_ply).unwrap();
+        assert_eq!(tree.nodes().len(), 6);
+    }
+}
@@ -0,0 +1,178 @@
+//! Single-game `.pawn` files.
+//!
+//! A `.pawn` file is a zstd-compressed JSON document holding one game as complete PGN, so its
+//! variations, comments, NAGs and `[%eval ...]` annotations travel unchanged, together with
+//! diagrams of its key positions as SVG for previews. Like prep bundles it does not reference the
+//! source database, and `import_game_bundle` hands the game back to the frontend to open.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use shakmaty::{fen::Fen, CastlingMode, Chess, Color, Position};
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        key_positions::key_position_fens,
+        models::{Event, Game, Player, Site},
+        schema::{events, games, players, sites},
+        ConnectionOptions, PgnExportOptions, PgnFormat, PgnGame,
+    },
+    error::{Error, Result},
+    puzzle_export::board_svg,
+    AppState,
+};
+
+const BUNDLE_FORMAT: &str = "pawn-appetit-game";
+const BUNDLE_VERSION: u32 = 1;
+const COMPRESSION_LEVEL: i32 = 9;
+/// Number of key positions drawn into a bundle.
+const KEY_POSITION_COUNT: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyPositionImage {
+    pub fen: String,
+    /// Board diagram of the position from White's side.
+    pub svg: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GameBundle {
+    pub format: String,
+    pub version: u32,
+    pub created_at: String,
+    /// The game as complete PGN, headers and annotations included.
+    pub pgn: String,
+    pub key_positions: Vec<KeyPositionImage>,
+}
+
+fn key_position_image(fen: String) -> Result<KeyPositionImage> {
+    let position: Chess = fen.parse::<Fen>()?.into_position(CastlingMode::Chess960)?;
+    Ok(KeyPositionImage {
+        svg: board_svg(position.board(), Color::White),
+        fen,
+    })
+}
+
+fn write_bundle(bundle: &GameBundle, dest: &Path) -> Result<()> {
+    let writer = BufWriter::new(File::create(dest)?);
+    let mut encoder = zstd::Encoder::new(writer, COMPRESSION_LEVEL)?;
+    serde_json::to_writer(&mut encoder, bundle)
+        .map_err(|e| Error::PackageManager(format!("Failed to write game bundle: {}", e)))?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn read_bundle(path: &Path) -> Result<GameBundle> {
+    let decoder = zstd::Decoder::new(BufReader::new(File::open(path)?))?;
+    let bundle: GameBundle = serde_json::from_reader(decoder)
+        .map_err(|e| Error::PackageManager(format!("Invalid game bundle: {}", e)))?;
+
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(Error::UnsupportedFileFormat(bundle.format));
+    }
+    if bundle.version > BUNDLE_VERSION {
+        return Err(Error::PackageManager(format!(
+            "Game bundle version {} is newer than supported version {}",
+            bundle.version, BUNDLE_VERSION
+        )));
+    }
+    Ok(bundle)
+}
+
+/// Writes game `game_id` of `file` with its annotations and key position diagrams to `dest`.
+#[tauri::command]
+#[specta::specta]
+pub async fn export_game_bundle(
+    file: PathBuf,
+    game_id: i32,
+    dest: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let (white_players, black_players) = diesel::alias!(players as white, players as black);
+    let row: (Game, Player, Player, Event, Site) = games::table
+        .inner_join(white_players.on(games::white_id.eq(white_players.field(players::id))))
+        .inner_join(black_players.on(games::black_id.eq(black_players.field(players::id))))
+        .inner_join(events::table.on(games::event_id.eq(events::id)))
+        .inner_join(sites::table.on(games::site_id.eq(sites::id)))
+        .filter(games::id.eq(game_id))
+        .first(db)?;
+
+    let key_positions = key_position_fens(&row.0.moves, row.0.fen.as_deref(), KEY_POSITION_COUNT)?
+        .into_iter()
+        .map(key_position_image)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut pgn = Vec::new();
+    PgnGame::from_row(row, &PgnFormat::default(), &PgnExportOptions::default())?.write(&mut pgn)?;
+
+    let bundle = GameBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        pgn: String::from_utf8(pgn)?,
+        key_positions,
+    };
+    write_bundle(&bundle, &dest)?;
+
+    log::info!("Exported game {} to {}", game_id, dest.display());
+    Ok(())
+}
+
+/// Reads a `.pawn` file for the frontend to open its game.
+#[tauri::command]
+#[specta::specta]
+pub async fn import_game_bundle(path: PathBuf) -> Result<GameBundle> {
+    read_bundle(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle() -> GameBundle {
+        GameBundle {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            created_at: "2024-01-01T00:00:00+00:00".to_string(),
+            pgn: "[White \"A\"]\n[Black \"B\"]\n\n1. e4 { [%eval 0.3] } (1. d4 $1) 1... e5 *\n"
+                .to_string(),
+            key_positions: vec![key_position_image(
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".to_string(),
+            )
+            .unwrap()],
+        }
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let dest = std::env::temp_dir().join(format!("{}.pawn", uuid::Uuid::new_v4()));
+        let bundle = bundle();
+        write_bundle(&bundle, &dest).unwrap();
+        let read = read_bundle(&dest);
+        std::fs::remove_file(&dest).unwrap();
+        assert_eq!(read.unwrap(), bundle);
+        assert!(bundle.key_positions[0].svg.starts_with("<svg"));
+    }
+
+    #[test]
+    fn test_rejects_other_formats() {
+        let dest = std::env::temp_dir().join(format!("{}.pawn", uuid::Uuid::new_v4()));
+        let mut bundle = bundle();
+        bundle.format = "pawn-appetit-prep".to_string();
+        write_bundle(&bundle, &dest).unwrap();
+        let read = read_bundle(&dest);
+        std::fs::remove_file(&dest).unwrap();
+        assert!(matches!(read, Err(Error::UnsupportedFileFormat(_))));
+    }
+}
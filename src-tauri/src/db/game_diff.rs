@@ -0,0 +1,145 @@
+//! Move-by-move comparison of two games, e.g. two games played in the same opening.
+//!
+//! The main lines are decoded and compared move by move; the result is the shared prefix, the
+//! position where the games part ways and how each game continues from there.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use serde::Serialize;
+use shakmaty::{
+    fen::Fen, san::SanPlus, CastlingMode, Chess, EnPassantMode, FromSetup, Move, Position,
+};
+use specta::Type;
+
+use crate::{
+    db::{encoding::extract_main_line_moves, get_db_or_create, schema::games, ConnectionOptions},
+    error::Result,
+    AppState,
+};
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GameComparison {
+    /// Whether both games start from the same position. If not, nothing is shared.
+    pub same_start: bool,
+    /// Moves (SAN) both games have in common.
+    pub common: Vec<String>,
+    /// Number of half-moves in `common`, i.e. the ply where the games diverge.
+    pub divergence_ply: u32,
+    /// Position after the common moves (the first game's start position if nothing is shared).
+    pub fen: String,
+    /// Continuation of the first game after the divergence.
+    pub continuation_a: Vec<String>,
+    /// Continuation of the second game after the divergence.
+    pub continuation_b: Vec<String>,
+}
+
+fn to_san(mut position: Chess, moves: &[Move]) -> Vec<String> {
+    moves
+        .iter()
+        .map(|m| SanPlus::from_move_and_play_unchecked(&mut position, m).to_string())
+        .collect()
+}
+
+fn compare_lines(start_a: Chess, a: &[Move], start_b: Chess, b: &[Move]) -> GameComparison {
+    let same_start = start_a.clone().into_setup(EnPassantMode::Legal)
+        == start_b.clone().into_setup(EnPassantMode::Legal);
+    let shared = if same_start {
+        a.iter().zip(b).take_while(|(x, y)| x == y).count()
+    } else {
+        0
+    };
+
+    let mut position = start_a;
+    let common = to_san(position.clone(), &a[..shared]);
+    for m in &a[..shared] {
+        position.play_unchecked(m);
+    }
+    let fen = Fen::from_position(position.clone(), EnPassantMode::Legal).to_string();
+    let continuation_b = if same_start {
+        to_san(position.clone(), &b[shared..])
+    } else {
+        to_san(start_b, b)
+    };
+
+    GameComparison {
+        same_start,
+        common,
+        divergence_ply: shared as u32,
+        fen,
+        continuation_a: to_san(position, &a[shared..]),
+        continuation_b,
+    }
+}
+
+fn load_main_line(db: &mut SqliteConnection, game_id: i32) -> Result<(Chess, Vec<Move>)> {
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first(db)?;
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let moves = extract_main_line_moves(&moves, Some(start.clone()))?;
+    Ok((start, moves))
+}
+
+/// Common prefix, divergence point and continuations of two games of the same database.
+#[tauri::command]
+#[specta::specta]
+pub async fn diff_games(
+    file: PathBuf,
+    game_id_a: i32,
+    game_id_b: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<GameComparison> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let (start_a, a) = load_main_line(db, game_id_a)?;
+    let (start_b, b) = load_main_line(db, game_id_b)?;
+    Ok(compare_lines(start_a, &a, start_b, &b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::san::San;
+
+    fn line(sans: &[&str]) -> Vec<Move> {
+        let mut position = Chess::default();
+        sans.iter()
+            .map(|san| {
+                let m = san.parse::<San>().unwrap().to_move(&position).unwrap();
+                position.play_unchecked(&m);
+                m
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compare_lines() {
+        let a = line(&["e4", "c5", "Nf3", "d6", "d4"]);
+        let b = line(&["e4", "c5", "Nf3", "Nc6", "Bb5"]);
+        let diff = compare_lines(Chess::default(), &a, Chess::default(), &b);
+        assert!(diff.same_start);
+        assert_eq!(diff.common, vec!["e4", "c5", "Nf3"]);
+        assert_eq!(diff.divergence_ply, 3);
+        assert_eq!(diff.continuation_a, vec!["d6", "d4"]);
+        assert_eq!(diff.continuation_b, vec!["Nc6", "Bb5"]);
+        assert!(diff.fen.ends_with("b KQkq - 1 2"), "{}", diff.fen);
+    }
+
+    #[test]
+    fn test_one_game_extends_the_other() {
+        let a = line(&["d4", "d5"]);
+        let b = line(&["d4", "d5", "c4"]);
+        let diff = compare_lines(Chess::default(), &a, Chess::default(), &b);
+        assert_eq!(diff.divergence_ply, 2);
+        assert!(diff.continuation_a.is_empty());
+        assert_eq!(diff.continuation_b, vec!["c4"]);
+    }
+}
@@ -0,0 +1,328 @@
+//! Search across every registered games database at once, for the command palette.
+//!
+//! The query is split into words and each word has to match somewhere: in the player or event
+//! name for those categories, and in the players, event, site or date of a game for the games.
+//! Every category is bounded by `CATEGORY_LIMIT` over all databases together, which are searched
+//! in path order until the category is full. Opening names come from the built-in opening book.
+
+use std::path::{Path, PathBuf};
+
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Integer, Nullable, Text},
+};
+use serde::Serialize;
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        location::{database_files, DatabaseKind},
+        schema::{events, players},
+        ConnectionOptions,
+    },
+    error::Result,
+    opening::{search_opening_name, OutOpening},
+    AppState,
+};
+
+/// Maximum number of results of each category.
+const CATEGORY_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerHit {
+    pub db: PathBuf,
+    pub id: i32,
+    pub name: String,
+    pub elo: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EventHit {
+    pub db: PathBuf,
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Type, QueryableByName)]
+#[serde(rename_all = "camelCase")]
+pub struct GameHit {
+    /// Filled in after loading; the query selects an empty placeholder.
+    #[diesel(sql_type = Text, deserialize_as = String)]
+    pub db: PathBuf,
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub white: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub black: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub event: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub date: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub result: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalSearchResults {
+    pub players: Vec<PlayerHit>,
+    pub events: Vec<EventHit>,
+    pub openings: Vec<OutOpening>,
+    /// Most recently added games first within each database.
+    pub games: Vec<GameHit>,
+}
+
+/// `LIKE` patterns matching each word of the query anywhere, with `\` as escape character.
+fn like_patterns(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .map(|word| {
+            let escaped = word
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        })
+        .collect()
+}
+
+fn search_players(
+    db: &mut SqliteConnection,
+    patterns: &[String],
+    limit: usize,
+) -> Result<Vec<(i32, Option<String>, Option<i32>)>> {
+    let mut query = players::table
+        .select((players::id, players::name, players::elo))
+        .into_boxed();
+    for pattern in patterns {
+        query = query.filter(players::name.like(pattern.clone()).escape('\\'));
+    }
+    Ok(query.order(players::name).limit(limit as i64).load(db)?)
+}
+
+fn search_events(
+    db: &mut SqliteConnection,
+    patterns: &[String],
+    limit: usize,
+) -> Result<Vec<(i32, Option<String>)>> {
+    let mut query = events::table
+        .select((events::id, events::name))
+        .into_boxed();
+    for pattern in patterns {
+        query = query.filter(events::name.like(pattern.clone()).escape('\\'));
+    }
+    Ok(query.order(events::name).limit(limit as i64).load(db)?)
+}
+
+const GAME_FIELDS: [&str; 5] = ["w.Name", "b.Name", "e.Name", "s.Name", "g.Date"];
+
+fn games_sql(words: usize) -> String {
+    let word_clause = format!(
+        "({})",
+        GAME_FIELDS
+            .iter()
+            .map(|field| format!("{} LIKE ? ESCAPE '\\'", field))
+            .collect::<Vec<_>>()
+            .join(" OR ")
+    );
+    format!(
+        "SELECT '' AS db, g.ID AS id, w.Name AS white, b.Name AS black, e.Name AS event,
+                g.Date AS date, g.Result AS result
+         FROM Games g
+         LEFT JOIN Players w ON w.ID = g.WhiteID
+         LEFT JOIN Players b ON b.ID = g.BlackID
+         LEFT JOIN Events e ON e.ID = g.EventID
+         LEFT JOIN Sites s ON s.ID = g.SiteID
+         WHERE {}
+         ORDER BY g.ID DESC
+         LIMIT ?",
+        vec![word_clause; words].join(" AND ")
+    )
+}
+
+fn search_games(
+    db: &mut SqliteConnection,
+    patterns: &[String],
+    limit: usize,
+) -> Result<Vec<GameHit>> {
+    let mut query = sql_query(games_sql(patterns.len())).into_boxed();
+    for pattern in patterns {
+        for _ in GAME_FIELDS {
+            query = query.bind::<Text, _>(pattern.clone());
+        }
+    }
+    Ok(query.bind::<BigInt, _>(limit as i64).load(db)?)
+}
+
+/// Adds the matches of one database to `results`, up to the room left in each category.
+fn search_database(
+    db: &mut SqliteConnection,
+    path: &Path,
+    patterns: &[String],
+    results: &mut GlobalSearchResults,
+) -> Result<()> {
+    let room = CATEGORY_LIMIT.saturating_sub(results.players.len());
+    if room > 0 {
+        for (id, name, elo) in search_players(db, patterns, room)? {
+            results.players.push(PlayerHit {
+                db: path.to_path_buf(),
+                id,
+                name: name.unwrap_or_default(),
+                elo,
+            });
+        }
+    }
+
+    let room = CATEGORY_LIMIT.saturating_sub(results.events.len());
+    if room > 0 {
+        for (id, name) in search_events(db, patterns, room)? {
+            results.events.push(EventHit {
+                db: path.to_path_buf(),
+                id,
+                name: name.unwrap_or_default(),
+            });
+        }
+    }
+
+    let room = CATEGORY_LIMIT.saturating_sub(results.games.len());
+    if room > 0 {
+        for mut game in search_games(db, patterns, room)? {
+            game.db = path.to_path_buf();
+            results.games.push(game);
+        }
+    }
+    Ok(())
+}
+
+/// Players, events, openings and games matching `query_text` in all registered databases, grouped
+/// by category for the command palette.
+#[tauri::command]
+#[specta::specta]
+pub async fn global_search(
+    query_text: String,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<GlobalSearchResults> {
+    let patterns = like_patterns(&query_text);
+    if patterns.is_empty() {
+        return Ok(GlobalSearchResults::default());
+    }
+
+    let mut results = GlobalSearchResults::default();
+    for path in database_files(&app, DatabaseKind::Games)? {
+        let searched =
+            get_db_or_create(&state, path.to_str().unwrap(), ConnectionOptions::default())
+                .and_then(|mut db| search_database(&mut db, &path, &patterns, &mut results));
+        if let Err(e) = searched {
+            log::warn!("Skipping database {} in search: {}", path.display(), e);
+        }
+    }
+
+    results.openings = search_opening_name(query_text.trim().to_string()).await?;
+    results.openings.truncate(CATEGORY_LIMIT);
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::connection::SimpleConnection;
+
+    fn test_db() -> SqliteConnection {
+        let mut db = SqliteConnection::establish(":memory:").unwrap();
+        db.batch_execute(
+            "CREATE TABLE Players (ID INTEGER PRIMARY KEY, Name TEXT, Elo INTEGER);
+            CREATE TABLE Events (ID INTEGER PRIMARY KEY, Name TEXT);
+            CREATE TABLE Sites (ID INTEGER PRIMARY KEY, Name TEXT);
+            CREATE TABLE Games (
+                ID INTEGER PRIMARY KEY, EventID INTEGER, SiteID INTEGER, Date TEXT,
+                WhiteID INTEGER, BlackID INTEGER, Result TEXT
+            );
+            INSERT INTO Players VALUES (1, 'Carlsen, Magnus', 2830), (2, 'Caruana, Fabiano', 2800),
+                (3, 'Nepomniachtchi, Ian', 2770);
+            INSERT INTO Events VALUES (1, 'Norway Chess 2024'), (2, 'World Championship 100%');
+            INSERT INTO Sites VALUES (1, 'Stavanger');
+            INSERT INTO Games VALUES (1, 1, 1, '2024.05.27', 1, 2, '1-0'),
+                (2, 1, 1, '2024.05.28', 3, 1, '1/2-1/2'),
+                (3, 2, NULL, '2023.04.09', 3, 2, '0-1');",
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_like_patterns() {
+        assert_eq!(
+            like_patterns("  carl 100%_ "),
+            vec!["%carl%", "%100\\%\\_%"]
+        );
+        assert!(like_patterns(" ").is_empty());
+    }
+
+    #[test]
+    fn test_search_database() {
+        let mut db = test_db();
+        let path = PathBuf::from("test.db3");
+        let mut results = GlobalSearchResults::default();
+        search_database(&mut db, &path, &like_patterns("car"), &mut results).unwrap();
+        assert_eq!(results.players.len(), 2);
+        assert_eq!(results.players[0].name, "Carlsen, Magnus");
+        assert!(results.events.is_empty());
+        assert_eq!(
+            results.games.iter().map(|g| g.id).collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+        assert_eq!(results.games[0].db, path);
+
+        let mut results = GlobalSearchResults::default();
+        search_database(
+            &mut db,
+            &path,
+            &like_patterns("carlsen stavanger"),
+            &mut results,
+        )
+        .unwrap();
+        assert!(results.players.is_empty());
+        assert_eq!(results.games.len(), 2);
+
+        let mut results = GlobalSearchResults::default();
+        search_database(&mut db, &path, &like_patterns("100%"), &mut results).unwrap();
+        assert_eq!(results.events.len(), 1);
+        assert_eq!(
+            results.games[0].event.as_deref(),
+            Some("World Championship 100%")
+        );
+    }
+
+    #[test]
+    fn test_category_limit() {
+        let mut db = test_db();
+        let mut results = GlobalSearchResults::default();
+        results.games = vec![
+            GameHit {
+                db: PathBuf::new(),
+                id: 0,
+                white: None,
+                black: None,
+                event: None,
+                date: None,
+                result: None,
+            };
+            CATEGORY_LIMIT - 1
+        ];
+        search_database(
+            &mut db,
+            Path::new("a.db3"),
+            &like_patterns("2024"),
+            &mut results,
+        )
+        .unwrap();
+        assert_eq!(results.games.len(), CATEGORY_LIMIT);
+        assert_eq!(results.games[CATEGORY_LIMIT - 1].id, 2);
+    }
+}
@@ -0,0 +1,183 @@
+//! Critical positions of a game, used as thumbnails in game lists and reports.
+//!
+//! With stored analysis (`[%eval ...]` comments) the moments are the moves with the largest
+//! evaluation swings. Unanalyzed games fall back to the moves that changed the material balance
+//! the most once the exchange settled.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use shakmaty::{fen::Fen, CastlingMode, Chess, EnPassantMode, FromSetup, Position, Role};
+
+use crate::{
+    db::{
+        eval_comment::eval_comment_cp,
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode},
+        schema::games,
+        ConnectionOptions,
+    },
+    error::Result,
+    AppState,
+};
+
+/// Plies over which a material change is measured, so a capture and its recapture cancel out.
+const EXCHANGE_PLIES: usize = 2;
+
+struct Ply {
+    /// Position before the move.
+    fen: String,
+    /// Material balance (white minus black, in pawns) before the move.
+    balance: i32,
+    eval_before: Option<f64>,
+    eval_after: Option<f64>,
+}
+
+fn material_balance(position: &Chess) -> i32 {
+    let board = position.board();
+    [
+        (Role::Pawn, 1),
+        (Role::Knight, 3),
+        (Role::Bishop, 3),
+        (Role::Rook, 5),
+        (Role::Queen, 9),
+    ]
+    .iter()
+    .map(|(role, value)| {
+        let pieces = board.by_role(*role);
+        let white = (pieces & board.white()).count() as i32;
+        let black = (pieces & board.black()).count() as i32;
+        (white - black) * value
+    })
+    .sum()
+}
+
+/// Indices of the `count` most critical plies, in game order.
+fn critical_plies(plies: &[Ply], final_balance: i32, count: usize) -> Vec<usize> {
+    let analyzed = plies
+        .iter()
+        .any(|p| p.eval_before.is_some() && p.eval_after.is_some());
+
+    let mut scored: Vec<(usize, f64)> = plies
+        .iter()
+        .enumerate()
+        .filter_map(|(i, ply)| {
+            let score = if analyzed {
+                (ply.eval_after? - ply.eval_before?).abs()
+            } else {
+                let settled = plies
+                    .get(i + EXCHANGE_PLIES)
+                    .map_or(final_balance, |p| p.balance);
+                (settled - ply.balance).abs() as f64
+            };
+            (score > 0.0).then_some((i, score))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut selected: Vec<usize> = scored.into_iter().take(count).map(|(i, _)| i).collect();
+    selected.sort_unstable();
+    selected
+}
+
+/// FENs of the `count` most critical moments of the game stored as `moves` from `fen`. Games
+/// without any critical moment yield their final position.
+pub(super) fn key_position_fens(
+    moves: &[u8],
+    fen: Option<&str>,
+    count: usize,
+) -> Result<Vec<String>> {
+    let mut position = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let tree = GameTree::from_bytes(moves, Some(position.clone()))?;
+
+    let mut plies: Vec<Ply> = Vec::new();
+    // Evaluation of the current position, carried over as the next move's baseline.
+    let mut last_eval: Option<f64> = None;
+    for node in tree.nodes() {
+        match node {
+            GameTreeNode::Move(san_plus) => {
+                let m = san_plus.san.to_move(&position)?;
+                plies.push(Ply {
+                    fen: Fen::from_position(position.clone(), EnPassantMode::Legal).to_string(),
+                    balance: material_balance(&position),
+                    eval_before: last_eval.take(),
+                    eval_after: None,
+                });
+                position.play_unchecked(&m);
+            }
+            GameTreeNode::Comment(comment) => {
+                if let (Some(eval), Some(ply)) = (eval_comment_cp(comment), plies.last_mut()) {
+                    ply.eval_after = Some(eval);
+                    last_eval = Some(eval);
+                }
+            }
+            GameTreeNode::Nag(_) | GameTreeNode::Variation(_) => {}
+        }
+    }
+
+    let fens: Vec<String> = critical_plies(&plies, material_balance(&position), count)
+        .into_iter()
+        .map(|i| plies[i].fen.clone())
+        .collect();
+    if fens.is_empty() && count > 0 {
+        return Ok(vec![Fen::from_position(position, EnPassantMode::Legal).to_string()]);
+    }
+    Ok(fens)
+}
+
+/// FENs of the `count` most critical moments of a game. Games without any critical moment yield
+/// their final position.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_game_key_positions(
+    file: PathBuf,
+    game_id: i32,
+    count: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<String>> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first(db)?;
+    key_position_fens(&moves, fen.as_deref(), count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ply(balance: i32, eval_before: Option<f64>, eval_after: Option<f64>) -> Ply {
+        Ply {
+            fen: String::new(),
+            balance,
+            eval_before,
+            eval_after,
+        }
+    }
+
+    #[test]
+    fn test_critical_plies_by_eval() {
+        let plies = vec![
+            ply(0, None, Some(20.0)),
+            ply(0, Some(20.0), Some(-300.0)),
+            ply(0, Some(-300.0), Some(-280.0)),
+            ply(0, Some(-280.0), Some(400.0)),
+        ];
+        assert_eq!(critical_plies(&plies, 0, 2), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_critical_plies_by_material() {
+        // A knight trade on plies 0-1, then a free rook on ply 2.
+        let plies = vec![ply(0, None, None), ply(3, None, None), ply(0, None, None)];
+        assert_eq!(critical_plies(&plies, 5, 1), vec![2]);
+    }
+}
@@ -0,0 +1,162 @@
+//! Tolerant reading of hand-typed PGN.
+//!
+//! Games typed by hand or exported by sloppy tools often break the PGN grammar in small ways:
+//! typographic quotes in headers, castling typed with zeroes, move numbers without dots, stray
+//! punctuation between moves. The strict importer silently drops such games. In lenient mode
+//! `normalize_pgn` fixes these mistakes before parsing, and the `Importer` keeps games with an
+//! illegal move or no result instead of dropping them. Every fix is reported as an
+//! `ImportCorrection` so the user can check what was changed.
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use specta::Type;
+
+static CASTLING_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b[0oO]-[0oO](-[0oO])?\b").unwrap());
+static MOVE_NUMBER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(^|\s)(\d+)(\s+[A-Za-z])").unwrap());
+static STRAY_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[^A-Za-z0-9\s.\-+#=!?*/$()%]").unwrap());
+
+#[derive(Debug, Clone, PartialEq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCorrection {
+    /// Position of the game in the file, starting at 1.
+    pub game: u32,
+    pub message: String,
+}
+
+/// Fix the movetext outside comments. Returns the fixed text and the fixes made.
+fn fix_movetext(text: &str) -> (String, Vec<String>) {
+    let mut fixes = Vec::new();
+    let mut text = text.to_string();
+    if text.contains('½') {
+        text = text.replace('½', "1/2");
+        fixes.push("wrote \"½\" as \"1/2\"".to_string());
+    }
+
+    let castled = CASTLING_RE.replace_all(&text, |caps: &Captures| {
+        let fixed = if caps.get(1).is_some() { "O-O-O" } else { "O-O" };
+        if &caps[0] != fixed {
+            fixes.push(format!("read \"{}\" as \"{}\"", &caps[0], fixed));
+        }
+        fixed.to_string()
+    });
+    let text = castled.into_owned();
+
+    let numbered = MOVE_NUMBER_RE.replace_all(&text, "$1$2.$3");
+    if numbered != text {
+        fixes.push("added missing dots after move numbers".to_string());
+    }
+    let text = numbered.into_owned();
+
+    let stray: String = STRAY_RE.find_iter(&text).map(|m| m.as_str()).collect();
+    if !stray.is_empty() {
+        fixes.push(format!("removed stray characters \"{}\"", stray));
+    }
+    (STRAY_RE.replace_all(&text, "").into_owned(), fixes)
+}
+
+/// Fix common hand-typing mistakes in a PGN file.
+pub fn normalize_pgn(pgn: &str) -> (String, Vec<ImportCorrection>) {
+    let mut corrections = Vec::new();
+    let mut out = String::with_capacity(pgn.len());
+    let mut game = 0;
+    let mut in_headers = false;
+    let mut in_comment = false;
+
+    for (n, line) in pgn.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let is_header = !in_comment && trimmed.starts_with('[');
+        if is_header && !in_headers {
+            game += 1;
+        } else if !is_header && !trimmed.is_empty() && (in_headers || game == 0) {
+            // Games without headers start with their movetext.
+            game = game.max(1);
+        }
+        if !trimmed.is_empty() {
+            in_headers = is_header;
+        }
+
+        let mut fixes = Vec::new();
+        let mut line = line.to_string();
+        if line.contains(['“', '”', '„', '‘', '’']) {
+            line = line.replace(['“', '”', '„'], "\"").replace(['‘', '’'], "'");
+            fixes.push("replaced typographic quotes".to_string());
+        }
+
+        if is_header {
+            out.push_str(&line);
+        } else {
+            // Only the parts outside `{...}` comments are movetext.
+            let mut rest = line.as_str();
+            while !rest.is_empty() {
+                if in_comment {
+                    match rest.find('}') {
+                        Some(end) => {
+                            out.push_str(&rest[..=end]);
+                            rest = &rest[end + 1..];
+                            in_comment = false;
+                        }
+                        None => {
+                            out.push_str(rest);
+                            rest = "";
+                        }
+                    }
+                } else {
+                    let end = rest.find(['{', ';']).unwrap_or(rest.len());
+                    let (movetext, movetext_fixes) = fix_movetext(&rest[..end]);
+                    out.push_str(&movetext);
+                    fixes.extend(movetext_fixes);
+                    rest = &rest[end..];
+                    if rest.starts_with(';') {
+                        // Rest-of-line comment.
+                        out.push_str(rest);
+                        rest = "";
+                    } else if !rest.is_empty() {
+                        out.push('{');
+                        rest = &rest[1..];
+                        in_comment = true;
+                    }
+                }
+            }
+        }
+        out.push('\n');
+
+        corrections.extend(fixes.into_iter().map(|fix| ImportCorrection {
+            game: game.max(1),
+            message: format!("Line {}: {}", n + 1, fix),
+        }));
+    }
+    (out, corrections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fix_movetext() {
+        let (text, fixes) = fix_movetext("1 e4 e5 2 Nf3, Nc6 3.Bc4 Bc5 4.0-0 Nf6 5.d3 o-o-o ½-½");
+        assert_eq!(text, "1. e4 e5 2. Nf3 Nc6 3.Bc4 Bc5 4.O-O Nf6 5.d3 O-O-O 1/2-1/2");
+        assert_eq!(fixes.len(), 5, "{:?}", fixes);
+
+        let (text, fixes) = fix_movetext("1.e4 e5 2.Nf3 Nc6 1-0");
+        assert_eq!(text, "1.e4 e5 2.Nf3 Nc6 1-0");
+        assert!(fixes.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_pgn() {
+        let pgn = "[Event “Club night”]\n[White \"A\"]\n\n1 e4 {0-0 is fine here, isn't it} e5\n\n[Event \"B\"]\n\n1.d4 d5 2.c4 | *\n";
+        let (text, corrections) = normalize_pgn(pgn);
+        assert_eq!(
+            text,
+            "[Event \"Club night\"]\n[White \"A\"]\n\n1. e4 {0-0 is fine here, isn't it} e5\n\n[Event \"B\"]\n\n1.d4 d5 2.c4  *\n"
+        );
+        let games: Vec<u32> = corrections.iter().map(|c| c.game).collect();
+        assert_eq!(games, vec![1, 1, 2]);
+        assert!(corrections[2].message.starts_with("Line 8:"));
+    }
+}
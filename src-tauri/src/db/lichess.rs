@@ -0,0 +1,248 @@
+//! Incremental import of a Lichess account's games through the export API.
+//!
+//! The games are streamed as PGN, oldest first, into `{username}_lichess.db3` in the games
+//! database folder, the name the position search recognizes as an online database. The start time
+//! of the newest imported game is kept in the `LichessLastSyncedAt` row of the `Info` table, so
+//! the next sync only asks Lichess for the games played since. It is saved after every batch of
+//! games, so a sync cut short resumes where it stopped.
+
+use std::{path::PathBuf, time::Duration};
+
+use diesel::{connection::SimpleConnection, prelude::*};
+use futures_util::StreamExt;
+use pgn_reader::BufferedReader;
+use serde::Serialize;
+use specta::Type;
+
+use crate::{
+    db::{
+        core, get_db_or_create, insert_to_db,
+        location::{DatabaseKind, DatabaseRef},
+        pgn::{Importer, TempGame},
+        player_aggregates,
+        schema::info,
+        sources, update_info_counts, ConnectionOptions, INDEXES_SQL,
+    },
+    error::{Error, Result},
+    http::Request,
+    oauth::lichess_token,
+    progress::{TaskKind, TaskProgress},
+    AppState,
+};
+
+const LICHESS_API: &str = "https://lichess.org/api";
+const LAST_SYNCED_AT: &str = "LichessLastSyncedAt";
+/// Games of the export are separated by two blank lines.
+const GAME_SEPARATOR: &[u8] = b"\n\n\n";
+/// Buffered PGN above which the complete games received so far are imported.
+const BATCH_BYTES: usize = 1 << 20;
+/// Lichess streams a few dozen games per second, so a first sync of a big account takes a while.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LichessSyncSummary {
+    pub path: PathBuf,
+    pub games: i32,
+    /// Unix time in seconds of the newest game in the database, if any.
+    pub last_synced_at: Option<i64>,
+}
+
+/// Lichess usernames are letters, digits, `_` and `-`, and case-insensitive.
+fn normalize_username(username: &str) -> Result<String> {
+    let username = username.trim().to_lowercase();
+    if username.is_empty()
+        || !username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(Error::PackageManager(format!(
+            "Invalid Lichess username: {}",
+            username
+        )));
+    }
+    Ok(username)
+}
+
+fn export_url(username: &str, since: Option<i64>) -> String {
+    let mut url = format!(
+        "{}/games/user/{}?sort=dateAsc&clocks=true&evals=true&opening=true",
+        LICHESS_API, username
+    );
+    if let Some(since) = since {
+        // `since` is in milliseconds and inclusive; the importer drops the game it names.
+        url.push_str(&format!("&since={}", since * 1000));
+    }
+    url
+}
+
+/// Length of the complete games at the start of `pgn`, up to and including the last separator.
+fn complete_games_len(pgn: &[u8]) -> Option<usize> {
+    pgn.windows(GAME_SEPARATOR.len())
+        .rposition(|window| window == GAME_SEPARATOR)
+        .map(|start| start + GAME_SEPARATOR.len())
+}
+
+fn last_synced_at(db: &mut SqliteConnection) -> Option<i64> {
+    info::table
+        .filter(info::name.eq(LAST_SYNCED_AT))
+        .select(info::value)
+        .first::<Option<String>>(db)
+        .ok()
+        .flatten()
+        .and_then(|value| value.parse().ok())
+}
+
+fn set_last_synced_at(db: &mut SqliteConnection, timestamp: i64) -> Result<()> {
+    diesel::insert_into(info::table)
+        .values((
+            info::name.eq(LAST_SYNCED_AT),
+            info::value.eq(timestamp.to_string()),
+        ))
+        .on_conflict(info::name)
+        .do_update()
+        .set(info::value.eq(timestamp.to_string()))
+        .execute(db)?;
+    Ok(())
+}
+
+/// Imports the games of `pgn` and moves the sync point past them. Returns the games imported.
+fn import_batch(
+    db: &mut SqliteConnection,
+    pgn: &[u8],
+    since: &mut Option<i64>,
+    source: &str,
+) -> Result<i32> {
+    let mut importer = Importer::new(*since);
+    let games: Vec<TempGame> = BufferedReader::new_cursor(pgn)
+        .into_iter(&mut importer)
+        .flatten()
+        .flatten()
+        .collect();
+    if games.is_empty() {
+        return Ok(0);
+    }
+
+    let last_id = sources::last_game_id(db)?;
+    let newest = games.iter().filter_map(TempGame::timestamp).max();
+    db.transaction::<_, Error, _>(|db| {
+        for game in &games {
+            insert_to_db(db, game)?;
+        }
+        if let Some(newest) = newest.filter(|newest| Some(*newest) > *since) {
+            set_last_synced_at(db, newest)?;
+            *since = Some(newest);
+        }
+        Ok(())
+    })?;
+    sources::record_import(db, last_id, source)?;
+    Ok(games.len() as i32)
+}
+
+/// Downloads the games of `username` on Lichess played since the last sync into
+/// `{username}_lichess.db3`, creating it if needed.
+#[tauri::command]
+#[specta::specta]
+pub async fn sync_lichess_games(
+    username: String,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<LichessSyncSummary> {
+    let username = normalize_username(&username)?;
+    let path = DatabaseRef::Name(format!("{}_lichess.db3", username))
+        .resolve(&app, DatabaseKind::Games)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let _write = state.db_writes.lock(&path, "sync_lichess_games").await;
+    let job = state.jobs.start(
+        &app,
+        TaskKind::Import,
+        path.to_string_lossy(),
+        format!("Syncing the Lichess games of {}", username),
+    );
+    let needs_init = !path.exists();
+    let db = &mut get_db_or_create(&state, path.to_str().unwrap(), ConnectionOptions::default())?;
+    if needs_init {
+        core::init_db(
+            db,
+            &format!("{} (Lichess)", username),
+            &format!("Games of {} on Lichess", username),
+        )?;
+    }
+    let mut since = last_synced_at(db);
+
+    let mut request = Request::get(export_url(&username, since))
+        .header("Accept", "application/x-chess-pgn")
+        .timeout(EXPORT_TIMEOUT);
+    if let Some(token) = lichess_token(&state) {
+        // Authenticated exports are streamed faster.
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status().as_u16()));
+    }
+
+    let progress_id = path.to_string_lossy();
+    let source = format!("https://lichess.org/@/{}", username);
+    let mut games = 0;
+    let mut pgn: Vec<u8> = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        if job.is_cancelled() {
+            break;
+        }
+        pgn.extend_from_slice(&chunk?);
+        if pgn.len() < BATCH_BYTES {
+            continue;
+        }
+        if let Some(len) = complete_games_len(&pgn) {
+            games += import_batch(db, &pgn[..len], &mut since, &source)?;
+            pgn.drain(..len);
+            TaskProgress::new(TaskKind::Import, progress_id.clone(), -1.0)
+                .message(format!("{} games imported", games))
+                .send(&app);
+        }
+    }
+    if !job.is_cancelled() {
+        games += import_batch(db, &pgn, &mut since, &source)?;
+    }
+
+    if needs_init {
+        db.batch_execute(INDEXES_SQL)?;
+    }
+    update_info_counts(db)?;
+    player_aggregates::update_after_import(db)?;
+
+    TaskProgress::done(TaskKind::Import, progress_id)
+        .message(format!("{} games imported", games))
+        .send(&app);
+    Ok(LichessSyncSummary {
+        path,
+        games,
+        last_synced_at: since,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_url() {
+        assert_eq!(
+            export_url("thibault", None),
+            "https://lichess.org/api/games/user/thibault?sort=dateAsc&clocks=true&evals=true&opening=true"
+        );
+        assert!(export_url("thibault", Some(1_700_000_000)).ends_with("&since=1700000000000"));
+    }
+
+    #[test]
+    fn test_complete_games_len() {
+        let first = "[Event \"a\"]\n\n1. e4 e5 1-0\n\n\n";
+        let pgn = format!("{}[Event \"b\"]\n\n1. d4", first);
+        assert_eq!(complete_games_len(pgn.as_bytes()), Some(first.len()));
+        assert_eq!(complete_games_len(b"[Event \"b\"]\n\n1. d4"), None);
+    }
+}
@@ -0,0 +1,396 @@
+//! Write-ahead journal of games being recorded live.
+//!
+//! Every move of a game played against the engine, read from a board or entered by hand is
+//! appended to `live_games/<id>.jsonl` in the app data directory and synced to disk before the
+//! command returns, so a crash loses at most the move being entered. Journals left behind by a
+//! crash are listed by `list_live_games` and can be resumed or finished. Finishing a game turns
+//! the journal into PGN, imports it into a database through `insert_to_db` and removes it.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use diesel::Connection;
+use pgn_reader::BufferedReader;
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    fen::Fen, san::SanPlus, uci::UciMove, CastlingMode, Chess, EnPassantMode, FromSetup, Position,
+};
+use specta::Type;
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::{
+    db::{
+        get_db_or_create, insert_to_db, pgn::Importer, sources, update_info_counts,
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+const JOURNAL_DIR: &str = "live_games";
+
+/// Source recorded for games saved from a journal, see `get_import_sources`.
+const LIVE_GAME_SOURCE: &str = "live game";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveGameHeaders {
+    pub event: Option<String>,
+    pub site: Option<String>,
+    pub round: Option<String>,
+    pub white: Option<String>,
+    pub white_elo: Option<i32>,
+    pub black: Option<String>,
+    pub black_elo: Option<i32>,
+    pub time_control: Option<String>,
+    /// Starting position, if not the standard one.
+    pub fen: Option<String>,
+}
+
+/// One line of a journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum JournalEntry {
+    Start {
+        headers: LiveGameHeaders,
+        /// RFC 3339 time the game started.
+        started_at: String,
+    },
+    Move {
+        uci: String,
+    },
+    Takeback,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveGame {
+    pub id: String,
+    pub headers: LiveGameHeaders,
+    pub started_at: String,
+    /// Moves played so far, in SAN.
+    pub moves: Vec<String>,
+    pub fen: String,
+}
+
+fn journal_dir(app: &tauri::AppHandle) -> Result<PathBuf> {
+    Ok(app.path().resolve(JOURNAL_DIR, BaseDirectory::AppData)?)
+}
+
+fn journal_path(app: &tauri::AppHandle, id: &str) -> Result<PathBuf> {
+    // Ids are generated here; anything else could point outside the journal folder.
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(Error::PackageManager(format!(
+            "Invalid live game id: {}",
+            id
+        )));
+    }
+    Ok(journal_dir(app)?.join(format!("{}.jsonl", id)))
+}
+
+fn append_entry(path: &PathBuf, entry: &JournalEntry) -> Result<()> {
+    let line = serde_json::to_string(entry)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize journal entry: {}", e)))?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+fn start_position(headers: &LiveGameHeaders) -> Result<Chess> {
+    match headers.fen.as_deref() {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Ok(Chess::from_setup(fen.into(), CastlingMode::Chess960)?)
+        }
+        None => Ok(Chess::default()),
+    }
+}
+
+/// Replay the journal `content`. A line cut short by a crash ends the journal.
+fn replay(id: &str, content: &str) -> Result<LiveGame> {
+    let mut entries = content
+        .lines()
+        .map_while(|line| serde_json::from_str::<JournalEntry>(line).ok());
+    let Some(JournalEntry::Start {
+        headers,
+        started_at,
+    }) = entries.next()
+    else {
+        return Err(Error::PackageManager(format!(
+            "Live game {} has no start entry",
+            id
+        )));
+    };
+
+    let start = start_position(&headers)?;
+    let mut moves: Vec<(SanPlus, Chess)> = Vec::new();
+    for entry in entries {
+        match entry {
+            JournalEntry::Move { uci } => {
+                let position = moves.last().map_or(&start, |(_, p)| p).clone();
+                let m = UciMove::from_ascii(uci.as_bytes())?.to_move(&position)?;
+                let san = SanPlus::from_move(position.clone(), &m);
+                let mut next = position;
+                next.play_unchecked(&m);
+                moves.push((san, next));
+            }
+            JournalEntry::Takeback => {
+                moves.pop();
+            }
+            JournalEntry::Start { .. } => {}
+        }
+    }
+
+    let position = moves.last().map_or(&start, |(_, p)| p);
+    Ok(LiveGame {
+        id: id.to_string(),
+        fen: Fen::from_position(position.clone(), EnPassantMode::Legal).to_string(),
+        headers,
+        started_at,
+        moves: moves.into_iter().map(|(san, _)| san.to_string()).collect(),
+    })
+}
+
+fn load(app: &tauri::AppHandle, id: &str) -> Result<LiveGame> {
+    replay(id, &fs::read_to_string(journal_path(app, id)?)?)
+}
+
+fn pgn_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The game as PGN, with `result` as its result.
+fn to_pgn(game: &LiveGame, result: &str) -> String {
+    let headers = &game.headers;
+    let date = game
+        .started_at
+        .get(..10)
+        .unwrap_or_default()
+        .replace('-', ".");
+    let mut tags: Vec<(&str, String)> = vec![
+        (
+            "Event",
+            headers.event.clone().unwrap_or_else(|| "?".to_string()),
+        ),
+        (
+            "Site",
+            headers.site.clone().unwrap_or_else(|| "?".to_string()),
+        ),
+        ("Date", date),
+        (
+            "Round",
+            headers.round.clone().unwrap_or_else(|| "?".to_string()),
+        ),
+        (
+            "White",
+            headers.white.clone().unwrap_or_else(|| "?".to_string()),
+        ),
+        (
+            "Black",
+            headers.black.clone().unwrap_or_else(|| "?".to_string()),
+        ),
+        ("Result", result.to_string()),
+    ];
+    if let Some(elo) = headers.white_elo {
+        tags.push(("WhiteElo", elo.to_string()));
+    }
+    if let Some(elo) = headers.black_elo {
+        tags.push(("BlackElo", elo.to_string()));
+    }
+    if let Some(time_control) = &headers.time_control {
+        tags.push(("TimeControl", time_control.clone()));
+    }
+    if let Some(fen) = &headers.fen {
+        tags.push(("SetUp", "1".to_string()));
+        tags.push(("FEN", fen.clone()));
+    }
+
+    let mut pgn: String = tags
+        .iter()
+        .map(|(key, value)| format!("[{} \"{}\"]\n", key, pgn_escape(value)))
+        .collect();
+    pgn.push('\n');
+    pgn.push_str(&game.moves.join(" "));
+    pgn.push(' ');
+    pgn.push_str(result);
+    pgn.push('\n');
+    pgn
+}
+
+/// Start recording a game. Returns the new game with its id.
+#[tauri::command]
+#[specta::specta]
+pub fn start_live_game(headers: LiveGameHeaders, app: tauri::AppHandle) -> Result<LiveGame> {
+    start_position(&headers)?;
+    fs::create_dir_all(journal_dir(&app)?)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    append_entry(
+        &journal_path(&app, &id)?,
+        &JournalEntry::Start {
+            headers,
+            started_at: chrono::Utc::now().to_rfc3339(),
+        },
+    )?;
+    load(&app, &id)
+}
+
+/// Record a move in UCI notation. Illegal moves are rejected and not journaled.
+#[tauri::command]
+#[specta::specta]
+pub fn record_live_move(id: String, uci: String, app: tauri::AppHandle) -> Result<LiveGame> {
+    let game = load(&app, &id)?;
+    let position: Chess =
+        Fen::from_ascii(game.fen.as_bytes())?.into_position(CastlingMode::Chess960)?;
+    UciMove::from_ascii(uci.as_bytes())?.to_move(&position)?;
+
+    append_entry(&journal_path(&app, &id)?, &JournalEntry::Move { uci })?;
+    load(&app, &id)
+}
+
+/// Take back the last recorded move.
+#[tauri::command]
+#[specta::specta]
+pub fn take_back_live_move(id: String, app: tauri::AppHandle) -> Result<LiveGame> {
+    append_entry(&journal_path(&app, &id)?, &JournalEntry::Takeback)?;
+    load(&app, &id)
+}
+
+/// Games whose journal is still open, e.g. because the app crashed while they were recorded.
+#[tauri::command]
+#[specta::specta]
+pub fn list_live_games(app: tauri::AppHandle) -> Result<Vec<LiveGame>> {
+    let dir = journal_dir(&app)?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut games = Vec::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if !path.extension().is_some_and(|ext| ext == "jsonl") {
+            continue;
+        }
+        let id = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        match load(&app, &id) {
+            Ok(game) => games.push(game),
+            Err(e) => log::warn!("Unreadable live game journal {}: {}", path.display(), e),
+        }
+    }
+    games.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+    Ok(games)
+}
+
+/// Save the game with `result` into the database `file` and close its journal.
+#[tauri::command]
+#[specta::specta]
+pub async fn finish_live_game(
+    id: String,
+    file: PathBuf,
+    result: String,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    if !matches!(result.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") {
+        return Err(Error::PackageManager(format!("Invalid result: {}", result)));
+    }
+    let game = load(&app, &id)?;
+    let pgn = to_pgn(&game, &result);
+
+    let mut importer = Importer::new(None);
+    let temp_game = BufferedReader::new_cursor(pgn.as_bytes())
+        .read_game(&mut importer)?
+        .flatten()
+        .ok_or(Error::NoMovesFound)?;
+
+    let _write = state.db_writes.lock(&file, "finish_live_game").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let last_id = sources::last_game_id(db)?;
+    db.transaction::<_, Error, _>(|db| insert_to_db(db, &temp_game))?;
+    sources::record_import(db, last_id, LIVE_GAME_SOURCE)?;
+    update_info_counts(db)?;
+
+    fs::remove_file(journal_path(&app, &id)?)?;
+    log::info!(
+        "Saved live game {} ({} moves) to {}",
+        id,
+        game.moves.len(),
+        file.display()
+    );
+    Ok(())
+}
+
+/// Drop a game without saving it.
+#[tauri::command]
+#[specta::specta]
+pub fn discard_live_game(id: String, app: tauri::AppHandle) -> Result<()> {
+    let path = journal_path(&app, &id)?;
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal(entries: &[JournalEntry]) -> String {
+        entries
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap() + "\n")
+            .collect()
+    }
+
+    fn start() -> JournalEntry {
+        JournalEntry::Start {
+            headers: LiveGameHeaders {
+                white: Some("Anna".to_string()),
+                ..Default::default()
+            },
+            started_at: "2026-03-01T18:00:00+00:00".to_string(),
+        }
+    }
+
+    fn mv(uci: &str) -> JournalEntry {
+        JournalEntry::Move {
+            uci: uci.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_replay_with_takeback() {
+        let content = journal(&[
+            start(),
+            mv("e2e4"),
+            mv("e7e5"),
+            JournalEntry::Takeback,
+            mv("c7c5"),
+        ]);
+        let game = replay("game", &content).unwrap();
+        assert_eq!(game.moves, vec!["e4", "c5"]);
+    }
+
+    #[test]
+    fn test_replay_ignores_torn_last_line() {
+        let mut content = journal(&[start(), mv("g1f3")]);
+        content.push_str("{\"type\":\"move\",\"uc");
+        let game = replay("game", &content).unwrap();
+        assert_eq!(game.moves, vec!["Nf3"]);
+    }
+
+    #[test]
+    fn test_to_pgn() {
+        let game = replay("game", &journal(&[start(), mv("e2e4"), mv("e7e5")])).unwrap();
+        let pgn = to_pgn(&game, "1-0");
+        assert!(pgn.contains("[White \"Anna\"]\n"));
+        assert!(pgn.contains("[Date \"2026.03.01\"]\n"));
+        assert!(pgn.ends_with("\ne4 e5 1-0\n"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Locations of game and puzzle databases.
+//!
+//! Databases are referred to either by file name, resolved against the app's `db` or `puzzles`
+//! directory, or by a path anywhere on disk. `DatabaseRef` makes that choice explicit and is the
+//! single place where names are turned into paths; commands still taking a plain path convert it
+//! with `DatabaseRef::from`, which treats relative paths as names.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::{
+    db::{read_db_info, DatabaseInfo},
+    error::Result,
+    puzzle::{read_puzzle_db_info, PuzzleDatabaseInfo},
+    AppState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum DatabaseKind {
+    Games,
+    Puzzles,
+}
+
+impl DatabaseKind {
+    /// Directory of the app data folder holding databases of this kind.
+    fn dir(self) -> &'static str {
+        match self {
+            Self::Games => "db",
+            Self::Puzzles => "puzzles",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum DatabaseRef {
+    /// File name inside the app's directory for the database kind, e.g. `caissabase.db3`.
+    Name(String),
+    /// Path anywhere on disk.
+    Path(PathBuf),
+}
+
+impl From<PathBuf> for DatabaseRef {
+    fn from(path: PathBuf) -> Self {
+        if path.is_absolute() {
+            Self::Path(path)
+        } else {
+            Self::Name(path.to_string_lossy().into_owned())
+        }
+    }
+}
+
+impl DatabaseRef {
+    pub fn resolve(&self, app: &tauri::AppHandle, kind: DatabaseKind) -> Result<PathBuf> {
+        match self {
+            Self::Name(name) => Ok(app
+                .path()
+                .resolve(Path::new(kind.dir()).join(name), BaseDirectory::AppData)?),
+            Self::Path(path) => Ok(path.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Type)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RegisteredDatabase {
+    Games { path: PathBuf, info: DatabaseInfo },
+    Puzzles { path: PathBuf, info: PuzzleDatabaseInfo },
+}
+
+/// `.db3` files directly inside the app's directory for `kind`, sorted by path.
+pub(super) fn database_files(app: &tauri::AppHandle, kind: DatabaseKind) -> Result<Vec<PathBuf>> {
+    let dir = app.path().resolve(kind.dir(), BaseDirectory::AppData)?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "db3"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Game and puzzle databases in the app's directories, with their info. Databases that cannot be
+/// read are left out.
+#[tauri::command]
+#[specta::specta]
+pub async fn list_registered_databases(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<RegisteredDatabase>> {
+    let mut databases = Vec::new();
+    for path in database_files(&app, DatabaseKind::Games)? {
+        match read_db_info(&path, &state) {
+            Ok(info) => databases.push(RegisteredDatabase::Games { path, info }),
+            Err(e) => log::warn!("Skipping database {}: {}", path.display(), e),
+        }
+    }
+    for path in database_files(&app, DatabaseKind::Puzzles)? {
+        match read_puzzle_db_info(&path) {
+            Ok(info) => databases.push(RegisteredDatabase::Puzzles { path, info }),
+            Err(e) => log::warn!("Skipping puzzle database {}: {}", path.display(), e),
+        }
+    }
+    Ok(databases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_ref_from_path() {
+        assert_eq!(
+            DatabaseRef::from(PathBuf::from("games.db3")),
+            DatabaseRef::Name("games.db3".to_string())
+        );
+        let absolute = std::env::temp_dir().join("games.db3");
+        assert_eq!(DatabaseRef::from(absolute.clone()), DatabaseRef::Path(absolute));
+    }
+}
@@ -0,0 +1,358 @@
+//! Merging of duplicate events and sites.
+//!
+//! Imported databases often spell the same tournament or venue several ways. A merge moves the
+//! games of the duplicates to the row that is kept and deletes the duplicates, like
+//! `merge_players` does for players. Every merge is written to `MergeJournal` with the deleted rows
+//! and the games each one had, so `undo_last_merge` can put them back; merges are undone newest
+//! first. Event and site ids are never reused, so a deleted row can always be restored under its
+//! old id.
+
+use std::path::PathBuf;
+
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{Integer, Nullable, Text},
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create, player_aggregates, schema::merge_journal, update_info_counts,
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MergeKind {
+    Event,
+    Site,
+}
+
+impl MergeKind {
+    fn name(self) -> &'static str {
+        match self {
+            MergeKind::Event => "event",
+            MergeKind::Site => "site",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "event" => Some(MergeKind::Event),
+            "site" => Some(MergeKind::Site),
+            _ => None,
+        }
+    }
+
+    fn table(self) -> &'static str {
+        match self {
+            MergeKind::Event => "Events",
+            MergeKind::Site => "Sites",
+        }
+    }
+
+    /// Column of `Games` referencing the table.
+    fn game_column(self) -> &'static str {
+        match self {
+            MergeKind::Event => "EventID",
+            MergeKind::Site => "SiteID",
+        }
+    }
+}
+
+/// A row deleted by a merge, with the games that referenced it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct MergedRow {
+    id: i32,
+    name: Option<String>,
+    games: Vec<i32>,
+}
+
+#[derive(QueryableByName)]
+struct NamedRow {
+    #[diesel(sql_type = Integer, column_name = "ID")]
+    id: i32,
+    #[diesel(sql_type = Nullable<Text>, column_name = "Name")]
+    name: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct GameId {
+    #[diesel(sql_type = Integer, column_name = "ID")]
+    id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeSummary {
+    /// Rows merged into the kept one, or restored by an undo.
+    pub merged: i32,
+    /// Games moved from the merged rows to the kept one, or back.
+    pub games: i32,
+}
+
+fn named_row(db: &mut SqliteConnection, kind: MergeKind, id: i32) -> Result<NamedRow> {
+    sql_query(format!(
+        "SELECT ID, Name FROM {} WHERE ID = ?",
+        kind.table()
+    ))
+    .bind::<Integer, _>(id)
+    .get_result(db)
+    .optional()?
+    .ok_or_else(|| Error::PackageManager(format!("No {} with id {}", kind.name(), id)))
+}
+
+fn merge(
+    db: &mut SqliteConnection,
+    kind: MergeKind,
+    keep_id: i32,
+    merge_ids: &[i32],
+) -> Result<MergeSummary> {
+    let mut merge_ids: Vec<i32> = merge_ids
+        .iter()
+        .copied()
+        .filter(|id| *id != keep_id)
+        .collect();
+    merge_ids.sort_unstable();
+    merge_ids.dedup();
+    if merge_ids.is_empty() {
+        return Err(Error::PackageManager(format!(
+            "No {}s to merge",
+            kind.name()
+        )));
+    }
+    if merge_ids.contains(&0) {
+        return Err(Error::PackageManager(format!(
+            "The unknown {} cannot be merged into another",
+            kind.name()
+        )));
+    }
+
+    db.transaction::<_, Error, _>(|db| {
+        named_row(db, kind, keep_id)?;
+        let mut merged = Vec::with_capacity(merge_ids.len());
+        for id in merge_ids {
+            let row = named_row(db, kind, id)?;
+            let games = sql_query(format!(
+                "SELECT ID FROM Games WHERE {} = ?",
+                kind.game_column()
+            ))
+            .bind::<Integer, _>(id)
+            .load::<GameId>(db)?
+            .into_iter()
+            .map(|game| game.id)
+            .collect();
+            sql_query(format!(
+                "UPDATE Games SET {column} = ? WHERE {column} = ?",
+                column = kind.game_column()
+            ))
+            .bind::<Integer, _>(keep_id)
+            .bind::<Integer, _>(id)
+            .execute(db)?;
+            sql_query(format!("DELETE FROM {} WHERE ID = ?", kind.table()))
+                .bind::<Integer, _>(id)
+                .execute(db)?;
+            merged.push(MergedRow {
+                id,
+                name: row.name,
+                games,
+            });
+        }
+
+        let entry = serde_json::to_string(&merged)
+            .map_err(|e| Error::PackageManager(format!("Failed to serialize merge: {}", e)))?;
+        diesel::insert_into(merge_journal::table)
+            .values((
+                merge_journal::kind.eq(kind.name()),
+                merge_journal::kept_id.eq(keep_id),
+                merge_journal::merged.eq(entry),
+            ))
+            .execute(db)?;
+        update_info_counts(db)?;
+
+        Ok(MergeSummary {
+            merged: merged.len() as i32,
+            games: merged.iter().map(|row| row.games.len() as i32).sum(),
+        })
+    })
+}
+
+/// Reverts the newest merge in the journal, or returns `None` when there is none.
+fn undo_merge(db: &mut SqliteConnection) -> Result<Option<MergeSummary>> {
+    db.transaction::<_, Error, _>(|db| {
+        let Some((entry_id, kind, keep_id, merged)) = merge_journal::table
+            .order(merge_journal::id.desc())
+            .select((
+                merge_journal::id,
+                merge_journal::kind,
+                merge_journal::kept_id,
+                merge_journal::merged,
+            ))
+            .first::<(i32, String, i32, String)>(db)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let kind = MergeKind::parse(&kind)
+            .ok_or_else(|| Error::PackageManager(format!("Unknown merge kind: {}", kind)))?;
+        let merged: Vec<MergedRow> = serde_json::from_str(&merged)
+            .map_err(|e| Error::PackageManager(format!("Invalid merge journal entry: {}", e)))?;
+
+        let mut games = 0;
+        for row in &merged {
+            sql_query(format!(
+                "INSERT INTO {} (ID, Name) VALUES (?, ?)",
+                kind.table()
+            ))
+            .bind::<Integer, _>(row.id)
+            .bind::<Nullable<Text>, _>(row.name.as_deref())
+            .execute(db)?;
+            let ids = serde_json::to_string(&row.games)
+                .map_err(|e| Error::PackageManager(format!("Failed to serialize merge: {}", e)))?;
+            // Games edited to another row since the merge stay where they are.
+            games += sql_query(format!(
+                "UPDATE Games SET {column} = ? WHERE {column} = ?
+                AND ID IN (SELECT value FROM json_each(?))",
+                column = kind.game_column()
+            ))
+            .bind::<Integer, _>(row.id)
+            .bind::<Integer, _>(keep_id)
+            .bind::<Text, _>(ids)
+            .execute(db)? as i32;
+        }
+
+        diesel::delete(merge_journal::table.filter(merge_journal::id.eq(entry_id))).execute(db)?;
+        update_info_counts(db)?;
+        Ok(Some(MergeSummary {
+            merged: merged.len() as i32,
+            games,
+        }))
+    })
+}
+
+/// Merge the events `merge_ids` into `keep_id`, moving their games to it.
+#[tauri::command]
+#[specta::specta]
+pub async fn merge_events(
+    file: PathBuf,
+    keep_id: i32,
+    merge_ids: Vec<i32>,
+    state: tauri::State<'_, AppState>,
+) -> Result<MergeSummary> {
+    let _write = state.db_writes.lock(&file, "merge_events").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let summary = merge(db, MergeKind::Event, keep_id, &merge_ids)?;
+    player_aggregates::invalidate(db)?;
+    Ok(summary)
+}
+
+/// Merge the sites `merge_ids` into `keep_id`, moving their games to it.
+#[tauri::command]
+#[specta::specta]
+pub async fn merge_sites(
+    file: PathBuf,
+    keep_id: i32,
+    merge_ids: Vec<i32>,
+    state: tauri::State<'_, AppState>,
+) -> Result<MergeSummary> {
+    let _write = state.db_writes.lock(&file, "merge_sites").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let summary = merge(db, MergeKind::Site, keep_id, &merge_ids)?;
+    player_aggregates::invalidate(db)?;
+    Ok(summary)
+}
+
+/// Undo the last event or site merge of `file`. Returns `None` when there is nothing to undo.
+#[tauri::command]
+#[specta::specta]
+pub async fn undo_last_merge(
+    file: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<MergeSummary>> {
+    let _write = state.db_writes.lock(&file, "undo_last_merge").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let summary = undo_merge(db)?;
+    if summary.is_some() {
+        player_aggregates::invalidate(db)?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::core;
+    use diesel::connection::SimpleConnection;
+
+    fn test_db() -> SqliteConnection {
+        let mut db = SqliteConnection::establish(":memory:").unwrap();
+        core::init_db(&mut db, "Test", "Test").unwrap();
+        db.batch_execute(
+            "INSERT INTO Events (ID, Name) VALUES (1, 'Tata Steel 2024'), (2, 'Tata Steel'),
+                (3, 'Tata Steel Masters');
+            INSERT INTO Sites (ID, Name) VALUES (1, 'Wijk aan Zee');
+            INSERT INTO Games (ID, EventID, SiteID) VALUES (1, 1, 1), (2, 2, 1), (3, 2, 1),
+                (4, 3, 0), (5, 0, 0);",
+        )
+        .unwrap();
+        db
+    }
+
+    fn event_ids(db: &mut SqliteConnection) -> Vec<i32> {
+        sql_query("SELECT EventID AS ID FROM Games ORDER BY ID")
+            .load::<GameId>(db)
+            .unwrap()
+            .into_iter()
+            .map(|game| game.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_and_undo() {
+        let mut db = test_db();
+        assert_eq!(
+            merge(&mut db, MergeKind::Event, 1, &[2, 3, 3, 1]).unwrap(),
+            MergeSummary {
+                merged: 2,
+                games: 3
+            }
+        );
+        assert_eq!(event_ids(&mut db), vec![1, 1, 1, 1, 0]);
+        assert!(named_row(&mut db, MergeKind::Event, 2).is_err());
+
+        // A game moved elsewhere after the merge is left alone by the undo.
+        db.batch_execute("UPDATE Games SET EventID = 0 WHERE ID = 4;")
+            .unwrap();
+        assert_eq!(
+            undo_merge(&mut db).unwrap(),
+            Some(MergeSummary {
+                merged: 2,
+                games: 2
+            })
+        );
+        assert_eq!(event_ids(&mut db), vec![1, 2, 2, 0, 0]);
+        assert_eq!(
+            named_row(&mut db, MergeKind::Event, 3)
+                .unwrap()
+                .name
+                .as_deref(),
+            Some("Tata Steel Masters")
+        );
+        assert_eq!(undo_merge(&mut db).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_merges() {
+        let mut db = test_db();
+        assert!(merge(&mut db, MergeKind::Event, 1, &[1]).is_err());
+        assert!(merge(&mut db, MergeKind::Event, 1, &[0]).is_err());
+        assert!(merge(&mut db, MergeKind::Site, 1, &[7]).is_err());
+        assert!(merge(&mut db, MergeKind::Site, 7, &[1]).is_err());
+        // Failed merges leave nothing to undo.
+        assert_eq!(undo_merge(&mut db).unwrap(), None);
+    }
+}
@@ -1,21 +1,73 @@
-mod encoding;
+mod annotate;
+mod annotation_sync;
+mod chesscom;
+mod column_stats;
+mod conditionals;
+mod derived_columns;
+mod diff;
+pub(crate) mod encoding;
+mod endgames;
+mod eval_comment;
+mod eval_sheet;
+mod event_timeline;
+mod explorer;
+mod external_analysis;
+mod game_bundle;
+mod game_diff;
+mod global_search;
+mod key_positions;
+mod lenient;
+mod lichess;
+mod live_game;
+mod location;
+mod merges;
 mod models;
+mod move_blob;
+mod move_data;
 mod ops;
-mod schema;
+mod opponent_model;
+mod otb_import;
+pub(crate) mod schema;
+mod scratch;
+mod scripting;
 mod search;
-mod core;
-mod pgn;
+mod smart_analysis;
+mod sources;
+pub(crate) mod core;
+pub(crate) mod pgn;
+mod pgn_format;
+mod phases;
+mod piece_constraints;
+mod player_aggregates;
+mod player_report;
+mod pool_stats;
 mod position_cache;
+mod position_export;
+mod position_filter;
+mod prep_bundle;
+pub(crate) mod review;
+mod saved_filters;
+mod students;
+mod subset_export;
+mod tags;
+mod time_forfeits;
+mod transpositions;
+mod validation;
+mod write_lock;
 
 use crate::{
+    chess::GamePhase,
     db::{
         encoding::{extract_main_line_moves},
         models::*,
         ops::*,
+        pool_stats::{PoolEventHandler, PoolMetrics},
         schema::*,
     },
     error::{Error, Result},
+    metrics::CommandSpan,
     opening::get_opening_from_setup,
+    progress::{TaskKind, TaskProgress},
     AppState,
 };
 use dashmap::DashMap;
@@ -37,12 +89,14 @@ use shakmaty::{
 use specta::Type;
 use std::{
     fs::{File, OpenOptions},
-    path::PathBuf,
-    sync::atomic::{AtomicUsize, Ordering},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
-use std::io::{BufWriter, Write};
-use tauri::{path::BaseDirectory, Manager};
+use std::io::{BufWriter, Read, Write};
 use tauri::{Emitter, State};
 
 use log::info;
@@ -51,9 +105,68 @@ use tauri_specta::Event as _;
 pub use self::models::NormalizedGame;
 pub use self::models::Puzzle;
 pub use self::schema::puzzles;
+pub use self::scripting::run_script;
 pub use self::search::{
-    is_position_in_db, search_position, PositionQuery, PositionQueryJs, PositionStats,
+    build_position_checkpoints, cancel_position_checks, get_position_preview, is_position_in_db, prefetch_line_stats, search_position,
+    PositionPresence, PositionPreview, PositionQuery, PositionQueryJs, PositionStats,
+};
+pub use self::annotate::annotate_movetext;
+pub use self::annotation_sync::{get_sync_config, set_sync_config, sync_pull, sync_push};
+pub use self::conditionals::{
+    add_conditional_line, export_conditional_lines, get_conditional_lines, remove_conditional_line,
+};
+pub use self::derived_columns::recompute_derived_columns;
+pub use self::diff::diff_databases;
+pub use self::endgames::{classify_endgames, get_endgame_distribution};
+pub use self::eval_sheet::export_analysis_csv;
+pub use self::event_timeline::get_event_timeline;
+pub use self::external_analysis::attach_external_analysis;
+pub use self::game_diff::diff_games;
+pub use self::key_positions::get_game_key_positions;
+pub use self::lenient::ImportCorrection;
+pub use self::live_game::{
+    discard_live_game, finish_live_game, list_live_games, record_live_move, start_live_game,
+    take_back_live_move,
+};
+pub use self::location::{list_registered_databases, DatabaseKind, DatabaseRef};
+pub use self::move_blob::{decode_moves_debug, repair_move_blob};
+pub use self::phases::{compute_game_phases, get_game_phases, GamePhaseBoundaries};
+pub use self::player_aggregates::{build_player_aggregates, MonthlyPlayerStats};
+pub use self::pool_stats::{get_connection_pool_stats, PoolMetrics};
+pub use self::player_report::{get_player_weakness_report, PlayerWeaknessReport};
+pub use self::position_export::export_position_stats;
+pub use self::position_filter::PositionFilters;
+pub use self::prep_bundle::{export_prep_bundle, import_prep_bundle};
+pub use self::write_lock::{get_database_writer, WriteGuard, WriteLocks};
+pub use self::review::{get_game_review, review_game, GameReview};
+pub use self::saved_filters::{delete_game_filter, list_game_filters, save_game_filter};
+pub use self::smart_analysis::{run_smart_analysis, stop_smart_analysis};
+pub use self::sources::{delete_games_by_source, get_import_sources};
+pub use self::students::{
+    create_student, delete_student, get_student_progress, link_student_source, list_students,
+    record_student_puzzle_result, unlink_student_source,
+};
+pub use self::global_search::global_search;
+pub use self::game_bundle::{export_game_bundle, import_game_bundle};
+pub use self::time_forfeits::get_flagging_stats;
+pub use self::move_data::{get_game_move_data, MoveData};
+pub use self::core::{migrate_database, MigrationReport};
+pub use self::pgn_format::{CommentPlacement, PgnExportOptions, PgnFormat, VariationStyle};
+pub use self::opponent_model::{
+    end_opponent_model, sample_opponent_move, start_opponent_model, OpponentModel,
 };
+pub use self::column_stats::{analyze_database, DatabaseAnalysis};
+pub use self::chesscom::import_chesscom_games;
+pub use self::scratch::{create_scratch_database, drop_scratch_database, ScratchDatabase};
+pub use self::lichess::sync_lichess_games;
+pub use self::merges::{merge_events, merge_sites, undo_last_merge, MergeSummary};
+pub use self::explorer::get_remote_explorer_stats;
+pub use self::transpositions::get_game_transpositions;
+pub use self::otb_import::import_otb_events;
+pub use self::validation::{validate_game_update, validate_pgn_game, FieldError};
+pub(crate) use self::validation::{ensure_valid, validate_pgn};
+pub use self::subset_export::export_subset_to_db;
+pub use self::tags::{list_tags, tag_game, untag_game};
 pub use self::position_cache::{
     is_position_cached, get_cached_position, save_position_cache, clear_cache_for_database,
 };
@@ -149,7 +262,7 @@ impl diesel::r2d2::CustomizeConnection<SqliteConnection, diesel::r2d2::Error>
     }
 }
 
-fn get_db_or_create(
+pub(crate) fn get_db_or_create(
     state: &State<AppState>,
     db_path: &str,
     options: ConnectionOptions,
@@ -157,19 +270,33 @@ fn get_db_or_create(
     let pool = match state.connection_pool.get(db_path) {
         Some(pool) => pool.clone(),
         None => {
-            let pool = Pool::builder()
+            let metrics = Arc::new(PoolMetrics::default());
+            let mut builder = Pool::builder()
                 .max_size(32) // OPTIMIZED: Increased from 16 to 32 for better concurrency
                 .min_idle(Some(4)) // OPTIMIZED: Keep minimum connections ready
-                .connection_timeout(Duration::from_secs(30))
+                .connection_timeout(Duration::from_secs(30));
+            if scratch::is_memory_database(db_path) {
+                // Shared-cache connections fail with SQLITE_LOCKED rather than wait for each
+                // other, and the database is gone once its last connection closes.
+                builder = builder
+                    .max_size(1)
+                    .min_idle(Some(1))
+                    .idle_timeout(None)
+                    .max_lifetime(None);
+            }
+            let pool = builder
                 .connection_customizer(Box::new(options))
-                .build(ConnectionManager::<SqliteConnection>::new(db_path))?;
+                .event_handler(Box::new(PoolEventHandler::new(db_path, metrics.clone())))
+                .build(ConnectionManager::<SqliteConnection>::new(
+                    scratch::connection_url(db_path),
+                ))?;
+            state.pool_metrics.insert(db_path.to_string(), metrics);
             state
                 .connection_pool
                 .insert(db_path.to_string(), pool.clone());
             pool
         }
     };
-
     Ok(pool.get()?)
 }
 
@@ -235,7 +362,9 @@ pub fn insert_to_db(db: &mut SqliteConnection, game: &TempGame) -> Result<()> {
         pawn_home: pawn_home as i32,
     };
 
-    core::add_game(db, new_game)?;
+    let inserted = core::add_game(db, new_game)?;
+    time_forfeits::record_time_forfeit(db, inserted.id, game)?;
+    move_data::record_move_data(db, inserted.id, &game.tree)?;
 
     Ok(())
 }
@@ -249,23 +378,37 @@ pub async fn convert_pgn(
     app: tauri::AppHandle,
     title: String,
     description: Option<String>,
+    lenient: Option<bool>,
+    source: Option<String>,
     state: tauri::State<'_, AppState>,
-) -> Result<()> {
+) -> Result<Vec<ImportCorrection>> {
     let description = description.unwrap_or_default();
+    let source = source.unwrap_or_else(|| sources::default_source(&file));
     let extension = file.extension();
-
-    let db_exists = db_path.exists();
+    let lenient = lenient.unwrap_or(false);
+
+    let _write = state.db_writes.lock(&db_path, "convert_pgn").await;
+    let job = state.jobs.start(
+        &app,
+        TaskKind::Import,
+        db_path.to_string_lossy(),
+        format!("Importing games into {}", title),
+    );
+    let db_exists = db_path.exists() || scratch::is_memory_database(&db_path.to_string_lossy());
+    let mut span = CommandSpan::start("convert_pgn");
 
     // create the database file
-    let db = &mut get_db_or_create(
-        &state,
-        db_path.to_str().unwrap(),
-        ConnectionOptions {
-            enable_foreign_keys: false,
-            busy_timeout: None,
-            journal_mode: JournalMode::Off,
-        },
-    )?;
+    let db = &mut span.time_connection(|| {
+        get_db_or_create(
+            &state,
+            db_path.to_str().unwrap(),
+            ConnectionOptions {
+                enable_foreign_keys: false,
+                busy_timeout: None,
+                journal_mode: JournalMode::Off,
+            },
+        )
+    })?;
 
     // Check if tables exist, even if the file exists
     // This handles cases where the file exists but is empty or corrupted
@@ -293,6 +436,7 @@ pub async fn convert_pgn(
         }
         core::init_db(db, &title, &description)?;
     }
+    let last_id = sources::last_game_id(db)?;
 
     let file = File::open(&file)?;
 
@@ -304,16 +448,30 @@ pub async fn convert_pgn(
         Box::new(file)
     };
 
+    // Lenient imports fix the text before parsing, which needs the whole file in memory
+    let mut corrections = Vec::new();
+    let uncompressed: Box<dyn std::io::Read + Send> = if lenient {
+        let mut bytes = Vec::new();
+        let mut uncompressed = uncompressed;
+        uncompressed.read_to_end(&mut bytes)?;
+        let (text, text_corrections) = lenient::normalize_pgn(&String::from_utf8_lossy(&bytes));
+        corrections = text_corrections;
+        Box::new(std::io::Cursor::new(text.into_bytes()))
+    } else {
+        uncompressed
+    };
+
     // start counting time
     let start = Instant::now();
 
-    let mut importer = Importer::new(timestamp.map(|t| t as i64));
+    let mut importer = Importer::new(timestamp.map(|t| t as i64)).lenient(lenient);
     
     // OPTIMIZED: Batch inserts for better performance
     // Collect games in batches to reduce transaction overhead
     const BATCH_SIZE: usize = 5000;
     let mut batch: Vec<TempGame> = Vec::with_capacity(BATCH_SIZE);
     let mut total_processed = 0;
+    let _awake = crate::app::platform::keep_awake::keep_awake("Importing games");
     
     for game in BufferedReader::new(uncompressed)
             .into_iter(&mut importer)
@@ -321,6 +479,11 @@ pub async fn convert_pgn(
             .flatten()
     {
         batch.push(game);
+        if job.is_cancelled() {
+            // Stop reading; the games read so far are still inserted below
+            log::warn!("Import into {:?} interrupted", db_path);
+            break;
+        }
         
         if batch.len() >= BATCH_SIZE {
             // Process batch in a single transaction
@@ -334,6 +497,9 @@ pub async fn convert_pgn(
             total_processed += BATCH_SIZE;
                 let elapsed = start.elapsed().as_millis() as u32;
             app.emit("convert_progress", (total_processed, elapsed)).unwrap();
+            TaskProgress::new(TaskKind::Import, db_path.to_string_lossy(), -1.0)
+                .message(format!("{} games imported", total_processed))
+                .send(&app);
             }
     }
     
@@ -353,13 +519,36 @@ pub async fn convert_pgn(
         let elapsed = start.elapsed().as_millis() as u32;
         app.emit("convert_progress", (total_processed, elapsed)).unwrap();
     }
+    span.add_rows(total_processed);
 
     if needs_init {
         // Create all the necessary indexes
         db.batch_execute(INDEXES_SQL)?;
     }
 
-    // get game, player, event and site counts and to the info table
+    sources::record_import(db, last_id, &source)?;
+    update_info_counts(db)?;
+    player_aggregates::update_after_import(db)?;
+
+    // The games imported before the cancellation are kept
+    if job.is_cancelled() {
+        info!("Import into {:?} cancelled after {} games", db_path, total_processed);
+        return Err(Error::JobCancelled);
+    }
+    TaskProgress::done(TaskKind::Import, db_path.to_string_lossy())
+        .message(format!("{} games imported", total_processed))
+        .send(&app);
+
+    corrections.append(&mut importer.corrections);
+    corrections.sort_by_key(|correction| correction.game);
+    if !corrections.is_empty() {
+        info!("{} corrections made while importing {:?}", corrections.len(), db_path);
+    }
+    Ok(corrections)
+}
+
+/// Store the game, player, event and site counts in the info table.
+fn update_info_counts(db: &mut SqliteConnection) -> Result<()> {
     let game_count: i64 = games::table.count().get_result(db)?;
     let player_count: i64 = players::table.count().get_result(db)?;
     let event_count: i64 = events::table.count().get_result(db)?;
@@ -415,13 +604,13 @@ pub async fn get_db_info(
     app: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
 ) -> Result<DatabaseInfo> {
-    let db_path = PathBuf::from("db").join(file);
-
-    // OPTIMIZED: Removed - called frequently, not critical
-
-    let path = app.path().resolve(db_path, BaseDirectory::AppData)?;
+    let path = DatabaseRef::from(file).resolve(&app, DatabaseKind::Games)?;
+    read_db_info(&path, &state)
+}
 
-    let db = &mut get_db_or_create(&state, path.to_str().unwrap(), ConnectionOptions::default())?;
+/// Title, description, counts and size of the game database at `path`.
+pub(crate) fn read_db_info(path: &Path, state: &State<AppState>) -> Result<DatabaseInfo> {
+    let db = &mut get_db_or_create(state, path.to_str().unwrap(), ConnectionOptions::default())?;
 
     let player_count = players::table.count().get_result::<i64>(db)? as i32;
     let game_count = games::table.count().get_result::<i64>(db)? as i32;
@@ -464,6 +653,7 @@ pub async fn get_db_info(
 #[tauri::command]
 #[specta::specta]
 pub async fn create_indexes(file: PathBuf, state: tauri::State<'_, AppState>) -> Result<()> {
+    let _write = state.db_writes.lock(&file, "create_indexes").await;
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
     db.batch_execute(INDEXES_SQL)?;
@@ -474,6 +664,7 @@ pub async fn create_indexes(file: PathBuf, state: tauri::State<'_, AppState>) ->
 #[tauri::command]
 #[specta::specta]
 pub async fn delete_indexes(file: PathBuf, state: tauri::State<'_, AppState>) -> Result<()> {
+    let _write = state.db_writes.lock(&file, "delete_indexes").await;
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
     db.batch_execute(DELETE_INDEXES_SQL)?;
@@ -489,6 +680,7 @@ pub async fn edit_db_info(
     description: Option<String>,
     state: tauri::State<'_, AppState>,
 ) -> Result<()> {
+    let _write = state.db_writes.lock(&file, "edit_db_info").await;
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
     if let Some(title) = title {
@@ -548,7 +740,7 @@ pub enum SortDirection {
     Desc,
 }
 
-#[derive(Default, Debug, Clone, Deserialize, PartialEq, Eq, Hash, Type)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct QueryOptions<SortT> {
     pub skip_count: bool,
@@ -580,7 +772,6 @@ pub struct GameQuery {
 mod bigint_serde {
     use serde::{Deserializer, Serializer};
     
-    #[allow(dead_code)]
     pub fn serialize<S>(value: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
@@ -802,7 +993,7 @@ mod bigint_serde {
     }
 }
 
-#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq, Hash, Type)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Type)]
 pub struct GameQueryJs {
     #[specta(optional)]
     pub options: Option<QueryOptions<GameSort>>,
@@ -835,6 +1026,21 @@ pub struct GameQueryJs {
     pub position: Option<PositionQueryJs>,
     #[specta(optional)]
     pub wanted_result: Option<String>,
+    /// Endgame type as returned by `get_endgame_distribution`, e.g. `R vs R`.
+    #[specta(optional)]
+    pub endgame: Option<String>,
+    /// Import source as recorded by `convert_pgn`, e.g. the imported file name.
+    #[specta(optional)]
+    pub source: Option<String>,
+    /// User tags the games must all carry, as set by `tag_game`.
+    #[specta(optional)]
+    pub tags: Option<Vec<String>>,
+    /// Only games lost on time by the side that was winning, see `get_flagging_stats`.
+    #[specta(optional)]
+    pub flagged_when_winning: Option<bool>,
+    /// Only games whose final position is in this phase, see `get_game_phases`.
+    #[specta(optional)]
+    pub decided_in: Option<GamePhase>,
 }
 
 impl GameQueryJs {
@@ -851,6 +1057,8 @@ impl GameQueryJs {
 pub struct QueryResponse<T> {
     pub data: T,
     pub count: Option<i32>,
+    /// Whether `count` is an upper bound from the database statistics rather than exact.
+    pub count_is_estimate: bool,
 }
 
 #[tauri::command]
@@ -860,9 +1068,14 @@ pub async fn get_games(
     query: GameQueryJs,
     state: tauri::State<'_, AppState>,
 ) -> Result<QueryResponse<Vec<NormalizedGame>>> {
-    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let mut span = CommandSpan::start("get_games");
+    let db = &mut span.time_connection(|| {
+        get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())
+    })?;
 
     let mut count: Option<i64> = None;
+    let mut count_is_estimate = false;
+    let estimated_rows = column_stats::estimate_game_rows(db, &query);
     let query_options = query.options.unwrap_or_default();
 
     let (white_players, black_players) = diesel::alias!(players as white, players as black);
@@ -899,6 +1112,89 @@ pub async fn get_games(
         count_query = count_query.filter(games::event_id.eq(tournament_id));
     }
 
+    if let Some(endgame) = query.endgame {
+        // Reads do not wait for writes; games left unclassified are found by a later call.
+        if let Some(_write) = state.db_writes.try_lock(&file, "get_games") {
+            endgames::backfill_endgames(db)?;
+        }
+        sql_query = sql_query.filter(
+            games::id.eq_any(
+                game_endgames::table
+                    .filter(game_endgames::endgame.eq(endgame.clone()))
+                    .select(game_endgames::game_id),
+            ),
+        );
+        count_query = count_query.filter(
+            games::id.eq_any(
+                game_endgames::table
+                    .filter(game_endgames::endgame.eq(endgame))
+                    .select(game_endgames::game_id),
+            ),
+        );
+    }
+
+    if let Some(source) = query.source {
+        sql_query = sql_query.filter(
+            games::id.eq_any(
+                game_sources::table
+                    .filter(game_sources::source.eq(source.clone()))
+                    .select(game_sources::game_id),
+            ),
+        );
+        count_query = count_query.filter(
+            games::id.eq_any(
+                game_sources::table
+                    .filter(game_sources::source.eq(source))
+                    .select(game_sources::game_id),
+            ),
+        );
+    }
+
+    if let Some(wanted_tags) = query.tags.filter(|t| !t.is_empty()) {
+        for tag in wanted_tags {
+            sql_query = sql_query.filter(
+                games::id.eq_any(
+                    game_tags::table
+                        .filter(game_tags::tag.eq(tag.clone()))
+                        .select(game_tags::game_id),
+                ),
+            );
+            count_query = count_query.filter(
+                games::id.eq_any(
+                    game_tags::table
+                        .filter(game_tags::tag.eq(tag))
+                        .select(game_tags::game_id),
+                ),
+            );
+        }
+    }
+
+    if query.flagged_when_winning == Some(true) {
+        let min_advantage = time_forfeits::WINNING_ADVANTAGE_CP;
+        sql_query = sql_query.filter(
+            games::id.eq_any(
+                game_time_forfeits::table
+                    .filter(game_time_forfeits::loser_advantage.ge(min_advantage))
+                    .select(game_time_forfeits::game_id),
+            ),
+        );
+        count_query = count_query.filter(
+            games::id.eq_any(
+                game_time_forfeits::table
+                    .filter(game_time_forfeits::loser_advantage.ge(min_advantage))
+                    .select(game_time_forfeits::game_id),
+            ),
+        );
+    }
+
+    if let Some(phase) = query.decided_in {
+        if let Some(_write) = state.db_writes.try_lock(&file, "get_games") {
+            phases::backfill_phases(db)?;
+        }
+        sql_query = sql_query.filter(games::id.eq_any(phases::games_decided_in(phase)));
+        count_query = count_query.filter(games::id.eq_any(phases::games_decided_in(phase)));
+    }
+
     if let Some(limit) = query_options.page_size {
         sql_query = sql_query.limit(limit as i64);
     }
@@ -1038,15 +1334,23 @@ pub async fn get_games(
     };
 
     if !query_options.skip_count {
-        count = Some(
-            count_query
-                .select(diesel::dsl::count(games::id))
-                .first(db)?,
-        );
+        count = match estimated_rows {
+            // Counting every match of a huge result costs more than loading the page.
+            Some(estimate) if estimate > column_stats::EXACT_COUNT_LIMIT => {
+                count_is_estimate = true;
+                Some(estimate)
+            }
+            _ => Some(
+                count_query
+                    .select(diesel::dsl::count(games::id))
+                    .first(db)?,
+            ),
+        };
     }
 
     let games: Vec<(Game, Player, Player, Event, Site)> = sql_query.load(db)?;
     let mut normalized_games = normalize_games(games)?;
+    sources::attach_sources(db, &mut normalized_games)?;
     
     // Sort by average ELO if needed (calculated in Rust)
     if matches!(query_options.sort, GameSort::AverageElo) {
@@ -1083,9 +1387,11 @@ pub async fn get_games(
         });
     }
 
+    span.add_rows(normalized_games.len());
     Ok(QueryResponse {
         data: normalized_games,
         count: count.map(|c| c as i32),
+        count_is_estimate,
     })
 }
 
@@ -1137,7 +1443,10 @@ pub async fn get_players(
     query: PlayerQuery,
     state: tauri::State<'_, AppState>,
 ) -> Result<QueryResponse<Vec<Player>>> {
-    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let mut span = CommandSpan::start("get_players");
+    let db = &mut span.time_connection(|| {
+        get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())
+    })?;
     let mut count: Option<i64> = None;
 
     let mut sql_query = players::table.into_boxed();
@@ -1183,10 +1492,12 @@ pub async fn get_players(
     };
 
     let players = sql_query.load::<Player>(db)?;
+    span.add_rows(players.len());
 
     Ok(QueryResponse {
         data: players,
         count: count.map(|c| c as i32),
+        count_is_estimate: false,
     })
 }
 
@@ -1252,12 +1563,14 @@ pub async fn get_tournaments(
     Ok(QueryResponse {
         data: events,
         count: count.map(|c| c as i32),
+        count_is_estimate: false,
     })
 }
 
 #[derive(Debug, Clone, Serialize, Type, Default)]
 pub struct PlayerGameInfo {
     pub site_stats_data: Vec<SiteStatsData>,
+    pub monthly_stats: Vec<MonthlyPlayerStats>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default, Type)]
@@ -1311,17 +1624,68 @@ pub struct DatabaseProgress {
     pub progress: f64,
 }
 
+/// Site under which a game is counted in player stats; lichess game URLs are grouped together.
+fn stats_site(site: &str) -> String {
+    if site.starts_with("https://lichess.org/") {
+        "Lichess".to_string()
+    } else {
+        site.to_string()
+    }
+}
+
+/// Name of the deepest known opening reached by a game, or an empty string if none is. `None` if
+/// the moves cannot be decoded.
+fn game_opening(moves: &[u8]) -> Option<String> {
+    let mut setups = vec![];
+    let mut chess = Chess::default();
+
+    // Extract main line moves from the extended format
+    let main_moves = extract_main_line_moves(moves, Some(chess.clone())).ok()?;
+
+    for (i, m) in main_moves.iter().enumerate() {
+        if i > 54 {
+            // max length of opening in data
+            break;
+        }
+        chess.play_unchecked(m);
+        setups.push(chess.clone().into_setup(EnPassantMode::Legal));
+    }
+
+    setups.reverse();
+    Some(
+        setups
+            .iter()
+            .find_map(|setup| get_opening_from_setup(setup.clone()).ok())
+            .unwrap_or_default(),
+    )
+}
+
 #[tauri::command]
 #[specta::specta]
 pub async fn get_players_game_info(
     file: PathBuf,
     id: i32,
+    summary: bool,
     state: tauri::State<'_, AppState>,
     app: tauri::AppHandle,
 ) -> Result<PlayerGameInfo> {
-    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let mut span = CommandSpan::start("get_players_game_info");
+    let db = &mut span.time_connection(|| {
+        get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())
+    })?;
     let timer = Instant::now();
 
+    // A summary only needs the monthly stats, which come from the aggregates when they are current.
+    if summary {
+        if let Some(monthly_stats) = player_aggregates::load_monthly_stats(db, id)? {
+            info!("Player stats read from aggregates in {:?}", timer.elapsed());
+            return Ok(PlayerGameInfo {
+                site_stats_data: Vec::new(),
+                monthly_stats,
+            });
+        }
+    }
+
     let sql_query = games::table
         .inner_join(sites::table.on(games::site_id.eq(sites::id)))
         .inner_join(players::table.on(players::id.eq(id)))
@@ -1353,6 +1717,7 @@ pub async fn get_players_game_info(
         Option<String>,
     );
     let info: Vec<GameInfo> = sql_query.load(db)?;
+    span.add_rows(info.len());
 
     let mut game_info = PlayerGameInfo::default();
     let progress = AtomicUsize::new(0);
@@ -1386,48 +1751,19 @@ pub async fn get_players_game_info(
                     return None;
                 }
 
-                let site = site.as_deref().map(|s| {
-                    if s.starts_with("https://lichess.org/") {
-                        "Lichess".to_string()
-                    } else {
-                        s.to_string()
-                    }
-                })?;
-
-                let mut setups = vec![];
-                let mut chess = Chess::default();
-                
-                // Extract main line moves from the extended format
-                let main_moves = match extract_main_line_moves(moves, Some(chess.clone())) {
-                    Ok(moves) => moves,
-                    Err(_) => {
-                        // If extraction fails, skip this game
-                        return None;
-                    }
-                };
-                
-                for (i, m) in main_moves.iter().enumerate() {
-                    if i > 54 {
-                        // max length of opening in data
-                        break;
-                    }
-                    chess.play_unchecked(m);
-                    setups.push(chess.clone().into_setup(EnPassantMode::Legal));
-                }
-
-                setups.reverse();
-                let opening = setups
-                    .iter()
-                    .find_map(|setup| get_opening_from_setup(setup.clone()).ok())
-                    .unwrap_or_default();
+                let site = stats_site(site.as_deref()?);
+                // If extraction fails, skip this game
+                let opening = game_opening(moves)?;
 
                 let p = progress.fetch_add(1, Ordering::Relaxed);
                 if p % 1000 == 0 || p == info.len() - 1 {
+                    let progress = (p as f64 / info.len() as f64) * 100_f64;
                     let _ = DatabaseProgress {
                         id: id.to_string(),
-                        progress: (p as f64 / info.len() as f64) * 100_f64,
+                        progress,
                     }
                     .emit(&app);
+                    TaskProgress::new(TaskKind::Database, id.to_string(), progress).send(&app);
                 }
 
                 Some(SiteStatsData {
@@ -1471,9 +1807,11 @@ pub async fn get_players_game_info(
         .into_iter()
         .map(|((site, player), data)| SiteStatsData { site, player, data })
         .collect();
+    game_info.monthly_stats = player_aggregates::monthly_from_games(&game_info.site_stats_data);
 
     // OPTIMIZED: Keep timing info but simplify
     info!("Player stats computed in {:?}", timer.elapsed());
+    TaskProgress::done(TaskKind::Database, id.to_string()).send(&app);
 
     Ok(game_info)
 }
@@ -1492,6 +1830,9 @@ pub async fn delete_database(
     let path_str = file.to_string_lossy().into_owned();
     
     log::info!("Attempting to delete database: {:?}", file);
+
+    // Wait for writes from other windows so the file is not removed under them
+    let _write = state.db_writes.lock(&file, "delete_database").await;
     
     // STEP 1: Cancel any ongoing searches by acquiring all permits
     // This will stop new searches and wait for current ones to complete
@@ -1508,6 +1849,20 @@ pub async fn delete_database(
     drop(permit1);
     drop(permit2);
     
+    // Connections still in use keep the file locked and make the removal below fail
+    if let Some(stats) = pool_stats::pool_stats(&state, &path_str) {
+        if stats.active > 0 {
+            log::warn!(
+                "{} connection(s) to {:?} still in use, the oldest held for {:.0} ms",
+                stats.active,
+                file,
+                stats.longest_hold_ms.unwrap_or_default()
+            );
+        }
+    }
+    state.pool_metrics.remove(&path_str);
+    state.migrated_dbs.remove(&path_str);
+
     // Remove from connection pool - this drops the pool and closes all connections
     if let Some((_, pool)) = state.connection_pool.remove(&path_str) {
         // Force drop the pool to close all connections immediately
@@ -1565,6 +1920,7 @@ pub async fn delete_duplicated_games(
     file: PathBuf,
     state: tauri::State<'_, AppState>,
 ) -> Result<()> {
+    let _write = state.db_writes.lock(&file, "delete_duplicated_games").await;
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
     db.batch_execute(GAMES_DELETE_DUPLICATES)?;
@@ -1578,6 +1934,7 @@ pub async fn delete_empty_games(
     file: PathBuf,
     state: tauri::State<'_, AppState>,
 ) -> Result<()> {
+    let _write = state.db_writes.lock(&file, "delete_empty_games").await;
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
     diesel::delete(games::table.filter(games::ply_count.eq(0))).execute(db)?;
@@ -1603,6 +1960,39 @@ struct PgnGame {
 }
 
 impl PgnGame {
+    fn from_row(
+        (game, white, black, event, site): (Game, Player, Player, Event, Site),
+        format: &PgnFormat,
+        options: &PgnExportOptions,
+    ) -> Result<Self> {
+        let start = game
+            .fen
+            .as_deref()
+            .and_then(|fen| Fen::from_ascii(fen.as_bytes()).ok())
+            .and_then(|fen| Chess::from_setup(fen.into(), CastlingMode::Chess960).ok());
+        let tree = pgn_format::filter_tree(&GameTree::from_bytes(&game.moves, start.clone())?, options);
+        let result = match game.result.as_deref() {
+            Some(result @ ("1-0" | "0-1" | "1/2-1/2")) => result,
+            _ => "*",
+        };
+        Ok(PgnGame {
+            moves: pgn_format::format_movetext(&tree, start, result, format)?,
+            event: event.name,
+            site: site.name,
+            date: game.date,
+            round: game.round,
+            white: white.name,
+            black: black.name,
+            result: game.result,
+            time_control: game.time_control,
+            eco: game.eco,
+            white_elo: game.white_elo.map(|e| e.to_string()),
+            black_elo: game.black_elo.map(|e| e.to_string()),
+            ply_count: game.ply_count.map(|e| e.to_string()),
+            fen: game.fen,
+        })
+    }
+
     fn write(&self, writer: &mut impl Write) -> Result<()> {
         writeln!(
             writer,
@@ -1651,13 +2041,7 @@ impl PgnGame {
             writeln!(writer, "[FEN \"{}\"]", fen)?;
         }
         writeln!(writer)?;
-        writer.write_all(self.moves.as_bytes())?;
-        match self.result.as_deref() {
-            Some("1-0") => writeln!(writer, "1-0"),
-            Some("0-1") => writeln!(writer, "0-1"),
-            Some("1/2-1/2") => writeln!(writer, "1/2-1/2"),
-            _ => writeln!(writer, "*"),
-        }?;
+        writeln!(writer, "{}", self.moves)?;
         writeln!(writer)?;
         Ok(())
     }
@@ -1668,8 +2052,12 @@ impl PgnGame {
 pub async fn export_to_pgn(
     file: PathBuf,
     dest_file: PathBuf,
+    format: Option<PgnFormat>,
+    options: Option<PgnExportOptions>,
     state: tauri::State<'_, AppState>,
 ) -> Result<()> {
+    let format = format.unwrap_or_default();
+    let options = options.unwrap_or_default();
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
     let file = OpenOptions::new()
@@ -1681,42 +2069,20 @@ pub async fn export_to_pgn(
     let mut writer = BufWriter::new(file);
 
     let (white_players, black_players) = diesel::alias!(players as white, players as black);
-    games::table
+    let mut query = games::table
         .inner_join(white_players.on(games::white_id.eq(white_players.field(players::id))))
         .inner_join(black_players.on(games::black_id.eq(black_players.field(players::id))))
         .inner_join(events::table.on(games::event_id.eq(events::id)))
         .inner_join(sites::table.on(games::site_id.eq(sites::id)))
+        .order(games::id)
+        .into_boxed();
+    if let Some(max_games) = options.max_games {
+        query = query.limit(max_games as i64);
+    }
+    query
         .load_iter::<(Game, Player, Player, Event, Site), DefaultLoadingMode>(db)?
         .flatten()
-        .map(|(game, white, black, event, site)| {
-            let pgn = PgnGame {
-                event: event.name,
-                site: site.name,
-                date: game.date,
-                round: game.round,
-                white: white.name,
-                black: black.name,
-                result: game.result,
-                time_control: game.time_control,
-                eco: game.eco,
-                white_elo: game.white_elo.map(|e| e.to_string()),
-                black_elo: game.black_elo.map(|e| e.to_string()),
-                ply_count: game.ply_count.map(|e| e.to_string()),
-                fen: game.fen.clone(),
-                 moves: GameTree::from_bytes(
-                    &game.moves,
-                    game.fen
-                        .map(|fen| Fen::from_ascii(fen.as_bytes()).ok())
-                        .flatten()
-                        .map(|fen| Chess::from_setup(fen.into(), CastlingMode::Chess960).ok())
-                        .flatten()
-                )?.to_string(),
-            };
-
-            pgn.write(&mut writer)?;
-
-            Ok(())
-        })
+        .map(|row| PgnGame::from_row(row, &format, &options)?.write(&mut writer))
         .collect::<Result<Vec<_>>>()?;
     Ok(())
 }
@@ -1727,10 +2093,12 @@ pub async fn export_position_games_to_pgn(
     file: PathBuf,
     fen: String,
     dest_file: PathBuf,
+    format: Option<PgnFormat>,
     app: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
 ) -> Result<()> {
     use crate::db::position_cache::{get_cached_position, normalize_db_path};
+    let format = format.unwrap_or_default();
     
     // Get cached game IDs for this position
     let db_path_str = normalize_db_path(&file);
@@ -1762,35 +2130,7 @@ pub async fn export_position_games_to_pgn(
         .filter(games::id.eq_any(&game_ids))
         .load_iter::<(Game, Player, Player, Event, Site), DefaultLoadingMode>(db)?
         .flatten()
-        .map(|(game, white, black, event, site)| {
-            let pgn = PgnGame {
-                event: event.name,
-                site: site.name,
-                date: game.date,
-                round: game.round,
-                white: white.name,
-                black: black.name,
-                result: game.result,
-                time_control: game.time_control,
-                eco: game.eco,
-                white_elo: game.white_elo.map(|e| e.to_string()),
-                black_elo: game.black_elo.map(|e| e.to_string()),
-                ply_count: game.ply_count.map(|e| e.to_string()),
-                fen: game.fen.clone(),
-                moves: GameTree::from_bytes(
-                    &game.moves,
-                    game.fen
-                        .map(|fen| Fen::from_ascii(fen.as_bytes()).ok())
-                        .flatten()
-                        .map(|fen| Chess::from_setup(fen.into(), CastlingMode::Chess960).ok())
-                        .flatten()
-                )?.to_string(),
-            };
-            
-            pgn.write(&mut writer)?;
-            
-            Ok(())
-        })
+        .map(|row| PgnGame::from_row(row, &format, &PgnExportOptions::default())?.write(&mut writer))
         .collect::<Result<Vec<_>>>()?;
     
     info!("Exported {} games from position {} to PGN", game_ids.len(), fen);
@@ -1803,8 +2143,10 @@ pub async fn export_selected_games_to_pgn(
     file: PathBuf,
     game_ids: Vec<i32>,
     dest_file: PathBuf,
+    format: Option<PgnFormat>,
     state: tauri::State<'_, AppState>,
 ) -> Result<()> {
+    let format = format.unwrap_or_default();
     if game_ids.is_empty() {
         return Err(Error::PackageManager("No games selected".to_string()));
     }
@@ -1828,35 +2170,7 @@ pub async fn export_selected_games_to_pgn(
         .filter(games::id.eq_any(&game_ids))
         .load_iter::<(Game, Player, Player, Event, Site), DefaultLoadingMode>(db)?
         .flatten()
-        .map(|(game, white, black, event, site)| {
-            let pgn = PgnGame {
-                event: event.name,
-                site: site.name,
-                date: game.date,
-                round: game.round,
-                white: white.name,
-                black: black.name,
-                result: game.result,
-                time_control: game.time_control,
-                eco: game.eco,
-                white_elo: game.white_elo.map(|e| e.to_string()),
-                black_elo: game.black_elo.map(|e| e.to_string()),
-                ply_count: game.ply_count.map(|e| e.to_string()),
-                fen: game.fen.clone(),
-                moves: GameTree::from_bytes(
-                    &game.moves,
-                    game.fen
-                        .map(|fen| Fen::from_ascii(fen.as_bytes()).ok())
-                        .flatten()
-                        .map(|fen| Chess::from_setup(fen.into(), CastlingMode::Chess960).ok())
-                        .flatten()
-                )?.to_string(),
-            };
-            
-            pgn.write(&mut writer)?;
-            
-            Ok(())
-        })
+        .map(|row| PgnGame::from_row(row, &format, &PgnExportOptions::default())?.write(&mut writer))
         .collect::<Result<Vec<_>>>()?;
     
     info!("Exported {} selected games to PGN", game_ids.len());
@@ -1870,6 +2184,7 @@ pub async fn delete_db_game(
     game_id: i32,
     state: tauri::State<'_, AppState>,
 ) -> Result<()> {
+    let _write = state.db_writes.lock(&file, "delete_db_game").await;
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
     core::remove_game(db, game_id)?;
@@ -1886,7 +2201,31 @@ pub async fn get_game(
 ) -> Result<NormalizedGame> {
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
-    Ok(core::get_game(db, game_id)?)
+    let mut game = core::get_game(db, game_id)?;
+    sources::attach_sources(db, std::slice::from_mut(&mut game))?;
+    Ok(game)
+}
+
+/// Move text of a game rendered in the user's notation preference, for previews.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_game_display_moves(
+    file: PathBuf,
+    game_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<String> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let game: Game = games::table.find(game_id).first(db)?;
+    let position = match game.fen.as_deref() {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+
+    GameTree::from_bytes(&game.moves, Some(position.clone()))?.to_display_string(Some(position))
 }
 
 #[tauri::command]
@@ -1896,11 +2235,21 @@ pub async fn update_game(
     game_id: i32,
     update: UpdateGame,
     state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
 ) -> Result<()> {
+    ensure_valid(validation::validate_update(&update))?;
+
+    let _write = state.db_writes.lock(&file, "update_game").await;
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
+    let before = core::get_game(db, game_id)?;
     core::update_game(db, game_id, &update)?;
 
+    // The edit is saved either way; a sync log failure only delays syncing it.
+    if let Err(e) = annotation_sync::record_edit(&app, &file, &before, &update) {
+        log::warn!("Failed to record annotation edit for sync: {}", e);
+    }
+
     Ok(())
 }
 
@@ -1912,6 +2261,7 @@ pub async fn merge_players(
     player2: i32,
     state: tauri::State<'_, AppState>,
 ) -> Result<()> {
+    let _write = state.db_writes.lock(&file, "merge_players").await;
     let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
 
     // Check if the players never played against each other
@@ -1934,6 +2284,7 @@ pub async fn merge_players(
         .execute(db)?;
 
     diesel::delete(players::table.filter(players::id.eq(player1))).execute(db)?;
+    player_aggregates::invalidate(db)?;
 
     let player_count: i64 = players::table.count().get_result(db)?;
     diesel::insert_into(info::table)
@@ -1949,6 +2300,89 @@ pub async fn merge_players(
     Ok(())
 }
 
+#[derive(Debug, Default, Serialize, Type)]
+pub struct OrphanCleanup {
+    pub players: usize,
+    pub events: usize,
+    pub sites: usize,
+}
+
+/// Remove players, events and sites that are no longer referenced by any game, e.g. after
+/// deleting games or merging players.
+#[tauri::command]
+#[specta::specta]
+pub async fn cleanup_orphans(
+    file: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<OrphanCleanup> {
+    let _write = state.db_writes.lock(&file, "cleanup_orphans").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let removed = db.transaction::<_, Error, _>(|db| {
+        // Row 0 is the seeded "Unknown" every game without a name points to. Players linked to a
+        // FIDE id or counted in the player aggregates, and the rows kept by a merge that can still
+        // be undone, are in use even without games.
+        let players = diesel::delete(
+            players::table
+                .filter(players::id.ne(0))
+                .filter(players::id.ne_all(games::table.select(games::white_id)))
+                .filter(players::id.ne_all(games::table.select(games::black_id)))
+                .filter(
+                    players::id.ne_all(player_fide_ids::table.select(player_fide_ids::player_id)),
+                )
+                .filter(
+                    players::id.ne_all(
+                        player_monthly_stats::table.select(player_monthly_stats::player_id),
+                    ),
+                ),
+        )
+        .execute(db)?;
+        let events = diesel::delete(
+            events::table
+                .filter(events::id.ne(0))
+                .filter(events::id.ne_all(games::table.select(games::event_id)))
+                .filter(
+                    events::id.ne_all(
+                        merge_journal::table
+                            .filter(merge_journal::kind.eq("event"))
+                            .select(merge_journal::kept_id),
+                    ),
+                ),
+        )
+        .execute(db)?;
+        let sites = diesel::delete(
+            sites::table
+                .filter(sites::id.ne(0))
+                .filter(sites::id.ne_all(games::table.select(games::site_id)))
+                .filter(
+                    sites::id.ne_all(
+                        merge_journal::table
+                            .filter(merge_journal::kind.eq("site"))
+                            .select(merge_journal::kept_id),
+                    ),
+                ),
+        )
+        .execute(db)?;
+
+        update_info_counts(db)?;
+
+        Ok(OrphanCleanup {
+            players,
+            events,
+            sites,
+        })
+    })?;
+
+    info!(
+        "Removed {} orphaned players, {} events and {} sites from {}",
+        removed.players,
+        removed.events,
+        removed.sites,
+        file.display()
+    );
+    Ok(removed)
+}
+
 /// Clear the in-memory game cache to free memory
 /// FIXED: Also clear position search cache to prevent unbounded growth
 #[tauri::command]
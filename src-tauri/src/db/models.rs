@@ -19,6 +19,8 @@ pub struct Puzzle {
     pub themes: Option<String>,
     pub game_url: Option<String>,
     pub opening_tags: Option<String>,
+    /// Lichess variant name (e.g. `crazyhouse`, `atomic`), `None` for standard chess.
+    pub variant: Option<String>,
 }
 
 #[derive(Default, Debug, Queryable, Serialize, Deserialize, Identifiable, Clone, Type)]
@@ -196,6 +198,9 @@ pub struct NormalizedGame {
     #[specta(optional)]
     pub ply_count: Option<i32>,
     pub moves: String,
+    /// Where the game was imported from, if recorded.
+    #[specta(optional)]
+    pub source: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Type)]
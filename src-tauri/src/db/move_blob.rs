@@ -0,0 +1,351 @@
+//! Diagnostics for the binary move format of stored games.
+//!
+//! `games.moves` holds one byte per move (the index of the move in `legal_moves()` of the
+//! position), interleaved with opcodes: `254`/`253` open and close a variation starting from the
+//! position before the last move, `252` is followed by a big-endian `u64` length and a UTF-8
+//! comment, `251` by a NAG byte. `decode_moves_debug` walks a blob opcode by opcode without ever
+//! failing, so the dump shows where a game that does not load goes wrong. `repair_move_blob` cuts a
+//! broken blob back to its longest decodable prefix and closes the variations left open.
+
+use std::path::{Path, PathBuf};
+
+use diesel::prelude::*;
+use serde::Serialize;
+use shakmaty::{fen::Fen, san::SanPlus, CastlingMode, Chess, FromSetup, Position};
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create, move_data::update_move_data, pgn::GameTree, schema::games,
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Type)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum MoveBlobOp {
+    /// A move byte, i.e. an index into the legal moves of the position.
+    Move { index: u8, san: String },
+    Nag { value: u8 },
+    Comment { length: u64, text: String },
+    StartVariation,
+    EndVariation,
+    /// The blob cannot be decoded from here on.
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveBlobEntry {
+    /// Byte offset of the opcode in the blob.
+    pub offset: u32,
+    /// Number of enclosing variations.
+    pub depth: u32,
+    pub op: MoveBlobOp,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveBlobDump {
+    pub length: u32,
+    pub entries: Vec<MoveBlobEntry>,
+    /// Offset of the first byte that cannot be decoded, if any.
+    pub error_offset: Option<u32>,
+    /// Variations still open at the end of the decodable part.
+    pub open_variations: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveBlobRepair {
+    /// Whether the blob was broken and has been rewritten.
+    pub repaired: bool,
+    pub original_length: u32,
+    pub repaired_length: u32,
+    /// Moves left in the main line.
+    pub main_line_moves: u32,
+}
+
+struct Frame {
+    prev: Chess,
+    cur: Chess,
+}
+
+/// Decode a blob opcode by opcode. Decoding stops at the first error, which is recorded as the
+/// last entry.
+fn dump_blob(bytes: &[u8], start: Chess) -> MoveBlobDump {
+    let mut entries = Vec::new();
+    let mut stack = vec![Frame {
+        prev: start.clone(),
+        cur: start,
+    }];
+    let mut error_offset = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let depth = (stack.len() - 1) as u32;
+        let result = match bytes[i] {
+            GameTree::NAG => match bytes.get(i + 1) {
+                Some(&value) => Ok((MoveBlobOp::Nag { value }, 2)),
+                None => Err("NAG without a value".to_string()),
+            },
+            GameTree::COMMENT => match bytes.get(i + 1..i + 9) {
+                Some(length) => {
+                    let length = u64::from_be_bytes(length.try_into().unwrap());
+                    let available = (bytes.len() - i - 9) as u64;
+                    if length > available {
+                        Err(format!(
+                            "Comment of {} bytes, but only {} bytes left",
+                            length, available
+                        ))
+                    } else {
+                        match std::str::from_utf8(&bytes[i + 9..i + 9 + length as usize]) {
+                            Ok(text) => Ok((
+                                MoveBlobOp::Comment {
+                                    length,
+                                    text: text.to_string(),
+                                },
+                                9 + length as usize,
+                            )),
+                            Err(e) => Err(format!("Comment is not valid UTF-8: {}", e)),
+                        }
+                    }
+                }
+                None => Err("Comment without a complete length".to_string()),
+            },
+            GameTree::START_VARIATION => {
+                let prev = stack.last().unwrap().prev.clone();
+                stack.push(Frame {
+                    prev: prev.clone(),
+                    cur: prev,
+                });
+                Ok((MoveBlobOp::StartVariation, 1))
+            }
+            GameTree::END_VARIATION => {
+                if stack.len() > 1 {
+                    stack.pop();
+                    Ok((MoveBlobOp::EndVariation, 1))
+                } else {
+                    Err("End of a variation that was never started".to_string())
+                }
+            }
+            index => {
+                let frame = stack.last_mut().unwrap();
+                let legal = frame.cur.legal_moves();
+                match legal.get(index as usize) {
+                    Some(m) => {
+                        frame.prev = frame.cur.clone();
+                        let san = SanPlus::from_move_and_play_unchecked(&mut frame.cur, m);
+                        Ok((
+                            MoveBlobOp::Move {
+                                index,
+                                san: san.to_string(),
+                            },
+                            1,
+                        ))
+                    }
+                    None => Err(format!(
+                        "Move index {} out of range, the position has {} legal moves",
+                        index,
+                        legal.len()
+                    )),
+                }
+            }
+        };
+        let (op, size) = match result {
+            Ok(decoded) => decoded,
+            Err(message) => (MoveBlobOp::Error { message }, 0),
+        };
+        // A variation's end marker belongs to the enclosing line.
+        let depth = if op == MoveBlobOp::EndVariation {
+            depth - 1
+        } else {
+            depth
+        };
+        entries.push(MoveBlobEntry {
+            offset: i as u32,
+            depth,
+            op,
+        });
+        if size == 0 {
+            error_offset = Some(i as u32);
+            break;
+        }
+        i += size;
+    }
+
+    MoveBlobDump {
+        length: bytes.len() as u32,
+        entries,
+        error_offset,
+        open_variations: (stack.len() - 1) as u32,
+    }
+}
+
+/// The longest decodable prefix of a blob with its open variations closed, or `None` if the
+/// blob decodes as it is.
+fn repair_blob(bytes: &[u8], start: Chess) -> Option<Vec<u8>> {
+    let dump = dump_blob(bytes, start);
+    if dump.error_offset.is_none() && dump.open_variations == 0 {
+        return None;
+    }
+    let mut repaired = bytes[..dump.error_offset.unwrap_or(dump.length) as usize].to_vec();
+    repaired.resize(
+        repaired.len() + dump.open_variations as usize,
+        GameTree::END_VARIATION,
+    );
+    Some(repaired)
+}
+
+fn load_game(
+    file: &Path,
+    game_id: i32,
+    state: &tauri::State<'_, AppState>,
+) -> Result<(Vec<u8>, Chess)> {
+    let db = &mut get_db_or_create(state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first(db)?;
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    Ok((moves, start))
+}
+
+/// Opcode-level dump of the moves of a game, to find out why a game fails to decode.
+#[tauri::command]
+#[specta::specta]
+pub async fn decode_moves_debug(
+    file: PathBuf,
+    game_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<MoveBlobDump> {
+    let (moves, start) = load_game(&file, game_id, &state)?;
+    Ok(dump_blob(&moves, start))
+}
+
+/// Cut the moves of a game back to the part that still decodes, e.g. after a truncated write.
+/// Everything after the first broken opcode is lost; intact blobs are left untouched.
+#[tauri::command]
+#[specta::specta]
+pub async fn repair_move_blob(
+    file: PathBuf,
+    game_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<MoveBlobRepair> {
+    let _write = state.db_writes.lock(&file, "repair_move_blob").await;
+    let (moves, start) = load_game(&file, game_id, &state)?;
+    let Some(repaired) = repair_blob(&moves, start.clone()) else {
+        let tree = GameTree::from_bytes(&moves, Some(start))?;
+        return Ok(MoveBlobRepair {
+            repaired: false,
+            original_length: moves.len() as u32,
+            repaired_length: moves.len() as u32,
+            main_line_moves: tree.count_main_line_moves() as u32,
+        });
+    };
+    let tree = GameTree::from_bytes(&repaired, Some(start)).map_err(|_| {
+        Error::PackageManager(format!("Moves of game {} could not be repaired", game_id))
+    })?;
+    let main_line_moves = tree.count_main_line_moves() as u32;
+
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    diesel::update(games::table.filter(games::id.eq(game_id)))
+        .set((
+            games::moves.eq(&repaired),
+            games::ply_count.eq(main_line_moves as i32),
+        ))
+        .execute(db)?;
+    update_move_data(db, game_id, &tree)?;
+    log::warn!(
+        "Repaired moves of game {} in {}: {} of {} bytes kept",
+        game_id,
+        file.display(),
+        repaired.len(),
+        moves.len()
+    );
+
+    Ok(MoveBlobRepair {
+        repaired: true,
+        original_length: moves.len() as u32,
+        repaired_length: repaired.len() as u32,
+        main_line_moves,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::pgn::GameTreeNode;
+    use pgn_reader::Nag;
+
+    fn sample_blob() -> Vec<u8> {
+        let san = |s: &str| GameTreeNode::Move(s.parse().unwrap());
+        let mut variation = GameTree::new();
+        variation.push(san("d4"));
+        let mut tree = GameTree::new();
+        tree.push(san("e4"));
+        tree.push(GameTreeNode::Nag(Nag(1)));
+        tree.push(GameTreeNode::Variation(variation));
+        tree.push(GameTreeNode::Comment("King's pawn".to_string()));
+        tree.push(san("e5"));
+        let mut bytes = Vec::new();
+        tree.encode(&mut bytes, None);
+        bytes
+    }
+
+    #[test]
+    fn test_dump_blob() {
+        let bytes = sample_blob();
+        let dump = dump_blob(&bytes, Chess::default());
+        assert_eq!(dump.error_offset, None);
+        assert_eq!(dump.open_variations, 0);
+        let ops: Vec<(u32, u32, &MoveBlobOp)> = dump
+            .entries
+            .iter()
+            .map(|entry| (entry.offset, entry.depth, &entry.op))
+            .collect();
+        assert!(matches!(ops[0], (0, 0, MoveBlobOp::Move { san, .. }) if san == "e4"));
+        assert_eq!(ops[1], (1, 0, &MoveBlobOp::Nag { value: 1 }));
+        assert_eq!(ops[2], (3, 0, &MoveBlobOp::StartVariation));
+        assert!(matches!(ops[3], (4, 1, MoveBlobOp::Move { san, .. }) if san == "d4"));
+        assert_eq!(ops[4], (5, 0, &MoveBlobOp::EndVariation));
+        assert!(matches!(ops[5], (6, 0, MoveBlobOp::Comment { length: 11, .. })));
+        assert!(matches!(ops[6], (26, 0, MoveBlobOp::Move { san, .. }) if san == "e5"));
+        assert_eq!(repair_blob(&bytes, Chess::default()), None);
+    }
+
+    #[test]
+    fn test_truncated_blob() {
+        let bytes = sample_blob();
+        // Cut in the middle of the comment.
+        let truncated = &bytes[..12];
+        let dump = dump_blob(truncated, Chess::default());
+        assert_eq!(dump.error_offset, Some(6));
+        assert!(matches!(dump.entries.last().unwrap().op, MoveBlobOp::Error { .. }));
+
+        let repaired = repair_blob(truncated, Chess::default()).unwrap();
+        assert_eq!(repaired, &bytes[..6]);
+        let tree = GameTree::from_bytes(&repaired, None).unwrap();
+        assert_eq!(tree.count_main_line_moves(), 1);
+
+        // Cut inside the variation: it gets closed.
+        let repaired = repair_blob(&bytes[..5], Chess::default()).unwrap();
+        assert_eq!(repaired.last(), Some(&GameTree::END_VARIATION));
+        assert!(GameTree::from_bytes(&repaired, None).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_move_index() {
+        let dump = dump_blob(&[12, 200], Chess::default());
+        assert_eq!(dump.error_offset, Some(1));
+        assert_eq!(repair_blob(&[12, 200], Chess::default()), Some(vec![12]));
+    }
+}
@@ -0,0 +1,263 @@
+//! Structured `[%eval]`, `[%clk]` and `[%emt]` data of stored games.
+//!
+//! Lichess exports and engine analysis carry evaluations and clock times as commands inside move
+//! comments. They are extracted into the `GameMoveData` table when a game is imported and
+//! rewritten whenever the moves of a game change, so evaluation graphs and time statistics can
+//! query them directly. Games imported before the table existed are parsed the first time their
+//! data is requested.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use serde::Serialize;
+use shakmaty::{fen::Fen, CastlingMode, Chess, FromSetup};
+use specta::Type;
+
+use crate::{
+    db::{
+        eval_comment::{parse_eval_comment, EvalScore},
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode},
+        schema::{game_move_data, games},
+        ConnectionOptions,
+    },
+    error::Result,
+    AppState,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveData {
+    /// 1 for the first move of the game.
+    pub ply: i32,
+    /// Centipawns from white's point of view.
+    pub eval: Option<i32>,
+    /// Moves to mate, positive when white mates.
+    pub mate: Option<i32>,
+    /// Time left after the move, in milliseconds.
+    pub clock_ms: Option<i32>,
+    /// Time spent on the move, in milliseconds.
+    pub emt_ms: Option<i32>,
+}
+
+impl MoveData {
+    fn is_empty(&self) -> bool {
+        self.eval.is_none()
+            && self.mate.is_none()
+            && self.clock_ms.is_none()
+            && self.emt_ms.is_none()
+    }
+}
+
+/// Argument of the `[%name ...]` command in `comment`.
+fn command<'a>(comment: &'a str, name: &str) -> Option<&'a str> {
+    let tag = format!("[%{} ", name);
+    let start = comment.find(&tag)? + tag.len();
+    let rest = &comment[start..];
+    Some(rest[..rest.find(']')?].trim())
+}
+
+/// Milliseconds of an `h:mm:ss` or `h:mm:ss.f` time.
+fn parse_time(value: &str) -> Option<i32> {
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        let part: f64 = part.trim().parse().ok()?;
+        seconds = seconds * 60.0 + part;
+    }
+    (seconds >= 0.0).then(|| (seconds * 1000.0).round() as i32)
+}
+
+/// Centipawns or mate distance stored for an `[%eval]` score.
+fn eval_columns(score: EvalScore) -> (Option<i32>, Option<i32>) {
+    match score {
+        EvalScore::Pawns(pawns) => (Some((pawns * 100.0).round() as i32), None),
+        EvalScore::Mate { moves, .. } => (None, Some(moves)),
+    }
+}
+
+/// Data of the main-line moves of `tree` that have any.
+pub(super) fn tree_move_data(tree: &GameTree) -> Vec<MoveData> {
+    let mut data: Vec<MoveData> = Vec::new();
+    let mut ply = 0;
+    for node in tree.nodes() {
+        match node {
+            GameTreeNode::Move(_) => ply += 1,
+            GameTreeNode::Comment(comment) if ply > 0 => {
+                if data.last().is_none_or(|d| d.ply != ply) {
+                    data.push(MoveData {
+                        ply,
+                        ..Default::default()
+                    });
+                }
+                let entry = data.last_mut().unwrap();
+                if let Some((eval, mate)) = parse_eval_comment(comment).map(eval_columns) {
+                    entry.eval = eval;
+                    entry.mate = mate;
+                }
+                if let Some(clock) = command(comment, "clk").and_then(parse_time) {
+                    entry.clock_ms = Some(clock);
+                }
+                if let Some(emt) = command(comment, "emt").and_then(parse_time) {
+                    entry.emt_ms = Some(emt);
+                }
+            }
+            _ => {}
+        }
+    }
+    data.retain(|d| !d.is_empty());
+    data
+}
+
+fn insert_move_data(db: &mut SqliteConnection, game_id: i32, data: &[MoveData]) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let rows: Vec<_> = data
+        .iter()
+        .map(|d| {
+            (
+                game_move_data::game_id.eq(game_id),
+                game_move_data::ply.eq(d.ply),
+                game_move_data::eval.eq(d.eval),
+                game_move_data::mate.eq(d.mate),
+                game_move_data::clock_ms.eq(d.clock_ms),
+                game_move_data::emt_ms.eq(d.emt_ms),
+            )
+        })
+        .collect();
+    diesel::insert_or_ignore_into(game_move_data::table)
+        .values(&rows)
+        .execute(db)?;
+    Ok(())
+}
+
+/// Records the data of a newly imported game, stored as `game_id`.
+pub(super) fn record_move_data(
+    db: &mut SqliteConnection,
+    game_id: i32,
+    tree: &GameTree,
+) -> Result<()> {
+    let data = tree_move_data(tree);
+    if data.is_empty() {
+        return Ok(());
+    }
+    insert_move_data(db, game_id, &data)
+}
+
+/// Replaces the data of `game_id` after its moves were rewritten to `tree`.
+pub(super) fn update_move_data(
+    db: &mut SqliteConnection,
+    game_id: i32,
+    tree: &GameTree,
+) -> Result<()> {
+    diesel::delete(game_move_data::table.filter(game_move_data::game_id.eq(game_id)))
+        .execute(db)?;
+    insert_move_data(db, game_id, &tree_move_data(tree))
+}
+
+/// Evaluation and clock data of the main-line moves of a game.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_game_move_data(
+    file: PathBuf,
+    game_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<MoveData>> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let stored: Vec<(i32, Option<i32>, Option<i32>, Option<i32>, Option<i32>)> =
+        game_move_data::table
+            .filter(game_move_data::game_id.eq(game_id))
+            .select((
+                game_move_data::ply,
+                game_move_data::eval,
+                game_move_data::mate,
+                game_move_data::clock_ms,
+                game_move_data::emt_ms,
+            ))
+            .order(game_move_data::ply)
+            .load(db)?;
+    if !stored.is_empty() {
+        return Ok(stored
+            .into_iter()
+            .map(|(ply, eval, mate, clock_ms, emt_ms)| MoveData {
+                ply,
+                eval,
+                mate,
+                clock_ms,
+                emt_ms,
+            })
+            .collect());
+    }
+
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first(db)?;
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let data = tree_move_data(&GameTree::from_bytes(&moves, Some(start))?);
+    insert_move_data(db, game_id, &data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::pgn::Importer;
+    use pgn_reader::BufferedReader;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse_time("0:05:00"), Some(300_000));
+        assert_eq!(parse_time("1:02:03.5"), Some(3_723_500));
+        assert_eq!(parse_time("soon"), None);
+        assert_eq!(eval_columns(EvalScore::Pawns(0.35)), (Some(35), None));
+        assert_eq!(
+            eval_columns(EvalScore::Mate {
+                moves: -3,
+                white_mates: false
+            }),
+            (None, Some(-3))
+        );
+        assert_eq!(command("[%clk 0:01:00] [%eval -1.2]", "eval"), Some("-1.2"));
+    }
+
+    #[test]
+    fn test_tree_move_data() {
+        let pgn = "1. e4 { [%eval 0.2] [%clk 0:03:00] } 1... e5 { [%clk 0:02:58] [%emt 0:00:02] } \
+                   2. Nf3 { good } ( 2. Qh5 { [%eval -0.5] } ) 2... Nc6 { [%eval #4] } *";
+        let mut reader = BufferedReader::new_cursor(pgn);
+        let game = reader
+            .read_game(&mut Importer::new(None))
+            .unwrap()
+            .flatten()
+            .unwrap();
+        assert_eq!(
+            tree_move_data(&game.tree),
+            vec![
+                MoveData {
+                    ply: 1,
+                    eval: Some(20),
+                    clock_ms: Some(180_000),
+                    ..Default::default()
+                },
+                MoveData {
+                    ply: 2,
+                    clock_ms: Some(178_000),
+                    emt_ms: Some(2_000),
+                    ..Default::default()
+                },
+                MoveData {
+                    ply: 4,
+                    mate: Some(4),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+}
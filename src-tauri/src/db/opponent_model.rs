@@ -0,0 +1,245 @@
+//! Simulated opponents for play against the engine.
+//!
+//! An opponent model holds the opening moves one player chose with one color in a database,
+//! counted per position. While the game stays in positions the player reached, the engine's move
+//! is sampled from their choices weighted by how often they made them; once it leaves them,
+//! `sample_opponent_move` returns nothing and the engine plays on its own. Models are built once
+//! and kept in `AppState` until `end_opponent_model`.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use diesel::{connection::DefaultLoadingMode, prelude::*};
+use rand::{seq::SliceRandom, Rng};
+use serde::Serialize;
+use shakmaty::{
+    fen::Fen,
+    san::San,
+    zobrist::{Zobrist64, ZobristHash},
+    CastlingMode, Chess, Color, EnPassantMode, FromSetup, Move, Position,
+};
+use specta::Type;
+
+use crate::{
+    db::{encoding::extract_main_line_moves, get_db_or_create, schema::games, ConnectionOptions},
+    error::{Error, Result},
+    repertoire::parse_color,
+    AppState,
+};
+
+/// Plies of each game taken into the model; later moves are left to the engine.
+const MODEL_PLIES: usize = 30;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct OpponentMove {
+    pub uci: String,
+    pub san: String,
+    /// Games in which the player chose this move here.
+    pub games: i32,
+    /// Share of the player's games from this position that continued with this move.
+    pub frequency: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct OpponentModelInfo {
+    pub model_id: String,
+    /// Games of the player with the model's color.
+    pub games: i32,
+    /// Positions with at least one move of the player.
+    pub positions: i32,
+}
+
+struct MoveCount {
+    m: Move,
+    games: i32,
+}
+
+#[derive(Default)]
+pub struct OpponentModel {
+    games: i32,
+    moves: HashMap<Zobrist64, Vec<MoveCount>>,
+}
+
+fn hash_of(position: &Chess) -> Zobrist64 {
+    position.zobrist_hash(EnPassantMode::Legal)
+}
+
+fn start_position(fen: Option<&str>) -> Option<Chess> {
+    match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse().ok()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960).ok()
+        }
+        None => Some(Chess::default()),
+    }
+}
+
+impl OpponentModel {
+    /// Counts the moves of `color` in the first `MODEL_PLIES` plies of a game.
+    fn add_game(&mut self, start: Chess, moves: &[Move], color: Color) {
+        self.games += 1;
+        let mut position = start;
+        for m in moves.iter().take(MODEL_PLIES) {
+            if position.turn() == color {
+                let counts = self.moves.entry(hash_of(&position)).or_default();
+                match counts.iter_mut().find(|count| count.m == *m) {
+                    Some(count) => count.games += 1,
+                    None => counts.push(MoveCount {
+                        m: m.clone(),
+                        games: 1,
+                    }),
+                }
+            }
+            position.play_unchecked(m);
+        }
+    }
+
+    fn sample(&self, position: &Chess, rng: &mut impl Rng) -> Option<OpponentMove> {
+        let counts = self.moves.get(&hash_of(position))?;
+        let total: i32 = counts.iter().map(|count| count.games).sum();
+        let chosen = counts.choose_weighted(rng, |count| count.games).ok()?;
+        Some(OpponentMove {
+            uci: chosen.m.to_uci(CastlingMode::Standard).to_string(),
+            san: San::from_move(position, &chosen.m).to_string(),
+            games: chosen.games,
+            frequency: chosen.games as f64 / total as f64,
+        })
+    }
+}
+
+/// Build the opening model of `player_id` playing `color` ("white" or "black") in `file`.
+#[tauri::command]
+#[specta::specta]
+pub async fn start_opponent_model(
+    file: PathBuf,
+    player_id: i32,
+    color: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<OpponentModelInfo> {
+    let color = parse_color(Some(&color))?.unwrap_or(Color::White);
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let mut query = games::table.select((games::moves, games::fen)).into_boxed();
+    query = match color {
+        Color::White => query.filter(games::white_id.eq(player_id)),
+        Color::Black => query.filter(games::black_id.eq(player_id)),
+    };
+
+    let mut model = OpponentModel::default();
+    for row in query.load_iter::<(Vec<u8>, Option<String>), DefaultLoadingMode>(db)? {
+        let (moves, fen) = row?;
+        let Some(start) = start_position(fen.as_deref()) else {
+            continue;
+        };
+        let Ok(moves) = extract_main_line_moves(&moves, Some(start.clone())) else {
+            continue;
+        };
+        model.add_game(start, &moves, color);
+    }
+    if model.games == 0 {
+        return Err(Error::PackageManager(format!(
+            "The player has no games with {}",
+            match color {
+                Color::White => "white",
+                Color::Black => "black",
+            }
+        )));
+    }
+
+    let model_id = uuid::Uuid::new_v4().to_string();
+    let info = OpponentModelInfo {
+        model_id: model_id.clone(),
+        games: model.games,
+        positions: model.moves.len() as i32,
+    };
+    state.opponent_models.insert(model_id, model);
+    Ok(info)
+}
+
+/// The simulated opponent's move in `fen`, or `None` when the player never reached it and the
+/// engine should choose.
+#[tauri::command]
+#[specta::specta]
+pub async fn sample_opponent_move(
+    model_id: String,
+    fen: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<OpponentMove>> {
+    let model = state
+        .opponent_models
+        .get(&model_id)
+        .ok_or_else(|| Error::PackageManager(format!("No opponent model with id {}", model_id)))?;
+    let fen: Fen = fen.parse()?;
+    let position: Chess = fen.into_position(CastlingMode::Chess960)?;
+    Ok(model.sample(&position, &mut rand::thread_rng()))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn end_opponent_model(
+    model_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<bool> {
+    Ok(state.opponent_models.remove(&model_id).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use shakmaty::san::SanPlus;
+
+    fn line(sans: &[&str]) -> Vec<Move> {
+        let mut position = Chess::default();
+        sans.iter()
+            .map(|san| {
+                let m = san
+                    .parse::<SanPlus>()
+                    .unwrap()
+                    .san
+                    .to_move(&position)
+                    .unwrap();
+                position.play_unchecked(&m);
+                m
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_model_counts_only_the_players_moves() {
+        let mut model = OpponentModel::default();
+        model.add_game(Chess::default(), &line(&["e4", "c5", "Nf3"]), Color::Black);
+        model.add_game(Chess::default(), &line(&["e4", "e6", "d4"]), Color::Black);
+        model.add_game(Chess::default(), &line(&["e4", "c5", "Nc3"]), Color::Black);
+        assert_eq!(model.games, 3);
+        assert_eq!(model.moves.len(), 1);
+
+        let after_e4 = {
+            let mut position = Chess::default();
+            position.play_unchecked(&line(&["e4"])[0]);
+            position
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        let sampled = model.sample(&after_e4, &mut rng).unwrap();
+        let expected = if sampled.san == "c5" { 2 } else { 1 };
+        assert_eq!(sampled.games, expected);
+        assert!((sampled.frequency - expected as f64 / 3.0).abs() < 1e-9);
+        assert!(model.sample(&Chess::default(), &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_sample_follows_frequencies() {
+        let mut model = OpponentModel::default();
+        for _ in 0..9 {
+            model.add_game(Chess::default(), &line(&["d4"]), Color::White);
+        }
+        model.add_game(Chess::default(), &line(&["c4"]), Color::White);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let d4 = (0..1000)
+            .filter(|_| model.sample(&Chess::default(), &mut rng).unwrap().uci == "d2d4")
+            .count();
+        assert!((850..=950).contains(&d4), "{}", d4);
+    }
+}
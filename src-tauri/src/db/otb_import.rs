@@ -0,0 +1,320 @@
+//! Guided import of a player's over-the-board games from event PGNs.
+//!
+//! FIDE and national federations publish the games of rated events as PGN files, usually one per
+//! event. `import_otb_events` downloads each file with `download_file` and imports its games under
+//! the event's name, with the URL as their source so an event can be removed again with
+//! `delete_games_by_source`. The user's games are recognized by the `WhiteFideId`/`BlackFideId`
+//! headers federation PGNs carry, or by name when those are missing, and their side is renamed to
+//! the user's name so the whole OTB history hangs off one player entry. The FIDE ID of that entry
+//! is kept in `player_fide_ids`.
+
+use std::path::PathBuf;
+
+use diesel::{
+    connection::SimpleConnection,
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Integer},
+};
+use pgn_reader::BufferedReader;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::Manager;
+
+use crate::{
+    db::{
+        core, get_db_or_create, insert_to_db,
+        ops::create_player,
+        pgn::{Importer, TempGame},
+        player_aggregates, sources, update_info_counts, ConnectionOptions, INDEXES_SQL,
+    },
+    error::{Error, Result},
+    fs::download_file,
+    progress::{TaskKind, TaskProgress},
+    AppState,
+};
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct OtbEvent {
+    pub url: String,
+    /// Event name to file the games under; defaults to the `Event` header of the first game.
+    #[specta(optional)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct OtbPlayer {
+    pub name: String,
+    pub fide_id: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct OtbEventImport {
+    pub url: String,
+    pub name: String,
+    pub games: i32,
+    /// Games of the user among them.
+    pub player_games: i32,
+    /// Why the event could not be imported; the other events are imported regardless.
+    #[specta(optional)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct OtbImportSummary {
+    pub player_id: i32,
+    pub events: Vec<OtbEventImport>,
+}
+
+/// Lowercase words of a name, so `Carlsen, Magnus` and `Magnus Carlsen` compare equal.
+fn name_words(name: &str) -> Vec<String> {
+    let mut words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.sort();
+    words
+}
+
+fn is_player(player: &OtbPlayer, fide_id: Option<u32>, name: Option<&str>) -> bool {
+    match fide_id {
+        Some(id) => id == player.fide_id,
+        None => name.is_some_and(|name| name_words(name) == name_words(&player.name)),
+    }
+}
+
+/// Files the games under `event` and renames the user's side. Returns the user's game count.
+fn prepare_games(games: &mut [TempGame], event: &str, player: &OtbPlayer) -> i32 {
+    let mut player_games = 0;
+    for game in games.iter_mut() {
+        game.event_name = Some(event.to_string());
+        let white = is_player(player, game.white_fide_id, game.white_name.as_deref());
+        let black = is_player(player, game.black_fide_id, game.black_name.as_deref());
+        if white {
+            game.white_name = Some(player.name.clone());
+        }
+        if black {
+            game.black_name = Some(player.name.clone());
+        }
+        if white || black {
+            player_games += 1;
+        }
+    }
+    player_games
+}
+
+fn url_stem(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file = path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(path);
+    file.trim_end_matches(".pgn").to_string()
+}
+
+async fn import_event(
+    db: &mut SqliteConnection,
+    index: usize,
+    event: &OtbEvent,
+    player: &OtbPlayer,
+    app: &tauri::AppHandle,
+) -> Result<OtbEventImport> {
+    let path = app
+        .path()
+        .app_cache_dir()?
+        .join("otb_import")
+        .join(format!("{}.pgn", uuid::Uuid::new_v4()));
+    download_file(
+        format!("otb_import_{}", index),
+        event.url.clone(),
+        path.clone(),
+        app.clone(),
+        None,
+        None,
+        None,
+    )
+    .await?;
+    let bytes = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    let bytes = bytes?;
+
+    let mut importer = Importer::new(None).lenient(true);
+    let mut games: Vec<TempGame> = BufferedReader::new_cursor(&bytes[..])
+        .into_iter(&mut importer)
+        .flatten()
+        .flatten()
+        .collect();
+    if games.is_empty() {
+        return Err(Error::NoMovesFound);
+    }
+
+    let name = event
+        .name
+        .clone()
+        .filter(|name| !name.trim().is_empty())
+        .or_else(|| games[0].event_name.clone())
+        .filter(|name| !name.trim().is_empty() && name != "?")
+        .unwrap_or_else(|| url_stem(&event.url));
+    let player_games = prepare_games(&mut games, &name, player);
+
+    let last_id = sources::last_game_id(db)?;
+    db.transaction::<_, Error, _>(|db| {
+        for game in &games {
+            insert_to_db(db, game)?;
+        }
+        Ok(())
+    })?;
+    sources::record_import(db, last_id, &event.url)?;
+
+    Ok(OtbEventImport {
+        url: event.url.clone(),
+        name,
+        games: games.len() as i32,
+        player_games,
+        error: None,
+    })
+}
+
+/// Downloads the PGNs of OTB events and imports them into `db_path`, creating it if needed, with
+/// the user's games linked to one player entry.
+#[tauri::command]
+#[specta::specta]
+pub async fn import_otb_events(
+    db_path: PathBuf,
+    events: Vec<OtbEvent>,
+    player: OtbPlayer,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<OtbImportSummary> {
+    let _write = state.db_writes.lock(&db_path, "import_otb_events").await;
+    let job = state.jobs.start(
+        &app,
+        TaskKind::Import,
+        db_path.to_string_lossy(),
+        format!("Importing the OTB games of {}", player.name),
+    );
+    let needs_init = !db_path.exists();
+    let db = &mut get_db_or_create(
+        &state,
+        db_path.to_str().unwrap(),
+        ConnectionOptions::default(),
+    )?;
+    if needs_init {
+        core::init_db(
+            db,
+            "OTB games",
+            &format!("Over-the-board games of {}", player.name),
+        )?;
+    }
+
+    let progress_id = db_path.to_string_lossy();
+    let mut imports = Vec::with_capacity(events.len());
+    for (index, event) in events.iter().enumerate() {
+        if job.is_cancelled() {
+            break;
+        }
+        TaskProgress::new(
+            TaskKind::Import,
+            progress_id.clone(),
+            index as f64 * 100.0 / events.len() as f64,
+        )
+        .message(format!("Importing {}", event.url))
+        .send(&app);
+
+        let import = import_event(db, index, event, &player, &app)
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to import OTB event {}: {}", event.url, e);
+                OtbEventImport {
+                    url: event.url.clone(),
+                    name: event.name.clone().unwrap_or_default(),
+                    games: 0,
+                    player_games: 0,
+                    error: Some(e.to_string()),
+                }
+            });
+        imports.push(import);
+    }
+
+    let player_id = create_player(db, &player.name)?.id;
+    sql_query("DELETE FROM player_fide_ids WHERE fide_id = ? AND player_id != ?")
+        .bind::<BigInt, _>(player.fide_id as i64)
+        .bind::<Integer, _>(player_id)
+        .execute(db)?;
+    sql_query("INSERT OR REPLACE INTO player_fide_ids (player_id, fide_id) VALUES (?, ?)")
+        .bind::<Integer, _>(player_id)
+        .bind::<BigInt, _>(player.fide_id as i64)
+        .execute(db)?;
+
+    if needs_init {
+        db.batch_execute(INDEXES_SQL)?;
+    }
+    update_info_counts(db)?;
+    player_aggregates::update_after_import(db)?;
+
+    let total: i32 = imports.iter().map(|import| import.games).sum();
+    TaskProgress::done(TaskKind::Import, progress_id)
+        .message(format!("{} games imported", total))
+        .send(&app);
+
+    Ok(OtbImportSummary {
+        player_id,
+        events: imports,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player() -> OtbPlayer {
+        OtbPlayer {
+            name: "Magnus Carlsen".to_string(),
+            fide_id: 1503014,
+        }
+    }
+
+    #[test]
+    fn test_is_player() {
+        let player = player();
+        assert!(is_player(&player, Some(1503014), Some("Carlsen, M.")));
+        assert!(!is_player(&player, Some(4100018), Some("Magnus Carlsen")));
+        assert!(is_player(&player, None, Some("Carlsen, Magnus")));
+        assert!(!is_player(&player, None, Some("Carlsen, M.")));
+    }
+
+    #[test]
+    fn test_prepare_games() {
+        let mut games = vec![
+            TempGame {
+                white_name: Some("Carlsen, M.".to_string()),
+                white_fide_id: Some(1503014),
+                black_name: Some("Nepomniachtchi, Ian".to_string()),
+                event_name: Some("?".to_string()),
+                ..Default::default()
+            },
+            TempGame {
+                white_name: Some("Caruana, Fabiano".to_string()),
+                black_name: Some("Nakamura, Hikaru".to_string()),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(prepare_games(&mut games, "Norway Chess", &player()), 1);
+        assert_eq!(games[0].white_name.as_deref(), Some("Magnus Carlsen"));
+        assert_eq!(games[1].event_name.as_deref(), Some("Norway Chess"));
+    }
+
+    #[test]
+    fn test_url_stem() {
+        assert_eq!(
+            url_stem("https://example.org/events/olympiad2024.pgn?download=1"),
+            "olympiad2024"
+        );
+    }
+}
@@ -4,6 +4,8 @@ use shakmaty::{
 use pgn_reader::{Nag, RawComment, RawHeader, SanPlus, Skip, Visitor};
 use chrono::{NaiveDate, NaiveTime};
 use crate::error::{Error, Result};
+use super::lenient::ImportCorrection;
+use crate::notation::{current_locale, localize_san, NotationLocale};
 
 pub type MaterialCount = ByColor<u8>;
 
@@ -29,10 +31,10 @@ pub enum GameTreeNode {
 pub struct GameTree(Vec<GameTreeNode>);
 
 impl GameTree {
-    const START_VARIATION: u8 = 254;
-    const END_VARIATION: u8 = 253;
-    const COMMENT: u8 = 252;
-    const NAG: u8 = 251; 
+    pub(super) const START_VARIATION: u8 = 254;
+    pub(super) const END_VARIATION: u8 = 253;
+    pub(super) const COMMENT: u8 = 252;
+    pub(super) const NAG: u8 = 251; 
 
 
     pub fn new() -> Self {
@@ -56,6 +58,11 @@ impl GameTree {
     pub fn nodes(&self) -> &Vec<GameTreeNode> {
         &self.0
     }
+
+    /// Take the inner nodes, e.g. to rebuild the tree with extra annotations.
+    pub fn into_nodes(self) -> Vec<GameTreeNode> {
+        self.0
+    }
  
     pub fn encode(&self, bytes: &mut Vec<u8>, position: Option<Chess>) {
         let mut cur_position = position.unwrap_or_default();
@@ -140,6 +147,17 @@ impl GameTree {
     }
 
     pub fn pretty_print(&self, writer: &mut std::fmt::Formatter<'_>, position: Option<Chess>) -> Result<()> {
+        self.pretty_print_localized(writer, position, NotationLocale::English)
+    }
+
+    /// Like `pretty_print`, but renders piece letters for the given notation locale.
+    /// Only use the result for display; it is not valid PGN unless `locale` is English.
+    pub fn pretty_print_localized(
+        &self,
+        writer: &mut impl std::fmt::Write,
+        position: Option<Chess>,
+        locale: NotationLocale,
+    ) -> Result<()> {
         let mut cur_position = position.unwrap_or_default();
         let mut prev_position = cur_position.clone();
 
@@ -149,19 +167,20 @@ impl GameTree {
             match item {
                 GameTreeNode::Move(m) => {
                     let i = cur_position.fullmoves().get();
+                    let san = localize_san(&m.to_string(), locale);
                     
                     if is_beginning {
                         is_beginning = false;
                         
                         if cur_position.turn().is_white() {
-                            write!(writer, "{}.{}", i, m)?;
+                            write!(writer, "{}.{}", i, san)?;
                         } else {
-                            write!(writer, "{}...{}", i, m)?;
+                            write!(writer, "{}...{}", i, san)?;
                         }
                     } else if cur_position.turn().is_white() {
-                        write!(writer, " {}.{}", i, m)?;
+                        write!(writer, " {}.{}", i, san)?;
                     } else {
-                        write!(writer, " {}", m)?;
+                        write!(writer, " {}", san)?;
                     }
 
                     prev_position = cur_position.clone();
@@ -176,7 +195,7 @@ impl GameTree {
                 },
                 GameTreeNode::Variation(branch) => {
                     writer.write_str(" ( ")?;
-                    branch.pretty_print(writer,  Some(prev_position.clone()))?;
+                    branch.pretty_print_localized(writer, Some(prev_position.clone()), locale)?;
                     writer.write_str(" ) ")?;
                     is_beginning = true;
                 }
@@ -185,8 +204,16 @@ impl GameTree {
         
         Ok(())
     }
+
+    /// Move text in the user's notation preference, for display only.
+    pub fn to_display_string(&self, position: Option<Chess>) -> Result<String> {
+        let mut out = String::new();
+        self.pretty_print_localized(&mut out, position, current_locale())?;
+        Ok(out)
+    }
 }
 
+/// Always standard SAN: this is the PGN serialization consumed by parsers and exports.
 impl std::fmt::Display for GameTree {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.pretty_print(f, None) {
@@ -212,7 +239,12 @@ pub struct TempGame {
     pub white_elo: Option<i32>,
     pub black_name: Option<String>,
     pub black_elo: Option<i32>,
+    /// FIDE IDs from the `WhiteFideId`/`BlackFideId` headers of federation PGNs.
+    pub white_fide_id: Option<u32>,
+    pub black_fide_id: Option<u32>,
     pub result: Option<String>,
+    /// `Termination` header, e.g. `Time forfeit` on Lichess or `X won on time` on Chess.com.
+    pub termination: Option<String>,
     pub time_control: Option<String>,
     pub eco: Option<String>,
     pub fen: Option<String>,
@@ -222,11 +254,26 @@ pub struct TempGame {
     pub tree: GameTree,
 }
 
+impl TempGame {
+    /// Unix time the game started at, from its date and `UTCTime` headers.
+    pub fn timestamp(&self) -> Option<i64> {
+        let date = NaiveDate::parse_from_str(self.date.as_ref()?, "%Y.%m.%d").ok()?;
+        let time = NaiveTime::parse_from_str(self.time.as_ref()?, "%H:%M:%S").ok()?;
+        Some(date.and_time(time).and_utc().timestamp())
+    }
+}
+
 pub struct Importer {
     game: TempGame,
     variants: Vec<GameTree>,
     timestamp: Option<i64>,
     skip: bool,
+    /// Keep games with an illegal move or no result instead of dropping them.
+    lenient: bool,
+    /// Games seen so far, counting skipped ones.
+    game_count: u32,
+    /// Fixes made to the games imported so far, in lenient mode.
+    pub corrections: Vec<ImportCorrection>,
 }
 
 
@@ -237,9 +284,52 @@ impl Importer {
             variants: Vec::new(),
             timestamp,
             skip: false,
+            lenient: false,
+            game_count: 0,
+            corrections: Vec::new(),
         }
     }
 
+    /// Recover from mistakes in hand-typed games, see `lenient::normalize_pgn`.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    fn correct(&mut self, message: String) {
+        self.corrections.push(ImportCorrection {
+            game: self.game_count,
+            message,
+        });
+    }
+
+    /// Cut the main line at its first illegal move. Returns whether moves were dropped.
+    fn truncate_at_illegal_move(&mut self) -> bool {
+        let mut position = self.game.position.clone();
+        let mut plies = 0;
+        for (i, node) in self.game.tree.0.iter().enumerate() {
+            if let GameTreeNode::Move(san) = node {
+                match san.san.to_move(&position) {
+                    Ok(m) => {
+                        position.play_unchecked(&m);
+                        plies += 1;
+                    }
+                    Err(_) => {
+                        let message = format!(
+                            "illegal move {} at ply {}, the rest of the game was dropped",
+                            san,
+                            plies + 1
+                        );
+                        self.game.tree.0.truncate(i);
+                        self.correct(message);
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
     #[inline]
     #[must_use]
     fn active_branch(&mut self) -> &mut GameTree {
@@ -252,6 +342,7 @@ impl Visitor for Importer {
 
     fn begin_game(&mut self) {
         self.skip = false;
+        self.game_count += 1;
     }
 
     fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
@@ -263,6 +354,12 @@ impl Visitor for Importer {
             self.game.white_elo = btoi::btoi(value.as_bytes()).ok();
         } else if key == b"BlackElo" {
             self.game.black_elo = btoi::btoi(value.as_bytes()).ok();
+        } else if key == b"WhiteFideId" {
+            self.game.white_fide_id = btoi::btoi(value.as_bytes()).ok();
+        } else if key == b"BlackFideId" {
+            self.game.black_fide_id = btoi::btoi(value.as_bytes()).ok();
+        } else if key == b"Termination" {
+            self.game.termination = Some(value.decode_utf8_lossy().into_owned());
         } else if key == b"TimeControl" {
             self.game.time_control = Some(value.decode_utf8_lossy().into_owned());
         } else if key == b"ECO" {
@@ -303,17 +400,7 @@ impl Visitor for Importer {
 
     fn end_headers(&mut self) -> Skip {
         // Skip games with timestamp before
-        let cur_timestamp = self.game.date.as_ref().and_then(|date| {
-            let date = NaiveDate::parse_from_str(date, "%Y.%m.%d").ok()?;
-            let time = self
-                .game
-                .time
-                .as_ref()
-                .and_then(|time| NaiveTime::parse_from_str(time, "%H:%M:%S").ok())?;
-            Some(date.and_time(time).and_utc().timestamp())
-        });
-
-        if let (Some(cur_timestamp), Some(timestamp)) = (cur_timestamp, self.timestamp) {
+        if let (Some(cur_timestamp), Some(timestamp)) = (self.game.timestamp(), self.timestamp) {
             if cur_timestamp <= timestamp {
                 self.skip = true;
             }
@@ -354,6 +441,14 @@ impl Visitor for Importer {
             self.game = TempGame::default();
             None
         } else {
+            if self.lenient {
+                self.truncate_at_illegal_move();
+                if self.game.result.is_none() {
+                    self.game.result = Some("*".to_string());
+                    self.correct("missing result, recorded as \"*\"".to_string());
+                }
+            }
+
             // encode game tree 
             self.game.tree.encode(&mut self.game.moves, Some(self.game.position.clone()));
 
@@ -449,6 +544,23 @@ mod test {
         assert_eq!(game3.tree.count_main_line_moves(), 4);
     }
 
+    #[test]
+    fn test_lenient_import() {
+        let pgn = "1.e4 e5 2.Nf3 Nc6 3.Bb5 Nc6 4.O-O";
+
+        let mut reader = BufferedReader::new_cursor(&pgn[..]);
+        let mut importer = Importer::new(None);
+        assert!(reader.read_game(&mut importer).unwrap().flatten().is_none());
+
+        let mut reader = BufferedReader::new_cursor(&pgn[..]);
+        let mut importer = Importer::new(None).lenient(true);
+        let game = reader.read_game(&mut importer).unwrap().flatten().unwrap();
+        assert_eq!(game.tree.count_main_line_moves(), 5);
+        assert_eq!(game.result.as_deref(), Some("*"));
+        assert_eq!(importer.corrections.len(), 2);
+        assert!(importer.corrections[0].message.contains("ply 6"));
+    }
+
     #[test]
     fn test_pgn_with_many_variations() {
         let pgn = "1.e4 Nf6 2.e5 Nd5 3.d4 d6 
@@ -0,0 +1,404 @@
+//! Layout of exported PGN move text.
+//!
+//! `GameTree`'s `Display` writes a game on a single line, which is fine for the frontend but makes
+//! exported files hard to read and to diff. The PGN exports lay the move text out with a
+//! `PgnFormat` instead: moves are wrapped at a column width, variations either stay inline or
+//! start on their own indented line, and comments either stay inline or get a line of their own.
+//!
+//! Tokens are never split, so a comment longer than the line width makes its line longer, and the
+//! text parses back into the same tree.
+//!
+//! What the move text holds is chosen separately with `PgnExportOptions`: variations, comments,
+//! NAGs and `[%eval]` evaluations can each be left out, down to the bare main line.
+
+use serde::{Deserialize, Serialize};
+use shakmaty::{Chess, Position};
+use specta::Type;
+
+use crate::{
+    db::pgn::{GameTree, GameTreeNode},
+    error::Result,
+};
+
+/// Spaces per variation level in `VariationStyle::Indented`.
+const INDENT_WIDTH: usize = 2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum VariationStyle {
+    /// Variations continue on the line of the move they replace.
+    #[default]
+    Inline,
+    /// Every variation starts on its own line, indented by its depth.
+    Indented,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum CommentPlacement {
+    #[default]
+    Inline,
+    /// Every comment is written on its own line.
+    OwnLine,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PgnFormat {
+    /// Column to wrap move text at; `None` writes each game's moves on one line.
+    pub line_width: Option<u32>,
+    pub variation_style: VariationStyle,
+    pub comment_placement: CommentPlacement,
+}
+
+impl Default for PgnFormat {
+    /// The 80 columns of the PGN export format, with inline variations and comments.
+    fn default() -> Self {
+        Self {
+            line_width: Some(80),
+            variation_style: VariationStyle::default(),
+            comment_placement: CommentPlacement::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PgnExportOptions {
+    pub include_variations: bool,
+    /// Comment text and commands other than `[%eval]`, such as `[%clk]`.
+    pub include_comments: bool,
+    pub include_nags: bool,
+    /// `[%eval]` commands, kept on their own when other comments are left out.
+    pub include_evals: bool,
+    /// Export at most this many games.
+    pub max_games: Option<u32>,
+}
+
+impl Default for PgnExportOptions {
+    /// Every annotation of every game.
+    fn default() -> Self {
+        Self {
+            include_variations: true,
+            include_comments: true,
+            include_nags: true,
+            include_evals: true,
+            max_games: None,
+        }
+    }
+}
+
+/// The parts of `comment` that `options` keeps, `None` if nothing is left.
+fn filter_comment(comment: &str, options: &PgnExportOptions) -> Option<String> {
+    let mut kept = Vec::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find("[%") {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        let text = rest[..start].trim();
+        if options.include_comments && !text.is_empty() {
+            kept.push(text);
+        }
+        let command = &rest[start..=start + len];
+        let keep = if command.starts_with("[%eval") {
+            options.include_evals
+        } else {
+            options.include_comments
+        };
+        if keep {
+            kept.push(command);
+        }
+        rest = &rest[start + len + 1..];
+    }
+    let text = rest.trim();
+    if options.include_comments && !text.is_empty() {
+        kept.push(text);
+    }
+    (!kept.is_empty()).then(|| kept.join(" "))
+}
+
+/// Copy of `tree` without the annotations `options` leaves out.
+pub(super) fn filter_tree(tree: &GameTree, options: &PgnExportOptions) -> GameTree {
+    let mut filtered = GameTree::new();
+    for node in tree.nodes() {
+        match node {
+            GameTreeNode::Move(san_plus) => filtered.push(GameTreeNode::Move(san_plus.clone())),
+            GameTreeNode::Nag(nag) if options.include_nags => {
+                filtered.push(GameTreeNode::Nag(*nag))
+            }
+            GameTreeNode::Comment(comment) => {
+                if let Some(comment) = filter_comment(comment, options) {
+                    filtered.push(GameTreeNode::Comment(comment));
+                }
+            }
+            GameTreeNode::Variation(branch) if options.include_variations => {
+                filtered.push(GameTreeNode::Variation(filter_tree(branch, options)))
+            }
+            _ => {}
+        }
+    }
+    filtered
+}
+
+struct Layout<'a> {
+    format: &'a PgnFormat,
+    out: String,
+    line_len: usize,
+    at_line_start: bool,
+    indent: usize,
+    /// Opening parenthesis waiting for the first token of a variation.
+    open_variation: bool,
+}
+
+impl<'a> Layout<'a> {
+    fn new(format: &'a PgnFormat) -> Self {
+        Self {
+            format,
+            out: String::new(),
+            line_len: 0,
+            at_line_start: true,
+            indent: 0,
+            open_variation: false,
+        }
+    }
+
+    fn newline(&mut self) {
+        if !self.at_line_start {
+            self.out.push('\n');
+            self.line_len = 0;
+            self.at_line_start = true;
+        }
+    }
+
+    fn token(&mut self, token: &str) {
+        let prefix = if std::mem::take(&mut self.open_variation) {
+            "("
+        } else {
+            ""
+        };
+        let len = prefix.len() + token.chars().count();
+        if !self.at_line_start {
+            let fits = match self.format.line_width {
+                Some(width) => self.line_len + 1 + len <= width as usize,
+                None => true,
+            };
+            if fits {
+                self.out.push(' ');
+                self.line_len += 1;
+            } else {
+                self.newline();
+            }
+        }
+        if self.at_line_start {
+            self.out.extend(std::iter::repeat(' ').take(self.indent));
+            self.line_len = self.indent;
+            self.at_line_start = false;
+        }
+        self.out.push_str(prefix);
+        self.out.push_str(token);
+        self.line_len += len;
+    }
+
+    fn close_variation(&mut self) {
+        if std::mem::take(&mut self.open_variation) {
+            self.token("()");
+        } else {
+            self.out.push(')');
+            self.line_len += 1;
+        }
+    }
+
+    fn tree(&mut self, tree: &GameTree, position: Chess) -> Result<()> {
+        let mut prev_position = position.clone();
+        let mut position = position;
+        // Black moves need their number at the start of a line of play and after interruptions.
+        let mut needs_number = true;
+
+        for node in tree.nodes() {
+            match node {
+                GameTreeNode::Move(san_plus) => {
+                    let number = position.fullmoves().get();
+                    let token = if position.turn().is_white() {
+                        format!("{}.{}", number, san_plus)
+                    } else if needs_number {
+                        format!("{}...{}", number, san_plus)
+                    } else {
+                        san_plus.to_string()
+                    };
+                    self.token(&token);
+                    needs_number = false;
+
+                    let m = san_plus.san.to_move(&position)?;
+                    prev_position = position.clone();
+                    position.play_unchecked(&m);
+                }
+                GameTreeNode::Nag(nag) => self.token(&nag.to_string()),
+                GameTreeNode::Comment(comment) => {
+                    let token = format!("{{{}}}", comment);
+                    match self.format.comment_placement {
+                        CommentPlacement::Inline => self.token(&token),
+                        CommentPlacement::OwnLine => {
+                            self.newline();
+                            self.token(&token);
+                            self.newline();
+                            needs_number = true;
+                        }
+                    }
+                }
+                GameTreeNode::Variation(branch) => {
+                    match self.format.variation_style {
+                        VariationStyle::Inline => {
+                            self.open_variation = true;
+                            self.tree(branch, prev_position.clone())?;
+                            self.close_variation();
+                        }
+                        VariationStyle::Indented => {
+                            self.newline();
+                            self.indent += INDENT_WIDTH;
+                            self.open_variation = true;
+                            self.tree(branch, prev_position.clone())?;
+                            self.close_variation();
+                            self.indent -= INDENT_WIDTH;
+                            self.newline();
+                        }
+                    }
+                    needs_number = true;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Move text of `tree`, played from `start`, followed by `result`, laid out with `format`.
+pub(super) fn format_movetext(
+    tree: &GameTree,
+    start: Option<Chess>,
+    result: &str,
+    format: &PgnFormat,
+) -> Result<String> {
+    let mut layout = Layout::new(format);
+    layout.tree(tree, start.unwrap_or_default())?;
+    layout.indent = 0;
+    layout.token(result);
+    Ok(layout.out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::pgn::Importer;
+    use pgn_reader::BufferedReader;
+
+    const PGN: &str = "1. e4 e5 2. Nf3 (2. Bc4 {Bishop's opening} Nf6 (2... Bc5) 3. d3) \
+                       2... Nc6 $1 {The main move} 3. Bb5 a6 *";
+
+    fn parse(pgn: &str) -> GameTree {
+        let mut importer = Importer::new(None);
+        BufferedReader::new_cursor(pgn.as_bytes())
+            .read_game(&mut importer)
+            .unwrap()
+            .flatten()
+            .unwrap()
+            .tree
+    }
+
+    fn format(format: PgnFormat) -> String {
+        format_movetext(&parse(PGN), None, "*", &format).unwrap()
+    }
+
+    #[test]
+    fn test_single_line() {
+        let text = format(PgnFormat {
+            line_width: None,
+            ..Default::default()
+        });
+        assert_eq!(
+            text,
+            "1.e4 e5 2.Nf3 (2.Bc4 {Bishop's opening} Nf6 (2...Bc5) 3.d3) 2...Nc6 $1 \
+             {The main move} 3.Bb5 a6 *"
+        );
+    }
+
+    #[test]
+    fn test_wraps_at_line_width() {
+        let text = format(PgnFormat {
+            line_width: Some(30),
+            ..Default::default()
+        });
+        assert!(text.lines().all(|line| line.len() <= 30), "{}", text);
+        assert_eq!(text.lines().next(), Some("1.e4 e5 2.Nf3 (2.Bc4"));
+    }
+
+    #[test]
+    fn test_indented_variations_and_own_line_comments() {
+        let text = format(PgnFormat {
+            line_width: None,
+            variation_style: VariationStyle::Indented,
+            comment_placement: CommentPlacement::OwnLine,
+        });
+        assert_eq!(
+            text,
+            "1.e4 e5 2.Nf3\n  (2.Bc4\n  {Bishop's opening}\n  2...Nf6\n    (2...Bc5)\n  3.d3)\n\
+             2...Nc6 $1\n{The main move}\n3.Bb5 a6 *"
+        );
+    }
+
+    #[test]
+    fn test_export_options() {
+        let tree = parse("1. e4 {[%eval 0.3] [%clk 0:05:00] Best by test} e5 $1 (1... c5) *");
+        let text = |options: PgnExportOptions| {
+            let format = PgnFormat {
+                line_width: None,
+                ..Default::default()
+            };
+            format_movetext(&filter_tree(&tree, &options), None, "*", &format).unwrap()
+        };
+
+        assert_eq!(
+            text(PgnExportOptions::default()),
+            "1.e4 {[%eval 0.3] [%clk 0:05:00] Best by test} e5 $1 (1...c5) *"
+        );
+        assert_eq!(
+            text(PgnExportOptions {
+                include_variations: false,
+                include_comments: false,
+                include_nags: false,
+                ..Default::default()
+            }),
+            "1.e4 {[%eval 0.3]} e5 *"
+        );
+        assert_eq!(
+            text(PgnExportOptions {
+                include_evals: false,
+                ..Default::default()
+            }),
+            "1.e4 {[%clk 0:05:00] Best by test} e5 $1 (1...c5) *"
+        );
+        assert_eq!(
+            text(PgnExportOptions {
+                include_comments: false,
+                include_evals: false,
+                ..Default::default()
+            }),
+            "1.e4 e5 $1 (1...c5) *"
+        );
+    }
+
+    #[test]
+    fn test_formatted_text_parses_back() {
+        let tree = parse(PGN);
+        for format in [
+            PgnFormat::default(),
+            PgnFormat {
+                line_width: Some(20),
+                variation_style: VariationStyle::Indented,
+                comment_placement: CommentPlacement::OwnLine,
+            },
+        ] {
+            let text = format_movetext(&tree, None, "*", &format).unwrap();
+            assert_eq!(parse(&text), tree);
+        }
+    }
+}
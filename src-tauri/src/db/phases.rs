@@ -0,0 +1,269 @@
+//! Opening, middlegame and endgame boundaries of stored games.
+//!
+//! A game leaves the opening once few pieces are left or either side has developed most of its
+//! back rank, and reaches the endgame once at most six queens, rooks and minor pieces remain, as in
+//! Lichess' game divider. The first ply of each phase is kept in the `GamePhases` table and computed
+//! incrementally, like the endgame classification, so existing databases are backfilled the first
+//! time they are needed. The phase of the final position is the phase a game was decided in.
+
+use std::path::PathBuf;
+
+use diesel::{prelude::*, sql_types::Integer, sqlite::Sqlite};
+use serde::Serialize;
+use shakmaty::{fen::Fen, Bitboard, Board, CastlingMode, Chess, Color, FromSetup, Position, Rank};
+use specta::Type;
+
+use crate::{
+    chess::GamePhase,
+    db::{
+        encoding::extract_main_line_moves,
+        get_db_or_create,
+        schema::{game_phases, games},
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+/// Queens, rooks and minor pieces left on the board at which the middlegame starts at the latest.
+const MIDDLEGAME_PIECES: usize = 10;
+/// Pieces left on its back rank below which a side counts as developed.
+const DEVELOPED_BACK_RANK: usize = 4;
+/// Queens, rooks and minor pieces left on the board at which the endgame starts.
+const ENDGAME_PIECES: usize = 6;
+const BACKFILL_BATCH: i64 = 5000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GamePhaseBoundaries {
+    /// Plies played when the middlegame starts, `None` if the game ends in the opening.
+    pub middlegame_ply: Option<i32>,
+    /// Plies played when the endgame starts, `None` if the game never gets there.
+    pub endgame_ply: Option<i32>,
+}
+
+impl GamePhaseBoundaries {
+    /// Phase of the position after `ply` plies.
+    pub fn phase_at(&self, ply: i32) -> GamePhase {
+        if self.endgame_ply.is_some_and(|start| ply >= start) {
+            GamePhase::Endgame
+        } else if self.middlegame_ply.is_some_and(|start| ply >= start) {
+            GamePhase::Middlegame
+        } else {
+            GamePhase::Opening
+        }
+    }
+}
+
+fn pieces(board: &Board) -> usize {
+    (board.occupied() & !board.pawns() & !board.kings()).count()
+}
+
+fn back_rank_developed(board: &Board, color: Color) -> bool {
+    let back_rank = Bitboard::from_rank(match color {
+        Color::White => Rank::First,
+        Color::Black => Rank::Eighth,
+    });
+    (board.by_color(color) & back_rank).count() < DEVELOPED_BACK_RANK
+}
+
+/// Follows the main line of a game position by position, tracking where each phase starts.
+#[derive(Debug, Default)]
+pub(super) struct PhaseDivider {
+    boundaries: GamePhaseBoundaries,
+    ply: i32,
+}
+
+impl PhaseDivider {
+    /// Feed the next position of the game, the starting position first. Returns its phase.
+    pub fn push(&mut self, position: &Chess) -> GamePhase {
+        let board = position.board();
+        if self.boundaries.endgame_ply.is_none() && pieces(board) <= ENDGAME_PIECES {
+            self.boundaries.endgame_ply = Some(self.ply);
+        }
+        if self.boundaries.middlegame_ply.is_none()
+            && (self.boundaries.endgame_ply.is_some()
+                || pieces(board) <= MIDDLEGAME_PIECES
+                || back_rank_developed(board, Color::White)
+                || back_rank_developed(board, Color::Black))
+        {
+            self.boundaries.middlegame_ply = Some(self.ply);
+        }
+        let phase = self.boundaries.phase_at(self.ply);
+        self.ply += 1;
+        phase
+    }
+
+    pub fn boundaries(&self) -> GamePhaseBoundaries {
+        self.boundaries
+    }
+}
+
+pub(super) fn game_phase_boundaries(
+    moves: &[u8],
+    fen: Option<&str>,
+) -> Result<GamePhaseBoundaries> {
+    let mut position = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let mut divider = PhaseDivider::default();
+    divider.push(&position);
+    for m in extract_main_line_moves(moves, Some(position.clone()))? {
+        position.play_unchecked(&m);
+        divider.push(&position);
+    }
+    Ok(divider.boundaries())
+}
+
+/// Segment every game that has no entry in `GamePhases` yet. Returns the number of games
+/// segmented.
+pub(super) fn backfill_phases(db: &mut SqliteConnection) -> Result<usize> {
+    let mut segmented = 0;
+    loop {
+        let batch: Vec<(i32, Vec<u8>, Option<String>)> = games::table
+            .filter(games::id.ne_all(game_phases::table.select(game_phases::game_id)))
+            .select((games::id, games::moves, games::fen))
+            .order(games::id)
+            .limit(BACKFILL_BATCH)
+            .load(db)?;
+        if batch.is_empty() {
+            break;
+        }
+
+        let rows: Vec<_> = batch
+            .iter()
+            .map(|(id, moves, fen)| {
+                // Undecodable games are recorded as never leaving the opening so they are not
+                // retried forever.
+                let boundaries = game_phase_boundaries(moves, fen.as_deref()).unwrap_or_default();
+                (
+                    game_phases::game_id.eq(*id),
+                    game_phases::middlegame_ply.eq(boundaries.middlegame_ply),
+                    game_phases::endgame_ply.eq(boundaries.endgame_ply),
+                )
+            })
+            .collect();
+        db.transaction::<_, Error, _>(|db| {
+            diesel::insert_into(game_phases::table)
+                .values(&rows)
+                .execute(db)?;
+            Ok(())
+        })?;
+        segmented += rows.len();
+    }
+
+    if segmented > 0 {
+        log::info!("Segmented the phases of {} games", segmented);
+    }
+    Ok(segmented)
+}
+
+/// Ids of the games whose final position is in `phase`.
+pub(super) fn games_decided_in(
+    phase: GamePhase,
+) -> game_phases::BoxedQuery<'static, Sqlite, Integer> {
+    let query = game_phases::table.select(game_phases::game_id).into_boxed();
+    match phase {
+        GamePhase::Opening => query.filter(game_phases::middlegame_ply.is_null()),
+        GamePhase::Middlegame => query
+            .filter(game_phases::middlegame_ply.is_not_null())
+            .filter(game_phases::endgame_ply.is_null()),
+        GamePhase::Endgame => query.filter(game_phases::endgame_ply.is_not_null()),
+    }
+}
+
+/// Compute the phase boundaries of all games not segmented yet.
+#[tauri::command]
+#[specta::specta]
+pub async fn compute_game_phases(
+    file: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize> {
+    let _write = state.db_writes.lock(&file, "compute_game_phases").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    backfill_phases(db)
+}
+
+/// Phase boundaries of a game, computed first if needed.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_game_phases(
+    file: PathBuf,
+    game_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<GamePhaseBoundaries> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    if let Some((middlegame_ply, endgame_ply)) = game_phases::table
+        .find(game_id)
+        .select((game_phases::middlegame_ply, game_phases::endgame_ply))
+        .first::<(Option<i32>, Option<i32>)>(db)
+        .optional()?
+    {
+        return Ok(GamePhaseBoundaries {
+            middlegame_ply,
+            endgame_ply,
+        });
+    }
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first(db)?;
+    game_phase_boundaries(&moves, fen.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::pgn::{GameTree, GameTreeNode};
+
+    fn boundaries(fens: &[&str]) -> GamePhaseBoundaries {
+        let mut divider = PhaseDivider::default();
+        for fen in fens {
+            let fen: Fen = fen.parse().unwrap();
+            let position: Chess = fen.into_position(CastlingMode::Chess960).unwrap();
+            divider.push(&position);
+        }
+        divider.boundaries()
+    }
+
+    #[test]
+    fn test_phase_divider() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        // White has castled and developed its minor pieces and queen.
+        let developed = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/2N1BN2/PPPPQPPP/R4RK1 b kq - 9 7";
+        let rook_endgame = "4k3/r4ppp/8/8/8/8/R4PPP/4K3 w - - 0 40";
+
+        assert_eq!(boundaries(&[start, start]), GamePhaseBoundaries::default());
+        assert_eq!(
+            boundaries(&[start, developed, rook_endgame]),
+            GamePhaseBoundaries {
+                middlegame_ply: Some(1),
+                endgame_ply: Some(2),
+            }
+        );
+        // Reaching the endgame directly also starts the middlegame.
+        let direct = boundaries(&[start, rook_endgame]);
+        assert_eq!(direct.middlegame_ply, Some(1));
+        assert_eq!(direct.phase_at(0), GamePhase::Opening);
+        assert_eq!(direct.phase_at(5), GamePhase::Endgame);
+    }
+
+    #[test]
+    fn test_game_phase_boundaries() {
+        // 1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7#
+        let mut tree = GameTree::new();
+        for san in ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"] {
+            tree.push(GameTreeNode::Move(san.parse().unwrap()));
+        }
+        let mut moves = Vec::new();
+        tree.encode(&mut moves, None);
+        assert_eq!(
+            game_phase_boundaries(&moves, None).unwrap(),
+            GamePhaseBoundaries::default()
+        );
+    }
+}
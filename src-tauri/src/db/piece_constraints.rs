@@ -0,0 +1,191 @@
+//! Piece-count constraints for partial position search.
+//!
+//! Partial queries normally match fixed piece placements. Constraints add wildcard conditions on
+//! how many pieces of a kind stand in an area of the board, which makes strategic patterns
+//! searchable ("a white rook on the 7th rank", "black has at most 5 pawns").
+//!
+//! Grammar, with constraints separated by commas, semicolons or whitespace:
+//!
+//! ```text
+//! constraint := piece [ '@' area ] [ op count ]
+//! piece      := 'K' | 'Q' | 'R' | 'B' | 'N' | 'P'    (white)
+//!             | 'k' | 'q' | 'r' | 'b' | 'n' | 'p'    (black)
+//! area       := rank ('1'..'8') | file ('a'..'h') | square ('e4')
+//! op         := '=' | '!=' | '<' | '<=' | '>' | '>='
+//! ```
+//!
+//! Ranks are absolute. Without an operator the constraint means "at least one", so `R@7` is any
+//! white rook on the 7th rank and `p=5` is exactly five black pawns.
+
+use shakmaty::{Bitboard, Board, Color, File, Rank, Role, Square};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum CountOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CountOp {
+    #[inline(always)]
+    fn test(self, actual: u32, expected: u32) -> bool {
+        match self {
+            CountOp::Eq => actual == expected,
+            CountOp::Ne => actual != expected,
+            CountOp::Lt => actual < expected,
+            CountOp::Le => actual <= expected,
+            CountOp::Gt => actual > expected,
+            CountOp::Ge => actual >= expected,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct PieceConstraint {
+    color: Color,
+    role: Role,
+    area: Bitboard,
+    op: CountOp,
+    count: u32,
+}
+
+impl PieceConstraint {
+    #[inline(always)]
+    pub fn matches(&self, board: &Board) -> bool {
+        let pieces = board.by_role(self.role) & board.by_color(self.color) & self.area;
+        self.op.test(pieces.count() as u32, self.count)
+    }
+
+    /// The same constraint with colors swapped and the area flipped vertically.
+    pub fn mirrored(&self) -> Self {
+        Self {
+            color: !self.color,
+            area: self.area.flip_vertical(),
+            ..self.clone()
+        }
+    }
+
+    fn parse(token: &str) -> Result<Self, Error> {
+        let invalid = || Error::FenError(format!("Invalid piece constraint: {}", token));
+
+        let mut chars = token.chars();
+        let piece = chars.next().ok_or_else(invalid)?;
+        let role = Role::from_char(piece.to_ascii_lowercase()).ok_or_else(invalid)?;
+        let color = if piece.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let rest = chars.as_str();
+
+        let op_start = rest.find(['=', '!', '<', '>']).unwrap_or(rest.len());
+        let (area, condition) = rest.split_at(op_start);
+
+        let area = match area.strip_prefix('@') {
+            None if area.is_empty() => Bitboard::FULL,
+            None => return Err(invalid()),
+            Some(area) => parse_area(area).ok_or_else(invalid)?,
+        };
+
+        let (op, count) = if condition.is_empty() {
+            (CountOp::Ge, "1")
+        } else if let Some(count) = condition.strip_prefix("!=") {
+            (CountOp::Ne, count)
+        } else if let Some(count) = condition.strip_prefix("<=") {
+            (CountOp::Le, count)
+        } else if let Some(count) = condition.strip_prefix(">=") {
+            (CountOp::Ge, count)
+        } else if let Some(count) = condition.strip_prefix('<') {
+            (CountOp::Lt, count)
+        } else if let Some(count) = condition.strip_prefix('>') {
+            (CountOp::Gt, count)
+        } else if let Some(count) = condition.strip_prefix('=') {
+            (CountOp::Eq, count)
+        } else {
+            return Err(invalid());
+        };
+        let count: u32 = count.parse().map_err(|_| invalid())?;
+
+        Ok(Self {
+            color,
+            role,
+            area,
+            op,
+            count,
+        })
+    }
+}
+
+fn parse_area(area: &str) -> Option<Bitboard> {
+    let mut chars = area.chars();
+    match (chars.next()?, chars.next(), chars.next()) {
+        (c @ '1'..='8', None, None) => Some(Bitboard::from_rank(Rank::from_char(c)?)),
+        (c @ 'a'..='h', None, None) => Some(Bitboard::from_file(File::from_char(c)?)),
+        (_, Some(_), None) => area.parse::<Square>().ok().map(Bitboard::from_square),
+        _ => None,
+    }
+}
+
+/// Parse a constraint list. An empty string yields no constraints.
+pub fn parse_constraints(input: &str) -> Result<Vec<PieceConstraint>, Error> {
+    input
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(PieceConstraint::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, CastlingMode, Chess, Position};
+
+    fn board(fen: &str) -> Board {
+        let fen: Fen = fen.parse().unwrap();
+        let pos: Chess = fen.into_position(CastlingMode::Standard).unwrap();
+        pos.board().clone()
+    }
+
+    fn check(constraints: &str, fen: &str) -> bool {
+        let board = board(fen);
+        parse_constraints(constraints)
+            .unwrap()
+            .iter()
+            .all(|c| c.matches(&board))
+    }
+
+    #[test]
+    fn test_piece_constraints() {
+        let rook_on_seventh = "6k1/1R3ppp/8/8/8/8/5PPP/6K1 w - - 0 1";
+        assert!(check("R@7", rook_on_seventh));
+        assert!(check("R@b7, p=3; P@f", rook_on_seventh));
+        assert!(!check("R@8", rook_on_seventh));
+        assert!(!check("r>=1", rook_on_seventh));
+        assert!(check("Q=0 q<1 P!=8", rook_on_seventh));
+
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(check("P@2=8 p@7=8", start));
+        assert!(check("", start));
+    }
+
+    #[test]
+    fn test_invalid_constraints() {
+        assert!(parse_constraints("X@7").is_err());
+        assert!(parse_constraints("R@9").is_err());
+        assert!(parse_constraints("R=x").is_err());
+        assert!(parse_constraints("R7").is_err());
+    }
+
+    #[test]
+    fn test_mirrored_constraint() {
+        let constraint = &parse_constraints("R@7").unwrap()[0];
+        let black_rook_on_second = board("6k1/5ppp/8/8/8/8/1r3PPP/6K1 w - - 0 1");
+        assert!(!constraint.matches(&black_rook_on_second));
+        assert!(constraint.mirrored().matches(&black_rook_on_second));
+    }
+}
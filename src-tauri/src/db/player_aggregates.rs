@@ -0,0 +1,491 @@
+//! Monthly aggregates of player results for the player dashboards.
+//!
+//! `get_players_game_info` replays every game of a player to find its opening, which is slow for
+//! prolific players. The `PlayerMonthlyStats` table keeps, per player, site and month, the number
+//! of games, the results, the summed rating and how often each opening was played. It is built once
+//! by `build_player_aggregates` and then folded forward after every import. A watermark records
+//! which games are covered; when games were deleted since, the aggregates are stale and callers
+//! fall back to computing from the games. Edits the watermark cannot see, such as `update_game`
+//! or merging players, events or sites, drop the aggregates until the next build.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use diesel::{connection::SimpleConnection, prelude::*, sql_query, sql_types::Text};
+use rayon::prelude::*;
+use serde::Serialize;
+use specta::Type;
+
+use crate::{
+    db::{
+        game_opening, get_db_or_create,
+        schema::{games, player_monthly_stats, player_stats_watermark, sites},
+        stats_site, ConnectionOptions, GameOutcome, SiteStatsData,
+    },
+    error::{Error, Result},
+    progress::{TaskKind, TaskProgress},
+    AppState,
+};
+
+const FOLD_BATCH: i64 = 5000;
+/// Openings listed per month.
+const MAIN_OPENINGS: usize = 3;
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+pub struct MonthlyPlayerStats {
+    pub site: String,
+    /// `YYYY-MM`.
+    pub month: String,
+    pub games: i32,
+    pub wins: i32,
+    pub draws: i32,
+    pub losses: i32,
+    pub average_elo: f64,
+    /// Most played openings, most played first.
+    pub main_openings: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct MonthAggregate {
+    games: i32,
+    wins: i32,
+    draws: i32,
+    losses: i32,
+    rating_sum: i64,
+    openings: HashMap<String, i32>,
+}
+
+impl MonthAggregate {
+    fn add(&mut self, result: &GameOutcome, elo: i32, opening: &str) {
+        self.games += 1;
+        match result {
+            GameOutcome::Won => self.wins += 1,
+            GameOutcome::Drawn => self.draws += 1,
+            GameOutcome::Lost => self.losses += 1,
+        }
+        self.rating_sum += elo as i64;
+        if !opening.is_empty() {
+            *self.openings.entry(opening.to_string()).or_default() += 1;
+        }
+    }
+
+    fn merge(&mut self, other: MonthAggregate) {
+        self.games += other.games;
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+        self.rating_sum += other.rating_sum;
+        for (opening, games) in other.openings {
+            *self.openings.entry(opening).or_default() += games;
+        }
+    }
+
+    fn into_stats(self, site: String, month: String) -> MonthlyPlayerStats {
+        let mut openings: Vec<(String, i32)> = self.openings.into_iter().collect();
+        openings.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        MonthlyPlayerStats {
+            site,
+            month,
+            games: self.games,
+            wins: self.wins,
+            draws: self.draws,
+            losses: self.losses,
+            average_elo: self.rating_sum as f64 / self.games.max(1) as f64,
+            main_openings: openings
+                .into_iter()
+                .take(MAIN_OPENINGS)
+                .map(|(opening, _)| opening)
+                .collect(),
+        }
+    }
+}
+
+/// `YYYY-MM` of a PGN date, if its year and month are known.
+fn month_of(date: &str) -> Option<String> {
+    let year = date.get(..4)?;
+    let month = date.get(5..7)?;
+    if !year
+        .bytes()
+        .chain(month.bytes())
+        .all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    Some(format!("{}-{}", year, month))
+}
+
+/// Monthly stats of the games returned by the full computation of `get_players_game_info`.
+pub(super) fn monthly_from_games(site_stats: &[SiteStatsData]) -> Vec<MonthlyPlayerStats> {
+    let mut months: HashMap<(String, String), MonthAggregate> = HashMap::new();
+    for site in site_stats {
+        for game in &site.data {
+            let Some(month) = month_of(&game.date) else {
+                continue;
+            };
+            months.entry((site.site.clone(), month)).or_default().add(
+                &game.result,
+                game.player_elo,
+                &game.opening,
+            );
+        }
+    }
+    sorted_stats(months)
+}
+
+fn sorted_stats(months: HashMap<(String, String), MonthAggregate>) -> Vec<MonthlyPlayerStats> {
+    let mut stats: Vec<MonthlyPlayerStats> = months
+        .into_iter()
+        .map(|((site, month), aggregate)| aggregate.into_stats(site, month))
+        .collect();
+    stats.sort_by(|a, b| a.site.cmp(&b.site).then_with(|| a.month.cmp(&b.month)));
+    stats
+}
+
+fn aggregates_exist(db: &mut SqliteConnection) -> bool {
+    #[derive(QueryableByName)]
+    struct TableInfo {
+        #[diesel(sql_type = Text, column_name = "name")]
+        _name: String,
+    }
+
+    sql_query("SELECT name FROM sqlite_master WHERE type='table' AND name='PlayerStatsWatermark'")
+        .load::<TableInfo>(db)
+        .is_ok_and(|tables| !tables.is_empty())
+}
+
+/// Drop the aggregates after a change to already folded games. Player summaries are computed from
+/// the games until `build_player_aggregates` runs again.
+pub(super) fn invalidate(db: &mut SqliteConnection) -> Result<()> {
+    if aggregates_exist(db) {
+        db.batch_execute("DELETE FROM PlayerMonthlyStats; DELETE FROM PlayerStatsWatermark;")?;
+    }
+    Ok(())
+}
+
+/// Last game folded into the aggregates and the number of games up to it, if built.
+fn watermark(db: &mut SqliteConnection) -> Result<Option<(i32, i64)>> {
+    Ok(player_stats_watermark::table
+        .select((
+            player_stats_watermark::last_game_id,
+            player_stats_watermark::game_count,
+        ))
+        .first(db)
+        .optional()?)
+}
+
+/// Whether no game covered by the watermark was deleted since it was written.
+fn covers_existing_games(db: &mut SqliteConnection, last_id: i32, game_count: i64) -> Result<bool> {
+    let covered: i64 = games::table
+        .filter(games::id.le(last_id))
+        .count()
+        .get_result(db)?;
+    Ok(covered == game_count)
+}
+
+type FoldRow = (
+    i32,
+    i32,
+    i32,
+    Option<String>,
+    Option<String>,
+    Vec<u8>,
+    Option<i32>,
+    Option<i32>,
+    Option<String>,
+    Option<String>,
+);
+
+/// Player, site and month a game counts for, with the player's result, rating and the opening.
+type Contribution = ((i32, String, String), GameOutcome, i32, String);
+
+/// Contributions of a game to the aggregates: one per side whose stats `get_players_game_info`
+/// would report.
+fn game_contributions(row: &FoldRow) -> Vec<Contribution> {
+    let (_, white_id, black_id, result, date, moves, white_elo, black_elo, site, fen) = row;
+    let (Some(result), Some(month), Some(site), None) = (
+        result.as_deref(),
+        date.as_deref().and_then(month_of),
+        site.as_deref(),
+        fen,
+    ) else {
+        return Vec::new();
+    };
+
+    let sides: Vec<(i32, GameOutcome, i32)> = [
+        (*white_id, true, *white_elo),
+        (*black_id, false, *black_elo),
+    ]
+    .into_iter()
+    .filter_map(|(player, is_white, elo)| {
+        Some((player, GameOutcome::from_str(result, is_white)?, elo?))
+    })
+    .collect();
+    if sides.is_empty() {
+        return Vec::new();
+    }
+    let Some(opening) = game_opening(moves) else {
+        return Vec::new();
+    };
+
+    let site = stats_site(site);
+    sides
+        .into_iter()
+        .map(|(player, outcome, elo)| {
+            (
+                (player, site.clone(), month.clone()),
+                outcome,
+                elo,
+                opening.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Fold the games added since the watermark into the aggregates. `progress` is called with the
+/// number of games read after each batch. Returns the number of games read.
+fn fold_new_games(db: &mut SqliteConnection, mut progress: impl FnMut(usize)) -> Result<usize> {
+    let (mut last_id, mut game_count) = watermark(db)?.unwrap_or((0, 0));
+    let mut months: HashMap<(i32, String, String), MonthAggregate> = HashMap::new();
+    let mut read = 0;
+    loop {
+        let batch: Vec<FoldRow> = games::table
+            .left_join(sites::table.on(games::site_id.eq(sites::id)))
+            .filter(games::id.gt(last_id))
+            .select((
+                games::id,
+                games::white_id,
+                games::black_id,
+                games::result,
+                games::date,
+                games::moves,
+                games::white_elo,
+                games::black_elo,
+                sites::name.nullable(),
+                games::fen,
+            ))
+            .order(games::id)
+            .limit(FOLD_BATCH)
+            .load(db)?;
+        let Some(&(batch_last, ..)) = batch.last() else {
+            break;
+        };
+        last_id = batch_last;
+        game_count += batch.len() as i64;
+        read += batch.len();
+
+        let contributions: Vec<Contribution> =
+            batch.par_iter().flat_map_iter(game_contributions).collect();
+        for (key, outcome, elo, opening) in contributions {
+            months.entry(key).or_default().add(&outcome, elo, &opening);
+        }
+        progress(read);
+    }
+
+    db.transaction::<_, Error, _>(|db| {
+        for ((player_id, site, month), mut aggregate) in months {
+            let stored: Option<(i32, i32, i32, i32, i64, String)> = player_monthly_stats::table
+                .find((player_id, &site, &month))
+                .select((
+                    player_monthly_stats::games,
+                    player_monthly_stats::wins,
+                    player_monthly_stats::draws,
+                    player_monthly_stats::losses,
+                    player_monthly_stats::rating_sum,
+                    player_monthly_stats::openings,
+                ))
+                .first(db)
+                .optional()?;
+            if let Some((games, wins, draws, losses, rating_sum, openings)) = stored {
+                aggregate.merge(MonthAggregate {
+                    games,
+                    wins,
+                    draws,
+                    losses,
+                    rating_sum,
+                    openings: serde_json::from_str(&openings).unwrap_or_default(),
+                });
+            }
+
+            let openings = serde_json::to_string(&aggregate.openings).map_err(|e| {
+                Error::PackageManager(format!("Failed to serialize openings: {}", e))
+            })?;
+            diesel::replace_into(player_monthly_stats::table)
+                .values((
+                    player_monthly_stats::player_id.eq(player_id),
+                    player_monthly_stats::site.eq(&site),
+                    player_monthly_stats::month.eq(&month),
+                    player_monthly_stats::games.eq(aggregate.games),
+                    player_monthly_stats::wins.eq(aggregate.wins),
+                    player_monthly_stats::draws.eq(aggregate.draws),
+                    player_monthly_stats::losses.eq(aggregate.losses),
+                    player_monthly_stats::rating_sum.eq(aggregate.rating_sum),
+                    player_monthly_stats::openings.eq(openings),
+                ))
+                .execute(db)?;
+        }
+        diesel::replace_into(player_stats_watermark::table)
+            .values((
+                player_stats_watermark::id.eq(1),
+                player_stats_watermark::last_game_id.eq(last_id),
+                player_stats_watermark::game_count.eq(game_count),
+            ))
+            .execute(db)?;
+        Ok(())
+    })?;
+    Ok(read)
+}
+
+/// Fold freshly imported games into the aggregates, if they were built and are not stale.
+pub(super) fn update_after_import(db: &mut SqliteConnection) -> Result<()> {
+    if !aggregates_exist(db) {
+        return Ok(());
+    }
+    match watermark(db)? {
+        Some((last_id, game_count)) if covers_existing_games(db, last_id, game_count)? => {
+            fold_new_games(db, |_| {})?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Monthly stats of `player_id` from the aggregates, or `None` if they are missing or do not
+/// cover the current games.
+pub(super) fn load_monthly_stats(
+    db: &mut SqliteConnection,
+    player_id: i32,
+) -> Result<Option<Vec<MonthlyPlayerStats>>> {
+    if !aggregates_exist(db) {
+        return Ok(None);
+    }
+    let Some((last_id, game_count)) = watermark(db)? else {
+        return Ok(None);
+    };
+    let newer: i64 = games::table
+        .filter(games::id.gt(last_id))
+        .count()
+        .get_result(db)?;
+    if newer > 0 || !covers_existing_games(db, last_id, game_count)? {
+        return Ok(None);
+    }
+
+    let rows: Vec<(String, String, i32, i32, i32, i32, i64, String)> = player_monthly_stats::table
+        .filter(player_monthly_stats::player_id.eq(player_id))
+        .select((
+            player_monthly_stats::site,
+            player_monthly_stats::month,
+            player_monthly_stats::games,
+            player_monthly_stats::wins,
+            player_monthly_stats::draws,
+            player_monthly_stats::losses,
+            player_monthly_stats::rating_sum,
+            player_monthly_stats::openings,
+        ))
+        .load(db)?;
+    let months = rows
+        .into_iter()
+        .map(
+            |(site, month, games, wins, draws, losses, rating_sum, openings)| {
+                let aggregate = MonthAggregate {
+                    games,
+                    wins,
+                    draws,
+                    losses,
+                    rating_sum,
+                    openings: serde_json::from_str(&openings).unwrap_or_default(),
+                };
+                ((site, month), aggregate)
+            },
+        )
+        .collect();
+    Ok(Some(sorted_stats(months)))
+}
+
+/// Build the monthly player aggregates, or bring them up to date. Aggregates made stale by deleted
+/// games are rebuilt from scratch. Returns the number of games folded in.
+#[tauri::command]
+#[specta::specta]
+pub async fn build_player_aggregates(
+    file: PathBuf,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize> {
+    let _write = state.db_writes.lock(&file, "build_player_aggregates").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    if let Some((last_id, game_count)) = watermark(db)? {
+        if !covers_existing_games(db, last_id, game_count)? {
+            log::info!(
+                "Player aggregates of {} are stale, rebuilding",
+                file.display()
+            );
+            invalidate(db)?;
+        }
+    }
+
+    let task_id = file.to_string_lossy().into_owned();
+    let total: i64 = games::table.count().get_result(db)?;
+    let folded = fold_new_games(db, |read| {
+        let percent = read as f64 / total.max(1) as f64 * 100.0;
+        TaskProgress::new(TaskKind::Database, task_id.clone(), percent.min(99.0))
+            .message(format!("{} games aggregated", read))
+            .send(&app);
+    })?;
+    TaskProgress::done(TaskKind::Database, task_id).send(&app);
+
+    log::info!(
+        "Folded {} games into the player aggregates of {}",
+        folded,
+        file.display()
+    );
+    Ok(folded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_month_of() {
+        assert_eq!(month_of("2024.03.15").as_deref(), Some("2024-03"));
+        assert_eq!(month_of("2024.??.??"), None);
+        assert_eq!(month_of("????.??.??"), None);
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let mut db = SqliteConnection::establish(":memory:").unwrap();
+        crate::db::core::init_db(&mut db, "Test", "Test").unwrap();
+        db.batch_execute(
+            "INSERT INTO PlayerMonthlyStats (PlayerID, Site, Month, Games, Wins, Draws, Losses,
+                 RatingSum, Openings) VALUES (1, 'Lichess', '2024-03', 1, 1, 0, 0, 2000, '{}');
+             INSERT INTO PlayerStatsWatermark (ID, LastGameID, GameCount) VALUES (1, 0, 0);",
+        )
+        .unwrap();
+        assert!(load_monthly_stats(&mut db, 1).unwrap().is_some());
+
+        invalidate(&mut db).unwrap();
+        assert!(load_monthly_stats(&mut db, 1).unwrap().is_none());
+        let rows: i64 = player_monthly_stats::table
+            .count()
+            .get_result(&mut db)
+            .unwrap();
+        assert_eq!(rows, 0);
+    }
+
+    #[test]
+    fn test_main_openings() {
+        let mut aggregate = MonthAggregate::default();
+        aggregate.add(&GameOutcome::Won, 2000, "Sicilian Defense");
+        aggregate.add(&GameOutcome::Lost, 2010, "French Defense");
+        aggregate.add(&GameOutcome::Drawn, 2020, "Sicilian Defense");
+        aggregate.add(&GameOutcome::Won, 2030, "");
+        let stats = aggregate.into_stats("Lichess".to_string(), "2024-03".to_string());
+        assert_eq!(stats.games, 4);
+        assert_eq!((stats.wins, stats.draws, stats.losses), (2, 1, 1));
+        assert_eq!(stats.average_elo, 2015.0);
+        assert_eq!(
+            stats.main_openings,
+            vec!["Sicilian Defense", "French Defense"]
+        );
+    }
+}
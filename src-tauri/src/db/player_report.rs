@@ -0,0 +1,275 @@
+//! Player weakness report built from engine evaluations stored in game comments.
+//!
+//! Analyzed games carry `[%eval ...]` annotations after each move. For every move played by the
+//! requested player the centipawn loss is derived from the evaluation before and after the move,
+//! and the losses are averaged by game phase, opening family and time control. Phases follow the
+//! same boundaries as the stored game phases.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use diesel::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use shakmaty::{fen::Fen, CastlingMode, Chess, Color, EnPassantMode, FromSetup, Position};
+use specta::Type;
+
+use crate::{
+    chess::GamePhase,
+    db::{
+        eval_comment::eval_comment_cp,
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode},
+        phases::PhaseDivider,
+        schema::games,
+        ConnectionOptions,
+    },
+    error::Result,
+    opening::get_opening_from_setup,
+    AppState,
+};
+
+/// Only the first plies are matched against the opening book.
+const OPENING_LOOKUP_PLIES: usize = 30;
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct WeaknessBucket {
+    pub key: String,
+    pub games: i32,
+    pub moves: i32,
+    pub acpl: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerWeaknessReport {
+    pub player_id: i32,
+    pub total_games: i32,
+    pub analyzed_games: i32,
+    pub overall_acpl: f64,
+    pub by_phase: Vec<WeaknessBucket>,
+    pub by_opening: Vec<WeaknessBucket>,
+    pub by_time_control: Vec<WeaknessBucket>,
+}
+
+/// Classify a PGN `TimeControl` value ("base+increment" in seconds) by estimated game length.
+pub fn time_control_category(time_control: Option<&str>) -> &'static str {
+    let Some(tc) = time_control.filter(|tc| !tc.is_empty() && *tc != "-" && *tc != "?") else {
+        return "unknown";
+    };
+    if tc.contains('/') {
+        return "classical";
+    }
+    let mut parts = tc.split('+');
+    let base: u32 = match parts.next().and_then(|b| b.trim().parse().ok()) {
+        Some(base) => base,
+        None => return "unknown",
+    };
+    let increment: u32 = parts.next().and_then(|i| i.trim().parse().ok()).unwrap_or(0);
+
+    match base + 40 * increment {
+        0..=179 => "bullet",
+        180..=479 => "blitz",
+        480..=1499 => "rapid",
+        _ => "classical",
+    }
+}
+
+fn opening_family(name: &str) -> String {
+    name.split(':').next().unwrap_or(name).trim().to_string()
+}
+
+/// Per-game centipawn losses of the player, tagged by phase, plus the opening family.
+pub(super) struct GameLosses {
+    pub phases: Vec<(GamePhase, f64)>,
+    pub opening: String,
+}
+
+pub(super) fn game_losses(moves: &[u8], fen: Option<&str>, player_color: Color) -> Option<GameLosses> {
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse().ok()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960).ok()?
+        }
+        None => Chess::default(),
+    };
+    let tree = GameTree::from_bytes(moves, Some(start.clone())).ok()?;
+
+    let mut position = start;
+    let mut last_eval: Option<f64> = None;
+    // Phase and mover of the most recent move, awaiting its evaluation comment.
+    let mut pending: Option<(GamePhase, Color)> = None;
+    let mut phases = Vec::new();
+    let mut opening = String::new();
+    let mut divider = PhaseDivider::default();
+    let mut ply = 0;
+
+    for node in tree.nodes() {
+        match node {
+            GameTreeNode::Move(san_plus) => {
+                // The previous move had no evaluation, so there is no baseline for this one.
+                if pending.is_some() {
+                    last_eval = None;
+                }
+                let mover = position.turn();
+                let phase = divider.push(&position);
+                let m = san_plus.san.to_move(&position).ok()?;
+                position.play_unchecked(&m);
+                ply += 1;
+                if ply <= OPENING_LOOKUP_PLIES {
+                    if let Ok(name) =
+                        get_opening_from_setup(position.clone().into_setup(EnPassantMode::Legal))
+                    {
+                        opening = opening_family(&name);
+                    }
+                }
+                pending = Some((phase, mover));
+            }
+            GameTreeNode::Comment(comment) => {
+                let (Some(eval), Some((phase, mover))) = (eval_comment_cp(comment), pending) else {
+                    continue;
+                };
+                if let Some(before) = last_eval {
+                    if mover == player_color {
+                        let loss = match mover {
+                            Color::White => before - eval,
+                            Color::Black => eval - before,
+                        };
+                        phases.push((phase, loss.max(0.0)));
+                    }
+                }
+                last_eval = Some(eval);
+                pending = None;
+            }
+            GameTreeNode::Nag(_) | GameTreeNode::Variation(_) => {}
+        }
+    }
+
+    if phases.is_empty() {
+        return None;
+    }
+    Some(GameLosses {
+        phases,
+        opening: if opening.is_empty() { "Unknown".to_string() } else { opening },
+    })
+}
+
+#[derive(Default)]
+struct Accumulator {
+    games: i32,
+    moves: i32,
+    total_loss: f64,
+}
+
+impl Accumulator {
+    fn add_game(&mut self, losses: &[f64]) {
+        if losses.is_empty() {
+            return;
+        }
+        self.games += 1;
+        self.moves += losses.len() as i32;
+        self.total_loss += losses.iter().sum::<f64>();
+    }
+
+    fn acpl(&self) -> f64 {
+        if self.moves == 0 {
+            0.0
+        } else {
+            self.total_loss / self.moves as f64
+        }
+    }
+}
+
+fn into_buckets(map: HashMap<String, Accumulator>) -> Vec<WeaknessBucket> {
+    let mut buckets: Vec<WeaknessBucket> = map
+        .into_iter()
+        .map(|(key, acc)| WeaknessBucket {
+            key,
+            games: acc.games,
+            moves: acc.moves,
+            acpl: acc.acpl(),
+        })
+        .collect();
+    // Worst areas first.
+    buckets.sort_by(|a, b| b.acpl.total_cmp(&a.acpl));
+    buckets
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_player_weakness_report(
+    file: PathBuf,
+    player_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<PlayerWeaknessReport> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let rows: Vec<(i32, Vec<u8>, Option<String>, Option<String>)> = games::table
+        .select((games::white_id, games::moves, games::fen, games::time_control))
+        .filter(games::white_id.eq(player_id).or(games::black_id.eq(player_id)))
+        .load(db)?;
+
+    let analyzed: Vec<(GameLosses, &'static str)> = rows
+        .par_iter()
+        .filter_map(|(white_id, moves, fen, time_control)| {
+            let color = if *white_id == player_id { Color::White } else { Color::Black };
+            let losses = game_losses(moves, fen.as_deref(), color)?;
+            Some((losses, time_control_category(time_control.as_deref())))
+        })
+        .collect();
+
+    let mut overall = Accumulator::default();
+    let mut by_phase: HashMap<String, Accumulator> = HashMap::new();
+    let mut by_opening: HashMap<String, Accumulator> = HashMap::new();
+    let mut by_time_control: HashMap<String, Accumulator> = HashMap::new();
+
+    for (game, time_control) in &analyzed {
+        let all: Vec<f64> = game.phases.iter().map(|(_, loss)| *loss).collect();
+        overall.add_game(&all);
+        by_opening.entry(game.opening.clone()).or_default().add_game(&all);
+        by_time_control
+            .entry(time_control.to_string())
+            .or_default()
+            .add_game(&all);
+
+        for phase in [GamePhase::Opening, GamePhase::Middlegame, GamePhase::Endgame] {
+            let losses: Vec<f64> = game
+                .phases
+                .iter()
+                .filter(|(p, _)| *p == phase)
+                .map(|(_, loss)| *loss)
+                .collect();
+            let key = match phase {
+                GamePhase::Opening => "opening",
+                GamePhase::Middlegame => "middlegame",
+                GamePhase::Endgame => "endgame",
+            };
+            by_phase.entry(key.to_string()).or_default().add_game(&losses);
+        }
+    }
+
+    Ok(PlayerWeaknessReport {
+        player_id,
+        total_games: rows.len() as i32,
+        analyzed_games: analyzed.len() as i32,
+        overall_acpl: overall.acpl(),
+        by_phase: into_buckets(by_phase),
+        by_opening: into_buckets(by_opening),
+        by_time_control: into_buckets(by_time_control),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_control_category() {
+        assert_eq!(time_control_category(Some("60+0")), "bullet");
+        assert_eq!(time_control_category(Some("180+2")), "blitz");
+        assert_eq!(time_control_category(Some("600+5")), "rapid");
+        assert_eq!(time_control_category(Some("5400+30")), "classical");
+        assert_eq!(time_control_category(Some("-")), "unknown");
+        assert_eq!(time_control_category(None), "unknown");
+    }
+}
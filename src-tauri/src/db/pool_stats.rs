@@ -0,0 +1,251 @@
+//! Connection pool metrics and leak detection.
+//!
+//! Every database pool gets a `PoolEventHandler` that records how long checkouts wait for a
+//! connection and which connections are currently checked out. A connection kept longer than
+//! `LONG_HOLD` is logged when it is returned and reported by `get_connection_pool_stats` while
+//! it is still out, which is usually what is behind "database is locked" errors, e.g. when
+//! `delete_database` runs while another command still holds a connection.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use diesel::r2d2::{
+    event::{CheckinEvent, CheckoutEvent, TimeoutEvent},
+    HandleEvent,
+};
+use serde::Serialize;
+use specta::Type;
+
+use crate::AppState;
+
+/// Connections checked out for longer than this are considered held too long. Imports and
+/// bulk operations legitimately exceed it, so it only leads to warnings.
+pub const LONG_HOLD: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+pub struct PoolMetrics {
+    checkouts: AtomicU64,
+    wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+    timeouts: AtomicU64,
+    long_holds: AtomicU64,
+    /// Checkout time of the connections currently in use, by connection id.
+    held: Mutex<HashMap<u64, Instant>>,
+}
+
+impl PoolMetrics {
+    fn record_checkout(&self, id: u64, wait: Duration, at: Instant) {
+        let wait = wait.as_micros() as u64;
+        self.checkouts.fetch_add(1, Ordering::Relaxed);
+        self.wait_micros.fetch_add(wait, Ordering::Relaxed);
+        self.max_wait_micros.fetch_max(wait, Ordering::Relaxed);
+        if let Ok(mut held) = self.held.lock() {
+            held.insert(id, at);
+        }
+    }
+
+    /// Returns whether the connection was held too long.
+    fn record_checkin(&self, id: u64, held_for: Duration) -> bool {
+        if let Ok(mut held) = self.held.lock() {
+            held.remove(&id);
+        }
+        let too_long = held_for > LONG_HOLD;
+        if too_long {
+            self.long_holds.fetch_add(1, Ordering::Relaxed);
+        }
+        too_long
+    }
+
+    fn snapshot(&self, now: Instant) -> PoolCounters {
+        let checkouts = self.checkouts.load(Ordering::Relaxed);
+        let wait_micros = self.wait_micros.load(Ordering::Relaxed);
+        let held: Vec<Duration> = self
+            .held
+            .lock()
+            .map(|held| held.values().map(|at| now.duration_since(*at)).collect())
+            .unwrap_or_default();
+        PoolCounters {
+            checkouts: checkouts as u32,
+            timeouts: self.timeouts.load(Ordering::Relaxed) as u32,
+            avg_wait_ms: if checkouts > 0 {
+                wait_micros as f64 / checkouts as f64 / 1000.0
+            } else {
+                0.0
+            },
+            max_wait_ms: self.max_wait_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+            long_holds: self.long_holds.load(Ordering::Relaxed) as u32,
+            held_too_long: held.iter().filter(|held| **held > LONG_HOLD).count() as u32,
+            longest_hold_ms: held
+                .iter()
+                .max()
+                .map(|held| held.as_secs_f64() * 1000.0),
+        }
+    }
+}
+
+#[derive(Default)]
+struct PoolCounters {
+    checkouts: u32,
+    timeouts: u32,
+    avg_wait_ms: f64,
+    max_wait_ms: f64,
+    long_holds: u32,
+    held_too_long: u32,
+    longest_hold_ms: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct PoolEventHandler {
+    path: String,
+    metrics: Arc<PoolMetrics>,
+}
+
+impl PoolEventHandler {
+    pub fn new(path: &str, metrics: Arc<PoolMetrics>) -> Self {
+        Self {
+            path: path.to_string(),
+            metrics,
+        }
+    }
+}
+
+impl HandleEvent for PoolEventHandler {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        self.metrics
+            .record_checkout(event.id(), event.duration(), Instant::now());
+    }
+
+    fn handle_timeout(&self, event: TimeoutEvent) {
+        self.metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+        log::warn!(
+            "Timed out after {:?} waiting for a connection to {}",
+            event.timeout(),
+            self.path
+        );
+    }
+
+    fn handle_checkin(&self, event: CheckinEvent) {
+        if self.metrics.record_checkin(event.id(), event.duration()) {
+            log::warn!(
+                "Connection {} to {} was held for {:?}",
+                event.id(),
+                self.path,
+                event.duration()
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionPoolStats {
+    pub path: String,
+    pub max_size: u32,
+    /// Open connections, idle or in use.
+    pub connections: u32,
+    pub idle: u32,
+    pub active: u32,
+    pub checkouts: u32,
+    /// Checkouts that gave up waiting for a connection.
+    pub timeouts: u32,
+    pub avg_wait_ms: f64,
+    pub max_wait_ms: f64,
+    /// Connections returned after being held longer than `LONG_HOLD`.
+    pub long_holds: u32,
+    /// Connections currently held longer than `LONG_HOLD`.
+    pub held_too_long: u32,
+    /// How long the oldest connection in use has been held.
+    pub longest_hold_ms: Option<f64>,
+}
+
+/// Statistics of the pool of one database, if it is open.
+pub fn pool_stats(state: &AppState, path: &str) -> Option<ConnectionPoolStats> {
+    let pool = state.connection_pool.get(path)?;
+    let pool_state = pool.state();
+    let counters = state
+        .pool_metrics
+        .get(path)
+        .map(|metrics| metrics.snapshot(Instant::now()))
+        .unwrap_or_default();
+    Some(ConnectionPoolStats {
+        path: path.to_string(),
+        max_size: pool.max_size(),
+        connections: pool_state.connections,
+        idle: pool_state.idle_connections,
+        active: pool_state.connections - pool_state.idle_connections,
+        checkouts: counters.checkouts,
+        timeouts: counters.timeouts,
+        avg_wait_ms: counters.avg_wait_ms,
+        max_wait_ms: counters.max_wait_ms,
+        long_holds: counters.long_holds,
+        held_too_long: counters.held_too_long,
+        longest_hold_ms: counters.longest_hold_ms,
+    })
+}
+
+/// Statistics of the connection pools of every open database. Connections held too long are
+/// logged as well.
+#[tauri::command]
+#[specta::specta]
+pub fn get_connection_pool_stats(state: tauri::State<'_, AppState>) -> Vec<ConnectionPoolStats> {
+    let paths: Vec<String> = state
+        .connection_pool
+        .iter()
+        .map(|entry| entry.key().clone())
+        .collect();
+    let mut stats: Vec<ConnectionPoolStats> = paths
+        .iter()
+        .filter_map(|path| pool_stats(&state, path))
+        .collect();
+    stats.sort_by(|a, b| a.path.cmp(&b.path));
+    for pool in stats.iter().filter(|pool| pool.held_too_long > 0) {
+        log::warn!(
+            "{} connection(s) to {} held too long, the oldest for {:.0} ms",
+            pool.held_too_long,
+            pool.path,
+            pool.longest_hold_ms.unwrap_or_default()
+        );
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_times() {
+        let metrics = PoolMetrics::default();
+        let now = Instant::now();
+        metrics.record_checkout(1, Duration::from_millis(2), now);
+        metrics.record_checkout(2, Duration::from_millis(6), now);
+        let counters = metrics.snapshot(now);
+        assert_eq!(counters.checkouts, 2);
+        assert_eq!(counters.avg_wait_ms, 4.0);
+        assert_eq!(counters.max_wait_ms, 6.0);
+        assert_eq!(counters.longest_hold_ms, Some(0.0));
+    }
+
+    #[test]
+    fn test_long_holds() {
+        let metrics = PoolMetrics::default();
+        let now = Instant::now();
+        metrics.record_checkout(1, Duration::ZERO, now);
+        metrics.record_checkout(2, Duration::ZERO, now);
+        let later = now + LONG_HOLD + Duration::from_secs(1);
+        assert_eq!(metrics.snapshot(later).held_too_long, 2);
+
+        assert!(metrics.record_checkin(1, later - now));
+        assert!(!metrics.record_checkin(2, Duration::from_millis(5)));
+        let counters = metrics.snapshot(later);
+        assert_eq!(counters.long_holds, 1);
+        assert_eq!(counters.held_too_long, 0);
+        assert_eq!(counters.longest_hold_ms, None);
+    }
+}
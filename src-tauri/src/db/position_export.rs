@@ -0,0 +1,227 @@
+//! Export of the move statistics of a position as a table for pasting into forums or notes.
+//!
+//! The table has one row per move played in the position: the move, the number of games, the
+//! score of the side to move and the average Elo of the players. It is rendered here rather than
+//! in the frontend so every export looks the same, with numbers in the user's regional format.
+
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use diesel::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use shakmaty::{fen::Fen, ByColor, Color};
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        pgn::MaterialCount,
+        schema::games,
+        search::SearchTarget,
+        ConnectionOptions, PositionQueryJs, PositionStats,
+    },
+    error::Result,
+    regional::{current_format, RegionalFormat},
+    AppState,
+};
+
+#[derive(Debug, Clone, Copy, Deserialize, Type)]
+pub enum StatsTableFormat {
+    #[serde(rename = "markdown")]
+    Markdown,
+    #[serde(rename = "csv")]
+    Csv,
+}
+
+/// Statistics of one move, with the Elo totals needed for the average.
+#[derive(Debug, Clone)]
+struct MoveRow {
+    stats: PositionStats,
+    elo_sum: i64,
+    elo_count: i64,
+}
+
+impl MoveRow {
+    fn games(&self) -> i32 {
+        self.stats.white + self.stats.draw + self.stats.black
+    }
+
+    /// Score of `side` in percent, counting a draw as half a point.
+    fn score(&self, side: Color) -> f64 {
+        let wins = match side {
+            Color::White => self.stats.white,
+            Color::Black => self.stats.black,
+        };
+        (wins as f64 + self.stats.draw as f64 / 2.0) / self.games().max(1) as f64 * 100.0
+    }
+
+    fn average_elo(&self) -> Option<i64> {
+        (self.elo_count > 0).then(|| (self.elo_sum + self.elo_count / 2) / self.elo_count)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CsvRow {
+    #[serde(rename = "move")]
+    move_: String,
+    games: i32,
+    score: String,
+    average_elo: String,
+}
+
+fn markdown_table(rows: &[MoveRow], side: Color, format: &RegionalFormat) -> String {
+    let mut out = String::from("| Move | Games | Score | Avg Elo |\n|:-----|------:|------:|--------:|\n");
+    for row in rows {
+        out.push_str(&format!(
+            "| {} | {} | {}% | {} |\n",
+            row.stats.move_,
+            format.format_number(row.games() as f64, 0),
+            format.format_number(row.score(side), 1),
+            row.average_elo().map(|elo| elo.to_string()).unwrap_or_else(|| "-".to_string()),
+        ));
+    }
+    out
+}
+
+fn csv_table(rows: &[MoveRow], side: Color, format: &RegionalFormat) -> Result<String> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(format.csv_delimiter())
+        .from_writer(Vec::new());
+    for row in rows {
+        writer
+            .serialize(CsvRow {
+                move_: row.stats.move_.clone(),
+                games: row.games(),
+                score: format.localize_decimal(&format!("{:.1}", row.score(side))),
+                average_elo: row.average_elo().map(|elo| elo.to_string()).unwrap_or_default(),
+            })
+            .map_err(std::io::Error::from)?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Scan the database for games reaching `fen` and collect the statistics of each next move.
+fn move_rows(db: &mut SqliteConnection, fen: &str) -> Result<Vec<MoveRow>> {
+    let target = SearchTarget::from_js(&PositionQueryJs {
+        fen: fen.to_string(),
+        type_: "exact".to_string(),
+        include_mirrored: None,
+        constraints: None,
+    })?;
+
+    let games: Vec<(Option<String>, Vec<u8>, Option<String>, i32, i32, i32, Option<i32>, Option<i32>)> =
+        games::table
+            .select((
+                games::result,
+                games::moves,
+                games::fen,
+                games::pawn_home,
+                games::white_material,
+                games::black_material,
+                games::white_elo,
+                games::black_elo,
+            ))
+            .load(db)?;
+
+    let rows: Mutex<HashMap<String, MoveRow>> = Mutex::new(HashMap::new());
+    games.par_iter().for_each(
+        |(result, moves, game_fen, pawn_home, white_material, black_material, white_elo, black_elo)| {
+            let material: MaterialCount = ByColor {
+                white: *white_material as u8,
+                black: *black_material as u8,
+            };
+            if !target.can_reach(&material, *pawn_home as u16) {
+                return;
+            }
+            let Some((m, result)) = target.match_game(moves, game_fen, result.as_deref()) else {
+                return;
+            };
+            let elos: Vec<i32> = [*white_elo, *black_elo].into_iter().flatten().collect();
+
+            let mut rows = rows.lock().unwrap();
+            let row = rows.entry(m.clone()).or_insert_with(|| MoveRow {
+                stats: PositionStats {
+                    move_: m,
+                    white: 0,
+                    draw: 0,
+                    black: 0,
+                },
+                elo_sum: 0,
+                elo_count: 0,
+            });
+            match result {
+                Some("1-0") => row.stats.white += 1,
+                Some("0-1") => row.stats.black += 1,
+                Some("1/2-1/2") => row.stats.draw += 1,
+                _ => (),
+            }
+            if !elos.is_empty() {
+                row.elo_sum += elos.iter().map(|&e| e as i64).sum::<i64>() / elos.len() as i64;
+                row.elo_count += 1;
+            }
+        },
+    );
+
+    let mut rows: Vec<MoveRow> = rows.into_inner().unwrap().into_values().collect();
+    rows.retain(|row| row.games() > 0);
+    rows.sort_by(|a, b| b.games().cmp(&a.games()).then_with(|| a.stats.move_.cmp(&b.stats.move_)));
+    Ok(rows)
+}
+
+/// Move statistics of `fen` as a Markdown or CSV table, most played move first.
+#[tauri::command]
+#[specta::specta]
+pub async fn export_position_stats(
+    file: PathBuf,
+    fen: String,
+    format: StatsTableFormat,
+    state: tauri::State<'_, AppState>,
+) -> Result<String> {
+    let side = Fen::from_ascii(fen.as_bytes())?.as_setup().turn;
+
+    let permit = state.new_request.acquire().await.unwrap();
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let rows = move_rows(db, &fen)?;
+    drop(permit);
+
+    let regional = current_format();
+    match format {
+        StatsTableFormat::Markdown => Ok(markdown_table(&rows, side, &regional)),
+        StatsTableFormat::Csv => csv_table(&rows, side, &regional),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(move_: &str, white: i32, draw: i32, black: i32, elo: Option<i64>) -> MoveRow {
+        MoveRow {
+            stats: PositionStats {
+                move_: move_.to_string(),
+                white,
+                draw,
+                black,
+            },
+            elo_sum: elo.unwrap_or(0) * 2,
+            elo_count: if elo.is_some() { 2 } else { 0 },
+        }
+    }
+
+    #[test]
+    fn test_markdown_table() {
+        let rows = vec![row("e4", 5, 2, 3, Some(2400)), row("d4", 1, 0, 0, None)];
+        let table = markdown_table(&rows, Color::White, &RegionalFormat::default());
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "| Move | Games | Score | Avg Elo |");
+        assert_eq!(lines[2], "| e4 | 10 | 60.0% | 2400 |");
+        assert_eq!(lines[3], "| d4 | 1 | 100.0% | - |");
+    }
+
+    #[test]
+    fn test_score_for_black() {
+        let row = row("e5", 3, 2, 5, None);
+        assert_eq!(row.score(Color::Black), 60.0);
+    }
+}
@@ -0,0 +1,240 @@
+//! Per-game Bloom filters of visited boards, used to skip games during exact position search.
+//!
+//! Replaying a game in `get_move_after_match` is what position search spends its time on, and
+//! most games never reach the searched position. `build_position_checkpoints` therefore stores,
+//! for every game, the hash of its final board and a Bloom filter of the hashes of the boards
+//! visited before it, in the `game_position_filters` table. An exact search hashes the target
+//! board once and skips every game whose filter rules it out, without decoding a single move.
+//!
+//! Filters answer "maybe" for boards they have not seen with a small probability, never "no" for
+//! boards they have. `update_game` and `remove_game` drop the filter of the game they change, and
+//! the filters are removed with their game, so edited games are replayed until the next build.
+//! The filters kept in `AppState` are reloaded after any write to the database, and a filter is
+//! also ignored when the length of the game's move blob no longer matches the one it was built
+//! from.
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Binary, Integer},
+};
+use shakmaty::{Chess, Position};
+
+use crate::{
+    db::{encoding::decode_move, search::board_hash},
+    error::{Error, Result},
+    AppState,
+};
+
+/// Filter size per stored board. With `HASHES` probes this rejects about 97% of the games that do
+/// not reach the target.
+const BITS_PER_BOARD: usize = 8;
+const HASHES: u64 = 4;
+
+pub struct PositionFilter {
+    moves_len: usize,
+    final_hash: u64,
+    bloom: Vec<u8>,
+}
+
+/// Filters of a database by game id.
+pub type PositionFilters = HashMap<i32, PositionFilter>;
+
+/// Bits probed for `hash` in a filter of `bits` bits, by double hashing.
+fn bit_indices(hash: u64, bits: usize) -> impl Iterator<Item = usize> {
+    let step = hash.rotate_left(32) | 1;
+    (0..HASHES).map(move |i| (hash.wrapping_add(i.wrapping_mul(step)) % bits as u64) as usize)
+}
+
+impl PositionFilter {
+    /// Filter of the boards `get_move_after_match` visits when replaying `moves` from `start`.
+    pub(super) fn build(moves: &[u8], start: Chess) -> Self {
+        let mut position = start;
+        let mut hashes = vec![board_hash(position.board())];
+        for &byte in moves {
+            let Some(m) = decode_move(byte, &position) else {
+                break;
+            };
+            position.play_unchecked(&m);
+            hashes.push(board_hash(position.board()));
+        }
+        let final_hash = hashes.pop().unwrap_or_default();
+
+        let bits = (hashes.len() * BITS_PER_BOARD).div_ceil(64).max(1) * 64;
+        let mut bloom = vec![0u8; bits / 8];
+        for hash in hashes {
+            for index in bit_indices(hash, bits) {
+                bloom[index / 8] |= 1 << (index % 8);
+            }
+        }
+        Self {
+            moves_len: moves.len(),
+            final_hash,
+            bloom,
+        }
+    }
+
+    fn may_contain(&self, hash: u64) -> bool {
+        hash == self.final_hash
+            || bit_indices(hash, self.bloom.len() * 8)
+                .all(|index| self.bloom[index / 8] & (1 << (index % 8)) != 0)
+    }
+}
+
+/// Whether the filter of game `id` proves that it never reaches any of the boards `hashes`.
+/// Games without a filter, or whose moves changed since it was built, are never ruled out.
+#[inline]
+pub(super) fn rules_out(
+    filters: Option<&PositionFilters>,
+    hashes: &[u64],
+    id: i32,
+    moves: &[u8],
+) -> bool {
+    let Some(filter) = filters.and_then(|filters| filters.get(&id)) else {
+        return false;
+    };
+    !hashes.is_empty()
+        && filter.moves_len == moves.len()
+        && !hashes.iter().any(|hash| filter.may_contain(*hash))
+}
+
+/// Drop the filter of game `id`, whose moves changed or which is removed.
+pub(super) fn forget_filter(db: &mut SqliteConnection, id: i32) -> Result<()> {
+    sql_query("DELETE FROM game_position_filters WHERE game_id = ?")
+        .bind::<Integer, _>(id)
+        .execute(db)?;
+    Ok(())
+}
+
+/// Store the filters of a batch of games, replacing older ones.
+pub(super) fn store_filters(db: &mut SqliteConnection, filters: &[(i32, PositionFilter)]) -> Result<()> {
+    db.transaction::<_, Error, _>(|db| {
+        for (game_id, filter) in filters {
+            sql_query(
+                "INSERT OR REPLACE INTO game_position_filters \
+                 (game_id, moves_len, final_hash, bloom) VALUES (?, ?, ?, ?)",
+            )
+            .bind::<Integer, _>(game_id)
+            .bind::<Integer, _>(filter.moves_len as i32)
+            .bind::<BigInt, _>(filter.final_hash as i64)
+            .bind::<Binary, _>(&filter.bloom)
+            .execute(db)?;
+        }
+        Ok(())
+    })
+}
+
+#[derive(QueryableByName)]
+struct FilterRow {
+    #[diesel(sql_type = Integer)]
+    game_id: i32,
+    #[diesel(sql_type = Integer)]
+    moves_len: i32,
+    #[diesel(sql_type = BigInt)]
+    final_hash: i64,
+    #[diesel(sql_type = Binary)]
+    bloom: Vec<u8>,
+}
+
+fn load_filters(db: &mut SqliteConnection) -> Result<PositionFilters> {
+    let rows: Vec<FilterRow> =
+        sql_query("SELECT game_id, moves_len, final_hash, bloom FROM game_position_filters")
+            .load(db)?;
+    Ok(rows
+        .into_iter()
+        .filter(|row| !row.bloom.is_empty())
+        .map(|row| {
+            let filter = PositionFilter {
+                moves_len: row.moves_len as usize,
+                final_hash: row.final_hash as u64,
+                bloom: row.bloom,
+            };
+            (row.game_id, filter)
+        })
+        .collect())
+}
+
+/// Filters of the database `file` for a search of the boards `hashes`, kept in `AppState` until
+/// the next write to the database. `None` when the search cannot use them (no exact target) or
+/// none can be loaded.
+pub(super) fn filters_for(
+    hashes: &[u64],
+    state: &AppState,
+    db: &mut SqliteConnection,
+    file: &Path,
+) -> Option<Arc<PositionFilters>> {
+    if hashes.is_empty() {
+        return None;
+    }
+    let generation = state.db_writes.generation(file);
+    if let Some(entry) = state.position_filters.get(file) {
+        let (loaded_at, filters) = &*entry;
+        if *loaded_at == generation {
+            return Some(filters.clone());
+        }
+    }
+    match load_filters(db) {
+        Ok(filters) => {
+            let filters = Arc::new(filters);
+            state
+                .position_filters
+                .insert(file.to_path_buf(), (generation, filters.clone()));
+            Some(filters)
+        }
+        Err(e) => {
+            log::warn!("Failed to load position filters of {}: {}", file.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(sans: &[&str]) -> Vec<u8> {
+        let mut position = Chess::default();
+        let mut bytes = Vec::new();
+        for san in sans {
+            let m = san.parse::<shakmaty::san::San>().unwrap().to_move(&position).unwrap();
+            let index = position.legal_moves().iter().position(|legal| *legal == m).unwrap();
+            bytes.push(index as u8);
+            position.play_unchecked(&m);
+        }
+        bytes
+    }
+
+    fn board_after(sans: &[&str]) -> u64 {
+        let mut position = Chess::default();
+        for san in sans {
+            let m = san.parse::<shakmaty::san::San>().unwrap().to_move(&position).unwrap();
+            position.play_unchecked(&m);
+        }
+        board_hash(position.board())
+    }
+
+    #[test]
+    fn test_filter_contains_visited_boards() {
+        let moves = encode(&["e4", "e5", "Nf3", "Nc6"]);
+        let filter = PositionFilter::build(&moves, Chess::default());
+        assert!(filter.may_contain(board_after(&[])));
+        assert!(filter.may_contain(board_after(&["e4", "e5"])));
+        assert!(filter.may_contain(board_after(&["e4", "e5", "Nf3", "Nc6"])));
+        assert!(!filter.may_contain(board_after(&["d4"])));
+    }
+
+    #[test]
+    fn test_rules_out() {
+        let moves = encode(&["e4", "e5"]);
+        let filters = PositionFilters::from([(1, PositionFilter::build(&moves, Chess::default()))]);
+        let d4 = [board_after(&["d4"])];
+        assert!(rules_out(Some(&filters), &d4, 1, &moves));
+        assert!(!rules_out(Some(&filters), &[board_after(&["e4"])], 1, &moves));
+        // Edited games and games without a filter are replayed.
+        assert!(!rules_out(Some(&filters), &d4, 1, &encode(&["d4"])));
+        assert!(!rules_out(Some(&filters), &d4, 2, &moves));
+        assert!(!rules_out(None, &d4, 1, &moves));
+    }
+}
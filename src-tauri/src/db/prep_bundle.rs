@@ -0,0 +1,129 @@
+//! Opponent preparation bundles.
+//!
+//! A bundle is a single zstd-compressed JSON document containing selected repertoire lines, key
+//! games (as PGN) and free-form notes about one opponent. It is self-contained so it can be copied
+//! to another device and opened with `import_prep_bundle` without access to the source database.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+
+use diesel::{connection::DefaultLoadingMode, prelude::*};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        models::{Event, Game, Player, Site},
+        schema::{events, games, players, sites},
+        ConnectionOptions, PgnExportOptions, PgnFormat, PgnGame,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+const BUNDLE_FORMAT: &str = "pawn-appetit-prep";
+const BUNDLE_VERSION: u32 = 1;
+const COMPRESSION_LEVEL: i32 = 9;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BundlePlayer {
+    pub name: Option<String>,
+    pub elo: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PrepBundle {
+    pub format: String,
+    pub version: u32,
+    pub created_at: String,
+    pub player: BundlePlayer,
+    /// Repertoire lines as PGN move text.
+    pub lines: Vec<String>,
+    /// Key games as complete PGN, headers included.
+    pub games: Vec<String>,
+    pub notes: Option<String>,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn export_prep_bundle(
+    file: PathBuf,
+    player_id: i32,
+    lines: Vec<String>,
+    games: Vec<i32>,
+    notes: Option<String>,
+    dest_file: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let player: Player = players::table.find(player_id).first(db)?;
+
+    let (white_players, black_players) = diesel::alias!(players as white, players as black);
+    let game_pgns = games::table
+        .inner_join(white_players.on(games::white_id.eq(white_players.field(players::id))))
+        .inner_join(black_players.on(games::black_id.eq(black_players.field(players::id))))
+        .inner_join(events::table.on(games::event_id.eq(events::id)))
+        .inner_join(sites::table.on(games::site_id.eq(sites::id)))
+        .filter(games::id.eq_any(&games))
+        .load_iter::<(Game, Player, Player, Event, Site), DefaultLoadingMode>(db)?
+        .flatten()
+        .map(|row| {
+            let mut buf = Vec::new();
+            PgnGame::from_row(row, &PgnFormat::default(), &PgnExportOptions::default())?.write(&mut buf)?;
+            Ok(String::from_utf8(buf)?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let bundle = PrepBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        player: BundlePlayer {
+            name: player.name,
+            elo: player.elo,
+        },
+        lines,
+        games: game_pgns,
+        notes,
+    };
+
+    let writer = BufWriter::new(File::create(&dest_file)?);
+    let mut encoder = zstd::Encoder::new(writer, COMPRESSION_LEVEL)?;
+    serde_json::to_writer(&mut encoder, &bundle)
+        .map_err(|e| Error::PackageManager(format!("Failed to write prep bundle: {}", e)))?;
+    encoder.finish()?;
+
+    log::info!(
+        "Exported prep bundle with {} lines and {} games to {}",
+        bundle.lines.len(),
+        bundle.games.len(),
+        dest_file.display()
+    );
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn import_prep_bundle(path: PathBuf) -> Result<PrepBundle> {
+    let decoder = zstd::Decoder::new(BufReader::new(File::open(&path)?))?;
+    let bundle: PrepBundle = serde_json::from_reader(decoder)
+        .map_err(|e| Error::PackageManager(format!("Invalid prep bundle: {}", e)))?;
+
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(Error::UnsupportedFileFormat(bundle.format));
+    }
+    if bundle.version > BUNDLE_VERSION {
+        return Err(Error::PackageManager(format!(
+            "Prep bundle version {} is newer than supported version {}",
+            bundle.version, BUNDLE_VERSION
+        )));
+    }
+    Ok(bundle)
+}
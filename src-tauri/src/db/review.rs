@@ -0,0 +1,374 @@
+//! One-click game review.
+//!
+//! `review_game` runs the engine over the main line of a stored game and turns the raw analysis
+//! into a single report: per-move classification, accuracy and ACPL per side, the opening name and
+//! the most critical moments. The report is also written to the app data directory so it can be
+//! reopened with `get_game_review` without analyzing the game again.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    fen::Fen, san::SanPlus, CastlingMode, Chess, Color, EnPassantMode, FromSetup, Position,
+};
+use specta::Type;
+use tauri::{path::BaseDirectory, Manager};
+use vampirc_uci::uci::{Score, ScoreValue};
+
+use crate::{
+    chess::{AnalysisOptions, GameAnalysisService, GoMode, MoveAnalysis},
+    db::{
+        eval_comment::EVAL_CAP_CP,
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode},
+        schema::games,
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    opening::get_opening_from_setup,
+    AppState,
+};
+
+const REVIEW_DEPTH: u32 = 18;
+const KEY_MOMENTS: usize = 3;
+const OPENING_LOOKUP_PLIES: usize = 30;
+
+/// Win-chance drops (in percentage points) separating the classifications.
+pub(super) const INACCURACY_THRESHOLD: f64 = 5.0;
+pub(super) const MISTAKE_THRESHOLD: f64 = 10.0;
+pub(super) const BLUNDER_THRESHOLD: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum MoveClassification {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewedMove {
+    pub ply: u32,
+    pub color: String,
+    pub san: String,
+    /// Evaluations in centipawns from white's point of view.
+    pub eval_before: f64,
+    pub eval_after: f64,
+    pub best_move: Option<String>,
+    pub cp_loss: f64,
+    pub win_chance_loss: f64,
+    pub classification: MoveClassification,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyMoment {
+    pub ply: u32,
+    /// Position before the move was played.
+    pub fen: String,
+    pub san: String,
+    pub best_move: Option<String>,
+    pub win_chance_loss: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SideReview {
+    pub accuracy: f64,
+    pub acpl: f64,
+    pub inaccuracies: u32,
+    pub mistakes: u32,
+    pub blunders: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GameReview {
+    pub game_id: i32,
+    pub engine: String,
+    pub created_at: String,
+    pub opening: Option<String>,
+    pub white: SideReview,
+    pub black: SideReview,
+    pub moves: Vec<ReviewedMove>,
+    pub key_moments: Vec<KeyMoment>,
+}
+
+pub(crate) fn score_cp(score: &Score) -> f64 {
+    match score.value {
+        ScoreValue::Cp(cp) => (cp as f64).clamp(-EVAL_CAP_CP, EVAL_CAP_CP),
+        ScoreValue::Mate(n) if n > 0 => EVAL_CAP_CP,
+        ScoreValue::Mate(_) => -EVAL_CAP_CP,
+    }
+}
+
+pub(crate) fn win_chance(cp: f64) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * cp).exp()) - 1.0)
+}
+
+fn move_accuracy(win_chance_loss: f64) -> f64 {
+    (103.1668 * (-0.04354 * win_chance_loss).exp() - 3.1669 + 1.0).clamp(0.0, 100.0)
+}
+
+fn harmonic_mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.len() as f64 / values.iter().map(|v| 1.0 / v.max(1.0)).sum::<f64>()
+}
+
+pub(crate) fn same_move(a: &str, b: &str) -> bool {
+    a.trim_end_matches(['+', '#', '!', '?']) == b.trim_end_matches(['+', '#', '!', '?'])
+}
+
+fn classify(played: &str, best: Option<&str>, win_chance_loss: f64) -> MoveClassification {
+    if best.is_some_and(|best| same_move(played, best)) {
+        MoveClassification::Best
+    } else if win_chance_loss > BLUNDER_THRESHOLD {
+        MoveClassification::Blunder
+    } else if win_chance_loss > MISTAKE_THRESHOLD {
+        MoveClassification::Mistake
+    } else if win_chance_loss > INACCURACY_THRESHOLD {
+        MoveClassification::Inaccuracy
+    } else {
+        MoveClassification::Good
+    }
+}
+
+/// Evaluation of a terminal position, where the engine is not consulted.
+pub(crate) fn terminal_eval(position: &Chess) -> f64 {
+    if position.is_checkmate() {
+        match position.turn() {
+            Color::White => -EVAL_CAP_CP,
+            Color::Black => EVAL_CAP_CP,
+        }
+    } else {
+        0.0
+    }
+}
+
+pub(crate) fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+fn summarize(moves: &[ReviewedMove], color: Color) -> SideReview {
+    let own: Vec<&ReviewedMove> = moves
+        .iter()
+        .filter(|m| m.color == color_name(color))
+        .collect();
+    let count = |c: MoveClassification| own.iter().filter(|m| m.classification == c).count() as u32;
+    let accuracies: Vec<f64> = own.iter().map(|m| move_accuracy(m.win_chance_loss)).collect();
+
+    SideReview {
+        accuracy: harmonic_mean(&accuracies),
+        acpl: if own.is_empty() {
+            0.0
+        } else {
+            own.iter().map(|m| m.cp_loss).sum::<f64>() / own.len() as f64
+        },
+        inaccuracies: count(MoveClassification::Inaccuracy),
+        mistakes: count(MoveClassification::Mistake),
+        blunders: count(MoveClassification::Blunder),
+    }
+}
+
+fn review_path(app: &tauri::AppHandle, file: &PathBuf, game_id: i32) -> Result<PathBuf> {
+    let db_name = file
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("database");
+    Ok(app.path().resolve(
+        format!("reviews/{}-{}.json", db_name, game_id),
+        BaseDirectory::AppData,
+    )?)
+}
+
+/// Analyze a game with `engine` and build its review, replacing any previously saved one.
+#[tauri::command]
+#[specta::specta]
+pub async fn review_game(
+    file: PathBuf,
+    game_id: i32,
+    engine: String,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<GameReview> {
+    let (moves, fen): (Vec<u8>, Option<String>) = {
+        let db =
+            &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+        games::table
+            .find(game_id)
+            .select((games::moves, games::fen))
+            .first(db)?
+    };
+
+    let start = match fen.as_deref() {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let start_fen = Fen::from_position(start.clone(), EnPassantMode::Legal).to_string();
+    let tree = GameTree::from_bytes(&moves, Some(start.clone()))?;
+
+    // Replay the main line once, collecting everything the review needs per ply.
+    let mut position = start;
+    let mut played: Vec<(SanPlus, String, Chess)> = Vec::new();
+    let mut opening = None;
+    for node in tree.nodes() {
+        if let GameTreeNode::Move(san_plus) = node {
+            let m = san_plus.san.to_move(&position)?;
+            let before = position.clone();
+            position.play_unchecked(&m);
+            if played.len() < OPENING_LOOKUP_PLIES {
+                if let Ok(name) =
+                    get_opening_from_setup(position.clone().into_setup(EnPassantMode::Legal))
+                {
+                    opening = Some(name);
+                }
+            }
+            played.push((
+                san_plus.clone(),
+                m.to_uci(CastlingMode::Standard).to_string(),
+                before,
+            ));
+        }
+    }
+    if played.is_empty() {
+        return Err(Error::NoMovesFound);
+    }
+
+    let analysis: Vec<MoveAnalysis> = GameAnalysisService::analyze_game(
+        format!("review_{}", game_id),
+        engine.clone(),
+        GoMode::Depth(REVIEW_DEPTH),
+        AnalysisOptions {
+            fen: start_fen,
+            moves: played.iter().map(|(_, uci, _)| uci.clone()).collect(),
+            ..Default::default()
+        },
+        Vec::new(),
+        state.clone(),
+        app.clone(),
+    )
+    .await?;
+
+    let eval_at = |ply: usize| -> f64 {
+        match analysis.get(ply).and_then(|a| a.best.first()) {
+            Some(best) => score_cp(&best.score),
+            None if ply == played.len() => terminal_eval(&position),
+            None => 0.0,
+        }
+    };
+
+    let mut reviewed = Vec::with_capacity(played.len());
+    let mut moments: Vec<KeyMoment> = Vec::new();
+    for (i, (san_plus, _, before)) in played.iter().enumerate() {
+        let eval_before = eval_at(i);
+        let eval_after = eval_at(i + 1);
+        let sign = match before.turn() {
+            Color::White => 1.0,
+            Color::Black => -1.0,
+        };
+        let cp_loss = (sign * (eval_before - eval_after)).max(0.0);
+        let win_chance_loss =
+            (win_chance(sign * eval_before) - win_chance(sign * eval_after)).max(0.0);
+        let best_move = analysis
+            .get(i)
+            .and_then(|a| a.best.first())
+            .and_then(|b| b.san_moves.first().cloned());
+        let san = san_plus.to_string();
+
+        moments.push(KeyMoment {
+            ply: i as u32 + 1,
+            fen: Fen::from_position(before.clone(), EnPassantMode::Legal).to_string(),
+            san: san.clone(),
+            best_move: best_move.clone(),
+            win_chance_loss,
+        });
+        reviewed.push(ReviewedMove {
+            ply: i as u32 + 1,
+            color: color_name(before.turn()).to_string(),
+            classification: classify(&san, best_move.as_deref(), win_chance_loss),
+            san,
+            eval_before,
+            eval_after,
+            best_move,
+            cp_loss,
+            win_chance_loss,
+        });
+    }
+
+    moments.sort_by(|a, b| b.win_chance_loss.total_cmp(&a.win_chance_loss));
+    moments.truncate(KEY_MOMENTS);
+    moments.retain(|m| m.win_chance_loss > INACCURACY_THRESHOLD);
+    moments.sort_by_key(|m| m.ply);
+
+    let review = GameReview {
+        game_id,
+        engine,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        opening,
+        white: summarize(&reviewed, Color::White),
+        black: summarize(&reviewed, Color::Black),
+        moves: reviewed,
+        key_moments: moments,
+    };
+
+    let path = review_path(&app, &file, game_id)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_vec(&review)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize review: {}", e)))?;
+    std::fs::write(&path, json)?;
+
+    Ok(review)
+}
+
+/// The last review saved for a game, if it has been reviewed.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_game_review(
+    file: PathBuf,
+    game_id: i32,
+    app: tauri::AppHandle,
+) -> Result<Option<GameReview>> {
+    let path = review_path(&app, &file, game_id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let review = serde_json::from_slice(&std::fs::read(&path)?)
+        .map_err(|e| Error::PackageManager(format!("Invalid saved review: {}", e)))?;
+    Ok(Some(review))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("Nf3+", Some("Nf3"), 30.0), MoveClassification::Best);
+        assert_eq!(classify("e4", Some("d4"), 2.0), MoveClassification::Good);
+        assert_eq!(classify("e4", Some("d4"), 7.0), MoveClassification::Inaccuracy);
+        assert_eq!(classify("e4", Some("d4"), 15.0), MoveClassification::Mistake);
+        assert_eq!(classify("e4", None, 25.0), MoveClassification::Blunder);
+    }
+
+    #[test]
+    fn test_accuracy() {
+        assert_eq!(move_accuracy(0.0), 100.0);
+        assert!(move_accuracy(20.0) < 50.0);
+        assert_eq!(harmonic_mean(&[]), 0.0);
+        assert!((win_chance(0.0) - 50.0).abs() < f64::EPSILON);
+    }
+}
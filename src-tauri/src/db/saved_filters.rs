@@ -0,0 +1,99 @@
+//! Named game filters ("database views") persisted inside each database file.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use serde::Serialize;
+use specta::Type;
+
+use crate::{
+    db::{get_db_or_create, schema::saved_filters, ConnectionOptions, GameQueryJs},
+    error::{Error, Result},
+    AppState,
+};
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedGameFilter {
+    pub name: String,
+    pub query: GameQueryJs,
+    pub updated_at: String,
+}
+
+fn invalid_filter(e: serde_json::Error) -> Error {
+    Error::PackageManager(format!("Invalid saved filter: {}", e))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn save_game_filter(
+    file: PathBuf,
+    name: String,
+    query: GameQueryJs,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(Error::PackageManager("Filter name cannot be empty".to_string()));
+    }
+
+    let _write = state.db_writes.lock(&file, "save_game_filter").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let serialized = serde_json::to_string(&query).map_err(invalid_filter)?;
+    let now = chrono::Utc::now().to_rfc3339();
+
+    diesel::insert_into(saved_filters::table)
+        .values((
+            saved_filters::name.eq(&name),
+            saved_filters::query.eq(&serialized),
+            saved_filters::updated_at.eq(&now),
+        ))
+        .on_conflict(saved_filters::name)
+        .do_update()
+        .set((
+            saved_filters::query.eq(&serialized),
+            saved_filters::updated_at.eq(&now),
+        ))
+        .execute(db)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn list_game_filters(
+    file: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<SavedGameFilter>> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let rows: Vec<(String, String, String)> = saved_filters::table
+        .select((saved_filters::name, saved_filters::query, saved_filters::updated_at))
+        .order(saved_filters::name.asc())
+        .load(db)?;
+
+    rows.into_iter()
+        .map(|(name, query, updated_at)| {
+            Ok(SavedGameFilter {
+                name,
+                query: serde_json::from_str(&query).map_err(invalid_filter)?,
+                updated_at,
+            })
+        })
+        .collect()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn delete_game_filter(
+    file: PathBuf,
+    name: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    let _write = state.db_writes.lock(&file, "delete_game_filter").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    diesel::delete(saved_filters::table.filter(saved_filters::name.eq(name))).execute(db)?;
+    Ok(())
+}
@@ -10,6 +10,7 @@ diesel::table! {
         themes -> Nullable<Text>,
         game_url -> Nullable<Text>,
         opening_tags -> Nullable<Text>,
+        variant -> Nullable<Text>,
     }
 }
 
@@ -103,6 +104,203 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    #[sql_name = "AnalyzedGames"]
+    analyzed_games (game_id) {
+        #[sql_name = "GameID"]
+        game_id -> Integer,
+        #[sql_name = "AnalyzedAt"]
+        analyzed_at -> Text,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "ColumnStats"]
+    column_stats (kind, key) {
+        #[sql_name = "Kind"]
+        kind -> Text,
+        #[sql_name = "Key"]
+        key -> Text,
+        #[sql_name = "Games"]
+        games -> BigInt,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "GameConditionals"]
+    game_conditionals (id) {
+        #[sql_name = "ID"]
+        id -> Integer,
+        #[sql_name = "GameID"]
+        game_id -> Integer,
+        #[sql_name = "Ply"]
+        ply -> Integer,
+        #[sql_name = "Moves"]
+        moves -> Text,
+        #[sql_name = "CreatedAt"]
+        created_at -> Text,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "GameEndgames"]
+    game_endgames (game_id) {
+        #[sql_name = "GameID"]
+        game_id -> Integer,
+        #[sql_name = "Endgame"]
+        endgame -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "GameMoveData"]
+    game_move_data (game_id, ply) {
+        #[sql_name = "GameID"]
+        game_id -> Integer,
+        #[sql_name = "Ply"]
+        ply -> Integer,
+        #[sql_name = "Eval"]
+        eval -> Nullable<Integer>,
+        #[sql_name = "Mate"]
+        mate -> Nullable<Integer>,
+        #[sql_name = "ClockMs"]
+        clock_ms -> Nullable<Integer>,
+        #[sql_name = "EmtMs"]
+        emt_ms -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "GamePhases"]
+    game_phases (game_id) {
+        #[sql_name = "GameID"]
+        game_id -> Integer,
+        #[sql_name = "MiddlegamePly"]
+        middlegame_ply -> Nullable<Integer>,
+        #[sql_name = "EndgamePly"]
+        endgame_ply -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "GameSources"]
+    game_sources (game_id) {
+        #[sql_name = "GameID"]
+        game_id -> Integer,
+        #[sql_name = "Source"]
+        source -> Text,
+        #[sql_name = "ImportedAt"]
+        imported_at -> Text,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "GameTimeForfeits"]
+    game_time_forfeits (game_id) {
+        #[sql_name = "GameID"]
+        game_id -> Integer,
+        #[sql_name = "Loser"]
+        loser -> Text,
+        #[sql_name = "LoserAdvantage"]
+        loser_advantage -> Integer,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "GameTags"]
+    game_tags (game_id, tag) {
+        #[sql_name = "GameID"]
+        game_id -> Integer,
+        #[sql_name = "Tag"]
+        tag -> Text,
+        #[sql_name = "TaggedAt"]
+        tagged_at -> Text,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "MergeJournal"]
+    merge_journal (id) {
+        #[sql_name = "ID"]
+        id -> Integer,
+        #[sql_name = "Kind"]
+        kind -> Text,
+        #[sql_name = "KeptID"]
+        kept_id -> Integer,
+        #[sql_name = "Merged"]
+        merged -> Text,
+        #[sql_name = "MergedAt"]
+        merged_at -> Text,
+    }
+}
+
+diesel::table! {
+    player_fide_ids (player_id) {
+        player_id -> Integer,
+        fide_id -> BigInt,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "PlayerMonthlyStats"]
+    player_monthly_stats (player_id, site, month) {
+        #[sql_name = "PlayerID"]
+        player_id -> Integer,
+        #[sql_name = "Site"]
+        site -> Text,
+        #[sql_name = "Month"]
+        month -> Text,
+        #[sql_name = "Games"]
+        games -> Integer,
+        #[sql_name = "Wins"]
+        wins -> Integer,
+        #[sql_name = "Draws"]
+        draws -> Integer,
+        #[sql_name = "Losses"]
+        losses -> Integer,
+        #[sql_name = "RatingSum"]
+        rating_sum -> BigInt,
+        #[sql_name = "Openings"]
+        openings -> Text,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "PlayerStatsWatermark"]
+    player_stats_watermark (id) {
+        #[sql_name = "ID"]
+        id -> Integer,
+        #[sql_name = "LastGameID"]
+        last_game_id -> Integer,
+        #[sql_name = "GameCount"]
+        game_count -> BigInt,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "SchemaVersion"]
+    schema_version (version) {
+        #[sql_name = "Version"]
+        version -> Integer,
+        #[sql_name = "Name"]
+        name -> Text,
+        #[sql_name = "AppliedAt"]
+        applied_at -> Text,
+    }
+}
+
+diesel::table! {
+    #[sql_name = "SavedFilters"]
+    saved_filters (name) {
+        #[sql_name = "Name"]
+        name -> Text,
+        #[sql_name = "Query"]
+        query -> Text,
+        #[sql_name = "UpdatedAt"]
+        updated_at -> Text,
+    }
+}
+
 diesel::table! {
     #[sql_name = "Sites"]
     sites (id) {
@@ -116,4 +314,4 @@ diesel::table! {
 diesel::joinable!(games -> events (event_id));
 diesel::joinable!(games -> sites (site_id));
 
-diesel::allow_tables_to_appear_in_same_query!(comments, events, games, info, players, sites,);
+diesel::allow_tables_to_appear_in_same_query!(analyzed_games, comments, events, game_endgames, game_move_data, game_phases, game_sources, game_tags, game_time_forfeits, games, info, merge_journal, player_fide_ids, player_monthly_stats, players, sites,);
@@ -0,0 +1,183 @@
+//! Scratch databases for a quick look at a PGN.
+//!
+//! A scratch database lives either in memory, for as long as the app runs, or in a file in the
+//! system's temporary directory, so importing a few hundred games just to browse them leaves
+//! nothing behind in the user's data directory. In-memory databases are SQLite shared-cache URIs.
+//! Shared-cache connections fail with `SQLITE_LOCKED` instead of waiting for each other, and the
+//! database disappears with its last connection, so `get_db_or_create` gives them a pool of a
+//! single connection that is never closed while idle. `drop_scratch_database` closes that pool,
+//! or removes the scratch file; scratch files older than `SCRATCH_FILE_MAX_AGE` are also removed
+//! whenever a new scratch database is created.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use diesel::connection::SimpleConnection;
+use serde::Serialize;
+use specta::Type;
+
+use crate::{
+    db::{core, get_db_or_create, ConnectionOptions, INDEXES_SQL},
+    error::{Error, Result},
+    AppState,
+};
+
+const SCRATCH_DIR: &str = "pawn-appetit-scratch";
+const SCRATCH_FILE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ScratchDatabase {
+    /// Path to pass to the database commands; a `file:` URI for in-memory databases.
+    pub path: PathBuf,
+    pub in_memory: bool,
+}
+
+/// Whether `db_path` names an in-memory database rather than a file.
+pub(super) fn is_memory_database(db_path: &str) -> bool {
+    db_path == ":memory:" || (db_path.starts_with("file:") && db_path.contains("mode=memory"))
+}
+
+/// SQLite URL to open `db_path` with. A plain `:memory:` would give each pooled connection its own
+/// empty database, so it names the one shared in-memory database instead.
+pub(super) fn connection_url(db_path: &str) -> &str {
+    if db_path == ":memory:" {
+        "file::memory:?cache=shared"
+    } else {
+        db_path
+    }
+}
+
+fn scratch_dir() -> PathBuf {
+    std::env::temp_dir().join(SCRATCH_DIR)
+}
+
+fn memory_url(id: &str) -> String {
+    format!("file:scratch-{}?mode=memory&cache=shared", id)
+}
+
+/// Removes the files of `dir` last modified at least `max_age` ago, returning how many.
+fn remove_stale_files(dir: &Path, max_age: Duration) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age >= max_age)
+        })
+        .filter(|entry| fs::remove_file(entry.path()).is_ok())
+        .count()
+}
+
+/// Create an empty games database in memory or in a temporary file.
+#[tauri::command]
+#[specta::specta]
+pub async fn create_scratch_database(
+    in_memory: bool,
+    state: tauri::State<'_, AppState>,
+) -> Result<ScratchDatabase> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let path = if in_memory {
+        PathBuf::from(memory_url(&id))
+    } else {
+        let dir = scratch_dir();
+        fs::create_dir_all(&dir)?;
+        let removed = remove_stale_files(&dir, SCRATCH_FILE_MAX_AGE);
+        if removed > 0 {
+            log::info!("Removed {} old scratch databases", removed);
+        }
+        dir.join(format!("{}.db3", id))
+    };
+
+    let db = &mut get_db_or_create(&state, path.to_str().unwrap(), ConnectionOptions::default())?;
+    core::init_db(db, "Scratch", "Temporary database")?;
+    db.batch_execute(INDEXES_SQL)?;
+    Ok(ScratchDatabase { path, in_memory })
+}
+
+/// Close a database made by `create_scratch_database`: an in-memory one is gone with its
+/// connection pool, a scratch file is removed.
+#[tauri::command]
+#[specta::specta]
+pub async fn drop_scratch_database(path: PathBuf, state: tauri::State<'_, AppState>) -> Result<()> {
+    let db_path = path.to_string_lossy().into_owned();
+    let in_memory = is_memory_database(&db_path);
+    if !in_memory && path.parent() != Some(scratch_dir().as_path()) {
+        return Err(Error::PackageManager(format!(
+            "Not a scratch database: {}",
+            path.display()
+        )));
+    }
+
+    state.connection_pool.remove(&db_path);
+    state.pool_metrics.remove(&db_path);
+    state.migrated_dbs.remove(&db_path);
+    state.position_filters.remove(&path);
+    state.line_cache.retain(|(_, file), _| file != &path);
+    if !in_memory && path.exists() {
+        fs::remove_file(&path)?;
+    }
+    log::info!("Dropped scratch database {}", db_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::{prelude::*, sql_query, sql_types::Integer};
+
+    #[derive(QueryableByName)]
+    struct Value {
+        #[diesel(sql_type = Integer)]
+        value: i32,
+    }
+
+    #[test]
+    fn test_memory_database_paths() {
+        let url = memory_url("abc");
+        assert!(is_memory_database(&url));
+        assert!(is_memory_database(":memory:"));
+        assert!(!is_memory_database("/home/user/mode=memory.db3"));
+        assert_eq!(connection_url(&url), url);
+        assert_eq!(connection_url(":memory:"), "file::memory:?cache=shared");
+    }
+
+    #[test]
+    fn test_memory_database_is_shared_between_connections() {
+        let url = memory_url(&uuid::Uuid::new_v4().to_string());
+        let mut first = SqliteConnection::establish(&url).unwrap();
+        first
+            .batch_execute("CREATE TABLE t (value INTEGER); INSERT INTO t VALUES (7);")
+            .unwrap();
+
+        let mut second = SqliteConnection::establish(&url).unwrap();
+        let values: Vec<Value> = sql_query("SELECT value FROM t").load(&mut second).unwrap();
+        assert_eq!(values.iter().map(|v| v.value).collect::<Vec<_>>(), vec![7]);
+
+        let other = memory_url(&uuid::Uuid::new_v4().to_string());
+        let mut third = SqliteConnection::establish(&other).unwrap();
+        assert!(sql_query("SELECT value FROM t")
+            .load::<Value>(&mut third)
+            .is_err());
+    }
+
+    #[test]
+    fn test_remove_stale_files() {
+        let dir = std::env::temp_dir().join(format!("scratch-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.db3"), b"").unwrap();
+        assert_eq!(remove_stale_files(&dir, Duration::from_secs(3600)), 0);
+        assert_eq!(remove_stale_files(&dir, Duration::ZERO), 1);
+        assert!(!dir.join("a.db3").exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+//! User scripts over a games database.
+//!
+//! `run_script` evaluates a Rhai script against one database so power users can compute their own
+//! statistics. Scripts are sandboxed: the database is opened read-only, modules cannot be imported
+//! from disk, and a script is terminated once it runs past `SCRIPT_TIME_LIMIT` or the app shuts
+//! down. Besides the Rhai standard library a script can call:
+//!
+//! - `query(sql)` / `query(sql, params)`: rows of a `SELECT` as an array of maps keyed by column.
+//! - `positions(game_id)`: the main line of a game as maps with `ply`, `fen`, `san` and `uci`.
+//! - `table(title, columns)` and `row(values)`: add a table to the report and rows to it.
+//!
+//! Printed lines, tables and the value of the last expression make up the `ScriptReport`.
+
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use rhai::{module_resolvers::DummyModuleResolver, Array, Dynamic, Engine, EvalAltResult, Map};
+use rusqlite::{types::Value, types::ValueRef, Connection, OpenFlags};
+use serde::Serialize;
+use shakmaty::{fen::Fen, san::SanPlus, CastlingMode, Chess, EnPassantMode, FromSetup, Position};
+use specta::Type;
+
+use crate::{
+    db::{
+        encoding::extract_main_line_moves,
+        location::{DatabaseKind, DatabaseRef},
+    },
+    error::{Error, Result},
+};
+
+const SCRIPT_TIME_LIMIT: Duration = Duration::from_secs(10 * 60);
+/// Rows a single `query` may return.
+const MAX_QUERY_ROWS: usize = 1_000_000;
+const MAX_STRING_SIZE: usize = 16 * 1024 * 1024;
+const MAX_MAP_SIZE: usize = 100_000;
+const MAX_CALL_LEVELS: usize = 64;
+
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportTable {
+    pub title: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptReport {
+    /// Lines printed with `print` and `debug`.
+    pub output: Vec<String>,
+    pub tables: Vec<ReportTable>,
+    /// Value of the script's last expression, if it has one.
+    pub result: Option<String>,
+}
+
+fn open_read_only(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|e| Error::PackageManager(format!("Failed to open {}: {}", path.display(), e)))
+}
+
+fn to_dynamic(value: ValueRef) -> Dynamic {
+    match value {
+        ValueRef::Null => Dynamic::UNIT,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned().into(),
+        ValueRef::Blob(blob) => Dynamic::from_blob(blob.to_vec()),
+    }
+}
+
+fn to_sql_value(value: Dynamic) -> ScriptResult<Value> {
+    if value.is_unit() {
+        Ok(Value::Null)
+    } else if let Ok(i) = value.as_int() {
+        Ok(Value::Integer(i))
+    } else if let Ok(f) = value.as_float() {
+        Ok(Value::Real(f))
+    } else if let Ok(b) = value.as_bool() {
+        Ok(Value::Integer(b as i64))
+    } else if value.is_string() {
+        Ok(Value::Text(value.into_string()?))
+    } else {
+        Err(format!("Unsupported query parameter of type {}", value.type_name()).into())
+    }
+}
+
+fn query(db: &Connection, sql: &str, params: Array) -> ScriptResult<Array> {
+    let mut stmt = db.prepare(sql).map_err(|e| e.to_string())?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let params = params
+        .into_iter()
+        .map(to_sql_value)
+        .collect::<ScriptResult<Vec<Value>>>()?;
+    let mut rows = stmt
+        .query(rusqlite::params_from_iter(params))
+        .map_err(|e| e.to_string())?;
+
+    let mut result = Array::new();
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        if result.len() == MAX_QUERY_ROWS {
+            return Err(format!("Query returned more than {} rows", MAX_QUERY_ROWS).into());
+        }
+        let mut map = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            let value = row.get_ref(i).map_err(|e| e.to_string())?;
+            map.insert(column.as_str().into(), to_dynamic(value));
+        }
+        result.push(map.into());
+    }
+    Ok(result)
+}
+
+fn positions(db: &Connection, game_id: i64) -> ScriptResult<Array> {
+    let (moves, fen): (Vec<u8>, Option<String>) = db
+        .query_row(
+            "SELECT Moves, FEN FROM Games WHERE ID = ?",
+            [game_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| format!("Game {}: {}", game_id, e))?;
+    let mut position = match fen {
+        Some(fen) => {
+            let fen: Fen = fen
+                .parse()
+                .map_err(|e| format!("Game {}: {}", game_id, e))?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)
+                .map_err(|e| format!("Game {}: {}", game_id, e))?
+        }
+        None => Chess::default(),
+    };
+    let main_line = extract_main_line_moves(&moves, Some(position.clone()))
+        .map_err(|e| format!("Game {}: {}", game_id, e))?;
+
+    let mut result = Array::with_capacity(main_line.len());
+    for (ply, m) in main_line.iter().enumerate() {
+        let uci = m.to_uci(CastlingMode::Standard).to_string();
+        let san = SanPlus::from_move_and_play_unchecked(&mut position, m).to_string();
+        let mut map = Map::new();
+        map.insert("ply".into(), (ply as i64 + 1).into());
+        map.insert("san".into(), san.into());
+        map.insert("uci".into(), uci.into());
+        map.insert(
+            "fen".into(),
+            Fen::from_position(position.clone(), EnPassantMode::Legal)
+                .to_string()
+                .into(),
+        );
+        result.push(map.into());
+    }
+    Ok(result)
+}
+
+fn to_strings(values: Array) -> Vec<String> {
+    values
+        .into_iter()
+        .map(|value| {
+            if value.is_unit() {
+                String::new()
+            } else {
+                value.to_string()
+            }
+        })
+        .collect()
+}
+
+/// A sandboxed engine whose API reads `db` and writes to `report`.
+fn script_engine(
+    db: Rc<Connection>,
+    report: Rc<RefCell<ScriptReport>>,
+    deadline: Instant,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_QUERY_ROWS)
+        .set_max_map_size(MAX_MAP_SIZE);
+    engine.on_progress(move |_| {
+        if Instant::now() >= deadline {
+            Some("Script time limit reached".into())
+        } else if crate::shutdown::is_shutting_down() {
+            Some("Script interrupted".into())
+        } else {
+            None
+        }
+    });
+
+    let output = report.clone();
+    engine.on_print(move |line| output.borrow_mut().output.push(line.to_string()));
+    let output = report.clone();
+    engine.on_debug(move |line, _, _| output.borrow_mut().output.push(line.to_string()));
+
+    let conn = db.clone();
+    engine.register_fn("query", move |sql: &str| query(&conn, sql, Array::new()));
+    let conn = db.clone();
+    engine.register_fn("query", move |sql: &str, params: Array| {
+        query(&conn, sql, params)
+    });
+    let conn = db;
+    engine.register_fn("positions", move |game_id: i64| positions(&conn, game_id));
+
+    let tables = report.clone();
+    engine.register_fn("table", move |title: &str, columns: Array| {
+        tables.borrow_mut().tables.push(ReportTable {
+            title: title.to_string(),
+            columns: to_strings(columns),
+            rows: Vec::new(),
+        });
+    });
+    engine.register_fn("row", move |values: Array| -> ScriptResult<()> {
+        let mut report = report.borrow_mut();
+        let table = report
+            .tables
+            .last_mut()
+            .ok_or("Call table(title, columns) before adding rows")?;
+        table.rows.push(to_strings(values));
+        Ok(())
+    });
+    engine
+}
+
+fn evaluate(script: &str, db: &Path) -> Result<ScriptReport> {
+    let db = Rc::new(open_read_only(db)?);
+    let report = Rc::new(RefCell::new(ScriptReport::default()));
+    let engine = script_engine(db, report.clone(), Instant::now() + SCRIPT_TIME_LIMIT);
+
+    let value = engine
+        .eval::<Dynamic>(script)
+        .map_err(|e| Error::PackageManager(format!("Script failed: {}", e)))?;
+    drop(engine);
+
+    let mut report = report.take();
+    if !value.is_unit() {
+        report.result = Some(value.to_string());
+    }
+    Ok(report)
+}
+
+/// Run the Rhai script at `path` against the games database `file`, read-only.
+#[tauri::command]
+#[specta::specta]
+pub async fn run_script(
+    path: PathBuf,
+    file: PathBuf,
+    app: tauri::AppHandle,
+) -> Result<ScriptReport> {
+    let script = tokio::fs::read_to_string(&path).await?;
+    let db = DatabaseRef::from(file).resolve(&app, DatabaseKind::Games)?;
+    let _job = crate::shutdown::start_job();
+    log::info!("Running script {} on {}", path.display(), db.display());
+    tokio::task::spawn_blocking(move || evaluate(&script, &db))
+        .await
+        .map_err(|e| Error::PackageManager(format!("Script task failed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let db = Connection::open(file.path()).unwrap();
+        db.execute_batch(
+            "CREATE TABLE Games (ID INTEGER PRIMARY KEY, Result TEXT, Moves BLOB, FEN TEXT);
+             INSERT INTO Games VALUES (1, '1-0', x'0c0c', NULL), (2, '1/2-1/2', x'', NULL);",
+        )
+        .unwrap();
+        file
+    }
+
+    #[test]
+    fn test_script_report() {
+        let db = database();
+        let report = evaluate(
+            r#"
+            let games = query("SELECT ID, Result FROM Games WHERE Result = ?", ["1-0"]);
+            table("Wins", ["id", "result"]);
+            for game in games {
+                row([game.ID, game.Result]);
+            }
+            let line = positions(1);
+            print(line[1].san);
+            line.len()
+            "#,
+            db.path(),
+        )
+        .unwrap();
+        assert_eq!(report.output, vec!["e5"]);
+        assert_eq!(report.tables[0].rows, vec![vec!["1", "1-0"]]);
+        assert_eq!(report.result.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_script_sandbox() {
+        let db = database();
+        assert!(evaluate(r#"query("DELETE FROM Games")"#, db.path()).is_err());
+        assert!(evaluate(r#"import "other" as other;"#, db.path()).is_err());
+        assert!(evaluate(r#"row([1])"#, db.path()).is_err());
+    }
+}
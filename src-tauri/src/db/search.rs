@@ -24,26 +24,33 @@ use shakmaty::{
 };
 use specta::Type;
 use std::{
+    collections::HashMap,
     path::PathBuf,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
-use tauri::Emitter;
+use tauri::{Emitter, Manager};
 
 use crate::{
     db::{
+        column_stats::{self, PlayerScan},
         get_db_or_create, get_pawn_home,
         models::*,
         normalize_games,
         pgn::{get_material_count, MaterialCount},
+        piece_constraints::{parse_constraints, PieceConstraint},
+        position_filter::{filters_for, rules_out, store_filters, PositionFilter, PositionFilters},
         schema::*,
         ConnectionOptions, GameSort, SortDirection,
         is_position_cached, get_cached_position, save_position_cache,
     },
     error::Error,
-    AppState,
+    metrics::CommandSpan,
+    progress::{TaskKind, TaskProgress},
+    AppState, GameData,
 };
 
 use super::GameQueryJs;
@@ -59,13 +66,6 @@ const ENABLE_MATERIAL_SQL_PREFILTER: bool = true;
 /// Create minimal + material indexes automatically.
 const ENABLE_AUX_INDEXES: bool = true;
 
-/// Enable checkpoint schema auto-creation.
-const ENABLE_CHECKPOINT_TABLE_SCHEMA: bool = true;
-
-/// Checkpoint stride (every N plies).
-#[allow(dead_code)]
-const CHECKPOINT_STRIDE: usize = 8;
-
 /// ============================================================================
 /// ONLINE database detection
 /// ============================================================================
@@ -129,27 +129,23 @@ fn ensure_aux_indexes(db: &mut SqliteConnection) {
 /// Checkpoint schema
 /// ============================================================================
 
-#[inline]
-fn ensure_checkpoint_table(db: &mut SqliteConnection) {
-    let _ = diesel::sql_query(
-        r#"
-        CREATE TABLE IF NOT EXISTS game_position_checkpoints (
-            game_id INTEGER NOT NULL,
-            ply INTEGER NOT NULL,
-            board_hash INTEGER NOT NULL,
-            turn INTEGER NOT NULL,
-            PRIMARY KEY (game_id, ply)
-        );
-
-        CREATE INDEX IF NOT EXISTS idx_gpc_board_turn
-        ON game_position_checkpoints(board_hash, turn);
-
-        CREATE INDEX IF NOT EXISTS idx_gpc_board
-        ON game_position_checkpoints(board_hash);
-        "#,
-    )
-    .execute(db);
-}
+// A checkpoint marks where a game enters a new pawn structure and material balance: ply 0 and
+// every ply after a pawn move or capture. Both only change irreversibly, so every board of the
+// segment up to the next checkpoint shares the checkpoint's `structure_hash`, and a game can only
+// reach an exact target inside the segment whose hash matches the target's. Checkpoints at least
+// `RESUME_INTERVAL` plies apart also store their position, and a search resumes the replay from
+// the last of those before the matching segment instead of from the first move.
+//
+// `moves_len` is the length of the move blob the checkpoints were built from. `update_game` and
+// `remove_game` drop the checkpoints of the game they change, the rows are removed with their
+// game, and games whose blob no longer has that length are replayed in full. The table is created
+// by the database migrations in `core`.
+//
+// The index and the position filters of `position_filter` answer different questions. The index
+// is looked up in SQL, before any game is loaded, but only knows structures: in the opening one
+// structure is shared by most games of the database. The filters know the exact boards a game
+// visits, so they drop the loaded games that share the structure but never reach the target,
+// without decoding them.
 
 /// ============================================================================
 /// Hashing utilities (no external deps)
@@ -174,7 +170,7 @@ fn bb_u64(bb: Bitboard) -> u64 {
 }
 
 #[inline(always)]
-fn board_hash(board: &shakmaty::Board) -> u64 {
+pub(super) fn board_hash(board: &shakmaty::Board) -> u64 {
     let white = board.white();
     let black = board.black();
 
@@ -215,14 +211,67 @@ fn board_hash(board: &shakmaty::Board) -> u64 {
     h
 }
 
+/// Hash of the pawns and the piece counts of `board`, constant between two checkpoints.
 #[inline(always)]
-fn position_hash_and_turn(position: &Chess) -> (i64, i32) {
-    let h = board_hash(position.board());
-    let turn_i32 = match position.turn() {
-        Color::White => 0,
-        Color::Black => 1,
-    };
-    (h as i64, turn_i32)
+fn structure_hash(board: &shakmaty::Board) -> i64 {
+    let mut h = 0x0F1E_2D3C_4B5A_6978u64;
+    mix64(&mut h, bb_u64(board.pawns() & board.white()));
+    mix64(&mut h, bb_u64(board.pawns() & board.black()));
+    for color in [Color::White, Color::Black] {
+        let side = board.by_color(color);
+        for pieces in [
+            board.knights(),
+            board.bishops(),
+            board.rooks(),
+            board.queens(),
+        ] {
+            mix64(&mut h, (pieces & side).count() as u64);
+        }
+    }
+    h as i64
+}
+
+/// Minimum plies between two checkpoints that store their position.
+const RESUME_INTERVAL: i32 = 24;
+
+struct Checkpoint {
+    ply: i32,
+    structure_hash: i64,
+    /// Position at `ply`, for the checkpoints a search can resume from.
+    fen: Option<String>,
+}
+
+/// Checkpoints of a game, replaying the same moves as `get_move_after_match`.
+fn checkpoint_segments(moves: &[u8], start: Chess) -> Vec<Checkpoint> {
+    use crate::db::encoding::decode_move;
+
+    let mut position = start;
+    let mut segments = vec![Checkpoint {
+        ply: 0,
+        structure_hash: structure_hash(position.board()),
+        fen: None,
+    }];
+    let mut last_resume = 0;
+    for (i, &byte) in moves.iter().enumerate() {
+        let Some(m) = decode_move(byte, &position) else {
+            break;
+        };
+        let irreversible = m.is_capture() || m.role() == shakmaty::Role::Pawn;
+        position.play_unchecked(&m);
+        if irreversible {
+            let ply = i as i32 + 1;
+            let fen = (ply - last_resume >= RESUME_INTERVAL).then(|| {
+                last_resume = ply;
+                Fen::from_position(position.clone(), EnPassantMode::Legal).to_string()
+            });
+            segments.push(Checkpoint {
+                ply,
+                structure_hash: structure_hash(position.board()),
+                fen,
+            });
+        }
+    }
+    segments
 }
 
 /// ============================================================================
@@ -320,6 +369,8 @@ pub struct PartialData {
     piece_positions: Setup,
     material: MaterialCount,
     masks: PartialMasks,
+    /// Wildcard conditions checked in addition to the fixed piece placements.
+    constraints: Vec<PieceConstraint>,
 }
 
 /// Query type for searching positions
@@ -352,9 +403,23 @@ impl PositionQuery {
             piece_positions: setup,
             material,
             masks,
+            constraints: Vec::new(),
         }))
     }
 
+    /// Attach piece-count constraints (see `piece_constraints`) to a partial query.
+    pub fn with_constraints(self, constraints: &str) -> Result<PositionQuery, Error> {
+        match self {
+            PositionQuery::Partial(mut data) => {
+                data.constraints = parse_constraints(constraints)?;
+                Ok(PositionQuery::Partial(data))
+            }
+            PositionQuery::Exact(_) => Err(Error::FenError(
+                "Piece constraints require a partial position query".to_string(),
+            )),
+        }
+    }
+
     #[inline(always)]
     fn target_material(&self) -> &MaterialCount {
         match self {
@@ -368,18 +433,191 @@ impl PositionQuery {
 pub struct PositionQueryJs {
     pub fen: String,
     pub type_: String,
+    /// Also match the color-flipped position and report it from the query side's perspective.
+    #[serde(default)]
+    #[specta(optional)]
+    pub include_mirrored: Option<bool>,
+    /// Piece-count constraints for partial queries, e.g. `"R@7, p<=5"`.
+    #[serde(default)]
+    #[specta(optional)]
+    pub constraints: Option<String>,
 }
 
 /// Convert JavaScript position query to internal format
 #[inline(always)]
 fn convert_position_query(query: PositionQueryJs) -> Result<PositionQuery, Error> {
-    match query.type_.as_str() {
+    let position_query = match query.type_.as_str() {
         "exact" => PositionQuery::exact_from_fen(&query.fen),
         "partial" => PositionQuery::partial_from_fen(&query.fen),
         _ => Err(Error::FenError(format!(
             "Invalid position query type: {}",
             query.type_
         ))),
+    }?;
+    match query.constraints.as_deref() {
+        Some(constraints) if !constraints.trim().is_empty() => {
+            position_query.with_constraints(constraints)
+        }
+        _ => Ok(position_query),
+    }
+}
+
+/// Mirror a FEN vertically and swap colors, so that white's structure becomes black's.
+/// Side to move, castling rights and the en passant square are flipped accordingly.
+fn mirror_fen(fen: &str) -> Result<String, Error> {
+    let mut fields = fen.split_whitespace();
+    let board = fields
+        .next()
+        .ok_or_else(|| Error::FenError("Empty FEN".to_string()))?;
+    let board: Vec<String> = board
+        .split('/')
+        .rev()
+        .map(|rank| rank.chars().map(swap_case).collect())
+        .collect();
+
+    let turn = match fields.next() {
+        Some("b") => "w",
+        _ => "b",
+    };
+    let castling: String = match fields.next() {
+        Some("-") | None => "-".to_string(),
+        Some(castling) => {
+            let mut swapped: Vec<char> = castling.chars().map(swap_case).collect();
+            // Keep the conventional order: white rights first.
+            swapped.sort_by_key(|c| c.is_ascii_lowercase());
+            swapped.into_iter().collect()
+        }
+    };
+    let ep = match fields.next() {
+        Some(ep) if ep.len() == 2 => ep
+            .chars()
+            .map(|c| match c {
+                '3' => '6',
+                '6' => '3',
+                c => c,
+            })
+            .collect(),
+        _ => "-".to_string(),
+    };
+    let halfmoves = fields.next().unwrap_or("0");
+    let fullmoves = fields.next().unwrap_or("1");
+
+    Ok(format!(
+        "{} {} {} {} {} {}",
+        board.join("/"),
+        turn,
+        castling,
+        ep,
+        halfmoves,
+        fullmoves
+    ))
+}
+
+#[inline]
+fn swap_case(c: char) -> char {
+    if c.is_ascii_uppercase() {
+        c.to_ascii_lowercase()
+    } else {
+        c.to_ascii_uppercase()
+    }
+}
+
+/// Translate a SAN move played in the mirrored position back to the original orientation.
+fn mirror_san(san: &str) -> String {
+    san.chars()
+        .map(|c| match c.to_digit(10) {
+            Some(rank @ 1..=8) => char::from_digit(9 - rank, 10).unwrap(),
+            _ => c,
+        })
+        .collect()
+}
+
+/// A game result seen from the other color.
+#[inline]
+fn flip_result(result: Option<&str>) -> Option<&str> {
+    match result {
+        Some("1-0") => Some("0-1"),
+        Some("0-1") => Some("1-0"),
+        other => other,
+    }
+}
+
+/// Position query used by the scans, optionally paired with its color-flipped counterpart.
+pub(super) struct SearchTarget {
+    query: PositionQuery,
+    mirrored: Option<PositionQuery>,
+}
+
+impl SearchTarget {
+    pub(super) fn from_js(query: &PositionQueryJs) -> Result<Self, Error> {
+        let primary = convert_position_query(query.clone())?;
+        let mirrored = if query.include_mirrored.unwrap_or(false) {
+            let mut mirrored = convert_position_query(PositionQueryJs {
+                fen: mirror_fen(&query.fen)?,
+                type_: query.type_.clone(),
+                include_mirrored: None,
+                constraints: None,
+            })?;
+            if let (PositionQuery::Partial(data), PositionQuery::Partial(original)) =
+                (&mut mirrored, &primary)
+            {
+                data.constraints = original.constraints.iter().map(|c| c.mirrored()).collect();
+            }
+            Some(mirrored)
+        } else {
+            None
+        };
+        Ok(Self {
+            query: primary,
+            mirrored,
+        })
+    }
+
+    /// Board hashes of the target and its mirror, for the position filters. Empty unless every
+    /// query is exact.
+    pub(super) fn exact_board_hashes(&self) -> Vec<u64> {
+        std::iter::once(&self.query)
+            .chain(self.mirrored.as_ref())
+            .map(PositionQuery::exact_board_hash)
+            .collect::<Option<Vec<u64>>>()
+            .unwrap_or_default()
+    }
+
+    /// Structure hashes of the target and its mirror, for the checkpoint index. Empty unless every
+    /// query is exact.
+    fn exact_structure_hashes(&self) -> Vec<i64> {
+        std::iter::once(&self.query)
+            .chain(self.mirrored.as_ref())
+            .map(PositionQuery::exact_structure_hash)
+            .collect::<Option<Vec<i64>>>()
+            .unwrap_or_default()
+    }
+
+    #[inline(always)]
+    pub(super) fn can_reach(&self, material: &MaterialCount, pawn_home: u16) -> bool {
+        self.query.can_reach(material, pawn_home)
+            || self
+                .mirrored
+                .as_ref()
+                .is_some_and(|q| q.can_reach(material, pawn_home))
+    }
+
+    /// Next move after the matched position, with the game result as seen by the query side.
+    /// Mirrored matches have their move and result flipped back to the query's orientation.
+    pub(super) fn match_game<'a>(
+        &self,
+        game: &[u8],
+        fen: &Option<String>,
+        result: Option<&'a str>,
+    ) -> Option<(String, Option<&'a str>)> {
+        if let Ok(Some(m)) = get_move_after_match(game, fen, &self.query) {
+            return Some((m, result));
+        }
+        let mirrored = self.mirrored.as_ref()?;
+        match get_move_after_match(game, fen, mirrored) {
+            Ok(Some(m)) => Some((mirror_san(&m), flip_result(result))),
+            _ => None,
+        }
     }
 }
 
@@ -404,11 +642,15 @@ impl PositionQuery {
                 true
             }
             PositionQuery::Partial(ref data) => {
+                let tested = position.board();
+                if !data.constraints.iter().all(|c| c.matches(tested)) {
+                    return false;
+                }
+
                 let m = &data.masks;
                 if m.non_empty == 0 {
                     return true;
                 }
-                let tested = position.board();
 
                 if (m.non_empty & PartialMasks::KINGS) != 0
                     && !is_contained(tested.kings(), m.kings)
@@ -466,6 +708,22 @@ impl PositionQuery {
         }
     }
 
+    /// Hash of the searched board, for exact queries.
+    fn exact_board_hash(&self) -> Option<u64> {
+        match self {
+            PositionQuery::Exact(ref data) => Some(board_hash(data.position.board())),
+            PositionQuery::Partial(_) => None,
+        }
+    }
+
+    /// Structure hash of the searched board, for exact queries.
+    fn exact_structure_hash(&self) -> Option<i64> {
+        match self {
+            PositionQuery::Exact(ref data) => Some(structure_hash(data.position.board())),
+            PositionQuery::Partial(_) => None,
+        }
+    }
+
     fn can_reach(&self, material: &MaterialCount, pawn_home: u16) -> bool {
         match self {
             PositionQuery::Exact(ref data) => {
@@ -504,89 +762,6 @@ pub struct PositionStats {
     pub black: i32,
 }
 
-/// Parses chess moves from binary format one at a time
-struct MoveStream<'a> {
-    bytes: &'a [u8],
-    position: Chess,
-    index: usize,
-}
-
-impl<'a> MoveStream<'a> {
-    const START_VARIATION: u8 = 254;
-    const END_VARIATION: u8 = 253;
-    const COMMENT: u8 = 252;
-    const NAG: u8 = 251;
-
-    fn new(bytes: &'a [u8], start_position: Chess) -> Self {
-        Self {
-            bytes,
-            position: start_position,
-            index: 0,
-        }
-    }
-
-    #[inline]
-    fn next_move(&mut self) -> Option<(Chess, String)> {
-        let bytes = self.bytes;
-        let len = bytes.len();
-
-        while self.index < len {
-            let byte = bytes[self.index];
-
-            match byte {
-                Self::COMMENT => {
-                    if self.index + 8 >= len {
-                        break;
-                    }
-                    let length_bytes = &bytes[self.index + 1..self.index + 9];
-                    if let Ok(length_array) = <[u8; 8]>::try_from(length_bytes) {
-                        let length = u64::from_be_bytes(length_array) as usize;
-                        self.index += 9 + length;
-                    } else {
-                        break;
-                    }
-                }
-                Self::NAG => {
-                    self.index += 2;
-                }
-                Self::START_VARIATION => {
-                    let mut depth = 1;
-                    self.index += 1;
-                    while self.index < len && depth > 0 {
-                        match bytes[self.index] {
-                            Self::START_VARIATION => depth += 1,
-                            Self::END_VARIATION => depth -= 1,
-                            _ => {}
-                        }
-                        self.index += 1;
-                    }
-                }
-                Self::END_VARIATION => {
-                    break;
-                }
-                move_byte => {
-                    let legal_moves = self.position.legal_moves();
-                    let idx = move_byte as usize;
-                    if idx < legal_moves.len() {
-                        if let Some(chess_move) = legal_moves.get(idx) {
-                            let san = SanPlus::from_move_and_play_unchecked(
-                                &mut self.position,
-                                chess_move,
-                            );
-                            let move_string = san.to_string();
-                            self.index += 1;
-                            return Some((self.position.clone(), move_string));
-                        }
-                    }
-                    break;
-                }
-            }
-        }
-
-        None
-    }
-}
-
 /// Find the next move played after a position matches the query
 /// This is the en-croissant version - simpler and more efficient
 #[inline]
@@ -650,14 +825,31 @@ pub struct ProgressPayload {
     pub finished: bool,
 }
 
+/// Emits both the legacy `search_progress` payload and the unified `TaskProgress` event.
+fn emit_search_progress(app: &tauri::AppHandle, tab_id: &str, progress: f64, finished: bool) {
+    let _ = app.emit(
+        "search_progress",
+        ProgressPayload {
+            progress,
+            id: tab_id.to_string(),
+            finished,
+        },
+    );
+    TaskProgress {
+        finished,
+        ..TaskProgress::new(TaskKind::Search, tab_id, progress)
+    }
+    .send(app);
+}
+
 /// ============================================================================
 /// Build checkpoints command
 /// ============================================================================
 
-/// Builds / extends the checkpoint index.
-/// This is optional maintenance for large DBs.
+/// Builds / extends the checkpoint index and the per-game position filters.
+/// This is optional maintenance for large DBs: once built, exact searches only replay the games
+/// the index points them to.
 /// It does NOT break existing flows.
-#[allow(dead_code)]
 #[tauri::command]
 #[specta::specta]
 pub async fn build_position_checkpoints(
@@ -670,12 +862,18 @@ pub async fn build_position_checkpoints(
         .to_str()
         .ok_or_else(|| Error::FenError("Invalid database path".to_string()))?;
 
+    let _write = state.db_writes.lock(&file, "build_position_checkpoints").await;
+    let job = state.jobs.start(
+        &app,
+        TaskKind::Search,
+        tab_id.clone(),
+        "Building position checkpoints",
+    );
     let db = &mut get_db_or_create(&state, file_str, ConnectionOptions::default())?;
 
     if ENABLE_AUX_INDEXES {
         ensure_aux_indexes(db);
     }
-    ensure_checkpoint_table(db);
 
     // PRAGMAs for bulk-ish insert
     let _ = diesel::sql_query(
@@ -700,7 +898,7 @@ pub async fn build_position_checkpoints(
     let mut last_id: i32 = 0;
 
     // Insert batching respecting SQLite variable limit
-    // 4 vars per row → 200 rows = 800 vars safe
+    // 5 vars per row → 200 rows = 1000 vars safe
     const INSERT_ROWS: usize = 200;
 
     let mut inserted_total: i64 = 0;
@@ -709,6 +907,11 @@ pub async fn build_position_checkpoints(
     let mut next_progress_tick: usize = progress_step;
 
     for _ in 0..batches_to_process {
+        // Games not indexed yet are still searched, so a partial index is usable.
+        if job.is_cancelled() {
+            log::info!("Checkpoint build for {} cancelled", file.display());
+            break;
+        }
         let batch: Vec<(i32, Vec<u8>, Option<String>)> = games::table
             .filter(games::id.gt(last_id))
             .order(games::id.asc())
@@ -725,7 +928,7 @@ pub async fn build_position_checkpoints(
         }
 
         // Collect checkpoints for this batch
-        let mut rows: Vec<(i32, i32, i64, i32)> = Vec::with_capacity(batch.len() * 4);
+        let mut rows: Vec<(i32, Checkpoint, usize)> = Vec::with_capacity(batch.len() * 16);
 
         for (game_id, moves, fen) in batch.iter() {
             // Start position
@@ -736,20 +939,20 @@ pub async fn build_position_checkpoints(
                 Chess::default()
             };
 
-            // ply 0 checkpoint
-            let (h0, t0) = position_hash_and_turn(&start_position);
-            rows.push((*game_id, 0, h0, t0));
-
-            let mut stream = MoveStream::new(moves, start_position);
-            let mut ply: i32 = 0;
+            rows.extend(
+                checkpoint_segments(moves, start_position)
+                    .into_iter()
+                    .map(|checkpoint| (*game_id, checkpoint, moves.len())),
+            );
+        }
 
-            while let Some((pos, _san)) = stream.next_move() {
-                ply += 1;
-                if (ply as usize) % CHECKPOINT_STRIDE == 0 {
-                    let (hh, tt) = position_hash_and_turn(&pos);
-                    rows.push((*game_id, ply, hh, tt));
-                }
-            }
+        // Replace the checkpoints of games rebuilt after an edit
+        if let (Some(first), Some(last)) = (batch.first(), batch.last()) {
+            diesel::sql_query(format!(
+                "DELETE FROM game_position_checkpoints WHERE game_id BETWEEN {} AND {}",
+                first.0, last.0
+            ))
+            .execute(db)?;
         }
 
         // Bulk insert in safe chunks
@@ -759,32 +962,50 @@ pub async fn build_position_checkpoints(
             }
 
             let mut sql = String::from(
-                "INSERT OR IGNORE INTO game_position_checkpoints \
-                 (game_id, ply, board_hash, turn) VALUES ",
+                "INSERT OR REPLACE INTO game_position_checkpoints \
+                 (game_id, ply, structure_hash, moves_len, fen) VALUES ",
             );
-            for (i, (gid, ply, bh, turn)) in chunk.iter().enumerate() {
+            for (i, (gid, checkpoint, moves_len)) in chunk.iter().enumerate() {
                 if i > 0 {
                     sql.push(',');
                 }
-                sql.push_str(&format!("({}, {}, {}, {})", gid, ply, bh, turn));
+                // FENs hold no quotes.
+                let fen = checkpoint
+                    .fen
+                    .as_ref()
+                    .map_or_else(|| "NULL".to_string(), |fen| format!("'{}'", fen));
+                sql.push_str(&format!(
+                    "({}, {}, {}, {}, {})",
+                    gid, checkpoint.ply, checkpoint.structure_hash, moves_len, fen
+                ));
             }
 
             let r = diesel::sql_query(sql).execute(db)?;
             inserted_total += r as i64;
         }
 
+        // Bloom filters of visited boards for the exact search prefilter
+        let filters: Vec<(i32, PositionFilter)> = batch
+            .par_iter()
+            .filter_map(|(game_id, moves, fen)| {
+                let start = match fen {
+                    Some(fen) => Chess::from_setup(
+                        Fen::from_ascii(fen.as_bytes()).ok()?.into_setup(),
+                        shakmaty::CastlingMode::Chess960,
+                    )
+                    .ok()?,
+                    None => Chess::default(),
+                };
+                Some((*game_id, PositionFilter::build(moves, start)))
+            })
+            .collect();
+        store_filters(db, &filters)?;
+
         // Progress
         processed_total = processed_total.saturating_add(batch.len());
         if processed_total >= next_progress_tick {
             let progress = (processed_total as f64 / total_games as f64 * 100.0).min(99.0);
-            let _ = app.emit(
-                "search_progress",
-                ProgressPayload {
-                    progress,
-                    id: tab_id.clone(),
-                    finished: false,
-                },
-            );
+            emit_search_progress(&app, &tab_id, progress, false);
             next_progress_tick = next_progress_tick.saturating_add(progress_step);
         }
 
@@ -793,18 +1014,159 @@ pub async fn build_position_checkpoints(
         }
     }
 
-    let _ = app.emit(
-        "search_progress",
-        ProgressPayload {
-            progress: 100.0,
-            id: tab_id.clone(),
-            finished: true,
-        },
-    );
+    emit_search_progress(&app, &tab_id, 100.0, true);
 
     Ok(inserted_total)
 }
 
+/// SQL condition keeping the games an exact search for the structures `hashes` has to replay: those
+/// with a matching checkpoint, and those the index does not cover (not built yet, or edited since).
+/// `None` when the index cannot narrow the search, i.e. the query is not exact or the index is empty.
+fn checkpoint_candidates(db: &mut SqliteConnection, hashes: &[i64]) -> Option<String> {
+    if hashes.is_empty() {
+        return None;
+    }
+    let indexed = diesel::select(diesel::dsl::sql::<diesel::sql_types::Bool>(
+        "EXISTS (SELECT 1 FROM game_position_checkpoints)",
+    ))
+    .get_result::<bool>(db)
+    .unwrap_or(false);
+    if !indexed {
+        return None;
+    }
+    let hashes = hashes
+        .iter()
+        .map(|hash| hash.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(format!(
+        "(Games.ID IN (SELECT game_id FROM game_position_checkpoints \
+          WHERE structure_hash IN ({hashes})) \
+         OR NOT EXISTS (SELECT 1 FROM game_position_checkpoints c \
+          WHERE c.game_id = Games.ID AND c.ply = 0 AND c.moves_len = length(Games.Moves)))"
+    ))
+}
+
+/// Loads the games a position search scans; with a `scan`, only that player's games with its
+/// color, read through the player index, and with `candidates`, only the games the checkpoint
+/// index leaves.
+fn load_search_games(
+    db: &mut SqliteConnection,
+    scan: Option<PlayerScan>,
+    candidates: Option<&str>,
+) -> QueryResult<Vec<GameData>> {
+    let mut query = games::table
+        .select((
+            games::id,
+            games::white_id,
+            games::black_id,
+            games::date,
+            games::result,
+            games::moves,
+            games::fen,
+            games::pawn_home,
+            games::white_material,
+            games::black_material,
+        ))
+        .into_boxed();
+    if let Some(scan) = scan {
+        query = match scan.color {
+            Color::White => query.filter(games::white_id.eq(scan.player_id)),
+            Color::Black => query.filter(games::black_id.eq(scan.player_id)),
+        };
+    }
+    if let Some(candidates) = candidates {
+        query = query.filter(diesel::dsl::sql::<diesel::sql_types::Bool>(candidates));
+    }
+    query.load(db)
+}
+
+/// Drop the checkpoints of game `id`, whose moves changed or which is removed.
+pub(super) fn forget_checkpoints(db: &mut SqliteConnection, id: i32) -> Result<(), Error> {
+    diesel::sql_query("DELETE FROM game_position_checkpoints WHERE game_id = ?")
+        .bind::<diesel::sql_types::Integer, _>(id)
+        .execute(db)?;
+    Ok(())
+}
+
+/// Where the replay of a game resumes: the stored position at `ply`, valid while the game's move
+/// blob is still `moves_len` long.
+struct ResumePoint {
+    ply: usize,
+    moves_len: usize,
+    fen: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct ResumeRow {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    game_id: i32,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    ply: i32,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    moves_len: i32,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    fen: String,
+}
+
+/// The last checkpoint with a stored position before the first segment of each game whose
+/// structure is one of `hashes`. Games without one are replayed from their first move.
+fn resume_points(db: &mut SqliteConnection, hashes: &[i64]) -> HashMap<i32, ResumePoint> {
+    let mut points = HashMap::new();
+    if hashes.is_empty() {
+        return points;
+    }
+    let hashes = hashes
+        .iter()
+        .map(|hash| hash.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let rows: Vec<ResumeRow> = match diesel::sql_query(format!(
+        "SELECT c.game_id, c.ply, c.moves_len, c.fen FROM game_position_checkpoints c \
+         JOIN (SELECT game_id, MIN(ply) AS ply FROM game_position_checkpoints \
+          WHERE structure_hash IN ({hashes}) GROUP BY game_id) m \
+          ON c.game_id = m.game_id AND c.ply <= m.ply \
+         WHERE c.fen IS NOT NULL"
+    ))
+    .load(db)
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            log::warn!("Failed to load checkpoint positions: {}", e);
+            return points;
+        }
+    };
+    for row in rows {
+        let point = points.entry(row.game_id).or_insert(ResumePoint {
+            ply: 0,
+            moves_len: row.moves_len as usize,
+            fen: None,
+        });
+        if point.fen.is_none() || row.ply as usize > point.ply {
+            point.ply = row.ply as usize;
+            point.fen = Some(row.fen);
+        }
+    }
+    points
+}
+
+/// The moves to replay of game `id` and the position they start from: from its resume point when
+/// it has one that still matches its moves, from the start of the game otherwise.
+#[inline]
+fn resume_from<'a>(
+    points: &'a HashMap<i32, ResumePoint>,
+    id: i32,
+    moves: &'a [u8],
+    fen: &'a Option<String>,
+) -> (&'a [u8], &'a Option<String>) {
+    match points.get(&id) {
+        Some(point) if point.moves_len == moves.len() && point.ply <= moves.len() => {
+            (&moves[point.ply..], &point.fen)
+        }
+        _ => (moves, fen),
+    }
+}
+
 /// ============================================================================
 /// LOCAL internal search (original behavior preserved)
 /// ============================================================================
@@ -815,23 +1177,36 @@ pub async fn build_position_checkpoints(
 /// Instead, we keep a Top-K of highest average ELO while scanning.
 /// To avoid breaking `state.db_cache` type, the AverageElo branch loads
 /// a local vector from DB including white_elo/black_elo.
+/// Exact searches on a database with a checkpoint index only load the games
+/// whose checkpoints match the target structure; their replay resumes from the
+/// nearest stored checkpoint before the target's segment and stops as soon as
+/// the game leaves that segment, which `is_reachable_by` detects.
 ///
 /// Returns: (openings stats, matching game ids)
 fn search_position_local_internal(
     db: &mut SqliteConnection,
-    position_query: &PositionQuery,
+    position_query: &SearchTarget,
     query: &GameQueryJs,
+    filters: Option<&PositionFilters>,
     app: &tauri::AppHandle,
     tab_id: &str,
     state: &AppState,
 ) -> Result<(Vec<PositionStats>, Vec<i32>), Error> {
     const MAX_SAMPLE_GAMES: usize = 1000;
+    let target_hashes = position_query.exact_board_hashes();
+    let structure_hashes = position_query.exact_structure_hashes();
+    let candidates = checkpoint_candidates(db, &structure_hashes);
+    let resume = match candidates {
+        Some(_) => resume_points(db, &structure_hashes),
+        None => HashMap::new(),
+    };
 
     let sort_avg = query
         .options
         .as_ref()
         .map(|o| matches!(o.sort, GameSort::AverageElo))
         .unwrap_or(false);
+    let player_scan = column_stats::position_player_scan(db, query);
 
     #[inline]
     fn avg_elo(white: Option<i32>, black: Option<i32>) -> i32 {
@@ -887,20 +1262,7 @@ fn search_position_local_internal(
     // ------------------------------------------------------------------------
     if sort_avg {
         // Load a local vector including elos
-        let games_with_elo: Vec<(
-            i32,            // id
-            i32,            // white_id
-            i32,            // black_id
-            Option<String>, // date
-            Option<String>, // result
-            Vec<u8>,        // moves
-            Option<String>, // fen
-            i32,            // pawn_home
-            i32,            // white_material
-            i32,            // black_material
-            Option<i32>,    // white_elo
-            Option<i32>,    // black_elo
-        )> = games::table
+        let mut games_query = games::table
             .select((
                 games::id,
                 games::white_id,
@@ -915,7 +1277,31 @@ fn search_position_local_internal(
                 games::white_elo,
                 games::black_elo,
             ))
-            .load(db)?;
+            .into_boxed();
+        if let Some(scan) = player_scan {
+            games_query = match scan.color {
+                Color::White => games_query.filter(games::white_id.eq(scan.player_id)),
+                Color::Black => games_query.filter(games::black_id.eq(scan.player_id)),
+            };
+        }
+        if let Some(candidates) = &candidates {
+            games_query =
+                games_query.filter(diesel::dsl::sql::<diesel::sql_types::Bool>(candidates));
+        }
+        let games_with_elo: Vec<(
+            i32,            // id
+            i32,            // white_id
+            i32,            // black_id
+            Option<String>, // date
+            Option<String>, // result
+            Vec<u8>,        // moves
+            Option<String>, // fen
+            i32,            // pawn_home
+            i32,            // white_material
+            i32,            // black_material
+            Option<i32>,    // white_elo
+            Option<i32>,    // black_elo
+        )> = games_query.load(db)?;
 
         let games_len = games_with_elo.len();
         if games_len == 0 {
@@ -986,25 +1372,23 @@ fn search_position_local_internal(
                 if !position_query.can_reach(&end_material, *end_pawn_home as u16) {
                     return;
                 }
+                if rules_out(filters, &target_hashes, *id, game) {
+                    return;
+                }
 
                 let index = processed.fetch_add(1, Ordering::Relaxed);
                 let current_tick = next_progress_tick_clone.load(Ordering::Relaxed);
                 if index >= current_tick {
-                    let _ = app.emit(
-                        "search_progress",
-                        ProgressPayload {
-                            progress: ((index + 1) as f64 / games_len as f64 * 100.0).min(99.0),
-                            id: tab_id.to_string(),
-                            finished: false,
-                        },
-                    );
+                    let progress = ((index + 1) as f64 / games_len as f64 * 100.0).min(99.0);
+                    emit_search_progress(app, tab_id, progress, false);
                     next_progress_tick_clone.store(
                         current_tick.saturating_add(progress_step),
                         Ordering::Relaxed,
                     );
                 }
 
-                if let Ok(Some(m)) = get_move_after_match(game, fen, position_query) {
+                let (game, fen) = resume_from(&resume, *id, game, fen);
+                if let Some((m, result)) = position_query.match_game(game, fen, result.as_deref()) {
                     // Keep Top-K by average elo
                     let a = avg_elo(*white_elo, *black_elo);
                     if let Ok(mut sample) = sample_games.try_lock() {
@@ -1016,7 +1400,7 @@ fn search_position_local_internal(
                     match entry {
                         Entry::Occupied(mut e) => {
                             let opening = e.get_mut();
-                            match result.as_deref() {
+                            match result {
                                 Some("1-0") => opening.white += 1,
                                 Some("0-1") => opening.black += 1,
                                 Some("1/2-1/2") => opening.draw += 1,
@@ -1025,7 +1409,7 @@ fn search_position_local_internal(
                         }
                         Entry::Vacant(e) => {
                             let move_str = e.key().clone();
-                            let (white, black, draw) = match result.as_deref() {
+                            let (white, black, draw) = match result {
                                 Some("1-0") => (1, 0, 0),
                                 Some("0-1") => (0, 1, 0),
                                 Some("1/2-1/2") => (0, 0, 1),
@@ -1056,24 +1440,20 @@ fn search_position_local_internal(
     // ------------------------------------------------------------------------
     // Branch B: Original LOCAL path (uses state.db_cache)
     // ------------------------------------------------------------------------
-    let mut games = state.db_cache.lock().unwrap();
-
-    if games.is_empty() {
-        *games = games::table
-            .select((
-                games::id,
-                games::white_id,
-                games::black_id,
-                games::date,
-                games::result,
-                games::moves,
-                games::fen,
-                games::pawn_home,
-                games::white_material,
-                games::black_material,
-            ))
-            .load(db)?;
+    let mut cache = state.db_cache.lock().unwrap();
+
+    // A player with few games is read through the index instead of filling the cache with every
+    // game, and so are the candidates of the checkpoint index; the cache only ever holds the whole
+    // database.
+    let scanned = match (player_scan, &candidates) {
+        (scan, Some(candidates)) => Some(load_search_games(db, scan, Some(candidates))?),
+        (Some(scan), None) if cache.is_empty() => Some(load_search_games(db, Some(scan), None)?),
+        _ => None,
+    };
+    if scanned.is_none() && cache.is_empty() {
+        *cache = load_search_games(db, None, None)?;
     }
+    let games: &Vec<GameData> = scanned.as_ref().unwrap_or(&cache);
 
     let games_len = games.len();
     if games_len == 0 {
@@ -1142,25 +1522,23 @@ fn search_position_local_internal(
             if !position_query.can_reach(&end_material, *end_pawn_home as u16) {
                 return;
             }
+            if rules_out(filters, &target_hashes, *id, game) {
+                return;
+            }
 
             let index = processed.fetch_add(1, Ordering::Relaxed);
             let current_tick = next_progress_tick_clone.load(Ordering::Relaxed);
             if index >= current_tick {
-                let _ = app.emit(
-                    "search_progress",
-                    ProgressPayload {
-                        progress: ((index + 1) as f64 / games_len as f64 * 100.0).min(99.0),
-                        id: tab_id.to_string(),
-                        finished: false,
-                    },
-                );
+                let progress = ((index + 1) as f64 / games_len as f64 * 100.0).min(99.0);
+                emit_search_progress(app, tab_id, progress, false);
                 next_progress_tick_clone.store(
                     current_tick.saturating_add(progress_step),
                     Ordering::Relaxed,
                 );
             }
 
-            if let Ok(Some(m)) = get_move_after_match(game, fen, position_query) {
+            let (game, fen) = resume_from(&resume, *id, game, fen);
+            if let Some((m, result)) = position_query.match_game(game, fen, result.as_deref()) {
                 {
                     let mut sample = sample_games.lock().unwrap();
                     if sample.len() < MAX_SAMPLE_GAMES {
@@ -1172,7 +1550,7 @@ fn search_position_local_internal(
                 match entry {
                     Entry::Occupied(mut e) => {
                         let opening = e.get_mut();
-                        match result.as_deref() {
+                        match result {
                             Some("1-0") => opening.white += 1,
                             Some("0-1") => opening.black += 1,
                             Some("1/2-1/2") => opening.draw += 1,
@@ -1181,7 +1559,7 @@ fn search_position_local_internal(
                     }
                     Entry::Vacant(e) => {
                         let move_str = e.key().clone();
-                        let (white, black, draw) = match result.as_deref() {
+                        let (white, black, draw) = match result {
                             Some("1-0") => (1, 0, 0),
                             Some("0-1") => (0, 1, 0),
                             Some("1/2-1/2") => (0, 0, 1),
@@ -1219,7 +1597,7 @@ fn search_position_local_internal(
 /// and does NOT rely on `games.pawn_home/white_material/black_material`.
 fn search_position_online_internal(
     db: &mut SqliteConnection,
-    position_query: &PositionQuery,
+    position_query: &SearchTarget,
     query: &GameQueryJs,
     app: &tauri::AppHandle,
     tab_id: &str,
@@ -1232,32 +1610,8 @@ fn search_position_online_internal(
     let sample_games: Mutex<Vec<i32>> = Mutex::new(Vec::with_capacity(MAX_SAMPLE_GAMES));
 
     // Load games directly from database (ONLINE path)
-    let games: Vec<(
-        i32,            // id
-        i32,            // white_id
-        i32,            // black_id
-        Option<String>, // date
-        Option<String>, // result
-        Vec<u8>,        // moves
-        Option<String>, // fen
-        i32,            // pawn_home (ignored)
-        i32,            // white_material (ignored)
-        i32,            // black_material (ignored)
-    )> = match games::table
-        .select((
-            games::id,
-            games::white_id,
-            games::black_id,
-            games::date,
-            games::result,
-            games::moves,
-            games::fen,
-            games::pawn_home,
-            games::white_material,
-            games::black_material,
-        ))
-        .load(db)
-    {
+    let player_scan = column_stats::position_player_scan(db, query);
+    let games = match load_search_games(db, player_scan, None) {
         Ok(g) => g,
         Err(_) => return (Vec::new(), Vec::new()),
     };
@@ -1339,21 +1693,15 @@ fn search_position_online_internal(
                 let index = processed.fetch_add(1, Ordering::Relaxed);
                 let current_tick = next_progress_tick_clone.load(Ordering::Relaxed);
                 if index >= current_tick {
-                    let _ = app.emit(
-                        "search_progress",
-                        ProgressPayload {
-                            progress: ((index + 1) as f64 / games_len as f64 * 100.0).min(99.0),
-                            id: tab_id.to_string(),
-                            finished: false,
-                        },
-                    );
+                    let progress = ((index + 1) as f64 / games_len as f64 * 100.0).min(99.0);
+                    emit_search_progress(app, tab_id, progress, false);
                     next_progress_tick_clone.store(
                         current_tick.saturating_add(progress_step),
                         Ordering::Relaxed,
                     );
                 }
 
-                if let Ok(Some(m)) = get_move_after_match(game, fen, position_query) {
+                if let Some((m, result)) = position_query.match_game(game, fen, result.as_deref()) {
                     if let Ok(mut sample) = sample_games.try_lock() {
                         if sample.len() < MAX_SAMPLE_GAMES {
                             sample.push(*id);
@@ -1364,7 +1712,7 @@ fn search_position_online_internal(
                     match entry {
                         Entry::Occupied(mut e) => {
                             let opening = e.get_mut();
-                            match result.as_deref() {
+                            match result {
                                 Some("1-0") => opening.white += 1,
                                 Some("0-1") => opening.black += 1,
                                 Some("1/2-1/2") => opening.draw += 1,
@@ -1373,7 +1721,7 @@ fn search_position_online_internal(
                         }
                         Entry::Vacant(e) => {
                             let move_str = e.key().clone();
-                            let (white, black, draw) = match result.as_deref() {
+                            let (white, black, draw) = match result {
                                 Some("1-0") => (1, 0, 0),
                                 Some("0-1") => (0, 1, 0),
                                 Some("1/2-1/2") => (0, 0, 1),
@@ -1479,21 +1827,16 @@ fn search_position_online_internal(
             let index = processed.fetch_add(1, Ordering::Relaxed);
             let current_tick = next_progress_tick_clone.load(Ordering::Relaxed);
             if index >= current_tick {
-                let _ = app.emit(
-                    "search_progress",
-                    ProgressPayload {
-                        progress: ((index + 1) as f64 / games_len as f64 * 100.0).min(99.0),
-                        id: tab_id.to_string(),
-                        finished: false,
-                    },
-                );
+                let progress = ((index + 1) as f64 / games_len as f64 * 100.0).min(99.0);
+                emit_search_progress(app, tab_id, progress, false);
                 next_progress_tick_clone.store(
                     current_tick.saturating_add(progress_step),
                     Ordering::Relaxed,
                 );
             }
 
-            if let Ok(Some(m)) = get_move_after_match(game, fen, position_query) {
+            let (game, fen) = resume_from(&resume, *id, game, fen);
+            if let Some((m, result)) = position_query.match_game(game, fen, result.as_deref()) {
                 {
                     let mut sample = sample_games.lock().unwrap();
                     if sample.len() < MAX_SAMPLE_GAMES {
@@ -1505,7 +1848,7 @@ fn search_position_online_internal(
                 match entry {
                     Entry::Occupied(mut e) => {
                         let opening = e.get_mut();
-                        match result.as_deref() {
+                        match result {
                             Some("1-0") => opening.white += 1,
                             Some("0-1") => opening.black += 1,
                             Some("1/2-1/2") => opening.draw += 1,
@@ -1514,7 +1857,7 @@ fn search_position_online_internal(
                     }
                     Entry::Vacant(e) => {
                         let move_str = e.key().clone();
-                        let (white, black, draw) = match result.as_deref() {
+                        let (white, black, draw) = match result {
                             Some("1-0") => (1, 0, 0),
                             Some("0-1") => (0, 1, 0),
                             Some("1/2-1/2") => (0, 0, 1),
@@ -1551,16 +1894,24 @@ pub async fn search_position(
     tab_id: String,
     state: tauri::State<'_, AppState>,
 ) -> Result<(Vec<PositionStats>, Vec<NormalizedGame>), Error> {
-    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let mut span = CommandSpan::start("search_position");
+    let db = &mut span.time_connection(|| {
+        get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())
+    })?;
 
     // Get FEN from position query
-    let fen = match &query.position {
-        Some(pos_query) => pos_query.fen.clone(),
+    let (fen, use_cache) = match &query.position {
+        Some(pos_query) => (
+            pos_query.fen.clone(),
+            // The cache is keyed by FEN, so it only holds plain searches.
+            !pos_query.include_mirrored.unwrap_or(false)
+                && pos_query.constraints.as_deref().is_none_or(|c| c.trim().is_empty()),
+        ),
         None => return Err(Error::NoMatchFound),
     };
 
     // Check if position is cached in database
-    if is_position_cached(&app, &fen, &file)? {
+    if use_cache && is_position_cached(&app, &fen, &file)? {
         // Load cached data
         if let Some((cached_stats, cached_game_ids)) = get_cached_position(&app, &fen, &file)? {
             // Apply game_details_limit
@@ -1647,14 +1998,7 @@ pub async fn search_position(
                 }
             }
 
-            let _ = app.emit(
-                "search_progress",
-                ProgressPayload {
-                    progress: 100.0,
-                    id: tab_id.clone(),
-                    finished: true,
-                },
-            );
+            emit_search_progress(&app, &tab_id, 100.0, true);
 
             return Ok((cached_stats, normalized_games));
         }
@@ -1662,7 +2006,7 @@ pub async fn search_position(
 
     // Convert position query for search
     let position_query = match &query.position {
-        Some(pos_query) => convert_position_query(pos_query.clone())?,
+        Some(pos_query) => SearchTarget::from_js(pos_query)?,
         None => return Err(Error::NoMatchFound),
     };
 
@@ -1672,18 +2016,16 @@ pub async fn search_position(
     let online = is_online_database(&file);
 
     // Optional schema/index safety for large/foreign DBs
-    // (kept behind flags and very cheap if already present)
+    // (kept behind flags and very cheap if already present; skipped while another operation writes)
     if ENABLE_AUX_INDEXES {
-        ensure_aux_indexes(db);
-    }
-    if ENABLE_CHECKPOINT_TABLE_SCHEMA {
-        ensure_checkpoint_table(db);
+        if let Some(_write) = state.db_writes.try_lock(&file, "search_position") {
+            ensure_aux_indexes(db);
+        }
     }
 
     // Phase 1: scan and collect openings + sample IDs
     let (openings, ids): (Vec<PositionStats>, Vec<i32>) = if online {
-        let total_count: i64 = games::table.count().get_result(db).unwrap_or(0);
-        let total_games = total_count.max(0) as usize;
+        let total_games = column_stats::total_games(db).max(0) as usize;
 
         search_position_online_internal(
             db,
@@ -1695,7 +2037,16 @@ pub async fn search_position(
             total_games,
         )
     } else {
-        search_position_local_internal(db, &position_query, &query, &app, &tab_id, state.inner())?
+        let filters = filters_for(&position_query.exact_board_hashes(), &state, db, &file);
+        search_position_local_internal(
+            db,
+            &position_query,
+            &query,
+            filters.as_deref(),
+            &app,
+            &tab_id,
+            state.inner(),
+        )?
     };
 
     if state.new_request.available_permits() == 0 {
@@ -1791,44 +2142,211 @@ pub async fn search_position(
     // Save results to persistent cache (save all game IDs, not just the loaded ones)
     // This allows us to load different subsets later based on game_details_limit
     // Save to cache after we've extracted ids_to_load
-    if let Err(e) = save_position_cache(&app, &fen, &file, &openings, &all_game_ids) {
-        // Log error but don't fail the request
-        log::warn!("Failed to save position cache: {}", e);
+    if use_cache {
+        if let Err(e) = save_position_cache(&app, &fen, &file, &openings, &all_game_ids) {
+            // Log error but don't fail the request
+            log::warn!("Failed to save position cache: {}", e);
+        }
     }
 
-    let _ = app.emit(
-        "search_progress",
-        ProgressPayload {
-            progress: 100.0,
-            id: tab_id.clone(),
-            finished: true,
-        },
-    );
+    emit_search_progress(&app, &tab_id, 100.0, true);
 
     drop(permit);
     Ok((openings, normalized_games))
 }
 
-/// Check if a position exists in the database (without full search)
+/// Positions warmed per `prefetch_line_stats` call.
+const PREFETCH_LIMIT: usize = 8;
+
+/// Task id reported by background prefetch searches.
+const PREFETCH_TASK_ID: &str = "prefetch";
+
+/// Warm the position cache for the upcoming positions of the line being navigated, so the explorer
+/// can answer from the cache when the user steps forward. Returns immediately; the searches run in
+/// the background, one position at a time, and are abandoned as soon as a newer prefetch request
+/// arrives (e.g. the user jumped to another line). Nothing is prefetched in safe mode.
+#[tauri::command]
+#[specta::specta]
+pub async fn prefetch_line_stats(
+    file: PathBuf,
+    fens: Vec<String>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), Error> {
+    if crate::app::safe_mode::is_safe_mode() {
+        return Ok(());
+    }
+    let generation = state.prefetch_generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        for fen in fens.iter().take(PREFETCH_LIMIT) {
+            if state.prefetch_generation.load(Ordering::SeqCst) != generation {
+                log::debug!("Prefetch for {} superseded", file.display());
+                break;
+            }
+            if let Err(e) = prefetch_position(&app, &state, &file, fen).await {
+                log::debug!("Failed to prefetch {}: {}", fen, e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Run a plain exact-position search for `fen` and store it in the position cache.
+async fn prefetch_position(
+    app: &tauri::AppHandle,
+    state: &tauri::State<'_, AppState>,
+    file: &PathBuf,
+    fen: &str,
+) -> Result<(), Error> {
+    if is_position_cached(app, fen, file)? {
+        return Ok(());
+    }
+
+    let position = PositionQueryJs {
+        fen: fen.to_string(),
+        type_: "exact".to_string(),
+        include_mirrored: None,
+        constraints: None,
+    };
+    let target = SearchTarget::from_js(&position)?;
+    let query = GameQueryJs::new().position(position);
+
+    let permit = state.new_request.acquire().await.unwrap();
+    let db = &mut get_db_or_create(state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let (openings, ids) = if is_online_database(file) {
+        let total_games = column_stats::total_games(db).max(0) as usize;
+        search_position_online_internal(db, &target, &query, app, PREFETCH_TASK_ID, state.inner(), total_games)
+    } else {
+        let filters = filters_for(&target.exact_board_hashes(), state, db, file);
+        search_position_local_internal(
+            db,
+            &target,
+            &query,
+            filters.as_deref(),
+            app,
+            PREFETCH_TASK_ID,
+            state.inner(),
+        )?
+    };
+    drop(permit);
+
+    save_position_cache(app, fen, file, &openings, &ids)
+}
+
+/// Moves listed in a `PositionPreview`.
+const PREVIEW_MOVES: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionPreview {
+    /// Most played moves, most popular first.
+    pub moves: Vec<PositionStats>,
+    /// Games reaching the position.
+    pub total: i32,
+    /// Highest-rated game reaching the position.
+    pub game: Option<NormalizedGame>,
+}
+
+/// Summary of a position for explorer hover tooltips. Only the position cache is read, so it
+/// answers quickly and returns `None` for positions that were never searched or prefetched instead
+/// of starting a search.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_position_preview(
+    file: PathBuf,
+    fen: String,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<PositionPreview>, Error> {
+    let Some((mut moves, game_ids)) = get_cached_position(&app, &fen, &file)? else {
+        return Ok(None);
+    };
+    let total = moves.iter().map(|m| m.white + m.draw + m.black).sum();
+    moves.sort_by_key(|m| std::cmp::Reverse(m.white + m.draw + m.black));
+    moves.truncate(PREVIEW_MOVES);
+
+    let game = if game_ids.is_empty() {
+        None
+    } else {
+        let db =
+            &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+        let (white_players, black_players) = diesel::alias!(players as white, players as black);
+        let best = games::table
+            .inner_join(white_players.on(games::white_id.eq(white_players.field(players::id))))
+            .inner_join(black_players.on(games::black_id.eq(black_players.field(players::id))))
+            .inner_join(events::table.on(games::event_id.eq(events::id)))
+            .inner_join(sites::table.on(games::site_id.eq(sites::id)))
+            .filter(games::id.eq_any(&game_ids))
+            .order(diesel::dsl::sql::<diesel::sql_types::Integer>(
+                "COALESCE(Games.WhiteElo, 0) + COALESCE(Games.BlackElo, 0) DESC",
+            ))
+            .first::<(Game, Player, Player, Event, Site)>(db)
+            .optional()?;
+        normalize_games(best.into_iter().collect())?.pop()
+    };
+
+    Ok(Some(PositionPreview { moves, total, game }))
+}
+
+/// Time `is_position_in_db` may take when the caller gives no deadline.
+const POSITION_CHECK_DEADLINE: Duration = Duration::from_secs(2);
+
+/// Games replayed between two deadline and cancellation checks.
+const POSITION_CHECK_STRIDE: usize = 32;
+
+/// Answer of `is_position_in_db`. `Unknown` means the check was cancelled or ran out of time
+/// before finding the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum PositionPresence {
+    Found,
+    NotFound,
+    Unknown,
+}
+
+/// Check if a position exists in the database (without full search).
+///
+/// The check gives up with `Unknown` once `deadline` (default `POSITION_CHECK_DEADLINE`) has passed
+/// or `cancel_position_checks` is called, so callers waiting on it never stall.
 pub async fn is_position_in_db(
     file: PathBuf,
     query: GameQueryJs,
+    deadline: Option<Duration>,
     state: tauri::State<'_, AppState>,
-) -> Result<bool, Error> {
+) -> Result<PositionPresence, Error> {
+    let started = Instant::now();
+    let deadline = started + deadline.unwrap_or(POSITION_CHECK_DEADLINE);
+    let generation = state.position_check_generation.load(Ordering::SeqCst);
+    let interrupted = || {
+        Instant::now() >= deadline
+            || state.position_check_generation.load(Ordering::SeqCst) != generation
+    };
+
     let mut cache_query = query.clone();
     cache_query.game_details_limit = None;
 
     if let Some(pos) = state.line_cache.get(&(cache_query.clone(), file.clone())) {
-        return Ok(!pos.0.is_empty());
+        return Ok(if pos.0.is_empty() {
+            PositionPresence::NotFound
+        } else {
+            PositionPresence::Found
+        });
     }
 
-    let permit = state.new_request.acquire().await.unwrap();
+    let Ok(permit) = tokio::time::timeout_at(deadline.into(), state.new_request.acquire()).await
+    else {
+        return Ok(PositionPresence::Unknown);
+    };
+    let permit = permit.unwrap();
 
     let position_query = match &query.position {
         Some(pos_query) => convert_position_query(pos_query.clone())?,
         None => {
             drop(permit);
-            return Ok(false);
+            return Ok(PositionPresence::NotFound);
         }
     };
 
@@ -1839,10 +2357,9 @@ pub async fn is_position_in_db(
     let db = &mut get_db_or_create(&state, file_str, ConnectionOptions::default())?;
 
     if ENABLE_AUX_INDEXES {
-        ensure_aux_indexes(db);
-    }
-    if ENABLE_CHECKPOINT_TABLE_SCHEMA {
-        ensure_checkpoint_table(db);
+        if let Some(_write) = state.db_writes.try_lock(&file, "is_position_in_db") {
+            ensure_aux_indexes(db);
+        }
     }
 
     let mut sample_query_builder = games::table.into_boxed();
@@ -1867,20 +2384,48 @@ pub async fn is_position_in_db(
         .limit(1000)
         .load(db)?;
 
-    let exists = sample.iter().any(|(_id, _result, game, fen)| {
-        get_move_after_match(game, fen, &position_query)
+    let target_hashes: Vec<u64> = position_query.exact_board_hash().into_iter().collect();
+    let filters = filters_for(&target_hashes, &state, db, &file);
+
+    let mut presence = PositionPresence::NotFound;
+    for (i, (id, _result, game, fen)) in sample.iter().enumerate() {
+        if i % POSITION_CHECK_STRIDE == 0 && interrupted() {
+            presence = PositionPresence::Unknown;
+            break;
+        }
+        if rules_out(filters.as_deref(), &target_hashes, *id, game) {
+            continue;
+        }
+        if get_move_after_match(game, fen, &position_query)
             .unwrap_or(None)
             .is_some()
-    });
+        {
+            presence = PositionPresence::Found;
+            break;
+        }
+    }
 
-    if !exists {
-        state
-            .line_cache
-            .insert((cache_query, file), (vec![], vec![]));
+    match presence {
+        PositionPresence::NotFound => {
+            state
+                .line_cache
+                .insert((cache_query, file), (vec![], vec![]));
+        }
+        PositionPresence::Unknown => {
+            log::debug!("Position check interrupted after {:?}", started.elapsed());
+        }
+        PositionPresence::Found => {}
     }
 
     drop(permit);
-    Ok(exists)
+    Ok(presence)
+}
+
+/// Interrupt the running `is_position_in_db` checks; they answer `Unknown`.
+#[tauri::command]
+#[specta::specta]
+pub fn cancel_position_checks(state: tauri::State<'_, AppState>) {
+    state.position_check_generation.fetch_add(1, Ordering::SeqCst);
 }
 
 #[cfg(test)]
@@ -1999,4 +2544,108 @@ mod tests {
         let result = get_move_after_match(&game[..], &None, &query).unwrap();
         assert_eq!(result, Some("e4".to_string()));
     }
+
+    #[test]
+    fn mirrored_match_test() {
+        assert_eq!(
+            mirror_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap(),
+            "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1"
+        );
+        assert_eq!(mirror_san("Nf3"), "Nf6");
+        assert_eq!(mirror_san("exd6+"), "exd3+");
+
+        let game = vec![12, 12]; // 1. e4 e5
+        let target = SearchTarget::from_js(&PositionQueryJs {
+            fen: "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1".to_string(),
+            type_: "exact".to_string(),
+            include_mirrored: Some(true),
+            constraints: None,
+        })
+        .unwrap();
+        let result = target.match_game(&game[..], &None, Some("1-0"));
+        assert_eq!(result, Some(("e4".to_string(), Some("0-1"))));
+    }
+
+    #[test]
+    fn checkpoint_segments_test() {
+        // 1. Nf3 Nf6 2. e4 Nxe4
+        let mut position = Chess::default();
+        let mut game = Vec::new();
+        for san in ["Nf3", "Nf6", "e4", "Nxe4"] {
+            let m = san.parse::<shakmaty::san::San>().unwrap().to_move(&position).unwrap();
+            let index = position.legal_moves().iter().position(|legal| *legal == m).unwrap();
+            game.push(index as u8);
+            position.play_unchecked(&m);
+        }
+
+        let segments = checkpoint_segments(&game, Chess::default());
+        assert_eq!(
+            segments.iter().map(|c| c.ply).collect::<Vec<_>>(),
+            vec![0, 3, 4]
+        );
+        // Knight moves keep the structure of the starting position.
+        let query = PositionQuery::exact_from_fen(
+            "rnbqkb1r/pppppppp/5n2/8/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 2 2",
+        )
+        .unwrap();
+        assert_eq!(
+            query.exact_structure_hash(),
+            Some(segments[0].structure_hash)
+        );
+        assert_eq!(
+            Some(structure_hash(position.board())),
+            segments.last().map(|c| c.structure_hash)
+        );
+        assert_ne!(segments[1].structure_hash, segments[2].structure_hash);
+        assert!(segments.iter().all(|c| c.fen.is_none()));
+    }
+
+    #[test]
+    fn resume_from_checkpoint_test() {
+        // Knights back and forth, then 1. e4 e5 2. Nf3 at plies 25-27.
+        let mut position = Chess::default();
+        let mut game = Vec::new();
+        let shuffle = ["Nf3", "Nf6", "Ng1", "Ng8"];
+        let sans = shuffle
+            .iter()
+            .cycle()
+            .take(24)
+            .chain(["e4", "e5", "Nf3"].iter());
+        for san in sans {
+            let m = san.parse::<shakmaty::san::San>().unwrap().to_move(&position).unwrap();
+            let index = position.legal_moves().iter().position(|legal| *legal == m).unwrap();
+            game.push(index as u8);
+            position.play_unchecked(&m);
+        }
+
+        let segments = checkpoint_segments(&game, Chess::default());
+        assert_eq!(
+            segments.iter().map(|c| c.ply).collect::<Vec<_>>(),
+            vec![0, 25, 26]
+        );
+        let resume = segments.iter().find(|c| c.fen.is_some()).unwrap();
+        assert_eq!(resume.ply, 25);
+
+        let points = HashMap::from([(
+            1,
+            ResumePoint {
+                ply: 25,
+                moves_len: game.len(),
+                fen: resume.fen.clone(),
+            },
+        )]);
+        let (moves, fen) = resume_from(&points, 1, &game, &None);
+        assert_eq!(moves.len(), 2);
+        let query = PositionQuery::exact_from_fen(
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 14",
+        )
+        .unwrap();
+        assert_eq!(
+            get_move_after_match(moves, fen, &query).unwrap(),
+            get_move_after_match(&game, &None, &query).unwrap()
+        );
+        // An edited game is replayed in full.
+        let (moves, _) = resume_from(&points, 1, &game[..26], &None);
+        assert_eq!(moves.len(), 26);
+    }
 }
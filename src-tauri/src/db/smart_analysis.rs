@@ -0,0 +1,343 @@
+//! Smart queue for bulk engine analysis under a time budget.
+//!
+//! Instead of going through a database front to back, `run_smart_analysis` analyzes the games
+//! most worth the engine time first: the user's own games, then decisive results, recent games
+//! and rated games. It keeps going until the time budget is spent ("analyze as much as possible
+//! overnight") or `stop_smart_analysis` is called. Analyzed games get `[%eval]` comments and
+//! best-move variations like attached external analysis, and are recorded in the database's
+//! `AnalyzedGames` table, so the next run resumes with the games left.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use shakmaty::{fen::Fen, CastlingMode, Chess, EnPassantMode, FromSetup};
+use specta::Type;
+
+use crate::{
+    chess::{AnalysisOptions, EngineOption, GameAnalysisService, GoMode},
+    db::{
+        encoding::extract_main_line_moves,
+        external_analysis::{attach_to_tree, AnalysisEntry},
+        get_db_or_create,
+        move_data::update_move_data,
+        pgn::GameTree,
+        schema::{analyzed_games, games, players},
+        ConnectionOptions,
+    },
+    error::Result,
+    progress::{TaskKind, TaskProgress},
+    AppState,
+};
+
+/// Progress id of smart analysis runs.
+const TASK_ID: &str = "smart_analysis";
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartAnalysisOptions {
+    /// Path of the engine binary.
+    pub engine: String,
+    pub go_mode: GoMode,
+    #[serde(default)]
+    pub uci_options: Vec<EngineOption>,
+    /// Names of the user's accounts; their games are analyzed first.
+    pub players: Vec<String>,
+    /// Seconds the run may take.
+    pub budget_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartAnalysisSummary {
+    /// Games analyzed during this run.
+    pub analyzed: i32,
+    /// Games still waiting for analysis.
+    pub remaining: i32,
+    /// Whether the run was stopped before the budget was spent.
+    pub stopped: bool,
+}
+
+#[derive(Debug, Clone, Queryable)]
+struct Candidate {
+    id: i32,
+    white_id: i32,
+    black_id: i32,
+    result: Option<String>,
+    date: Option<String>,
+    white_elo: Option<i32>,
+    black_elo: Option<i32>,
+}
+
+/// Queue rank of a game; higher ranks are analyzed first. Each criterion outweighs all the
+/// following ones together.
+fn priority(game: &Candidate, user_ids: &HashSet<i32>, recent_since: &str) -> u8 {
+    let mut priority = 0;
+    if user_ids.contains(&game.white_id) || user_ids.contains(&game.black_id) {
+        priority += 8;
+    }
+    if matches!(game.result.as_deref(), Some("1-0") | Some("0-1")) {
+        priority += 4;
+    }
+    if game.date.as_deref().is_some_and(|date| date >= recent_since) {
+        priority += 2;
+    }
+    if game.white_elo.unwrap_or(0) > 0 && game.black_elo.unwrap_or(0) > 0 {
+        priority += 1;
+    }
+    priority
+}
+
+/// Games not analyzed yet, in analysis order: by priority, then most recent first.
+fn queue(mut games: Vec<Candidate>, user_ids: &HashSet<i32>, recent_since: &str) -> Vec<i32> {
+    games.sort_by(|a, b| {
+        priority(b, user_ids, recent_since)
+            .cmp(&priority(a, user_ids, recent_since))
+            .then_with(|| b.date.cmp(&a.date))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    games.into_iter().map(|game| game.id).collect()
+}
+
+fn load_queue(db: &mut SqliteConnection, user_names: &[String]) -> Result<Vec<i32>> {
+    let user_ids: HashSet<i32> = players::table
+        .filter(players::name.eq_any(user_names))
+        .select(players::id)
+        .load::<i32>(db)?
+        .into_iter()
+        .collect();
+    let candidates: Vec<Candidate> = games::table
+        .filter(games::id.ne_all(analyzed_games::table.select(analyzed_games::game_id)))
+        .select((
+            games::id,
+            games::white_id,
+            games::black_id,
+            games::result,
+            games::date,
+            games::white_elo,
+            games::black_elo,
+        ))
+        .load(db)?;
+
+    let recent_since = (chrono::Utc::now() - chrono::Duration::days(365))
+        .format("%Y.%m.%d")
+        .to_string();
+    Ok(queue(candidates, &user_ids, &recent_since))
+}
+
+/// Analyze the main line of a game and store the evaluations in it.
+async fn analyze_stored_game(
+    file: &Path,
+    game_id: i32,
+    options: &SmartAnalysisOptions,
+    state: &tauri::State<'_, AppState>,
+    app: &tauri::AppHandle,
+) -> Result<()> {
+    let (moves, fen): (Vec<u8>, Option<String>) = {
+        let db =
+            &mut get_db_or_create(state, file.to_str().unwrap(), ConnectionOptions::default())?;
+        games::table
+            .find(game_id)
+            .select((games::moves, games::fen))
+            .first(db)?
+    };
+    let start = match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let main_line = extract_main_line_moves(&moves, Some(start.clone()))?;
+
+    let annotated = if main_line.is_empty() {
+        None
+    } else {
+        let analysis = GameAnalysisService::analyze_game(
+            format!("{}_{}", TASK_ID, game_id),
+            options.engine.clone(),
+            options.go_mode.clone(),
+            AnalysisOptions {
+                fen: Fen::from_position(start.clone(), EnPassantMode::Legal).to_string(),
+                moves: main_line
+                    .iter()
+                    .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+                    .collect(),
+                ..Default::default()
+            },
+            options.uci_options.clone(),
+            state.clone(),
+            app.clone(),
+        )
+        .await?;
+
+        // The analysis has one entry per position, starting with the initial one.
+        let entries: Vec<Option<AnalysisEntry>> = analysis
+            .iter()
+            .enumerate()
+            .map(|(ply, a)| {
+                a.best
+                    .first()
+                    .map(|line| AnalysisEntry::from_line(ply as u32, line))
+            })
+            .collect();
+        let by_ply: HashMap<u32, &AnalysisEntry> = entries
+            .iter()
+            .enumerate()
+            .filter_map(|(ply, entry)| entry.as_ref().map(|entry| (ply as u32, entry)))
+            .collect();
+
+        let tree = GameTree::from_bytes(&moves, Some(start.clone()))?;
+        let (tree, _) = attach_to_tree(tree, &start, &by_ply)?;
+        let mut bytes = Vec::new();
+        tree.encode(&mut bytes, Some(start));
+
+        Some((bytes, tree))
+    };
+
+    // The write lock is only held for storing, so the game may have been edited meanwhile. It is
+    // then left unanalyzed and queued again by the next run.
+    let _write = state.db_writes.lock(file, "run_smart_analysis").await;
+    let db = &mut get_db_or_create(state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    if let Some((bytes, tree)) = annotated {
+        let current: Vec<u8> = games::table.find(game_id).select(games::moves).first(db)?;
+        if current != moves {
+            log::info!("Game {} changed during its analysis, not storing it", game_id);
+            return Ok(());
+        }
+        diesel::update(games::table.filter(games::id.eq(game_id)))
+            .set(games::moves.eq(bytes))
+            .execute(db)?;
+        update_move_data(db, game_id, &tree)?;
+    }
+
+    // Games without moves are recorded too, so they are not queued again.
+    diesel::insert_or_ignore_into(analyzed_games::table)
+        .values(analyzed_games::game_id.eq(game_id))
+        .execute(db)?;
+    Ok(())
+}
+
+/// Analyze the games of a database in priority order until `budget_secs` are spent. Games are
+/// only started if the average time per game so far still fits in the budget.
+#[tauri::command]
+#[specta::specta]
+pub async fn run_smart_analysis(
+    file: PathBuf,
+    options: SmartAnalysisOptions,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<SmartAnalysisSummary> {
+    let job = state.jobs.start(
+        &app,
+        TaskKind::Analysis,
+        TASK_ID,
+        format!("Analyzing the games of {}", file.display()),
+    );
+    let _awake = crate::app::platform::keep_awake::keep_awake("Analyzing database games");
+    state.smart_analysis_stop.store(false, Ordering::Relaxed);
+    let queue = {
+        let db =
+            &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+        load_queue(db, &options.players)?
+    };
+
+    let started = Instant::now();
+    let budget = Duration::from_secs(options.budget_secs);
+    let mut analyzed = 0;
+    let mut stopped = false;
+    for game_id in &queue {
+        if state.smart_analysis_stop.load(Ordering::Relaxed) || job.is_cancelled() {
+            stopped = true;
+            break;
+        }
+        let elapsed = started.elapsed();
+        let average = if analyzed > 0 {
+            elapsed / analyzed
+        } else {
+            Duration::ZERO
+        };
+        if elapsed + average > budget {
+            break;
+        }
+
+        let percent = (elapsed.as_secs_f64() / budget.as_secs_f64().max(1.0) * 100.0).min(100.0);
+        TaskProgress::new(TaskKind::Analysis, TASK_ID, percent)
+            .message(format!("{} of {} games analyzed", analyzed, queue.len()))
+            .send(&app);
+
+        crate::app::platform::power::pace_background_job().await;
+        analyze_stored_game(&file, *game_id, &options, &state, &app).await?;
+        analyzed += 1;
+    }
+    TaskProgress::done(TaskKind::Analysis, TASK_ID)
+        .message(format!(
+            "{} games analyzed, {} left",
+            analyzed,
+            queue.len() - analyzed as usize
+        ))
+        .send(&app);
+
+    log::info!(
+        "Smart analysis of {} analyzed {} games in {:?}, {} left",
+        file.display(),
+        analyzed,
+        started.elapsed(),
+        queue.len() - analyzed as usize
+    );
+    Ok(SmartAnalysisSummary {
+        analyzed: analyzed as i32,
+        remaining: (queue.len() - analyzed as usize) as i32,
+        stopped,
+    })
+}
+
+/// Stop the running smart analysis after the game being analyzed.
+#[tauri::command]
+#[specta::specta]
+pub fn stop_smart_analysis(state: tauri::State<'_, AppState>) {
+    state.smart_analysis_stop.store(true, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(id: i32, white_id: i32, result: &str, date: &str, elo: Option<i32>) -> Candidate {
+        Candidate {
+            id,
+            white_id,
+            black_id: 100,
+            result: Some(result.to_string()),
+            date: Some(date.to_string()),
+            white_elo: elo,
+            black_elo: elo,
+        }
+    }
+
+    #[test]
+    fn test_priority() {
+        let user: HashSet<i32> = [1].into_iter().collect();
+        let since = "2024.01.01";
+        assert_eq!(priority(&game(1, 1, "1-0", "2024.05.01", Some(2000)), &user, since), 15);
+        assert_eq!(priority(&game(2, 2, "1/2-1/2", "2020.05.01", None), &user, since), 0);
+        assert_eq!(priority(&game(3, 2, "0-1", "2020.05.01", Some(1800)), &user, since), 5);
+    }
+
+    #[test]
+    fn test_queue_order() {
+        let user: HashSet<i32> = [1].into_iter().collect();
+        let games = vec![
+            game(1, 2, "1-0", "2024.03.01", Some(2000)),
+            game(2, 1, "1/2-1/2", "2019.01.01", None),
+            game(3, 2, "1-0", "2024.06.01", Some(2000)),
+            game(4, 2, "1/2-1/2", "2024.06.01", Some(2000)),
+        ];
+        assert_eq!(queue(games, &user, "2024.01.01"), vec![2, 3, 1, 4]);
+    }
+}
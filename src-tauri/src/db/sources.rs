@@ -0,0 +1,159 @@
+//! Import provenance of games.
+//!
+//! Every import records its source for the games it added: the file name by default, or a label
+//! given by the caller such as a URL, a TWIC issue or an account sync. Sources live in the
+//! `GameSources` table, so the games of one import can be filtered in `get_games` or removed
+//! with `delete_games_by_source`. Games imported before the table existed have no source.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{Integer, Text},
+};
+use serde::Serialize;
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        models::NormalizedGame,
+        schema::{game_sources, games},
+        update_info_counts, ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSource {
+    pub source: String,
+    pub games: i64,
+    /// Time of the first import from this source, in UTC.
+    pub imported_at: String,
+}
+
+/// Source recorded when the caller gives none: the name of the imported file.
+pub(super) fn default_source(file: &Path) -> String {
+    file.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file.to_string_lossy().into_owned())
+}
+
+/// Highest game id, so the games added by an import can be told apart afterwards.
+pub(super) fn last_game_id(db: &mut SqliteConnection) -> Result<i32> {
+    let id: Option<i32> = games::table
+        .select(diesel::dsl::max(games::id))
+        .first(db)?;
+    Ok(id.unwrap_or(0))
+}
+
+/// Record `source` for every game added after `last_id`. Returns the number of games recorded.
+pub(super) fn record_import(db: &mut SqliteConnection, last_id: i32, source: &str) -> Result<usize> {
+    let recorded = sql_query(
+        "INSERT OR IGNORE INTO GameSources (GameID, Source) SELECT ID, ? FROM Games WHERE ID > ?",
+    )
+    .bind::<Text, _>(source)
+    .bind::<Integer, _>(last_id)
+    .execute(db)?;
+    Ok(recorded)
+}
+
+/// Fill in the source of each game.
+/// Deletes the games imported from `source`, without updating the info counts.
+pub(super) fn delete_source_games(db: &mut SqliteConnection, source: &str) -> Result<usize> {
+    db.transaction::<_, Error, _>(|db| {
+        let deleted = diesel::delete(
+            games::table.filter(
+                games::id.eq_any(
+                    game_sources::table
+                        .filter(game_sources::source.eq(source))
+                        .select(game_sources::game_id),
+                ),
+            ),
+        )
+        .execute(db)?;
+        // Cascades when foreign keys are enabled; done explicitly for connections without them.
+        diesel::delete(game_sources::table.filter(game_sources::source.eq(source))).execute(db)?;
+        Ok(deleted)
+    })
+}
+
+pub(super) fn attach_sources(db: &mut SqliteConnection, games: &mut [NormalizedGame]) -> Result<()> {
+    if games.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<i32> = games.iter().map(|game| game.id).collect();
+    let sources: HashMap<i32, String> = game_sources::table
+        .filter(game_sources::game_id.eq_any(ids))
+        .select((game_sources::game_id, game_sources::source))
+        .load::<(i32, String)>(db)?
+        .into_iter()
+        .collect();
+    for game in games.iter_mut() {
+        game.source = sources.get(&game.id).cloned();
+    }
+    Ok(())
+}
+
+/// Sources of the games in a database, most recent import first.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_import_sources(
+    file: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ImportSource>> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let rows: Vec<(String, i64, Option<String>)> = game_sources::table
+        .group_by(game_sources::source)
+        .select((
+            game_sources::source,
+            diesel::dsl::count_star(),
+            diesel::dsl::min(game_sources::imported_at),
+        ))
+        .order(diesel::dsl::min(game_sources::imported_at).desc())
+        .load(db)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(source, games, imported_at)| ImportSource {
+            source,
+            games,
+            imported_at: imported_at.unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Delete every game imported from `source`. Returns the number of games deleted.
+#[tauri::command]
+#[specta::specta]
+pub async fn delete_games_by_source(
+    file: PathBuf,
+    source: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize> {
+    let _write = state.db_writes.lock(&file, "delete_games_by_source").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+    let deleted = delete_source_games(db, &source)?;
+    update_info_counts(db)?;
+
+    log::info!("Deleted {} games imported from {}", deleted, source);
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_source() {
+        assert_eq!(default_source(Path::new("/tmp/twic1550g.pgn")), "twic1550g.pgn");
+        assert_eq!(default_source(Path::new("games.pgn.zst")), "games.pgn.zst");
+    }
+}
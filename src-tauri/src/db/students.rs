@@ -0,0 +1,611 @@
+//! Student tracking for coaches.
+//!
+//! Students, their linked data sources (online account names, game databases and repertoire
+//! files) and their puzzle attempts live in `students.db3` in the app data directory.
+//! `get_student_progress` combines those sources into puzzle statistics, a monthly accuracy trend
+//! computed from evaluated games, and how closely the student's games follow their repertoires.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    path::PathBuf,
+};
+
+use diesel::{connection::SimpleConnection, prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    fen::Fen,
+    zobrist::{Zobrist64, ZobristHash},
+    CastlingMode, Chess, Color, EnPassantMode, FromSetup, Position,
+};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+
+use crate::{
+    db::{
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode},
+        player_report::game_losses,
+        schema::{games, players},
+        ConnectionOptions, WriteGuard,
+    },
+    error::{Error, Result},
+    repertoire::repertoire_moves,
+    AppState,
+};
+
+diesel::define_sql_function! {
+    /// SQLite's `lower`, which only folds ASCII letters.
+    fn lower(text: diesel::sql_types::Text) -> diesel::sql_types::Text;
+}
+
+diesel::table! {
+    students (id) {
+        id -> Integer,
+        name -> Text,
+        notes -> Nullable<Text>,
+        created_at -> Text,
+    }
+}
+
+diesel::table! {
+    student_sources (id) {
+        id -> Integer,
+        student_id -> Integer,
+        kind -> Text,
+        platform -> Nullable<Text>,
+        value -> Text,
+        color -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    student_puzzle_attempts (id) {
+        id -> Integer,
+        student_id -> Integer,
+        puzzle_id -> Integer,
+        rating -> Integer,
+        solved -> Bool,
+        attempted_at -> Text,
+    }
+}
+
+fn students_db_path(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app.path().resolve("students.db3", BaseDirectory::AppData)?)
+}
+
+/// Write access to `students.db3`, so windows changing it at the same time take turns.
+async fn lock_students_db(app: &AppHandle, operation: &'static str) -> Result<WriteGuard> {
+    let db_path = students_db_path(app)?;
+    Ok(app.state::<AppState>().db_writes.lock(&db_path, operation).await)
+}
+
+fn get_students_db(app: &AppHandle) -> Result<SqliteConnection> {
+    let db_path = students_db_path(app)?;
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut conn = SqliteConnection::establish(&db_path.to_string_lossy())?;
+    conn.batch_execute(
+        r#"
+        PRAGMA foreign_keys = ON;
+
+        CREATE TABLE IF NOT EXISTS students (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            notes TEXT,
+            created_at TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS student_sources (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            student_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            platform TEXT,
+            value TEXT NOT NULL,
+            color TEXT,
+            FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS student_puzzle_attempts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            student_id INTEGER NOT NULL,
+            puzzle_id INTEGER NOT NULL,
+            rating INTEGER NOT NULL,
+            solved BOOLEAN NOT NULL,
+            attempted_at TEXT NOT NULL,
+            FOREIGN KEY (student_id) REFERENCES students(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_student_sources_student ON student_sources(student_id);
+        CREATE INDEX IF NOT EXISTS idx_student_puzzle_attempts_student ON student_puzzle_attempts(student_id);
+        "#,
+    )?;
+    Ok(conn)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum StudentSourceKind {
+    /// Online account; `value` is the username as it appears in game headers.
+    Account,
+    /// Game database; `value` is the database path.
+    Database,
+    /// Repertoire PGN; `value` is the file path and `color` the side it is played with.
+    Repertoire,
+}
+
+impl StudentSourceKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Account => "account",
+            Self::Database => "database",
+            Self::Repertoire => "repertoire",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "account" => Some(Self::Account),
+            "database" => Some(Self::Database),
+            "repertoire" => Some(Self::Repertoire),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct StudentSource {
+    pub id: i32,
+    pub kind: StudentSourceKind,
+    pub platform: Option<String>,
+    pub value: String,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct Student {
+    pub id: i32,
+    pub name: String,
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub sources: Vec<StudentSource>,
+}
+
+fn load_sources(conn: &mut SqliteConnection, student_id: i32) -> Result<Vec<StudentSource>> {
+    let rows: Vec<(i32, String, Option<String>, String, Option<String>)> = student_sources::table
+        .filter(student_sources::student_id.eq(student_id))
+        .select((
+            student_sources::id,
+            student_sources::kind,
+            student_sources::platform,
+            student_sources::value,
+            student_sources::color,
+        ))
+        .order(student_sources::id.asc())
+        .load(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, kind, platform, value, color)| {
+            Some(StudentSource {
+                id,
+                kind: StudentSourceKind::parse(&kind)?,
+                platform,
+                value,
+                color,
+            })
+        })
+        .collect())
+}
+
+fn load_student(conn: &mut SqliteConnection, student_id: i32) -> Result<Student> {
+    let (id, name, notes, created_at): (i32, String, Option<String>, String) =
+        students::table.find(student_id).first(conn)?;
+    Ok(Student {
+        id,
+        name,
+        notes,
+        created_at,
+        sources: load_sources(conn, id)?,
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn create_student(
+    app: AppHandle,
+    name: String,
+    notes: Option<String>,
+) -> Result<Student> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(Error::PackageManager("Student name cannot be empty".to_string()));
+    }
+    let _write = lock_students_db(&app, "create_student").await?;
+    let conn = &mut get_students_db(&app)?;
+    let id: i32 = diesel::insert_into(students::table)
+        .values((
+            students::name.eq(&name),
+            students::notes.eq(&notes),
+            students::created_at.eq(chrono::Utc::now().to_rfc3339()),
+        ))
+        .returning(students::id)
+        .get_result(conn)?;
+    load_student(conn, id)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn list_students(app: AppHandle) -> Result<Vec<Student>> {
+    let conn = &mut get_students_db(&app)?;
+    let ids: Vec<i32> = students::table
+        .select(students::id)
+        .order(students::name.asc())
+        .load(conn)?;
+    ids.into_iter().map(|id| load_student(conn, id)).collect()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn delete_student(app: AppHandle, student_id: i32) -> Result<()> {
+    let _write = lock_students_db(&app, "delete_student").await?;
+    let conn = &mut get_students_db(&app)?;
+    diesel::delete(students::table.find(student_id)).execute(conn)?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn link_student_source(
+    app: AppHandle,
+    student_id: i32,
+    kind: StudentSourceKind,
+    platform: Option<String>,
+    value: String,
+    color: Option<String>,
+) -> Result<StudentSource> {
+    if kind == StudentSourceKind::Repertoire && !matches!(color.as_deref(), Some("white" | "black")) {
+        return Err(Error::PackageManager(
+            "Repertoire sources need a color (white or black)".to_string(),
+        ));
+    }
+    let _write = lock_students_db(&app, "link_student_source").await?;
+    let conn = &mut get_students_db(&app)?;
+    let id: i32 = diesel::insert_into(student_sources::table)
+        .values((
+            student_sources::student_id.eq(student_id),
+            student_sources::kind.eq(kind.as_str()),
+            student_sources::platform.eq(&platform),
+            student_sources::value.eq(&value),
+            student_sources::color.eq(&color),
+        ))
+        .returning(student_sources::id)
+        .get_result(conn)?;
+    Ok(StudentSource {
+        id,
+        kind,
+        platform,
+        value,
+        color,
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn unlink_student_source(app: AppHandle, source_id: i32) -> Result<()> {
+    let _write = lock_students_db(&app, "unlink_student_source").await?;
+    let conn = &mut get_students_db(&app)?;
+    diesel::delete(student_sources::table.find(source_id)).execute(conn)?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn record_student_puzzle_result(
+    app: AppHandle,
+    student_id: i32,
+    puzzle_id: i32,
+    rating: i32,
+    solved: bool,
+) -> Result<()> {
+    let _write = lock_students_db(&app, "record_student_puzzle_result").await?;
+    let conn = &mut get_students_db(&app)?;
+    diesel::insert_into(student_puzzle_attempts::table)
+        .values((
+            student_puzzle_attempts::student_id.eq(student_id),
+            student_puzzle_attempts::puzzle_id.eq(puzzle_id),
+            student_puzzle_attempts::rating.eq(rating),
+            student_puzzle_attempts::solved.eq(solved),
+            student_puzzle_attempts::attempted_at.eq(chrono::Utc::now().to_rfc3339()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct StudentPuzzleStats {
+    pub attempted: i32,
+    pub solved: i32,
+    pub solve_rate: f64,
+    pub average_solved_rating: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AccuracyPoint {
+    /// `YYYY-MM`.
+    pub month: String,
+    pub games: i32,
+    pub acpl: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RepertoireAdherence {
+    pub repertoire: String,
+    pub color: String,
+    /// Games that reached at least one repertoire position.
+    pub games: i32,
+    pub followed_moves: i32,
+    pub deviations: i32,
+    pub adherence: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct StudentProgress {
+    pub student: Student,
+    pub puzzles: StudentPuzzleStats,
+    pub accuracy: Vec<AccuracyPoint>,
+    pub repertoire: Vec<RepertoireAdherence>,
+}
+
+fn puzzle_stats(conn: &mut SqliteConnection, student_id: i32) -> Result<StudentPuzzleStats> {
+    let attempts: Vec<(i32, bool)> = student_puzzle_attempts::table
+        .filter(student_puzzle_attempts::student_id.eq(student_id))
+        .select((student_puzzle_attempts::rating, student_puzzle_attempts::solved))
+        .load(conn)?;
+
+    let attempted = attempts.len() as i32;
+    let solved_ratings: Vec<i32> = attempts.iter().filter(|(_, s)| *s).map(|(r, _)| *r).collect();
+    let solved = solved_ratings.len() as i32;
+    Ok(StudentPuzzleStats {
+        attempted,
+        solved,
+        solve_rate: if attempted > 0 { solved as f64 / attempted as f64 } else { 0.0 },
+        average_solved_rating: if solved > 0 {
+            solved_ratings.iter().map(|r| *r as f64).sum::<f64>() / solved as f64
+        } else {
+            0.0
+        },
+    })
+}
+
+/// A game played by the student in one of the linked databases.
+struct StudentGame {
+    color: Color,
+    date: Option<String>,
+    moves: Vec<u8>,
+    fen: Option<String>,
+}
+
+/// Ids of the players named as one of `usernames`, ignoring ASCII case like the online platforms.
+fn player_ids(db: &mut SqliteConnection, usernames: &[String]) -> Result<Vec<i32>> {
+    let mut ids = Vec::new();
+    for username in usernames {
+        let matches: Vec<i32> = players::table
+            .filter(lower(players::name).eq(lower(username)))
+            .select(players::id)
+            .load(db)?;
+        ids.extend(matches);
+    }
+    Ok(ids)
+}
+
+fn load_student_games(
+    state: &tauri::State<'_, AppState>,
+    database: &str,
+    usernames: &[String],
+) -> Result<Vec<StudentGame>> {
+    let db = &mut get_db_or_create(state, database, ConnectionOptions::default())?;
+
+    let player_ids = player_ids(db, usernames)?;
+    if player_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let rows: Vec<(i32, Option<String>, Vec<u8>, Option<String>)> = games::table
+        .filter(games::white_id.eq_any(&player_ids).or(games::black_id.eq_any(&player_ids)))
+        .select((games::white_id, games::date, games::moves, games::fen))
+        .load(db)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(white_id, date, moves, fen)| StudentGame {
+            color: if player_ids.contains(&white_id) { Color::White } else { Color::Black },
+            date,
+            moves,
+            fen,
+        })
+        .collect())
+}
+
+fn accuracy_trend(games: &[StudentGame]) -> Vec<AccuracyPoint> {
+    let mut months: BTreeMap<String, (i32, i32, f64)> = BTreeMap::new();
+    for game in games {
+        let Some(month) = game
+            .date
+            .as_deref()
+            .filter(|d| d.len() >= 7 && !d.starts_with('?'))
+            .map(|d| d[..7].replace('.', "-"))
+        else {
+            continue;
+        };
+        let Some(losses) = game_losses(&game.moves, game.fen.as_deref(), game.color) else {
+            continue;
+        };
+        let entry = months.entry(month).or_default();
+        entry.0 += 1;
+        entry.1 += losses.phases.len() as i32;
+        entry.2 += losses.phases.iter().map(|(_, loss)| loss).sum::<f64>();
+    }
+
+    months
+        .into_iter()
+        .map(|(month, (games, moves, total))| AccuracyPoint {
+            month,
+            games,
+            acpl: if moves > 0 { total / moves as f64 } else { 0.0 },
+        })
+        .collect()
+}
+
+/// Walk the main line and compare the student's moves with the repertoire until the game
+/// leaves it. Returns `(followed, deviated)`, or `None` if no repertoire position was reached.
+fn game_adherence(
+    game: &StudentGame,
+    book: &HashMap<Zobrist64, Vec<String>>,
+) -> Option<(i32, bool)> {
+    let mut position = match game.fen.as_deref() {
+        Some(fen) => {
+            let fen: Fen = fen.parse().ok()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960).ok()?
+        }
+        None => Chess::default(),
+    };
+    let tree = GameTree::from_bytes(&game.moves, Some(position.clone())).ok()?;
+
+    let mut followed = 0;
+    let mut reached = false;
+    for node in tree.nodes() {
+        let GameTreeNode::Move(san_plus) = node else {
+            continue;
+        };
+        if position.turn() == game.color {
+            let hash: Zobrist64 = position.zobrist_hash(EnPassantMode::Legal);
+            let Some(expected) = book.get(&hash) else {
+                break;
+            };
+            reached = true;
+            if !expected.contains(&san_plus.to_string()) {
+                return Some((followed, true));
+            }
+            followed += 1;
+        }
+        let m = san_plus.san.to_move(&position).ok()?;
+        position.play_unchecked(&m);
+    }
+    reached.then_some((followed, false))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_student_progress(
+    app: AppHandle,
+    student_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<StudentProgress> {
+    let (student, puzzles) = {
+        let conn = &mut get_students_db(&app)?;
+        (load_student(conn, student_id)?, puzzle_stats(conn, student_id)?)
+    };
+
+    let values = |kind: StudentSourceKind| {
+        student
+            .sources
+            .iter()
+            .filter(move |s| s.kind == kind)
+            .map(|s| s.value.clone())
+    };
+    let usernames: Vec<String> = values(StudentSourceKind::Account).collect();
+
+    let mut games = Vec::new();
+    if !usernames.is_empty() {
+        for database in values(StudentSourceKind::Database) {
+            match load_student_games(&state, &database, &usernames) {
+                Ok(found) => games.extend(found),
+                Err(e) => log::warn!("Skipping linked database {}: {}", database, e),
+            }
+        }
+    }
+
+    let mut repertoire = Vec::new();
+    for source in student.sources.iter().filter(|s| s.kind == StudentSourceKind::Repertoire) {
+        let (color, color_name) = match source.color.as_deref() {
+            Some("black") => (Color::Black, "black"),
+            _ => (Color::White, "white"),
+        };
+        let book = match File::open(PathBuf::from(&source.value))
+            .map_err(Error::from)
+            .and_then(|file| repertoire_moves(file, color))
+        {
+            Ok(book) => book,
+            Err(e) => {
+                log::warn!("Skipping linked repertoire {}: {}", source.value, e);
+                continue;
+            }
+        };
+
+        let (mut counted, mut followed_moves, mut deviations) = (0, 0, 0);
+        for game in games.iter().filter(|g| g.color == color) {
+            if let Some((followed, deviated)) = game_adherence(game, &book) {
+                counted += 1;
+                followed_moves += followed;
+                deviations += deviated as i32;
+            }
+        }
+        repertoire.push(RepertoireAdherence {
+            repertoire: source.value.clone(),
+            color: color_name.to_string(),
+            games: counted,
+            followed_moves,
+            deviations,
+            adherence: if counted > 0 {
+                1.0 - deviations as f64 / counted as f64
+            } else {
+                0.0
+            },
+        });
+    }
+
+    Ok(StudentProgress {
+        accuracy: accuracy_trend(&games),
+        student,
+        puzzles,
+        repertoire,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_player_ids() {
+        let mut db = SqliteConnection::establish(":memory:").unwrap();
+        db.batch_execute(
+            "CREATE TABLE Players (ID INTEGER PRIMARY KEY, Name TEXT);
+            INSERT INTO Players VALUES (1, 'Magnus_C'), (2, 'MagnusXC'), (3, 'magnus_c'),
+                (4, 'Hikaru');",
+        )
+        .unwrap();
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            player_ids(&mut db, &names(&["MAGNUS_C"])).unwrap(),
+            vec![1, 3]
+        );
+        assert_eq!(
+            player_ids(&mut db, &names(&["magnus%"])).unwrap(),
+            Vec::<i32>::new()
+        );
+        assert_eq!(
+            player_ids(&mut db, &names(&["hikaru", "magnusxc"])).unwrap(),
+            vec![4, 2]
+        );
+    }
+}
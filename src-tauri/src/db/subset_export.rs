@@ -0,0 +1,278 @@
+//! Export of a filtered subset of a database into a new database file.
+//!
+//! The games matching a `GameQueryJs` filter are copied into a freshly initialised database.
+//! Players, events and sites are re-created in the destination as they are met, so it only holds
+//! the entries its games refer to, and the info counts and indexes are rebuilt at the end. Useful
+//! to share a single tournament or to carry a trimmed database on a smaller machine.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use diesel::{connection::SimpleConnection, prelude::*, sqlite::Sqlite};
+
+use crate::{
+    db::{
+        core, endgames,
+        get_db_or_create,
+        models::{Event, Game, NewGame, Player, Site},
+        ops::{create_event, create_player, create_site},
+        schema::{events, game_endgames, games, info, players, sites},
+        update_info_counts, ConnectionOptions, GameQueryJs, JournalMode, Sides, INDEXES_SQL,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+/// Games copied per transaction.
+const BATCH_SIZE: usize = 5000;
+
+type GameIdQuery = diesel::dsl::IntoBoxed<'static, diesel::dsl::Select<games::table, games::id>, Sqlite>;
+
+/// Ids of the games matching `query`, with the same semantics as the `get_games` filters.
+fn game_id_query(query: &GameQueryJs) -> GameIdQuery {
+    let mut ids = games::table.select(games::id).into_boxed();
+
+    if let Some(outcome) = &query.outcome {
+        ids = ids.filter(games::result.eq(outcome.clone()));
+    }
+    if let Some(start_date) = &query.start_date {
+        ids = ids.filter(games::date.ge(start_date.clone()));
+    }
+    if let Some(end_date) = &query.end_date {
+        ids = ids.filter(games::date.le(end_date.clone()));
+    }
+    if let Some(tournament_id) = query.tournament_id {
+        ids = ids.filter(games::event_id.eq(tournament_id));
+    }
+    if let Some(endgame) = &query.endgame {
+        ids = ids.filter(
+            games::id.eq_any(
+                game_endgames::table
+                    .filter(game_endgames::endgame.eq(endgame.clone()))
+                    .select(game_endgames::game_id),
+            ),
+        );
+    }
+
+    match query.sides {
+        Some(Sides::WhiteBlack) => {
+            if let Some(player1) = query.player1 {
+                ids = ids.filter(games::white_id.eq(player1));
+            }
+            if let Some(player2) = query.player2 {
+                ids = ids.filter(games::black_id.eq(player2));
+            }
+            if let Some(range1) = query.range1 {
+                ids = ids.filter(games::white_elo.between(range1.0, range1.1));
+            }
+            if let Some(range2) = query.range2 {
+                ids = ids.filter(games::black_elo.between(range2.0, range2.1));
+            }
+        }
+        Some(Sides::BlackWhite) => {
+            if let Some(player1) = query.player1 {
+                ids = ids.filter(games::black_id.eq(player1));
+            }
+            if let Some(player2) = query.player2 {
+                ids = ids.filter(games::white_id.eq(player2));
+            }
+            if let Some(range1) = query.range1 {
+                ids = ids.filter(games::black_elo.between(range1.0, range1.1));
+            }
+            if let Some(range2) = query.range2 {
+                ids = ids.filter(games::white_elo.between(range2.0, range2.1));
+            }
+        }
+        Some(Sides::Any) => {
+            for player in [query.player1, query.player2].into_iter().flatten() {
+                ids = ids.filter(games::white_id.eq(player).or(games::black_id.eq(player)));
+            }
+            let either = |range: (i32, i32)| {
+                games::white_elo
+                    .between(range.0, range.1)
+                    .or(games::black_elo.between(range.0, range.1))
+            };
+            match (query.range1, query.range2) {
+                (Some(range1), Some(range2)) => {
+                    ids = ids.filter(either(range1).or(either(range2)));
+                }
+                (Some(range), None) | (None, Some(range)) => {
+                    ids = ids.filter(either(range));
+                }
+                (None, None) => {}
+            }
+        }
+        None => {}
+    }
+
+    ids
+}
+
+/// Maps ids of the source database to ids of the same names in the destination.
+#[derive(Default)]
+struct IdMap {
+    players: HashMap<i32, i32>,
+    events: HashMap<i32, i32>,
+    sites: HashMap<i32, i32>,
+}
+
+impl IdMap {
+    fn player(&mut self, db: &mut SqliteConnection, player: &Player) -> Result<i32> {
+        remap(&mut self.players, player.id, player.name.as_deref(), |name| {
+            Ok(create_player(db, name)?.id)
+        })
+    }
+
+    fn event(&mut self, db: &mut SqliteConnection, event: &Event) -> Result<i32> {
+        remap(&mut self.events, event.id, event.name.as_deref(), |name| {
+            Ok(create_event(db, name)?.id)
+        })
+    }
+
+    fn site(&mut self, db: &mut SqliteConnection, site: &Site) -> Result<i32> {
+        remap(&mut self.sites, site.id, site.name.as_deref(), |name| {
+            Ok(create_site(db, name)?.id)
+        })
+    }
+}
+
+/// Id 0 is the seeded "Unknown" entry in every database and is kept as is.
+fn remap(
+    map: &mut HashMap<i32, i32>,
+    id: i32,
+    name: Option<&str>,
+    create: impl FnOnce(&str) -> Result<i32>,
+) -> Result<i32> {
+    if id == 0 {
+        return Ok(0);
+    }
+    if let Some(new_id) = map.get(&id) {
+        return Ok(*new_id);
+    }
+    let new_id = match name {
+        Some(name) => create(name)?,
+        None => 0,
+    };
+    map.insert(id, new_id);
+    Ok(new_id)
+}
+
+fn source_info(db: &mut SqliteConnection, name: &str) -> Option<String> {
+    info::table
+        .filter(info::name.eq(name))
+        .select(info::value)
+        .first::<Option<String>>(db)
+        .ok()
+        .flatten()
+}
+
+/// Create `dest` as a new database holding the games of `source` that match `query`. Returns the
+/// number of games exported. An existing `dest` is never overwritten.
+#[tauri::command]
+#[specta::specta]
+pub async fn export_subset_to_db(
+    source: PathBuf,
+    query: GameQueryJs,
+    dest: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<i32> {
+    let _write = state.db_writes.lock(&dest, "export_subset_to_db").await;
+    if dest.exists() {
+        return Err(Error::PackageManager(format!(
+            "Destination database already exists: {}",
+            dest.display()
+        )));
+    }
+
+    let src = &mut get_db_or_create(&state, source.to_str().unwrap(), ConnectionOptions::default())?;
+    if query.endgame.is_some() {
+        if let Some(_write) = state.db_writes.try_lock(&source, "export_subset_to_db") {
+            endgames::backfill_endgames(src)?;
+        }
+    }
+    let ids: Vec<i32> = game_id_query(&query).order(games::id.asc()).load(src)?;
+
+    let title = source_info(src, "Title").unwrap_or_else(|| "Untitled".to_string());
+    let description = source_info(src, "Description").unwrap_or_default();
+
+    let db = &mut get_db_or_create(
+        &state,
+        dest.to_str().unwrap(),
+        ConnectionOptions {
+            enable_foreign_keys: false,
+            busy_timeout: None,
+            journal_mode: JournalMode::Off,
+        },
+    )?;
+    core::init_db(db, &title, &description)?;
+
+    let (white_players, black_players) = diesel::alias!(players as white, players as black);
+    let mut id_map = IdMap::default();
+    for chunk in ids.chunks(BATCH_SIZE) {
+        let rows: Vec<(Game, Player, Player, Event, Site)> = games::table
+            .inner_join(white_players.on(games::white_id.eq(white_players.field(players::id))))
+            .inner_join(black_players.on(games::black_id.eq(black_players.field(players::id))))
+            .inner_join(events::table.on(games::event_id.eq(events::id)))
+            .inner_join(sites::table.on(games::site_id.eq(sites::id)))
+            .filter(games::id.eq_any(chunk))
+            .order(games::id.asc())
+            .load(src)?;
+
+        db.transaction::<_, Error, _>(|db| {
+            for (game, white, black, event, site) in &rows {
+                let new_game = NewGame {
+                    white_id: id_map.player(db, white)?,
+                    black_id: id_map.player(db, black)?,
+                    event_id: id_map.event(db, event)?,
+                    site_id: id_map.site(db, site)?,
+                    date: game.date.as_deref(),
+                    time: game.time.as_deref(),
+                    round: game.round.as_deref(),
+                    white_elo: game.white_elo,
+                    black_elo: game.black_elo,
+                    white_material: game.white_material,
+                    black_material: game.black_material,
+                    result: game.result.as_deref(),
+                    time_control: game.time_control.as_deref(),
+                    eco: game.eco.as_deref(),
+                    ply_count: game.ply_count.unwrap_or_default(),
+                    fen: game.fen.as_deref(),
+                    moves: &game.moves,
+                    pawn_home: game.pawn_home,
+                };
+                core::add_game(db, new_game)?;
+            }
+            Ok(())
+        })?;
+    }
+
+    db.batch_execute(INDEXES_SQL)?;
+    update_info_counts(db)?;
+
+    log::info!(
+        "Exported {} games from {} to {}",
+        ids.len(),
+        source.display(),
+        dest.display()
+    );
+    Ok(ids.len() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remap() {
+        let mut map = HashMap::new();
+        let mut next = 10;
+        let mut create = |_: &str| {
+            next += 1;
+            Ok(next)
+        };
+        assert_eq!(remap(&mut map, 0, Some("Unknown"), &mut create).unwrap(), 0);
+        assert_eq!(remap(&mut map, 5, Some("Carlsen"), &mut create).unwrap(), 11);
+        assert_eq!(remap(&mut map, 5, Some("Carlsen"), &mut create).unwrap(), 11);
+        assert_eq!(remap(&mut map, 7, None, &mut create).unwrap(), 0);
+        assert_eq!(remap(&mut map, 8, Some("Nepo"), &mut create).unwrap(), 12);
+    }
+}
@@ -0,0 +1,106 @@
+//! User tags on games, e.g. "model game" or "endgame lesson".
+//!
+//! Tags are free-form labels stored in the `GameTags` table of each database. Unlike collections
+//! they need no setup: tagging a game creates the tag, and a tag disappears with its last game.
+//! `get_games` filters by tags through `GameQueryJs::tags`.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use serde::Serialize;
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        schema::{game_tags, games},
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+    pub tag: String,
+    pub games: i64,
+}
+
+fn normalize_tag(tag: &str) -> Result<String> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ");
+    if tag.is_empty() {
+        return Err(Error::PackageManager("Tag cannot be empty".to_string()));
+    }
+    Ok(tag)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn tag_game(
+    file: PathBuf,
+    game_id: i32,
+    tag: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    let tag = normalize_tag(&tag)?;
+    let _write = state.db_writes.lock(&file, "tag_game").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    diesel::insert_or_ignore_into(game_tags::table)
+        .values((game_tags::game_id.eq(game_id), game_tags::tag.eq(&tag)))
+        .execute(db)?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn untag_game(
+    file: PathBuf,
+    game_id: i32,
+    tag: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    let tag = normalize_tag(&tag)?;
+    let _write = state.db_writes.lock(&file, "untag_game").await;
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    diesel::delete(
+        game_tags::table
+            .filter(game_tags::game_id.eq(game_id))
+            .filter(game_tags::tag.eq(&tag)),
+    )
+    .execute(db)?;
+    Ok(())
+}
+
+/// Tags in use with their number of games, most used first.
+#[tauri::command]
+#[specta::specta]
+pub async fn list_tags(file: PathBuf, state: tauri::State<'_, AppState>) -> Result<Vec<TagCount>> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    // Tags of deleted games are skipped; the cascade only applies when foreign keys are enabled.
+    let rows: Vec<(String, i64)> = game_tags::table
+        .filter(game_tags::game_id.eq_any(games::table.select(games::id)))
+        .group_by(game_tags::tag)
+        .select((game_tags::tag, diesel::dsl::count_star()))
+        .order((diesel::dsl::count_star().desc(), game_tags::tag.asc()))
+        .load(db)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(tag, games)| TagCount { tag, games })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag("  model   game ").unwrap(), "model game");
+        assert!(normalize_tag("   ").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Detection of games lost on time in a winning position.
+//!
+//! Online PGNs say how a game ended in their `Termination` header, which is not kept in the
+//! database, so time forfeits are recognized while importing and recorded in `GameTimeForfeits`
+//! together with how far ahead the side that lost on time was. That advantage comes from the last
+//! `[%eval ...]` of the main line when the game was analyzed, and from the material balance
+//! otherwise. Games imported before this table existed are not covered.
+
+use std::path::PathBuf;
+
+use diesel::prelude::*;
+use serde::Serialize;
+use shakmaty::Color;
+use specta::Type;
+
+use crate::{
+    db::{
+        eval_comment::eval_comment_cp,
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode, TempGame},
+        schema::{game_time_forfeits, games},
+        ConnectionOptions,
+    },
+    error::Result,
+    AppState,
+};
+
+/// Advantage, in centipawns, from which the side that lost on time counts as winning.
+pub(super) const WINNING_ADVANTAGE_CP: i32 = 200;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct FlaggingStats {
+    pub lost_on_time: i32,
+    /// Games lost on time with an advantage of at least `WINNING_ADVANTAGE_CP`.
+    pub lost_on_time_winning: i32,
+    pub won_on_time: i32,
+    /// Games won on time while the opponent had an advantage of at least `WINNING_ADVANTAGE_CP`.
+    pub won_on_time_losing: i32,
+}
+
+fn is_time_forfeit(termination: &str) -> bool {
+    let termination = termination.to_lowercase();
+    ["time forfeit", "on time", "timeout"]
+        .iter()
+        .any(|pattern| termination.contains(pattern))
+}
+
+fn loser(result: &str) -> Option<Color> {
+    match result {
+        "1-0" => Some(Color::Black),
+        "0-1" => Some(Color::White),
+        _ => None,
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+/// Last evaluation of the main line, in centipawns from white's point of view.
+fn final_eval(tree: &GameTree) -> Option<f64> {
+    tree.nodes().iter().rev().find_map(|node| match node {
+        GameTreeNode::Comment(comment) => eval_comment_cp(comment),
+        _ => None,
+    })
+}
+
+/// Side that lost `game` on time and its advantage at the end in centipawns, if it was lost on
+/// time.
+fn time_forfeit(game: &TempGame) -> Option<(Color, i32)> {
+    if !is_time_forfeit(game.termination.as_deref()?) {
+        return None;
+    }
+    let loser = loser(game.result.as_deref()?)?;
+    let white_advantage = final_eval(&game.tree).map_or_else(
+        || (game.material_count.white as i32 - game.material_count.black as i32) * 100,
+        |eval| eval.round() as i32,
+    );
+    let advantage = match loser {
+        Color::White => white_advantage,
+        Color::Black => -white_advantage,
+    };
+    Some((loser, advantage))
+}
+
+/// Records `game`, stored as `game_id`, if it was lost on time.
+pub(super) fn record_time_forfeit(
+    db: &mut SqliteConnection,
+    game_id: i32,
+    game: &TempGame,
+) -> Result<()> {
+    let Some((loser, advantage)) = time_forfeit(game) else {
+        return Ok(());
+    };
+    diesel::replace_into(game_time_forfeits::table)
+        .values((
+            game_time_forfeits::game_id.eq(game_id),
+            game_time_forfeits::loser.eq(color_name(loser)),
+            game_time_forfeits::loser_advantage.eq(advantage),
+        ))
+        .execute(db)?;
+    Ok(())
+}
+
+/// Tallies time forfeits given as `(loser, loser advantage, white id, black id)` for `player_id`.
+fn flagging_stats(
+    player_id: i32,
+    forfeits: impl IntoIterator<Item = (String, i32, i32, i32)>,
+) -> FlaggingStats {
+    let mut stats = FlaggingStats::default();
+    for (loser, advantage, white_id, black_id) in forfeits {
+        let loser_id = if loser == color_name(Color::White) {
+            white_id
+        } else {
+            black_id
+        };
+        let winning = advantage >= WINNING_ADVANTAGE_CP;
+        if loser_id == player_id {
+            stats.lost_on_time += 1;
+            stats.lost_on_time_winning += winning as i32;
+        } else {
+            stats.won_on_time += 1;
+            stats.won_on_time_losing += winning as i32;
+        }
+    }
+    stats
+}
+
+/// How often a player lost on time in a winning position, and won on time in a losing one.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_flagging_stats(
+    file: PathBuf,
+    player_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<FlaggingStats> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let forfeits: Vec<(String, i32, i32, i32)> = game_time_forfeits::table
+        .inner_join(games::table.on(games::id.eq(game_time_forfeits::game_id)))
+        .filter(
+            games::white_id
+                .eq(player_id)
+                .or(games::black_id.eq(player_id)),
+        )
+        .select((
+            game_time_forfeits::loser,
+            game_time_forfeits::loser_advantage,
+            games::white_id,
+            games::black_id,
+        ))
+        .load(db)?;
+    Ok(flagging_stats(player_id, forfeits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pgn_reader::BufferedReader;
+
+    use crate::db::pgn::Importer;
+
+    fn import(pgn: &str) -> TempGame {
+        let mut importer = Importer::new(None);
+        BufferedReader::new_cursor(pgn.as_bytes())
+            .read_game(&mut importer)
+            .unwrap()
+            .flatten()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_time_forfeit_from_eval() {
+        let game = import(
+            "[Result \"1-0\"]\n[Termination \"Time forfeit\"]\n\n\
+             1. e4 { [%eval 0.2] } e5 { [%eval -3.1] } 1-0",
+        );
+        assert_eq!(time_forfeit(&game), Some((Color::Black, 310)));
+    }
+
+    #[test]
+    fn test_time_forfeit_from_material() {
+        let game = import(
+            "[Result \"0-1\"]\n[Termination \"Magnus won on time\"]\n\n\
+             1. e4 d5 2. exd5 0-1",
+        );
+        assert_eq!(time_forfeit(&game), Some((Color::White, 100)));
+
+        let drawn = import(
+            "[Result \"1/2-1/2\"]\n[Termination \"Game drawn by timeout vs insufficient material\"]\n\n\
+             1. e4 1/2-1/2",
+        );
+        assert_eq!(time_forfeit(&drawn), None);
+        let resigned = import("[Result \"1-0\"]\n[Termination \"Normal\"]\n\n1. e4 1-0");
+        assert_eq!(time_forfeit(&resigned), None);
+    }
+
+    #[test]
+    fn test_flagging_stats() {
+        let stats = flagging_stats(
+            1,
+            [
+                ("white".to_string(), 450, 1, 2),
+                ("black".to_string(), -80, 1, 3),
+                ("black".to_string(), 90, 2, 1),
+                ("white".to_string(), 1000, 3, 1),
+            ],
+        );
+        assert_eq!(
+            stats,
+            FlaggingStats {
+                lost_on_time: 2,
+                lost_on_time_winning: 1,
+                won_on_time: 2,
+                won_on_time_losing: 1,
+            }
+        );
+    }
+}
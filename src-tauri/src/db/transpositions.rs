@@ -0,0 +1,204 @@
+//! Transpositions inside the move tree of a game.
+//!
+//! Annotated games often reach the same position through different move orders in their
+//! variations. Every position of the tree is keyed by its Zobrist hash while the tree is loaded;
+//! positions reached at more than one place are returned with the paths of all of them, so the
+//! board can offer to jump between them. Paths use the frontend's convention: one child index per
+//! move, the main line being child 0 and the variations of a move following in order.
+//!
+//! A position repeated further down the same line is a repetition, not a transposition, and only
+//! counts when it is also reached from another line.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use diesel::prelude::*;
+use serde::Serialize;
+use shakmaty::{
+    fen::Fen,
+    zobrist::{Zobrist64, ZobristHash},
+    CastlingMode, Chess, EnPassantMode, FromSetup, Position,
+};
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode},
+        schema::games,
+        ConnectionOptions,
+    },
+    error::Result,
+    AppState,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+pub struct Transposition {
+    pub fen: String,
+    /// Every place in the tree where the position is reached, in tree order. `fen` is the
+    /// position at the first one, move counters included.
+    pub paths: Vec<Vec<u32>>,
+}
+
+#[derive(Default)]
+struct Occurrences {
+    order: Vec<Zobrist64>,
+    positions: HashMap<Zobrist64, Vec<(Vec<u32>, Chess)>>,
+}
+
+impl Occurrences {
+    fn record(&mut self, position: &Chess, path: &[u32]) {
+        let hash: Zobrist64 = position.zobrist_hash(EnPassantMode::Legal);
+        self.positions
+            .entry(hash)
+            .or_insert_with(|| {
+                self.order.push(hash);
+                Vec::new()
+            })
+            .push((path.to_vec(), position.clone()));
+    }
+}
+
+/// Walks a line starting at `parent`, whose first move is child `first_child` of it.
+fn walk(
+    tree: &GameTree,
+    position: Chess,
+    parent: &[u32],
+    first_child: u32,
+    seen: &mut Occurrences,
+) -> Result<()> {
+    let mut prev_position = position.clone();
+    let mut position = position;
+    let mut prev_path = parent.to_vec();
+    let mut path = parent.to_vec();
+    let mut next_child = first_child;
+    let mut variations = 0;
+
+    for node in tree.nodes() {
+        match node {
+            GameTreeNode::Move(san_plus) => {
+                let m = san_plus.san.to_move(&position)?;
+                prev_position = position.clone();
+                position.play_unchecked(&m);
+                prev_path = path.clone();
+                path.push(next_child);
+                next_child = 0;
+                variations = 0;
+                seen.record(&position, &path);
+            }
+            GameTreeNode::Variation(branch) => {
+                variations += 1;
+                walk(branch, prev_position.clone(), &prev_path, variations, seen)?;
+            }
+            GameTreeNode::Comment(_) | GameTreeNode::Nag(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn is_prefix(a: &[u32], b: &[u32]) -> bool {
+    a.len() <= b.len() && b[..a.len()] == *a
+}
+
+/// Positions of the tree reached from more than one line.
+pub(crate) fn find_transpositions(tree: &GameTree, start: &Chess) -> Result<Vec<Transposition>> {
+    let mut seen = Occurrences::default();
+    seen.record(start, &[]);
+    walk(tree, start.clone(), &[], 0, &mut seen)?;
+
+    let Occurrences {
+        order,
+        mut positions,
+    } = seen;
+    Ok(order
+        .iter()
+        .filter_map(|hash| positions.remove(hash))
+        .filter(|occurrences| {
+            occurrences.iter().enumerate().any(|(i, (a, _))| {
+                occurrences[i + 1..]
+                    .iter()
+                    .any(|(b, _)| !is_prefix(a, b) && !is_prefix(b, a))
+            })
+        })
+        .map(|mut occurrences| {
+            occurrences.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let fen = Fen::from_position(occurrences[0].1.clone(), EnPassantMode::Legal);
+            Transposition {
+                fen: fen.to_string(),
+                paths: occurrences.into_iter().map(|(path, _)| path).collect(),
+            }
+        })
+        .collect())
+}
+
+/// Transpositions between the variations of a game, for "jump to transposition" navigation.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_game_transpositions(
+    file: PathBuf,
+    game_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Transposition>> {
+    let db = &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+
+    let (moves, fen): (Vec<u8>, Option<String>) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first(db)?;
+
+    let start = match fen.as_deref() {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let tree = GameTree::from_bytes(&moves, Some(start.clone()))?;
+    find_transpositions(&tree, &start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pgn_reader::SanPlus;
+
+    fn line(sans: &[&str]) -> Vec<GameTreeNode> {
+        sans.iter()
+            .map(|san| GameTreeNode::Move(san.parse::<SanPlus>().unwrap()))
+            .collect()
+    }
+
+    fn tree(nodes: Vec<GameTreeNode>) -> GameTree {
+        let mut tree = GameTree::new();
+        for node in nodes {
+            tree.push(node);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_transposition_between_variations() {
+        // 1. e4 (1. Nf3 Nc6 2. e4 e5) 1... e5 2. Nf3 Nc6 (2... Nf6)
+        let mut nodes = line(&["e4"]);
+        nodes.push(GameTreeNode::Variation(tree(line(&[
+            "Nf3", "Nc6", "e4", "e5",
+        ]))));
+        nodes.extend(line(&["e5", "Nf3", "Nc6"]));
+        nodes.push(GameTreeNode::Variation(tree(line(&["Nf6"]))));
+
+        let found = find_transpositions(&tree(nodes), &Chess::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].fen,
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"
+        );
+        assert_eq!(found[0].paths, vec![vec![0, 0, 0, 0], vec![1, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn test_repetition_is_not_a_transposition() {
+        let nodes = line(&["Nf3", "Nf6", "Ng1", "Ng8"]);
+        assert!(find_transpositions(&tree(nodes), &Chess::default())
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,419 @@
+//! Validation of games saved by the frontend.
+//!
+//! `update_game` and `write_game` check what they are given before touching a database or file,
+//! so a buggy editor cannot store a corrupted game: every move of every line has to be legal, the
+//! result has to agree with a final position that ends the game, and dates, times and ratings
+//! have to be well formed. Problems are reported per field; `validate_game_update` and
+//! `validate_pgn_game` return them as a list for the editor to point at, and the saving commands
+//! refuse with all of them in the error message.
+//!
+//! Games of variants other than standard chess only get their headers checked.
+
+use std::fmt;
+
+use chrono::{NaiveDate, NaiveTime};
+use pgn_reader::{BufferedReader, RawHeader, SanPlus, Skip, Visitor};
+use serde::Serialize;
+use shakmaty::{fen::Fen, CastlingMode, Chess, Color, FromSetup, Position, PositionError};
+use specta::Type;
+
+use crate::{
+    db::models::UpdateGame,
+    error::{Error, Result},
+    puzzle_validation::puzzle_variant,
+};
+
+const MAX_ELO: i32 = 4000;
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+pub struct FieldError {
+    /// Field of `UpdateGame`, or PGN header name, the problem is about. Move problems are
+    /// reported on `moves`.
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Checks a PGN date, `YYYY.MM.DD` with `??` for unknown parts.
+fn check_date(date: &str) -> Option<String> {
+    let parts: Vec<&str> = date.split('.').collect();
+    let well_formed = parts.len() == 3
+        && parts.iter().zip([4, 2, 2]).all(|(part, len)| {
+            part.len() == len
+                && (part.bytes().all(|b| b.is_ascii_digit()) || part.bytes().all(|b| b == b'?'))
+        });
+    if !well_formed {
+        return Some(format!(
+            "{} is not a YYYY.MM.DD date (?? marks unknown parts)",
+            date
+        ));
+    }
+    let [year, month, day] = [parts[0], parts[1], parts[2]].map(|part| part.parse::<u32>().ok());
+    if month.is_some_and(|m| !(1..=12).contains(&m)) || day.is_some_and(|d| !(1..=31).contains(&d))
+    {
+        return Some(format!("{} is not a calendar date", date));
+    }
+    if let (Some(year), Some(month), Some(day)) = (year, month, day) {
+        if NaiveDate::from_ymd_opt(year as i32, month, day).is_none() {
+            return Some(format!("{} is not a calendar date", date));
+        }
+    }
+    None
+}
+
+fn check_time(time: &str) -> Option<String> {
+    NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .err()
+        .map(|_| format!("{} is not a HH:MM:SS time", time))
+}
+
+fn check_elo(elo: i32) -> Option<String> {
+    (!(0..=MAX_ELO).contains(&elo)).then(|| format!("{} is not between 0 and {}", elo, MAX_ELO))
+}
+
+/// Checks a rating header, which may also be empty, `-` or `?` when unknown.
+fn check_elo_header(elo: &str) -> Option<String> {
+    match elo.trim() {
+        "" | "-" | "?" => None,
+        elo => match elo.parse::<i32>() {
+            Ok(elo) => check_elo(elo),
+            Err(_) => Some(format!("{} is not a rating", elo)),
+        },
+    }
+}
+
+fn parse_start(fen: &str) -> std::result::Result<Chess, String> {
+    let fen: Fen = fen
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid FEN: {}", e))?;
+    Chess::from_setup(fen.into(), CastlingMode::Chess960)
+        .or_else(PositionError::ignore_too_much_material)
+        .map_err(|e| format!("invalid position: {}", e))
+}
+
+/// Checks that `result` agrees with the final position when that position ends the game.
+fn check_result(result: &str, position: &Chess) -> Option<String> {
+    let expected = match position.outcome()? {
+        shakmaty::Outcome::Decisive {
+            winner: Color::White,
+        } => "1-0",
+        shakmaty::Outcome::Decisive {
+            winner: Color::Black,
+        } => "0-1",
+        shakmaty::Outcome::Draw => "1/2-1/2",
+    };
+    (result != expected).then(|| {
+        format!(
+            "the final position ends the game as {}, not {}",
+            expected, result
+        )
+    })
+}
+
+/// Replays every line of a game, collecting its headers and the illegal moves.
+struct Replay {
+    start: Chess,
+    pos: Chess,
+    prev: Chess,
+    /// Whether the current line hit an illegal move; its remaining moves are not checked.
+    broken: bool,
+    stack: Vec<(Chess, Chess, bool)>,
+    /// Whether the game is of another variant than standard chess, whose moves are not checked.
+    variant: bool,
+    headers: Vec<(String, String)>,
+    /// Final position of the main line, if it could be replayed.
+    end: Option<Chess>,
+    errors: Vec<FieldError>,
+}
+
+impl Replay {
+    fn new(start: Chess) -> Self {
+        Replay {
+            pos: start.clone(),
+            prev: start.clone(),
+            start,
+            broken: false,
+            stack: Vec::new(),
+            variant: false,
+            headers: Vec::new(),
+            end: None,
+            errors: Vec::new(),
+        }
+    }
+
+    fn header_value(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Visitor for Replay {
+    type Result = ();
+
+    fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
+        let key = String::from_utf8_lossy(key).into_owned();
+        let value = value.decode_utf8_lossy().into_owned();
+        if key == "Variant" {
+            match puzzle_variant(Some(&value)) {
+                Ok(variant) => self.variant = variant.is_some(),
+                Err(e) => {
+                    // Moves of an unknown variant cannot be checked either.
+                    self.errors.push(FieldError::new("Variant", e));
+                    self.variant = true;
+                }
+            }
+        }
+        self.headers.push((key, value));
+    }
+
+    fn end_headers(&mut self) -> Skip {
+        if self.variant {
+            return Skip(true);
+        }
+        if let Some(fen) = self.header_value("FEN").map(str::to_string) {
+            match parse_start(&fen) {
+                Ok(start) => self.start = start,
+                Err(e) => {
+                    self.errors.push(FieldError::new("FEN", e));
+                    self.broken = true;
+                }
+            }
+        }
+        self.pos = self.start.clone();
+        self.prev = self.start.clone();
+        Skip(false)
+    }
+
+    fn san(&mut self, san_plus: SanPlus) {
+        if self.broken {
+            return;
+        }
+        match san_plus.san.to_move(&self.pos) {
+            Ok(m) => {
+                self.prev = self.pos.clone();
+                self.pos.play_unchecked(&m);
+            }
+            Err(_) => {
+                let number = self.pos.fullmoves();
+                let dots = if self.pos.turn().is_white() {
+                    "."
+                } else {
+                    "..."
+                };
+                self.errors.push(FieldError::new(
+                    "moves",
+                    format!("illegal move {}{}{}", number, dots, san_plus),
+                ));
+                self.broken = true;
+            }
+        }
+    }
+
+    fn begin_variation(&mut self) -> Skip {
+        self.stack
+            .push((self.pos.clone(), self.prev.clone(), self.broken));
+        // A variation replaces the last move played.
+        self.pos = self.prev.clone();
+        Skip(false)
+    }
+
+    fn end_variation(&mut self) {
+        if let Some((pos, prev, broken)) = self.stack.pop() {
+            self.pos = pos;
+            self.prev = prev;
+            self.broken = broken;
+        }
+    }
+
+    fn end_game(&mut self) -> Self::Result {
+        if !self.variant && !self.broken {
+            self.end = Some(self.pos.clone());
+        }
+    }
+}
+
+fn replay(pgn: &str, start: Chess) -> Replay {
+    let mut replay = Replay::new(start);
+    let mut reader = BufferedReader::new_cursor(pgn.as_bytes());
+    match reader.read_game(&mut replay) {
+        Ok(Some(())) => {}
+        Ok(None) => replay
+            .errors
+            .push(FieldError::new("moves", "no game found")),
+        Err(e) => replay
+            .errors
+            .push(FieldError::new("moves", format!("unreadable PGN: {}", e))),
+    }
+    replay
+}
+
+/// Problems with a game edited in a database.
+pub(crate) fn validate_update(update: &UpdateGame) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    let start = if update.fen.trim().is_empty() {
+        Chess::default()
+    } else {
+        parse_start(&update.fen).unwrap_or_else(|e| {
+            errors.push(FieldError::new("fen", e));
+            Chess::default()
+        })
+    };
+    if let Some(e) = update.date.as_deref().and_then(check_date) {
+        errors.push(FieldError::new("date", e));
+    }
+    if let Some(e) = update.time.as_deref().and_then(check_time) {
+        errors.push(FieldError::new("time", e));
+    }
+    if let Some(e) = update.white_elo.and_then(check_elo) {
+        errors.push(FieldError::new("white_elo", e));
+    }
+    if let Some(e) = update.black_elo.and_then(check_elo) {
+        errors.push(FieldError::new("black_elo", e));
+    }
+
+    let replay = replay(&update.moves, start);
+    errors.extend(replay.errors);
+    if let Some(e) = replay
+        .end
+        .as_ref()
+        .and_then(|end| check_result(&update.result.to_string(), end))
+    {
+        errors.push(FieldError::new("result", e));
+    }
+    errors
+}
+
+/// Problems with a game written to a PGN file, reported on header names and `moves`.
+pub(crate) fn validate_pgn(pgn: &str) -> Vec<FieldError> {
+    let replay = replay(pgn, Chess::default());
+    let mut errors = replay.errors.clone();
+
+    for key in ["Date", "UTCDate"] {
+        if let Some(e) = replay.header_value(key).and_then(check_date) {
+            errors.push(FieldError::new(key, e));
+        }
+    }
+    for key in ["Time", "UTCTime"] {
+        if let Some(e) = replay.header_value(key).and_then(check_time) {
+            errors.push(FieldError::new(key, e));
+        }
+    }
+    for key in ["WhiteElo", "BlackElo"] {
+        if let Some(e) = replay.header_value(key).and_then(check_elo_header) {
+            errors.push(FieldError::new(key, e));
+        }
+    }
+    if let Some(result) = replay.header_value("Result") {
+        if !RESULTS.contains(&result) {
+            errors.push(FieldError::new(
+                "Result",
+                format!("{} is not a game result", result),
+            ));
+        } else if let Some(e) = replay
+            .end
+            .as_ref()
+            .and_then(|end| check_result(result, end))
+        {
+            errors.push(FieldError::new("Result", e));
+        }
+    }
+    errors
+}
+
+/// Refuses a game with any problem.
+pub(crate) fn ensure_valid(errors: Vec<FieldError>) -> Result<()> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidGame(errors))
+    }
+}
+
+/// Problems `update_game` would refuse the update for, empty when it can be saved.
+#[tauri::command]
+#[specta::specta]
+pub fn validate_game_update(update: UpdateGame) -> Result<Vec<FieldError>> {
+    Ok(validate_update(&update))
+}
+
+/// Problems `write_game` would refuse the game for, empty when it can be saved.
+#[tauri::command]
+#[specta::specta]
+pub fn validate_pgn_game(pgn: String) -> Result<Vec<FieldError>> {
+    Ok(validate_pgn(&pgn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_date() {
+        assert_eq!(check_date("2024.02.29"), None);
+        assert_eq!(check_date("2024.??.??"), None);
+        assert_eq!(check_date("????.??.??"), None);
+        assert!(check_date("2023.02.29").is_some());
+        assert!(check_date("2024.13.??").is_some());
+        assert!(check_date("2024-01-01").is_some());
+        assert!(check_date("24.1.1").is_some());
+    }
+
+    #[test]
+    fn test_check_elo_header() {
+        assert_eq!(check_elo_header("2750"), None);
+        assert_eq!(check_elo_header("-"), None);
+        assert!(check_elo_header("99999").is_some());
+        assert!(check_elo_header("strong").is_some());
+    }
+
+    #[test]
+    fn test_validate_pgn_moves() {
+        let pgn = "[Event \"?\"]\n\n1. e4 e5 (1... Nf6 2. Nc3 Ke3) 2. Nf3 *";
+        assert_eq!(
+            validate_pgn(pgn),
+            vec![FieldError::new("moves", "illegal move 2...Ke3")]
+        );
+        assert!(validate_pgn("1. e4 e5 2. Nf3 Nc6 *").is_empty());
+    }
+
+    #[test]
+    fn test_validate_pgn_result() {
+        let mate = "[Result \"1/2-1/2\"]\n\n1. f3 e5 2. g4 Qh4# 1/2-1/2";
+        assert_eq!(
+            validate_pgn(mate),
+            vec![FieldError::new(
+                "Result",
+                "the final position ends the game as 0-1, not 1/2-1/2"
+            )]
+        );
+        let resigned = "[Result \"1-0\"]\n\n1. e4 e5 1-0";
+        assert!(validate_pgn(resigned).is_empty());
+    }
+
+    #[test]
+    fn test_validate_pgn_from_position() {
+        let pgn = "[FEN \"4k3/8/8/8/8/8/8/4K2R w K - 0 1\"]\n\n1. O-O Kd7 *";
+        assert!(validate_pgn(pgn).is_empty());
+        let pgn = "[FEN \"4k3/8/8/8/8/8/8/4K2R w K - 0 1\"]\n\n1. e4 *";
+        assert_eq!(validate_pgn(pgn).len(), 1);
+    }
+}
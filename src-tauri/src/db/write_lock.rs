@@ -0,0 +1,195 @@
+//! One writer at a time per database file.
+//!
+//! All windows of the app share one `AppState`, so two windows can import into, edit or delete
+//! the same database at once. SQLite only waits for other writers up to its busy timeout, and
+//! imports run with the journal turned off, where a write from another connection can leave the
+//! file corrupt. Commands that modify a database take its lock from `WriteLocks` first, which
+//! queues them behind the running write instead; reads do not take it.
+//!
+//! Every release of a lock taken with `lock` also bumps the database's generation, which caches
+//! built from its games, such as the position filters, compare to tell that they are stale.
+//! `try_lock` is for opportunistic writes that leave the games alone and does not bump it.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use dashmap::DashMap;
+use tokio::sync::OwnedMutexGuard;
+
+use crate::AppState;
+
+#[derive(Debug, Default)]
+struct DatabaseLock {
+    write: Arc<tokio::sync::Mutex<()>>,
+    /// Operation holding `write`, for the log of the ones waiting on it.
+    holder: Mutex<Option<&'static str>>,
+    /// Number of `lock` guards released so far.
+    generation: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+pub struct WriteLocks {
+    locks: DashMap<PathBuf, Arc<DatabaseLock>>,
+}
+
+/// Exclusive write access to a database, released when dropped.
+#[derive(Debug)]
+pub struct WriteGuard {
+    lock: Arc<DatabaseLock>,
+    _guard: OwnedMutexGuard<()>,
+    /// Whether releasing the guard marks the games as changed.
+    bumps_generation: bool,
+}
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        *self.lock.holder.lock().unwrap() = None;
+        if self.bumps_generation {
+            self.lock.generation.fetch_add(1, Ordering::Release);
+        }
+    }
+}
+
+/// The same file reached through different paths shares one lock. A file that does not exist yet,
+/// such as a database an import is about to create, is keyed by its canonical directory so the
+/// key stays the same once it is created.
+fn lock_key(path: &Path) -> PathBuf {
+    if let Ok(path) = std::fs::canonicalize(path) {
+        return path;
+    }
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return path.to_path_buf();
+    };
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    std::fs::canonicalize(parent)
+        .map(|parent| parent.join(name))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl WriteLocks {
+    fn get(&self, path: &Path) -> Arc<DatabaseLock> {
+        self.locks.entry(lock_key(path)).or_default().clone()
+    }
+
+    fn acquired(
+        lock: Arc<DatabaseLock>,
+        guard: OwnedMutexGuard<()>,
+        operation: &'static str,
+        bumps_generation: bool,
+    ) -> WriteGuard {
+        *lock.holder.lock().unwrap() = Some(operation);
+        WriteGuard {
+            lock,
+            _guard: guard,
+            bumps_generation,
+        }
+    }
+
+    /// Write access to `path` if no other operation is writing to it, for writes that do not
+    /// change the games, such as caches of search results.
+    pub fn try_lock(&self, path: &Path, operation: &'static str) -> Option<WriteGuard> {
+        let lock = self.get(path);
+        let guard = lock.write.clone().try_lock_owned().ok()?;
+        Some(Self::acquired(lock, guard, operation, false))
+    }
+
+    /// Waits until no other operation is writing to `path`.
+    pub async fn lock(&self, path: &Path, operation: &'static str) -> WriteGuard {
+        let lock = self.get(path);
+        if let Ok(guard) = lock.write.clone().try_lock_owned() {
+            return Self::acquired(lock, guard, operation, true);
+        }
+        let holder = *lock.holder.lock().unwrap();
+        log::info!(
+            "{} waits for {} to finish writing to {}",
+            operation,
+            holder.unwrap_or("another operation"),
+            path.display()
+        );
+        let guard = lock.write.clone().lock_owned().await;
+        Self::acquired(lock, guard, operation, true)
+    }
+
+    /// Number of writes to `path` finished so far. A cache built from its games is stale once
+    /// this changes.
+    pub fn generation(&self, path: &Path) -> u64 {
+        self.locks
+            .get(&lock_key(path))
+            .map_or(0, |lock| lock.generation.load(Ordering::Acquire))
+    }
+
+    /// Operation currently writing to `path`, if any.
+    pub fn writer(&self, path: &Path) -> Option<&'static str> {
+        let lock = self.locks.get(&lock_key(path))?;
+        let holder = *lock.holder.lock().unwrap();
+        holder
+    }
+}
+
+/// Operation currently writing to the database `file`, so a window can show that it is busy.
+#[tauri::command]
+#[specta::specta]
+pub fn get_database_writer(file: PathBuf, state: tauri::State<'_, AppState>) -> Option<String> {
+    state.db_writes.writer(&file).map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_locks() {
+        let locks = WriteLocks::default();
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("games.db3");
+        std::fs::write(&file, b"").unwrap();
+        let other_spelling = dir.path().join(".").join("games.db3");
+
+        let guard = locks.try_lock(&file, "import").unwrap();
+        assert_eq!(locks.writer(&other_spelling), Some("import"));
+        assert!(locks.try_lock(&other_spelling, "update_game").is_none());
+        assert!(locks
+            .try_lock(&dir.path().join("other.db3"), "update_game")
+            .is_some());
+
+        drop(guard);
+        assert_eq!(locks.writer(&file), None);
+        assert!(locks.try_lock(&other_spelling, "update_game").is_some());
+    }
+
+    #[test]
+    fn test_lock_before_file_exists() {
+        let locks = WriteLocks::default();
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("new.db3");
+
+        let _guard = locks.try_lock(&file, "import").unwrap();
+        std::fs::write(&file, b"").unwrap();
+        let other_spelling = dir.path().join(".").join("new.db3");
+        assert_eq!(locks.writer(&other_spelling), Some("import"));
+        assert!(locks.try_lock(&other_spelling, "update_game").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generation() {
+        let locks = WriteLocks::default();
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("games.db3");
+        std::fs::write(&file, b"").unwrap();
+
+        assert_eq!(locks.generation(&file), 0);
+        drop(locks.try_lock(&file, "search_position"));
+        assert_eq!(locks.generation(&file), 0);
+        drop(locks.lock(&file, "update_game").await);
+        assert_eq!(locks.generation(&dir.path().join(".").join("games.db3")), 1);
+    }
+}
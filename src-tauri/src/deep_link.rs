@@ -0,0 +1,196 @@
+//! Handler of `pawnappetit://` links.
+//!
+//! Links let pages outside the app (blogs, Discord, forums) open something directly in it:
+//!
+//! - `pawnappetit://fen/<FEN>`: a position. Spaces may be written as `_`, like in Lichess
+//!   analysis URLs, or percent-encoded.
+//! - `pawnappetit://game/<id>`: a Lichess game, by its 8 character id. The 12 character form
+//!   that includes the player's side is accepted too.
+//! - `pawnappetit://puzzle/<id>`: a Lichess puzzle.
+//!
+//! The scheme is registered through the deep link plugin (see `tauri.conf.json`). Every link is
+//! validated before it reaches the frontend as an `OpenIntent` event; invalid links are logged
+//! and dropped. Links the app was started with arrive before the frontend listens, so they are
+//! kept until it asks for them with `take_pending_open_intents`.
+
+use std::sync::Mutex;
+
+use reqwest::Url;
+use serde::Serialize;
+use shakmaty::{fen::Fen, CastlingMode, Chess};
+use specta::Type;
+use tauri_specta::Event;
+
+use crate::error::Result;
+
+pub const SCHEME: &str = "pawnappetit";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum OpenTarget {
+    Position { fen: String },
+    LichessGame { id: String },
+    LichessPuzzle { id: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type, Event)]
+pub struct OpenIntent {
+    pub url: String,
+    pub target: OpenTarget,
+}
+
+static PENDING: Mutex<Vec<OpenIntent>> = Mutex::new(Vec::new());
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn is_id(text: &str, len: usize) -> bool {
+    text.len() == len && text.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Validates a link and tells what it opens.
+pub fn parse_link(link: &str) -> std::result::Result<OpenTarget, String> {
+    let url = Url::parse(link).map_err(|e| format!("invalid link: {}", e))?;
+    if url.scheme() != SCHEME {
+        return Err(format!("unsupported scheme: {}", url.scheme()));
+    }
+    let kind = url.host_str().unwrap_or_default();
+    let payload = url.path().trim_start_matches('/').trim_end_matches('/');
+
+    match kind {
+        "fen" => {
+            let fen = percent_decode(payload)
+                .ok_or("invalid FEN encoding")?
+                .replace('_', " ");
+            let parsed: Fen = fen
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid FEN: {}", e))?;
+            parsed
+                .into_position::<Chess>(CastlingMode::Chess960)
+                .map_err(|e| format!("invalid position: {}", e))?;
+            Ok(OpenTarget::Position {
+                fen: fen.trim().to_string(),
+            })
+        }
+        "game" if is_id(payload, 8) || is_id(payload, 12) => Ok(OpenTarget::LichessGame {
+            id: payload[..8].to_string(),
+        }),
+        "puzzle" if is_id(payload, 5) => Ok(OpenTarget::LichessPuzzle {
+            id: payload.to_string(),
+        }),
+        "game" | "puzzle" => Err(format!("invalid {} id: {}", kind, payload)),
+        _ => Err(format!("unknown link type: {}", kind)),
+    }
+}
+
+fn intent(link: &str) -> Option<OpenIntent> {
+    match parse_link(link) {
+        Ok(target) => Some(OpenIntent {
+            url: link.to_string(),
+            target,
+        }),
+        Err(e) => {
+            log::warn!("Ignoring link {}: {}", link, e);
+            None
+        }
+    }
+}
+
+/// Forwards links received while the app runs to the frontend.
+fn handle_links<'a>(app: &tauri::AppHandle, links: impl IntoIterator<Item = &'a str>) {
+    for intent in links.into_iter().filter_map(intent) {
+        log::info!("Opening link {}", intent.url);
+        if let Err(e) = intent.emit(app) {
+            log::warn!("Failed to emit open intent: {}", e);
+        }
+    }
+}
+
+/// Keeps the links the app was started with until the frontend asks for them.
+fn queue_startup_links<'a>(links: impl IntoIterator<Item = &'a str>) {
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    pending.extend(links.into_iter().filter_map(intent));
+}
+
+/// Collects the links the app was started with and listens for the ones opened later.
+pub fn init(app: &tauri::App) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    use tauri_plugin_deep_link::DeepLinkExt;
+
+    // Installers register the scheme; AppImages and development builds have to do it at runtime.
+    #[cfg(any(target_os = "linux", all(debug_assertions, target_os = "windows")))]
+    if let Err(e) = app.deep_link().register_all() {
+        log::warn!("Failed to register the {} scheme: {}", SCHEME, e);
+    }
+
+    if let Some(urls) = app.deep_link().get_current()? {
+        queue_startup_links(urls.iter().map(|url| url.as_str()));
+    }
+    let handle = app.handle().clone();
+    app.deep_link().on_open_url(move |event| {
+        handle_links(&handle, event.urls().iter().map(|url| url.as_str()));
+    });
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn take_pending_open_intents() -> Result<Vec<OpenIntent>> {
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(std::mem::take(&mut *pending))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_position_link() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let expected = Ok(OpenTarget::Position {
+            fen: start.to_string(),
+        });
+        assert_eq!(
+            parse_link(&format!("pawnappetit://fen/{}", start.replace(' ', "_"))),
+            expected
+        );
+        assert_eq!(
+            parse_link(&format!("pawnappetit://fen/{}", start.replace(' ', "%20"))),
+            expected
+        );
+        assert!(parse_link("pawnappetit://fen/8/8/8/8_w_-_-").is_err());
+    }
+
+    #[test]
+    fn test_parse_lichess_links() {
+        assert_eq!(
+            parse_link("pawnappetit://game/q7ZvsdUFb1Wq"),
+            Ok(OpenTarget::LichessGame {
+                id: "q7ZvsdUF".to_string()
+            })
+        );
+        assert_eq!(
+            parse_link("pawnappetit://puzzle/K69di/"),
+            Ok(OpenTarget::LichessPuzzle {
+                id: "K69di".to_string()
+            })
+        );
+        assert!(parse_link("pawnappetit://game/../../etc").is_err());
+        assert!(parse_link("pawnappetit://settings/x").is_err());
+        assert!(parse_link("https://lichess.org/q7ZvsdUF").is_err());
+    }
+}
@@ -87,6 +87,12 @@ pub enum Error {
     #[error("Search stopped")]
     SearchStopped,
 
+    #[error("Job cancelled")]
+    JobCancelled,
+
+    #[error("Automatic background work is disabled in safe mode")]
+    SafeMode,
+
     #[error("Missing reference database")]
     MissingReferenceDatabase,
 
@@ -111,6 +117,12 @@ pub enum Error {
     #[error("Package manager error: {0}")]
     PackageManager(String),
 
+    #[error("HTTP error: {0}")]
+    HttpStatus(u16),
+
+    #[error("Invalid game: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    InvalidGame(Vec<crate::db::FieldError>),
+
     #[allow(dead_code)]
     #[error("Engine timeout")]
     EngineTimeout,
@@ -4,16 +4,42 @@ use std::{
 };
 
 use bincode::{config, Decode, Encode};
+use chrono::{Duration, NaiveDate, SecondsFormat, Utc};
+use diesel::{connection::SimpleConnection, prelude::*, sqlite::SqliteConnection};
 use quick_xml::de::from_reader;
 use serde::{Deserialize, Deserializer, Serialize};
 use specta::Type;
 use strsim::{jaro_winkler, sorensen_dice};
 use tauri::{path::BaseDirectory, Manager};
-use tauri_specta::Event;
 
-use crate::{error::Error, fs::DownloadProgress};
+use crate::{error::Error, fs::DownloadProgress, http};
 use crate::{fs::download_file, AppState};
 
+/// Profiles change rarely, so repeated lookups within a session reuse the fetched page.
+const FIDE_PROFILE_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+/// FIDE publishes one rating list a month, so a stored history is refreshed at most this often.
+const RATING_HISTORY_MAX_AGE_DAYS: i64 = 7;
+
+diesel::table! {
+    fide_rating_history (fide_id, period) {
+        fide_id -> Integer,
+        period -> Text,
+        standard -> Nullable<Integer>,
+        standard_games -> Nullable<Integer>,
+        rapid -> Nullable<Integer>,
+        rapid_games -> Nullable<Integer>,
+        blitz -> Nullable<Integer>,
+        blitz_games -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    fide_history_updates (fide_id) {
+        fide_id -> Integer,
+        updated_at -> Text,
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Type, Clone, Decode, Encode)]
 pub struct FidePlayer {
     pub fideid: u32,
@@ -114,7 +140,7 @@ pub async fn download_fide_db(
         id: "fide_db".to_string(),
         finished: true,
     }
-    .emit(&app)?;
+    .report(&app)?;
 
     remove_file(&xml_path)?;
 
@@ -166,26 +192,12 @@ pub async fn find_fide_player(
 #[specta::specta]
 pub async fn fetch_fide_profile_html(fide_id: String) -> Result<String, String> {
     let url = format!("https://ratings.fide.com/profile/{}", fide_id);
-    
-    let client = reqwest::Client::builder()
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-    
-    let response = client
-        .get(&url)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to fetch FIDE profile: {}", e))?;
-    
-    if !response.status().is_success() {
-        return Err(format!("HTTP error: {}", response.status()));
-    }
-    
-    let html = response
+
+    let html = http::Request::get(url)
+        .cache_for(FIDE_PROFILE_CACHE_TTL)
         .text()
         .await
-        .map_err(|e| format!("Failed to read response: {}", e))?;
+        .map_err(|e| format!("Failed to fetch FIDE profile: {}", e))?;
     
     Ok(html)
 }
@@ -252,37 +264,12 @@ pub async fn save_fide_photo(fide_id: String, photo_data: String, app: tauri::Ap
     } else if photo_data.starts_with("http") {
         
         // Download from URL
-        let client = reqwest::Client::builder()
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
+        let bytes = http::Request::get(&photo_data)
             .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .map_err(|e| {
-                let err_msg = format!("Failed to create HTTP client: {}", e);
-                error!("save_fide_photo: {}", err_msg);
-                err_msg
-            })?;
-        
-        let response = client
-            .get(&photo_data)
-            .send()
-            .await
-            .map_err(|e| {
-                let err_msg = format!("Failed to download photo: {}", e);
-                error!("save_fide_photo: {}", err_msg);
-                err_msg
-            })?;
-        
-        if !response.status().is_success() {
-            let err_msg = format!("Photo download failed with status: {}", response.status());
-            error!("save_fide_photo: {}", err_msg);
-            return Err(err_msg);
-        }
-        
-        let bytes = response
             .bytes()
             .await
             .map_err(|e| {
-                let err_msg = format!("Failed to read photo bytes: {}", e);
+                let err_msg = format!("Failed to download photo: {}", e);
                 error!("save_fide_photo: {}", err_msg);
                 err_msg
             })?;
@@ -311,3 +298,329 @@ pub async fn save_fide_photo(fide_id: String, photo_data: String, app: tauri::Ap
     
     Ok(path_str)
 }
+
+/// Ratings of a player in one monthly FIDE rating list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type, Queryable)]
+#[serde(rename_all = "camelCase")]
+pub struct FideRatingPeriod {
+    /// First day of the month of the list, `YYYY-MM-DD`.
+    pub period: String,
+    pub standard: Option<i32>,
+    pub standard_games: Option<i32>,
+    pub rapid: Option<i32>,
+    pub rapid_games: Option<i32>,
+    pub blitz: Option<i32>,
+    pub blitz_games: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum FideTimeControl {
+    Standard,
+    Rapid,
+    Blitz,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RatingPoint {
+    pub date: String,
+    pub rating: i32,
+    /// Rated games played in the period, when the list tells.
+    pub games: Option<i32>,
+}
+
+/// Ratings of one time control, oldest first, for a chart.
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RatingSeries {
+    pub time_control: FideTimeControl,
+    pub points: Vec<RatingPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct FideRatingHistory {
+    pub fide_id: u32,
+    /// RFC 3339 time the history was last downloaded.
+    pub updated_at: Option<String>,
+    /// Rating lists the player appears in, oldest first.
+    pub periods: Vec<FideRatingPeriod>,
+    /// One series per time control the player has a rating in.
+    pub series: Vec<RatingSeries>,
+}
+
+fn get_fide_history_db(app: &tauri::AppHandle) -> Result<SqliteConnection, Error> {
+    let db_path = app
+        .path()
+        .resolve("fide_history.db3", BaseDirectory::AppData)?;
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut conn = SqliteConnection::establish(&db_path.to_string_lossy())?;
+    conn.batch_execute(
+        r#"
+        CREATE TABLE IF NOT EXISTS fide_rating_history (
+            fide_id INTEGER NOT NULL,
+            period TEXT NOT NULL,
+            standard INTEGER,
+            standard_games INTEGER,
+            rapid INTEGER,
+            rapid_games INTEGER,
+            blitz INTEGER,
+            blitz_games INTEGER,
+            PRIMARY KEY (fide_id, period)
+        ) WITHOUT ROWID;
+
+        CREATE TABLE IF NOT EXISTS fide_history_updates (
+            fide_id INTEGER PRIMARY KEY,
+            updated_at TEXT NOT NULL
+        );
+        "#,
+    )?;
+    Ok(conn)
+}
+
+/// Text of the cells of a table row, without markup.
+fn row_cells(row: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut rest = row;
+    while let Some(start) = rest.find("<td") {
+        rest = &rest[start..];
+        let Some(open_end) = rest.find('>') else {
+            break;
+        };
+        let end = rest.find("</td>").unwrap_or(rest.len());
+        let inner = &rest[(open_end + 1).min(end)..end];
+        let mut text = String::new();
+        let mut in_tag = false;
+        for c in inner.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' => in_tag = false,
+                _ if !in_tag => text.push(c),
+                _ => {}
+            }
+        }
+        cells.push(text.replace("&nbsp;", " ").trim().to_string());
+        rest = &rest[end..];
+        if rest.len() > 5 {
+            rest = &rest[5..];
+        } else {
+            break;
+        }
+    }
+    cells
+}
+
+/// Parses the rating history table of a FIDE profile chart page. Its rows hold the period as
+/// `2024-Jan`, followed by the rating and games of the standard, rapid and blitz lists; a
+/// player missing from a list has empty cells.
+fn parse_rating_history(html: &str) -> Vec<FideRatingPeriod> {
+    let number = |cell: Option<&String>| cell.and_then(|c| c.parse::<i32>().ok());
+    let rating = |cell: Option<&String>| number(cell).filter(|&r| r > 0);
+    let mut periods: Vec<FideRatingPeriod> = html
+        .split("<tr")
+        .skip(1)
+        .filter_map(|row| {
+            let cells = row_cells(row);
+            let period =
+                NaiveDate::parse_from_str(&format!("{}-01", cells.first()?), "%Y-%b-%d").ok()?;
+            Some(FideRatingPeriod {
+                period: period.format("%Y-%m-%d").to_string(),
+                standard: rating(cells.get(1)),
+                standard_games: number(cells.get(2)),
+                rapid: rating(cells.get(3)),
+                rapid_games: number(cells.get(4)),
+                blitz: rating(cells.get(5)),
+                blitz_games: number(cells.get(6)),
+            })
+        })
+        .collect();
+    periods.sort_by(|a, b| a.period.cmp(&b.period));
+    periods.dedup_by(|a, b| a.period == b.period);
+    periods
+}
+
+fn rating_series(periods: &[FideRatingPeriod]) -> Vec<RatingSeries> {
+    let series = |time_control, column: fn(&FideRatingPeriod) -> (Option<i32>, Option<i32>)| {
+        let points = periods
+            .iter()
+            .filter_map(|p| {
+                let (rating, games) = column(p);
+                Some(RatingPoint {
+                    date: p.period.clone(),
+                    rating: rating?,
+                    games,
+                })
+            })
+            .collect();
+        RatingSeries {
+            time_control,
+            points,
+        }
+    };
+    [
+        series(FideTimeControl::Standard, |p| {
+            (p.standard, p.standard_games)
+        }),
+        series(FideTimeControl::Rapid, |p| (p.rapid, p.rapid_games)),
+        series(FideTimeControl::Blitz, |p| (p.blitz, p.blitz_games)),
+    ]
+    .into_iter()
+    .filter(|series| !series.points.is_empty())
+    .collect()
+}
+
+fn store_rating_history(
+    db: &mut SqliteConnection,
+    fide_id: i32,
+    periods: &[FideRatingPeriod],
+) -> Result<String, Error> {
+    let updated_at = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    db.transaction(|db| {
+        diesel::delete(fide_rating_history::table.filter(fide_rating_history::fide_id.eq(fide_id)))
+            .execute(db)?;
+        let rows: Vec<_> = periods
+            .iter()
+            .map(|p| {
+                (
+                    fide_rating_history::fide_id.eq(fide_id),
+                    fide_rating_history::period.eq(&p.period),
+                    fide_rating_history::standard.eq(p.standard),
+                    fide_rating_history::standard_games.eq(p.standard_games),
+                    fide_rating_history::rapid.eq(p.rapid),
+                    fide_rating_history::rapid_games.eq(p.rapid_games),
+                    fide_rating_history::blitz.eq(p.blitz),
+                    fide_rating_history::blitz_games.eq(p.blitz_games),
+                )
+            })
+            .collect();
+        diesel::insert_into(fide_rating_history::table)
+            .values(&rows)
+            .execute(db)?;
+        diesel::replace_into(fide_history_updates::table)
+            .values((
+                fide_history_updates::fide_id.eq(fide_id),
+                fide_history_updates::updated_at.eq(&updated_at),
+            ))
+            .execute(db)?;
+        Ok::<_, Error>(())
+    })?;
+    Ok(updated_at)
+}
+
+/// Monthly standard, rapid and blitz ratings of a player. The history is downloaded from the
+/// player's FIDE profile and kept in `fide_history.db3`, so charts keep working offline; it is
+/// downloaded again when it is older than a week or `refresh` is set.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_fide_rating_history(
+    fide_id: u32,
+    refresh: Option<bool>,
+    app: tauri::AppHandle,
+) -> Result<FideRatingHistory, Error> {
+    let id = fide_id as i32;
+    let db = &mut get_fide_history_db(&app)?;
+    let mut updated_at: Option<String> = fide_history_updates::table
+        .find(id)
+        .select(fide_history_updates::updated_at)
+        .first(db)
+        .optional()?;
+
+    let stale = updated_at
+        .as_deref()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .is_none_or(|t| {
+            Utc::now() - t.with_timezone(&Utc) > Duration::days(RATING_HISTORY_MAX_AGE_DAYS)
+        });
+    if refresh.unwrap_or(false) || stale {
+        let url = format!("https://ratings.fide.com/profile/{}/chart", fide_id);
+        match http::Request::get(url).text().await {
+            Ok(html) => {
+                let periods = parse_rating_history(&html);
+                if periods.is_empty() && updated_at.is_some() {
+                    log::warn!(
+                        "FIDE profile {} has no rating history, keeping the stored one",
+                        fide_id
+                    );
+                } else {
+                    updated_at = Some(store_rating_history(db, id, &periods)?);
+                }
+            }
+            // Without a connection the stored history is still worth showing
+            Err(e) if updated_at.is_some() => {
+                log::warn!("Failed to update the rating history of {}: {}", fide_id, e);
+            }
+            Err(e) => {
+                return Err(Error::PackageManager(format!(
+                    "Failed to fetch the FIDE rating history: {}",
+                    e
+                )))
+            }
+        }
+    }
+
+    let periods: Vec<FideRatingPeriod> = fide_rating_history::table
+        .filter(fide_rating_history::fide_id.eq(id))
+        .select((
+            fide_rating_history::period,
+            fide_rating_history::standard,
+            fide_rating_history::standard_games,
+            fide_rating_history::rapid,
+            fide_rating_history::rapid_games,
+            fide_rating_history::blitz,
+            fide_rating_history::blitz_games,
+        ))
+        .order(fide_rating_history::period)
+        .load(db)?;
+    Ok(FideRatingHistory {
+        fide_id,
+        updated_at,
+        series: rating_series(&periods),
+        periods,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rating_history() {
+        let html = r#"
+            <table class="profile-table profile-table_chart-table">
+            <tr><th>Period</th><th>RTNG</th><th>GMS</th><th>RPD</th><th>GMS</th><th>BLZ</th><th>GMS</th></tr>
+            <tr><td width=90>2024-Feb</td><td>2830</td><td>9</td><td>2823</td><td>0</td><td>&nbsp;</td><td></td></tr>
+            <tr><td width=90>2024-Jan</td><td><b>2831</b></td><td>0</td><td>2820</td><td>11</td><td>2886</td><td>21</td></tr>
+            </table>"#;
+        let periods = parse_rating_history(html);
+        assert_eq!(periods.len(), 2);
+        assert_eq!(
+            periods[0],
+            FideRatingPeriod {
+                period: "2024-01-01".to_string(),
+                standard: Some(2831),
+                standard_games: Some(0),
+                rapid: Some(2820),
+                rapid_games: Some(11),
+                blitz: Some(2886),
+                blitz_games: Some(21),
+            }
+        );
+        assert_eq!(periods[1].blitz, None);
+
+        let series = rating_series(&periods);
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].time_control, FideTimeControl::Standard);
+        assert_eq!(
+            series[0]
+                .points
+                .iter()
+                .map(|p| p.rating)
+                .collect::<Vec<_>>(),
+            vec![2831, 2830]
+        );
+        assert_eq!(series[2].points.len(), 1);
+    }
+}
@@ -0,0 +1,332 @@
+//! Display names for technical identifiers such as puzzle themes and opening tags.
+//!
+//! Names come from bundled per-language catalogs in `data/friendly_names`, with user overrides
+//! from `friendly_names.json` in the app config directory taking precedence. A lookup tries the
+//! overrides and the catalog of the requested language, then the English catalog, and finally
+//! derives a name from the identifier itself (`queenRookEndgame` -> `Queen & Rook Endgame`) using
+//! the English catalog's fix-ups. Puzzle databases store English names; other languages are for
+//! display through `get_friendly_names`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::RwLock,
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+
+use crate::error::Error;
+
+const OVERRIDES_FILE: &str = "friendly_names.json";
+
+/// Language used when a catalog has no entry for the requested one.
+const FALLBACK_LOCALE: &str = "en";
+
+const BUNDLED_CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../data/friendly_names/en.json")),
+    ("es", include_str!("../data/friendly_names/es.json")),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum FriendlyNameKind {
+    Theme,
+    OpeningTag,
+}
+
+/// Names of one language, keyed by lowercase identifier.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Catalog {
+    #[serde(default)]
+    themes: HashMap<String, String>,
+    #[serde(default)]
+    opening_tags: HashMap<String, String>,
+    /// Replacements applied to names derived from identifiers, e.g. `End Game` -> `Endgame`.
+    #[serde(default, skip_serializing_if = "Fixups::is_empty")]
+    fixups: Fixups,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Fixups {
+    #[serde(default)]
+    themes: Vec<(String, String)>,
+    #[serde(default)]
+    opening_tags: Vec<(String, String)>,
+}
+
+impl Fixups {
+    fn is_empty(&self) -> bool {
+        self.themes.is_empty() && self.opening_tags.is_empty()
+    }
+
+    fn get(&self, kind: FriendlyNameKind) -> &[(String, String)] {
+        match kind {
+            FriendlyNameKind::Theme => &self.themes,
+            FriendlyNameKind::OpeningTag => &self.opening_tags,
+        }
+    }
+}
+
+impl Catalog {
+    fn names(&self, kind: FriendlyNameKind) -> &HashMap<String, String> {
+        match kind {
+            FriendlyNameKind::Theme => &self.themes,
+            FriendlyNameKind::OpeningTag => &self.opening_tags,
+        }
+    }
+
+    fn names_mut(&mut self, kind: FriendlyNameKind) -> &mut HashMap<String, String> {
+        match kind {
+            FriendlyNameKind::Theme => &mut self.themes,
+            FriendlyNameKind::OpeningTag => &mut self.opening_tags,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FriendlyNames {
+    bundled: HashMap<String, Catalog>,
+    overrides: HashMap<String, Catalog>,
+}
+
+impl FriendlyNames {
+    fn bundled() -> Self {
+        let bundled = BUNDLED_CATALOGS
+            .iter()
+            .filter_map(|(locale, json)| match serde_json::from_str::<Catalog>(json) {
+                Ok(catalog) => Some((locale.to_string(), catalog)),
+                Err(e) => {
+                    log::error!("Invalid bundled friendly names for {}: {}", locale, e);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            bundled,
+            overrides: HashMap::new(),
+        }
+    }
+
+    fn lookup(&self, kind: FriendlyNameKind, key: &str, locale: &str) -> Option<&String> {
+        let key = key.to_lowercase();
+        [
+            self.overrides.get(locale),
+            self.bundled.get(locale),
+            self.overrides.get(FALLBACK_LOCALE),
+            self.bundled.get(FALLBACK_LOCALE),
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(|catalog| catalog.names(kind).get(&key))
+    }
+
+    fn name(&self, kind: FriendlyNameKind, key: &str, locale: &str) -> String {
+        match self.lookup(kind, key, locale) {
+            Some(name) => name.clone(),
+            None => {
+                let fixups = self
+                    .bundled
+                    .get(FALLBACK_LOCALE)
+                    .map(|catalog| catalog.fixups.get(kind))
+                    .unwrap_or_default();
+                humanize(key, fixups)
+            }
+        }
+    }
+
+    /// Every known name of `kind` in `locale`, falling back to English for missing entries.
+    fn all(&self, kind: FriendlyNameKind, locale: &str) -> HashMap<String, String> {
+        let mut names = HashMap::new();
+        for catalog in [
+            self.bundled.get(FALLBACK_LOCALE),
+            self.overrides.get(FALLBACK_LOCALE),
+            self.bundled.get(locale),
+            self.overrides.get(locale),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            names.extend(catalog.names(kind).iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        names
+    }
+}
+
+static FRIENDLY_NAMES: Lazy<RwLock<FriendlyNames>> = Lazy::new(|| RwLock::new(FriendlyNames::bundled()));
+
+/// Language part of a locale code, e.g. `es` for `es-ES`.
+fn language(locale: &str) -> String {
+    locale.split(['-', '_']).next().unwrap_or_default().to_lowercase()
+}
+
+/// Derive a display name from an identifier by splitting camelCase, digits, `-` and `_` into
+/// capitalized words, then applying `fixups`.
+fn humanize(key: &str, fixups: &[(String, String)]) -> String {
+    let mut result = String::new();
+    let mut chars = key.chars().peekable();
+    let mut prev_was_lower = false;
+    let mut prev_was_upper = false;
+    let mut prev_was_digit = false;
+    let mut word_start = true;
+
+    while let Some(ch) = chars.next() {
+        let is_upper = ch.is_uppercase();
+        let is_lower = ch.is_lowercase();
+        let is_digit = ch.is_ascii_digit();
+
+        // Add space before uppercase if previous was lowercase or digit
+        if is_upper && (prev_was_lower || prev_was_digit) && !result.is_empty() {
+            result.push(' ');
+            word_start = true;
+        }
+        // Add space before lowercase if we have multiple uppercase letters in a row (like "QueenRook")
+        else if is_lower && prev_was_upper {
+            if let Some(&next_ch) = chars.peek() {
+                if next_ch.is_uppercase() {
+                    result.push(' ');
+                    word_start = true;
+                }
+            }
+        }
+        // Add space before digit if previous was letter
+        else if is_digit && (prev_was_lower || prev_was_upper) && !result.is_empty() {
+            result.push(' ');
+            word_start = true;
+        }
+
+        if ch == '-' || ch == '_' {
+            result.push(' ');
+            word_start = true;
+            continue;
+        }
+
+        // Capitalize first letter of each word
+        if word_start {
+            result.push_str(&ch.to_uppercase().collect::<String>());
+            word_start = false;
+        } else {
+            result.push(ch);
+        }
+
+        prev_was_lower = is_lower;
+        prev_was_upper = is_upper;
+        prev_was_digit = is_digit;
+    }
+
+    let mut result = result.split_whitespace().collect::<Vec<_>>().join(" ");
+    for (from, to) in fixups {
+        result = result.replace(from.as_str(), to);
+    }
+    result
+}
+
+/// English display name of `key`, as stored in puzzle databases.
+pub fn friendly_name(kind: FriendlyNameKind, key: &str) -> String {
+    match FRIENDLY_NAMES.read() {
+        Ok(names) => names.name(kind, key, FALLBACK_LOCALE),
+        Err(_) => FriendlyNames::bundled().name(kind, key, FALLBACK_LOCALE),
+    }
+}
+
+fn overrides_path(app: &AppHandle) -> Result<PathBuf, Error> {
+    Ok(app.path().resolve(OVERRIDES_FILE, BaseDirectory::AppConfig)?)
+}
+
+fn save_overrides(app: &AppHandle, overrides: &HashMap<String, Catalog>) -> Result<(), Error> {
+    let path = overrides_path(app)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(overrides)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize friendly names: {}", e)))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Load the user's overrides. A missing file means no overrides.
+pub fn init(app: &AppHandle) -> Result<(), Error> {
+    let path = overrides_path(app)?;
+    if !path.exists() {
+        return Ok(());
+    }
+    let overrides: HashMap<String, Catalog> = serde_json::from_str(&fs::read_to_string(&path)?)
+        .map_err(|e| Error::PackageManager(format!("Invalid friendly names overrides: {}", e)))?;
+    let mut names = FRIENDLY_NAMES
+        .write()
+        .map_err(|e| Error::MutexLockFailed(e.to_string()))?;
+    names.overrides = overrides
+        .into_iter()
+        .map(|(locale, catalog)| (language(&locale), catalog))
+        .collect();
+    Ok(())
+}
+
+/// Display names of every known identifier of `kind` in `locale`, keyed by lowercase identifier.
+#[tauri::command]
+#[specta::specta]
+pub fn get_friendly_names(
+    kind: FriendlyNameKind,
+    locale: String,
+) -> Result<HashMap<String, String>, Error> {
+    let names = FRIENDLY_NAMES
+        .read()
+        .map_err(|e| Error::MutexLockFailed(e.to_string()))?;
+    Ok(names.all(kind, &language(&locale)))
+}
+
+/// Set the user's name for `key` in `locale`, or remove the override when `name` is `None`.
+#[tauri::command]
+#[specta::specta]
+pub fn set_friendly_name_override(
+    kind: FriendlyNameKind,
+    locale: String,
+    key: String,
+    name: Option<String>,
+    app: AppHandle,
+) -> Result<(), Error> {
+    let mut names = FRIENDLY_NAMES
+        .write()
+        .map_err(|e| Error::MutexLockFailed(e.to_string()))?;
+    let catalog = names.overrides.entry(language(&locale)).or_default();
+    let key = key.to_lowercase();
+    match name {
+        Some(name) => catalog.names_mut(kind).insert(key, name),
+        None => catalog.names_mut(kind).remove(&key),
+    };
+    save_overrides(&app, &names.overrides)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_catalogs_parse() {
+        let names = FriendlyNames::bundled();
+        assert_eq!(names.bundled.len(), BUNDLED_CATALOGS.len());
+        assert_eq!(names.name(FriendlyNameKind::Theme, "backRankMate", "en"), "Back Rank Mate");
+        assert_eq!(names.name(FriendlyNameKind::OpeningTag, "ruylopez", "es"), "Ruy López");
+    }
+
+    #[test]
+    fn test_lookup_falls_back() {
+        let mut names = FriendlyNames::bundled();
+        names
+            .overrides
+            .entry("es".to_string())
+            .or_default()
+            .themes
+            .insert("fork".to_string(), "Horquilla".to_string());
+        assert_eq!(names.name(FriendlyNameKind::Theme, "fork", "es"), "Horquilla");
+        assert_eq!(names.name(FriendlyNameKind::Theme, "fork", "en"), "Fork");
+        assert_eq!(names.name(FriendlyNameKind::Theme, "zugzwang", "ja"), "Zugzwang");
+        assert_eq!(names.name(FriendlyNameKind::Theme, "rookKingEndGame", "en"), "Rook King Endgame");
+        assert_eq!(names.name(FriendlyNameKind::OpeningTag, "semi_slav", "en"), "Semi-Slav");
+    }
+}
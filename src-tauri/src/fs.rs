@@ -1,7 +1,7 @@
 use std::path::{Path, PathBuf};
 
 use log::{info, warn};
-use reqwest::{Client, Url};
+use reqwest::Url;
 use specta::Type;
 use tauri_specta::Event;
 use tokio::io::AsyncWriteExt;
@@ -13,6 +13,8 @@ use futures_util::StreamExt;
 use tauri::Manager;
 
 use crate::error::Error;
+use crate::http;
+use crate::progress::{TaskKind, TaskProgress};
 
 const MAX_DOWNLOAD_SIZE: u64 = 10 * 1024 * 1024 * 1024;
 
@@ -23,6 +25,19 @@ pub struct DownloadProgress {
     pub finished: bool,
 }
 
+impl DownloadProgress {
+    /// Emit this update together with the unified `TaskProgress` event.
+    pub fn report(self, app: &tauri::AppHandle) -> Result<(), Error> {
+        let task = TaskProgress {
+            finished: self.finished,
+            ..TaskProgress::new(TaskKind::Download, self.id.clone(), self.progress as f64)
+        };
+        self.emit(app)?;
+        task.send(app);
+        Ok(())
+    }
+}
+
 #[tauri::command]
 #[specta::specta]
 pub async fn download_file(
@@ -69,19 +84,12 @@ pub async fn download_file(
     
     validate_destination_path(&app, &path)?;
     
-    let client = Client::builder()
+    let mut req = http::Request::get(&url)
         .timeout(std::time::Duration::from_secs(300))
-        .redirect(reqwest::redirect::Policy::limited(10)) // Follow up to 10 redirects
-        .build()?;
+        // Mimic a browser, some mirrors reject unknown clients
+        .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+        .header("Accept", "*/*");
 
-    let mut req = client.get(&url);
-    
-    // Add User-Agent to mimic a browser
-    req = req.header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36");
-    
-    // Add Accept header for better compatibility
-    req = req.header("Accept", "*/*");
-    
     if let Some(ref token_val) = token {
         req = req.header("Authorization", format!("Bearer {}", token_val));
     }
@@ -163,7 +171,7 @@ async fn download_to_file(
             id: id.to_string(),
             finished: false,
         }
-        .emit(app)?;
+        .report(app)?;
     }
     
     file.sync_all().await?;
@@ -176,7 +184,7 @@ async fn download_to_file(
             id: id.to_string(),
             finished: true,
         }
-        .emit(app)?;
+        .report(app)?;
     }
     
     Ok(())
@@ -228,7 +236,7 @@ async fn download_and_extract(
             id: id.to_string(),
             finished: false,
         }
-        .emit(app)?;
+        .report(app)?;
     }
 
     info!("Downloaded {} bytes, starting extraction to {}", downloaded, path.display());
@@ -238,7 +246,7 @@ async fn download_and_extract(
         id: id.to_string(),
         finished: false,
     }
-    .emit(app)?;
+    .report(app)?;
 
     tmp_file.sync_all().await?;
     drop(tmp_file);
@@ -270,7 +278,7 @@ async fn download_and_extract(
             id: id.to_string(),
             finished: true,
         }
-        .emit(app)?;
+        .report(app)?;
     }
     
     Ok(())
@@ -338,7 +346,7 @@ fn is_private_or_localhost(host: &str) -> bool {
     }
 }
 
-fn unzip_file_from_path(dest_dir: &Path, archive_path: &Path) -> Result<(), Error> {
+pub(crate) fn unzip_file_from_path(dest_dir: &Path, archive_path: &Path) -> Result<(), Error> {
     let file = std::fs::File::open(archive_path)?;
     let mut archive = zip::ZipArchive::new(file)?;
 
@@ -387,7 +395,7 @@ fn unzip_file_from_path(dest_dir: &Path, archive_path: &Path) -> Result<(), Erro
     Ok(())
 }
 
-fn extract_tar_file_from_path(dest_dir: &Path, archive_path: &Path, is_gz: bool) -> Result<(), Error> {
+pub(crate) fn extract_tar_file_from_path(dest_dir: &Path, archive_path: &Path, is_gz: bool) -> Result<(), Error> {
     use flate2::read::GzDecoder;
     use std::io::Read;
 
@@ -0,0 +1,270 @@
+//! Shared HTTP layer for external APIs (FIDE, Lichess, Chess.com, downloads).
+//!
+//! All requests go through a single `reqwest::Client` and get:
+//! - per-host rate limiting, so bursts from different features do not trip API limits;
+//! - retries with exponential backoff for idempotent requests on connection errors, 429 and 5xx;
+//! - an in-memory response cache with a per-request TTL;
+//! - offline detection: connection failures mark the network as offline and cached responses are
+//!   served even when stale until a request succeeds again.
+
+use std::{
+    num::NonZeroU32,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use nonzero_ext::nonzero;
+use once_cell::sync::Lazy;
+use reqwest::{Method, Response, StatusCode, Url};
+
+use crate::error::{Error, Result};
+
+pub const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_RETRIES: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const REQUESTS_PER_SECOND_PER_HOST: NonZeroU32 = nonzero!(5u32);
+
+fn build_client(redirect: reqwest::redirect::Policy) -> reqwest::Client {
+    reqwest::Client::builder()
+        .user_agent(BROWSER_USER_AGENT)
+        .connect_timeout(Duration::from_secs(15))
+        .redirect(redirect)
+        .build()
+        .expect("failed to build shared http client")
+}
+
+static CLIENT: Lazy<reqwest::Client> =
+    Lazy::new(|| build_client(reqwest::redirect::Policy::limited(10)));
+/// Client for requests that must not follow redirects, such as OAuth token requests.
+static NO_REDIRECT_CLIENT: Lazy<reqwest::Client> =
+    Lazy::new(|| build_client(reqwest::redirect::Policy::none()));
+
+static LIMITER: Lazy<DefaultKeyedRateLimiter<String>> =
+    Lazy::new(|| RateLimiter::keyed(Quota::per_second(REQUESTS_PER_SECOND_PER_HOST)));
+
+struct CachedResponse {
+    body: Vec<u8>,
+    expires: Instant,
+}
+
+static CACHE: Lazy<DashMap<String, CachedResponse>> = Lazy::new(DashMap::new);
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the last request failed to reach its host.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_network_offline() -> bool {
+    is_offline()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn clear_http_cache() {
+    CACHE.clear();
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Builder for a request through the shared client.
+pub struct Request {
+    method: Method,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    timeout: Duration,
+    cache_ttl: Option<Duration>,
+    follow_redirects: bool,
+}
+
+impl Request {
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: Vec::new(),
+            body: None,
+            timeout: DEFAULT_TIMEOUT,
+            cache_ttl: None,
+            follow_redirects: true,
+        }
+    }
+
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new(Method::GET, url)
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Return redirect responses as they are instead of following them.
+    pub fn no_redirects(mut self) -> Self {
+        self.follow_redirects = false;
+        self
+    }
+
+    /// Cache successful GET responses for `ttl`. Only applies to `bytes` and `text`.
+    pub fn cache_for(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        (self.method == Method::GET && self.cache_ttl.is_some()).then(|| self.url.clone())
+    }
+
+    /// Send the request and return the raw response, whatever its status. Streaming callers
+    /// (downloads) use this; the response is never cached.
+    pub async fn send(&self) -> Result<Response> {
+        let url = Url::parse(&self.url)
+            .map_err(|e| Error::PackageManager(format!("Invalid URL: {}", e)))?;
+        let host = url.host_str().unwrap_or_default().to_string();
+        let retries = if self.method == Method::GET { MAX_RETRIES } else { 0 };
+
+        let mut attempt = 0;
+        loop {
+            LIMITER.until_key_ready(&host).await;
+
+            let client = if self.follow_redirects {
+                &CLIENT
+            } else {
+                &NO_REDIRECT_CLIENT
+            };
+            let mut builder = client
+                .request(self.method.clone(), url.clone())
+                .timeout(self.timeout);
+            for (name, value) in &self.headers {
+                builder = builder.header(name, value);
+            }
+            if let Some(body) = &self.body {
+                builder = builder.body(body.clone());
+            }
+
+            match builder.send().await {
+                Ok(response) => {
+                    OFFLINE.store(false, Ordering::Relaxed);
+                    if attempt < retries && is_retryable_status(response.status()) {
+                        log::warn!("{} returned {}, retrying", self.url, response.status());
+                    } else {
+                        return Ok(response);
+                    }
+                }
+                Err(e) => {
+                    if e.is_connect() {
+                        OFFLINE.store(true, Ordering::Relaxed);
+                    }
+                    if attempt >= retries || !(e.is_connect() || e.is_timeout()) {
+                        return Err(e.into());
+                    }
+                    log::warn!("Request to {} failed ({}), retrying", self.url, e);
+                }
+            }
+
+            tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    /// Response body of a successful request, served from the cache when possible.
+    pub async fn bytes(self) -> Result<Vec<u8>> {
+        let key = self.cache_key();
+        if let Some(key) = &key {
+            if let Some(cached) = CACHE.get(key) {
+                if cached.expires > Instant::now() {
+                    return Ok(cached.body.clone());
+                }
+            }
+        }
+
+        let response = match self.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                // Keep features usable offline with whatever we fetched before.
+                if let Some(cached) = key.as_ref().filter(|_| is_offline()).and_then(|k| CACHE.get(k)) {
+                    log::info!("Offline, serving stale response for {}", self.url);
+                    return Ok(cached.body.clone());
+                }
+                return Err(e);
+            }
+        };
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::HttpStatus(status.as_u16()));
+        }
+        let body = response.bytes().await?.to_vec();
+
+        if let (Some(key), Some(ttl)) = (key, self.cache_ttl) {
+            CACHE.insert(
+                key,
+                CachedResponse {
+                    body: body.clone(),
+                    expires: Instant::now() + ttl,
+                },
+            );
+        }
+        Ok(body)
+    }
+
+    pub async fn text(self) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.bytes().await?).into_owned())
+    }
+}
+
+/// HTTP client for `oauth2` token requests, routed through the shared client without following
+/// redirects.
+pub async fn oauth_http_client(request: oauth2::HttpRequest) -> Result<oauth2::HttpResponse> {
+    let method = Method::from_bytes(request.method.as_str().as_bytes())
+        .map_err(|e| Error::PackageManager(format!("Invalid HTTP method: {}", e)))?;
+    // Following a redirect could send the authorization code or token to another host.
+    let mut req = Request::new(method, request.url.to_string())
+        .body(request.body)
+        .no_redirects();
+    for (name, value) in request.headers.iter() {
+        if let Ok(value) = value.to_str() {
+            req = req.header(name.as_str(), value);
+        }
+    }
+
+    let response = req.send().await?;
+    let status_code = oauth2::http::StatusCode::from_u16(response.status().as_u16())
+        .map_err(|e| Error::PackageManager(format!("Invalid HTTP status: {}", e)))?;
+    let mut headers = oauth2::http::HeaderMap::new();
+    for (name, value) in response.headers() {
+        if let (Ok(name), Ok(value)) = (
+            oauth2::http::HeaderName::from_bytes(name.as_str().as_bytes()),
+            oauth2::http::HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    let body = response.bytes().await?.to_vec();
+
+    Ok(oauth2::HttpResponse {
+        status_code,
+        headers,
+        body,
+    })
+}
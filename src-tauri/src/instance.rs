@@ -0,0 +1,155 @@
+//! One running app per user, with any number of windows.
+//!
+//! Opening a PGN file or a `pawnappetit://` link while the app runs starts a second process. The
+//! single instance plugin hands its arguments to the running one and exits: links go through the
+//! deep link handler, and a file argument is resolved against the second process' working
+//! directory and sent to the frontend as an `OpenFile` event, after the main window is brought
+//! to the front. The file the first process was started with is still read through the CLI
+//! plugin.
+//!
+//! More windows are opened with `open_window`. They share the `AppState` of the process, so
+//! database writes from any of them are serialized by its `db_writes` locks.
+
+use std::path::{Path, PathBuf};
+#[cfg(desktop)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::Serialize;
+use specta::Type;
+#[cfg(desktop)]
+use tauri::Manager;
+use tauri_specta::Event;
+
+use crate::{
+    deep_link::SCHEME,
+    error::{Error, Result},
+};
+
+/// Label of the window the app starts with.
+#[cfg(desktop)]
+pub const MAIN_WINDOW: &str = "main";
+/// Prefix of the labels of windows opened with `open_window`, see the capabilities.
+#[cfg(desktop)]
+const WINDOW_PREFIX: &str = "window-";
+
+#[cfg(desktop)]
+static NEXT_WINDOW: AtomicUsize = AtomicUsize::new(1);
+
+/// A file passed to a second launch of the app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type, Event)]
+pub struct OpenFile {
+    pub path: String,
+}
+
+/// The file argument of a launch, made absolute against `cwd`. Flags and links are skipped.
+#[cfg_attr(mobile, allow(dead_code))]
+fn file_argument(args: &[String], cwd: &Path) -> Option<PathBuf> {
+    let link = format!("{}:", SCHEME);
+    let arg = args
+        .iter()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-') && !arg.starts_with(&link))?;
+    let path = Path::new(arg);
+    Some(if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        cwd.join(path)
+    })
+}
+
+#[cfg(desktop)]
+fn focus_main_window(app: &tauri::AppHandle) {
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        return;
+    };
+    let _ = window.unminimize();
+    let _ = window.show();
+    if let Err(e) = window.set_focus() {
+        log::warn!("Failed to focus the main window: {}", e);
+    }
+}
+
+/// Called in the running app when the app is launched again with `args`.
+#[cfg(desktop)]
+fn on_second_launch(app: &tauri::AppHandle, args: Vec<String>, cwd: String) {
+    log::info!("App launched again with {:?}", args);
+    focus_main_window(app);
+
+    let Some(path) = file_argument(&args, Path::new(&cwd)) else {
+        return;
+    };
+    let event = OpenFile {
+        path: path.to_string_lossy().into_owned(),
+    };
+    if let Err(e) = event.emit(app) {
+        log::warn!(
+            "Failed to forward {} to the frontend: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// The single instance plugin. It has to be the first plugin registered.
+#[cfg(desktop)]
+pub fn plugin() -> tauri::plugin::TauriPlugin<tauri::Wry> {
+    tauri_plugin_single_instance::init(|app, args, cwd| on_second_launch(app, args, cwd))
+}
+
+/// Open another app window and return its label.
+#[tauri::command]
+#[specta::specta]
+pub async fn open_window(app: tauri::AppHandle) -> Result<String> {
+    #[cfg(desktop)]
+    {
+        let label = format!(
+            "{}{}",
+            WINDOW_PREFIX,
+            NEXT_WINDOW.fetch_add(1, Ordering::Relaxed)
+        );
+        tauri::WebviewWindowBuilder::new(&app, &label, tauri::WebviewUrl::default())
+            .title("Pawn Appétit")
+            .inner_size(1280.0, 800.0)
+            .build()
+            .map_err(|e| Error::PackageManager(format!("Failed to open a window: {}", e)))?;
+        log::info!("Opened window {}", label);
+        Ok(label)
+    }
+
+    #[cfg(mobile)]
+    {
+        let _ = app;
+        Err(Error::PackageManager(
+            "Multiple windows are not supported on this platform".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_argument() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let cwd = std::env::current_dir().unwrap();
+
+        assert_eq!(
+            file_argument(&args(&["pawn-appetit", "games/wch.pgn"]), &cwd),
+            Some(cwd.join("games/wch.pgn"))
+        );
+        let absolute = cwd.join("wch.pgn");
+        assert_eq!(
+            file_argument(
+                &args(&["pawn-appetit", "--flag", absolute.to_str().unwrap()]),
+                &cwd
+            ),
+            Some(absolute)
+        );
+        assert_eq!(
+            file_argument(&args(&["pawn-appetit", "pawnappetit://puzzle/K69di"]), &cwd),
+            None
+        );
+        assert_eq!(file_argument(&args(&["pawn-appetit"]), &cwd), None);
+    }
+}
@@ -0,0 +1,246 @@
+//! Registry of running background jobs.
+//!
+//! Long operations (imports and syncs, checkpoint builds, game and database analyses, engine
+//! matches) register a `Job` for as long as they run. A job shares its id with the `TaskProgress`
+//! events of the operation, so every progress update also updates the job and is re-emitted as a
+//! `JobProgress` event; the final `JobProgress` is sent when the job is dropped. A job that sent
+//! its final `TaskProgress::done` before being dropped is announced to the job webhook, if one is
+//! configured. `list_jobs` shows the running jobs and `cancel_job` asks one to stop: the job checks
+//! `Job::is_cancelled` between units of work, which also turns true when the app shuts down, so
+//! shutdown waits for jobs like any other.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use specta::Type;
+use tauri::Manager;
+use tauri_specta::Event;
+
+use crate::{
+    error::{Error, Result},
+    progress::{TaskKind, TaskProgress},
+    shutdown::JobGuard,
+    AppState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    Running,
+    /// Cancellation was requested; the job stops at its next check.
+    Cancelling,
+    Finished,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    /// Id of the `TaskProgress` events of the job, e.g. the database path of an import.
+    pub id: String,
+    pub kind: TaskKind,
+    pub label: String,
+    /// Completion percentage in `0.0..=100.0`, or a negative value when unknown.
+    pub percent: f64,
+    pub message: Option<String>,
+    /// RFC 3339 time the job started.
+    pub started_at: String,
+    pub status: JobStatus,
+}
+
+/// Progress of a job, sent on every update and once more when it ends.
+#[derive(Debug, Clone, Serialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    pub job: JobInfo,
+}
+
+#[derive(Debug)]
+struct JobEntry {
+    info: Mutex<JobInfo>,
+    cancelled: AtomicBool,
+    /// Whether the last progress update was the final one, which failed jobs never send.
+    completed: AtomicBool,
+}
+
+impl JobEntry {
+    fn snapshot(&self) -> JobInfo {
+        let mut info = self.info.lock().unwrap().clone();
+        if self.cancelled.load(Ordering::Relaxed) && info.status == JobStatus::Running {
+            info.status = JobStatus::Cancelling;
+        }
+        info
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct JobRegistry {
+    jobs: DashMap<String, Arc<JobEntry>>,
+}
+
+impl JobRegistry {
+    /// Register a job until the returned handle is dropped. A job started with the id of a running
+    /// one replaces it in the registry.
+    pub fn start(
+        &self,
+        app: &tauri::AppHandle,
+        kind: TaskKind,
+        id: impl Into<String>,
+        label: impl Into<String>,
+    ) -> Job {
+        let info = JobInfo {
+            id: id.into(),
+            kind,
+            label: label.into(),
+            percent: -1.0,
+            message: None,
+            started_at: chrono::Utc::now().to_rfc3339(),
+            status: JobStatus::Running,
+        };
+        let entry = Arc::new(JobEntry {
+            info: Mutex::new(info.clone()),
+            cancelled: AtomicBool::new(false),
+            completed: AtomicBool::new(false),
+        });
+        self.jobs.insert(info.id.clone(), entry.clone());
+        send(app, info);
+        Job {
+            entry,
+            app: app.clone(),
+            _guard: crate::shutdown::start_job(),
+        }
+    }
+
+    fn list(&self) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self.jobs.iter().map(|job| job.snapshot()).collect();
+        jobs.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        jobs
+    }
+
+    fn cancel(&self, id: &str) -> bool {
+        match self.jobs.get(id) {
+            Some(job) => !job.cancelled.swap(true, Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    /// Copy a progress update into the job with the same id, if one is running.
+    fn update(&self, progress: &TaskProgress) -> Option<JobInfo> {
+        let job = self.jobs.get(&progress.id)?;
+        job.completed.store(progress.finished, Ordering::Relaxed);
+        let mut info = job.info.lock().unwrap();
+        info.percent = progress.percent;
+        if progress.message.is_some() {
+            info.message = progress.message.clone();
+        }
+        drop(info);
+        Some(job.snapshot())
+    }
+}
+
+/// Announce a completed job to the webhook, with its last progress message as the summary.
+fn notify_finished(job: &JobInfo) {
+    let (name, title) = match job.kind {
+        TaskKind::Analysis => ("analysis", "Analysis finished"),
+        TaskKind::Database => ("database", "Database task finished"),
+        TaskKind::Download => ("download", "Download finished"),
+        TaskKind::Import => ("import", "Import finished"),
+        TaskKind::Search => ("search", "Search finished"),
+    };
+    let message = match &job.message {
+        Some(message) => format!("{}: {}", job.label, message),
+        None => job.label.clone(),
+    };
+    crate::webhook::job_finished(name, title, message);
+}
+
+fn send<R: tauri::Runtime>(app: &tauri::AppHandle<R>, job: JobInfo) {
+    let id = job.id.clone();
+    if let Err(e) = (JobProgress { job }).emit(app) {
+        log::debug!("Failed to emit job progress for {}: {}", id, e);
+    }
+}
+
+/// Forward a `TaskProgress` update to its job. Called by `TaskProgress::send`.
+pub(crate) fn report<R: tauri::Runtime>(app: &tauri::AppHandle<R>, progress: &TaskProgress) {
+    let Some(state) = app.try_state::<AppState>() else {
+        return;
+    };
+    if let Some(job) = state.jobs.update(progress) {
+        send(app, job);
+    }
+}
+
+/// Whether the job with `id` was cancelled or the app is shutting down, for code that reports
+/// progress by id without holding the `Job`.
+pub fn is_cancelled(app: &tauri::AppHandle, id: &str) -> bool {
+    crate::shutdown::is_shutting_down()
+        || app
+            .state::<AppState>()
+            .jobs
+            .jobs
+            .get(id)
+            .is_some_and(|job| job.cancelled.load(Ordering::Relaxed))
+}
+
+/// A registered job; it is removed from the registry when dropped.
+pub struct Job {
+    entry: Arc<JobEntry>,
+    app: tauri::AppHandle,
+    _guard: JobGuard,
+}
+
+impl Job {
+    /// Whether the job should stop at its next safe point.
+    pub fn is_cancelled(&self) -> bool {
+        self.entry.cancelled.load(Ordering::Relaxed) || crate::shutdown::is_shutting_down()
+    }
+
+    /// `Err(Error::JobCancelled)` once the job should stop.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::JobCancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        let mut info = self.entry.snapshot();
+        info.status = if self.is_cancelled() {
+            JobStatus::Cancelled
+        } else {
+            JobStatus::Finished
+        };
+        let jobs = &self.app.state::<AppState>().jobs.jobs;
+        jobs.remove_if(&info.id, |_, entry| Arc::ptr_eq(entry, &self.entry));
+        if info.status == JobStatus::Finished && self.entry.completed.load(Ordering::Relaxed) {
+            notify_finished(&info);
+        }
+        send(&self.app, info);
+    }
+}
+
+/// Running background jobs, oldest first.
+#[tauri::command]
+#[specta::specta]
+pub fn list_jobs(state: tauri::State<'_, AppState>) -> Vec<JobInfo> {
+    state.jobs.list()
+}
+
+/// Ask the job `id` to stop. Returns false if no such job is running or it was already cancelled.
+#[tauri::command]
+#[specta::specta]
+pub fn cancel_job(id: String, state: tauri::State<'_, AppState>) -> bool {
+    let cancelled = state.jobs.cancel(&id);
+    if cancelled {
+        log::info!("Cancelling job {}", id);
+    }
+    cancelled
+}
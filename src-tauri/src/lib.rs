@@ -4,24 +4,48 @@
 )]
 
 mod app;
+mod bootstrap;
 mod chess;
+mod clock;
+mod crash;
 mod db;
+mod deep_link;
 mod error;
 mod fide;
+mod friendly_names;
 mod fs;
+mod http;
+mod instance;
+mod jobs;
 mod lexer;
+mod metrics;
+mod notation;
 mod oauth;
 mod opening;
 mod package_manager;
 mod pgn;
+mod progress;
 mod puzzle;
+mod puzzle_export;
+mod puzzle_motifs;
+mod puzzle_stats;
+mod puzzle_validation;
+mod recents;
+mod regional;
+mod repertoire;
+mod shutdown;
+mod tabs;
 mod telemetry;
+mod training;
+mod webhook;
 
 use std::sync::Arc;
 
-use chess::{BestMovesPayload, EngineProcess, ReportProgress};
-use dashmap::DashMap;
-use db::{DatabaseProgress, GameQueryJs, NormalizedGame, PositionStats};
+use chess::{BestMovesPayload, EngineMatchProgress, EngineProcess, ReportProgress};
+use dashmap::{DashMap, DashSet};
+use db::{
+    DatabaseProgress, GameQueryJs, NormalizedGame, OpponentModel, PositionStats,
+};
 use derivative::Derivative;
 use fide::FidePlayer;
 use oauth::AuthState;
@@ -31,26 +55,68 @@ use sysinfo::SystemExt;
 use tauri::AppHandle;
 
 use crate::chess::{
-    get_best_moves, analyze_game, get_engine_config, get_engine_logs, kill_engine, kill_engines, stop_engine
+    get_best_moves, analyze_game, describe_position, validate_setup, complete_setup, probe_tablebase, set_tablebase_paths, get_match_statistics, estimate_playing_strength, run_engine_match, stop_engine_match, load_test_suite, run_test_suite, submit_test_suite_answers, get_test_suite_history, start_pondering, get_analysis_presets, set_database_analysis_preset, set_tab_type_analysis_preset, resolve_analysis_preset, get_engine_config, scan_for_engines, get_engine_logs, kill_engine, kill_engines, stop_engine, set_engine_option,
+    end_analysis_session
 };
+use crate::crash::{delete_crash_report, list_crash_reports, submit_crash_report};
 use crate::db::{
     clear_games, convert_pgn, create_indexes, delete_database, delete_db_game, delete_empty_games,
-    delete_indexes, export_to_pgn, export_position_games_to_pgn, export_selected_games_to_pgn, get_player, get_players_game_info, get_tournaments,
-    search_position,
+    delete_indexes, export_analysis_csv, export_to_pgn, export_position_games_to_pgn, export_selected_games_to_pgn, get_player, get_player_weakness_report, get_players_game_info, get_tournaments,
+    search_position, get_position_preview, build_position_checkpoints, run_script, create_student, delete_student, get_student_progress, link_student_source,
+    list_students, record_student_puzzle_result, unlink_student_source, review_game, get_game_review,
+    get_game_key_positions, diff_databases, cleanup_orphans, classify_endgames,
+    get_endgame_distribution, compute_game_phases, get_game_phases, get_sync_config, set_sync_config, sync_push, sync_pull,
+    annotate_movetext, export_subset_to_db, add_conditional_line,
+    remove_conditional_line, get_conditional_lines, export_conditional_lines, prefetch_line_stats,
+    diff_games, attach_external_analysis, run_smart_analysis, stop_smart_analysis,
+    get_event_timeline, decode_moves_debug, repair_move_blob, get_connection_pool_stats,
+    get_import_sources, delete_games_by_source, export_position_stats, tag_game, untag_game,
+    list_tags, recompute_derived_columns, cancel_position_checks,
+    list_registered_databases, start_live_game, record_live_move, take_back_live_move,
+    list_live_games, finish_live_game, discard_live_game, build_player_aggregates,
+    get_remote_explorer_stats, get_game_transpositions, validate_game_update,
+    validate_pgn_game, import_otb_events, global_search,
+    export_game_bundle, import_game_bundle, get_flagging_stats, get_game_move_data, migrate_database, get_database_writer, start_opponent_model,
+    sample_opponent_move, end_opponent_model, analyze_database, import_chesscom_games,
+    create_scratch_database, drop_scratch_database, sync_lichess_games, merge_events, merge_sites, undo_last_merge,
 };
-use crate::fide::{download_fide_db, find_fide_player, fetch_fide_profile_html, save_fide_photo};
+use crate::bootstrap::{bootstrap_content, get_bootstrap_status};
+use crate::fide::{download_fide_db, find_fide_player, fetch_fide_profile_html, get_fide_rating_history, save_fide_photo};
+use crate::friendly_names::{get_friendly_names, set_friendly_name_override};
 use crate::fs::{set_file_as_executable, DownloadProgress};
+use crate::http::{clear_http_cache, get_network_offline};
+use crate::jobs::{cancel_job, list_jobs, JobProgress};
 use crate::lexer::lex_pgn;
-use crate::oauth::authenticate;
+use crate::metrics::{clear_performance_metrics, get_performance_report, set_performance_metrics_enabled};
+use crate::notation::{get_notation_locale, set_notation_locale};
+use crate::oauth::{authenticate, set_lichess_token};
 use crate::package_manager::{
     check_package_installed, check_package_manager_available, find_executable_path, install_package,
 };
 use crate::pgn::{count_pgn_games, delete_game, read_games, write_game};
-use crate::puzzle::{get_puzzle, get_puzzle_db_info, get_puzzle_rating_range, import_puzzle_file, check_puzzle_db_columns, get_puzzle_themes, get_puzzle_opening_tags, validate_puzzle_database};
-use crate::telemetry::{get_telemetry_config, get_telemetry_enabled, set_telemetry_enabled, get_user_country_api, get_user_country_locale, get_user_id_command, get_platform_info_command};
+use crate::progress::TaskProgress;
+use crate::regional::{format_game_date, format_locale_number, get_regional_format, set_regional_country};
+use crate::clock::{
+    clock_move, get_clock_state, pause_clock, resume_clock, start_clock, stop_clock, ClockState,
+};
+use crate::recents::{add_recent_item, clear_recents, get_recent_items, pin_item};
+use crate::tabs::{close_tab, create_tab, duplicate_tab, get_tab_state, list_tabs, update_tab};
+use crate::puzzle_export::export_puzzles_to_anki;
+use crate::puzzle::{auto_tag_puzzles, get_puzzle, get_puzzle_db_info, get_puzzle_rating_range, import_puzzle_file, check_puzzle_db_columns, get_puzzle_themes, get_puzzle_opening_tags, validate_puzzle_database};
+use crate::repertoire::{
+    add_repertoire_line, create_repertoire, detect_conflicts, end_repertoire_training,
+    find_repertoire_gaps, get_due_positions, next_training_line, record_training_result,
+    start_repertoire_training, submit_training_move, sync_lichess_study, TrainingSession,
+};
+use crate::telemetry::{get_telemetry_categories, get_telemetry_config, get_telemetry_enabled, set_telemetry_category, set_telemetry_enabled, get_user_country_api, get_user_country_locale, get_user_id_command, get_platform_info_command};
+use crate::training::{
+    end_guess_the_move, end_vision_session, get_guess_state, get_guess_the_move_history,
+    get_vision_stats, start_guess_the_move, start_vision_session, submit_guess,
+    submit_vision_answer, GuessSession, VisionSession,
+};
 use crate::{
     db::{
-        delete_duplicated_games, edit_db_info, get_db_info, get_games, get_game, get_players, merge_players, update_game
+        delete_duplicated_games, delete_game_filter, edit_db_info, export_prep_bundle, get_db_info, get_games, get_game, get_game_display_moves, get_players, import_prep_bundle, list_game_filters, merge_players, save_game_filter, update_game
     },
     fs::{download_file, file_exists, get_file_metadata},
     opening::{get_opening_from_fen, get_opening_from_name, search_opening_name},
@@ -77,6 +143,8 @@ pub struct AppState {
         String,
         diesel::r2d2::Pool<diesel::r2d2::ConnectionManager<diesel::SqliteConnection>>,
     >,
+    /// Checkout and hold times of the connections of each pool in `connection_pool`.
+    pool_metrics: DashMap<String, Arc<db::PoolMetrics>>,
     line_cache: DashMap<(GameQueryJs, std::path::PathBuf), (Vec<PositionStats>, Vec<NormalizedGame>)>,
     // Cache for games loaded from database (en-croissant approach - more efficient)
     db_cache: std::sync::Mutex<Vec<GameData>>,
@@ -86,6 +154,42 @@ pub struct AppState {
     fide_players: RwLock<Vec<FidePlayer>>,
     engine_processes: DashMap<(String, String), Arc<tokio::sync::Mutex<EngineProcess>>>,
     auth: AuthState,
+    /// Lichess access token used by backend calls to the Lichess API.
+    lichess_token: std::sync::Mutex<Option<String>>,
+    guess_sessions: DashMap<String, GuessSession>,
+    /// Board vision drill sessions by id.
+    vision_sessions: DashMap<String, VisionSession>,
+    /// Repertoire training sessions by id.
+    training_sessions: DashMap<String, TrainingSession>,
+    /// Simulated opponents for play against the engine by id.
+    opponent_models: DashMap<String, OpponentModel>,
+    /// Open analysis tabs by id.
+    tabs: DashMap<String, tabs::Tab>,
+    /// Chess clock of the over-the-board game being recorded.
+    clock: std::sync::Mutex<Option<clock::ChessClock>>,
+    /// Bumped whenever the clock is started or stopped so the previous ticker ends.
+    clock_generation: std::sync::atomic::AtomicUsize,
+    /// Bumped by every `prefetch_line_stats` call so older prefetches stop early.
+    prefetch_generation: std::sync::atomic::AtomicUsize,
+    /// Set by `stop_smart_analysis` to end the running smart analysis after its current game.
+    smart_analysis_stop: std::sync::atomic::AtomicBool,
+    /// Set by `stop_engine_match` to end the running engine match after its current game.
+    engine_match_stop: std::sync::atomic::AtomicBool,
+    /// Bumped by `cancel_position_checks` so running `is_position_in_db` checks give up.
+    position_check_generation: std::sync::atomic::AtomicUsize,
+    /// Position filters of each database, loaded on the first exact search after a write, with
+    /// the write generation they were loaded at.
+    position_filters: DashMap<std::path::PathBuf, (u64, Arc<db::PositionFilters>)>,
+    /// Syzygy tablebases, opened on the first probe.
+    tablebase: chess::TablebaseState,
+    /// Keep-awake guards held for the frontend by id.
+    keep_awake: DashMap<String, app::platform::keep_awake::KeepAwake>,
+    /// Running background jobs by id.
+    jobs: jobs::JobRegistry,
+    /// Write locks of the databases, shared by all windows.
+    db_writes: db::WriteLocks,
+    /// Databases whose pending migrations ran since they were opened.
+    migrated_dbs: DashSet<String>,
 }
 
 // ============================================================================
@@ -98,12 +202,27 @@ pub async fn run() {
     let specta_builder = tauri_specta::Builder::new()
         .commands(tauri_specta::collect_commands!(
             app::platform::screen_capture,
+            app::platform::keep_awake::acquire_keep_awake,
+            app::platform::keep_awake::release_keep_awake,
+            app::platform::power::set_power_policy,
+            app::platform::power::get_power_status,
+            deep_link::take_pending_open_intents,
+            instance::open_window,
+            get_database_writer,
+            webhook::set_job_webhook,
+            webhook::get_job_webhook,
+            webhook::test_job_webhook,
+            list_jobs,
+            cancel_job,
             find_fide_player,
             fetch_fide_profile_html,
             save_fide_photo,
+            get_fide_rating_history,
             get_best_moves,
             analyze_game,
             stop_engine,
+            set_engine_option,
+            end_analysis_session,
             kill_engine,
             kill_engines,
             get_engine_logs,
@@ -114,6 +233,7 @@ pub async fn run() {
             get_opening_from_name,
             get_players_game_info,
             get_engine_config,
+            scan_for_engines,
             file_exists,
             get_file_metadata,
             merge_players,
@@ -137,15 +257,44 @@ pub async fn run() {
             export_position_games_to_pgn,
             export_selected_games_to_pgn,
             authenticate,
+            set_lichess_token,
+            sync_lichess_study,
+            get_remote_explorer_stats,
+            get_game_transpositions,
+            validate_game_update,
+            validate_pgn_game,
+            import_otb_events,
+            global_search,
+            export_game_bundle,
+            import_game_bundle,
+            get_flagging_stats,
+            get_game_move_data,
+            migrate_database,
+            start_opponent_model,
+            sample_opponent_move,
+            end_opponent_model,
+            analyze_database,
+            import_chesscom_games,
+            create_scratch_database,
+            drop_scratch_database,
+            sync_lichess_games,
+            merge_events,
+            merge_sites,
+            undo_last_merge,
             write_game,
             download_fide_db,
             download_file,
+            get_bootstrap_status,
+            bootstrap_content,
             get_tournaments,
             get_db_info,
             get_games,
             get_game,
             update_game,
             search_position,
+            get_position_preview,
+            build_position_checkpoints,
+            run_script,
             get_players,
             get_puzzle_db_info,
             get_puzzle_rating_range,
@@ -165,13 +314,154 @@ pub async fn run() {
             install_package,
             check_package_installed,
             find_executable_path,
-            open_external_link
+            open_external_link,
+            get_performance_report,
+            set_performance_metrics_enabled,
+            clear_performance_metrics,
+            app::safe_mode::get_startup_status,
+            app::safe_mode::exit_safe_mode,
+            app::safe_mode::check_databases_integrity,
+            set_notation_locale,
+            get_notation_locale,
+            get_game_display_moves,
+            get_player_weakness_report,
+            save_game_filter,
+            list_game_filters,
+            delete_game_filter,
+            detect_conflicts,
+            create_repertoire,
+            add_repertoire_line,
+            get_due_positions,
+            record_training_result,
+            export_prep_bundle,
+            import_prep_bundle,
+            create_student,
+            list_students,
+            delete_student,
+            link_student_source,
+            unlink_student_source,
+            record_student_puzzle_result,
+            get_student_progress,
+            export_analysis_csv,
+            review_game,
+            get_game_review,
+            get_game_key_positions,
+            auto_tag_puzzles,
+            export_puzzles_to_anki,
+            get_network_offline,
+            clear_http_cache,
+            set_regional_country,
+            get_regional_format,
+            format_game_date,
+            format_locale_number,
+            diff_databases,
+            cleanup_orphans,
+            start_guess_the_move,
+            submit_guess,
+            get_guess_state,
+            end_guess_the_move,
+            get_guess_the_move_history,
+            start_vision_session,
+            submit_vision_answer,
+            end_vision_session,
+            get_vision_stats,
+            describe_position,
+            validate_setup,
+            complete_setup,
+            probe_tablebase,
+            set_tablebase_paths,
+            classify_endgames,
+            get_endgame_distribution,
+            compute_game_phases,
+            get_game_phases,
+            get_sync_config,
+            set_sync_config,
+            sync_push,
+            sync_pull,
+            get_telemetry_categories,
+            set_telemetry_category,
+            list_crash_reports,
+            delete_crash_report,
+            submit_crash_report,
+            get_match_statistics,
+            estimate_playing_strength,
+            run_engine_match,
+            stop_engine_match,
+            load_test_suite,
+            run_test_suite,
+            submit_test_suite_answers,
+            get_test_suite_history,
+            start_pondering,
+            get_analysis_presets,
+            set_database_analysis_preset,
+            set_tab_type_analysis_preset,
+            resolve_analysis_preset,
+            find_repertoire_gaps,
+            annotate_movetext,
+            export_subset_to_db,
+            add_conditional_line,
+            remove_conditional_line,
+            get_conditional_lines,
+            export_conditional_lines,
+            prefetch_line_stats,
+            diff_games,
+            attach_external_analysis,
+            get_recent_items,
+            add_recent_item,
+            pin_item,
+            clear_recents,
+            run_smart_analysis,
+            stop_smart_analysis,
+            get_event_timeline,
+            decode_moves_debug,
+            repair_move_blob,
+            get_connection_pool_stats,
+            get_import_sources,
+            delete_games_by_source,
+            export_position_stats,
+            tag_game,
+            untag_game,
+            list_tags,
+            recompute_derived_columns,
+            cancel_position_checks,
+            list_registered_databases,
+            start_live_game,
+            record_live_move,
+            take_back_live_move,
+            list_live_games,
+            finish_live_game,
+            discard_live_game,
+            start_repertoire_training,
+            submit_training_move,
+            next_training_line,
+            end_repertoire_training,
+            build_player_aggregates,
+            get_friendly_names,
+            set_friendly_name_override,
+            create_tab,
+            close_tab,
+            duplicate_tab,
+            get_tab_state,
+            list_tabs,
+            update_tab,
+            start_clock,
+            pause_clock,
+            resume_clock,
+            clock_move,
+            get_clock_state,
+            stop_clock
         ))
         .events(tauri_specta::collect_events!(
             BestMovesPayload,
+            EngineMatchProgress,
+            ClockState,
             DatabaseProgress,
+            deep_link::OpenIntent,
             DownloadProgress,
-            ReportProgress
+            instance::OpenFile,
+            JobProgress,
+            ReportProgress,
+            TaskProgress
         ));
 
     #[cfg(all(debug_assertions, not(target_os = "android")))]
@@ -189,8 +479,14 @@ pub async fn run() {
         .setup(move |app| {
             app::setup::setup_tauri_app(app, &specta_builder)
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                shutdown::on_exit(app);
+                app::safe_mode::mark_clean_exit(app);
+            }
+        });
 }
 
 // ============================================================================
@@ -0,0 +1,286 @@
+//! Opt-in performance metrics of the heavy database commands.
+//!
+//! Only the commands whose cost grows with the size of a database are measured: `convert_pgn`,
+//! `get_games`, `get_players`, `get_players_game_info` and `search_position`, each wrapped in a
+//! `CommandSpan`. When enabled, they record their wall time, the time spent waiting for a pooled
+//! database connection and the number of rows they touched; other commands do not show up in the
+//! report. Samples are buffered in memory and flushed into `performance_metrics.db3` in the app
+//! data directory, so that users on slow systems can attach a `get_performance_report()` summary
+//! to bug reports.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use diesel::{connection::SimpleConnection, prelude::*, sql_query, sql_types};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+
+use crate::error::Error;
+
+/// Number of buffered samples that triggers a write to the metrics database.
+const FLUSH_THRESHOLD: usize = 32;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static METRICS_DB: OnceCell<PathBuf> = OnceCell::new();
+static PENDING: Lazy<Mutex<Vec<CommandSample>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Type)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+}
+
+impl MetricsConfig {
+    fn get_config_path(app: &AppHandle) -> Result<PathBuf, Error> {
+        Ok(app
+            .path()
+            .resolve("metrics_config.json", BaseDirectory::AppConfig)?)
+    }
+
+    pub fn load(app: &AppHandle) -> Result<Self, Error> {
+        let config_path = Self::get_config_path(app)?;
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&config_path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::PackageManager(format!("Invalid metrics config: {}", e)))
+    }
+
+    pub fn save(&self, app: &AppHandle) -> Result<(), Error> {
+        let config_path = Self::get_config_path(app)?;
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::PackageManager(format!("Failed to serialize metrics config: {}", e)))?;
+        fs::write(&config_path, json)?;
+        Ok(())
+    }
+}
+
+/// Resolve the metrics database location and restore the persisted opt-in flag.
+pub fn init(app: &AppHandle) -> Result<(), Error> {
+    let db_path = app
+        .path()
+        .resolve("performance_metrics.db3", BaseDirectory::AppData)?;
+    let _ = METRICS_DB.set(db_path);
+
+    let config = MetricsConfig::load(app)?;
+    ENABLED.store(config.enabled, Ordering::Relaxed);
+    log::info!("Performance metrics enabled: {}", config.enabled);
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone)]
+struct CommandSample {
+    command: &'static str,
+    started_at: i64,
+    duration: Duration,
+    connection_wait: Duration,
+    rows: u64,
+}
+
+/// Measures a single command invocation; the sample is recorded when the span is dropped,
+/// so early returns through `?` are still accounted for.
+pub struct CommandSpan {
+    command: &'static str,
+    started: Instant,
+    started_at: i64,
+    connection_wait: Duration,
+    rows: u64,
+    active: bool,
+}
+
+impl CommandSpan {
+    pub fn start(command: &'static str) -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        Self {
+            command,
+            started: Instant::now(),
+            started_at,
+            connection_wait: Duration::ZERO,
+            rows: 0,
+            active: is_enabled(),
+        }
+    }
+
+    /// Run `acquire` (typically a pool checkout) and attribute its duration to connection wait.
+    pub fn time_connection<T>(&mut self, acquire: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = acquire();
+        self.connection_wait += start.elapsed();
+        result
+    }
+
+    pub fn add_rows(&mut self, rows: usize) {
+        self.rows = self.rows.saturating_add(rows as u64);
+    }
+}
+
+impl Drop for CommandSpan {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        let sample = CommandSample {
+            command: self.command,
+            started_at: self.started_at,
+            duration: self.started.elapsed(),
+            connection_wait: self.connection_wait,
+            rows: self.rows,
+        };
+        let should_flush = match PENDING.lock() {
+            Ok(mut pending) => {
+                pending.push(sample);
+                pending.len() >= FLUSH_THRESHOLD
+            }
+            Err(_) => false,
+        };
+        if should_flush {
+            if let Err(e) = flush() {
+                log::warn!("Failed to flush performance metrics: {}", e);
+            }
+        }
+    }
+}
+
+fn open_metrics_db() -> Result<SqliteConnection, Error> {
+    let db_path = METRICS_DB
+        .get()
+        .ok_or_else(|| Error::PackageManager("Performance metrics are not initialized".to_string()))?;
+    if let Some(parent) = db_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut conn = SqliteConnection::establish(&db_path.to_string_lossy())?;
+    conn.batch_execute(
+        r#"
+        CREATE TABLE IF NOT EXISTS command_metrics (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            command TEXT NOT NULL,
+            started_at INTEGER NOT NULL,
+            duration_ms REAL NOT NULL,
+            connection_wait_ms REAL NOT NULL,
+            rows_touched INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_command_metrics_command
+            ON command_metrics(command);
+        "#,
+    )?;
+    Ok(conn)
+}
+
+/// Write all buffered samples to the metrics database.
+pub fn flush() -> Result<(), Error> {
+    let samples: Vec<CommandSample> = {
+        let mut pending = PENDING
+            .lock()
+            .map_err(|e| Error::MutexLockFailed(e.to_string()))?;
+        std::mem::take(&mut *pending)
+    };
+    if samples.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = open_metrics_db()?;
+    conn.transaction::<_, Error, _>(|conn| {
+        for sample in &samples {
+            sql_query(
+                "INSERT INTO command_metrics (command, started_at, duration_ms, connection_wait_ms, rows_touched) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind::<sql_types::Text, _>(sample.command)
+            .bind::<sql_types::BigInt, _>(sample.started_at)
+            .bind::<sql_types::Double, _>(sample.duration.as_secs_f64() * 1000.0)
+            .bind::<sql_types::Double, _>(sample.connection_wait.as_secs_f64() * 1000.0)
+            .bind::<sql_types::BigInt, _>(sample.rows as i64)
+            .execute(conn)?;
+        }
+        Ok(())
+    })
+}
+
+#[derive(Debug, Serialize, Type, QueryableByName)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandMetricsSummary {
+    #[diesel(sql_type = sql_types::Text)]
+    pub command: String,
+    #[diesel(sql_type = sql_types::BigInt)]
+    pub calls: i64,
+    #[diesel(sql_type = sql_types::Double)]
+    pub avg_duration_ms: f64,
+    #[diesel(sql_type = sql_types::Double)]
+    pub max_duration_ms: f64,
+    #[diesel(sql_type = sql_types::Double)]
+    pub avg_connection_wait_ms: f64,
+    #[diesel(sql_type = sql_types::BigInt)]
+    pub total_rows: i64,
+}
+
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PerformanceReport {
+    pub enabled: bool,
+    pub commands: Vec<CommandMetricsSummary>,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_performance_report() -> Result<PerformanceReport, Error> {
+    flush()?;
+    let mut conn = open_metrics_db()?;
+    let commands = sql_query(
+        "SELECT command,
+                COUNT(*) AS calls,
+                AVG(duration_ms) AS avg_duration_ms,
+                MAX(duration_ms) AS max_duration_ms,
+                AVG(connection_wait_ms) AS avg_connection_wait_ms,
+                SUM(rows_touched) AS total_rows
+         FROM command_metrics
+         GROUP BY command
+         ORDER BY avg_duration_ms DESC",
+    )
+    .load::<CommandMetricsSummary>(&mut conn)?;
+
+    Ok(PerformanceReport {
+        enabled: is_enabled(),
+        commands,
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_performance_metrics_enabled(app: AppHandle, enabled: bool) -> Result<(), Error> {
+    MetricsConfig { enabled }.save(&app)?;
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        flush()?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn clear_performance_metrics() -> Result<(), Error> {
+    if let Ok(mut pending) = PENDING.lock() {
+        pending.clear();
+    }
+    let mut conn = open_metrics_db()?;
+    conn.batch_execute("DELETE FROM command_metrics;")?;
+    Ok(())
+}
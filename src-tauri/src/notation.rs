@@ -0,0 +1,178 @@
+//! Localized rendering of SAN move text.
+//!
+//! PGN and engine lines are always produced in standard (English) SAN so they remain parseable;
+//! this module translates them for display according to the user's notation preference, either
+//! with language-specific piece letters or with Unicode figurines.
+//!
+//! The preference applies to text that is only displayed: `GameTree::to_display_string` (and
+//! `get_game_display_moves`), and the display moves of engine and tablebase lines.
+//! `GameTree::to_string` stays in English SAN, as it is the PGN written to files and exports and
+//! the move text `get_game` hands to the frontend parser. Analysis comments need no translation,
+//! since they only hold `[%eval]` annotations.
+//!
+//! The preference is kept in `notation.json` in the app config directory.
+
+use std::{fs, path::PathBuf, sync::RwLock};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+
+use crate::error::Error;
+
+const CONFIG_FILE: &str = "notation.json";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum NotationLocale {
+    #[default]
+    English,
+    German,
+    French,
+    Spanish,
+    Italian,
+    Dutch,
+    Figurine,
+}
+
+impl NotationLocale {
+    pub fn from_code(code: &str) -> Option<Self> {
+        let lang = code.split(['-', '_']).next().unwrap_or_default().to_lowercase();
+        match lang.as_str() {
+            "en" => Some(Self::English),
+            "de" => Some(Self::German),
+            "fr" => Some(Self::French),
+            "es" => Some(Self::Spanish),
+            "it" => Some(Self::Italian),
+            "nl" => Some(Self::Dutch),
+            "figurine" => Some(Self::Figurine),
+            _ => None,
+        }
+    }
+
+    /// Replacement for an English piece letter (`K`, `Q`, `R`, `B`, `N`).
+    fn piece(self, letter: char) -> &'static str {
+        let table: [&'static str; 5] = match self {
+            Self::English => ["K", "Q", "R", "B", "N"],
+            Self::German => ["K", "D", "T", "L", "S"],
+            Self::French => ["R", "D", "T", "F", "C"],
+            Self::Spanish => ["R", "D", "T", "A", "C"],
+            Self::Italian => ["R", "D", "T", "A", "C"],
+            Self::Dutch => ["K", "D", "T", "L", "P"],
+            Self::Figurine => ["♔", "♕", "♖", "♗", "♘"],
+        };
+        match letter {
+            'K' => table[0],
+            'Q' => table[1],
+            'R' => table[2],
+            'B' => table[3],
+            'N' => table[4],
+            _ => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NotationConfig {
+    locale: NotationLocale,
+}
+
+static NOTATION_LOCALE: RwLock<NotationLocale> = RwLock::new(NotationLocale::English);
+
+pub fn current_locale() -> NotationLocale {
+    NOTATION_LOCALE.read().map(|l| *l).unwrap_or_default()
+}
+
+fn config_path(app: &AppHandle) -> Result<PathBuf, Error> {
+    Ok(app.path().resolve(CONFIG_FILE, BaseDirectory::AppConfig)?)
+}
+
+/// Load the saved preference. A missing file means English.
+pub fn init(app: &AppHandle) -> Result<(), Error> {
+    let path = config_path(app)?;
+    if !path.exists() {
+        return Ok(());
+    }
+    let config: NotationConfig = serde_json::from_str(&fs::read_to_string(&path)?)
+        .map_err(|e| Error::PackageManager(format!("Invalid notation config: {}", e)))?;
+    *NOTATION_LOCALE
+        .write()
+        .map_err(|e| Error::MutexLockFailed(e.to_string()))? = config.locale;
+    Ok(())
+}
+
+/// Translate a single SAN token. Castling, pawn moves and annotations are left untouched;
+/// only the leading piece letter and the promotion piece are replaced.
+pub fn localize_san(san: &str, locale: NotationLocale) -> String {
+    if locale == NotationLocale::English {
+        return san.to_string();
+    }
+
+    let mut out = String::with_capacity(san.len() + 2);
+    let mut after_equals = false;
+    for (i, c) in san.chars().enumerate() {
+        let is_piece = matches!(c, 'K' | 'Q' | 'R' | 'B' | 'N');
+        if is_piece && (i == 0 || after_equals) {
+            out.push_str(locale.piece(c));
+        } else {
+            out.push(c);
+        }
+        after_equals = c == '=';
+    }
+    out
+}
+
+/// Translate SAN using the current notation preference.
+pub fn display_san(san: &str) -> String {
+    localize_san(san, current_locale())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_notation_locale(locale: String, app: AppHandle) -> Result<NotationLocale, Error> {
+    let parsed = NotationLocale::from_code(&locale)
+        .ok_or_else(|| Error::PackageManager(format!("Unsupported notation locale: {}", locale)))?;
+
+    let path = config_path(&app)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(&NotationConfig { locale: parsed })
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize notation config: {}", e)))?;
+    fs::write(path, json)?;
+
+    let mut current = NOTATION_LOCALE
+        .write()
+        .map_err(|e| Error::MutexLockFailed(e.to_string()))?;
+    *current = parsed;
+    Ok(parsed)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_notation_locale() -> NotationLocale {
+    current_locale()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_localize_san() {
+        assert_eq!(localize_san("Nf3", NotationLocale::German), "Sf3");
+        assert_eq!(localize_san("Bxe5+", NotationLocale::French), "Fxe5+");
+        assert_eq!(localize_san("exd8=Q#", NotationLocale::German), "exd8=D#");
+        assert_eq!(localize_san("O-O-O", NotationLocale::Spanish), "O-O-O");
+        assert_eq!(localize_san("Qh5", NotationLocale::Figurine), "♕h5");
+        assert_eq!(localize_san("Rb1", NotationLocale::English), "Rb1");
+    }
+
+    #[test]
+    fn test_locale_from_code() {
+        assert_eq!(NotationLocale::from_code("de-DE"), Some(NotationLocale::German));
+        assert_eq!(NotationLocale::from_code("figurine"), Some(NotationLocale::Figurine));
+        assert_eq!(NotationLocale::from_code("xx"), None);
+    }
+}
@@ -1,7 +1,7 @@
 use axum::{extract::Query, response::IntoResponse, routing::get, Extension, Router};
 use log::info;
 use oauth2::{
-    basic::BasicClient, reqwest::async_http_client, AuthUrl, AuthorizationCode, ClientId,
+    basic::BasicClient, AuthUrl, AuthorizationCode, ClientId,
     CsrfToken, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, TokenResponse, TokenUrl,
 };
 use serde::Deserialize;
@@ -69,6 +69,8 @@ pub async fn authenticate(
         .client
         .authorize_url(|| state.auth.csrf_token.clone())
         .add_scope(Scope::new("preference:read".to_string()))
+        .add_scope(Scope::new("study:read".to_string()))
+        .add_scope(Scope::new("study:write".to_string()))
         .add_extra_param("username", username)
         .set_pkce_challenge(state.auth.pkce.0.clone())
         .url();
@@ -77,6 +79,31 @@ pub async fn authenticate(
     Ok(())
 }
 
+fn store_token(state: &AppState, token: Option<String>) {
+    *state.lichess_token.lock().unwrap_or_else(|e| e.into_inner()) = token;
+}
+
+/// Access token of the logged in Lichess account, if any.
+pub fn lichess_token(state: &AppState) -> Option<String> {
+    state
+        .lichess_token
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// Hands the backend the token of a Lichess session restored by the frontend, or forgets it on
+/// logout.
+#[tauri::command]
+#[specta::specta]
+pub fn set_lichess_token(
+    token: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), Error> {
+    store_token(&state, token);
+    Ok(())
+}
+
 #[derive(Deserialize)]
 struct CallbackQuery {
     code: AuthorizationCode,
@@ -98,11 +125,12 @@ async fn authorize(
         .client
         .exchange_code(query.code.clone())
         .set_pkce_verifier(PkceCodeVerifier::new(auth.pkce.1.clone()))
-        .request_async(async_http_client)
+        .request_async(crate::http::oauth_http_client)
         .await
     {
         Ok(token) => {
             let access_token = token.access_token().secret();
+            store_token(&app.state::<AppState>(), Some(access_token.clone()));
             if let Err(e) = app.emit("access_token", access_token) {
                 log::error!("Failed to emit access token: {}", e);
             }
@@ -4,7 +4,11 @@ use std::{
     path::PathBuf,
 };
 
-use crate::{error::Error, AppState};
+use crate::{
+    db::{ensure_valid, validate_pgn},
+    error::Error,
+    AppState,
+};
 
 const GAME_OFFSET_FREQ: usize = 100;
 
@@ -229,6 +233,8 @@ pub async fn write_game(
     pgn: String,
     state: tauri::State<'_, AppState>,
 ) -> Result<(), Error> {
+    ensure_valid(validate_pgn(&pgn))?;
+
     if !file.exists() {
         File::create(&file)?;
     }
@@ -0,0 +1,70 @@
+//! Unified progress reporting for long-running backend tasks.
+//!
+//! Historically every subsystem emitted its own progress shape (`DatabaseProgress`,
+//! `DownloadProgress`, `ReportProgress`, raw `convert_progress`/`search_progress` tuples).
+//! `TaskProgress` is the single event the frontend should listen to going forward; the
+//! legacy events are still emitted alongside it until all listeners have been migrated.
+
+use serde::Serialize;
+use specta::Type;
+use tauri_specta::Event;
+
+/// Subsystem a progress update originates from.
+#[derive(Debug, Clone, Copy, Serialize, Type, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum TaskKind {
+    Analysis,
+    Database,
+    Download,
+    Import,
+    Search,
+}
+
+/// Generic progress event shared by all subsystems.
+#[derive(Debug, Clone, Serialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskProgress {
+    pub kind: TaskKind,
+    pub id: String,
+    /// Completion percentage in `0.0..=100.0`, or a negative value when unknown.
+    pub percent: f64,
+    #[specta(optional)]
+    pub message: Option<String>,
+    pub finished: bool,
+}
+
+impl TaskProgress {
+    /// Create an in-flight progress update.
+    pub fn new(kind: TaskKind, id: impl Into<String>, percent: f64) -> Self {
+        Self {
+            kind,
+            id: id.into(),
+            percent,
+            message: None,
+            finished: false,
+        }
+    }
+
+    /// Create a final (100%) progress update.
+    pub fn done(kind: TaskKind, id: impl Into<String>) -> Self {
+        Self {
+            finished: true,
+            ..Self::new(kind, id, 100.0)
+        }
+    }
+
+    /// Attach a human readable status message.
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Emit the event, logging instead of failing when the webview is gone. The job with the same
+    /// id, if any, is updated too.
+    pub fn send<R: tauri::Runtime>(self, app: &tauri::AppHandle<R>) {
+        crate::jobs::report(app, &self);
+        if let Err(e) = self.emit(app) {
+            log::debug!("Failed to emit task progress for {}: {}", self.id, e);
+        }
+    }
+}
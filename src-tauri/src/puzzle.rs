@@ -1,4 +1,4 @@
-use std::{collections::{VecDeque, HashMap}, path::PathBuf, sync::Mutex, fs::File, io::{Read, BufReader, Seek, SeekFrom}};
+use std::{collections::{VecDeque, HashMap}, path::{Path, PathBuf}, sync::Mutex, fs::File, io::{Read, BufReader, Seek, SeekFrom}};
 
 use diesel::{dsl::sql, sql_types::Bool, Connection, ExpressionMethods, QueryDsl, RunQueryDsl, insert_into, connection::SimpleConnection, BoolExpressionMethods};
 use once_cell::sync::Lazy;
@@ -6,270 +6,20 @@ use rand::Rng;
 use serde::Deserialize;
 use serde::Serialize;
 use specta::Type;
-use tauri::{path::BaseDirectory, Manager, Emitter};
+use tauri::{Emitter, Manager};
 use csv::ReaderBuilder;
 
 use crate::{
-    db::{puzzles, Puzzle},
+    db::{puzzles, DatabaseKind, DatabaseRef, Puzzle},
     error::Error,
+    friendly_names::{friendly_name, FriendlyNameKind},
+    progress::{TaskKind, TaskProgress},
+    puzzle_motifs::classify_puzzle,
+    puzzle_stats::{puzzle_db_stats, PuzzleDbStats, RatingBucket, ThemeCount},
+    puzzle_validation::{check_solution, flag_dubious_puzzles, puzzle_variant, PuzzleEngineCheck},
+    AppState,
 };
 
-/// Converts a technical theme name to a friendly name
-fn get_theme_friendly_name(theme: &str) -> String {
-    let theme_lower = theme.to_lowercase();
-    let friendly_names: HashMap<&str, &str> = [
-        ("advantage", "Advantage"),
-        ("anastasiamate", "Anastasia's Mate"),
-        ("arabianmate", "Arabian Mate"),
-        ("attackingf2f7", "Attacking f2/f7"),
-        ("backrankmate", "Back Rank Mate"),
-        ("bishopendgame", "Bishop Endgame"),
-        ("bodenmate", "Boden's Mate"),
-        ("capturingdefender", "Capturing Defender"),
-        ("castling", "Castling"),
-        ("crushing", "Crushing"),
-        ("defensive", "Defensive"),
-        ("deflection", "Deflection"),
-        ("discoveredattack", "Discovered Attack"),
-        ("doublecheck", "Double Check"),
-        ("doublestake", "Double Threat"),
-        ("endgame", "Endgame"),
-        ("enpassant", "En Passant"),
-        ("equality", "Equality"),
-        ("exposedking", "Exposed King"),
-        ("fork", "Fork"),
-        ("hangingpiece", "Hanging Piece"),
-        ("interference", "Interference"),
-        ("intermezzo", "Intermezzo"),
-        ("knightendgame", "Knight Endgame"),
-        ("long", "Long"),
-        ("mate", "Mate"),
-        ("matein1", "Mate in 1"),
-        ("matein2", "Mate in 2"),
-        ("matein3", "Mate in 3"),
-        ("matein4", "Mate in 4"),
-        ("matein5", "Mate in 5"),
-        ("middlegame", "Middlegame"),
-        ("one-move", "One Move"),
-        ("opening", "Opening"),
-        ("pawnendgame", "Pawn Endgame"),
-        ("pin", "Pin"),
-        ("promotion", "Promotion"),
-        ("queenendgame", "Queen Endgame"),
-        ("queenrookendgame", "Queen & Rook Endgame"),
-        ("queenrook", "Queen & Rook"),
-        ("doublebishopmate", "Double Bishop Mate"),
-        ("doublebishop", "Double Bishop"),
-        ("queensideattack", "Queenside Attack"),
-        ("kingsideattack", "Kingside Attack"),
-        ("quietmove", "Quiet Move"),
-        ("rookendgame", "Rook Endgame"),
-        ("sacrifice", "Sacrifice"),
-        ("short", "Short"),
-        ("skewer", "Skewer"),
-        ("smotheredmate", "Smothered Mate"),
-        ("trappedpiece", "Trapped Piece"),
-        ("underpromotion", "Underpromotion"),
-        ("verylong", "Very Long"),
-        ("x-rayattack", "X-Ray Attack"),
-        ("zugzwang", "Zugzwang"),
-    ]
-    .iter()
-    .cloned()
-    .collect();
-    
-    // Check exact match first
-    if let Some(friendly) = friendly_names.get(theme_lower.as_str()) {
-        return friendly.to_string();
-    }
-    
-    // Try to split camelCase or words separated by common patterns
-    // Split on common word boundaries: lowercase to uppercase transitions, numbers, etc.
-    let mut result = String::new();
-    let mut chars = theme.chars().peekable();
-    let mut prev_was_lower = false;
-    let mut prev_was_upper = false;
-    let mut prev_was_digit = false;
-    let mut word_start = true;
-    
-    while let Some(ch) = chars.next() {
-        let is_upper = ch.is_uppercase();
-        let is_lower = ch.is_lowercase();
-        let is_digit = ch.is_ascii_digit();
-        
-        // Add space before uppercase if previous was lowercase or digit
-        if is_upper && (prev_was_lower || prev_was_digit) && !result.is_empty() {
-            result.push(' ');
-            word_start = true;
-        }
-        // Add space before lowercase if we have multiple uppercase letters in a row (like "QueenRook")
-        else if is_lower && prev_was_upper {
-            if let Some(&next_ch) = chars.peek() {
-                if next_ch.is_uppercase() {
-                    result.push(' ');
-                    word_start = true;
-                }
-            }
-        }
-        // Add space before digit if previous was letter
-        else if is_digit && (prev_was_lower || prev_was_upper) && !result.is_empty() {
-            result.push(' ');
-            word_start = true;
-        }
-        
-        // Handle special cases
-        if ch == '-' || ch == '_' {
-            result.push(' ');
-            word_start = true;
-            continue;
-        }
-        
-        // Capitalize first letter of each word
-        if word_start {
-            result.push_str(&ch.to_uppercase().collect::<String>());
-            word_start = false;
-        } else {
-            result.push(ch);
-        }
-        
-        prev_was_lower = is_lower;
-        prev_was_upper = is_upper;
-        prev_was_digit = is_digit;
-    }
-    
-    // Clean up multiple spaces
-    result = result.split_whitespace().collect::<Vec<_>>().join(" ");
-    
-    // Handle common patterns and fix specific cases
-    result = result
-        .replace("End Game", "Endgame")
-        .replace("Mate In", "Mate in")
-        .replace("Queen Rook", "Queen & Rook")
-        .replace("King Side", "Kingside")
-        .replace("Queen Side", "Queenside")
-        .replace("X Ray", "X-Ray")
-        .replace("En Passant", "En Passant")
-        .replace("F 2 F 7", "f2/f7")
-        .replace("F2 F7", "f2/f7");
-    
-    result
-}
-
-/// Converts a technical opening tag name to a friendly name
-fn get_opening_tag_friendly_name(tag: &str) -> String {
-    let tag_lower = tag.to_lowercase();
-    let friendly_names: HashMap<&str, &str> = [
-        ("sicilian", "Sicilian Defense"),
-        ("french", "French Defense"),
-        ("catalan", "Catalan Opening"),
-        ("queensgambit", "Queen's Gambit"),
-        ("kingsgambit", "King's Gambit"),
-        ("italian", "Italian Game"),
-        ("spanish", "Spanish Game"),
-        ("ruylopez", "Ruy López"),
-        ("carokann", "Caro-Kann Defense"),
-        ("pirc", "Pirc Defense"),
-        ("modern", "Modern Defense"),
-        ("nimzoindian", "Nimzo-Indian Defense"),
-        ("queensindian", "Queen's Indian Defense"),
-        ("kingsindian", "King's Indian Defense"),
-        ("english", "English Opening"),
-        ("dutch", "Dutch Defense"),
-        ("scandinavian", "Scandinavian Defense"),
-        ("alekhine", "Alekhine's Defense"),
-        ("benoni", "Benoni Defense"),
-        ("grunfeld", "Grünfeld Defense"),
-        ("london", "London System"),
-        ("trompowsky", "Trompowsky Attack"),
-        ("reti", "Réti Opening"),
-        ("bird", "Bird's Opening"),
-        ("bogoindian", "Bogo-Indian Defense"),
-        ("slav", "Slav Defense"),
-        ("semi-slav", "Semi-Slav Defense"),
-        ("tarrasch", "Tarrasch Defense"),
-        ("scholar", "Scholar's Mate"),
-        ("fools", "Fool's Mate"),
-    ]
-    .iter()
-    .cloned()
-    .collect();
-    
-    // Check exact match first
-    if let Some(friendly) = friendly_names.get(tag_lower.as_str()) {
-        return friendly.to_string();
-    }
-    
-    // Split camelCase or words separated by common patterns
-    // This handles cases like "QueenRook" -> "Queen Rook"
-    let mut result = String::new();
-    let mut chars = tag.chars().peekable();
-    let mut prev_was_lower = false;
-    let mut prev_was_upper = false;
-    let mut prev_was_digit = false;
-    let mut word_start = true;
-    
-    while let Some(ch) = chars.next() {
-        let is_upper = ch.is_uppercase();
-        let is_lower = ch.is_lowercase();
-        let is_digit = ch.is_ascii_digit();
-        
-        // Add space before uppercase if previous was lowercase or digit
-        if is_upper && (prev_was_lower || prev_was_digit) && !result.is_empty() {
-            result.push(' ');
-            word_start = true;
-        }
-        // Add space before lowercase if we have multiple uppercase letters in a row (like "QueenRook")
-        else if is_lower && prev_was_upper {
-            if let Some(&next_ch) = chars.peek() {
-                if next_ch.is_uppercase() {
-                    result.push(' ');
-                    word_start = true;
-                }
-            }
-        }
-        // Add space before digit if previous was letter
-        else if is_digit && (prev_was_lower || prev_was_upper) && !result.is_empty() {
-            result.push(' ');
-            word_start = true;
-        }
-        
-        // Handle special cases
-        if ch == '-' || ch == '_' {
-            result.push(' ');
-            word_start = true;
-            continue;
-        }
-        
-        // Capitalize first letter of each word
-        if word_start {
-            result.push_str(&ch.to_uppercase().collect::<String>());
-            word_start = false;
-        } else {
-            result.push(ch);
-        }
-        
-        prev_was_lower = is_lower;
-        prev_was_upper = is_upper;
-        prev_was_digit = is_digit;
-    }
-    
-    // Clean up multiple spaces
-    result = result.split_whitespace().collect::<Vec<_>>().join(" ");
-    
-    // Handle common patterns and fix specific cases
-    result = result
-        .replace("Queen Rook", "Queen & Rook")
-        .replace("King Side", "Kingside")
-        .replace("Queen Side", "Queenside")
-        .replace("Semi Slav", "Semi-Slav")
-        .replace("Bogo Indian", "Bogo-Indian")
-        .replace("Nimzo Indian", "Nimzo-Indian")
-        .replace("King S", "King's")
-        .replace("Queen S", "Queen's");
-    
-    result
-}
-
 /// Cache for puzzles to reduce database queries
 #[derive(Debug)]
 struct PuzzleCache {
@@ -310,7 +60,6 @@ impl PuzzleCache {
     ///
     /// # Arguments
     /// * `size` - The maximum number of puzzles to cache at once
-    #[allow(dead_code)]
     fn with_cache_size(mut self, size: usize) -> Self {
         self.cache_size = size;
         self
@@ -361,6 +110,8 @@ impl PuzzleCache {
             game_url: Option<String>,
             #[diesel(sql_type = Nullable<Text>, column_name = "opening_tags")]
             opening_tags: Option<String>,
+            #[diesel(sql_type = Nullable<Text>, column_name = "variant")]
+            variant: Option<String>,
         }
         
         // Build the query using raw SQL for maximum performance
@@ -445,6 +196,7 @@ impl PuzzleCache {
             themes: row.themes,
             game_url: row.game_url,
             opening_tags: row.opening_tags,
+            variant: row.variant,
         }).collect();
         
         // If random, shuffle the results
@@ -522,6 +274,7 @@ impl PuzzleCache {
                 // Re-establish connection after migration
                 db = diesel::SqliteConnection::establish(file)?;
             }
+            ensure_variant_column(&mut db)?;
             
             // Check if normalized tables exist (for new databases or after migration)
             let has_normalized_tables = {
@@ -670,6 +423,21 @@ pub fn get_puzzle(
     }
 }
 
+/// Loads up to `limit` puzzles matching the filters, ordered by id, without going through the
+/// shared cache
+pub(crate) fn load_puzzles(
+    file: &str,
+    min_rating: u16,
+    max_rating: u16,
+    themes: Option<Vec<String>>,
+    opening_tags: Option<Vec<String>>,
+    limit: usize,
+) -> Result<Vec<Puzzle>, Error> {
+    let mut cache = PuzzleCache::new().with_cache_size(limit);
+    cache.get_puzzles_with_filters(file, min_rating, max_rating, false, themes, opening_tags)?;
+    Ok(cache.cache.into())
+}
+
 /// Checks if a puzzle database has the themes and opening_tags columns
 ///
 /// # Arguments
@@ -884,7 +652,7 @@ pub fn get_puzzle_themes(file: String) -> Result<Vec<ThemeGroup>, Error> {
                 let category = get_theme_category(&r.theme).to_string();
                 let option = ThemeOption {
                     value: r.theme.clone(),
-                    label: r.friendly_name.unwrap_or_else(|| get_theme_friendly_name(&r.theme)),
+                    label: r.friendly_name.unwrap_or_else(|| friendly_name(FriendlyNameKind::Theme, &r.theme)),
                 };
                 grouped.entry(category).or_insert_with(Vec::new).push(option);
             }
@@ -911,7 +679,7 @@ pub fn get_puzzle_themes(file: String) -> Result<Vec<ThemeGroup>, Error> {
                 let category = get_theme_category(&r.theme).to_string();
                 let option = ThemeOption {
                     value: r.theme.clone(),
-                    label: get_theme_friendly_name(&r.theme),
+                    label: friendly_name(FriendlyNameKind::Theme, &r.theme),
                 };
                 grouped.entry(category).or_insert_with(Vec::new).push(option);
             }
@@ -951,7 +719,7 @@ pub fn get_puzzle_themes(file: String) -> Result<Vec<ThemeGroup>, Error> {
         let category = get_theme_category(&theme).to_string();
         let option = ThemeOption {
             value: theme.clone(),
-            label: get_theme_friendly_name(&theme),
+            label: friendly_name(FriendlyNameKind::Theme, &theme),
         };
         grouped.entry(category).or_insert_with(Vec::new).push(option);
     }
@@ -1042,7 +810,7 @@ pub fn get_puzzle_opening_tags(file: String) -> Result<Vec<OpeningTagOption>, Er
             // Return both value (technical) and label (friendly name)
             return Ok(tags.into_iter().map(|r| OpeningTagOption {
                 value: r.opening_tag.clone(),
-                label: r.friendly_name.unwrap_or_else(|| get_opening_tag_friendly_name(&r.opening_tag)),
+                label: r.friendly_name.unwrap_or_else(|| friendly_name(FriendlyNameKind::OpeningTag, &r.opening_tag)),
             }).collect());
         } else {
             #[derive(QueryableByName)]
@@ -1054,7 +822,7 @@ pub fn get_puzzle_opening_tags(file: String) -> Result<Vec<OpeningTagOption>, Er
                 .load(&mut db)?;
             return Ok(tags.into_iter().map(|r| OpeningTagOption {
                 value: r.opening_tag.clone(),
-                label: get_opening_tag_friendly_name(&r.opening_tag),
+                label: friendly_name(FriendlyNameKind::OpeningTag, &r.opening_tag),
             }).collect());
         }
     }
@@ -1079,7 +847,7 @@ pub fn get_puzzle_opening_tags(file: String) -> Result<Vec<OpeningTagOption>, Er
     
     let mut result: Vec<OpeningTagOption> = unique_tags.into_iter().map(|tag| OpeningTagOption {
         value: tag.clone(),
-        label: get_opening_tag_friendly_name(&tag),
+        label: friendly_name(FriendlyNameKind::OpeningTag, &tag),
     }).collect();
     result.sort_by(|a, b| a.label.cmp(&b.label));
     Ok(result)
@@ -1128,6 +896,12 @@ pub struct PuzzleDatabaseInfo {
     storage_size: i64,
     /// Full path to the database file
     path: String,
+    /// Number of puzzles per rating range, lowest first
+    rating_buckets: Vec<RatingBucket>,
+    /// Number of puzzles per theme, most frequent first
+    theme_counts: Vec<ThemeCount>,
+    /// When the puzzles last changed, as far as the app has seen (RFC 3339)
+    last_updated: Option<String>,
 }
 
 /// Gets information about a puzzle database
@@ -1137,9 +911,10 @@ pub struct PuzzleDatabaseInfo {
 /// - The number of puzzles in the database
 /// - The size of the database file
 /// - The full path to the database file
+/// - The rating distribution, theme counts and last update time, cached inside the database
 ///
 /// # Arguments
-/// * `file` - Relative path to the puzzle database within the app's data directory
+/// * `file` - File name inside the app's `puzzles` directory, or an absolute path
 /// * `app` - Tauri app handle used to resolve the full path
 ///
 /// # Returns
@@ -1151,16 +926,12 @@ pub async fn get_puzzle_db_info(
     file: PathBuf,
     app: tauri::AppHandle,
 ) -> Result<PuzzleDatabaseInfo, Error> {
-    // Ensure we're working with a relative path by checking if it's absolute
-    let file_path = if file.is_absolute() {
-        // If it's already absolute, use it directly
-        file
-    } else {
-        // Otherwise, resolve it relative to the db directory in AppData
-        let db_path = PathBuf::from("puzzles").join(file);
-        app.path().resolve(db_path, BaseDirectory::AppData)?
-    };
+    let file_path = DatabaseRef::from(file).resolve(&app, DatabaseKind::Puzzles)?;
+    read_puzzle_db_info(&file_path)
+}
 
+/// Puzzle count, size and summary statistics of the puzzle database at `file_path`.
+pub(crate) fn read_puzzle_db_info(file_path: &Path) -> Result<PuzzleDatabaseInfo, Error> {
     // Verify the file actually exists before trying to open it
     if !file_path.exists() {
         return Err(Error::IoError(std::io::Error::new(
@@ -1211,12 +982,24 @@ pub async fn get_puzzle_db_info(
         })?
         .to_string_lossy();
 
+    let stats = if puzzle_count > 0 {
+        puzzle_db_stats(&mut db).unwrap_or_else(|e| {
+            log::warn!("Failed to read puzzle database stats: {}", e);
+            PuzzleDbStats::default()
+        })
+    } else {
+        PuzzleDbStats::default()
+    };
+
     Ok(PuzzleDatabaseInfo {
         title: filename.to_string(),
         description: "".to_string(),
         puzzle_count,
         storage_size,
         path: file_path.to_string_lossy().to_string(),
+        rating_buckets: stats.rating_buckets,
+        theme_counts: stats.theme_counts,
+        last_updated: stats.last_updated,
     })
 }
 
@@ -1227,11 +1010,14 @@ pub async fn get_puzzle_db_info(
 /// - Existing puzzle database files (.db, .db3)
 /// - Compressed files (.zst)
 ///
+/// Puzzles whose solution cannot be played are skipped.
+///
 /// # Arguments
 /// * `source_file` - Path to the source puzzle file
 /// * `db_path` - Path where the new puzzle database should be created
 /// * `title` - Title for the puzzle database
 /// * `description` - Optional description for the puzzle database
+/// * `engine_check` - Optional engine search tagging puzzles whose first move is not clearly best
 /// * `app` - Tauri app handle for progress events
 ///
 /// # Returns
@@ -1244,6 +1030,7 @@ pub async fn import_puzzle_file(
     db_path: PathBuf,
     title: String,
     description: Option<String>,
+    engine_check: Option<PuzzleEngineCheck>,
     app: tauri::AppHandle,
 ) -> Result<(), Error> {
     let description = description.unwrap_or_default();
@@ -1256,6 +1043,13 @@ pub async fn import_puzzle_file(
         )));
     }
 
+    let job = app.state::<AppState>().jobs.start(
+        &app,
+        TaskKind::Import,
+        db_path.to_string_lossy(),
+        format!("Importing puzzles into {}", title),
+    );
+
     // Create parent directory for the database if it doesn't exist
     if let Some(parent) = db_path.parent() {
         std::fs::create_dir_all(parent)?;
@@ -1279,11 +1073,13 @@ pub async fn import_puzzle_file(
     
     // Check if it's a CSV file (could be .csv or .csv.zst)
     let is_csv = file_name.ends_with(".csv") || file_name.ends_with(".csv.zst");
-    
-    match extension {
+
+    let imported = match extension {
         Some("db") | Some("db3") => {
             // Copy existing puzzle database
-            copy_puzzle_database(&source_file, &db_path, &title, &description).await
+            copy_puzzle_database(&source_file, &db_path, &title, &description).await?;
+            TaskProgress::done(TaskKind::Import, db_path.to_string_lossy()).send(&app);
+            return Ok(());
         }
         Some("pgn") => {
             // Parse PGN file and extract puzzles
@@ -1305,7 +1101,33 @@ pub async fn import_puzzle_file(
             "Unsupported file format: {:?}",
             extension
         ))),
+    };
+    if let Err(e) = imported {
+        // A cancelled import leaves no half-filled database behind
+        if job.is_cancelled() && db_path.exists() {
+            let _ = std::fs::remove_file(&db_path);
+        }
+        return Err(e);
+    }
+
+    // Optionally confirm the solutions with a quick engine search; dubious puzzles are tagged
+    // rather than dropped, so the normalized theme tables are rebuilt afterwards
+    if let Some(check) = engine_check {
+        flag_dubious_puzzles(&db_path, &check, |checked, total| {
+            TaskProgress::new(
+                TaskKind::Import,
+                db_path.to_string_lossy(),
+                checked as f64 / total as f64 * 100.0,
+            )
+            .message(format!("{} puzzles checked", checked))
+            .send(&app);
+        })
+        .await?;
+        populate_normalized_tables(&db_path)?;
     }
+
+    TaskProgress::done(TaskKind::Import, db_path.to_string_lossy()).send(&app);
+    Ok(())
 }
 
 /// Validates that a file is a valid SQLite database
@@ -1382,6 +1204,33 @@ fn validate_sqlite_database(file_path: &PathBuf) -> Result<(), Error> {
     Ok(())
 }
 
+/// Emits the legacy `import_puzzle_progress` tuple and the unified `TaskProgress` event.
+/// A `total` of 0 means the number of puzzles is not known yet. Fails with `JobCancelled` once
+/// the import job was cancelled.
+fn emit_import_progress(
+    app: &tauri::AppHandle,
+    db_path: &PathBuf,
+    processed: usize,
+    total: usize,
+) -> Result<(), Error> {
+    let _ = app.emit("import_puzzle_progress", (processed, total));
+    let percent = if total > 0 {
+        (processed as f64 / total as f64 * 100.0).min(100.0)
+    } else {
+        -1.0
+    };
+    TaskProgress {
+        finished: total > 0 && processed >= total,
+        ..TaskProgress::new(TaskKind::Import, db_path.to_string_lossy(), percent)
+    }
+    .message(format!("{} puzzles imported", processed))
+    .send(app);
+    if crate::jobs::is_cancelled(app, &db_path.to_string_lossy()) {
+        return Err(Error::JobCancelled);
+    }
+    Ok(())
+}
+
 /// Copies an existing puzzle database to a new location
 async fn copy_puzzle_database(
     source_file: &PathBuf,
@@ -1395,9 +1244,48 @@ async fn copy_puzzle_database(
     // Copy the source database file to the destination path
     std::fs::copy(source_file, db_path)
         .map_err(|e| Error::IoError(std::io::Error::new(e.kind(), format!("Failed to copy database: {}", e))))?;
+    
+    // Databases created before variant support lack the column
+    let mut db = diesel::SqliteConnection::establish(&db_path.to_string_lossy())?;
+    ensure_variant_column(&mut db)?;
     Ok(())
 }
 
+/// Detects themes for puzzles that have none (custom puzzles, PGN exercises) and refreshes the
+/// normalized theme table. Returns the number of puzzles that were tagged.
+#[tauri::command]
+#[specta::specta]
+pub async fn auto_tag_puzzles(file: PathBuf) -> Result<i32, Error> {
+    let mut db = diesel::SqliteConnection::establish(&file.to_string_lossy())?;
+    ensure_variant_column(&mut db)?;
+
+    // The motif detection only knows standard chess
+    let untagged: Vec<(i32, String, String)> = puzzles::table
+        .select((puzzles::id, puzzles::fen, puzzles::moves))
+        .filter(puzzles::themes.is_null().or(puzzles::themes.eq("")))
+        .filter(puzzles::variant.is_null())
+        .load(&mut db)?;
+
+    let mut tagged = 0;
+    db.transaction::<_, Error, _>(|db| {
+        for (id, fen, moves) in &untagged {
+            if let Some(themes) = auto_themes(fen, moves) {
+                diesel::update(puzzles::table.filter(puzzles::id.eq(id)))
+                    .set(puzzles::themes.eq(themes))
+                    .execute(db)?;
+                tagged += 1;
+            }
+        }
+        Ok(())
+    })?;
+
+    if tagged > 0 {
+        migrate_puzzle_database_to_normalized(&file)?;
+        populate_normalized_tables(&file)?;
+    }
+    Ok(tagged)
+}
+
 /// Validates a downloaded puzzle database file
 #[tauri::command]
 #[specta::specta]
@@ -1427,13 +1315,15 @@ async fn import_puzzles_from_pgn(
         ))
     })?;
     
-    let puzzles = parse_puzzles_from_pgn(file).map_err(|e| {
+    let mut puzzles = parse_puzzles_from_pgn(file).map_err(|e| {
         Error::IoError(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
             format!("Failed to parse puzzles from '{}': {}", source_file.display(), e),
         ))
     })?;
     
+    retain_playable(&mut puzzles);
+    
     if puzzles.is_empty() {
         return Err(Error::IoError(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
@@ -1457,9 +1347,13 @@ async fn import_puzzles_from_pgn(
         
         // Emit progress event
         let processed = ((i + 1) * batch_size).min(total_puzzles);
-        let _ = app.emit("import_puzzle_progress", (processed, total_puzzles));
+        emit_import_progress(app, db_path, processed, total_puzzles)?;
     }
     
+    // Populate normalized tables so detected themes can be filtered on
+    populate_normalized_tables(db_path)?;
+    create_puzzle_indexes(db_path)?;
+    
     Ok(())
 }
 
@@ -1488,13 +1382,15 @@ async fn import_puzzles_from_compressed(
         ))
     })?;
     
-    let puzzles = parse_puzzles_from_pgn(decoder).map_err(|e| {
+    let mut puzzles = parse_puzzles_from_pgn(decoder).map_err(|e| {
         Error::IoError(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
             format!("Failed to parse puzzles from compressed file '{}': {}", source_file.display(), e),
         ))
     })?;
     
+    retain_playable(&mut puzzles);
+    
     if puzzles.is_empty() {
         return Err(Error::IoError(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
@@ -1520,9 +1416,13 @@ async fn import_puzzles_from_compressed(
         
         // Emit progress event
         let processed = ((i + 1) * batch_size).min(total_puzzles);
-        let _ = app.emit("import_puzzle_progress", (processed, total_puzzles));
+        emit_import_progress(app, db_path, processed, total_puzzles)?;
     }
     
+    // Populate normalized tables so detected themes can be filtered on
+    populate_normalized_tables(db_path)?;
+    create_puzzle_indexes(db_path)?;
+    
     Ok(())
 }
 
@@ -1566,6 +1466,7 @@ async fn import_puzzles_from_csv(
         let mut batch = Vec::with_capacity(batch_size);
         let mut total_inserted = 0;
         let mut batch_count = 0;
+        let mut rejected = 0;
         
         for result in csv_reader.deserialize() {
             let record: LichessPuzzleCsv = result.map_err(|e| {
@@ -1580,6 +1481,14 @@ async fn import_puzzles_from_csv(
                 continue;
             }
             
+            // Skip puzzles whose solution cannot be played
+            let variant = stored_variant(record.variant.as_deref());
+            if let Err(reason) = check_solution(&record.fen, &record.moves, variant.as_deref()) {
+                log::warn!("Skipping puzzle {}: {}", record.puzzle_id, reason);
+                rejected += 1;
+                continue;
+            }
+            
             let puzzle = NewPuzzle {
                 fen: record.fen,
                 moves: record.moves,
@@ -1590,6 +1499,7 @@ async fn import_puzzles_from_csv(
                 themes: record.themes,
                 game_url: record.game_url,
                 opening_tags: record.opening_tags,
+                variant,
             };
             
             batch.push(puzzle);
@@ -1610,7 +1520,7 @@ async fn import_puzzles_from_csv(
                 
                 // Emit progress event every 10 batches to avoid too many events
                 if batch_count % 10 == 0 {
-                    let _ = app.emit("import_puzzle_progress", (total_inserted, 0));
+                    emit_import_progress(app, db_path, total_inserted, 0)?;
                 }
                 
                 batch.clear();
@@ -1630,6 +1540,10 @@ async fn import_puzzles_from_csv(
             total_inserted += batch.len();
         }
         
+        if rejected > 0 {
+            log::warn!("Rejected {} puzzles with unplayable solutions", rejected);
+        }
+        
         if total_inserted == 0 {
             return Err(Error::IoError(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
@@ -1638,7 +1552,7 @@ async fn import_puzzles_from_csv(
         }
         
         // Emit final progress
-        let _ = app.emit("import_puzzle_progress", (total_inserted, total_inserted));
+        emit_import_progress(app, db_path, total_inserted, total_inserted)?;
         
         // Populate normalized tables for fast filtering
         populate_normalized_tables(db_path)?;
@@ -1704,6 +1618,7 @@ async fn import_puzzles_from_csv_compressed(
         let mut batch = Vec::with_capacity(batch_size);
         let mut total_inserted = 0;
         let mut batch_count = 0;
+        let mut rejected = 0;
         
         for result in csv_reader.deserialize() {
             let record: LichessPuzzleCsv = result.map_err(|e| {
@@ -1718,6 +1633,14 @@ async fn import_puzzles_from_csv_compressed(
                 continue;
             }
             
+            // Skip puzzles whose solution cannot be played
+            let variant = stored_variant(record.variant.as_deref());
+            if let Err(reason) = check_solution(&record.fen, &record.moves, variant.as_deref()) {
+                log::warn!("Skipping puzzle {}: {}", record.puzzle_id, reason);
+                rejected += 1;
+                continue;
+            }
+            
             let puzzle = NewPuzzle {
                 fen: record.fen,
                 moves: record.moves,
@@ -1728,6 +1651,7 @@ async fn import_puzzles_from_csv_compressed(
                 themes: record.themes,
                 game_url: record.game_url,
                 opening_tags: record.opening_tags,
+                variant,
             };
             
             batch.push(puzzle);
@@ -1748,7 +1672,7 @@ async fn import_puzzles_from_csv_compressed(
                 
                 // Emit progress event every 10 batches to avoid too many events
                 if batch_count % 10 == 0 {
-                    let _ = app.emit("import_puzzle_progress", (total_inserted, 0));
+                    emit_import_progress(app, db_path, total_inserted, 0)?;
                 }
                 
                 batch.clear();
@@ -1768,6 +1692,10 @@ async fn import_puzzles_from_csv_compressed(
             total_inserted += batch.len();
         }
         
+        if rejected > 0 {
+            log::warn!("Rejected {} puzzles with unplayable solutions", rejected);
+        }
+        
         if total_inserted == 0 {
             return Err(Error::IoError(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
@@ -1776,7 +1704,7 @@ async fn import_puzzles_from_csv_compressed(
         }
         
         // Emit final progress
-        let _ = app.emit("import_puzzle_progress", (total_inserted, total_inserted));
+        emit_import_progress(app, db_path, total_inserted, total_inserted)?;
         
         // Populate normalized tables for fast filtering
         populate_normalized_tables(db_path)?;
@@ -1883,8 +1811,8 @@ fn populate_normalized_tables(db_path: &PathBuf) -> Result<(), Error> {
                 for (id, theme) in &theme_batch {
                     // Use INSERT with proper escaping
                     let escaped_theme = theme.replace("'", "''");
-                    let friendly_name = get_theme_friendly_name(theme);
-                    let escaped_friendly = friendly_name.replace("'", "''");
+                    let friendly = friendly_name(FriendlyNameKind::Theme, theme);
+                    let escaped_friendly = friendly.replace("'", "''");
                     diesel::sql_query(&format!(
                         "INSERT INTO puzzle_themes (puzzle_id, theme, friendly_name) VALUES ({}, '{}', '{}')",
                         id, escaped_theme, escaped_friendly
@@ -1900,8 +1828,8 @@ fn populate_normalized_tables(db_path: &PathBuf) -> Result<(), Error> {
                 for (id, tag) in &tag_batch {
                     // Use INSERT with proper escaping
                     let escaped_tag = tag.replace("'", "''");
-                    let friendly_name = get_opening_tag_friendly_name(tag);
-                    let escaped_friendly = friendly_name.replace("'", "''");
+                    let friendly = friendly_name(FriendlyNameKind::OpeningTag, tag);
+                    let escaped_friendly = friendly.replace("'", "''");
                     diesel::sql_query(&format!(
                         "INSERT INTO puzzle_opening_tags (puzzle_id, opening_tag, friendly_name) VALUES ({}, '{}', '{}')",
                         id, escaped_tag, escaped_friendly
@@ -1918,8 +1846,8 @@ fn populate_normalized_tables(db_path: &PathBuf) -> Result<(), Error> {
         db.transaction::<_, Error, _>(|db| {
             for (id, theme) in &theme_batch {
                 let escaped_theme = theme.replace("'", "''");
-                let friendly_name = get_theme_friendly_name(theme);
-                let escaped_friendly = friendly_name.replace("'", "''");
+                let friendly = friendly_name(FriendlyNameKind::Theme, theme);
+                let escaped_friendly = friendly.replace("'", "''");
                 diesel::sql_query(&format!(
                     "INSERT INTO puzzle_themes (puzzle_id, theme, friendly_name) VALUES ({}, '{}', '{}')",
                     id, escaped_theme, escaped_friendly
@@ -1933,8 +1861,8 @@ fn populate_normalized_tables(db_path: &PathBuf) -> Result<(), Error> {
         db.transaction::<_, Error, _>(|db| {
             for (id, tag) in &tag_batch {
                 let escaped_tag = tag.replace("'", "''");
-                let friendly_name = get_opening_tag_friendly_name(tag);
-                let escaped_friendly = friendly_name.replace("'", "''");
+                let friendly = friendly_name(FriendlyNameKind::OpeningTag, tag);
+                let escaped_friendly = friendly.replace("'", "''");
                 diesel::sql_query(&format!(
                     "INSERT INTO puzzle_opening_tags (puzzle_id, opening_tag, friendly_name) VALUES ({}, '{}', '{}')",
                     id, escaped_tag, escaped_friendly
@@ -2041,9 +1969,9 @@ fn migrate_puzzle_database_to_normalized(db_path: &PathBuf) -> Result<(), Error>
         
         for theme_row in themes {
             let theme = theme_row.theme;
-            let friendly_name = get_theme_friendly_name(&theme);
+            let friendly = friendly_name(FriendlyNameKind::Theme, &theme);
             let escaped_theme = theme.replace("'", "''");
-            let escaped_friendly = friendly_name.replace("'", "''");
+            let escaped_friendly = friendly.replace("'", "''");
             let _ = db.batch_execute(&format!(
                 "UPDATE puzzle_themes SET friendly_name = '{}' WHERE theme = '{}' AND friendly_name IS NULL",
                 escaped_friendly, escaped_theme
@@ -2062,9 +1990,9 @@ fn migrate_puzzle_database_to_normalized(db_path: &PathBuf) -> Result<(), Error>
         
         for tag_row in tags {
             let tag = tag_row.opening_tag;
-            let friendly_name = get_opening_tag_friendly_name(&tag);
+            let friendly = friendly_name(FriendlyNameKind::OpeningTag, &tag);
             let escaped_tag = tag.replace("'", "''");
-            let escaped_friendly = friendly_name.replace("'", "''");
+            let escaped_friendly = friendly.replace("'", "''");
             let _ = db.batch_execute(&format!(
                 "UPDATE puzzle_opening_tags SET friendly_name = '{}' WHERE opening_tag = '{}' AND friendly_name IS NULL",
                 escaped_friendly, escaped_tag
@@ -2181,6 +2109,14 @@ fn parse_puzzles_from_pgn<R: Read>(mut reader: R) -> Result<Vec<NewPuzzle>, Erro
                             current_puzzle.nb_plays = nb_plays;
                         }
                     }
+                    "Themes" => {
+                        if !value.trim().is_empty() {
+                            current_puzzle.themes = Some(value);
+                        }
+                    }
+                    "Variant" => {
+                        current_puzzle.variant = stored_variant(Some(&value));
+                    }
                     _ => {}
                 }
             }
@@ -2194,10 +2130,68 @@ fn parse_puzzles_from_pgn<R: Read>(mut reader: R) -> Result<Vec<NewPuzzle>, Erro
     if in_puzzle && current_puzzle.is_complete() {
         puzzles.push(current_puzzle);
     }
+
+    // Exercise files rarely carry themes; detect the motifs so theme filters work for them too.
+    // The motif detection only knows standard chess
+    for puzzle in puzzles.iter_mut().filter(|p| p.themes.is_none() && p.variant.is_none()) {
+        puzzle.themes = auto_themes(&puzzle.fen, &puzzle.moves);
+    }
     
     Ok(puzzles)
 }
 
+/// Space-separated themes detected from the solution line, if any
+fn auto_themes(fen: &str, moves: &str) -> Option<String> {
+    let themes = classify_puzzle(fen, moves);
+    if themes.is_empty() {
+        None
+    } else {
+        Some(themes.join(" "))
+    }
+}
+
+/// Name to store in the `variant` column: the canonical name of a known variant, `None` for
+/// standard chess. Unknown names are kept so the solution check rejects the puzzle.
+fn stored_variant(name: Option<&str>) -> Option<String> {
+    match puzzle_variant(name) {
+        Ok(variant) => variant.map(|(variant, _)| variant.to_string()),
+        Err(_) => name.map(str::to_string),
+    }
+}
+
+/// Adds the `variant` column to puzzle databases created before variant support
+fn ensure_variant_column(db: &mut diesel::SqliteConnection) -> Result<(), Error> {
+    use diesel::sql_query;
+    use diesel::prelude::*;
+    
+    #[derive(QueryableByName)]
+    struct ColumnInfo {
+        #[diesel(sql_type = diesel::sql_types::Text, column_name = "name")]
+        name: String,
+    }
+    
+    let columns: Vec<ColumnInfo> = sql_query("PRAGMA table_info(puzzles)").load(db)?;
+    if !columns.is_empty() && !columns.iter().any(|col| col.name == "variant") {
+        db.batch_execute("ALTER TABLE puzzles ADD COLUMN variant TEXT;")?;
+    }
+    Ok(())
+}
+
+/// Drops puzzles whose solution cannot be played from their position
+fn retain_playable(puzzles: &mut Vec<NewPuzzle>) {
+    let before = puzzles.len();
+    puzzles.retain(|puzzle| match check_solution(&puzzle.fen, &puzzle.moves, puzzle.variant.as_deref()) {
+        Ok(()) => true,
+        Err(reason) => {
+            log::warn!("Skipping puzzle {}: {}", puzzle.fen, reason);
+            false
+        }
+    });
+    if puzzles.len() < before {
+        log::warn!("Rejected {} puzzles with unplayable solutions", before - puzzles.len());
+    }
+}
+
 /// Parses a PGN header line and returns the key-value pair
 fn parse_pgn_header(line: &str) -> Option<(String, String)> {
     if !line.starts_with('[') || !line.ends_with(']') {
@@ -2233,6 +2227,7 @@ struct NewPuzzle {
     themes: Option<String>,
     game_url: Option<String>,
     opening_tags: Option<String>,
+    variant: Option<String>,
 }
 
 impl NewPuzzle {
@@ -2245,7 +2240,6 @@ impl NewPuzzle {
 #[derive(Debug, Deserialize)]
 struct LichessPuzzleCsv {
     #[serde(rename = "PuzzleId")]
-    #[allow(dead_code)]
     puzzle_id: String,
     #[serde(rename = "FEN")]
     fen: String,
@@ -2265,6 +2259,8 @@ struct LichessPuzzleCsv {
     game_url: Option<String>,
     #[serde(rename = "OpeningTags")]
     opening_tags: Option<String>,
+    #[serde(rename = "Variant", default)]
+    variant: Option<String>,
 }
 
 /// Parses puzzles from a CSV reader
@@ -2299,6 +2295,7 @@ fn parse_puzzles_from_csv<R: Read>(reader: R) -> Result<Vec<NewPuzzle>, Error> {
             themes: record.themes,
             game_url: record.game_url,
             opening_tags: record.opening_tags,
+            variant: stored_variant(record.variant.as_deref()),
         };
         
         puzzles.push(puzzle);
@@ -0,0 +1,333 @@
+//! Export of puzzles as an Anki deck.
+//!
+//! Anki imports plain text files whose leading `#key:value` lines describe the columns, so the
+//! export is a tab-separated file with one note per puzzle: the position on the front, the
+//! solution on the back and the puzzle themes as tags. The front shows either the FEN or a board
+//! diagram; diagrams are written as SVG files to a `<deck>.media` folder next to the deck, whose
+//! content has to be copied to Anki's `collection.media` folder for the images to show.
+//!
+//! Puzzles follow the Lichess convention: the first move of the line is the opponent's, so the
+//! card shows the position after it, from the solver's side.
+
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use shakmaty::{
+    fen::Fen,
+    san::SanPlus,
+    uci::UciMove,
+    variant::{Variant, VariantPosition},
+    Board, CastlingMode, Color, EnPassantMode, File as BoardFile, Position, Rank, Role, Square,
+};
+use specta::Type;
+
+use crate::{
+    db::Puzzle,
+    error::{Error, Result},
+    friendly_names::{friendly_name, FriendlyNameKind},
+    puzzle::load_puzzles,
+    puzzle_validation::puzzle_variant,
+};
+
+/// Size in pixels of a square in board diagrams.
+const SQUARE_SIZE: u32 = 45;
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PuzzleExportFilter {
+    pub min_rating: u16,
+    pub max_rating: u16,
+    pub themes: Option<Vec<String>>,
+    pub opening_tags: Option<Vec<String>>,
+    /// Maximum number of puzzles to export, all matching ones when `None`.
+    pub limit: Option<u32>,
+    /// Show board diagrams instead of the FEN on the front of the cards.
+    pub board_images: bool,
+}
+
+/// A puzzle ready to be turned into a note.
+struct Card {
+    id: i32,
+    /// Position shown to the solver, after the opponent's move.
+    position: VariantPosition,
+    /// The opponent's move leading to `position`, numbered, e.g. `2. g4`.
+    opponent_move: String,
+    solution: Vec<String>,
+}
+
+fn prepare_card(puzzle: &Puzzle) -> std::result::Result<Card, String> {
+    let rules = puzzle_variant(puzzle.variant.as_deref())?.map_or(Variant::Chess, |(_, r)| r);
+    let fen = puzzle
+        .fen
+        .parse::<Fen>()
+        .map_err(|e| format!("invalid FEN: {}", e))?;
+    let mut position = VariantPosition::from_setup(rules, fen.into(), CastlingMode::Chess960)
+        .map_err(|e| format!("invalid position: {}", e))?;
+
+    let start = position.clone();
+    let mut sans = Vec::new();
+    let mut shown = None;
+    for uci in puzzle.moves.split_whitespace() {
+        let m = UciMove::from_ascii(uci.as_bytes())
+            .ok()
+            .and_then(|uci| uci.to_move(&position).ok())
+            .ok_or_else(|| format!("illegal move {}", uci))?;
+        sans.push(SanPlus::from_move_and_play_unchecked(&mut position, &m).to_string());
+        if shown.is_none() {
+            shown = Some(position.clone());
+        }
+    }
+    let position = shown.ok_or("empty solution")?;
+    let opponent_move = numbered_line(&start, &sans[..1]);
+    sans.remove(0);
+    if sans.is_empty() {
+        return Err("no move to find".to_string());
+    }
+    Ok(Card {
+        id: puzzle.id,
+        position,
+        opponent_move,
+        solution: sans,
+    })
+}
+
+/// Moves `sans` played from `position`, numbered, e.g. `23...Qxh2+ 24. Kxh2 Rh5#`.
+fn numbered_line(position: &VariantPosition, sans: &[String]) -> String {
+    let mut turn = position.turn();
+    let mut number = position.fullmoves().get();
+    let mut line = Vec::with_capacity(sans.len());
+    for (i, san) in sans.iter().enumerate() {
+        match turn {
+            Color::White => line.push(format!("{}. {}", number, san)),
+            Color::Black if i == 0 => line.push(format!("{}...{}", number, san)),
+            Color::Black => line.push(san.clone()),
+        }
+        if turn == Color::Black {
+            number += 1;
+        }
+        turn = !turn;
+    }
+    line.join(" ")
+}
+
+fn piece_glyph(color: Color, role: Role) -> char {
+    match (color, role) {
+        (Color::White, Role::King) => '♔',
+        (Color::White, Role::Queen) => '♕',
+        (Color::White, Role::Rook) => '♖',
+        (Color::White, Role::Bishop) => '♗',
+        (Color::White, Role::Knight) => '♘',
+        (Color::White, Role::Pawn) => '♙',
+        (Color::Black, Role::King) => '♚',
+        (Color::Black, Role::Queen) => '♛',
+        (Color::Black, Role::Rook) => '♜',
+        (Color::Black, Role::Bishop) => '♝',
+        (Color::Black, Role::Knight) => '♞',
+        (Color::Black, Role::Pawn) => '♟',
+    }
+}
+
+/// SVG diagram of `board` seen from `orientation`'s side.
+pub(crate) fn board_svg(board: &Board, orientation: Color) -> String {
+    let size = SQUARE_SIZE * 8;
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {size} {size}">"#
+    );
+    for rank in Rank::ALL {
+        for file in BoardFile::ALL {
+            let square = Square::from_coords(file, rank);
+            let (col, row) = match orientation {
+                Color::White => (u32::from(file), 7 - u32::from(rank)),
+                Color::Black => (7 - u32::from(file), u32::from(rank)),
+            };
+            let (x, y) = (col * SQUARE_SIZE, row * SQUARE_SIZE);
+            let fill = if square.is_light() {
+                "#f0d9b5"
+            } else {
+                "#b58863"
+            };
+            svg.push_str(&format!(
+                r#"<rect x="{x}" y="{y}" width="{SQUARE_SIZE}" height="{SQUARE_SIZE}" fill="{fill}"/>"#
+            ));
+            if let Some(piece) = board.piece_at(square) {
+                svg.push_str(&format!(
+                    r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                    x + SQUARE_SIZE / 2,
+                    y + SQUARE_SIZE / 2,
+                    SQUARE_SIZE * 4 / 5,
+                    piece_glyph(piece.color, piece.role)
+                ));
+            }
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn image_name(id: i32) -> String {
+    format!("puzzle-{}.svg", id)
+}
+
+/// Folder receiving the diagrams of the deck `dest`.
+fn media_dir(dest: &Path) -> PathBuf {
+    let mut name = dest.file_stem().unwrap_or_default().to_os_string();
+    name.push(".media");
+    dest.with_file_name(name)
+}
+
+fn front(card: &Card, board_images: bool) -> String {
+    let side = match card.position.turn() {
+        Color::White => "White",
+        Color::Black => "Black",
+    };
+    let diagram = if board_images {
+        format!(r#"<img src="{}">"#, image_name(card.id))
+    } else {
+        Fen::from_position(card.position.clone(), EnPassantMode::Legal).to_string()
+    };
+    format!(
+        "{}<br>After {}, {} to play.",
+        diagram, card.opponent_move, side
+    )
+}
+
+fn back(card: &Card, puzzle: &Puzzle, themes: &[&str]) -> String {
+    let mut back = numbered_line(&card.position, &card.solution);
+    back.push_str(&format!("<br>Rating: {}", puzzle.rating));
+    if !themes.is_empty() {
+        let names: Vec<String> = themes
+            .iter()
+            .map(|theme| friendly_name(FriendlyNameKind::Theme, theme))
+            .collect();
+        back.push_str(&format!("<br>Themes: {}", names.join(", ")));
+    }
+    if let Some(url) = &puzzle.game_url {
+        back.push_str(&format!(r#"<br><a href="{0}">{0}</a>"#, url));
+    }
+    back
+}
+
+/// Exports the puzzles of `file` matching `filter` to the Anki deck `dest`. Puzzles whose line
+/// cannot be replayed are skipped. Returns the number of exported puzzles.
+#[tauri::command]
+#[specta::specta]
+pub async fn export_puzzles_to_anki(
+    file: PathBuf,
+    filter: PuzzleExportFilter,
+    dest: PathBuf,
+) -> Result<i32> {
+    let puzzles = load_puzzles(
+        &file.to_string_lossy(),
+        filter.min_rating,
+        filter.max_rating,
+        filter.themes,
+        filter.opening_tags,
+        // SQLite rejects limits beyond i64
+        filter
+            .limit
+            .map_or(i64::MAX as usize, |limit| limit as usize),
+    )?;
+
+    let media = media_dir(&dest);
+    if filter.board_images {
+        std::fs::create_dir_all(&media)?;
+    }
+
+    let mut out = File::create(&dest)?;
+    writeln!(out, "#separator:tab")?;
+    writeln!(out, "#html:true")?;
+    writeln!(out, "#notetype:Basic")?;
+    writeln!(out, "#columns:Front\tBack\tTags")?;
+    writeln!(out, "#tags column:3")?;
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .from_writer(out);
+
+    let mut exported = 0;
+    for puzzle in &puzzles {
+        let card = match prepare_card(puzzle) {
+            Ok(card) => card,
+            Err(e) => {
+                log::warn!("Skipping puzzle {}: {}", puzzle.id, e);
+                continue;
+            }
+        };
+        if filter.board_images {
+            let orientation = card.position.turn();
+            let svg = board_svg(card.position.board(), orientation);
+            std::fs::write(media.join(image_name(card.id)), svg)?;
+        }
+        let themes: Vec<&str> = puzzle
+            .themes
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect();
+        writer
+            .write_record([
+                front(&card, filter.board_images),
+                back(&card, puzzle, &themes),
+                themes.join(" "),
+            ])
+            .map_err(|e| Error::PackageManager(e.to_string()))?;
+        exported += 1;
+    }
+    writer.flush()?;
+    Ok(exported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puzzle(fen: &str, moves: &str) -> Puzzle {
+        Puzzle {
+            id: 7,
+            fen: fen.to_string(),
+            moves: moves.to_string(),
+            rating: 1500,
+            rating_deviation: 80,
+            popularity: 90,
+            nb_plays: 100,
+            themes: Some("mateIn1 short".to_string()),
+            game_url: None,
+            opening_tags: None,
+            variant: None,
+        }
+    }
+
+    #[test]
+    fn test_card_shows_position_after_opponent_move() {
+        // 1. f3 e5 2. g4, Black mates with Qh4#
+        let puzzle = puzzle(
+            "rnbqkbnr/pppp1ppp/8/4p3/8/5P2/PPPPP1PP/RNBQKBNR w KQkq - 0 2",
+            "g2g4 d8h4",
+        );
+        let card = prepare_card(&puzzle).unwrap();
+        assert_eq!(card.position.turn(), Color::Black);
+        assert_eq!(card.opponent_move, "2. g4");
+        assert_eq!(numbered_line(&card.position, &card.solution), "2...Qh4#");
+        assert!(front(&card, false).ends_with("After 2. g4, Black to play."));
+        assert!(front(&card, true).starts_with(r#"<img src="puzzle-7.svg">"#));
+    }
+
+    #[test]
+    fn test_card_rejects_unplayable_lines() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(prepare_card(&puzzle(start, "e2e4")).is_err());
+        assert!(prepare_card(&puzzle(start, "e2e5 e7e5")).is_err());
+    }
+
+    #[test]
+    fn test_media_dir() {
+        assert_eq!(
+            media_dir(Path::new("/tmp/tactics.txt")),
+            PathBuf::from("/tmp/tactics.media")
+        );
+    }
+}
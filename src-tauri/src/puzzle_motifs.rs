@@ -0,0 +1,159 @@
+//! Motif detection for puzzles that come without themes.
+//!
+//! Lichess puzzles ship with curated themes, but custom puzzles and PGN exercise files do not.
+//! The classifier replays the solution line and tags the motifs it can recognise statically, so
+//! theme filtering also works for those puzzles.
+//!
+//! Puzzles follow the Lichess convention used throughout the puzzle database: the FEN is the
+//! position before the opponent's move, `moves` is a space-separated UCI line, and every second
+//! move starting from the second one is played by the solver.
+
+use shakmaty::{
+    attacks, fen::Fen, uci::UciMove, Bitboard, CastlingMode, Chess, Color, Position, Role, Square,
+};
+
+const MAX_MATE_THEME: usize = 5;
+
+fn piece_value(role: Role) -> u32 {
+    match role {
+        Role::Pawn => 1,
+        Role::Knight | Role::Bishop => 3,
+        Role::Rook => 5,
+        Role::Queen => 9,
+        Role::King => 100,
+    }
+}
+
+/// The piece that just moved to `to` attacks two or more enemy pieces worth at least as much.
+fn is_fork(position: &Chess, to: Square, attacker: Color) -> bool {
+    let board = position.board();
+    let Some(piece) = board.piece_at(to) else {
+        return false;
+    };
+    let targets = board.attacks_from(to) & board.by_color(!attacker);
+    targets
+        .into_iter()
+        .filter(|sq| {
+            board.role_at(*sq).is_some_and(|role| {
+                role != Role::Pawn && piece_value(role) >= piece_value(piece.role)
+            })
+        })
+        .count()
+        >= 2
+}
+
+/// The slider that just moved to `to` pins a single enemy piece against the enemy king.
+fn is_pin(position: &Chess, to: Square, attacker: Color) -> bool {
+    let board = position.board();
+    let Some(role) = board.role_at(to) else {
+        return false;
+    };
+    let Some(king) = board.king_of(!attacker) else {
+        return false;
+    };
+    let reach = match role {
+        Role::Bishop => attacks::bishop_attacks(to, Bitboard::EMPTY),
+        Role::Rook => attacks::rook_attacks(to, Bitboard::EMPTY),
+        Role::Queen => attacks::queen_attacks(to, Bitboard::EMPTY),
+        _ => return false,
+    };
+    if !reach.contains(king) {
+        return false;
+    }
+    let blockers = attacks::between(to, king) & board.occupied();
+    blockers.count() == 1 && !(blockers & board.by_color(!attacker)).is_empty()
+}
+
+fn length_theme(solver_moves: usize) -> &'static str {
+    match solver_moves {
+        0 | 1 => "oneMove",
+        2 => "short",
+        3 => "long",
+        _ => "veryLong",
+    }
+}
+
+/// Themes (Lichess theme keys) recognised in a puzzle's solution. Returns an empty list when the
+/// puzzle cannot be replayed.
+pub fn classify_puzzle(fen: &str, moves: &str) -> Vec<String> {
+    let Some(mut position) = fen
+        .parse::<Fen>()
+        .ok()
+        .and_then(|fen| fen.into_position::<Chess>(CastlingMode::Chess960).ok())
+    else {
+        return Vec::new();
+    };
+
+    let mut themes: Vec<String> = Vec::new();
+    let mut add = |theme: &str| {
+        if !themes.iter().any(|t| t == theme) {
+            themes.push(theme.to_string());
+        }
+    };
+
+    let mut solver_moves = 0;
+    for (i, uci) in moves.split_whitespace().enumerate() {
+        let Some(m) = UciMove::from_ascii(uci.as_bytes())
+            .ok()
+            .and_then(|uci| uci.to_move(&position).ok())
+        else {
+            return Vec::new();
+        };
+        let mover = position.turn();
+        position.play_unchecked(&m);
+
+        if i % 2 == 0 {
+            continue;
+        }
+        solver_moves += 1;
+        if m.is_promotion() {
+            add("promotion");
+        }
+        if is_fork(&position, m.to(), mover) {
+            add("fork");
+        }
+        if is_pin(&position, m.to(), mover) {
+            add("pin");
+        }
+    }
+
+    if position.is_checkmate() {
+        add("mate");
+        if solver_moves <= MAX_MATE_THEME {
+            add(&format!("mateIn{}", solver_moves));
+        }
+    }
+    add(length_theme(solver_moves));
+    themes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_rank_mate() {
+        let themes = classify_puzzle("6k1/5ppp/8/8/8/8/r4PPP/3R2K1 b - - 0 1", "a2b2 d1d8");
+        assert_eq!(themes, vec!["mate", "mateIn1", "oneMove"]);
+    }
+
+    #[test]
+    fn test_knight_fork() {
+        // After ...h6 the knight checks from e7 and hits the queen on c8.
+        let themes = classify_puzzle("2q3k1/7p/8/3N4/8/8/5PPP/6K1 b - - 0 1", "h7h6 d5e7");
+        assert!(themes.contains(&"fork".to_string()));
+    }
+
+    #[test]
+    fn test_pin() {
+        let themes = classify_puzzle("4k3/p3n3/8/8/8/8/8/R6K b - - 0 1", "a7a6 a1d1");
+        assert!(!themes.contains(&"pin".to_string()));
+        let themes = classify_puzzle("4k3/p3n3/8/8/8/8/8/R6K b - - 0 1", "a7a6 a1e1");
+        assert!(themes.contains(&"pin".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_line() {
+        assert!(classify_puzzle("8/8/8/8/8/8/8/8 w - - 0 1", "e2e4").is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+//! Summary statistics of a puzzle database, shown on the cards of the puzzle database picker.
+//!
+//! The rating distribution and theme counts need a full scan of the puzzles, so they are stored in
+//! a `puzzle_db_info` table inside the puzzle database together with the puzzle count and highest
+//! puzzle id they were computed for, and only recomputed once those change. `last_updated` is the
+//! time the puzzles were first seen in their current state.
+
+use std::collections::{BTreeMap, HashMap};
+
+use diesel::{
+    connection::SimpleConnection,
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Integer, Text},
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    db::puzzles,
+    error::{Error, Result},
+};
+
+/// Width of the rating distribution buckets.
+pub const RATING_BUCKET_SIZE: i32 = 200;
+
+const CREATE_INFO_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS puzzle_db_info (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        puzzle_count INTEGER NOT NULL,
+        max_id INTEGER NOT NULL,
+        stats TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RatingBucket {
+    /// Lowest rating of the bucket; it spans `RATING_BUCKET_SIZE` points.
+    pub min_rating: i32,
+    pub count: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ThemeCount {
+    pub theme: String,
+    pub count: i32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PuzzleDbStats {
+    pub rating_buckets: Vec<RatingBucket>,
+    /// Most frequent themes first.
+    pub theme_counts: Vec<ThemeCount>,
+    /// RFC 3339 time the puzzles were first seen in their current state.
+    #[serde(skip)]
+    pub last_updated: Option<String>,
+}
+
+fn bucket_of(rating: i32) -> i32 {
+    rating.div_euclid(RATING_BUCKET_SIZE) * RATING_BUCKET_SIZE
+}
+
+fn rating_buckets(ratings: impl IntoIterator<Item = (i32, i64)>) -> Vec<RatingBucket> {
+    let mut buckets: BTreeMap<i32, i64> = BTreeMap::new();
+    for (rating, count) in ratings {
+        *buckets.entry(bucket_of(rating)).or_default() += count;
+    }
+    buckets
+        .into_iter()
+        .map(|(min_rating, count)| RatingBucket {
+            min_rating,
+            count: count as i32,
+        })
+        .collect()
+}
+
+/// Counts the themes of puzzles whose `themes` column lists them separated by spaces or commas.
+fn theme_counts<'a>(themes: impl IntoIterator<Item = &'a str>) -> Vec<ThemeCount> {
+    let mut counts: HashMap<&str, i32> = HashMap::new();
+    for list in themes {
+        for theme in list
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|theme| !theme.is_empty())
+        {
+            *counts.entry(theme).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<ThemeCount> = counts
+        .into_iter()
+        .map(|(theme, count)| ThemeCount {
+            theme: theme.to_string(),
+            count,
+        })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.theme.cmp(&b.theme)));
+    counts
+}
+
+fn compute_stats(db: &mut SqliteConnection) -> Result<PuzzleDbStats> {
+    #[derive(QueryableByName)]
+    struct RatingRow {
+        #[diesel(sql_type = Integer)]
+        rating: i32,
+        #[diesel(sql_type = BigInt)]
+        count: i64,
+    }
+    let ratings: Vec<RatingRow> =
+        sql_query("SELECT rating, COUNT(*) AS count FROM puzzles GROUP BY rating").load(db)?;
+
+    let themes: Vec<Option<String>> = puzzles::table
+        .select(puzzles::themes)
+        .filter(puzzles::themes.is_not_null())
+        .load(db)?;
+
+    Ok(PuzzleDbStats {
+        rating_buckets: rating_buckets(ratings.into_iter().map(|r| (r.rating, r.count))),
+        theme_counts: theme_counts(themes.iter().flatten().map(String::as_str)),
+        last_updated: None,
+    })
+}
+
+#[derive(QueryableByName)]
+struct CachedRow {
+    #[diesel(sql_type = BigInt)]
+    puzzle_count: i64,
+    #[diesel(sql_type = BigInt)]
+    max_id: i64,
+    #[diesel(sql_type = Text)]
+    stats: String,
+    #[diesel(sql_type = Text)]
+    updated_at: String,
+}
+
+/// Statistics of the puzzle database, from the info table while it is up to date.
+pub(crate) fn puzzle_db_stats(db: &mut SqliteConnection) -> Result<PuzzleDbStats> {
+    let puzzle_count: i64 = puzzles::table.count().get_result(db)?;
+    let max_id = puzzles::table
+        .select(diesel::dsl::max(puzzles::id))
+        .first::<Option<i32>>(db)?
+        .unwrap_or(0) as i64;
+
+    db.batch_execute(CREATE_INFO_TABLE)?;
+    let cached: Option<CachedRow> = sql_query(
+        "SELECT puzzle_count, max_id, stats, updated_at FROM puzzle_db_info WHERE id = 1",
+    )
+    .get_result(db)
+    .optional()?;
+    if let Some(row) = cached.filter(|row| row.puzzle_count == puzzle_count && row.max_id == max_id)
+    {
+        if let Ok(mut stats) = serde_json::from_str::<PuzzleDbStats>(&row.stats) {
+            stats.last_updated = Some(row.updated_at);
+            return Ok(stats);
+        }
+    }
+
+    let mut stats = compute_stats(db)?;
+    let updated_at = chrono::Utc::now().to_rfc3339();
+    let json = serde_json::to_string(&stats).map_err(|e| Error::PackageManager(e.to_string()))?;
+    sql_query(
+        "INSERT OR REPLACE INTO puzzle_db_info (id, puzzle_count, max_id, stats, updated_at)
+         VALUES (1, ?, ?, ?, ?)",
+    )
+    .bind::<BigInt, _>(puzzle_count)
+    .bind::<BigInt, _>(max_id)
+    .bind::<Text, _>(json)
+    .bind::<Text, _>(updated_at.clone())
+    .execute(db)?;
+    stats.last_updated = Some(updated_at);
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> SqliteConnection {
+        let mut db = SqliteConnection::establish(":memory:").unwrap();
+        db.batch_execute(
+            "CREATE TABLE puzzles (
+                id INTEGER PRIMARY KEY, fen TEXT NOT NULL, moves TEXT NOT NULL,
+                rating INTEGER NOT NULL, rating_deviation INTEGER NOT NULL,
+                popularity INTEGER NOT NULL, nb_plays INTEGER NOT NULL, themes TEXT,
+                game_url TEXT, opening_tags TEXT, variant TEXT
+            );
+            INSERT INTO puzzles (fen, moves, rating, rating_deviation, popularity, nb_plays, themes)
+            VALUES ('', '', 1450, 0, 0, 0, 'fork short'),
+                   ('', '', 1530, 0, 0, 0, 'mateIn2 short'),
+                   ('', '', 2010, 0, 0, 0, NULL);",
+        )
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_theme_counts() {
+        let counts = theme_counts(["fork short", "short,mateIn2", ""]);
+        assert_eq!(
+            counts,
+            vec![
+                ThemeCount {
+                    theme: "short".to_string(),
+                    count: 2
+                },
+                ThemeCount {
+                    theme: "fork".to_string(),
+                    count: 1
+                },
+                ThemeCount {
+                    theme: "mateIn2".to_string(),
+                    count: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_puzzle_db_stats_cached_until_puzzles_change() {
+        let mut db = test_db();
+        let stats = puzzle_db_stats(&mut db).unwrap();
+        assert_eq!(
+            stats.rating_buckets,
+            vec![
+                RatingBucket {
+                    min_rating: 1400,
+                    count: 2
+                },
+                RatingBucket {
+                    min_rating: 2000,
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(stats.theme_counts[0].theme, "short");
+        let updated = stats.last_updated.clone();
+        assert!(updated.is_some());
+        assert_eq!(puzzle_db_stats(&mut db).unwrap(), stats);
+
+        db.batch_execute(
+            "INSERT INTO puzzles (fen, moves, rating, rating_deviation, popularity, nb_plays)
+             VALUES ('', '', 900, 0, 0, 0)",
+        )
+        .unwrap();
+        let stats = puzzle_db_stats(&mut db).unwrap();
+        assert_eq!(stats.rating_buckets[0].min_rating, 800);
+    }
+}
@@ -0,0 +1,278 @@
+//! Validation of third-party puzzles on import.
+//!
+//! Every imported puzzle has its solution replayed from its FEN; puzzles with an invalid position
+//! or an illegal move are rejected. Optionally, a short engine search checks that the solver's
+//! first move is clearly the best one. Puzzles failing that check are still imported but tagged
+//! with the `dubious` theme, so they can be reviewed or filtered out like any other theme.
+//!
+//! Puzzles follow the Lichess convention (see `puzzle_motifs`): the first move of the line is the
+//! opponent's, the solver's first move is the second one. Variant puzzles (Crazyhouse, Atomic,
+//! Antichess, ...) are replayed with the rules of their variant.
+
+use std::path::Path;
+
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde::Deserialize;
+use shakmaty::{
+    fen::Fen,
+    uci::UciMove,
+    variant::{Variant, VariantPosition},
+    CastlingMode, Position,
+};
+use specta::Type;
+use vampirc_uci::{
+    parse_one,
+    uci::{Score, ScoreValue},
+    UciInfoAttribute, UciMessage,
+};
+
+use crate::{
+    chess::{EngineOption, EngineOptions, EngineProcess, GoMode},
+    db::puzzles,
+    error::Error,
+};
+
+/// Theme added to puzzles whose first move is not clearly best.
+pub const DUBIOUS_THEME: &str = "dubious";
+
+/// Scores beyond any real evaluation, so that mates always outrank material.
+const MATE_CP: i32 = 100_000;
+
+#[derive(Debug, Clone, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PuzzleEngineCheck {
+    /// Path of the engine binary.
+    pub engine: String,
+    pub depth: u32,
+    /// Centipawns by which the solver's first move must beat the second best move.
+    pub min_margin: i32,
+}
+
+/// Variants puzzles can be played in, by their name in the `variant` column.
+const VARIANTS: &[(&str, Variant)] = &[
+    ("crazyhouse", Variant::Crazyhouse),
+    ("atomic", Variant::Atomic),
+    ("antichess", Variant::Antichess),
+    ("kingofthehill", Variant::KingOfTheHill),
+    ("threecheck", Variant::ThreeCheck),
+    ("racingkings", Variant::RacingKings),
+    ("horde", Variant::Horde),
+];
+
+/// Stored name and rules of a puzzle variant, `None` for standard chess. Names are matched like
+/// Lichess spells them, ignoring case and punctuation (`Three-check`, `King of the Hill`).
+pub fn puzzle_variant(name: Option<&str>) -> Result<Option<(&'static str, Variant)>, String> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    let key: String = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let key = match key.as_str() {
+        "" | "standard" | "chess" | "fromposition" => return Ok(None),
+        "giveaway" => "antichess",
+        "3check" => "threecheck",
+        key => key,
+    };
+    VARIANTS
+        .iter()
+        .find(|(variant, _)| *variant == key)
+        .map(|&(variant, rules)| Some((variant, rules)))
+        .ok_or_else(|| format!("unsupported variant: {}", name))
+}
+
+/// Replay a solution line with the rules of `variant`, returning why it cannot be played if it is
+/// invalid.
+pub fn check_solution(fen: &str, moves: &str, variant: Option<&str>) -> Result<(), String> {
+    let rules = puzzle_variant(variant)?.map_or(Variant::Chess, |(_, rules)| rules);
+    let fen = fen
+        .parse::<Fen>()
+        .map_err(|e| format!("invalid FEN: {}", e))?;
+    let mut position = VariantPosition::from_setup(rules, fen.into(), CastlingMode::Chess960)
+        .map_err(|e| format!("invalid position: {}", e))?;
+
+    let mut played = 0;
+    for uci in moves.split_whitespace() {
+        let m = UciMove::from_ascii(uci.as_bytes())
+            .ok()
+            .and_then(|uci| uci.to_move(&position).ok())
+            .ok_or_else(|| format!("illegal move {} at ply {}", uci, played + 1))?;
+        position.play_unchecked(&m);
+        played += 1;
+    }
+    if played == 0 {
+        return Err("empty solution".to_string());
+    }
+    Ok(())
+}
+
+fn score_cp(score: &Score) -> i32 {
+    match score.value {
+        ScoreValue::Cp(cp) => cp,
+        ScoreValue::Mate(n) if n > 0 => MATE_CP - n as i32,
+        ScoreValue::Mate(n) => -MATE_CP - n as i32,
+    }
+}
+
+/// Whether the engine's lines (first move and score for MultiPV 1 and 2) confirm `solution` as
+/// clearly best.
+fn is_clearly_best(
+    best: Option<&(String, i32)>,
+    second: Option<&(String, i32)>,
+    solution: &str,
+    min_margin: i32,
+) -> bool {
+    let Some((best_move, best_score)) = best else {
+        return false;
+    };
+    if best_move != solution {
+        return false;
+    }
+    match second {
+        Some((_, second_score)) => best_score - second_score >= min_margin,
+        // Only one legal move.
+        None => true,
+    }
+}
+
+/// Run `check` over every puzzle of the database at `db_path` and tag the dubious ones. Returns
+/// the number of puzzles tagged; `on_progress` receives the number of puzzles checked so far and
+/// the total.
+pub async fn flag_dubious_puzzles(
+    db_path: &Path,
+    check: &PuzzleEngineCheck,
+    on_progress: impl Fn(usize, usize),
+) -> Result<usize, Error> {
+    let mut db = diesel::SqliteConnection::establish(&db_path.to_string_lossy())?;
+    // Standard UCI engines do not play variants, so only standard puzzles are checked.
+    let rows: Vec<(i32, String, String, Option<String>)> = puzzles::table
+        .filter(puzzles::variant.is_null())
+        .select((puzzles::id, puzzles::fen, puzzles::moves, puzzles::themes))
+        .load(&mut db)?;
+
+    let (mut proc, mut reader) = EngineProcess::new(check.engine.clone().into()).await?;
+    let mut dubious = Vec::new();
+    for (i, (id, fen, moves, themes)) in rows.iter().enumerate() {
+        let line: Vec<&str> = moves.split_whitespace().collect();
+        if line.len() < 2 {
+            continue;
+        }
+
+        proc.set_options(EngineOptions {
+            fen: fen.clone(),
+            moves: vec![line[0].to_string()],
+            extra_options: vec![EngineOption {
+                name: "MultiPV".to_string(),
+                value: "2".to_string(),
+            }],
+        })
+        .await?;
+        proc.go(&GoMode::Depth(check.depth)).await?;
+
+        let mut lines: [Option<(String, i32)>; 2] = [None, None];
+        while let Some(output) = reader.next_line().await? {
+            match parse_one(&output) {
+                UciMessage::Info(attrs) => {
+                    let mut multipv = 1;
+                    let mut score = None;
+                    let mut first = None;
+                    for attr in attrs {
+                        match attr {
+                            UciInfoAttribute::MultiPv(n) => multipv = n,
+                            UciInfoAttribute::Score(s) => score = Some(score_cp(&s)),
+                            UciInfoAttribute::Pv(pv) => first = pv.first().map(|m| m.to_string()),
+                            _ => {}
+                        }
+                    }
+                    if let (Some(score), Some(first), 1..=2) = (score, first, multipv) {
+                        lines[multipv as usize - 1] = Some((first, score));
+                    }
+                }
+                UciMessage::BestMove { .. } => break,
+                _ => {}
+            }
+        }
+
+        if !is_clearly_best(lines[0].as_ref(), lines[1].as_ref(), line[1], check.min_margin) {
+            let themes = match themes.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+                Some(themes) => format!("{} {}", themes, DUBIOUS_THEME),
+                None => DUBIOUS_THEME.to_string(),
+            };
+            dubious.push((*id, themes));
+        }
+        on_progress(i + 1, rows.len());
+    }
+    proc.kill().await?;
+
+    db.transaction::<_, Error, _>(|db| {
+        for (id, themes) in &dubious {
+            diesel::update(puzzles::table.filter(puzzles::id.eq(id)))
+                .set(puzzles::themes.eq(themes))
+                .execute(db)?;
+        }
+        Ok(())
+    })?;
+    log::info!(
+        "Engine check tagged {} of {} puzzles as dubious",
+        dubious.len(),
+        rows.len()
+    );
+    Ok(dubious.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR b KQkq - 3 3";
+
+    #[test]
+    fn test_check_solution() {
+        assert!(check_solution(FEN, "g8f6 f3f7", None).is_ok());
+        assert!(check_solution(FEN, "g8f6 f3f8", None).is_err());
+        assert!(check_solution("not a fen", "e2e4", None).is_err());
+        assert!(check_solution(FEN, "", None).is_err());
+    }
+
+    #[test]
+    fn test_variant_solutions() {
+        // Captures are forced in antichess.
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1";
+        assert!(check_solution(start, "e2e3 b7b5 f1b5", Some("antichess")).is_ok());
+        assert!(check_solution(start, "e2e3 b7b5 e3e4", Some("antichess")).is_err());
+        assert!(check_solution(start, "e2e3 b7b5 e3e4", None).is_ok());
+
+        let pocket = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Pp] w KQkq - 0 1";
+        assert!(check_solution(pocket, "P@e4", Some("Crazyhouse")).is_ok());
+        assert!(check_solution(start, "e2e4", Some("bughouse")).is_err());
+    }
+
+    #[test]
+    fn test_puzzle_variant() {
+        assert_eq!(puzzle_variant(None), Ok(None));
+        assert_eq!(puzzle_variant(Some("Standard")), Ok(None));
+        assert_eq!(
+            puzzle_variant(Some("Three-check")),
+            Ok(Some(("threecheck", Variant::ThreeCheck)))
+        );
+        assert_eq!(
+            puzzle_variant(Some("giveaway")),
+            Ok(Some(("antichess", Variant::Antichess)))
+        );
+        assert!(puzzle_variant(Some("bughouse")).is_err());
+    }
+
+    #[test]
+    fn test_is_clearly_best() {
+        let best = ("f3f7".to_string(), MATE_CP - 1);
+        let second = ("c4f7".to_string(), 150);
+        assert!(is_clearly_best(Some(&best), Some(&second), "f3f7", 200));
+        assert!(!is_clearly_best(Some(&best), Some(&second), "c4f7", 200));
+        let close = ("f3f7".to_string(), 200);
+        assert!(!is_clearly_best(Some(&close), Some(&second), "f3f7", 200));
+        assert!(is_clearly_best(Some(&close), None, "f3f7", 200));
+        assert!(!is_clearly_best(None, None, "f3f7", 200));
+    }
+}
@@ -0,0 +1,280 @@
+//! Registry of recently opened databases, PGN files and positions.
+//!
+//! The list lives in `recents.json` in the app data directory rather than in a window's local
+//! storage, so every window sees the same list and a command-line front end can read it through
+//! `RecentsRegistry` without a running app. Pinned items stay at the top and are never evicted or
+//! cleared; the other items are kept most recent first, up to `MAX_RECENTS`. Every change is
+//! broadcast as a `recents_changed` event carrying the new list.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Emitter, Manager};
+
+use crate::error::Error;
+
+/// Unpinned items kept in the registry.
+pub const MAX_RECENTS: usize = 20;
+
+/// Serializes read-modify-write cycles of commands issued by different windows.
+static RECENTS_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum RecentKind {
+    Database,
+    Pgn,
+    Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentItem {
+    pub kind: RecentKind,
+    /// File path for databases and PGN files, FEN for positions.
+    pub value: String,
+    /// Display name, e.g. the database title.
+    pub label: Option<String>,
+    /// RFC 3339 timestamp of the last time the item was opened.
+    pub opened_at: String,
+    pub pinned: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecentsRegistry {
+    items: Vec<RecentItem>,
+}
+
+impl RecentsRegistry {
+    pub fn load_from(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::PackageManager(format!("Invalid recents registry: {}", e)))
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::PackageManager(format!("Failed to serialize recents: {}", e)))?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Pinned items first, then the rest, most recently opened first.
+    pub fn items(&self) -> &[RecentItem] {
+        &self.items
+    }
+
+    fn position(&self, kind: RecentKind, value: &str) -> Option<usize> {
+        self.items
+            .iter()
+            .position(|item| item.kind == kind && item.value == value)
+    }
+
+    /// Move an item to the top of its group, adding it if needed.
+    pub fn record(
+        &mut self,
+        kind: RecentKind,
+        value: String,
+        label: Option<String>,
+        opened_at: String,
+    ) {
+        let pinned = match self.position(kind, &value) {
+            Some(index) => self.items.remove(index).pinned,
+            None => false,
+        };
+        let item = RecentItem {
+            kind,
+            value,
+            label,
+            opened_at,
+            pinned,
+        };
+        let index = if pinned {
+            0
+        } else {
+            self.items.iter().take_while(|item| item.pinned).count()
+        };
+        self.items.insert(index, item);
+        self.evict();
+    }
+
+    /// Pin or unpin an item. Returns whether the item exists.
+    pub fn pin(&mut self, kind: RecentKind, value: &str, pinned: bool) -> bool {
+        let Some(index) = self.position(kind, value) else {
+            return false;
+        };
+        let mut item = self.items.remove(index);
+        item.pinned = pinned;
+        let (pinned_items, recent): (Vec<RecentItem>, Vec<RecentItem>) =
+            self.items.drain(..).partition(|item| item.pinned);
+        self.items = pinned_items;
+        if pinned {
+            self.items.insert(0, item);
+            self.items.extend(recent);
+        } else {
+            // Unpinned items are ordered by when they were last opened.
+            self.items.extend(recent);
+            let at = self
+                .items
+                .iter()
+                .position(|other| !other.pinned && other.opened_at < item.opened_at)
+                .unwrap_or(self.items.len());
+            self.items.insert(at, item);
+        }
+        self.evict();
+        true
+    }
+
+    /// Remove every unpinned item.
+    pub fn clear(&mut self) {
+        self.items.retain(|item| item.pinned);
+    }
+
+    fn evict(&mut self) {
+        let mut unpinned = 0;
+        self.items.retain(|item| {
+            if item.pinned {
+                return true;
+            }
+            unpinned += 1;
+            unpinned <= MAX_RECENTS
+        });
+    }
+}
+
+fn registry_path(app: &AppHandle) -> Result<PathBuf, Error> {
+    Ok(app.path().resolve("recents.json", BaseDirectory::AppData)?)
+}
+
+/// Files are keyed by their canonical path, so a database opened through different relative
+/// paths appears once.
+fn item_key(kind: RecentKind, value: String) -> String {
+    match kind {
+        RecentKind::Database | RecentKind::Pgn => fs::canonicalize(&value)
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or(value),
+        RecentKind::Position => value.trim().to_string(),
+    }
+}
+
+/// Load the registry, apply `change` and save and broadcast the result if it reports a change.
+fn update(
+    app: &AppHandle,
+    change: impl FnOnce(&mut RecentsRegistry) -> bool,
+) -> Result<Vec<RecentItem>, Error> {
+    let _guard = RECENTS_LOCK
+        .lock()
+        .map_err(|e| Error::MutexLockFailed(format!("Failed to lock recents: {}", e)))?;
+    let path = registry_path(app)?;
+    let mut registry = RecentsRegistry::load_from(&path)?;
+    if change(&mut registry) {
+        registry.save_to(&path)?;
+        let _ = app.emit("recents_changed", registry.items());
+    }
+    Ok(registry.items)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_recent_items(app: AppHandle) -> Result<Vec<RecentItem>, Error> {
+    update(&app, |_| false)
+}
+
+/// Record that an item was opened, moving it to the top of the list.
+#[tauri::command]
+#[specta::specta]
+pub fn add_recent_item(
+    app: AppHandle,
+    kind: RecentKind,
+    value: String,
+    label: Option<String>,
+) -> Result<Vec<RecentItem>, Error> {
+    let value = item_key(kind, value);
+    update(&app, |registry| {
+        registry.record(kind, value, label, chrono::Utc::now().to_rfc3339());
+        true
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn pin_item(
+    app: AppHandle,
+    kind: RecentKind,
+    value: String,
+    pinned: bool,
+) -> Result<Vec<RecentItem>, Error> {
+    let value = item_key(kind, value);
+    update(&app, |registry| registry.pin(kind, &value, pinned))
+}
+
+/// Forget every item that is not pinned.
+#[tauri::command]
+#[specta::specta]
+pub fn clear_recents(app: AppHandle) -> Result<Vec<RecentItem>, Error> {
+    update(&app, |registry| {
+        registry.clear();
+        true
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(registry: &RecentsRegistry) -> Vec<&str> {
+        registry.items().iter().map(|item| item.value.as_str()).collect()
+    }
+
+    fn record(registry: &mut RecentsRegistry, value: &str, opened_at: &str) {
+        registry.record(
+            RecentKind::Database,
+            value.to_string(),
+            None,
+            opened_at.to_string(),
+        );
+    }
+
+    #[test]
+    fn test_record_and_pin() {
+        let mut registry = RecentsRegistry::default();
+        record(&mut registry, "a.db3", "2024-01-01");
+        record(&mut registry, "b.db3", "2024-01-02");
+        record(&mut registry, "c.db3", "2024-01-03");
+        assert_eq!(values(&registry), vec!["c.db3", "b.db3", "a.db3"]);
+
+        assert!(registry.pin(RecentKind::Database, "a.db3", true));
+        record(&mut registry, "b.db3", "2024-01-04");
+        assert_eq!(values(&registry), vec!["a.db3", "b.db3", "c.db3"]);
+
+        assert!(registry.pin(RecentKind::Database, "a.db3", false));
+        assert_eq!(values(&registry), vec!["b.db3", "c.db3", "a.db3"]);
+        assert!(!registry.pin(RecentKind::Pgn, "a.db3", true));
+    }
+
+    #[test]
+    fn test_evict_and_clear() {
+        let mut registry = RecentsRegistry::default();
+        record(&mut registry, "pinned.db3", "2024-01-01");
+        registry.pin(RecentKind::Database, "pinned.db3", true);
+        for i in 0..MAX_RECENTS + 5 {
+            record(&mut registry, &format!("{}.db3", i), "2024-01-02");
+        }
+        assert_eq!(registry.items().len(), MAX_RECENTS + 1);
+        assert_eq!(registry.items()[0].value, "pinned.db3");
+
+        registry.clear();
+        assert_eq!(values(&registry), vec!["pinned.db3"]);
+    }
+}
@@ -0,0 +1,224 @@
+//! Regional formatting of dates and numbers for files generated by the backend.
+//!
+//! PGN keeps its own `YYYY.MM.DD` dates and engine output uses `.` decimals, but reports and
+//! spreadsheets are read by people, so exports render them with the conventions of the user's
+//! country. The country defaults to the one detected from the system locale and can be
+//! overridden from the settings.
+
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use specta::Type;
+
+use crate::{error::Error, telemetry::get_user_country_from_locale};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum DateOrder {
+    DayMonthYear,
+    MonthDayYear,
+    YearMonthDay,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionalFormat {
+    pub country: Option<String>,
+    pub date_order: DateOrder,
+    pub date_separator: char,
+    pub decimal_separator: char,
+    pub thousands_separator: char,
+}
+
+impl Default for RegionalFormat {
+    fn default() -> Self {
+        Self {
+            country: None,
+            date_order: DateOrder::YearMonthDay,
+            date_separator: '-',
+            decimal_separator: '.',
+            thousands_separator: ',',
+        }
+    }
+}
+
+impl RegionalFormat {
+    /// Conventions for an ISO 3166 alpha-2 country code. Unknown countries get ISO dates and
+    /// `.` decimals.
+    pub fn for_country(code: &str) -> Self {
+        let code = code.trim().to_uppercase();
+        let (date_order, date_separator) = match code.as_str() {
+            "US" | "PH" | "BZ" | "FM" => (DateOrder::MonthDayYear, '/'),
+            "CN" | "JP" | "TW" | "ZA" => (DateOrder::YearMonthDay, '/'),
+            "SE" | "LT" | "CA" => (DateOrder::YearMonthDay, '-'),
+            "HU" | "KR" => (DateOrder::YearMonthDay, '.'),
+            "DE" | "AT" | "CH" | "RU" | "UA" | "BY" | "PL" | "CZ" | "SK" | "NO" | "FI" | "DK"
+            | "TR" | "RO" | "BG" | "HR" | "SI" | "RS" | "EE" | "LV" | "AZ" | "KZ" | "IS" => {
+                (DateOrder::DayMonthYear, '.')
+            }
+            "NL" | "IN" => (DateOrder::DayMonthYear, '-'),
+            "" => return Self::default(),
+            _ => (DateOrder::DayMonthYear, '/'),
+        };
+        let (decimal_separator, thousands_separator) = match code.as_str() {
+            "CH" | "LI" => ('.', '\''),
+            "FR" | "SE" | "NO" | "FI" | "CZ" | "SK" | "PL" | "RU" | "UA" | "BY" | "HU" | "BG"
+            | "LT" | "LV" | "EE" | "PT" | "KZ" | "ZA" => (',', ' '),
+            "DE" | "AT" | "ES" | "IT" | "NL" | "BE" | "DK" | "BR" | "AR" | "CL" | "CO" | "UY"
+            | "PY" | "VE" | "EC" | "BO" | "TR" | "GR" | "RO" | "HR" | "SI" | "RS" | "ID" | "VN"
+            | "IS" | "AZ" => (',', '.'),
+            _ => ('.', ','),
+        };
+        Self {
+            country: Some(code),
+            date_order,
+            date_separator,
+            decimal_separator,
+            thousands_separator,
+        }
+    }
+
+    /// Field delimiter for CSV exports, so decimal commas do not split columns.
+    pub fn csv_delimiter(&self) -> u8 {
+        if self.decimal_separator == ',' {
+            b';'
+        } else {
+            b','
+        }
+    }
+
+    /// Render a PGN date (`YYYY.MM.DD`, with `??` for unknown parts). Partially known dates
+    /// keep only the known components; unparseable dates are returned unchanged.
+    pub fn format_date(&self, date: &str) -> String {
+        let parts: Vec<&str> = date.trim().split(['.', '-', '/']).collect();
+        let known = |part: Option<&&str>| {
+            part.filter(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+                .copied()
+        };
+        let Some(year) = known(parts.first()) else {
+            return date.to_string();
+        };
+        let month = known(parts.get(1));
+        let day = month.and(known(parts.get(2)));
+
+        let components: Vec<&str> = match (self.date_order, month) {
+            (_, None) => vec![year],
+            (DateOrder::YearMonthDay, Some(month)) => [year, month].into_iter().chain(day).collect(),
+            (DateOrder::DayMonthYear, Some(month)) => day.into_iter().chain([month, year]).collect(),
+            (DateOrder::MonthDayYear, Some(month)) => {
+                [month].into_iter().chain(day).chain([year]).collect()
+            }
+        };
+        components.join(&self.date_separator.to_string())
+    }
+
+    /// Render a number with `decimals` fractional digits and grouped thousands.
+    pub fn format_number(&self, value: f64, decimals: usize) -> String {
+        let formatted = format!("{:.*}", decimals, value.abs());
+        let (int_part, frac_part) = match formatted.split_once('.') {
+            Some((i, f)) => (i, Some(f)),
+            None => (formatted.as_str(), None),
+        };
+
+        let mut out = String::with_capacity(formatted.len() + int_part.len() / 3 + 1);
+        if value.is_sign_negative() && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            out.push('-');
+        }
+        for (i, c) in int_part.chars().enumerate() {
+            if i > 0 && (int_part.len() - i) % 3 == 0 {
+                out.push(self.thousands_separator);
+            }
+            out.push(c);
+        }
+        if let Some(frac) = frac_part {
+            out.push(self.decimal_separator);
+            out.push_str(frac);
+        }
+        out
+    }
+
+    /// Swap the decimal point of an already formatted number (e.g. a stored evaluation).
+    pub fn localize_decimal(&self, value: &str) -> String {
+        value.replace('.', &self.decimal_separator.to_string())
+    }
+}
+
+static REGIONAL_FORMAT: Lazy<RwLock<RegionalFormat>> = Lazy::new(|| {
+    RwLock::new(
+        get_user_country_from_locale()
+            .map(|country| RegionalFormat::for_country(&country))
+            .unwrap_or_default(),
+    )
+});
+
+pub fn current_format() -> RegionalFormat {
+    REGIONAL_FORMAT
+        .read()
+        .map(|f| f.clone())
+        .unwrap_or_default()
+}
+
+/// Override the country used for exports. `None` goes back to the detected system locale.
+#[tauri::command]
+#[specta::specta]
+pub fn set_regional_country(country: Option<String>) -> Result<RegionalFormat, Error> {
+    let format = match country.or_else(get_user_country_from_locale) {
+        Some(country) => RegionalFormat::for_country(&country),
+        None => RegionalFormat::default(),
+    };
+    let mut current = REGIONAL_FORMAT
+        .write()
+        .map_err(|e| Error::MutexLockFailed(e.to_string()))?;
+    *current = format.clone();
+    Ok(format)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_regional_format() -> RegionalFormat {
+    current_format()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn format_game_date(date: String) -> String {
+    current_format().format_date(&date)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn format_locale_number(value: f64, decimals: u32) -> String {
+    current_format().format_number(value, decimals as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        let us = RegionalFormat::for_country("us");
+        let de = RegionalFormat::for_country("DE");
+        let se = RegionalFormat::for_country("SE");
+        assert_eq!(us.format_date("2023.05.17"), "05/17/2023");
+        assert_eq!(de.format_date("2023.05.17"), "17.05.2023");
+        assert_eq!(se.format_date("2023.05.17"), "2023-05-17");
+        assert_eq!(de.format_date("2023.05.??"), "05.2023");
+        assert_eq!(us.format_date("2023.??.??"), "2023");
+        assert_eq!(us.format_date("????.??.??"), "????.??.??");
+    }
+
+    #[test]
+    fn test_format_number() {
+        let us = RegionalFormat::for_country("US");
+        let de = RegionalFormat::for_country("DE");
+        let fr = RegionalFormat::for_country("FR");
+        assert_eq!(us.format_number(1234567.891, 2), "1,234,567.89");
+        assert_eq!(de.format_number(-1234.5, 1), "-1.234,5");
+        assert_eq!(fr.format_number(2500.0, 0), "2 500");
+        assert_eq!(us.format_number(-0.001, 2), "0.00");
+        assert_eq!(de.csv_delimiter(), b';');
+        assert_eq!(de.localize_decimal("-0.45"), "-0,45");
+    }
+}
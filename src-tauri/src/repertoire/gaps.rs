@@ -0,0 +1,261 @@
+//! Gaps in an opening repertoire, measured against a reference database.
+//!
+//! For every position of the repertoire where the opponent is to move and at least one reply is
+//! prepared, the reference games are used to count how often each reply is played. Replies above
+//! the frequency threshold that lead to a position without a prepared answer are reported, with
+//! the moves played most often from there as candidates.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use diesel::{connection::DefaultLoadingMode, prelude::*};
+use serde::Serialize;
+use shakmaty::{
+    fen::Fen,
+    san::San,
+    zobrist::{Zobrist64, ZobristHash},
+    CastlingMode, Chess, Color, EnPassantMode, FromSetup, Position,
+};
+use specta::Type;
+
+use crate::{
+    db::{encoding::extract_main_line_moves, get_db_or_create, schema::games, ConnectionOptions},
+    error::Result,
+    repertoire::{parse_color, repertoire_positions, RepertoirePosition, RepertoireRef},
+    AppState,
+};
+
+/// Candidate moves returned per gap.
+const MAX_CANDIDATES: usize = 5;
+
+#[derive(Debug, Clone, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CandidateMove {
+    pub san: String,
+    pub games: i32,
+    /// Score of the repertoire side in these games, between 0 and 1.
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RepertoireGap {
+    /// Position before the opponent's reply.
+    pub parent_fen: String,
+    /// The opponent's reply (SAN).
+    pub reply: String,
+    /// Position after the reply, where the repertoire has no move.
+    pub fen: String,
+    /// Reference games in which the reply was played.
+    pub games: i32,
+    /// Share of the reference games from `parent_fen` that continued with the reply.
+    pub frequency: f64,
+    pub candidates: Vec<CandidateMove>,
+}
+
+#[derive(Default)]
+pub(super) struct MoveTally {
+    pub games: i32,
+    pub points: f64,
+}
+
+#[derive(Default)]
+pub(super) struct ReplyTally {
+    pub san: String,
+    pub games: i32,
+    pub fen: String,
+}
+
+#[derive(Default)]
+pub(super) struct ParentTally {
+    pub games: i32,
+    /// Replies played in the reference games, keyed by the position they lead to.
+    pub replies: HashMap<Zobrist64, ReplyTally>,
+}
+
+/// How the reference games continue from the opponent positions of a repertoire.
+pub(super) struct ReferenceTallies {
+    /// Replies played from each opponent position with a prepared reply.
+    pub parents: HashMap<Zobrist64, ParentTally>,
+    /// Answers played after each reply that leaves the repertoire, with the repertoire side's
+    /// score.
+    pub answers: HashMap<Zobrist64, HashMap<String, MoveTally>>,
+}
+
+fn start_position(fen: Option<&str>) -> Option<Chess> {
+    match fen {
+        Some(fen) => {
+            let fen: Fen = fen.parse().ok()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960).ok()
+        }
+        None => Some(Chess::default()),
+    }
+}
+
+fn ply_of(fen: &str) -> u32 {
+    fen.parse::<Fen>()
+        .map(|fen| {
+            let setup = fen.into_setup();
+            (setup.fullmoves.get() - 1) * 2 + (setup.turn == Color::Black) as u32
+        })
+        .unwrap_or(0)
+}
+
+fn score_for(result: Option<&str>, color: Color) -> f64 {
+    let white = match result {
+        Some("1-0") => 1.0,
+        Some("0-1") => 0.0,
+        _ => 0.5,
+    };
+    match color {
+        Color::White => white,
+        Color::Black => 1.0 - white,
+    }
+}
+
+/// Replay the reference games through the opening and tally the opponent replies from the
+/// positions in `parents`, and the answers to replies leading outside `prepared`. Only the plies
+/// up to the deepest repertoire position are looked at.
+pub(super) fn tally_reference_games(
+    db: &mut SqliteConnection,
+    color: Color,
+    prepared: &HashMap<Zobrist64, RepertoirePosition>,
+    parents: &HashMap<Zobrist64, RepertoirePosition>,
+) -> Result<ReferenceTallies> {
+    let max_ply = parents.values().map(|p| ply_of(&p.fen)).max().unwrap_or(0) + 2;
+    let mut parent_tallies: HashMap<Zobrist64, ParentTally> = HashMap::new();
+    let mut answers: HashMap<Zobrist64, HashMap<String, MoveTally>> = HashMap::new();
+
+    let rows = games::table
+        .select((games::moves, games::fen, games::result))
+        .load_iter::<(Vec<u8>, Option<String>, Option<String>), DefaultLoadingMode>(db)?;
+    for row in rows {
+        let (moves, fen, result) = row?;
+        let Some(mut position) = start_position(fen.as_deref()) else {
+            continue;
+        };
+        let Ok(moves) = extract_main_line_moves(&moves, Some(position.clone())) else {
+            continue;
+        };
+        let score = score_for(result.as_deref(), color);
+        // The position just after an opponent reply from a repertoire position.
+        let mut after_reply: Option<Zobrist64> = None;
+
+        for m in moves.iter().take(max_ply as usize) {
+            let hash: Zobrist64 = position.zobrist_hash(EnPassantMode::Legal);
+            if let Some(reply_hash) = after_reply.take() {
+                let san = San::from_move(&position, m).to_string();
+                let tally = answers.entry(reply_hash).or_default().entry(san).or_default();
+                tally.games += 1;
+                tally.points += score;
+            }
+
+            let before = position.clone();
+            position.play_unchecked(m);
+            if position.turn() == color && parents.contains_key(&hash) {
+                let reply_hash: Zobrist64 = position.zobrist_hash(EnPassantMode::Legal);
+                let parent = parent_tallies.entry(hash).or_default();
+                parent.games += 1;
+                let reply = parent.replies.entry(reply_hash).or_insert_with(|| ReplyTally {
+                    san: San::from_move(&before, m).to_string(),
+                    fen: Fen::from_position(position.clone(), EnPassantMode::Legal).to_string(),
+                    ..Default::default()
+                });
+                reply.games += 1;
+                if !prepared.contains_key(&reply_hash) {
+                    after_reply = Some(reply_hash);
+                }
+            }
+        }
+    }
+
+    Ok(ReferenceTallies {
+        parents: parent_tallies,
+        answers,
+    })
+}
+
+/// Popular opponent replies in `reference_db` for which `repertoire` (played as `color`) has no
+/// prepared answer. `min_frequency` is a share between 0 and 1.
+#[tauri::command]
+#[specta::specta]
+pub async fn find_repertoire_gaps(
+    repertoire: RepertoireRef,
+    color: String,
+    reference_db: PathBuf,
+    min_frequency: f64,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<RepertoireGap>> {
+    let color = parse_color(Some(&color))?.unwrap_or(Color::White);
+    let pgn = repertoire.read(&app)?;
+    let prepared = repertoire_positions(&pgn[..], color)?;
+    // Opponent positions with at least one prepared reply.
+    let parents = repertoire_positions(&pgn[..], !color)?;
+    if parents.is_empty() {
+        return Ok(Vec::new());
+    }
+    let db = &mut get_db_or_create(&state, reference_db.to_str().unwrap(), ConnectionOptions::default())?;
+    let tallies = tally_reference_games(db, color, &prepared, &parents)?;
+
+    let mut gaps = Vec::new();
+    for (parent_hash, parent) in tallies.parents {
+        for (reply_hash, reply) in parent.replies {
+            let frequency = reply.games as f64 / parent.games as f64;
+            if frequency < min_frequency || prepared.contains_key(&reply_hash) {
+                continue;
+            }
+            let mut candidates: Vec<CandidateMove> = tallies
+                .answers
+                .get(&reply_hash)
+                .map(|moves| {
+                    moves
+                        .iter()
+                        .map(|(san, tally)| CandidateMove {
+                            san: san.clone(),
+                            games: tally.games,
+                            score: tally.points / tally.games as f64,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            candidates.sort_by(|a, b| b.games.cmp(&a.games).then(b.score.total_cmp(&a.score)));
+            candidates.truncate(MAX_CANDIDATES);
+
+            gaps.push(RepertoireGap {
+                parent_fen: parents[&parent_hash].fen.clone(),
+                reply: reply.san,
+                fen: reply.fen,
+                games: reply.games,
+                frequency,
+                candidates,
+            });
+        }
+    }
+    gaps.sort_by(|a, b| b.games.cmp(&a.games));
+    log::info!(
+        "Found {} repertoire gaps in {} against {}",
+        gaps.len(),
+        repertoire,
+        reference_db.display()
+    );
+    Ok(gaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ply_of() {
+        assert_eq!(ply_of("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), 0);
+        assert_eq!(ply_of("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"), 1);
+        assert_eq!(ply_of("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"), 3);
+    }
+
+    #[test]
+    fn test_score_for() {
+        assert_eq!(score_for(Some("1-0"), Color::White), 1.0);
+        assert_eq!(score_for(Some("1-0"), Color::Black), 0.0);
+        assert_eq!(score_for(Some("*"), Color::Black), 0.5);
+    }
+}
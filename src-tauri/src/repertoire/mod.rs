@@ -0,0 +1,302 @@
+//! Opening repertoires, and the checks and training built on them.
+//!
+//! A repertoire is a PGN file whose games and variations describe the moves the user intends to
+//! play, or a repertoire kept in `repertoires.db3` for spaced-repetition training. `RepertoireRef`
+//! names either; stored repertoires are read as PGN with one game per prepared move, so the
+//! commands analysing a repertoire only deal with PGN. Different lines can transpose into the
+//! same position; if they recommend different moves there the preparation is inconsistent.
+//! Positions are keyed by their Zobrist hash, so move order does not matter.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::Read,
+    path::PathBuf,
+};
+
+use pgn_reader::{BufferedReader, RawHeader, SanPlus, Skip, Visitor};
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    fen::Fen,
+    zobrist::{Zobrist64, ZobristHash},
+    CastlingMode, Chess, Color, EnPassantMode, FromSetup, Position, PositionError,
+};
+use specta::Type;
+use tauri::AppHandle;
+
+use crate::error::{Error, Result};
+
+mod gaps;
+mod spaced_repetition;
+mod study_sync;
+mod training;
+
+pub use gaps::find_repertoire_gaps;
+pub use spaced_repetition::{
+    add_repertoire_line, create_repertoire, get_due_positions, record_training_result,
+};
+pub use study_sync::sync_lichess_study;
+pub use training::{
+    end_repertoire_training, next_training_line, start_repertoire_training, submit_training_move,
+    TrainingSession,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum RepertoireRef {
+    /// Repertoire stored in `repertoires.db3`.
+    Id(i32),
+    /// PGN file.
+    File(PathBuf),
+}
+
+impl RepertoireRef {
+    /// The repertoire as PGN.
+    pub fn read(&self, app: &AppHandle) -> Result<Vec<u8>> {
+        match self {
+            Self::Id(id) => Ok(spaced_repetition::stored_repertoire_pgn(app, *id)?.into_bytes()),
+            Self::File(path) => Ok(std::fs::read(path)?),
+        }
+    }
+}
+
+impl fmt::Display for RepertoireRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "repertoire {}", id),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ConflictingMove {
+    pub san: String,
+    /// Move sequences (in SAN, from the game start) that recommend this move.
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RepertoireConflict {
+    pub fen: String,
+    pub moves: Vec<ConflictingMove>,
+}
+
+#[derive(Default)]
+struct PositionEntry {
+    fen: String,
+    /// Recommended move -> lines reaching the position and playing it.
+    moves: BTreeMap<String, Vec<String>>,
+}
+
+struct ConflictCollector {
+    color: Option<Color>,
+    positions: HashMap<Zobrist64, PositionEntry>,
+    start: Chess,
+    pos: Chess,
+    prev: Chess,
+    line: Vec<String>,
+    stack: Vec<(Chess, Chess, Vec<String>)>,
+    invalid: bool,
+}
+
+impl ConflictCollector {
+    fn new(color: Option<Color>) -> Self {
+        Self {
+            color,
+            positions: HashMap::new(),
+            start: Chess::default(),
+            pos: Chess::default(),
+            prev: Chess::default(),
+            line: Vec::new(),
+            stack: Vec::new(),
+            invalid: false,
+        }
+    }
+
+    fn into_conflicts(self) -> Vec<RepertoireConflict> {
+        let mut conflicts: Vec<RepertoireConflict> = self
+            .positions
+            .into_values()
+            .filter(|entry| entry.moves.len() > 1)
+            .map(|entry| RepertoireConflict {
+                fen: entry.fen,
+                moves: entry
+                    .moves
+                    .into_iter()
+                    .map(|(san, lines)| ConflictingMove { san, lines })
+                    .collect(),
+            })
+            .collect();
+        conflicts.sort_by(|a, b| a.fen.cmp(&b.fen));
+        conflicts
+    }
+}
+
+impl Visitor for ConflictCollector {
+    type Result = ();
+
+    fn begin_game(&mut self) {
+        self.start = Chess::default();
+        self.line.clear();
+        self.stack.clear();
+        self.invalid = false;
+    }
+
+    fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
+        if key == b"FEN" {
+            let setup = Fen::from_ascii(value.as_bytes())
+                .ok()
+                .and_then(|fen| {
+                    Chess::from_setup(fen.into_setup(), CastlingMode::Chess960)
+                        .or_else(PositionError::ignore_too_much_material)
+                        .ok()
+                });
+            match setup {
+                Some(position) => self.start = position,
+                None => self.invalid = true,
+            }
+        }
+    }
+
+    fn end_headers(&mut self) -> Skip {
+        self.pos = self.start.clone();
+        self.prev = self.start.clone();
+        Skip(self.invalid)
+    }
+
+    fn san(&mut self, san_plus: SanPlus) {
+        if self.invalid {
+            return;
+        }
+        let Ok(m) = san_plus.san.to_move(&self.pos) else {
+            self.invalid = true;
+            return;
+        };
+        let san = san_plus.to_string();
+
+        if self.color.is_none_or(|c| c == self.pos.turn()) {
+            let hash: Zobrist64 = self.pos.zobrist_hash(EnPassantMode::Legal);
+            let entry = self.positions.entry(hash).or_insert_with(|| PositionEntry {
+                fen: Fen::from_position(self.pos.clone(), EnPassantMode::Legal).to_string(),
+                ..Default::default()
+            });
+            entry
+                .moves
+                .entry(san.clone())
+                .or_default()
+                .push(self.line.join(" "));
+        }
+
+        self.prev = self.pos.clone();
+        self.pos.play_unchecked(&m);
+        self.line.push(san);
+    }
+
+    fn begin_variation(&mut self) -> Skip {
+        self.stack
+            .push((self.pos.clone(), self.prev.clone(), self.line.clone()));
+        // A variation replaces the last move played.
+        self.pos = self.prev.clone();
+        self.line.pop();
+        Skip(self.invalid)
+    }
+
+    fn end_variation(&mut self) {
+        if let Some((pos, prev, line)) = self.stack.pop() {
+            self.pos = pos;
+            self.prev = prev;
+            self.line = line;
+        }
+    }
+
+    fn end_game(&mut self) -> Self::Result {}
+}
+
+pub(crate) fn parse_color(color: Option<&str>) -> Result<Option<Color>> {
+    match color {
+        None => Ok(None),
+        Some("white") => Ok(Some(Color::White)),
+        Some("black") => Ok(Some(Color::Black)),
+        Some(other) => Err(Error::PackageManager(format!("Invalid repertoire color: {}", other))),
+    }
+}
+
+pub struct RepertoirePosition {
+    pub fen: String,
+    /// Recommended moves (SAN).
+    pub moves: Vec<String>,
+}
+
+/// Every position of the repertoire where `color` is to move.
+pub fn repertoire_positions<R: Read>(
+    reader: R,
+    color: Color,
+) -> Result<HashMap<Zobrist64, RepertoirePosition>> {
+    let mut collector = ConflictCollector::new(Some(color));
+    let mut reader = BufferedReader::new(reader);
+    while reader.read_game(&mut collector)?.is_some() {}
+    Ok(collector
+        .positions
+        .into_iter()
+        .map(|(hash, entry)| {
+            let position = RepertoirePosition {
+                fen: entry.fen,
+                moves: entry.moves.into_keys().collect(),
+            };
+            (hash, position)
+        })
+        .collect())
+}
+
+/// Recommended moves (SAN) for every position of the repertoire where `color` is to move.
+pub fn repertoire_moves<R: Read>(reader: R, color: Color) -> Result<HashMap<Zobrist64, Vec<String>>> {
+    Ok(repertoire_positions(reader, color)?
+        .into_iter()
+        .map(|(hash, position)| (hash, position.moves))
+        .collect())
+}
+
+fn find_conflicts<R: Read>(reader: R, color: Option<Color>) -> Result<Vec<RepertoireConflict>> {
+    let mut collector = ConflictCollector::new(color);
+    let mut reader = BufferedReader::new(reader);
+    while reader.read_game(&mut collector)?.is_some() {}
+    Ok(collector.into_conflicts())
+}
+
+/// Find positions in a repertoire where transposing lines recommend different moves. `color`
+/// restricts the check to positions where that side is to move (the repertoire side); without it
+/// both sides are checked.
+#[tauri::command]
+#[specta::specta]
+pub async fn detect_conflicts(
+    app: AppHandle,
+    repertoire: RepertoireRef,
+    color: Option<String>,
+) -> Result<Vec<RepertoireConflict>> {
+    let color = parse_color(color.as_deref())?;
+    let pgn = repertoire.read(&app)?;
+    tokio::task::spawn_blocking(move || find_conflicts(&pgn[..], color))
+        .await
+        .map_err(|e| Error::PackageManager(format!("Conflict detection task failed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transposition_conflict() {
+        let pgn = b"1. d4 Nf6 2. c4 e6 (2... g6) 3. Nc3 *\n\n1. d4 e6 2. c4 Nf6 3. Nc3 *\n";
+        let conflicts = find_conflicts(&pgn[..], Some(Color::White)).unwrap();
+        assert!(conflicts.is_empty());
+
+        let pgn = b"1. d4 Nf6 2. c4 e6 3. Nc3 *\n\n1. d4 e6 2. c4 Nf6 3. Nf3 *\n";
+        let conflicts = find_conflicts(&pgn[..], Some(Color::White)).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        let moves: Vec<&str> = conflicts[0].moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(moves, vec!["Nc3", "Nf3"]);
+    }
+}
@@ -0,0 +1,589 @@
+//! Spaced-repetition drilling of repertoire lines.
+//!
+//! Repertoires and their positions live in `repertoires.db3` in the app data directory. Adding a
+//! line stores every position of it where the repertoire side is to move, together with the
+//! prepared move; lines that transpose share the position and may add alternative moves. Each
+//! position is scheduled with SM-2: recalling it well stretches its interval by its ease factor,
+//! failing it resets the interval to a day, lowers the ease and counts a lapse, so the positions
+//! that keep being forgotten come back most often.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use diesel::{connection::SimpleConnection, prelude::*, sqlite::SqliteConnection};
+use serde::Serialize;
+use shakmaty::{
+    fen::{Epd, Fen},
+    san::SanPlus,
+    Chess, Color, EnPassantMode, Position,
+};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+
+use crate::{
+    db::WriteGuard,
+    error::{Error, Result},
+    repertoire::parse_color,
+    AppState,
+};
+
+diesel::table! {
+    repertoires (id) {
+        id -> Integer,
+        name -> Text,
+        color -> Text,
+        created_at -> Text,
+    }
+}
+
+diesel::table! {
+    repertoire_positions (id) {
+        id -> Integer,
+        repertoire_id -> Integer,
+        epd -> Text,
+        fen -> Text,
+        moves -> Text,
+        line -> Text,
+        ease_factor -> Double,
+        interval_days -> Integer,
+        repetitions -> Integer,
+        lapses -> Integer,
+        due_at -> Text,
+        last_reviewed_at -> Nullable<Text>,
+    }
+}
+
+/// Ease factor of a position that was never reviewed.
+const INITIAL_EASE: f64 = 2.5;
+/// SM-2 never lets the ease factor drop below this.
+const MIN_EASE: f64 = 1.3;
+/// Lowest answer quality that counts as recalled.
+const PASSING_QUALITY: u8 = 3;
+/// Due positions returned by `get_due_positions` without a limit.
+const DEFAULT_DUE_LIMIT: i64 = 20;
+
+fn repertoires_db_path(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app
+        .path()
+        .resolve("repertoires.db3", BaseDirectory::AppData)?)
+}
+
+/// Write access to `repertoires.db3`, so windows changing it at the same time take turns.
+async fn lock_repertoires_db(app: &AppHandle, operation: &'static str) -> Result<WriteGuard> {
+    let db_path = repertoires_db_path(app)?;
+    Ok(app
+        .state::<AppState>()
+        .db_writes
+        .lock(&db_path, operation)
+        .await)
+}
+
+fn get_repertoires_db(app: &AppHandle) -> Result<SqliteConnection> {
+    let db_path = repertoires_db_path(app)?;
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut conn = SqliteConnection::establish(&db_path.to_string_lossy())?;
+    init_repertoires_db(&mut conn)?;
+    Ok(conn)
+}
+
+fn init_repertoires_db(conn: &mut SqliteConnection) -> Result<()> {
+    conn.batch_execute(
+        r#"
+        PRAGMA foreign_keys = ON;
+
+        CREATE TABLE IF NOT EXISTS repertoires (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            color TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS repertoire_positions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            repertoire_id INTEGER NOT NULL,
+            epd TEXT NOT NULL,
+            fen TEXT NOT NULL,
+            moves TEXT NOT NULL,
+            line TEXT NOT NULL,
+            ease_factor REAL NOT NULL,
+            interval_days INTEGER NOT NULL,
+            repetitions INTEGER NOT NULL,
+            lapses INTEGER NOT NULL,
+            due_at TEXT NOT NULL,
+            last_reviewed_at TEXT,
+            UNIQUE (repertoire_id, epd),
+            FOREIGN KEY (repertoire_id) REFERENCES repertoires(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_repertoire_positions_due ON repertoire_positions(repertoire_id, due_at);
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Timestamps are stored with whole seconds in UTC so they compare as strings.
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct Repertoire {
+    pub id: i32,
+    pub name: String,
+    pub color: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AddedRepertoireLine {
+    /// Positions of the line the repertoire did not have yet.
+    pub new_positions: i32,
+    /// Known positions that got the line's move as an alternative.
+    pub new_moves: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Type, Queryable)]
+#[serde(rename_all = "camelCase")]
+pub struct DuePosition {
+    pub id: i32,
+    pub fen: String,
+    /// Prepared moves (SAN) separated by spaces; any of them is a correct answer.
+    pub moves: String,
+    /// Moves (SAN) of the first line that reached the position, from the initial position.
+    pub line: String,
+    pub ease_factor: f64,
+    pub interval_days: i32,
+    pub repetitions: i32,
+    /// Times the position was forgotten.
+    pub lapses: i32,
+    pub due_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Schedule {
+    ease_factor: f64,
+    interval_days: i32,
+    repetitions: i32,
+    lapses: i32,
+}
+
+impl Schedule {
+    /// SM-2 update for an answer of `quality`, from 0 (blackout) to 5 (perfect recall).
+    fn review(self, quality: u8) -> Self {
+        let miss = f64::from(5 - quality);
+        let ease_factor = (self.ease_factor + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+        if quality < PASSING_QUALITY {
+            return Self {
+                ease_factor,
+                interval_days: 1,
+                repetitions: 0,
+                lapses: self.lapses + 1,
+            };
+        }
+        let interval_days = match self.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (f64::from(self.interval_days) * self.ease_factor).round() as i32,
+        };
+        Self {
+            ease_factor,
+            interval_days,
+            repetitions: self.repetitions + 1,
+            lapses: self.lapses,
+        }
+    }
+}
+
+/// Positions of `line` where `color` is to move, as `(epd, fen, prepared move, line so far)`.
+fn line_positions(line: &[String], color: Color) -> Result<Vec<(String, String, String, String)>> {
+    let mut position = Chess::default();
+    let mut played: Vec<String> = Vec::with_capacity(line.len());
+    let mut positions = Vec::new();
+    for san in line {
+        let san_plus: SanPlus = san.parse()?;
+        let m = san_plus.san.to_move(&position)?;
+        if position.turn() == color {
+            positions.push((
+                Epd::from_position(position.clone(), EnPassantMode::Legal).to_string(),
+                Fen::from_position(position.clone(), EnPassantMode::Legal).to_string(),
+                san_plus.san.to_string(),
+                played.join(" "),
+            ));
+        }
+        played.push(san_plus.san.to_string());
+        position.play_unchecked(&m);
+    }
+    Ok(positions)
+}
+
+fn add_line(
+    conn: &mut SqliteConnection,
+    repertoire_id: i32,
+    line: &[String],
+    now: DateTime<Utc>,
+) -> Result<AddedRepertoireLine> {
+    let color: String = repertoires::table
+        .find(repertoire_id)
+        .select(repertoires::color)
+        .first(conn)?;
+    let color = parse_color(Some(&color))?.unwrap_or(Color::White);
+    let positions = line_positions(line, color)?;
+
+    conn.transaction(|conn| {
+        let mut added = AddedRepertoireLine {
+            new_positions: 0,
+            new_moves: 0,
+        };
+        for (epd, fen, san, played) in positions {
+            let existing: Option<(i32, String)> = repertoire_positions::table
+                .filter(repertoire_positions::repertoire_id.eq(repertoire_id))
+                .filter(repertoire_positions::epd.eq(&epd))
+                .select((repertoire_positions::id, repertoire_positions::moves))
+                .first(conn)
+                .optional()?;
+            match existing {
+                Some((_, moves)) if moves.split(' ').any(|known| known == san) => {}
+                Some((id, moves)) => {
+                    diesel::update(repertoire_positions::table.find(id))
+                        .set(repertoire_positions::moves.eq(format!("{} {}", moves, san)))
+                        .execute(conn)?;
+                    added.new_moves += 1;
+                }
+                None => {
+                    diesel::insert_into(repertoire_positions::table)
+                        .values((
+                            repertoire_positions::repertoire_id.eq(repertoire_id),
+                            repertoire_positions::epd.eq(&epd),
+                            repertoire_positions::fen.eq(&fen),
+                            repertoire_positions::moves.eq(&san),
+                            repertoire_positions::line.eq(&played),
+                            repertoire_positions::ease_factor.eq(INITIAL_EASE),
+                            repertoire_positions::interval_days.eq(0),
+                            repertoire_positions::repetitions.eq(0),
+                            repertoire_positions::lapses.eq(0),
+                            repertoire_positions::due_at.eq(timestamp(now)),
+                        ))
+                        .execute(conn)?;
+                    added.new_positions += 1;
+                }
+            }
+        }
+        Ok(added)
+    })
+}
+
+/// The lines of a repertoire as PGN, one game per prepared move in the order they were added.
+fn lines_pgn(conn: &mut SqliteConnection, repertoire_id: i32) -> Result<String> {
+    repertoires::table
+        .find(repertoire_id)
+        .select(repertoires::id)
+        .first::<i32>(conn)?;
+    let lines: Vec<(String, String)> = repertoire_positions::table
+        .filter(repertoire_positions::repertoire_id.eq(repertoire_id))
+        .order(repertoire_positions::id.asc())
+        .select((repertoire_positions::line, repertoire_positions::moves))
+        .load(conn)?;
+
+    let mut pgn = String::new();
+    for (line, moves) in lines {
+        for san in moves.split(' ') {
+            for (ply, m) in line.split_whitespace().chain([san]).enumerate() {
+                if ply % 2 == 0 {
+                    pgn.push_str(&format!("{}. ", ply / 2 + 1));
+                }
+                pgn.push_str(m);
+                pgn.push(' ');
+            }
+            pgn.push_str("*\n\n");
+        }
+    }
+    Ok(pgn)
+}
+
+/// The lines of the stored repertoire `repertoire_id` as PGN.
+pub(super) fn stored_repertoire_pgn(app: &AppHandle, repertoire_id: i32) -> Result<String> {
+    let conn = &mut get_repertoires_db(app)?;
+    lines_pgn(conn, repertoire_id)
+}
+
+const DUE_POSITION_COLUMNS: (
+    repertoire_positions::id,
+    repertoire_positions::fen,
+    repertoire_positions::moves,
+    repertoire_positions::line,
+    repertoire_positions::ease_factor,
+    repertoire_positions::interval_days,
+    repertoire_positions::repetitions,
+    repertoire_positions::lapses,
+    repertoire_positions::due_at,
+) = (
+    repertoire_positions::id,
+    repertoire_positions::fen,
+    repertoire_positions::moves,
+    repertoire_positions::line,
+    repertoire_positions::ease_factor,
+    repertoire_positions::interval_days,
+    repertoire_positions::repetitions,
+    repertoire_positions::lapses,
+    repertoire_positions::due_at,
+);
+
+/// Positions due at `now`, most overdue first and, among equally due ones, most often failed
+/// first.
+fn due_positions(
+    conn: &mut SqliteConnection,
+    repertoire_id: i32,
+    now: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<DuePosition>> {
+    Ok(repertoire_positions::table
+        .filter(repertoire_positions::repertoire_id.eq(repertoire_id))
+        .filter(repertoire_positions::due_at.le(timestamp(now)))
+        .order((
+            repertoire_positions::due_at.asc(),
+            repertoire_positions::lapses.desc(),
+            repertoire_positions::id.asc(),
+        ))
+        .select(DUE_POSITION_COLUMNS)
+        .limit(limit)
+        .load(conn)?)
+}
+
+fn record_result(
+    conn: &mut SqliteConnection,
+    position_id: i32,
+    quality: u8,
+    now: DateTime<Utc>,
+) -> Result<DuePosition> {
+    if quality > 5 {
+        return Err(Error::PackageManager(format!(
+            "Invalid training quality {}, expected 0 to 5",
+            quality
+        )));
+    }
+    let (ease_factor, interval_days, repetitions, lapses) = repertoire_positions::table
+        .find(position_id)
+        .select((
+            repertoire_positions::ease_factor,
+            repertoire_positions::interval_days,
+            repertoire_positions::repetitions,
+            repertoire_positions::lapses,
+        ))
+        .first(conn)?;
+    let schedule = Schedule {
+        ease_factor,
+        interval_days,
+        repetitions,
+        lapses,
+    }
+    .review(quality);
+
+    diesel::update(repertoire_positions::table.find(position_id))
+        .set((
+            repertoire_positions::ease_factor.eq(schedule.ease_factor),
+            repertoire_positions::interval_days.eq(schedule.interval_days),
+            repertoire_positions::repetitions.eq(schedule.repetitions),
+            repertoire_positions::lapses.eq(schedule.lapses),
+            repertoire_positions::due_at.eq(timestamp(
+                now + Duration::days(i64::from(schedule.interval_days)),
+            )),
+            repertoire_positions::last_reviewed_at.eq(timestamp(now)),
+        ))
+        .execute(conn)?;
+    Ok(repertoire_positions::table
+        .find(position_id)
+        .select(DUE_POSITION_COLUMNS)
+        .first(conn)?)
+}
+
+/// Create an empty repertoire played with `color` ("white" or "black").
+#[tauri::command]
+#[specta::specta]
+pub async fn create_repertoire(app: AppHandle, name: String, color: String) -> Result<Repertoire> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(Error::PackageManager(
+            "Repertoire name cannot be empty".to_string(),
+        ));
+    }
+    parse_color(Some(&color))?;
+    let _write = lock_repertoires_db(&app, "create_repertoire").await?;
+    let conn = &mut get_repertoires_db(&app)?;
+    let created_at = timestamp(Utc::now());
+    let id: i32 = diesel::insert_into(repertoires::table)
+        .values((
+            repertoires::name.eq(&name),
+            repertoires::color.eq(&color),
+            repertoires::created_at.eq(&created_at),
+        ))
+        .returning(repertoires::id)
+        .get_result(conn)?;
+    Ok(Repertoire {
+        id,
+        name,
+        color,
+        created_at,
+    })
+}
+
+/// Add a line, given as SAN moves from the initial position, to a repertoire. Its positions with
+/// the repertoire side to move are due for training right away.
+#[tauri::command]
+#[specta::specta]
+pub async fn add_repertoire_line(
+    app: AppHandle,
+    repertoire_id: i32,
+    moves: Vec<String>,
+) -> Result<AddedRepertoireLine> {
+    let _write = lock_repertoires_db(&app, "add_repertoire_line").await?;
+    let conn = &mut get_repertoires_db(&app)?;
+    add_line(conn, repertoire_id, &moves, Utc::now())
+}
+
+/// Positions of a repertoire due for training, at most `limit` (20 by default).
+#[tauri::command]
+#[specta::specta]
+pub fn get_due_positions(
+    app: AppHandle,
+    repertoire_id: i32,
+    limit: Option<u32>,
+) -> Result<Vec<DuePosition>> {
+    let conn = &mut get_repertoires_db(&app)?;
+    let limit = limit.map_or(DEFAULT_DUE_LIMIT, i64::from);
+    due_positions(conn, repertoire_id, Utc::now(), limit)
+}
+
+/// Reschedule a position after training it. `quality` grades the answer from 0 (forgotten) to 5
+/// (instant recall); below 3 the position counts as forgotten.
+#[tauri::command]
+#[specta::specta]
+pub async fn record_training_result(
+    app: AppHandle,
+    position_id: i32,
+    quality: u8,
+) -> Result<DuePosition> {
+    let _write = lock_repertoires_db(&app, "record_training_result").await?;
+    let conn = &mut get_repertoires_db(&app)?;
+    record_result(conn, position_id, quality, Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> SqliteConnection {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        init_repertoires_db(&mut conn).unwrap();
+        diesel::insert_into(repertoires::table)
+            .values((
+                repertoires::id.eq(1),
+                repertoires::name.eq("Sicilian"),
+                repertoires::color.eq("black"),
+                repertoires::created_at.eq("2024-01-01T00:00:00Z"),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+        conn
+    }
+
+    fn line(moves: &str) -> Vec<String> {
+        moves.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn test_sm2_schedule() {
+        let new = Schedule {
+            ease_factor: INITIAL_EASE,
+            interval_days: 0,
+            repetitions: 0,
+            lapses: 0,
+        };
+        let first = new.review(5);
+        assert_eq!((first.interval_days, first.repetitions), (1, 1));
+        assert!((first.ease_factor - 2.6).abs() < 1e-9);
+        let second = first.review(4);
+        assert_eq!(second.interval_days, 6);
+        let third = second.review(4);
+        assert_eq!(third.interval_days, 16);
+
+        let forgotten = third.review(1);
+        assert_eq!(
+            (
+                forgotten.interval_days,
+                forgotten.repetitions,
+                forgotten.lapses
+            ),
+            (1, 0, 1)
+        );
+        assert!(forgotten.ease_factor < third.ease_factor);
+        let mut hopeless = forgotten;
+        for _ in 0..10 {
+            hopeless = hopeless.review(0);
+        }
+        assert_eq!(hopeless.ease_factor, MIN_EASE);
+    }
+
+    #[test]
+    fn test_add_line_and_transpositions() {
+        let mut conn = test_db();
+        let now = at("2024-03-01T12:00:00Z");
+        let added = add_line(&mut conn, 1, &line("e4 c5 Nf3 d6 d4 cxd4"), now).unwrap();
+        assert_eq!((added.new_positions, added.new_moves), (3, 0));
+
+        // Same positions with another prepared move in one of them.
+        let added = add_line(&mut conn, 1, &line("e4 c5 Nf3 Nc6"), now).unwrap();
+        assert_eq!((added.new_positions, added.new_moves), (0, 1));
+        let added = add_line(&mut conn, 1, &line("e4 c5 Nf3 d6"), now).unwrap();
+        assert_eq!((added.new_positions, added.new_moves), (0, 0));
+
+        let due = due_positions(&mut conn, 1, now, 10).unwrap();
+        assert_eq!(due.len(), 3);
+        assert_eq!(due[0].moves, "c5");
+        assert_eq!(due[1].moves, "d6 Nc6");
+        assert_eq!(due[1].line, "e4 c5 Nf3");
+
+        assert!(add_line(&mut conn, 1, &line("e4 e4"), now).is_err());
+    }
+
+    #[test]
+    fn test_lines_pgn() {
+        let mut conn = test_db();
+        let now = at("2024-03-01T12:00:00Z");
+        add_line(&mut conn, 1, &line("e4 c5 Nf3 d6"), now).unwrap();
+        add_line(&mut conn, 1, &line("e4 c5 Nf3 Nc6"), now).unwrap();
+        assert_eq!(
+            lines_pgn(&mut conn, 1).unwrap(),
+            "1. e4 c5 *\n\n1. e4 c5 2. Nf3 d6 *\n\n1. e4 c5 2. Nf3 Nc6 *\n\n"
+        );
+        assert!(lines_pgn(&mut conn, 2).is_err());
+    }
+
+    #[test]
+    fn test_record_result_reschedules() {
+        let mut conn = test_db();
+        let now = at("2024-03-01T12:00:00Z");
+        add_line(&mut conn, 1, &line("d4 Nf6 c4 e6"), now).unwrap();
+        let due = due_positions(&mut conn, 1, now, 10).unwrap();
+        assert_eq!(due.len(), 2);
+
+        let recalled = record_result(&mut conn, due[0].id, 5, now).unwrap();
+        assert_eq!(recalled.due_at, "2024-03-02T12:00:00Z");
+        let forgotten = record_result(&mut conn, due[1].id, 1, now).unwrap();
+        assert_eq!(forgotten.lapses, 1);
+        assert!(due_positions(&mut conn, 1, now, 10).unwrap().is_empty());
+
+        let tomorrow = at("2024-03-02T12:00:00Z");
+        let due = due_positions(&mut conn, 1, tomorrow, 10).unwrap();
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[0].id, forgotten.id);
+        assert!(record_result(&mut conn, due[0].id, 6, now).is_err());
+    }
+}
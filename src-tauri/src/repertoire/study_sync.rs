@@ -0,0 +1,587 @@
+//! Two-way sync of a repertoire file with a chapter of a Lichess study.
+//!
+//! Both sides are parsed into a tree of moves with their comments and NAGs, and merged against
+//! the version agreed on at the last sync, which is kept in `study_sync.json` in the app data
+//! folder. Moves and annotations added or removed on one side are applied to the other; when both
+//! sides changed the same comment, or one side removed a line the other changed, the local version
+//! wins and the clash is reported as a conflict. The merged chapter is written back through
+//! `GameTree`, so it is plain PGN either way.
+//!
+//! The Lichess API cannot replace the content of a chapter, so pushing imports the merged PGN as a
+//! new chapter and deletes the old one; the chapter id changes on every push and is remembered for
+//! the next sync.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use pgn_reader::{BufferedReader, Nag, SanPlus};
+use reqwest::{Method, Url};
+use serde::{Deserialize, Serialize};
+use shakmaty::{fen::Fen, Chess, EnPassantMode, Position};
+use specta::Type;
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::{
+    db::pgn::{GameTree, GameTreeNode, Importer},
+    error::{Error, Result},
+    http::Request,
+    notation::NotationLocale,
+    oauth::lichess_token,
+    AppState,
+};
+
+const LICHESS: &str = "https://lichess.org";
+const SYNC_FILE: &str = "study_sync.json";
+
+/// Moves of a chapter as a tree, children keyed by SAN with the main line first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Line {
+    comment: String,
+    nags: Vec<u8>,
+    children: Vec<(String, Line)>,
+}
+
+impl Line {
+    fn child(&self, san: &str) -> Option<&Line> {
+        self.children
+            .iter()
+            .find(|(child, _)| child == san)
+            .map(|(_, line)| line)
+    }
+
+    fn child_or_insert(&mut self, san: &str) -> &mut Line {
+        let index = match self.children.iter().position(|(child, _)| child == san) {
+            Some(index) => index,
+            None => {
+                self.children.push((san.to_string(), Line::default()));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index].1
+    }
+
+    fn at(&mut self, path: &[String]) -> &mut Line {
+        path.iter()
+            .fold(self, |line, san| line.child_or_insert(san))
+    }
+}
+
+struct Chapter {
+    /// Header lines of the PGN, kept as they are.
+    headers: String,
+    start: Chess,
+    start_fen: String,
+    root: Line,
+}
+
+fn header_lines(pgn: &str) -> String {
+    pgn.lines()
+        .skip_while(|line| line.trim().is_empty())
+        .take_while(|line| line.trim_start().starts_with('['))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn result_of(headers: &str) -> &str {
+    headers
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("[Result \""))
+        .and_then(|rest| rest.strip_suffix("\"]"))
+        .unwrap_or("*")
+}
+
+fn append_comment(comment: &mut String, text: &str) {
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+    if !comment.is_empty() {
+        comment.push(' ');
+    }
+    comment.push_str(text);
+}
+
+/// Add the moves of `nodes`, played from `position` after the moves `path`, to `root`.
+fn add_nodes(
+    root: &mut Line,
+    path: &[String],
+    mut position: Chess,
+    nodes: &[GameTreeNode],
+) -> Result<()> {
+    let mut path = path.to_vec();
+    let mut before_last: Option<(Vec<String>, Chess)> = None;
+    for node in nodes {
+        match node {
+            GameTreeNode::Move(san) => {
+                let m = san.san.to_move(&position)?;
+                before_last = Some((path.clone(), position.clone()));
+                // Re-rendered so both sides agree on disambiguation and check marks.
+                let san = SanPlus::from_move_and_play_unchecked(&mut position, &m);
+                path.push(san.to_string());
+                root.at(&path);
+            }
+            GameTreeNode::Comment(comment) => append_comment(&mut root.at(&path).comment, comment),
+            GameTreeNode::Nag(nag) => root.at(&path).nags.push(nag.0),
+            GameTreeNode::Variation(variation) => {
+                if let Some((path, position)) = &before_last {
+                    add_nodes(root, path, position.clone(), variation.nodes())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_chapter(pgn: &str) -> Result<Chapter> {
+    let mut importer = Importer::new(None).lenient(true);
+    let game = BufferedReader::new_cursor(pgn.as_bytes())
+        .read_game(&mut importer)?
+        .flatten()
+        .ok_or_else(|| Error::PackageManager("No valid game in the chapter".to_string()))?;
+    let mut root = Line::default();
+    add_nodes(&mut root, &[], game.position.clone(), game.tree.nodes())?;
+    Ok(Chapter {
+        headers: header_lines(pgn),
+        start_fen: Fen::from_position(game.position.clone(), EnPassantMode::Legal).to_string(),
+        start: game.position,
+        root,
+    })
+}
+
+fn push_move(tree: &mut GameTree, san: &str, line: &Line) -> Result<()> {
+    tree.push(GameTreeNode::Move(san.parse()?));
+    for nag in &line.nags {
+        tree.push(GameTreeNode::Nag(Nag(*nag)));
+    }
+    if !line.comment.is_empty() {
+        tree.push(GameTreeNode::Comment(line.comment.clone()));
+    }
+    Ok(())
+}
+
+fn push_moves(tree: &mut GameTree, mut line: &Line) -> Result<()> {
+    while let Some(((san, main), alternatives)) = line.children.split_first() {
+        push_move(tree, san, main)?;
+        for (san, alternative) in alternatives {
+            let mut variation = GameTree::new();
+            push_move(&mut variation, san, alternative)?;
+            push_moves(&mut variation, alternative)?;
+            tree.push(GameTreeNode::Variation(variation));
+        }
+        line = main;
+    }
+    Ok(())
+}
+
+/// PGN of `root` played from the start of `chapter`, with the chapter's headers.
+fn write_chapter(chapter: &Chapter, root: &Line) -> Result<String> {
+    let mut tree = GameTree::new();
+    if !root.comment.is_empty() {
+        tree.push(GameTreeNode::Comment(root.comment.clone()));
+    }
+    push_moves(&mut tree, root)?;
+    let mut movetext = String::new();
+    tree.pretty_print_localized(
+        &mut movetext,
+        Some(chapter.start.clone()),
+        NotationLocale::English,
+    )?;
+    Ok(format!(
+        "{}\n\n{} {}\n",
+        chapter.headers,
+        movetext.trim(),
+        result_of(&chapter.headers)
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConflict {
+    /// Moves leading to the conflict, in SAN.
+    pub line: String,
+    pub message: String,
+}
+
+fn conflict(path: &[String], message: impl Into<String>) -> SyncConflict {
+    SyncConflict {
+        line: path.join(" "),
+        message: message.into(),
+    }
+}
+
+/// Three-way merge of a value, `None` if both sides changed it differently.
+fn merge_value<'a, T: PartialEq>(base: &T, local: &'a T, remote: &'a T) -> Option<&'a T> {
+    if local == remote || remote == base {
+        Some(local)
+    } else if local == base {
+        Some(remote)
+    } else {
+        None
+    }
+}
+
+fn merge(
+    base: Option<&Line>,
+    local: &Line,
+    remote: &Line,
+    path: &mut Vec<String>,
+    conflicts: &mut Vec<SyncConflict>,
+) -> Line {
+    let empty = Line::default();
+    let base_line = base.unwrap_or(&empty);
+
+    let comment = match merge_value(&base_line.comment, &local.comment, &remote.comment) {
+        Some(comment) => comment.clone(),
+        None => {
+            conflicts.push(conflict(
+                path,
+                format!(
+                    "Comment changed on both sides, Lichess has: {}",
+                    remote.comment
+                ),
+            ));
+            local.comment.clone()
+        }
+    };
+    let nags = match merge_value(&base_line.nags, &local.nags, &remote.nags) {
+        Some(nags) => nags.clone(),
+        None => {
+            conflicts.push(conflict(path, "Annotations changed on both sides"));
+            local.nags.clone()
+        }
+    };
+
+    let mut children = Vec::new();
+    for (san, line) in &local.children {
+        path.push(san.clone());
+        let base_child = base.and_then(|base| base.child(san));
+        match (remote.child(san), base_child) {
+            (Some(remote_child), _) => children.push((
+                san.clone(),
+                merge(base_child, line, remote_child, path, conflicts),
+            )),
+            // Removed on Lichess
+            (None, Some(base_child)) if base_child == line => {}
+            (None, Some(_)) => {
+                conflicts.push(conflict(path, "Removed on Lichess but changed locally"));
+                children.push((san.clone(), line.clone()));
+            }
+            (None, None) => children.push((san.clone(), line.clone())),
+        }
+        path.pop();
+    }
+    for (san, line) in &remote.children {
+        if local.child(san).is_some() {
+            continue;
+        }
+        path.push(san.clone());
+        match base.and_then(|base| base.child(san)) {
+            // Removed locally
+            Some(base_child) if base_child == line => {}
+            Some(_) => {
+                conflicts.push(conflict(path, "Removed locally but changed on Lichess"));
+                children.push((san.clone(), line.clone()));
+            }
+            None => children.push((san.clone(), line.clone())),
+        }
+        path.pop();
+    }
+
+    Line {
+        comment,
+        nags,
+        children,
+    }
+}
+
+/// Study and optional chapter id from `abcd1234`, `abcd1234/efgh5678` or a study URL.
+fn parse_study_ref(study: &str) -> Result<(String, Option<String>)> {
+    let study = study.trim().trim_end_matches('/');
+    let ids = study.rsplit_once("/study/").map_or(study, |(_, ids)| ids);
+    let is_id = |id: &str| id.len() == 8 && id.bytes().all(|b| b.is_ascii_alphanumeric());
+
+    let mut parts = ids.split('/');
+    let study_id = parts.next().unwrap_or_default();
+    let chapter_id = parts.next();
+    if !is_id(study_id) || chapter_id.is_some_and(|id| !is_id(id)) || parts.next().is_some() {
+        return Err(Error::PackageManager(format!(
+            "Invalid Lichess study: {}",
+            study
+        )));
+    }
+    Ok((study_id.to_string(), chapter_id.map(str::to_string)))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncRecord {
+    chapter_id: String,
+    /// Chapter as merged at the last sync.
+    base_pgn: String,
+}
+
+fn records_path(app: &tauri::AppHandle) -> Result<PathBuf> {
+    Ok(app.path().resolve(SYNC_FILE, BaseDirectory::AppData)?)
+}
+
+fn load_records(path: &Path) -> Result<HashMap<String, SyncRecord>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| Error::PackageManager(format!("Invalid study sync records: {}", e)))
+}
+
+fn save_records(path: &Path, records: &HashMap<String, SyncRecord>) -> Result<()> {
+    let json =
+        serde_json::to_string_pretty(records).map_err(|e| Error::PackageManager(e.to_string()))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+async fn fetch_chapter(token: &str, study: &str, chapter: &str) -> Result<Option<String>> {
+    let response = Request::get(format!("{}/api/study/{}/{}.pgn", LICHESS, study, chapter))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await?;
+    let status = response.status();
+    if status.as_u16() == 404 {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(Error::HttpStatus(status.as_u16()));
+    }
+    Ok(Some(response.text().await?))
+}
+
+#[derive(Deserialize)]
+struct ImportedChapters {
+    chapters: Vec<ImportedChapter>,
+}
+
+#[derive(Deserialize)]
+struct ImportedChapter {
+    id: String,
+}
+
+/// Import `pgn` as a new chapter of `study`, returning its id.
+async fn import_chapter(token: &str, study: &str, name: &str, pgn: &str) -> Result<String> {
+    let mut form =
+        Url::parse("http://localhost/").map_err(|e| Error::PackageManager(e.to_string()))?;
+    form.query_pairs_mut()
+        .append_pair("pgn", pgn)
+        .append_pair("name", name);
+    let response = Request::new(
+        Method::POST,
+        format!("{}/api/study/{}/import-pgn", LICHESS, study),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .header("Content-Type", "application/x-www-form-urlencoded")
+    .body(form.query().unwrap_or_default().as_bytes().to_vec())
+    .send()
+    .await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status().as_u16()));
+    }
+    let imported: ImportedChapters = response.json().await?;
+    imported
+        .chapters
+        .into_iter()
+        .next()
+        .map(|chapter| chapter.id)
+        .ok_or_else(|| Error::PackageManager("Lichess did not create the chapter".to_string()))
+}
+
+async fn delete_chapter(token: &str, study: &str, chapter: &str) -> Result<()> {
+    let response = Request::new(
+        Method::DELETE,
+        format!("{}/api/study/{}/{}", LICHESS, study, chapter),
+    )
+    .header("Authorization", format!("Bearer {}", token))
+    .send()
+    .await?;
+    let status = response.status();
+    if !status.is_success() && status.as_u16() != 404 {
+        return Err(Error::HttpStatus(status.as_u16()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct StudySyncResult {
+    /// Id of the chapter now holding the repertoire.
+    pub chapter_id: String,
+    /// Whether changes from Lichess were written to the repertoire.
+    pub pulled: bool,
+    /// Whether the chapter on Lichess was replaced.
+    pub pushed: bool,
+    pub conflicts: Vec<SyncConflict>,
+}
+
+/// Sync the repertoire PGN file `repertoire` with a chapter of the Lichess study `study_id`
+/// (`<study>` or `<study>/<chapter>`). Without a chapter, the one of the last sync is used, or a
+/// new one is created. Repertoires kept for spaced-repetition training have no PGN to merge into
+/// and cannot be synced.
+#[tauri::command]
+#[specta::specta]
+pub async fn sync_lichess_study(
+    study_id: String,
+    repertoire: PathBuf,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<StudySyncResult> {
+    let token = lichess_token(&state)
+        .ok_or_else(|| Error::PackageManager("Log in to Lichess to sync studies".to_string()))?;
+    let (study, chapter) = parse_study_ref(&study_id)?;
+
+    let records_file = records_path(&app)?;
+    let mut records = load_records(&records_file)?;
+    let key = format!("{}:{}", study, repertoire.display());
+    let record = records.get(&key);
+    let chapter = chapter.or_else(|| record.map(|record| record.chapter_id.clone()));
+
+    let local = parse_chapter(&fs::read_to_string(&repertoire)?)?;
+    let remote = match &chapter {
+        Some(chapter) => fetch_chapter(&token, &study, chapter).await?,
+        None => None,
+    };
+    let remote = remote.as_deref().map(parse_chapter).transpose()?;
+    // The base only applies to the chapter it was recorded for.
+    let base = record
+        .filter(|record| chapter.as_ref() == Some(&record.chapter_id))
+        .map(|record| parse_chapter(&record.base_pgn))
+        .transpose()?;
+
+    let mut conflicts = Vec::new();
+    let merged = match &remote {
+        Some(remote) => {
+            if remote.start_fen != local.start_fen {
+                return Err(Error::PackageManager(
+                    "The chapter starts from a different position than the repertoire".to_string(),
+                ));
+            }
+            merge(
+                base.as_ref().map(|base| &base.root),
+                &local.root,
+                &remote.root,
+                &mut Vec::new(),
+                &mut conflicts,
+            )
+        }
+        None => local.root.clone(),
+    };
+    let merged_pgn = write_chapter(&local, &merged)?;
+
+    let pulled = merged != local.root;
+    if pulled {
+        fs::write(&repertoire, &merged_pgn)?;
+    }
+    let (chapter_id, pushed) = match (chapter, &remote) {
+        (Some(chapter), Some(remote)) if remote.root == merged => (chapter, false),
+        (chapter, _) => {
+            let name = repertoire
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "Repertoire".to_string());
+            let new_id = import_chapter(&token, &study, &name, &merged_pgn).await?;
+            if let (Some(chapter), Some(_)) = (chapter, &remote) {
+                delete_chapter(&token, &study, &chapter).await?;
+            }
+            (new_id, true)
+        }
+    };
+
+    records.insert(
+        key,
+        SyncRecord {
+            chapter_id: chapter_id.clone(),
+            base_pgn: merged_pgn,
+        },
+    );
+    save_records(&records_file, &records)?;
+
+    Ok(StudySyncResult {
+        chapter_id,
+        pulled,
+        pushed,
+        conflicts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(movetext: &str) -> Chapter {
+        parse_chapter(&format!("[Event \"Repertoire\"]\n\n{} *\n", movetext)).unwrap()
+    }
+
+    fn sync(base: &str, local: &str, remote: &str) -> (String, Vec<SyncConflict>) {
+        let (base, local, remote) = (chapter(base), chapter(local), chapter(remote));
+        let mut conflicts = Vec::new();
+        let merged = merge(
+            Some(&base.root),
+            &local.root,
+            &remote.root,
+            &mut Vec::new(),
+            &mut conflicts,
+        );
+        (write_chapter(&local, &merged).unwrap(), conflicts)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let original = chapter("1. e4 { main } e5 (1... c5 $1 2. Nf3) 2. Nf3");
+        let pgn = write_chapter(&original, &original.root).unwrap();
+        assert!(pgn.starts_with("[Event \"Repertoire\"]\n\n"));
+        assert_eq!(parse_chapter(&pgn).unwrap().root, original.root);
+    }
+
+    #[test]
+    fn test_merge_both_directions() {
+        let (merged, conflicts) = sync(
+            "1. e4 e5",
+            "1. e4 e5 2. Nf3",
+            "1. e4 { best by test } e5 (1... c5)",
+        );
+        assert!(conflicts.is_empty());
+        let expected = chapter("1. e4 { best by test } e5 (1... c5) 2. Nf3");
+        assert_eq!(parse_chapter(&merged).unwrap().root, expected.root);
+    }
+
+    #[test]
+    fn test_merge_deletions_and_conflicts() {
+        let (merged, conflicts) = sync(
+            "1. e4 { old } e5 (1... c5)",
+            "1. e4 { local } e5",
+            "1. e4 { remote } e5 (1... c5)",
+        );
+        // c5 was removed locally and left alone on Lichess.
+        assert!(!merged.contains("c5"));
+        assert_eq!(
+            conflicts,
+            vec![SyncConflict {
+                line: "e4".to_string(),
+                message: "Comment changed on both sides, Lichess has: remote".to_string(),
+            }]
+        );
+        assert!(merged.contains("{local}"));
+    }
+
+    #[test]
+    fn test_parse_study_ref() {
+        assert_eq!(
+            parse_study_ref("https://lichess.org/study/abcd1234/efgh5678").unwrap(),
+            ("abcd1234".to_string(), Some("efgh5678".to_string()))
+        );
+        assert_eq!(
+            parse_study_ref("abcd1234").unwrap(),
+            ("abcd1234".to_string(), None)
+        );
+        assert!(parse_study_ref("abcd/../x").is_err());
+    }
+}
@@ -0,0 +1,509 @@
+//! Repertoire training against deviations from the book.
+//!
+//! The trainee plays their side of a repertoire while the backend plays the opponent. Most of the
+//! time the opponent follows the repertoire, but with a configurable probability it leaves it with
+//! a reply taken from a reference database, weighted by how often that reply is played there. The
+//! trainee must then answer with a move that scores well in the reference games, or with the
+//! prepared move if the reply transposes back into the repertoire.
+//!
+//! A session is a small state machine (`TrainingPhase`) kept in `AppState`. The reference games
+//! are scanned once when the session starts, so new lines can be trained without rescanning.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use rand::{seq::SliceRandom, Rng};
+use serde::Serialize;
+use shakmaty::{
+    fen::Fen,
+    san::SanPlus,
+    zobrist::{Zobrist64, ZobristHash},
+    Chess, Color, EnPassantMode, Move, Position,
+};
+use specta::Type;
+
+use crate::{
+    db::{
+        get_db_or_create,
+        review::color_name,
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    repertoire::{
+        gaps::{tally_reference_games, MoveTally, ReferenceTallies},
+        parse_color, repertoire_positions, RepertoirePosition, RepertoireRef,
+    },
+    training::parse_guess,
+    AppState,
+};
+
+/// Reference games a reply needs to be played in before the opponent deviates with it.
+const MIN_DEVIATION_GAMES: i32 = 10;
+/// Reference games an answer to a deviation needs before it is judged.
+const MIN_ANSWER_GAMES: i32 = 5;
+/// How far below the best scoring answer (as a share between 0 and 1) an answer is still correct.
+const ANSWER_SCORE_MARGIN: f64 = 0.05;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TrainingPhase {
+    /// The trainee is to move in a position of the repertoire.
+    InBook,
+    /// The opponent left the repertoire with `reply`; the trainee must find a good answer.
+    Deviation { reply: String },
+    /// The line was played to its end, or the deviation was answered correctly.
+    Completed,
+    /// The trainee played `played` instead of one of the `expected` moves.
+    Failed {
+        played: String,
+        expected: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TrainingState {
+    pub session_id: String,
+    pub color: String,
+    pub fen: String,
+    /// Moves of the current line, in SAN.
+    pub moves: Vec<String>,
+    pub phase: TrainingPhase,
+    pub lines_completed: u32,
+    pub lines_failed: u32,
+}
+
+pub struct TrainingSession {
+    color: Color,
+    /// Prepared moves of the trainee by position.
+    prepared: HashMap<Zobrist64, RepertoirePosition>,
+    /// Opponent moves of the repertoire by position.
+    opponent: HashMap<Zobrist64, RepertoirePosition>,
+    reference: ReferenceTallies,
+    deviation_probability: f64,
+    position: Chess,
+    moves: Vec<String>,
+    phase: TrainingPhase,
+    lines_completed: u32,
+    lines_failed: u32,
+}
+
+fn hash_of(position: &Chess) -> Zobrist64 {
+    position.zobrist_hash(EnPassantMode::Legal)
+}
+
+fn to_move(position: &Chess, san: &str) -> Option<Move> {
+    san.parse::<SanPlus>().ok()?.san.to_move(position).ok()
+}
+
+/// Answers (SAN) to a deviation that score within `ANSWER_SCORE_MARGIN` of the best answer played
+/// in at least `MIN_ANSWER_GAMES` reference games.
+fn good_answers(answers: &HashMap<String, MoveTally>) -> Vec<String> {
+    let scores: Vec<(&String, f64)> = answers
+        .iter()
+        .filter(|(_, tally)| tally.games >= MIN_ANSWER_GAMES)
+        .map(|(san, tally)| (san, tally.points / tally.games as f64))
+        .collect();
+    let Some(best) = scores.iter().map(|(_, score)| *score).reduce(f64::max) else {
+        return Vec::new();
+    };
+    let mut good: Vec<String> = scores
+        .into_iter()
+        .filter(|(_, score)| *score >= best - ANSWER_SCORE_MARGIN)
+        .map(|(san, _)| san.clone())
+        .collect();
+    good.sort();
+    good
+}
+
+impl TrainingSession {
+    fn new(
+        color: Color,
+        prepared: HashMap<Zobrist64, RepertoirePosition>,
+        opponent: HashMap<Zobrist64, RepertoirePosition>,
+        reference: ReferenceTallies,
+        deviation_probability: f64,
+    ) -> Self {
+        Self {
+            color,
+            prepared,
+            opponent,
+            reference,
+            deviation_probability,
+            position: Chess::default(),
+            moves: Vec::new(),
+            phase: TrainingPhase::Completed,
+            lines_completed: 0,
+            lines_failed: 0,
+        }
+    }
+
+    /// Start a new line from the initial position.
+    fn begin_line(&mut self, rng: &mut impl Rng) -> Result<()> {
+        self.position = Chess::default();
+        self.moves.clear();
+        if self.position.turn() == self.color {
+            self.phase = self.phase_after_opponent(None);
+        } else {
+            self.opponent_turn(rng);
+        }
+        if self.phase == TrainingPhase::Completed {
+            return Err(Error::PackageManager(
+                "The repertoire has no moves from the initial position".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Phase once the opponent has moved, given the deviation it played, if any.
+    fn phase_after_opponent(&self, deviation: Option<String>) -> TrainingPhase {
+        if self.prepared.contains_key(&hash_of(&self.position)) {
+            TrainingPhase::InBook
+        } else if let Some(reply) = deviation {
+            TrainingPhase::Deviation { reply }
+        } else {
+            TrainingPhase::Completed
+        }
+    }
+
+    /// Moves accepted in the current position, in SAN.
+    fn expected_moves(&self) -> Vec<String> {
+        let hash = hash_of(&self.position);
+        match &self.phase {
+            TrainingPhase::InBook => self
+                .prepared
+                .get(&hash)
+                .map(|p| p.moves.clone())
+                .unwrap_or_default(),
+            TrainingPhase::Deviation { .. } => self
+                .reference
+                .answers
+                .get(&hash)
+                .map(good_answers)
+                .unwrap_or_default(),
+            TrainingPhase::Completed | TrainingPhase::Failed { .. } => Vec::new(),
+        }
+    }
+
+    fn play(&mut self, m: &Move) {
+        self.moves
+            .push(SanPlus::from_move(self.position.clone(), m).to_string());
+        self.position.play_unchecked(m);
+    }
+
+    /// A reply from the reference games that leaves the repertoire, weighted by its popularity.
+    /// Only replies whose answers can be judged are considered.
+    fn sample_deviation(&self, book_moves: &[Move], rng: &mut impl Rng) -> Option<(Move, String)> {
+        let parent = self.reference.parents.get(&hash_of(&self.position))?;
+        let candidates: Vec<(Move, String, i32)> = parent
+            .replies
+            .iter()
+            .filter(|(_, reply)| reply.games >= MIN_DEVIATION_GAMES)
+            .filter(|(reply_hash, _)| {
+                self.prepared.contains_key(reply_hash)
+                    || self
+                        .reference
+                        .answers
+                        .get(reply_hash)
+                        .is_some_and(|answers| !good_answers(answers).is_empty())
+            })
+            .filter_map(|(_, reply)| {
+                let m = to_move(&self.position, &reply.san)?;
+                (!book_moves.contains(&m)).then(|| (m, reply.san.clone(), reply.games))
+            })
+            .collect();
+        candidates
+            .choose_weighted(rng, |(_, _, games)| *games)
+            .ok()
+            .map(|(m, san, _)| (m.clone(), san.clone()))
+    }
+
+    /// Play the opponent's move: a repertoire move, or a deviation with the session's probability.
+    fn opponent_turn(&mut self, rng: &mut impl Rng) {
+        let book_moves: Vec<Move> = match self.opponent.get(&hash_of(&self.position)) {
+            Some(book) if !self.position.is_game_over() => book
+                .moves
+                .iter()
+                .filter_map(|san| to_move(&self.position, san))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if book_moves.is_empty() {
+            self.phase = TrainingPhase::Completed;
+            return;
+        }
+
+        if rng.gen_bool(self.deviation_probability) {
+            if let Some((m, reply)) = self.sample_deviation(&book_moves, rng) {
+                self.play(&m);
+                self.phase = self.phase_after_opponent(Some(reply));
+                return;
+            }
+        }
+        let m = book_moves.choose(rng).unwrap().clone();
+        self.play(&m);
+        self.phase = self.phase_after_opponent(None);
+    }
+
+    /// Check the trainee's move and advance the line.
+    fn submit(&mut self, m: Move, rng: &mut impl Rng) -> Result<()> {
+        let deviation = match self.phase {
+            TrainingPhase::InBook => false,
+            TrainingPhase::Deviation { .. } => true,
+            TrainingPhase::Completed | TrainingPhase::Failed { .. } => {
+                return Err(Error::PackageManager(
+                    "The training line is over".to_string(),
+                ));
+            }
+        };
+
+        let expected = self.expected_moves();
+        if !expected
+            .iter()
+            .any(|san| to_move(&self.position, san).as_ref() == Some(&m))
+        {
+            self.phase = TrainingPhase::Failed {
+                played: SanPlus::from_move(self.position.clone(), &m).to_string(),
+                expected,
+            };
+            self.lines_failed += 1;
+            return Ok(());
+        }
+
+        self.play(&m);
+        if deviation {
+            self.phase = TrainingPhase::Completed;
+        } else {
+            self.opponent_turn(rng);
+        }
+        if self.phase == TrainingPhase::Completed {
+            self.lines_completed += 1;
+        }
+        Ok(())
+    }
+
+    fn state(&self, session_id: &str) -> TrainingState {
+        TrainingState {
+            session_id: session_id.to_string(),
+            color: color_name(self.color).to_string(),
+            fen: Fen::from_position(self.position.clone(), EnPassantMode::Legal).to_string(),
+            moves: self.moves.clone(),
+            phase: self.phase.clone(),
+            lines_completed: self.lines_completed,
+            lines_failed: self.lines_failed,
+        }
+    }
+}
+
+fn missing_session(session_id: &str) -> Error {
+    Error::PackageManager(format!(
+        "Unknown repertoire training session: {}",
+        session_id
+    ))
+}
+
+/// Start training `repertoire`, played as `color`. The opponent leaves the repertoire with
+/// probability `deviation_probability` at each of its moves, using replies and answers from
+/// `reference_db`.
+#[tauri::command]
+#[specta::specta]
+pub async fn start_repertoire_training(
+    repertoire: RepertoireRef,
+    color: String,
+    reference_db: PathBuf,
+    deviation_probability: f64,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<TrainingState> {
+    if !(0.0..=1.0).contains(&deviation_probability) {
+        return Err(Error::PackageManager(format!(
+            "Invalid deviation probability: {}",
+            deviation_probability
+        )));
+    }
+    let color = parse_color(Some(&color))?.unwrap_or(Color::White);
+    let pgn = repertoire.read(&app)?;
+    let prepared = repertoire_positions(&pgn[..], color)?;
+    let opponent = repertoire_positions(&pgn[..], !color)?;
+
+    let db = &mut get_db_or_create(
+        &state,
+        reference_db.to_str().unwrap(),
+        ConnectionOptions::default(),
+    )?;
+    let reference = tally_reference_games(db, color, &prepared, &opponent)?;
+
+    let mut session =
+        TrainingSession::new(color, prepared, opponent, reference, deviation_probability);
+    session.begin_line(&mut rand::thread_rng())?;
+
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let training_state = session.state(&session_id);
+    state.training_sessions.insert(session_id, session);
+    Ok(training_state)
+}
+
+/// Play the trainee's move, given in UCI or SAN, and the opponent's reply.
+#[tauri::command]
+#[specta::specta]
+pub async fn submit_training_move(
+    session_id: String,
+    played: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<TrainingState> {
+    let mut session = state
+        .training_sessions
+        .get_mut(&session_id)
+        .ok_or_else(|| missing_session(&session_id))?;
+    let m = parse_guess(&session.position, &played)?;
+    session.submit(m, &mut rand::thread_rng())?;
+    Ok(session.state(&session_id))
+}
+
+/// Start another line in the same session.
+#[tauri::command]
+#[specta::specta]
+pub async fn next_training_line(
+    session_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<TrainingState> {
+    let mut session = state
+        .training_sessions
+        .get_mut(&session_id)
+        .ok_or_else(|| missing_session(&session_id))?;
+    session.begin_line(&mut rand::thread_rng())?;
+    Ok(session.state(&session_id))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn end_repertoire_training(
+    session_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<TrainingState>> {
+    Ok(state
+        .training_sessions
+        .remove(&session_id)
+        .map(|(id, session)| session.state(&id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::repertoire::gaps::{ParentTally, ReplyTally};
+
+    fn after(sans: &[&str]) -> Chess {
+        let mut position = Chess::default();
+        for san in sans {
+            let m = to_move(&position, san).unwrap();
+            position.play_unchecked(&m);
+        }
+        position
+    }
+
+    fn tally(games: i32, points: f64) -> MoveTally {
+        MoveTally { games, points }
+    }
+
+    /// 1. e4 e5 2. Nf3 for white, with 1... c5 played 20 times in the reference games.
+    fn session() -> TrainingSession {
+        let pgn = b"1. e4 e5 2. Nf3 *\n";
+        let prepared = repertoire_positions(&pgn[..], Color::White).unwrap();
+        let opponent = repertoire_positions(&pgn[..], Color::Black).unwrap();
+
+        let sicilian = hash_of(&after(&["e4", "c5"]));
+        let parent = ParentTally {
+            games: 20,
+            replies: HashMap::from([(
+                sicilian,
+                ReplyTally {
+                    san: "c5".to_string(),
+                    games: 20,
+                    fen: String::new(),
+                },
+            )]),
+        };
+        let reference = ReferenceTallies {
+            parents: HashMap::from([(hash_of(&after(&["e4"])), parent)]),
+            answers: HashMap::from([(
+                sicilian,
+                HashMap::from([
+                    ("Nf3".to_string(), tally(10, 6.0)),
+                    ("Nc3".to_string(), tally(10, 5.8)),
+                    ("a3".to_string(), tally(6, 1.0)),
+                ]),
+            )]),
+        };
+        TrainingSession::new(Color::White, prepared, opponent, reference, 1.0)
+    }
+
+    #[test]
+    fn test_good_answers() {
+        let answers = HashMap::from([
+            ("Nf3".to_string(), tally(10, 6.0)),
+            ("Nc3".to_string(), tally(10, 5.8)),
+            ("a3".to_string(), tally(6, 1.0)),
+            ("h4".to_string(), tally(2, 2.0)),
+        ]);
+        assert_eq!(good_answers(&answers), vec!["Nc3", "Nf3"]);
+    }
+
+    #[test]
+    fn test_deviation_answered() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut session = session();
+        session.begin_line(&mut rng).unwrap();
+        assert_eq!(session.phase, TrainingPhase::InBook);
+
+        let e4 = to_move(&session.position, "e4").unwrap();
+        session.submit(e4, &mut rng).unwrap();
+        assert_eq!(
+            session.phase,
+            TrainingPhase::Deviation {
+                reply: "c5".to_string()
+            }
+        );
+
+        let nf3 = to_move(&session.position, "Nf3").unwrap();
+        session.submit(nf3, &mut rng).unwrap();
+        assert_eq!(session.phase, TrainingPhase::Completed);
+        assert_eq!(session.moves, vec!["e4", "c5", "Nf3"]);
+        assert_eq!(session.lines_completed, 1);
+    }
+
+    #[test]
+    fn test_wrong_answer_fails() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut session = session();
+        session.begin_line(&mut rng).unwrap();
+        let e4 = to_move(&session.position, "e4").unwrap();
+        session.submit(e4, &mut rng).unwrap();
+
+        let a3 = to_move(&session.position, "a3").unwrap();
+        session.submit(a3, &mut rng).unwrap();
+        assert_eq!(
+            session.phase,
+            TrainingPhase::Failed {
+                played: "a3".to_string(),
+                expected: vec!["Nc3".to_string(), "Nf3".to_string()],
+            }
+        );
+        assert!(session.submit(a3, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_book_line_without_deviations() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut session = session();
+        session.deviation_probability = 0.0;
+        session.begin_line(&mut rng).unwrap();
+        let e4 = to_move(&session.position, "e4").unwrap();
+        session.submit(e4, &mut rng).unwrap();
+        assert_eq!(session.phase, TrainingPhase::InBook);
+        let nf3 = to_move(&session.position, "Nf3").unwrap();
+        session.submit(nf3, &mut rng).unwrap();
+        assert_eq!(session.phase, TrainingPhase::Completed);
+        assert_eq!(session.moves, vec!["e4", "e5", "Nf3"]);
+    }
+}
@@ -0,0 +1,151 @@
+//! Shutdown sequencing for background jobs, databases and engines.
+//!
+//! When the app exits, `on_exit` asks running jobs (imports, game analyses, smart analysis) to
+//! stop at their next safe point and waits for them, flushes buffered metrics, checkpoints the
+//! WAL of every open database and sends `quit` to the engines before killing the ones that do
+//! not exit. The whole sequence is bounded by `SHUTDOWN_TIMEOUT`, so a hung engine or job
+//! cannot keep the app from closing.
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use diesel::{sql_query, RunQueryDsl};
+use tauri::{AppHandle, Manager};
+
+use crate::AppState;
+
+/// Upper bound for the whole shutdown sequence.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time an engine gets to exit after `quit` before it is killed.
+const ENGINE_QUIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+static ACTIVE_JOBS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the app is shutting down. Long-running jobs check it between units of work (games,
+/// positions, batches) and stop once it is set.
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+/// Marks a background job as running until dropped; shutdown waits for running jobs.
+pub struct JobGuard(());
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        ACTIVE_JOBS.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+pub fn start_job() -> JobGuard {
+    ACTIVE_JOBS.fetch_add(1, Ordering::AcqRel);
+    JobGuard(())
+}
+
+fn active_jobs() -> usize {
+    ACTIVE_JOBS.load(Ordering::Acquire)
+}
+
+fn remaining(deadline: Instant) -> Duration {
+    deadline.saturating_duration_since(Instant::now())
+}
+
+async fn wait_for_jobs(deadline: Instant) {
+    while active_jobs() > 0 && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    if active_jobs() > 0 {
+        log::warn!("{} background job(s) still running at shutdown", active_jobs());
+    }
+}
+
+fn checkpoint_databases(state: &AppState, deadline: Instant) {
+    let paths: Vec<String> = state
+        .connection_pool
+        .iter()
+        .map(|entry| entry.key().clone())
+        .collect();
+    for path in paths {
+        let Some((_, pool)) = state.connection_pool.remove(&path) else {
+            continue;
+        };
+        let timeout = remaining(deadline).max(Duration::from_millis(10));
+        match pool.get_timeout(timeout) {
+            Ok(mut db) => {
+                // A no-op for databases not in WAL mode.
+                if let Err(e) = sql_query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&mut db) {
+                    log::warn!("Failed to checkpoint {}: {}", path, e);
+                }
+            }
+            Err(e) => log::warn!("No connection to checkpoint {}: {}", path, e),
+        }
+    }
+    state.pool_metrics.clear();
+}
+
+async fn stop_engines(state: &AppState, deadline: Instant) {
+    let keys: Vec<_> = state
+        .engine_processes
+        .iter()
+        .map(|entry| entry.key().clone())
+        .collect();
+    for key in keys {
+        let Some((_, process)) = state.engine_processes.remove(&key) else {
+            continue;
+        };
+        let timeout = remaining(deadline).min(ENGINE_QUIT_TIMEOUT);
+        match tokio::time::timeout(timeout, process.lock()).await {
+            Ok(mut process) => process.quit(timeout).await,
+            Err(_) => log::warn!("Engine {} of tab {} is busy, not stopped", key.1, key.0),
+        }
+    }
+}
+
+/// Stop background work and release databases and engines, within `SHUTDOWN_TIMEOUT`.
+pub async fn shutdown(state: &AppState) {
+    let started = Instant::now();
+    let deadline = started + SHUTDOWN_TIMEOUT;
+    SHUTTING_DOWN.store(true, Ordering::Relaxed);
+    state.smart_analysis_stop.store(true, Ordering::Relaxed);
+    state.prefetch_generation.fetch_add(1, Ordering::Relaxed);
+    state.position_check_generation.fetch_add(1, Ordering::Relaxed);
+
+    wait_for_jobs(deadline).await;
+    if let Err(e) = crate::metrics::flush() {
+        log::warn!("Failed to flush metrics: {}", e);
+    }
+    checkpoint_databases(state, deadline);
+    stop_engines(state, deadline).await;
+    log::info!("Shutdown sequence finished in {:?}", started.elapsed());
+}
+
+/// Run the shutdown sequence from the (synchronous) exit event handler.
+pub fn on_exit(app: &AppHandle) {
+    let app = app.clone();
+    // The event loop may run inside the async runtime, so block on a separate thread.
+    let handle = std::thread::spawn(move || {
+        tauri::async_runtime::block_on(shutdown(&app.state::<AppState>()));
+    });
+    if handle.join().is_err() {
+        log::error!("Shutdown sequence panicked");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_guard() {
+        let before = active_jobs();
+        let first = start_job();
+        let second = start_job();
+        assert_eq!(active_jobs(), before + 2);
+        drop(first);
+        drop(second);
+        assert_eq!(active_jobs(), before);
+    }
+}
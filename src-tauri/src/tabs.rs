@@ -0,0 +1,222 @@
+//! Analysis board tabs.
+//!
+//! The backend keeps the state of every open analysis tab: its start position, the moves to the
+//! current position and the serialized game tree. Engines are keyed by tab in
+//! `engine_processes`, so a tab's engines are read from there rather than stored, and closing a
+//! tab stops them. That way the frontend and the engine lifecycle cannot disagree about which
+//! tabs exist.
+
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use shakmaty::{fen::Fen, uci::UciMove, CastlingMode, Chess, EnPassantMode, Position};
+use specta::Type;
+
+use crate::{chess::kill_engines, error::Error, AppState};
+
+#[derive(Debug, Clone)]
+pub struct Tab {
+    name: String,
+    start_fen: String,
+    moves: Vec<String>,
+    fen: String,
+    pgn: Option<String>,
+    opened_at: Instant,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TabState {
+    pub id: String,
+    pub name: String,
+    pub start_fen: String,
+    /// Moves (UCI) from the start position to the current position.
+    pub moves: Vec<String>,
+    /// Current position.
+    pub fen: String,
+    /// Game tree of the tab as PGN, as last saved by the frontend.
+    pub pgn: Option<String>,
+    /// Engines currently running for the tab.
+    pub engines: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TabUpdate {
+    pub name: Option<String>,
+    pub start_fen: Option<String>,
+    pub moves: Option<Vec<String>>,
+    pub pgn: Option<String>,
+}
+
+/// FEN after playing `moves` from `start_fen`.
+fn play_line(start_fen: &str, moves: &[String]) -> Result<String, Error> {
+    let fen: Fen = start_fen.parse()?;
+    let mut position: Chess = fen.into_position(CastlingMode::Chess960)?;
+    for m in moves {
+        let uci: UciMove = m.parse()?;
+        let m = uci.to_move(&position)?;
+        position.play_unchecked(&m);
+    }
+    Ok(Fen::from_position(position, EnPassantMode::Legal).to_string())
+}
+
+/// Name of a copy of a tab: "Analysis" becomes "Analysis (2)", "Analysis (2)" becomes
+/// "Analysis (3)".
+fn copy_name(name: &str) -> String {
+    if let Some((base, n)) = name
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" ("))
+        .and_then(|(base, n)| n.parse::<u32>().ok().map(|n| (base, n)))
+    {
+        return format!("{} ({})", base, n + 1);
+    }
+    format!("{} (2)", name)
+}
+
+fn tab_state(state: &AppState, id: &str, tab: &Tab) -> TabState {
+    let mut engines: Vec<String> = state
+        .engine_processes
+        .iter()
+        .filter(|entry| entry.key().0.starts_with(id))
+        .map(|entry| entry.key().1.clone())
+        .collect();
+    engines.sort();
+    TabState {
+        id: id.to_string(),
+        name: tab.name.clone(),
+        start_fen: tab.start_fen.clone(),
+        moves: tab.moves.clone(),
+        fen: tab.fen.clone(),
+        pgn: tab.pgn.clone(),
+        engines,
+    }
+}
+
+fn missing_tab(id: &str) -> Error {
+    Error::PackageManager(format!("Unknown tab: {}", id))
+}
+
+fn insert_tab(state: &AppState, tab: Tab) -> TabState {
+    let id = uuid::Uuid::new_v4().to_string();
+    let tab_state = tab_state(state, &id, &tab);
+    state.tabs.insert(id, tab);
+    tab_state
+}
+
+/// Open a tab on `fen`, or on the initial position.
+#[tauri::command]
+#[specta::specta]
+pub fn create_tab(
+    name: String,
+    fen: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<TabState, Error> {
+    let start_fen = fen.unwrap_or_else(|| {
+        Fen::from_position(Chess::default(), EnPassantMode::Legal).to_string()
+    });
+    let fen = play_line(&start_fen, &[])?;
+    Ok(insert_tab(
+        &state,
+        Tab {
+            name,
+            start_fen,
+            moves: Vec::new(),
+            fen,
+            pgn: None,
+            opened_at: Instant::now(),
+        },
+    ))
+}
+
+/// Open a copy of a tab. Engines are not copied.
+#[tauri::command]
+#[specta::specta]
+pub fn duplicate_tab(id: String, state: tauri::State<'_, AppState>) -> Result<TabState, Error> {
+    let mut tab = state
+        .tabs
+        .get(&id)
+        .ok_or_else(|| missing_tab(&id))?
+        .clone();
+    tab.name = copy_name(&tab.name);
+    tab.opened_at = Instant::now();
+    Ok(insert_tab(&state, tab))
+}
+
+/// Close a tab and stop its engines.
+#[tauri::command]
+#[specta::specta]
+pub async fn close_tab(id: String, state: tauri::State<'_, AppState>) -> Result<(), Error> {
+    state.tabs.remove(&id).ok_or_else(|| missing_tab(&id))?;
+    kill_engines(id, state).await
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_tab_state(id: String, state: tauri::State<'_, AppState>) -> Result<TabState, Error> {
+    let tab = state.tabs.get(&id).ok_or_else(|| missing_tab(&id))?;
+    Ok(tab_state(&state, &id, &tab))
+}
+
+/// Open tabs, in the order they were opened.
+#[tauri::command]
+#[specta::specta]
+pub fn list_tabs(state: tauri::State<'_, AppState>) -> Vec<TabState> {
+    let mut tabs: Vec<(Instant, TabState)> = state
+        .tabs
+        .iter()
+        .map(|entry| (entry.opened_at, tab_state(&state, entry.key(), entry.value())))
+        .collect();
+    tabs.sort_by_key(|(opened_at, _)| *opened_at);
+    tabs.into_iter().map(|(_, tab)| tab).collect()
+}
+
+/// Change the name, position or game tree of a tab. Moves are checked against the start
+/// position; changing the start position without new moves clears them.
+#[tauri::command]
+#[specta::specta]
+pub fn update_tab(
+    id: String,
+    update: TabUpdate,
+    state: tauri::State<'_, AppState>,
+) -> Result<TabState, Error> {
+    let mut tab = state.tabs.get_mut(&id).ok_or_else(|| missing_tab(&id))?;
+    let start_changed = update.start_fen.is_some();
+    let start_fen = update.start_fen.unwrap_or_else(|| tab.start_fen.clone());
+    let moves = match update.moves {
+        Some(moves) => moves,
+        None if start_changed => Vec::new(),
+        None => tab.moves.clone(),
+    };
+    tab.fen = play_line(&start_fen, &moves)?;
+    tab.start_fen = start_fen;
+    tab.moves = moves;
+    if let Some(name) = update.name {
+        tab.name = name;
+    }
+    if update.pgn.is_some() {
+        tab.pgn = update.pgn;
+    }
+    Ok(tab_state(&state, &id, &tab))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_play_line() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let fen = play_line(start, &["e2e4".to_string(), "e7e5".to_string()]).unwrap();
+        assert_eq!(fen, "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+        assert!(play_line(start, &["e2e5".to_string()]).is_err());
+        assert!(play_line("not a fen", &[]).is_err());
+    }
+
+    #[test]
+    fn test_copy_name() {
+        assert_eq!(copy_name("Analysis"), "Analysis (2)");
+        assert_eq!(copy_name("Analysis (2)"), "Analysis (3)");
+        assert_eq!(copy_name("Game (final)"), "Game (final) (2)");
+    }
+}
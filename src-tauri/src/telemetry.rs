@@ -13,6 +13,9 @@ use tokio::sync::Semaphore;
 pub struct TelemetryConfig {
     pub enabled: bool,
     pub initial_run_completed: bool,
+    /// Per-category consent, only effective while `enabled` is set.
+    #[serde(default)]
+    pub categories: TelemetryCategories,
 }
 
 impl Default for TelemetryConfig {
@@ -21,6 +24,53 @@ impl Default for TelemetryConfig {
             // Production-grade privacy: opt-in by default.
             enabled: false,
             initial_run_completed: false,
+            categories: TelemetryCategories::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum TelemetryCategory {
+    CrashReports,
+    FeatureUsage,
+    PerformanceMetrics,
+}
+
+/// Consent for each kind of data. All categories default to allowed so that turning telemetry on
+/// keeps its previous meaning; users can then opt out of individual categories.
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryCategories {
+    pub crash_reports: bool,
+    pub feature_usage: bool,
+    pub performance_metrics: bool,
+}
+
+impl Default for TelemetryCategories {
+    fn default() -> Self {
+        Self {
+            crash_reports: true,
+            feature_usage: true,
+            performance_metrics: true,
+        }
+    }
+}
+
+impl TelemetryCategories {
+    fn get(&self, category: TelemetryCategory) -> bool {
+        match category {
+            TelemetryCategory::CrashReports => self.crash_reports,
+            TelemetryCategory::FeatureUsage => self.feature_usage,
+            TelemetryCategory::PerformanceMetrics => self.performance_metrics,
+        }
+    }
+
+    fn set(&mut self, category: TelemetryCategory, enabled: bool) {
+        match category {
+            TelemetryCategory::CrashReports => self.crash_reports = enabled,
+            TelemetryCategory::FeatureUsage => self.feature_usage = enabled,
+            TelemetryCategory::PerformanceMetrics => self.performance_metrics = enabled,
         }
     }
 }
@@ -95,6 +145,11 @@ impl TelemetryConfig {
         Ok(())
     }
 
+    /// Whether data of `category` may be sent.
+    pub fn allows(&self, category: TelemetryCategory) -> bool {
+        self.enabled && self.categories.get(category)
+    }
+
     pub fn mark_initial_run_completed(&mut self, app: &AppHandle) -> Result<(), TelemetryError> {
         self.initial_run_completed = true;
         self.save(app)?;
@@ -120,7 +175,7 @@ fn get_user_id(app: &AppHandle) -> String {
     }
 }
 
-fn get_platform_info() -> String {
+pub(crate) fn get_platform_info() -> String {
     let mut sys = System::new();
     sys.refresh_system();
     
@@ -161,7 +216,7 @@ async fn get_user_country_from_api() -> Option<String> {
     None
 }
 
-fn get_user_country_from_locale() -> Option<String> {
+pub(crate) fn get_user_country_from_locale() -> Option<String> {
     std::env::var("LC_ALL")
         .or_else(|_| std::env::var("LC_CTYPE"))
         .or_else(|_| std::env::var("LANG"))
@@ -227,12 +282,34 @@ async fn get_user_country() -> Option<String> {
     None
 }
 
-async fn track_event_to_supabase(event_name: &str, app: &AppHandle) -> Result<(), TelemetryError> {
-    // Allow runtime override for production deployments.
+/// Supabase project URL and anon key, overridable at runtime for production deployments.
+pub(crate) fn supabase_endpoint() -> (String, String) {
     let supabase_url = std::env::var("PAWN_APPETIT_SUPABASE_URL")
         .unwrap_or_else(|_| "https://jklxpooswizrhfdghcog.supabase.co".to_string());
     let supabase_key = std::env::var("PAWN_APPETIT_SUPABASE_ANON_KEY")
         .unwrap_or_else(|_| "sb_publishable_sLNbFdo6jEh5JYYiT9XgmQ_P8jx7z2V".to_string());
+    (supabase_url, supabase_key)
+}
+
+/// Whether the user consented to sending data of `category`. A missing or unreadable config
+/// counts as no consent.
+pub(crate) fn telemetry_allows(app: &AppHandle, category: TelemetryCategory) -> bool {
+    TelemetryConfig::load(app)
+        .map(|config| config.allows(category))
+        .unwrap_or(false)
+}
+
+async fn track_event_to_supabase(
+    event_name: &str,
+    category: TelemetryCategory,
+    app: &AppHandle,
+) -> Result<(), TelemetryError> {
+    if !TelemetryConfig::load(app)?.allows(category) {
+        log::info!("Telemetry category {:?} not allowed, skipping '{}' event", category, event_name);
+        return Ok(());
+    }
+
+    let (supabase_url, supabase_key) = supabase_endpoint();
 
     let country = get_user_country().await;
 
@@ -266,7 +343,7 @@ async fn track_event_to_supabase(event_name: &str, app: &AppHandle) -> Result<()
     Ok(())
 }
 
-fn track_event_safe(app: &AppHandle, event_name: &str) {
+pub(crate) fn track_event_safe(app: &AppHandle, category: TelemetryCategory, event_name: &str) {
     let app_handle = app.clone();
     let event_name = event_name.to_string();
     
@@ -275,7 +352,7 @@ fn track_event_safe(app: &AppHandle, event_name: &str) {
             Ok(p) => p,
             Err(_) => return,
         };
-        if let Err(e) = track_event_to_supabase(&event_name, &app_handle).await {
+        if let Err(e) = track_event_to_supabase(&event_name, category, &app_handle).await {
             log::warn!("Failed to track '{}' event: {}. This is normal if analytics are disabled or not configured.", event_name, e);
         }
     });
@@ -288,7 +365,7 @@ pub fn handle_initial_run_telemetry(app: &AppHandle) -> Result<(), String> {
     if config.enabled && !config.initial_run_completed {
         log::info!("Initial run detected and telemetry enabled. Tracking 'initial_run' event.");
 
-        track_event_safe(app, "initial_run");
+        track_event_safe(app, TelemetryCategory::FeatureUsage, "initial_run");
 
         config.mark_initial_run_completed(app)
             .map_err(|e| format!("Failed to mark initial run as completed: {}", e))?;
@@ -327,6 +404,33 @@ pub fn set_telemetry_enabled(app: AppHandle, enabled: bool) -> Result<(), String
     Ok(())
 }
 
+#[tauri::command]
+#[specta::specta]
+pub fn get_telemetry_categories(app: AppHandle) -> Result<TelemetryCategories, String> {
+    let config = TelemetryConfig::load(&app)
+        .map_err(|e| format!("Failed to load telemetry config: {}", e))?;
+
+    Ok(config.categories)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_telemetry_category(
+    app: AppHandle,
+    category: TelemetryCategory,
+    enabled: bool,
+) -> Result<TelemetryCategories, String> {
+    let mut config = TelemetryConfig::load(&app)
+        .map_err(|e| format!("Failed to load telemetry config: {}", e))?;
+
+    config.categories.set(category, enabled);
+    config.save(&app)
+        .map_err(|e| format!("Failed to update telemetry setting: {}", e))?;
+
+    log::info!("Telemetry category {:?} updated: enabled={}", category, enabled);
+    Ok(config.categories)
+}
+
 #[tauri::command]
 #[specta::specta]
 pub fn get_telemetry_config(app: AppHandle) -> Result<TelemetryConfig, String> {
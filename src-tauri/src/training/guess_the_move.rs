@@ -0,0 +1,494 @@
+//! Guess-the-move training.
+//!
+//! A master game is replayed with its upcoming moves hidden. At each of the trainee's turns they
+//! submit a guess, which is scored against the move actually played and, when an engine is
+//! configured, against the engine's evaluation of both moves. The game then advances to the
+//! trainee's next turn. Finished sessions are appended to a history file in the app data directory.
+//!
+//! Sessions live in `AppState` so the scoring and the game state are owned by the backend; the
+//! frontend only renders the returned `GuessState`.
+
+use std::{path::PathBuf, time::Instant};
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    fen::Fen,
+    san::{San, SanPlus},
+    uci::UciMove,
+    CastlingMode, Chess, Color, EnPassantMode, FromSetup, Move, Position,
+};
+use specta::Type;
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::{
+    chess::{AnalysisOptions, GameAnalysisService, GoMode},
+    db::{
+        core,
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode},
+        review::{color_name, same_move, score_cp, terminal_eval, win_chance},
+        schema::games,
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    AppState,
+};
+
+const GUESS_DEPTH: u32 = 14;
+/// Points for guessing the game move.
+const MAX_POINTS: u32 = 10;
+/// Best score for a different move the engine rates at least as highly as the game move.
+const EQUAL_MOVE_POINTS: f64 = 8.0;
+/// Win-chance loss (percentage points) relative to the game move at which a guess scores nothing.
+const ZERO_POINTS_LOSS: f64 = 20.0;
+const HISTORY_FILE: &str = "guess-the-move.json";
+
+struct PlayedMove {
+    before: Chess,
+    san: SanPlus,
+    m: Move,
+}
+
+pub struct GuessSession {
+    file: PathBuf,
+    game_id: i32,
+    white: String,
+    black: String,
+    /// Side the trainee guesses for; `None` guesses every move.
+    color: Option<Color>,
+    engine: Option<String>,
+    moves: Vec<PlayedMove>,
+    final_position: Chess,
+    /// Index into `moves` of the next move to guess.
+    ply: usize,
+    results: Vec<GuessResult>,
+    /// When the current position was shown, to measure thinking time.
+    shown_at: Instant,
+}
+
+impl GuessSession {
+    fn is_trainee_move(&self, ply: usize) -> bool {
+        match (self.color, self.moves.get(ply)) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(color), Some(played)) => played.before.turn() == color,
+        }
+    }
+
+    /// Play the opponent's moves until it is the trainee's turn or the game ends.
+    fn advance(&mut self) {
+        while self.ply < self.moves.len() && !self.is_trainee_move(self.ply) {
+            self.ply += 1;
+        }
+        self.shown_at = Instant::now();
+    }
+
+    fn position(&self) -> &Chess {
+        self.moves
+            .get(self.ply)
+            .map(|m| &m.before)
+            .unwrap_or(&self.final_position)
+    }
+
+    fn state(&self, session_id: &str) -> GuessState {
+        let position = self.position();
+        GuessState {
+            session_id: session_id.to_string(),
+            game_id: self.game_id,
+            white: self.white.clone(),
+            black: self.black.clone(),
+            fen: Fen::from_position(position.clone(), EnPassantMode::Legal).to_string(),
+            turn: color_name(position.turn()).to_string(),
+            ply: self.ply as u32,
+            total_plies: self.moves.len() as u32,
+            revealed: self.moves[..self.ply].iter().map(|m| m.san.to_string()).collect(),
+            score: self.results.iter().map(|r| r.points).sum(),
+            max_score: self.results.len() as u32 * MAX_POINTS,
+            finished: self.ply >= self.moves.len(),
+        }
+    }
+
+    fn summary(&self) -> GuessSessionSummary {
+        GuessSessionSummary {
+            file: self.file.clone(),
+            game_id: self.game_id,
+            white: self.white.clone(),
+            black: self.black.clone(),
+            color: self.color.map(|c| color_name(c).to_string()),
+            guesses: self.results.len() as u32,
+            matched: self.results.iter().filter(|r| r.matched).count() as u32,
+            score: self.results.iter().map(|r| r.points).sum(),
+            max_score: self.results.len() as u32 * MAX_POINTS,
+            time_ms: self.results.iter().map(|r| r.time_ms).sum(),
+            finished_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GuessState {
+    pub session_id: String,
+    pub game_id: i32,
+    pub white: String,
+    pub black: String,
+    /// Position in which the next guess is expected.
+    pub fen: String,
+    pub turn: String,
+    pub ply: u32,
+    pub total_plies: u32,
+    /// Moves of the game played so far, in SAN.
+    pub revealed: Vec<String>,
+    pub score: u32,
+    pub max_score: u32,
+    pub finished: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GuessResult {
+    pub ply: u32,
+    pub guess: String,
+    pub actual: String,
+    pub matched: bool,
+    pub points: u32,
+    /// Engine evaluations after each move, in centipawns from white's point of view.
+    pub guess_eval: Option<f64>,
+    pub actual_eval: Option<f64>,
+    pub time_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GuessOutcome {
+    pub result: GuessResult,
+    pub state: GuessState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GuessSessionSummary {
+    pub file: PathBuf,
+    pub game_id: i32,
+    pub white: String,
+    pub black: String,
+    pub color: Option<String>,
+    pub guesses: u32,
+    pub matched: u32,
+    pub score: u32,
+    pub max_score: u32,
+    pub time_ms: u64,
+    pub finished_at: String,
+}
+
+/// Points for a guess that differs from the game move, given the engine evaluations of both.
+fn guess_points(mover: Color, guess_eval: f64, actual_eval: f64) -> u32 {
+    let sign = match mover {
+        Color::White => 1.0,
+        Color::Black => -1.0,
+    };
+    let loss = win_chance(sign * actual_eval) - win_chance(sign * guess_eval);
+    let points = EQUAL_MOVE_POINTS * (1.0 - loss.max(0.0) / ZERO_POINTS_LOSS);
+    points.round().clamp(0.0, EQUAL_MOVE_POINTS) as u32
+}
+
+/// Parse a guess given either in UCI or SAN.
+pub(crate) fn parse_guess(position: &Chess, guess: &str) -> Result<Move> {
+    let guess = guess.trim();
+    if let Some(m) = UciMove::from_ascii(guess.as_bytes())
+        .ok()
+        .and_then(|uci| uci.to_move(position).ok())
+    {
+        return Ok(m);
+    }
+    let san: San = guess.trim_end_matches(['+', '#', '!', '?']).parse()?;
+    Ok(san.to_move(position)?)
+}
+
+/// Engine evaluation after `m` is played in `before`.
+async fn eval_after(
+    engine: &str,
+    id: &str,
+    before: &Chess,
+    m: &Move,
+    state: &tauri::State<'_, AppState>,
+    app: &tauri::AppHandle,
+) -> Result<f64> {
+    let mut after = before.clone();
+    after.play_unchecked(m);
+    if after.is_game_over() {
+        return Ok(terminal_eval(&after));
+    }
+
+    let analysis = GameAnalysisService::analyze_game(
+        id.to_string(),
+        engine.to_string(),
+        GoMode::Depth(GUESS_DEPTH),
+        AnalysisOptions {
+            fen: Fen::from_position(before.clone(), EnPassantMode::Legal).to_string(),
+            moves: vec![m.to_uci(CastlingMode::Standard).to_string()],
+            ..Default::default()
+        },
+        Vec::new(),
+        state.clone(),
+        app.clone(),
+    )
+    .await?;
+    Ok(analysis
+        .get(1)
+        .and_then(|a| a.best.first())
+        .map(|best| score_cp(&best.score))
+        .unwrap_or(0.0))
+}
+
+fn history_path(app: &tauri::AppHandle) -> Result<PathBuf> {
+    Ok(app.path().resolve(HISTORY_FILE, BaseDirectory::AppData)?)
+}
+
+fn load_history(app: &tauri::AppHandle) -> Result<Vec<GuessSessionSummary>> {
+    let path = history_path(app)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(&std::fs::read(&path)?)
+        .map_err(|e| Error::PackageManager(format!("Invalid guess-the-move history: {}", e)))
+}
+
+fn save_summary(app: &tauri::AppHandle, summary: GuessSessionSummary) -> Result<()> {
+    let mut history = load_history(app)?;
+    history.push(summary);
+    let path = history_path(app)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_vec(&history)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize history: {}", e)))?;
+    std::fs::write(&path, json)?;
+    Ok(())
+}
+
+fn missing_session(session_id: &str) -> Error {
+    Error::PackageManager(format!("Unknown guess-the-move session: {}", session_id))
+}
+
+/// Start a session on a stored game. `color` restricts guessing to one side ("white"/"black").
+#[tauri::command]
+#[specta::specta]
+pub async fn start_guess_the_move(
+    file: PathBuf,
+    game_id: i32,
+    color: Option<String>,
+    engine: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<GuessState> {
+    let color = match color.as_deref() {
+        None => None,
+        Some("white") => Some(Color::White),
+        Some("black") => Some(Color::Black),
+        Some(other) => {
+            return Err(Error::PackageManager(format!("Invalid color: {}", other)));
+        }
+    };
+
+    let (game, moves, fen) = {
+        let db =
+            &mut get_db_or_create(&state, file.to_str().unwrap(), ConnectionOptions::default())?;
+        let game = core::get_game(db, game_id)?;
+        let (moves, fen): (Vec<u8>, Option<String>) = games::table
+            .find(game_id)
+            .select((games::moves, games::fen))
+            .first(db)?;
+        (game, moves, fen)
+    };
+
+    let mut position = match fen.as_deref() {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let tree = GameTree::from_bytes(&moves, Some(position.clone()))?;
+    let mut played = Vec::new();
+    for node in tree.nodes() {
+        if let GameTreeNode::Move(san_plus) = node {
+            let m = san_plus.san.to_move(&position)?;
+            played.push(PlayedMove {
+                before: position.clone(),
+                san: san_plus.clone(),
+                m: m.clone(),
+            });
+            position.play_unchecked(&m);
+        }
+    }
+    if played.is_empty() {
+        return Err(Error::NoMovesFound);
+    }
+
+    let mut session = GuessSession {
+        file,
+        game_id,
+        white: game.white,
+        black: game.black,
+        color,
+        engine,
+        moves: played,
+        final_position: position,
+        ply: 0,
+        results: Vec::new(),
+        shown_at: Instant::now(),
+    };
+    session.advance();
+
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let guess_state = session.state(&session_id);
+    state.guess_sessions.insert(session_id, session);
+    Ok(guess_state)
+}
+
+/// Score a guess for the current position, reveal the game move and advance to the next turn.
+/// The session is saved to the history once the game is over.
+#[tauri::command]
+#[specta::specta]
+pub async fn submit_guess(
+    session_id: String,
+    guess: String,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<GuessOutcome> {
+    // Copy what scoring needs so the session is not locked while the engine runs.
+    let (before, actual, actual_san, engine, ply, time_ms) = {
+        let session = state
+            .guess_sessions
+            .get(&session_id)
+            .ok_or_else(|| missing_session(&session_id))?;
+        let played = session
+            .moves
+            .get(session.ply)
+            .ok_or(Error::NoMovesFound)?;
+        (
+            played.before.clone(),
+            played.m.clone(),
+            played.san.clone(),
+            session.engine.clone(),
+            session.ply,
+            session.shown_at.elapsed().as_millis() as u64,
+        )
+    };
+
+    let guess_move = parse_guess(&before, &guess)?;
+    let guess_san = SanPlus::from_move(before.clone(), &guess_move).to_string();
+    let matched = guess_move == actual || same_move(&guess_san, &actual_san.to_string());
+
+    let mut result = GuessResult {
+        ply: ply as u32 + 1,
+        guess: guess_san,
+        actual: actual_san.to_string(),
+        matched,
+        points: if matched { MAX_POINTS } else { 0 },
+        guess_eval: None,
+        actual_eval: None,
+        time_ms,
+    };
+    if let (false, Some(engine)) = (matched, engine.as_deref()) {
+        let id = format!("guess_{}", session_id);
+        let guess_eval = eval_after(engine, &id, &before, &guess_move, &state, &app).await?;
+        let actual_eval = eval_after(engine, &id, &before, &actual, &state, &app).await?;
+        result.points = guess_points(before.turn(), guess_eval, actual_eval);
+        result.guess_eval = Some(guess_eval);
+        result.actual_eval = Some(actual_eval);
+    }
+
+    let guess_state = {
+        let mut session = state
+            .guess_sessions
+            .get_mut(&session_id)
+            .ok_or_else(|| missing_session(&session_id))?;
+        if session.ply != ply {
+            return Err(Error::PackageManager(
+                "Guess submitted for a position that is no longer current".to_string(),
+            ));
+        }
+        session.results.push(result.clone());
+        session.ply += 1;
+        session.advance();
+        session.state(&session_id)
+    };
+
+    if guess_state.finished {
+        if let Some((_, session)) = state.guess_sessions.remove(&session_id) {
+            save_summary(&app, session.summary())?;
+        }
+    }
+
+    Ok(GuessOutcome {
+        result,
+        state: guess_state,
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_guess_state(
+    session_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<GuessState> {
+    let session = state
+        .guess_sessions
+        .get(&session_id)
+        .ok_or_else(|| missing_session(&session_id))?;
+    Ok(session.state(&session_id))
+}
+
+/// Stop a session early. Sessions with at least one guess are saved to the history.
+#[tauri::command]
+#[specta::specta]
+pub async fn end_guess_the_move(
+    session_id: String,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<Option<GuessSessionSummary>> {
+    let Some((_, session)) = state.guess_sessions.remove(&session_id) else {
+        return Ok(None);
+    };
+    if session.results.is_empty() {
+        return Ok(None);
+    }
+    let summary = session.summary();
+    save_summary(&app, summary.clone())?;
+    Ok(Some(summary))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_guess_the_move_history(
+    app: tauri::AppHandle,
+) -> Result<Vec<GuessSessionSummary>> {
+    load_history(&app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_points() {
+        // As good as the game move.
+        assert_eq!(guess_points(Color::White, 50.0, 40.0), 8);
+        // Much worse than the game move, for either side.
+        assert_eq!(guess_points(Color::White, -600.0, 50.0), 0);
+        assert_eq!(guess_points(Color::Black, 600.0, -50.0), 0);
+        let small = guess_points(Color::Black, 20.0, -30.0);
+        assert!(small > 0 && small < 8);
+    }
+
+    #[test]
+    fn test_parse_guess() {
+        let position = Chess::default();
+        let uci = parse_guess(&position, "g1f3").unwrap();
+        let san = parse_guess(&position, "Nf3").unwrap();
+        assert_eq!(uci, san);
+        assert!(parse_guess(&position, "Nf6").is_err());
+    }
+}
@@ -0,0 +1,17 @@
+//! Training drills played against the backend.
+//!
+//! Sessions live in `AppState` and are scored by the backend, so the frontend only renders the
+//! state each command returns.
+
+mod guess_the_move;
+mod vision;
+
+pub(crate) use guess_the_move::parse_guess;
+pub use guess_the_move::{
+    end_guess_the_move, get_guess_state, get_guess_the_move_history, start_guess_the_move,
+    submit_guess, GuessSession,
+};
+pub use vision::{
+    end_vision_session, get_vision_stats, start_vision_session, submit_vision_answer,
+    VisionSession,
+};
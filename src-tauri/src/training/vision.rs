@@ -0,0 +1,674 @@
+//! Board vision drills.
+//!
+//! Four drills train seeing the board rather than calculating: naming a highlighted square,
+//! steering a knight to a target square in as few moves as possible, and finding every check or
+//! every capture in a position. Positions for the last two come from random play out of the
+//! initial position or from random games of a database.
+//!
+//! Exercises are generated and scored by the backend: a session lives in `AppState` with the
+//! answers, and the frontend only gets the prompts. Finished sessions are appended to a history
+//! file in the app data directory, from which `get_vision_stats` sums up each drill.
+
+use std::{collections::BTreeSet, path::PathBuf, time::Instant};
+
+use diesel::{dsl::max, prelude::*};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    attacks::knight_attacks, fen::Fen, san::SanPlus, CastlingMode, Chess, Color, EnPassantMode,
+    FromSetup, Position, Square,
+};
+use specta::Type;
+use tauri::{path::BaseDirectory, Manager};
+
+use crate::{
+    db::{
+        get_db_or_create,
+        pgn::{GameTree, GameTreeNode},
+        review::color_name,
+        schema::games,
+        ConnectionOptions,
+    },
+    error::{Error, Result},
+    training::parse_guess,
+    AppState,
+};
+
+const HISTORY_FILE: &str = "vision-history.json";
+const MAX_EXERCISES: u32 = 100;
+/// Random plies played from the initial position for a random position.
+const RANDOM_PLIES: std::ops::RangeInclusive<usize> = 8..=40;
+/// Positions drawn before giving up on finding one with an answer.
+const POSITION_ATTEMPTS: usize = 200;
+/// Opening plies skipped when drawing a position from a database game.
+const MIN_GAME_PLY: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum VisionDrill {
+    Coordinates,
+    KnightPath,
+    Checks,
+    Captures,
+}
+
+/// What the trainee is shown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum VisionPrompt {
+    /// Name the highlighted square on a board seen from `orientation`.
+    Coordinates { square: String, orientation: String },
+    /// Move a knight from `from` to `to` over an empty board; the answer is the squares it lands
+    /// on, ending with `to`.
+    KnightPath { from: String, to: String },
+    /// List every move giving check.
+    Checks { fen: String },
+    /// List every capture.
+    Captures { fen: String },
+}
+
+struct Exercise {
+    prompt: VisionPrompt,
+    /// Position of the check and capture drills.
+    position: Option<Chess>,
+}
+
+pub struct VisionSession {
+    drill: VisionDrill,
+    exercises: Vec<Exercise>,
+    /// Index of the current exercise.
+    index: usize,
+    results: Vec<VisionResult>,
+    shown_at: Instant,
+}
+
+impl VisionSession {
+    fn state(&self, session_id: &str) -> VisionState {
+        VisionState {
+            session_id: session_id.to_string(),
+            drill: self.drill,
+            index: self.index as u32,
+            total: self.exercises.len() as u32,
+            prompt: self.exercises.get(self.index).map(|e| e.prompt.clone()),
+            correct: self.results.iter().filter(|r| r.correct).count() as u32,
+            finished: self.index >= self.exercises.len(),
+        }
+    }
+
+    fn summary(&self) -> VisionSessionSummary {
+        VisionSessionSummary {
+            drill: self.drill,
+            exercises: self.results.len() as u32,
+            correct: self.results.iter().filter(|r| r.correct).count() as u32,
+            time_ms: self.results.iter().map(|r| r.time_ms).sum(),
+            finished_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct VisionState {
+    pub session_id: String,
+    pub drill: VisionDrill,
+    pub index: u32,
+    pub total: u32,
+    /// Current exercise, `None` once the session is finished.
+    pub prompt: Option<VisionPrompt>,
+    pub correct: u32,
+    pub finished: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct VisionResult {
+    pub correct: bool,
+    /// The full answer: the square, a shortest knight path, or every check or capture in SAN.
+    pub expected: Vec<String>,
+    /// Checks or captures the answer left out.
+    pub missed: Vec<String>,
+    /// Parts of the answer that are wrong.
+    pub wrong: Vec<String>,
+    pub time_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct VisionOutcome {
+    pub result: VisionResult,
+    pub state: VisionState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct VisionSessionSummary {
+    pub drill: VisionDrill,
+    pub exercises: u32,
+    pub correct: u32,
+    pub time_ms: u64,
+    pub finished_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct VisionDrillStats {
+    pub drill: VisionDrill,
+    pub sessions: u32,
+    pub exercises: u32,
+    pub correct: u32,
+    /// Mean time per exercise.
+    pub average_ms: u64,
+    /// Share of correct answers of the best session, in `0.0..=1.0`.
+    pub best_accuracy: f64,
+}
+
+fn knight_distances(from: Square) -> [Option<u32>; 64] {
+    let mut distances = [None; 64];
+    distances[usize::from(from)] = Some(0);
+    let mut frontier = vec![from];
+    let mut distance = 0;
+    while !frontier.is_empty() {
+        distance += 1;
+        let mut next = Vec::new();
+        for square in frontier {
+            for target in knight_attacks(square) {
+                if distances[usize::from(target)].is_none() {
+                    distances[usize::from(target)] = Some(distance);
+                    next.push(target);
+                }
+            }
+        }
+        frontier = next;
+    }
+    distances
+}
+
+/// A shortest knight path from `from` to `to`, without `from`.
+fn knight_path(from: Square, to: Square) -> Vec<Square> {
+    let distances = knight_distances(to);
+    let mut path = Vec::new();
+    let mut square = from;
+    while square != to {
+        let here = distances[usize::from(square)].unwrap_or(0);
+        square = knight_attacks(square)
+            .into_iter()
+            .find(|next| distances[usize::from(*next)] == Some(here - 1))
+            .expect("every square is reachable by a knight");
+        path.push(square);
+    }
+    path
+}
+
+/// Moves of `position` the drill asks for, in SAN.
+fn drill_moves(drill: VisionDrill, position: &Chess) -> Vec<String> {
+    position
+        .legal_moves()
+        .into_iter()
+        .filter(|m| match drill {
+            VisionDrill::Checks => position.clone().play(m).is_ok_and(|after| after.is_check()),
+            VisionDrill::Captures => m.is_capture(),
+            VisionDrill::Coordinates | VisionDrill::KnightPath => false,
+        })
+        .map(|m| SanPlus::from_move(position.clone(), &m).to_string())
+        .collect()
+}
+
+fn random_square(rng: &mut impl Rng) -> Square {
+    Square::new(rng.gen_range(0..64))
+}
+
+fn random_position(rng: &mut impl Rng) -> Chess {
+    let mut position = Chess::default();
+    for _ in 0..rng.gen_range(RANDOM_PLIES) {
+        let moves = position.legal_moves();
+        let Some(m) = moves.choose(rng) else {
+            break;
+        };
+        let next = position.clone().play(m).expect("legal move");
+        if next.is_game_over() {
+            break;
+        }
+        position = next;
+    }
+    position
+}
+
+/// Positions of the mainline of a stored game after the opening.
+fn game_positions(db: &mut SqliteConnection, game_id: i32) -> Result<Vec<Chess>> {
+    let Some((moves, fen)) = games::table
+        .find(game_id)
+        .select((games::moves, games::fen))
+        .first::<(Vec<u8>, Option<String>)>(db)
+        .optional()?
+    else {
+        return Ok(Vec::new());
+    };
+    let mut position = match fen.as_deref() {
+        Some(fen) => {
+            let fen: Fen = fen.parse()?;
+            Chess::from_setup(fen.into(), CastlingMode::Chess960)?
+        }
+        None => Chess::default(),
+    };
+    let tree = GameTree::from_bytes(&moves, Some(position.clone()))?;
+    let mut positions = Vec::new();
+    for node in tree.nodes() {
+        if let GameTreeNode::Move(san_plus) = node {
+            let m = san_plus.san.to_move(&position)?;
+            position.play_unchecked(&m);
+            positions.push(position.clone());
+        }
+    }
+    Ok(positions.into_iter().skip(MIN_GAME_PLY).collect())
+}
+
+/// A position with at least one answer for `drill`, from random games of `db` or random play.
+fn drill_position(
+    drill: VisionDrill,
+    db: Option<&mut SqliteConnection>,
+    rng: &mut impl Rng,
+) -> Result<Chess> {
+    match db {
+        Some(db) => {
+            let max_id: Option<i32> = games::table.select(max(games::id)).first(db)?;
+            let max_id = max_id.ok_or(Error::NoMovesFound)?;
+            for _ in 0..POSITION_ATTEMPTS {
+                let positions = game_positions(db, rng.gen_range(1..=max_id))?;
+                if let Some(position) = positions.choose(rng) {
+                    if !position.is_game_over() && !drill_moves(drill, position).is_empty() {
+                        return Ok(position.clone());
+                    }
+                }
+            }
+        }
+        None => {
+            for _ in 0..POSITION_ATTEMPTS {
+                let position = random_position(rng);
+                if !drill_moves(drill, &position).is_empty() {
+                    return Ok(position);
+                }
+            }
+        }
+    }
+    Err(Error::PackageManager(
+        "No suitable position found for the drill".to_string(),
+    ))
+}
+
+fn generate_exercise(
+    drill: VisionDrill,
+    db: Option<&mut SqliteConnection>,
+    rng: &mut impl Rng,
+) -> Result<Exercise> {
+    Ok(match drill {
+        VisionDrill::Coordinates => Exercise {
+            prompt: VisionPrompt::Coordinates {
+                square: random_square(rng).to_string(),
+                orientation: color_name(if rng.gen() {
+                    Color::White
+                } else {
+                    Color::Black
+                })
+                .to_string(),
+            },
+            position: None,
+        },
+        VisionDrill::KnightPath => {
+            let from = random_square(rng);
+            let to = loop {
+                let to = random_square(rng);
+                // A single hop is too easy to be worth a drill.
+                if knight_distances(from)[usize::from(to)].is_some_and(|d| d >= 2) {
+                    break to;
+                }
+            };
+            Exercise {
+                prompt: VisionPrompt::KnightPath {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                },
+                position: None,
+            }
+        }
+        VisionDrill::Checks | VisionDrill::Captures => {
+            let position = drill_position(drill, db, rng)?;
+            let fen = Fen::from_position(position.clone(), EnPassantMode::Legal).to_string();
+            Exercise {
+                prompt: match drill {
+                    VisionDrill::Checks => VisionPrompt::Checks { fen },
+                    _ => VisionPrompt::Captures { fen },
+                },
+                position: Some(position),
+            }
+        }
+    })
+}
+
+fn parse_square(square: &str) -> Option<Square> {
+    square.trim().to_ascii_lowercase().parse().ok()
+}
+
+fn score_knight_path(from: Square, to: Square, answer: &[String]) -> (bool, Vec<String>) {
+    let mut wrong = Vec::new();
+    let mut square = from;
+    for step in answer {
+        match parse_square(step) {
+            Some(next) if knight_attacks(square).contains(next) => square = next,
+            _ => {
+                wrong.push(step.clone());
+                break;
+            }
+        }
+    }
+    let shortest = knight_distances(from)[usize::from(to)].unwrap_or(0) as usize;
+    (
+        wrong.is_empty() && square == to && answer.len() == shortest,
+        wrong,
+    )
+}
+
+fn score(exercise: &Exercise, answer: &[String], time_ms: u64) -> VisionResult {
+    // The frontend may send the starting square of a knight path or empty entries.
+    let answer: Vec<String> = answer
+        .iter()
+        .map(|a| a.trim().to_string())
+        .filter(|a| !a.is_empty())
+        .collect();
+    let mut result = VisionResult {
+        correct: false,
+        expected: Vec::new(),
+        missed: Vec::new(),
+        wrong: Vec::new(),
+        time_ms,
+    };
+    match (&exercise.prompt, &exercise.position) {
+        (VisionPrompt::Coordinates { square, .. }, _) => {
+            result.correct = answer.len() == 1 && parse_square(&answer[0]) == parse_square(square);
+            if !result.correct {
+                result.wrong = answer;
+            }
+            result.expected = vec![square.clone()];
+        }
+        (VisionPrompt::KnightPath { from, to }, _) => {
+            let (from, to) = (parse_square(from).unwrap(), parse_square(to).unwrap());
+            let steps = match answer.first() {
+                Some(first) if parse_square(first) == Some(from) => &answer[1..],
+                _ => &answer[..],
+            };
+            (result.correct, result.wrong) = score_knight_path(from, to, steps);
+            result.expected = knight_path(from, to)
+                .into_iter()
+                .map(|s| s.to_string())
+                .collect();
+        }
+        (VisionPrompt::Checks { .. }, Some(position))
+        | (VisionPrompt::Captures { .. }, Some(position)) => {
+            let drill = match exercise.prompt {
+                VisionPrompt::Checks { .. } => VisionDrill::Checks,
+                _ => VisionDrill::Captures,
+            };
+            let expected: BTreeSet<String> = drill_moves(drill, position).into_iter().collect();
+            let mut found = BTreeSet::new();
+            for given in answer {
+                match parse_guess(position, &given) {
+                    Ok(m) => {
+                        let san = SanPlus::from_move(position.clone(), &m).to_string();
+                        if expected.contains(&san) {
+                            found.insert(san);
+                        } else {
+                            result.wrong.push(given);
+                        }
+                    }
+                    Err(_) => result.wrong.push(given),
+                }
+            }
+            result.missed = expected.difference(&found).cloned().collect();
+            result.correct = result.missed.is_empty() && result.wrong.is_empty();
+            result.expected = expected.into_iter().collect();
+        }
+        (VisionPrompt::Checks { .. } | VisionPrompt::Captures { .. }, None) => {}
+    }
+    result
+}
+
+fn drill_stats(history: &[VisionSessionSummary], drill: VisionDrill) -> Option<VisionDrillStats> {
+    let sessions: Vec<&VisionSessionSummary> = history
+        .iter()
+        .filter(|s| s.drill == drill && s.exercises > 0)
+        .collect();
+    if sessions.is_empty() {
+        return None;
+    }
+    let exercises: u32 = sessions.iter().map(|s| s.exercises).sum();
+    Some(VisionDrillStats {
+        drill,
+        sessions: sessions.len() as u32,
+        exercises,
+        correct: sessions.iter().map(|s| s.correct).sum(),
+        average_ms: sessions.iter().map(|s| s.time_ms).sum::<u64>() / exercises as u64,
+        best_accuracy: sessions
+            .iter()
+            .map(|s| s.correct as f64 / s.exercises as f64)
+            .fold(0.0, f64::max),
+    })
+}
+
+fn history_path(app: &tauri::AppHandle) -> Result<PathBuf> {
+    Ok(app.path().resolve(HISTORY_FILE, BaseDirectory::AppData)?)
+}
+
+fn load_history(app: &tauri::AppHandle) -> Result<Vec<VisionSessionSummary>> {
+    let path = history_path(app)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(&std::fs::read(&path)?)
+        .map_err(|e| Error::PackageManager(format!("Invalid vision drill history: {}", e)))
+}
+
+fn save_summary(app: &tauri::AppHandle, summary: VisionSessionSummary) -> Result<()> {
+    let mut history = load_history(app)?;
+    history.push(summary);
+    let path = history_path(app)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_vec(&history)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize history: {}", e)))?;
+    std::fs::write(&path, json)?;
+    Ok(())
+}
+
+fn missing_session(session_id: &str) -> Error {
+    Error::PackageManager(format!("Unknown vision drill session: {}", session_id))
+}
+
+/// Start a session of `count` exercises. Check and capture positions are taken from random games
+/// of `file` when given, and from random play otherwise.
+#[tauri::command]
+#[specta::specta]
+pub async fn start_vision_session(
+    drill: VisionDrill,
+    count: u32,
+    file: Option<PathBuf>,
+    state: tauri::State<'_, AppState>,
+) -> Result<VisionState> {
+    let count = count.clamp(1, MAX_EXERCISES);
+    let mut db = match &file {
+        Some(file) => Some(get_db_or_create(
+            &state,
+            file.to_str().unwrap(),
+            ConnectionOptions::default(),
+        )?),
+        None => None,
+    };
+    let mut rng = rand::thread_rng();
+    let exercises = (0..count)
+        .map(|_| generate_exercise(drill, db.as_deref_mut(), &mut rng))
+        .collect::<Result<Vec<_>>>()?;
+
+    let session = VisionSession {
+        drill,
+        exercises,
+        index: 0,
+        results: Vec::new(),
+        shown_at: Instant::now(),
+    };
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let vision_state = session.state(&session_id);
+    state.vision_sessions.insert(session_id, session);
+    Ok(vision_state)
+}
+
+/// Score the answer to the current exercise and move on to the next one. The session is saved to
+/// the history once the last exercise is answered.
+#[tauri::command]
+#[specta::specta]
+pub async fn submit_vision_answer(
+    session_id: String,
+    answer: Vec<String>,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<VisionOutcome> {
+    let (result, vision_state) = {
+        let mut session = state
+            .vision_sessions
+            .get_mut(&session_id)
+            .ok_or_else(|| missing_session(&session_id))?;
+        let exercise = session
+            .exercises
+            .get(session.index)
+            .ok_or_else(|| Error::PackageManager("The session is finished".to_string()))?;
+        let result = score(
+            exercise,
+            &answer,
+            session.shown_at.elapsed().as_millis() as u64,
+        );
+        session.results.push(result.clone());
+        session.index += 1;
+        session.shown_at = Instant::now();
+        (result, session.state(&session_id))
+    };
+
+    if vision_state.finished {
+        if let Some((_, session)) = state.vision_sessions.remove(&session_id) {
+            save_summary(&app, session.summary())?;
+        }
+    }
+    Ok(VisionOutcome {
+        result,
+        state: vision_state,
+    })
+}
+
+/// Stop a session early. Sessions with at least one answer are saved to the history.
+#[tauri::command]
+#[specta::specta]
+pub async fn end_vision_session(
+    session_id: String,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<Option<VisionSessionSummary>> {
+    let Some((_, session)) = state.vision_sessions.remove(&session_id) else {
+        return Ok(None);
+    };
+    if session.results.is_empty() {
+        return Ok(None);
+    }
+    let summary = session.summary();
+    save_summary(&app, summary.clone())?;
+    Ok(Some(summary))
+}
+
+/// Totals of the saved sessions of each drill that has been played.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_vision_stats(app: tauri::AppHandle) -> Result<Vec<VisionDrillStats>> {
+    let history = load_history(&app)?;
+    Ok([
+        VisionDrill::Coordinates,
+        VisionDrill::KnightPath,
+        VisionDrill::Checks,
+        VisionDrill::Captures,
+    ]
+    .into_iter()
+    .filter_map(|drill| drill_stats(&history, drill))
+    .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(moves: &[&str]) -> Vec<String> {
+        moves.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn test_knight_path() {
+        assert_eq!(
+            knight_distances(Square::A1)[usize::from(Square::H8)],
+            Some(6)
+        );
+        let path = knight_path(Square::A1, Square::B3);
+        assert_eq!(path, vec![Square::B3]);
+        assert_eq!(knight_path(Square::A1, Square::H8).len(), 6);
+
+        let exercise = Exercise {
+            prompt: VisionPrompt::KnightPath {
+                from: "g1".to_string(),
+                to: "g5".to_string(),
+            },
+            position: None,
+        };
+        assert!(score(&exercise, &answer(&["f3", "g5"]), 0).correct);
+        // The starting square may be included.
+        assert!(score(&exercise, &answer(&["g1", "h3", "g5"]), 0).correct);
+        let wrong = score(&exercise, &answer(&["f3", "e5", "g4"]), 0);
+        assert!(!wrong.correct);
+        assert_eq!(wrong.wrong, vec!["g4"]);
+    }
+
+    #[test]
+    fn test_score_checks_and_captures() {
+        let fen: Fen = "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 2 3"
+            .parse()
+            .unwrap();
+        let position: Chess = fen.into_position(CastlingMode::Standard).unwrap();
+        let mut checks = drill_moves(VisionDrill::Checks, &position);
+        checks.sort();
+        assert_eq!(checks, vec!["Bxf7+", "Qxf7#"]);
+
+        let exercise = Exercise {
+            prompt: VisionPrompt::Checks { fen: String::new() },
+            position: Some(position),
+        };
+        let result = score(&exercise, &answer(&["f3f7", "Bb5"]), 0);
+        assert!(!result.correct);
+        assert_eq!(result.missed, vec!["Bxf7+"]);
+        assert_eq!(result.wrong, vec!["Bb5"]);
+        assert!(score(&exercise, &answer(&["Qxf7#", "Bxf7+"]), 0).correct);
+    }
+
+    #[test]
+    fn test_drill_stats() {
+        let summary = |drill, exercises, correct| VisionSessionSummary {
+            drill,
+            exercises,
+            correct,
+            time_ms: exercises as u64 * 1000,
+            finished_at: String::new(),
+        };
+        let history = vec![
+            summary(VisionDrill::Coordinates, 10, 8),
+            summary(VisionDrill::Coordinates, 20, 19),
+            summary(VisionDrill::Checks, 5, 1),
+        ];
+        let stats = drill_stats(&history, VisionDrill::Coordinates).unwrap();
+        assert_eq!(stats.sessions, 2);
+        assert_eq!(stats.correct, 27);
+        assert_eq!(stats.average_ms, 1000);
+        assert_eq!(stats.best_accuracy, 0.95);
+        assert!(drill_stats(&history, VisionDrill::KnightPath).is_none());
+    }
+}
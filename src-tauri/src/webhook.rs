@@ -0,0 +1,198 @@
+//! Webhook notifications for finished background jobs.
+//!
+//! Imports and batch analyses can run for hours, often unattended. When a webhook URL is
+//! configured, every job of the `jobs` registry that completes is announced with a JSON `POST` to
+//! it, which services such as ntfy turn into a phone notification. The `Title` header carries the
+//! job name for services that use it. Deliveries run in the background and are retried with backoff
+//! on network errors and `429`/`5xx` responses; a notification that still fails is logged and
+//! dropped.
+//!
+//! The URL is kept in `webhook.json` in the app config directory.
+
+use std::{fs, path::PathBuf, sync::RwLock, time::Duration};
+
+use reqwest::{Method, Url};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{path::BaseDirectory, AppHandle, Manager};
+
+use crate::{error::Error, http::Request};
+
+const CONFIG_FILE: &str = "webhook.json";
+const MAX_ATTEMPTS: u32 = 4;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookConfig {
+    url: Option<String>,
+}
+
+static CONFIG: RwLock<WebhookConfig> = RwLock::new(WebhookConfig { url: None });
+
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct JobNotification {
+    /// Kind of job, e.g. `import` or `analysis`.
+    pub job: String,
+    pub title: String,
+    pub message: String,
+    /// RFC 3339 time the job finished.
+    pub finished_at: String,
+}
+
+fn config_path(app: &AppHandle) -> Result<PathBuf, Error> {
+    Ok(app.path().resolve(CONFIG_FILE, BaseDirectory::AppConfig)?)
+}
+
+/// Load the configured URL. A missing file means no webhook.
+pub fn init(app: &AppHandle) -> Result<(), Error> {
+    let path = config_path(app)?;
+    if !path.exists() {
+        return Ok(());
+    }
+    let config: WebhookConfig = serde_json::from_str(&fs::read_to_string(&path)?)
+        .map_err(|e| Error::PackageManager(format!("Invalid webhook config: {}", e)))?;
+    *CONFIG
+        .write()
+        .map_err(|e| Error::MutexLockFailed(e.to_string()))? = config;
+    Ok(())
+}
+
+fn webhook_url() -> Option<String> {
+    CONFIG.read().ok()?.url.clone()
+}
+
+fn validate_url(url: &str) -> Result<String, Error> {
+    let parsed =
+        Url::parse(url.trim()).map_err(|e| Error::PackageManager(format!("Invalid URL: {}", e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(Error::PackageManager(
+            "Webhook URLs must use http or https".to_string(),
+        ));
+    }
+    Ok(parsed.to_string())
+}
+
+fn is_retryable(status: u16) -> bool {
+    status == 429 || status >= 500
+}
+
+async fn deliver(url: &str, notification: &JobNotification) -> Result<(), Error> {
+    let body =
+        serde_json::to_vec(notification).map_err(|e| Error::PackageManager(e.to_string()))?;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let result = Request::new(Method::POST, url)
+            .header("Content-Type", "application/json")
+            .header("Title", notification.title.clone())
+            .body(body.clone())
+            .timeout(TIMEOUT)
+            .send()
+            .await;
+        let error = match result {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) if !is_retryable(response.status().as_u16()) => {
+                return Err(Error::HttpStatus(response.status().as_u16()))
+            }
+            Ok(response) => Error::HttpStatus(response.status().as_u16()),
+            Err(e) => e,
+        };
+        if attempt >= MAX_ATTEMPTS {
+            return Err(error);
+        }
+        log::warn!("Webhook delivery failed ({}), retrying", error);
+        tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+    }
+}
+
+/// Announce a finished job to the configured webhook, if any, without waiting for the delivery.
+pub fn job_finished(job: &str, title: impl Into<String>, message: impl Into<String>) {
+    let Some(url) = webhook_url() else {
+        return;
+    };
+    let notification = JobNotification {
+        job: job.to_string(),
+        title: title.into(),
+        message: message.into(),
+        finished_at: chrono::Utc::now().to_rfc3339(),
+    };
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = deliver(&url, &notification).await {
+            log::warn!(
+                "Failed to notify webhook of {} job: {}",
+                notification.job,
+                e
+            );
+        }
+    });
+}
+
+/// Set the webhook URL, or remove it with `None`.
+#[tauri::command]
+#[specta::specta]
+pub fn set_job_webhook(url: Option<String>, app: AppHandle) -> Result<(), Error> {
+    let url = url
+        .filter(|url| !url.trim().is_empty())
+        .map(|url| validate_url(&url))
+        .transpose()?;
+    let config = WebhookConfig { url };
+
+    let path = config_path(&app)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(&config)
+        .map_err(|e| Error::PackageManager(format!("Failed to serialize webhook config: {}", e)))?;
+    fs::write(path, json)?;
+
+    *CONFIG
+        .write()
+        .map_err(|e| Error::MutexLockFailed(e.to_string()))? = config;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_job_webhook() -> Option<String> {
+    webhook_url()
+}
+
+/// Send a test notification to the configured webhook and wait for the delivery.
+#[tauri::command]
+#[specta::specta]
+pub async fn test_job_webhook() -> Result<(), Error> {
+    let url = webhook_url()
+        .ok_or_else(|| Error::PackageManager("No webhook URL configured".to_string()))?;
+    let notification = JobNotification {
+        job: "test".to_string(),
+        title: "Pawn Appetit".to_string(),
+        message: "Webhook notifications are working".to_string(),
+        finished_at: chrono::Utc::now().to_rfc3339(),
+    };
+    deliver(&url, &notification).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_url() {
+        assert_eq!(
+            validate_url(" https://ntfy.sh/my-topic ").unwrap(),
+            "https://ntfy.sh/my-topic"
+        );
+        assert!(validate_url("file:///etc/passwd").is_err());
+        assert!(validate_url("not a url").is_err());
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(429));
+        assert!(is_retryable(503));
+        assert!(!is_retryable(404));
+    }
+}
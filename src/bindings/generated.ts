@@ -44,9 +44,9 @@ async saveFidePhoto(fideId: string, photoData: string) : Promise<Result<string,
 /**
  * Get best moves from the engine for a given position and options.
  */
-async getBestMoves(id: string, engine: string, tab: string, goMode: GoMode, options: EngineOptions) : Promise<Result<[number, BestMoves[]] | null, string>> {
+async getBestMoves(id: string, engine: string, tab: string, goMode: GoMode, options: EngineOptions, emission: EmissionOptions | null) : Promise<Result<[number, BestMoves[]] | null, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("get_best_moves", { id, engine, tab, goMode, options }) };
+    return { status: "ok", data: await TAURI_INVOKE("get_best_moves", { id, engine, tab, goMode, options, emission }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -164,9 +164,9 @@ async getOpeningFromName(name: string) : Promise<Result<string, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async getPlayersGameInfo(file: string, id: number) : Promise<Result<PlayerGameInfo, string>> {
+async getPlayersGameInfo(file: string, id: number, summary: boolean) : Promise<Result<PlayerGameInfo, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("get_players_game_info", { file, id }) };
+    return { status: "ok", data: await TAURI_INVOKE("get_players_game_info", { file, id, summary }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -208,9 +208,9 @@ async mergePlayers(file: string, player1: number, player2: number) : Promise<Res
     else return { status: "error", error: e  as any };
 }
 },
-async convertPgn(file: string, dbPath: string, timestamp: number | null, title: string, description: string | null) : Promise<Result<null, string>> {
+async convertPgn(file: string, dbPath: string, timestamp: number | null, title: string, description: string | null, lenient: boolean | null, source: string | null) : Promise<Result<ImportCorrection[], string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("convert_pgn", { file, dbPath, timestamp, title, description }) };
+    return { status: "ok", data: await TAURI_INVOKE("convert_pgn", { file, dbPath, timestamp, title, description, lenient, source }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -339,25 +339,25 @@ async deleteDatabase(file: string) : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async exportToPgn(file: string, destFile: string) : Promise<Result<null, string>> {
+async exportToPgn(file: string, destFile: string, format: PgnFormat | null, options: PgnExportOptions | null) : Promise<Result<null, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("export_to_pgn", { file, destFile }) };
+    return { status: "ok", data: await TAURI_INVOKE("export_to_pgn", { file, destFile, format, options }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async exportPositionGamesToPgn(file: string, fen: string, destFile: string) : Promise<Result<null, string>> {
+async exportPositionGamesToPgn(file: string, fen: string, destFile: string, format: PgnFormat | null) : Promise<Result<null, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("export_position_games_to_pgn", { file, fen, destFile }) };
+    return { status: "ok", data: await TAURI_INVOKE("export_position_games_to_pgn", { file, fen, destFile, format }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async exportSelectedGamesToPgn(file: string, gameIds: number[], destFile: string) : Promise<Result<null, string>> {
+async exportSelectedGamesToPgn(file: string, gameIds: number[], destFile: string, format: PgnFormat | null) : Promise<Result<null, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("export_selected_games_to_pgn", { file, gameIds, destFile }) };
+    return { status: "ok", data: await TAURI_INVOKE("export_selected_games_to_pgn", { file, gameIds, destFile, format }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -411,6 +411,20 @@ async getDbInfo(file: string) : Promise<Result<DatabaseInfo, string>> {
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Run the pending migrations of a games database. The app runs it on every database it lists,
+ * before anything else reads it, so other commands never change the schema. Migrating runs on a
+ * blocking thread under the write lock and reports each step as `Database` progress; a database
+ * is only remembered as migrated once every migration succeeded.
+ */
+async migrateDatabase(file: string) : Promise<Result<MigrationReport, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("migrate_database", { file }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async getGames(file: string, query: GameQueryJs) : Promise<Result<QueryResponse<NormalizedGame[]>, string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_games", { file, query }) };
@@ -510,20 +524,23 @@ async getPuzzleRatingRange(file: string) : Promise<Result<[number, number], stri
  * - Existing puzzle database files (.db, .db3)
  * - Compressed files (.zst)
  * 
+ * Puzzles whose solution cannot be played are skipped.
+ * 
  * # Arguments
  * * `source_file` - Path to the source puzzle file
  * * `db_path` - Path where the new puzzle database should be created
  * * `title` - Title for the puzzle database
  * * `description` - Optional description for the puzzle database
+ * * `engine_check` - Optional engine search tagging puzzles whose first move is not clearly best
  * * `app` - Tauri app handle for progress events
  * 
  * # Returns
  * * `Ok(())` if import was successful
  * * `Err(Error)` if there was a problem importing the file
  */
-async importPuzzleFile(sourceFile: string, dbPath: string, title: string, description: string | null) : Promise<Result<null, string>> {
+async importPuzzleFile(sourceFile: string, dbPath: string, title: string, description: string | null, engineCheck: PuzzleEngineCheck | null) : Promise<Result<null, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("import_puzzle_file", { sourceFile, dbPath, title, description }) };
+    return { status: "ok", data: await TAURI_INVOKE("import_puzzle_file", { sourceFile, dbPath, title, description, engineCheck }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -727,9 +744,30 @@ export type BestMoves = { nodes: number; depth: number; score: Score; uciMoves:
  * Event payload for best-move updates (emitted to frontend).
  */
 export type BestMovesPayload = { bestLines: BestMoves[]; engine: string; tab: string; fen: string; moves: string[]; progress: number }
+export type CommentPlacement = "inline" | 
+/**
+ * Every comment is written on its own line.
+ */
+"ownLine"
 export type DatabaseInfo = { title: string; description: string; player_count: number; event_count: number; game_count: number; storage_size: bigint; filename: string; indexed: boolean }
 export type DatabaseProgress = { id: string; progress: number }
 export type DownloadProgress = { progress: number; id: string; finished: boolean }
+/**
+ * Server-side filtering of the lines emitted while an engine is searching.
+ */
+export type EmissionOptions = { 
+/**
+ * Only emit lines within this many centipawns of the best line.
+ */
+maxCpLoss: number | null; 
+/**
+ * Emit nothing before the search reaches this depth.
+ */
+minDepth: number | null; 
+/**
+ * Maximum number of updates emitted per second (10 by default).
+ */
+maxPerSecond: number | null }
 /**
  * UCI engine configuration (name and available options).
  */
@@ -750,6 +788,7 @@ export type Event = { id: number; name: string | null }
 export type FidePlayer = { fideid: number; name: string; country: string; sex: string; title: string | null; w_title: string | null; o_title: string | null; foa_title: string | null; rating: number | null; games: number | null; k: number | null; rapid_rating: number | null; rapid_games: number | null; rapid_k: number | null; blitz_rating: number | null; blitz_games: number | null; blitz_k: number | null; birthday: number | null; flag: string | null }
 export type FileMetadata = { last_modified: bigint; size: bigint; is_dir: boolean; is_readonly: boolean }
 export type GameOutcome = "Won" | "Drawn" | "Lost"
+export type GamePhase = "opening" | "middlegame" | "endgame"
 export type GameQueryJs = { options?: QueryOptions<GameSort> | null; 
 /**
  * Optional limit for number of game details to load (stats are always full)
@@ -757,17 +796,60 @@ export type GameQueryJs = { options?: QueryOptions<GameSort> | null;
  * Using u64 instead of usize for better bigint compatibility with TypeScript
  * Serialized as string to handle bigint in JSON
  */
-game_details_limit?: bigint | null; player1?: number | null; player2?: number | null; tournament_id?: number | null; start_date?: string | null; end_date?: string | null; range1?: [number, number] | null; range2?: [number, number] | null; sides?: Sides | null; outcome?: string | null; position?: PositionQueryJs | null; wanted_result?: string | null }
+game_details_limit?: bigint | null; player1?: number | null; player2?: number | null; tournament_id?: number | null; start_date?: string | null; end_date?: string | null; range1?: [number, number] | null; range2?: [number, number] | null; sides?: Sides | null; outcome?: string | null; position?: PositionQueryJs | null; wanted_result?: string | null; 
+/**
+ * Endgame type as returned by `get_endgame_distribution`, e.g. `R vs R`.
+ */
+endgame?: string | null; 
+/**
+ * Import source as recorded by `convert_pgn`, e.g. the imported file name.
+ */
+source?: string | null; 
+/**
+ * User tags the games must all carry, as set by `tag_game`.
+ */
+tags?: string[] | null; 
+/**
+ * Only games lost on time by the side that was winning, see `get_flagging_stats`.
+ */
+flagged_when_winning?: boolean | null; 
+/**
+ * Only games whose final position is in this phase, see `get_game_phases`.
+ */
+decided_in?: GamePhase | null }
 export type GameSort = "id" | "date" | "whiteElo" | "blackElo" | "averageElo" | "ply_count"
 /**
  * Engine search mode (depth, time, nodes, etc).
  */
 export type GoMode = { t: "PlayersTime"; c: PlayersTime } | { t: "Depth"; c: number } | { t: "Time"; c: number } | { t: "Nodes"; c: number } | { t: "Infinite" }
+export type ImportCorrection = { 
+/**
+ * Position of the game in the file, starting at 1.
+ */
+game: number; message: string }
+export type MigrationReport = { fromVersion: number; toVersion: number; 
+/**
+ * Names of the migrations applied, in order.
+ */
+applied: string[] }
+export type MonthlyPlayerStats = { site: string; 
+/**
+ * `YYYY-MM`.
+ */
+month: string; games: number; wins: number; draws: number; losses: number; average_elo: number; 
+/**
+ * Most played openings, most played first.
+ */
+main_openings: string[] }
 /**
  * Analysis result for a single move/position.
  */
 export type MoveAnalysis = { best: BestMoves[]; novelty: boolean; is_sacrifice: boolean }
-export type NormalizedGame = { id: number; fen: string; event: string; event_id: number; site: string; site_id: number; date?: string | null; time?: string | null; round?: string | null; white: string; white_id: number; white_elo?: number | null; black: string; black_id: number; black_elo?: number | null; result: Outcome; time_control?: string | null; eco?: string | null; ply_count?: number | null; moves: string }
+export type NormalizedGame = { id: number; fen: string; event: string; event_id: number; site: string; site_id: number; date?: string | null; time?: string | null; round?: string | null; white: string; white_id: number; white_elo?: number | null; black: string; black_id: number; black_elo?: number | null; result: Outcome; time_control?: string | null; eco?: string | null; ply_count?: number | null; moves: string; 
+/**
+ * Where the game was imported from, if recorded.
+ */
+source?: string | null }
 /**
  * Opening tag option with technical value and friendly label
  */
@@ -775,8 +857,26 @@ export type OpeningTagOption = { value: string; label: string }
 export type OutOpening = { name: string; fen: string }
 export type Outcome = "1-0" | "0-1" | "1/2-1/2" | "*"
 export type PackageManagerResult = { success: boolean; stdout: string; stderr: string }
+export type PgnExportOptions = { includeVariations: boolean; 
+/**
+ * Comment text and commands other than `[%eval]`, such as `[%clk]`.
+ */
+includeComments: boolean; includeNags: boolean; 
+/**
+ * `[%eval]` commands, kept on their own when other comments are left out.
+ */
+includeEvals: boolean; 
+/**
+ * Export at most this many games.
+ */
+maxGames: number | null }
+export type PgnFormat = { 
+/**
+ * Column to wrap move text at; `None` writes each game's moves on one line.
+ */
+lineWidth: number | null; variationStyle: VariationStyle; commentPlacement: CommentPlacement }
 export type Player = { id: number; name: string | null; elo: number | null }
-export type PlayerGameInfo = { site_stats_data: SiteStatsData[] }
+export type PlayerGameInfo = { site_stats_data: SiteStatsData[]; monthly_stats: MonthlyPlayerStats[] }
 export type PlayerQuery = { options: QueryOptions<PlayerSort>; name?: string | null; range?: [number, number] | null }
 export type PlayerSort = "id" | "name" | "elo"
 /**
@@ -785,7 +885,11 @@ export type PlayerSort = "id" | "name" | "elo"
 export type PlayersTime = { white: number; black: number; winc: number; binc: number }
 export type PositionQueryJs = { fen: string; type_: string }
 export type PositionStats = { move: string; white: number; draw: number; black: number }
-export type Puzzle = { id: number; fen: string; moves: string; rating: number; rating_deviation: number; popularity: number; nb_plays: number; themes: string | null; game_url: string | null; opening_tags: string | null }
+export type Puzzle = { id: number; fen: string; moves: string; rating: number; rating_deviation: number; popularity: number; nb_plays: number; themes: string | null; game_url: string | null; opening_tags: string | null; 
+/**
+ * Lichess variant name (e.g. `crazyhouse`, `atomic`), `None` for standard chess.
+ */
+variant: string | null }
 /**
  * Information about a puzzle database
  */
@@ -810,9 +914,39 @@ storageSize: bigint;
 /**
  * Full path to the database file
  */
-path: string }
+path: string; 
+/**
+ * Number of puzzles per rating range, lowest first
+ */
+ratingBuckets: RatingBucket[]; 
+/**
+ * Number of puzzles per theme, most frequent first
+ */
+themeCounts: ThemeCount[]; 
+/**
+ * When the puzzles last changed, as far as the app has seen (RFC 3339)
+ */
+lastUpdated: string | null }
+export type PuzzleEngineCheck = { 
+/**
+ * Path of the engine binary.
+ */
+engine: string; depth: number; 
+/**
+ * Centipawns by which the solver's first move must beat the second best move.
+ */
+minMargin: number }
 export type QueryOptions<SortT> = { skipCount: boolean; page?: number | null; pageSize?: number | null; sort: SortT; direction: SortDirection }
-export type QueryResponse<T> = { data: T; count: number | null }
+export type QueryResponse<T> = { data: T; count: number | null; 
+/**
+ * Whether `count` is an upper bound from the database statistics rather than exact.
+ */
+count_is_estimate: boolean }
+export type RatingBucket = { 
+/**
+ * Lowest rating of the bucket; it spans `RATING_BUCKET_SIZE` points.
+ */
+minRating: number; count: number }
 /**
  * Event payload for reporting analysis progress.
  */
@@ -836,6 +970,7 @@ export type SiteStatsData = { site: string; player: string; data: StatsData[] }
 export type SortDirection = "asc" | "desc"
 export type StatsData = { date: string; is_player_white: boolean; player_elo: number; result: GameOutcome; time_control: string; opening: string }
 export type TelemetryConfig = { enabled: boolean; initial_run_completed: boolean }
+export type ThemeCount = { theme: string; count: number }
 /**
  * Theme group containing a category name and its themes
  */
@@ -920,6 +1055,15 @@ name: string;
  */
 default: string | null } }
 export type UpdateGame = { fen: string; event: string; site: string; date?: string | null; time?: string | null; round?: string | null; white: string; white_elo?: number | null; black: string; black_elo?: number | null; result: Outcome; time_control?: string | null; eco?: string | null; ply_count?: number | null; moves: string }
+export type VariationStyle = 
+/**
+ * Variations continue on the line of the move they replace.
+ */
+"inline" | 
+/**
+ * Every variation starts on its own line, indented by its depth.
+ */
+"indented"
 
 /** tauri-specta globals **/
 
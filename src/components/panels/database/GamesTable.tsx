@@ -150,7 +150,7 @@ function GamesTable({
         return;
       }
       
-      const result = await commands.exportPositionGamesToPgn(databasePath, fen, destFile);
+      const result = await commands.exportPositionGamesToPgn(databasePath, fen, destFile, null);
       if (result.status === "error") {
         console.error("Failed to export games:", result.error);
       }
@@ -177,7 +177,7 @@ function GamesTable({
       }
       
       const gameIdsArray = Array.from(selectedGameIds);
-      const result = await commands.exportSelectedGamesToPgn(databasePath, gameIdsArray, destFile);
+      const result = await commands.exportSelectedGamesToPgn(databasePath, gameIdsArray, destFile, null);
       if (result.status === "error") {
         console.error("Failed to export selected games:", result.error);
       }
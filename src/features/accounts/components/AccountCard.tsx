@@ -143,7 +143,7 @@ export function AccountCard({
     const dbPath = await resolve(await appDataDir(), "db", expectedDbFilename);
     info(`Converting PGN to database: ${filepath} -> ${dbPath}`);
     try {
-      unwrap(await commands.convertPgn(filepath, dbPath, timestamp ? timestamp / 1000 : null, filename, null));
+      unwrap(await commands.convertPgn(filepath, dbPath, timestamp ? timestamp / 1000 : null, filename, null, null, `${filename} sync`));
       info(`Conversion complete, database saved to: ${dbPath}`);
       // Wait a bit to ensure the file is fully written and indexed
       await new Promise((resolve) => setTimeout(resolve, 1000));
@@ -94,7 +94,7 @@ function Databases() {
               throw new Error("Player not found in database");
             }
             const player = players.data[0];
-            const info = unwrap(await commands.getPlayersGameInfo(db.file, player.id));
+            const info = unwrap(await commands.getPlayersGameInfo(db.file, player.id, false));
             return { db, info };
           }),
       );
@@ -169,6 +169,7 @@ function Databases() {
             setName={setName}
             info={{
               site_stats_data: personalInfo.flatMap((i) => i.info.site_stats_data),
+              monthly_stats: personalInfo.flatMap((i) => i.info.monthly_stats),
             }}
           />
         ))}
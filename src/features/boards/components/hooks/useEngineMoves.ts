@@ -159,7 +159,7 @@ export function useEngineMoves(
           extraOptions: (engine.settings || [])
             .filter((s) => s.name !== "MultiPV")
             .map((s) => ({ ...s, value: s.value?.toString() ?? "" })),
-        });
+        }, null);
 
         // Set a timeout (1 second) to detect if engine is stuck
         // If no response after 1s, clear the request and force a retry
@@ -59,7 +59,7 @@ export function AddPuzzle({
 
       const dbPath = await resolve(await appDataDir(), "puzzles", `${title}.db3`);
 
-      const result = await commands.importPuzzleFile(path, dbPath, title, description ?? null);
+      const result = await commands.importPuzzleFile(path, dbPath, title, description ?? null, null);
       if (result.status === "error") {
         throw new Error(result.error);
       }
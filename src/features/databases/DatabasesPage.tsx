@@ -856,7 +856,7 @@ function DatabaseActions({
 
     setConvertLoading(true);
     try {
-      await commands.convertPgn(file, database.file, null, "", null);
+      await commands.convertPgn(file, database.file, null, "", null, null, null);
       mutate();
     } finally {
       setConvertLoading(false);
@@ -871,7 +871,7 @@ function DatabaseActions({
 
     setExportLoading(true);
     try {
-      await commands.exportToPgn(database.file, destFile);
+      await commands.exportToPgn(database.file, destFile, null, null);
     } finally {
       setExportLoading(false);
     }
@@ -8,7 +8,7 @@ function PlayerCard({ player, file }: { player: Player; file: string }) {
   const { data: info, isLoading } = useQuery({
     queryKey: ["player-game-info", file, player.id],
     queryFn: async () => {
-      const games = await commands.getPlayersGameInfo(file, player.id);
+      const games = await commands.getPlayersGameInfo(file, player.id, false);
       return unwrap(games);
     },
     staleTime: Infinity,
@@ -130,7 +130,7 @@ const useDatabaseOperations = (
       try {
         setLoading(true);
         const dbPath = await resolve(await appDataDir(), "db", `${title}.db3`);
-        unwrap(await commands.convertPgn(path, dbPath, null, title, description ?? null));
+        unwrap(await commands.convertPgn(path, dbPath, null, title, description ?? null, null, null));
         setDatabases();
       } catch (error) {
         console.error("Failed to convert database:", error);
@@ -149,7 +149,7 @@ const useDatabaseOperations = (
       }
 
       const dbPath = await resolve(await appDataDir(), "puzzles", `${title}.db3`);
-      const result = await commands.importPuzzleFile(path, dbPath, title, description ?? null);
+      const result = await commands.importPuzzleFile(path, dbPath, title, description ?? null, null);
 
       if (result.status === "error") {
         throw new Error(result.error);
@@ -667,7 +667,7 @@ function PuzzleDbCard({ setPuzzleDbs, puzzleDb, databaseId, initInstalled }: Puz
             setIsImporting(true);
             
             // Import the downloaded CSV file
-            await commands.importPuzzleFile(tempPath, dbPath, name, puzzleDb.description || null);
+            await commands.importPuzzleFile(tempPath, dbPath, name, puzzleDb.description || null, null);
           } catch (error) {
             // If import fails, remove the database file if it was created
             try {
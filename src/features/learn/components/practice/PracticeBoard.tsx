@@ -107,7 +107,7 @@ function PracticeBoard({
       };
 
       commands
-        .getBestMoves(engine.name, engine.path, engineTabRef.current, goMode, options)
+        .getBestMoves(engine.name, engine.path, engineTabRef.current, goMode, options, null)
         .then((res) => {
           if (res.status === "error") {
             engineThinkingRef.current = false;
@@ -160,6 +160,17 @@ export async function getDatabases(): Promise<DatabaseInfo[]> {
 async function getDatabase(name: string): Promise<DatabaseInfo> {
   const appDataDirPath = await appDataDir();
   const path = await resolve(appDataDirPath, "db", name);
+  // Databases from older versions are upgraded before anything reads them.
+  const migrated = await commands.migrateDatabase(path);
+  if (migrated.status === "error") {
+    return {
+      type: "error",
+      filename: path,
+      file: path,
+      error: migrated.error,
+      indexed: false,
+    };
+  }
   const res = await commands.getDbInfo(path);
   if (res.status === "ok") {
     return {
@@ -1,7 +1,7 @@
 import { useQuery } from "@tanstack/react-query";
 import type { Platform } from "@tauri-apps/plugin-os";
 import { z } from "zod";
-import { type BestMoves, commands, type EngineOptions, type GoMode } from "@/bindings";
+import { type BestMoves, commands, type EmissionOptions, type EngineOptions, type GoMode } from "@/bindings";
 import { isInstallMethodSupported } from "./packageManager";
 import { unwrap } from "./unwrap";
 
@@ -412,8 +412,9 @@ export function getBestMoves(
   tab: string,
   goMode: GoMode,
   options: EngineOptions,
+  emission: EmissionOptions | null = null,
 ): Promise<[number, BestMoves[]] | null> {
-  return commands.getBestMoves(engine.name, engine.path, tab, goMode, options).then((r) => unwrap(r));
+  return commands.getBestMoves(engine.name, engine.path, tab, goMode, options, emission).then((r) => unwrap(r));
 }
 
 export function useDefaultEngines(os: Platform | undefined, opened: boolean) {
@@ -16,6 +16,7 @@ export interface Puzzle {
   rating_deviation: number;
   popularity: number;
   nb_plays: number;
+  variant?: string | null;
   completion: Completion;
 }
 
@@ -129,6 +130,9 @@ async function getFilesFromFilesSection(): Promise<PuzzleDatabaseInfo[]> {
           puzzleCount: unwrap(await commands.countPgnGames(file.path)),
           storageSize: BigInt(stats.size),
           path: file.path,
+          ratingBuckets: [],
+          themeCounts: [],
+          lastUpdated: null,
         };
       }),
     );